@@ -0,0 +1,289 @@
+// This is free and unencumbered software released into the public domain.
+
+#[cfg(not(feature = "std"))]
+compile_error!("asimov-apple requires the 'std' feature");
+
+use asimov_apple_module::cli::{
+    self, agent, calendar, contacts, daemon, digest, export, http, import, mail, mcp, messages,
+    music, notes, photos, reminders, safari, schema, search,
+};
+use asimov_module::SysexitsError;
+use clap::{Parser, Subcommand};
+use clientele::StandardOptions;
+use std::{error::Error as StdError, ffi::OsString, path::Path};
+
+/// Maps the standalone program names to the `asimov-apple` subcommands they
+/// are equivalent to, so that this binary may be installed under (or
+/// symlinked to) any of those names.
+const MULTICALL_NAMES: &[(&str, &[&str])] = &[
+    ("asimov-apple-calendar-emitter", &["calendar", "emit"]),
+    ("asimov-apple-contacts-emitter", &["contacts", "emit"]),
+    ("asimov-apple-importer", &["import"]),
+    ("asimov-apple-mail-emitter", &["mail", "emit"]),
+    ("asimov-apple-messages-emitter", &["messages", "emit"]),
+    ("asimov-apple-music-emitter", &["music", "emit"]),
+    ("asimov-apple-notes-cataloger", &["notes", "catalog"]),
+    ("asimov-apple-notes-emitter", &["notes", "emit"]),
+    ("asimov-apple-notes-fetcher", &["notes", "fetch"]),
+    ("asimov-apple-photos-emitter", &["photos", "emit"]),
+    ("asimov-apple-reminders-emitter", &["reminders", "emit"]),
+    ("asimov-apple-safari-emitter", &["safari"]),
+];
+
+/// asimov-apple
+#[derive(Debug, Parser)]
+#[command(name = "asimov-apple")]
+struct Options {
+    #[clap(flatten)]
+    flags: StandardOptions,
+
+    #[clap(flatten)]
+    common: cli::CommonOptions,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Apple Notes
+    #[command(subcommand)]
+    Notes(NotesCommand),
+
+    /// Apple Reminders
+    #[command(subcommand)]
+    Reminders(RemindersCommand),
+
+    /// Apple Calendar
+    #[command(subcommand)]
+    Calendar(CalendarCommand),
+
+    /// Apple Contacts
+    #[command(subcommand)]
+    Contacts(ContactsCommand),
+
+    /// Apple Photos
+    #[command(subcommand)]
+    Photos(PhotosCommand),
+
+    /// Apple Mail
+    #[command(subcommand)]
+    Mail(MailCommand),
+
+    /// Apple Messages
+    #[command(subcommand)]
+    Messages(MessagesCommand),
+
+    /// Apple Music
+    #[command(subcommand)]
+    Music(MusicCommand),
+
+    /// Safari
+    #[command(subcommand)]
+    Safari(safari::SafariCommand),
+
+    /// Export notes into another app's format
+    #[command(subcommand)]
+    Export(export::ExportCommand),
+
+    /// Upsert emitted records into a database or graph store
+    Import(import::ImportOptions),
+
+    /// Add emitted records to the local full-text search index
+    Index(search::IndexOptions),
+
+    /// Search the local full-text search index
+    Search(search::SearchOptions),
+
+    /// Email a digest of the notes changed since the last one
+    Digest(digest::DigestOptions),
+
+    /// Watch several sources from one process until Ctrl-C
+    Daemon(daemon::DaemonOptions),
+
+    /// Install a launchd agent that runs asimov-apple on a schedule
+    InstallAgent(agent::InstallAgentOptions),
+
+    /// Unload and remove a launchd agent
+    UninstallAgent(agent::UninstallAgentOptions),
+
+    /// Print the JSON Schema (or SHACL shapes) of every record type
+    Schema(schema::SchemaOptions),
+
+    /// Serve a local HTTP API returning the same JSON records
+    ServeHttp(http::ServeHttpOptions),
+
+    /// Serve the extractors as Model Context Protocol tools over stdio
+    ServeMcp(mcp::ServeMcpOptions),
+}
+
+#[derive(Debug, Subcommand)]
+enum NotesCommand {
+    /// List every note without fetching bodies
+    Catalog(notes::CatalogOptions),
+
+    /// Emit every note as JSONL
+    Emit(Box<notes::EmitOptions>),
+
+    /// Fetch a single note by URN
+    Fetch(notes::FetchOptions),
+}
+
+#[derive(Debug, Subcommand)]
+enum RemindersCommand {
+    /// Emit every reminder as JSONL
+    Emit(Box<reminders::EmitOptions>),
+
+    /// Create a reminder
+    Create(reminders::CreateOptions),
+
+    /// Mark a reminder completed by URN
+    Complete(reminders::CompleteOptions),
+}
+
+#[derive(Debug, Subcommand)]
+enum CalendarCommand {
+    /// Emit every event as JSONL
+    Emit(Box<calendar::EmitOptions>),
+
+    /// Emit the times events take up, without what the events are
+    #[command(name = "freebusy")]
+    FreeBusy(Box<calendar::FreeBusyOptions>),
+
+    /// Create an event
+    Create(calendar::CreateOptions),
+
+    /// Change an event by URN
+    Update(calendar::UpdateOptions),
+}
+
+#[derive(Debug, Subcommand)]
+enum ContactsCommand {
+    /// Emit every contact as JSONL
+    Emit(Box<contacts::EmitOptions>),
+
+    /// Emit the contacts that are likely duplicates, without changing any
+    DedupeReport(Box<contacts::DedupeReportOptions>),
+
+    /// Create contacts from records on stdin
+    Create(contacts::CreateOptions),
+
+    /// Change contacts by the records on stdin
+    Update(contacts::UpdateOptions),
+}
+
+#[derive(Debug, Subcommand)]
+enum PhotosCommand {
+    /// Emit the metadata of every photo and video as JSONL
+    Emit(Box<photos::EmitOptions>),
+
+    /// Add keywords to a photo or video, printing its record
+    Tag(photos::TagOptions),
+
+    /// Set the caption of a photo or video, printing its record
+    Caption(photos::CaptionOptions),
+}
+
+#[derive(Debug, Subcommand)]
+enum MailCommand {
+    /// Emit every conversation and message as JSONL
+    Emit(Box<mail::EmitOptions>),
+
+    /// Emit the rules and smart mailboxes mail is organized by as JSONL
+    Rules(Box<mail::RulesOptions>),
+}
+
+#[derive(Debug, Subcommand)]
+enum MessagesCommand {
+    /// Emit every message as JSONL
+    Emit(Box<messages::EmitOptions>),
+}
+
+#[derive(Debug, Subcommand)]
+enum MusicCommand {
+    /// Emit every track in the library as JSONL
+    Emit(Box<music::EmitOptions>),
+}
+
+/// Rewrites `asimov-apple-notes-emitter ARGS…` into
+/// `asimov-apple notes emit ARGS…`, leaving other invocations alone.
+fn expand_multicall(mut args: Vec<OsString>) -> Vec<OsString> {
+    let program = args
+        .first()
+        .and_then(|arg0| Path::new(arg0).file_stem())
+        .and_then(|stem| stem.to_str());
+
+    if let Some((_, subcommands)) = MULTICALL_NAMES
+        .iter()
+        .find(|(name, _)| Some(*name) == program)
+    {
+        args.splice(1..1, subcommands.iter().map(OsString::from));
+    }
+
+    args
+}
+
+pub fn main() -> Result<SysexitsError, Box<dyn StdError>> {
+    // Load environment variables from `.env`:
+    asimov_module::dotenv().ok();
+
+    // Expand wildcards and @argfiles:
+    let args = expand_multicall(asimov_module::args_os()?);
+
+    // Parse command-line options:
+    let options = Options::parse_from(args);
+
+    // Handle the `--version` and `--license` flags:
+    if let Some(exit_code) = cli::handle_standard_flags(&options.flags) {
+        return Ok(exit_code);
+    }
+
+    let Some(command) = options.command else {
+        use clap::CommandFactory;
+        Options::command().print_help()?;
+        return Ok(SysexitsError::EX_USAGE);
+    };
+
+    // Configure logging & tracing, and handle Ctrl-C:
+    if let Err(err) = cli::init(&options.flags, &options.common) {
+        return Ok(cli::handle_error(&err));
+    }
+
+    let result = match command {
+        Command::Notes(NotesCommand::Catalog(opts)) => notes::catalog(&opts),
+        Command::Notes(NotesCommand::Emit(opts)) => notes::emit(&opts),
+        Command::Notes(NotesCommand::Fetch(opts)) => notes::fetch(&opts),
+        Command::Reminders(RemindersCommand::Emit(opts)) => reminders::emit(&opts),
+        Command::Reminders(RemindersCommand::Create(opts)) => reminders::create(&opts),
+        Command::Reminders(RemindersCommand::Complete(opts)) => reminders::complete(&opts),
+        Command::Calendar(CalendarCommand::Emit(opts)) => calendar::emit(&opts),
+        Command::Calendar(CalendarCommand::FreeBusy(opts)) => calendar::freebusy(&opts),
+        Command::Calendar(CalendarCommand::Create(opts)) => calendar::create(&opts),
+        Command::Calendar(CalendarCommand::Update(opts)) => calendar::update(&opts),
+        Command::Contacts(ContactsCommand::Emit(opts)) => contacts::emit(&opts),
+        Command::Contacts(ContactsCommand::DedupeReport(opts)) => contacts::dedupe_report(&opts),
+        Command::Contacts(ContactsCommand::Create(opts)) => contacts::create(&opts),
+        Command::Contacts(ContactsCommand::Update(opts)) => contacts::update(&opts),
+        Command::Photos(PhotosCommand::Emit(opts)) => photos::emit(&opts),
+        Command::Photos(PhotosCommand::Tag(opts)) => photos::tag(&opts),
+        Command::Photos(PhotosCommand::Caption(opts)) => photos::caption(&opts),
+        Command::Mail(MailCommand::Emit(opts)) => mail::emit(&opts),
+        Command::Mail(MailCommand::Rules(opts)) => mail::rules(&opts),
+        Command::Messages(MessagesCommand::Emit(opts)) => messages::emit(&opts),
+        Command::Music(MusicCommand::Emit(opts)) => music::emit(&opts),
+        Command::Safari(command) => safari::run(&command),
+        Command::Export(command) => export::run(&command),
+        Command::Import(opts) => import::import(&opts),
+        Command::Index(opts) => search::index(&opts),
+        Command::Search(opts) => search::search(&opts),
+        Command::Digest(opts) => digest::run(&opts),
+        Command::Daemon(opts) => daemon::run(&opts),
+        Command::InstallAgent(opts) => agent::install(&opts),
+        Command::UninstallAgent(opts) => agent::uninstall(&opts),
+        Command::Schema(opts) => schema::print(&opts),
+        Command::ServeHttp(opts) => http::serve(&opts),
+        Command::ServeMcp(opts) => mcp::serve(&opts),
+    };
+
+    Ok(cli::finish(result))
+}
@@ -0,0 +1,323 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads binary property lists (`bplist00`), as Messages keeps in some of
+//! its columns and Safari in its files, where `plutil` can't turn them into
+//! JSON, for the dates and data in them.
+
+use core::cell::Cell;
+use std::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// How deep arrays and dictionaries may nest before the rest is ignored,
+/// so that a list referring to itself can't go on forever.
+const MAX_DEPTH: usize = 32;
+
+/// How many objects may be decoded in all, counting those referred to
+/// more than once each time, so that a small list sharing its objects
+/// can't decode into an enormous one.
+const MAX_DECODED: usize = 1 << 20;
+
+/// A value in a property list.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Plist {
+    Bool(bool),
+    Integer(i64),
+    Real(f64),
+    /// Seconds since 2001-01-01.
+    Date(f64),
+    Data(Vec<u8>),
+    String(String),
+    /// A reference into an archive, as `NSKeyedArchiver` writes.
+    Uid(u64),
+    Array(Vec<Plist>),
+    Dict(Vec<(String, Plist)>),
+}
+
+impl Plist {
+    /// Returns the value for `key`, if this is a dictionary that has it.
+    pub fn get(&self, key: &str) -> Option<&Plist> {
+        match self {
+            Plist::Dict(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Plist]> {
+        match self {
+            Plist::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&[(String, Plist)]> {
+        match self {
+            Plist::Dict(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    pub fn as_data(&self) -> Option<&[u8]> {
+        match self {
+            Plist::Data(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Returns a number, or a date, as seconds since 2001-01-01.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Plist::Integer(n) => Some(n as f64),
+            Plist::Real(n) | Plist::Date(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Plist::Integer(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a binary property list, or returns `None` if `data` isn't one
+/// or is cut short.
+pub fn parse(data: &[u8]) -> Option<Plist> {
+    if !data.starts_with(b"bplist00") || data.len() < 8 + 32 {
+        return None;
+    }
+    let trailer = &data[data.len() - 32..];
+    let reader = Reader {
+        data,
+        offset_size: usize::from(trailer[6]),
+        ref_size: usize::from(trailer[7]),
+        objects: usize::try_from(be(&trailer[8..16])).ok()?,
+        offsets: usize::try_from(be(&trailer[24..32])).ok()?,
+        decoded: Cell::new(0),
+    };
+    let top = usize::try_from(be(&trailer[16..24])).ok()?;
+    reader.object(top, 0)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset_size: usize,
+    ref_size: usize,
+    objects: usize,
+    /// Where the table of where each object starts is.
+    offsets: usize,
+    /// How many objects were decoded so far.
+    decoded: Cell<usize>,
+}
+
+impl Reader<'_> {
+    fn object(&self, index: usize, depth: usize) -> Option<Plist> {
+        if index >= self.objects || depth > MAX_DEPTH || self.decoded.get() >= MAX_DECODED {
+            return None;
+        }
+        self.decoded.set(self.decoded.get() + 1);
+        let entry = self
+            .offsets
+            .checked_add(index.checked_mul(self.offset_size)?)?;
+        let start = usize::try_from(be(self.bytes(entry, self.offset_size)?)).ok()?;
+        let marker = *self.data.get(start)?;
+        let info = usize::from(marker & 0x0F);
+        let rest = start.checked_add(1)?;
+        Some(match marker >> 4 {
+            0x0 => match marker {
+                0x08 => Plist::Bool(false),
+                0x09 => Plist::Bool(true),
+                _ => return None,
+            },
+            0x1 => {
+                let bytes = self.bytes(rest, 1 << info)?;
+                // Only those of 8 bytes are signed; 16 are kept to their
+                // low 8:
+                Plist::Integer(be(&bytes[bytes.len().saturating_sub(8)..]) as i64)
+            }
+            0x2 => Plist::Real(self.real(rest, 1 << info)?),
+            0x3 => Plist::Date(self.real(rest, 8)?),
+            0x4 => {
+                let (length, start) = self.length(info, rest)?;
+                Plist::Data(self.bytes(start, length)?.to_vec())
+            }
+            0x5 => {
+                let (length, start) = self.length(info, rest)?;
+                let bytes = self.bytes(start, length)?;
+                Plist::String(String::from_utf8_lossy(bytes).into_owned())
+            }
+            0x6 => {
+                let (length, start) = self.length(info, rest)?;
+                let bytes = self.bytes(start, length.checked_mul(2)?)?;
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                    .collect();
+                Plist::String(String::from_utf16_lossy(&units))
+            }
+            0x8 => Plist::Uid(be(self.bytes(rest, info + 1)?)),
+            0xA => {
+                let (length, start) = self.length(info, rest)?;
+                let items = (0..length)
+                    .map(|n| self.object(self.reference(start, n)?, depth + 1))
+                    .collect::<Option<_>>()?;
+                Plist::Array(items)
+            }
+            0xD => {
+                let (length, start) = self.length(info, rest)?;
+                let entries = (0..length)
+                    .map(|n| {
+                        let key = match self.object(self.reference(start, n)?, depth + 1)? {
+                            Plist::String(key) => key,
+                            key => key.as_i64()?.to_string(),
+                        };
+                        let value = self.reference(start, length.checked_add(n)?)?;
+                        Some((key, self.object(value, depth + 1)?))
+                    })
+                    .collect::<Option<_>>()?;
+                Plist::Dict(entries)
+            }
+            _ => return None,
+        })
+    }
+
+    /// Returns the length of an object, in its marker or, if too long for
+    /// it, in the integer after, and where its content starts.
+    fn length(&self, info: usize, rest: usize) -> Option<(usize, usize)> {
+        if info != 0x0F {
+            return Some((info, rest));
+        }
+        let marker = *self.data.get(rest)?;
+        if marker >> 4 != 0x1 {
+            return None;
+        }
+        let size = 1 << (marker & 0x0F);
+        let start = rest.checked_add(1)?;
+        let length = usize::try_from(be(self.bytes(start, size)?)).ok()?;
+        Some((length, start.checked_add(size)?))
+    }
+
+    /// Returns the `length` bytes at `start`, if all there.
+    fn bytes(&self, start: usize, length: usize) -> Option<&[u8]> {
+        self.data.get(start..start.checked_add(length)?)
+    }
+
+    /// Returns the index of the `n`th object an array or dictionary
+    /// starting at `start` refers to.
+    fn reference(&self, start: usize, n: usize) -> Option<usize> {
+        let at = start.checked_add(n.checked_mul(self.ref_size)?)?;
+        usize::try_from(be(self.bytes(at, self.ref_size)?)).ok()
+    }
+
+    fn real(&self, start: usize, size: usize) -> Option<f64> {
+        let bytes = self.bytes(start, size)?;
+        match size {
+            4 => Some(f64::from(f32::from_be_bytes(bytes.try_into().ok()?))),
+            8 => Some(f64::from_be_bytes(bytes.try_into().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a big-endian unsigned integer of up to 8 bytes.
+fn be(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .take(8)
+        .fold(0, |value, &byte| value << 8 | u64::from(byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+
+    /// Appends the offset table and the trailer to the objects after the
+    /// header in `data`, which start at `offsets`, with 1-byte offsets and
+    /// references, `top` being the top object.
+    fn finish(mut data: Vec<u8>, offsets: &[u8], top: u8) -> Vec<u8> {
+        let table = data.len() as u64;
+        data.extend_from_slice(offsets);
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 1]);
+        data.extend_from_slice(&(offsets.len() as u64).to_be_bytes());
+        data.extend_from_slice(&u64::from(top).to_be_bytes());
+        data.extend_from_slice(&table.to_be_bytes());
+        data
+    }
+
+    /// `{ "a": 1, "b": [true, "hi"] }`.
+    fn sample() -> Vec<u8> {
+        let mut data = b"bplist00".to_vec();
+        data.extend_from_slice(&[0xD2, 1, 2, 3, 4]); // 8: the dictionary
+        data.extend_from_slice(&[0x51, b'a']); // 13
+        data.extend_from_slice(&[0x51, b'b']); // 15
+        data.extend_from_slice(&[0x10, 1]); // 17
+        data.extend_from_slice(&[0xA2, 5, 6]); // 19: the array
+        data.push(0x09); // 22
+        data.extend_from_slice(&[0x52, b'h', b'i']); // 23
+        finish(data, &[8, 13, 15, 17, 19, 22, 23], 0)
+    }
+
+    #[test]
+    fn parses_a_hand_built_list() {
+        assert_eq!(
+            parse(&sample()),
+            Some(Plist::Dict(vec![
+                ("a".to_string(), Plist::Integer(1)),
+                (
+                    "b".to_string(),
+                    Plist::Array(vec![Plist::Bool(true), Plist::String("hi".to_string())])
+                ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn rejects_a_truncated_list() {
+        // Cut short, the trailer is read from what comes before it, which
+        // must never panic:
+        let data = sample();
+        let whole = parse(&data);
+        for length in 0..data.len() {
+            assert_ne!(parse(&data[..length]), whole, "cut to {length} bytes");
+        }
+
+        // A string of 255 bytes, with 2 there:
+        let mut data = b"bplist00".to_vec();
+        data.extend_from_slice(&[0x5F, 0x10, 0xFF, b'h', b'i']);
+        assert_eq!(parse(&finish(data, &[8], 0)), None);
+    }
+
+    #[test]
+    fn rejects_an_overflowing_trailer() {
+        let mut data = sample();
+        let trailer = data.len() - 32;
+        data[trailer + 6] = 0xFF;
+        data[trailer + 8..trailer + 16].copy_from_slice(&u64::MAX.to_be_bytes());
+        data[trailer + 24..trailer + 32].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(parse(&data), None);
+
+        let mut data = sample();
+        data[trailer + 16..trailer + 24].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(parse(&data), None);
+    }
+
+    #[test]
+    fn caps_shared_references() {
+        // Each array refers twice to the one before, doubling what it
+        // decodes into, 2^30 strings in all:
+        let mut data = b"bplist00".to_vec();
+        let mut offsets = vec![8];
+        data.extend_from_slice(&[0x51, b'x']);
+        for n in 0..30 {
+            offsets.push(data.len() as u8);
+            data.extend_from_slice(&[0xA2, n, n]);
+        }
+        assert_eq!(parse(&finish(data, &offsets, 30)), None);
+    }
+}
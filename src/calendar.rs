@@ -0,0 +1,1093 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Apple Calendar, read through EventKit from JavaScript for Automation,
+//! which, unlike the Calendar AppleScript dictionary, gives time zones and
+//! works without Calendar running.
+
+use crate::{
+    Error, Result,
+    eventkit::{self, Entity},
+    recurrence::{self, SCRIPT as RECURRENCE_SCRIPT},
+    timezone::{self, LocalTime, TimeZone},
+};
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeSet,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The application name used in error messages.
+pub const APP: &str = "Calendar";
+
+/// How events are extracted, as recorded in their provenance.
+pub const BACKEND: &str = "eventkit";
+
+/// The prefix of the URNs identifying individual events.
+pub const URN_PREFIX: &str = "urn:apple:calendar:event:";
+
+/// The prefix of the URNs identifying calendars.
+pub const CALENDAR_URN_PREFIX: &str = "urn:apple:calendar:calendar:";
+
+/// The prefix of the URNs identifying busy intervals.
+pub const BUSY_URN_PREFIX: &str = "urn:apple:calendar:busy:";
+
+/// How many days before and after now events are extracted from by
+/// default, as EventKit lists every occurrence of a recurring event.
+pub const WINDOW_DAYS: u32 = 90;
+
+/// The longest span EventKit looks through at once (it looks through at
+/// most four years); longer windows are read a span at a time.
+pub(crate) const SPAN_DAYS: u32 = 1460;
+
+/// The JavaScript that defines `record(e)` and `calendarRecord(c)`,
+/// describing event `e` and calendar `c` in the format [`parse`] reads. Times come with the offset of the event's time
+/// zone, unless it floats (happening at the same local time anywhere), and
+/// all-day events span dates, the end inclusive. Occurrences are told
+/// apart by when they were to start originally, in the event's time zone.
+/// It goes after [`recurrence::SCRIPT`].
+const RECORD_SCRIPT: &str = r#"
+function zoned(value, zone) {
+    if (nil(value)) return null;
+    if (nil(zone)) return date(value);
+    var f = $.NSDateFormatter.alloc.init;
+    f.locale = formatter.locale;
+    f.timeZone = zone;
+    f.dateFormat = "yyyy-MM-dd'T'HH:mm:ssxxx";
+    return f.stringFromDate(value).js;
+}
+function wallClock(value, zone, allDay) {
+    var time = zoned(value, zone);
+    return time === null ? null : time.slice(0, allDay ? 10 : 19);
+}
+function calendarRecord(c) {
+    return {
+        id: c.calendarIdentifier.js,
+        name: string(c.title) || '',
+        color: color(c),
+        account: account(c),
+        type: c.type,
+        readOnly: !c.allowsContentModifications,
+    };
+}
+function person(p) {
+    if (nil(p)) return null;
+    var url = nil(p.URL) ? null : p.URL.absoluteString.js;
+    return {
+        name: string(p.name),
+        email: url !== null && url.indexOf('mailto:') === 0 ? decodeURIComponent(url.slice(7)) : null,
+        status: p.participantStatus,
+        role: p.participantRole,
+    };
+}
+function people(list) {
+    var found = [];
+    for (var i = 0; !nil(list) && i < list.count; i++) found.push(person(list.objectAtIndex(i)));
+    return found;
+}
+function alarms(e) {
+    var found = [];
+    for (var i = 0; !nil(e.alarms) && i < e.alarms.count; i++) {
+        var a = e.alarms.objectAtIndex(i);
+        found.push(nil(a.absoluteDate)
+            ? { offset: a.relativeOffset, type: a.type }
+            : { time: zoned(a.absoluteDate, e.timeZone), type: a.type });
+    }
+    return found;
+}
+function place(e) {
+    var p = e.structuredLocation;
+    if (nil(p)) return null;
+    var point = nil(p.geoLocation) ? null : p.geoLocation.coordinate;
+    return {
+        name: string(p.title),
+        latitude: point === null ? null : point.latitude,
+        longitude: point === null ? null : point.longitude,
+        radius: p.radius > 0 ? p.radius : null,
+    };
+}
+function record(e) {
+    var zone = e.timeZone;
+    var day = function (value) { var d = date(value); return d === null ? null : d.slice(0, 10); };
+    return {
+        id: e.calendarItemIdentifier.js,
+        occurrence: e.hasRecurrenceRules ? wallClock(e.occurrenceDate, zone, e.allDay) : null,
+        detached: e.isDetached,
+        title: string(e.title) || '',
+        notes: string(e.notes),
+        url: nil(e.URL) ? null : e.URL.absoluteString.js,
+        location: string(e.location),
+        place: place(e),
+        allDay: e.allDay,
+        start: e.allDay ? day(e.startDate) : zoned(e.startDate, zone),
+        end: e.allDay ? day(e.endDate) : zoned(e.endDate, zone),
+        timeZone: nil(zone) ? null : zone.name.js,
+        status: e.status,
+        free: e.availability === 1,
+        organizer: person(e.organizer),
+        attendees: people(e.attendees),
+        alarms: alarms(e),
+        calendar: string(e.calendar.title) || '',
+        account: account(e.calendar),
+        created: date(e.creationDate),
+        modified: date(e.lastModifiedDate),
+    };
+}
+"#;
+
+/// Which events to extract: those that overlap the window from `from` to
+/// `to`, each defaulting to [`WINDOW_DAYS`] away from now, in the
+/// calendars selected.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub from: Option<LocalTime>,
+    pub to: Option<LocalTime>,
+    /// Only the events in calendars by these names, if any are given.
+    pub calendars: Vec<String>,
+    /// None of the events in calendars by these names.
+    pub excluded_calendars: Vec<String>,
+    /// Whether to read the Birthdays calendar.
+    pub birthdays: bool,
+    /// Whether to read subscribed calendars, such as holiday calendars.
+    pub subscribed: bool,
+    /// Whether to look up, with CoreLocation, where the locations of
+    /// events that have no coordinates are.
+    pub geocode: bool,
+}
+
+/// Everything a script extracts: the calendars selected, every event in
+/// the window, with each occurrence of a recurring event on its own, and
+/// the recurring events themselves.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Library {
+    pub calendars: Vec<Calendar>,
+    pub events: Vec<Event>,
+    /// The recurring events that occur in the window, each as it first
+    /// occurs, with its rule and exception dates.
+    pub masters: Vec<Event>,
+}
+
+impl Library {
+    /// Returns the occurrences of the recurring event `master` that were
+    /// moved, or otherwise changed, on their own.
+    pub fn detached<'a>(&'a self, master: &'a Event) -> impl Iterator<Item = &'a Event> {
+        self.events
+            .iter()
+            .filter(move |event| event.detached && event.id == master.id)
+    }
+}
+
+/// What kind of calendar a calendar is, as EventKit has it
+/// (`EKCalendarType`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CalendarType {
+    /// Kept on this Mac only.
+    #[default]
+    Local,
+    /// Kept on a CalDAV server, as iCloud and Google calendars are.
+    CalDav,
+    Exchange,
+    /// Subscribed to, read-only, as holiday calendars are.
+    Subscription,
+    /// Made up of the birthdays in Contacts.
+    Birthday,
+}
+
+impl CalendarType {
+    /// Returns the name of the type, as in `caldav`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CalendarType::Local => "local",
+            CalendarType::CalDav => "caldav",
+            CalendarType::Exchange => "exchange",
+            CalendarType::Subscription => "subscription",
+            CalendarType::Birthday => "birthday",
+        }
+    }
+}
+
+/// A calendar, which events are part of.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Calendar {
+    pub id: String,
+    pub name: String,
+    /// The calendar's color in Calendar, as in `#1BADF8`.
+    pub color: Option<String>,
+    pub account: String,
+    pub kind: CalendarType,
+    /// Whether its events can't be changed, as in subscribed calendars
+    /// and the Birthdays calendar.
+    pub read_only: bool,
+}
+
+impl Calendar {
+    /// Returns the stable URN identifying this calendar.
+    pub fn urn(&self) -> String {
+        format!("{CALENDAR_URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this calendar.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "DataFeed",
+            "@id": self.urn(),
+            "name": self.name,
+            "color": self.color,
+            "account": self.account,
+            "calendarType": self.kind.as_str(),
+            "readOnly": self.read_only.then_some(true),
+            "source": "apple-calendar",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Someone taking part in an event, as its organizer or an attendee.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Person {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    /// Whether they have accepted the invitation, as EventKit has it
+    /// (`EKParticipantStatus`).
+    pub status: u8,
+    /// Whether they are required to attend, as EventKit has it
+    /// (`EKParticipantRole`).
+    pub role: u8,
+}
+
+impl Person {
+    /// Returns `accepted`, `declined`, and so on, or `None` if unknown.
+    pub fn status_label(&self) -> Option<&'static str> {
+        match self.status {
+            1 => Some("pending"),
+            2 => Some("accepted"),
+            3 => Some("declined"),
+            4 => Some("tentative"),
+            5 => Some("delegated"),
+            6 => Some("completed"),
+            7 => Some("in-process"),
+            _ => None,
+        }
+    }
+
+    /// Returns `required`, `optional`, `chair`, or `non-participant`, or
+    /// `None` if unknown.
+    pub fn role_label(&self) -> Option<&'static str> {
+        match self.role {
+            1 => Some("required"),
+            2 => Some("optional"),
+            3 => Some("chair"),
+            4 => Some("non-participant"),
+            _ => None,
+        }
+    }
+
+    /// Returns the JSON-LD `Person` for this participant.
+    pub fn to_json(&self) -> Value {
+        let mut person = json!({
+            "@type": "Person",
+            "name": self.name,
+            "email": self.email,
+            "participationStatus": self.status_label(),
+            "role": self.role_label(),
+        });
+        if let Some(person) = person.as_object_mut() {
+            person.retain(|_, value| !value.is_null());
+        }
+        person
+    }
+}
+
+/// How an alarm notifies, as EventKit has it (`EKAlarmType`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlarmType {
+    /// With a notification.
+    #[default]
+    Display,
+    /// With a notification and a sound.
+    Audio,
+    /// By opening a file.
+    Procedure,
+    /// By email.
+    Email,
+}
+
+impl AlarmType {
+    /// Returns the name of the type, as in `display`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlarmType::Display => "display",
+            AlarmType::Audio => "audio",
+            AlarmType::Procedure => "procedure",
+            AlarmType::Email => "email",
+        }
+    }
+}
+
+/// When, and how, an event notifies of itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Alarm {
+    /// How many seconds after the event starts it goes off (before, if
+    /// negative), unless it goes off at `time`.
+    pub offset: Option<i64>,
+    /// When it goes off, in the form of the event's `start`.
+    pub time: Option<String>,
+    pub kind: AlarmType,
+}
+
+impl Alarm {
+    /// Returns the alarm as JSON, its offset an ISO 8601 duration, as in
+    /// `-PT15M`.
+    pub fn to_json(&self) -> Value {
+        let mut alarm = json!({
+            "offset": self.offset.map(duration),
+            "time": self.time,
+            "alarmType": self.kind.as_str(),
+        });
+        if let Some(alarm) = alarm.as_object_mut() {
+            alarm.retain(|_, value| !value.is_null());
+        }
+        alarm
+    }
+}
+
+/// Returns `seconds` as an ISO 8601 duration, as iCalendar writes
+/// triggers: `-P1D`, `-PT1H30M`, `PT0S`.
+fn duration(seconds: i64) -> String {
+    let sign = if seconds < 0 { "-" } else { "" };
+    let seconds = seconds.unsigned_abs();
+    if seconds > 0 && seconds.is_multiple_of(86400) {
+        return match seconds.is_multiple_of(7 * 86400) {
+            true => format!("{sign}P{}W", seconds / (7 * 86400)),
+            false => format!("{sign}P{}D", seconds / 86400),
+        };
+    }
+    let (days, hours, minutes, seconds) = (
+        seconds / 86400,
+        seconds % 86400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+    );
+    let mut text = format!("{sign}P");
+    if days > 0 {
+        text += &format!("{days}D");
+    }
+    text.push('T');
+    if hours > 0 {
+        text += &format!("{hours}H");
+    }
+    if minutes > 0 {
+        text += &format!("{minutes}M");
+    }
+    if seconds > 0 || (hours == 0 && minutes == 0) {
+        text += &format!("{seconds}S");
+    }
+    text
+}
+
+/// How sure an event is to happen, as EventKit has it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Status {
+    #[default]
+    Confirmed,
+    Tentative,
+    Cancelled,
+}
+
+/// Where an event's location is: the structured location EventKit keeps
+/// for places picked from Maps, or else where CoreLocation found it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Place {
+    /// The place's name, as in `Apple Park`.
+    pub name: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// How far the place extends, in meters.
+    pub radius: Option<f64>,
+}
+
+/// A single event, or one occurrence of a recurring one, as EventKit
+/// describes it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Event {
+    pub id: String,
+    /// For an occurrence of a recurring event, when it was to happen
+    /// originally (even if since moved), in ISO 8601 local time.
+    pub occurrence: Option<String>,
+    pub title: String,
+    pub notes: Option<String>,
+    pub url: Option<String>,
+    pub location: Option<String>,
+    /// Where the location is, if known.
+    pub place: Option<Place>,
+    pub all_day: bool,
+    /// When the event starts, in ISO 8601 with the offset of its time
+    /// zone, as in `2025-01-20T13:30:00+01:00`, or for an all-day event,
+    /// the date it starts on.
+    pub start: Option<String>,
+    /// When the event ends, as for `start`; for an all-day event, the last
+    /// date it spans.
+    pub end: Option<String>,
+    /// The IANA name of the event's time zone, or `None` if it floats.
+    pub time_zone: Option<String>,
+    pub status: Status,
+    /// Whether the event leaves the time it takes free, as events shown
+    /// as Free do.
+    pub free: bool,
+    pub organizer: Option<Person>,
+    pub attendees: Vec<Person>,
+    pub alarms: Vec<Alarm>,
+    pub calendar: String,
+    pub account: String,
+    pub created: Option<String>,
+    pub modified: Option<String>,
+    /// For a recurring event as a whole, how it recurs.
+    pub recurrence: Option<recurrence::Rule>,
+    /// For a recurring event as a whole, when in the window it was to
+    /// occur but doesn't, in the form of `occurrence`.
+    pub exceptions: Vec<String>,
+    /// Whether this occurrence was changed on its own.
+    pub detached: bool,
+}
+
+impl Event {
+    /// Returns the URN of the recurring event this is an occurrence of.
+    pub fn master_urn(&self) -> String {
+        format!("{URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the stable URN identifying this event, or occurrence.
+    pub fn urn(&self) -> String {
+        match &self.occurrence {
+            Some(occurrence) => format!("{URN_PREFIX}{}/{occurrence}", self.id),
+            None => format!("{URN_PREFIX}{}", self.id),
+        }
+    }
+
+    /// Returns when the event takes place, from its start up to its end,
+    /// in seconds since the epoch. All-day events take up the dates they
+    /// span whole, and they and floating events are taken in `local` time.
+    pub fn span(&self, local: &TimeZone) -> Option<(i64, i64)> {
+        let (start, end) = (self.start.as_deref()?, self.end.as_deref()?);
+        match self.all_day {
+            true => Some((
+                local.instant(&format!("{start}T00:00:00"))?,
+                local.instant(&format!("{end}T23:59:59"))? + 1,
+            )),
+            false => Some((local.instant(start)?, local.instant(end)?)),
+        }
+    }
+
+    /// Returns where the event takes place as a schema.org `Place`, with
+    /// `latitude`, `longitude`, and `radius` (in meters) when known.
+    fn location_json(&self) -> Option<Value> {
+        let place = self.place.clone().unwrap_or_default();
+        let name = self.location.as_ref().or(place.name.as_ref());
+        if name.is_none() && place.latitude.is_none() {
+            return None;
+        }
+        let mut location = json!({
+            "@type": "Place",
+            "name": name,
+            "latitude": place.latitude,
+            "longitude": place.longitude,
+            "radius": place.radius,
+        });
+        if let Some(location) = location.as_object_mut() {
+            location.retain(|_, value| !value.is_null());
+        }
+        Some(location)
+    }
+
+    /// Returns the JSON-LD record for this event. Unset fields are left
+    /// out.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "Event",
+            "@id": self.urn(),
+            "name": self.title,
+            "description": self.notes,
+            "startDate": self.start,
+            "endDate": self.end,
+            "timeZone": self.time_zone,
+            "allDay": self.all_day.then_some(true),
+            "location": self.location_json(),
+            "url": self.url,
+            "eventStatus": match self.status {
+                Status::Cancelled => "EventCancelled",
+                _ => "EventScheduled",
+            },
+            "tentative": (self.status == Status::Tentative).then_some(true),
+            "organizer": self.organizer.as_ref().map(Person::to_json),
+            "attendee": match self.attendees.is_empty() {
+                true => Value::Null,
+                false => self.attendees.iter().map(Person::to_json).collect(),
+            },
+            "alarm": match self.alarms.is_empty() {
+                true => Value::Null,
+                false => self.alarms.iter().map(Alarm::to_json).collect(),
+            },
+            "recurrenceRule": self.recurrence.as_ref().map(ToString::to_string),
+            "exceptionDates": match self.exceptions.is_empty() {
+                true => Value::Null,
+                false => json!(self.exceptions),
+            },
+            "recurrenceOf": self.occurrence.as_ref().map(|_| self.master_urn()),
+            "isPartOf": self.calendar,
+            "account": self.account,
+            "dateCreated": self.created,
+            "dateModified": self.modified,
+            "source": "apple-calendar",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// A stretch of time taken up by events, in one calendar or any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Busy<'a> {
+    /// The calendar the events are in, if counted on their own.
+    pub calendar: Option<&'a Calendar>,
+    /// When it starts and ends, in seconds since the epoch.
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Busy<'_> {
+    /// Returns the stable URN identifying this interval.
+    pub fn urn(&self) -> String {
+        let (start, end) = (timezone::iso8601(self.start), timezone::iso8601(self.end));
+        match self.calendar {
+            Some(calendar) => format!("{BUSY_URN_PREFIX}{}/{start}/{end}", calendar.id),
+            None => format!("{BUSY_URN_PREFIX}{start}/{end}"),
+        }
+    }
+
+    /// Returns the JSON-LD record for this interval, which says nothing of
+    /// the events in it.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "Schedule",
+            "@id": self.urn(),
+            "startDate": timezone::iso8601(self.start),
+            "endDate": timezone::iso8601(self.end),
+            "isPartOf": self.calendar.map(|calendar| &calendar.name),
+            "account": self.calendar.map(|calendar| &calendar.account),
+            "source": "apple-calendar",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Returns the stretches of time `events` take up within `window` (its
+/// start and end in seconds since the epoch), merging those that overlap
+/// or meet, in order. Events shown as free, and cancelled ones, take up
+/// none.
+pub fn busy<'a>(
+    events: impl IntoIterator<Item = &'a Event>,
+    window: (i64, i64),
+    local: &TimeZone,
+) -> Vec<(i64, i64)> {
+    let (from, to) = window;
+    let mut spans: Vec<(i64, i64)> = events
+        .into_iter()
+        .filter(|event| !event.free && event.status != Status::Cancelled)
+        .filter_map(|event| event.span(local))
+        .map(|(start, end)| (start.max(from), end.min(to)))
+        .filter(|(start, end)| start < end)
+        .collect();
+    spans.sort_unstable();
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Returns the script that describes the calendars and events `filter`
+/// selects, as a JSON object with `calendars`, `events`, and `masters`
+/// arrays, and the window, as `from` and `to`.
+pub fn script(filter: &Filter) -> String {
+    let prelude = eventkit::prelude(Entity::Event);
+    let window = i64::from(WINDOW_DAYS) * 86400;
+    let span = i64::from(SPAN_DAYS) * 86400;
+    let filter = json!({
+        "from": filter.from.as_ref().map(LocalTime::as_str),
+        "to": filter.to.as_ref().map(LocalTime::as_str),
+        "calendars": filter.calendars,
+        "excluded": filter.excluded_calendars,
+        "birthdays": filter.birthdays,
+        "subscribed": filter.subscribed,
+        "geocode": filter.geocode,
+    });
+    format!(
+        r#"
+{prelude}
+{RECURRENCE_SCRIPT}
+{RECORD_SCRIPT}
+var filter = {filter};
+function bound(time, offset) {{
+    return time === null
+        ? $.NSDate.dateWithTimeIntervalSinceNow(offset)
+        : formatter.dateFromString(time);
+}}
+var from = bound(filter.from, -{window});
+var to = bound(filter.to, {window});
+var calendars = [];
+var all = store.calendarsForEntityType($.EKEntityTypeEvent);
+for (var i = 0; i < all.count; i++) {{
+    var c = all.objectAtIndex(i), name = string(c.title);
+    if (filter.calendars.length > 0 && filter.calendars.indexOf(name) < 0) continue;
+    if (filter.excluded.indexOf(name) >= 0) continue;
+    // EKCalendarTypeBirthday and EKCalendarTypeSubscription:
+    if (!filter.birthdays && c.type === 4) continue;
+    if (!filter.subscribed && (c.type === 3 || c.isSubscribed)) continue;
+    calendars.push(c);
+}}
+var output = {{ from: date(from), to: date(to), calendars: calendars.map(calendarRecord), events: [], masters: [] }};
+// Events overlapping two spans come up in both:
+var seen = {{}};
+// EventKit takes no calendars at all to mean every calendar:
+for (var start = from; calendars.length > 0 && start.compare(to) < 0; start = start.dateByAddingTimeInterval({span})) {{
+    var end = start.dateByAddingTimeInterval({span});
+    if (end.compare(to) > 0) end = to;
+    var predicate = store.predicateForEventsWithStartDateEndDateCalendars(start, end, calendars);
+    var events = store.eventsMatchingPredicate(predicate);
+    for (var i = 0; i < events.count; i++) {{
+        var e = record(events.objectAtIndex(i));
+        var key = e.id + '/' + e.occurrence;
+        if (seen[key]) continue;
+        seen[key] = true;
+        output.events.push(e);
+        if (e.occurrence !== null && !seen[e.id]) {{
+            seen[e.id] = true;
+            var m = store.calendarItemWithIdentifier(e.id);
+            if (nil(m)) continue;
+            var master = record(m);
+            master.occurrence = null;
+            master.detached = false;
+            master.recurrence = recurrence(m);
+            output.masters.push(master);
+        }}
+    }}
+}}
+if (filter.geocode) {{
+    ObjC.import('CoreLocation');
+    var geocoder = $.CLGeocoder.alloc.init;
+    // CoreLocation looks up only so many places a minute, so each
+    // location is looked up once:
+    var found = {{}};
+    var geocode = function (text) {{
+        if (!found.hasOwnProperty(text)) {{
+            var done = false;
+            found[text] = null;
+            geocoder.geocodeAddressStringCompletionHandler(text, function (marks, error) {{
+                if (!nil(marks) && marks.count > 0 && !nil(marks.objectAtIndex(0).location)) {{
+                    var point = marks.objectAtIndex(0).location.coordinate;
+                    found[text] = {{ name: null, latitude: point.latitude, longitude: point.longitude, radius: null }};
+                }}
+                done = true;
+            }});
+            wait(function () {{ return done; }});
+        }}
+        return found[text];
+    }};
+    output.events.concat(output.masters).forEach(function (e) {{
+        if (e.location === null || (e.place !== null && e.place.latitude !== null)) return;
+        e.place = geocode(e.location);
+    }});
+}}
+JSON.stringify(output);
+"#
+    )
+}
+
+/// The JavaScript that defines `findCalendar(name)`, `apply(e, input)`,
+/// which makes the changes in `input` (see [`Changes`]) to event `e`, and
+/// `save(e, span)`. It goes after [`RECORD_SCRIPT`].
+const SAVE_SCRIPT: &str = r#"
+function findCalendar(name) {
+    var all = store.calendarsForEntityType($.EKEntityTypeEvent);
+    for (var i = 0; i < all.count; i++) {
+        if (string(all.objectAtIndex(i).title) === name) return all.objectAtIndex(i);
+    }
+    return null;
+}
+function apply(e, input) {
+    if (input.title !== null) e.title = input.title;
+    if (input.location !== null) e.location = input.location;
+    if (input.notes !== null) e.notes = input.notes;
+    if (input.start !== null) {
+        // A new start keeps the event as long as it was:
+        var length = nil(e.startDate) || nil(e.endDate)
+            ? (input.timed ? 3600 : 0)
+            : e.endDate.timeIntervalSinceDate(e.startDate);
+        e.allDay = !input.timed;
+        e.startDate = formatter.dateFromString(input.start);
+        e.endDate = e.startDate.dateByAddingTimeInterval(length);
+    }
+    if (input.end !== null) e.endDate = formatter.dateFromString(input.end);
+    if (input.attendees.length > 0) {
+        // EventKit has no public way to invite people, only its own class:
+        if ($.EKAttendee === undefined) {
+            throw new Error('This version of macOS does not let attendees be added.');
+        }
+        var people = $.NSMutableArray.arrayWithArray(nil(e.attendees) ? $.NSArray.array : e.attendees);
+        input.attendees.forEach(function (email) {
+            var attendee = $.EKAttendee.alloc.init;
+            attendee.setValueForKey(email, 'emailAddress');
+            people.addObject(attendee);
+        });
+        e.setValueForKey(people, 'attendees');
+    }
+}
+function save(e, span) {
+    var error = Ref();
+    if (!store.saveEventSpanCommitError(e, span, true, error)) {
+        throw new Error('Could not save the event: ' + error[0].localizedDescription.js);
+    }
+}
+"#;
+
+/// What to set on an event: each field given, and the attendees added.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub title: Option<String>,
+    /// When the event starts: a date makes it an all-day event.
+    pub start: Option<LocalTime>,
+    /// When the event ends; for an all-day event, the last date it spans.
+    pub end: Option<LocalTime>,
+    pub location: Option<String>,
+    pub notes: Option<String>,
+    /// The email addresses of the people to add as attendees.
+    pub attendees: Vec<String>,
+}
+
+impl Changes {
+    fn to_json(&self) -> Value {
+        json!({
+            "title": self.title,
+            "start": self.start.as_ref().map(LocalTime::as_str),
+            "end": self.end.as_ref().map(LocalTime::as_str),
+            "timed": self.start.as_ref().is_some_and(LocalTime::has_time),
+            "location": self.location,
+            "notes": self.notes,
+            "attendees": self.attendees,
+        })
+    }
+}
+
+/// Returns the script that creates an event, as `changes` has it, in the
+/// calendar named `calendar`, or else in the default calendar for new
+/// events, and describes it as [`script`] does, the calendar not found
+/// leaving `events` empty. Without an end, it lasts an hour, or for an
+/// all-day event, the day.
+pub fn create_script(calendar: Option<&str>, changes: &Changes) -> String {
+    let input = changes.to_json();
+    let calendar = json!(calendar);
+    let prelude = eventkit::prelude(Entity::Event);
+    format!(
+        r#"
+{prelude}
+{RECURRENCE_SCRIPT}
+{RECORD_SCRIPT}
+{SAVE_SCRIPT}
+var input = {input};
+var name = {calendar};
+var calendar = name === null ? store.defaultCalendarForNewEvents : findCalendar(name);
+var output = {{ calendars: [], events: [], masters: [] }};
+if (!nil(calendar)) {{
+    var e = $.EKEvent.eventWithEventStore(store);
+    e.calendar = calendar;
+    apply(e, input);
+    save(e, $.EKSpanThisEvent);
+    output.calendars.push(calendarRecord(calendar));
+    output.events.push(record(e));
+}}
+JSON.stringify(output);
+"#
+    )
+}
+
+/// Returns the script that changes the event `id` as `changes` has it,
+/// moving it to the calendar named `calendar` if given, and describes it
+/// as [`script`] does, the event or calendar not found leaving `events`
+/// empty. For a recurring event, `occurrence` (when it was to happen
+/// originally, as in its URN) picks the one occurrence to change;
+/// without it, the changes apply to every occurrence.
+pub fn update_script(
+    id: &str,
+    occurrence: Option<&str>,
+    calendar: Option<&str>,
+    changes: &Changes,
+) -> String {
+    let input = changes.to_json();
+    let target = json!({ "id": id, "occurrence": occurrence, "calendar": calendar });
+    let prelude = eventkit::prelude(Entity::Event);
+    format!(
+        r#"
+{prelude}
+{RECURRENCE_SCRIPT}
+{RECORD_SCRIPT}
+{SAVE_SCRIPT}
+var input = {input};
+var target = {target};
+var e = null;
+if (target.occurrence === null) {{
+    e = store.calendarItemWithIdentifier(target.id);
+}} else {{
+    // The occurrence is somewhere within a day or so of when it was to
+    // happen, whatever its time zone:
+    var around = formatter.dateFromString(target.occurrence.length > 10
+        ? target.occurrence : target.occurrence + 'T00:00:00');
+    var predicate = store.predicateForEventsWithStartDateEndDateCalendars(
+        around.dateByAddingTimeInterval(-2 * 86400), around.dateByAddingTimeInterval(3 * 86400), $());
+    var events = store.eventsMatchingPredicate(predicate);
+    for (var i = 0; i < events.count; i++) {{
+        var r = record(events.objectAtIndex(i));
+        if (r.id === target.id && r.occurrence === target.occurrence) {{
+            e = events.objectAtIndex(i);
+            break;
+        }}
+    }}
+}}
+var calendar = target.calendar === null || nil(e) ? null : findCalendar(target.calendar);
+var output = {{ calendars: [], events: [], masters: [] }};
+if (!nil(e) && (target.calendar === null || !nil(calendar))) {{
+    if (!nil(calendar)) e.calendar = calendar;
+    apply(e, input);
+    save(e, target.occurrence === null ? $.EKSpanFutureEvents : $.EKSpanThisEvent);
+    output.calendars.push(calendarRecord(e.calendar));
+    output.events.push(record(e));
+}}
+JSON.stringify(output);
+"#
+    )
+}
+
+/// Extracts the event id from an event URN, also accepting a bare
+/// EventKit id, with the original start of the occurrence, if the URN is
+/// one of an occurrence.
+pub fn id_from_urn(input: &str) -> Option<(&str, Option<&str>)> {
+    let input = input.trim();
+    let urn = input.strip_prefix(URN_PREFIX).unwrap_or(input);
+    let (id, occurrence) = match urn.split_once('/') {
+        Some((id, occurrence)) => (id, Some(occurrence)),
+        None => (urn, None),
+    };
+    if id.is_empty() || id.contains(':') {
+        return None;
+    }
+    match occurrence {
+        Some(occurrence) if occurrence.parse::<LocalTime>().is_err() => None,
+        occurrence => Some((id, occurrence)),
+    }
+}
+
+/// Parses the output of [`script`].
+pub fn parse(output: &str) -> Result<Library> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Library::default()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading events",
+            message: e.to_string(),
+        })?,
+    };
+    from_json(&output)
+}
+
+/// Reads the calendars and events of a JSON object as [`script`] prints,
+/// and as [`eventkit::read_events`] returns.
+pub fn from_json(output: &Value) -> Result<Library> {
+    let array = |key: &str| {
+        output
+            .get(key)
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+    };
+    let calendars: Vec<Calendar> = array("calendars")
+        .iter()
+        .map(parse_calendar)
+        .collect::<Result<_>>()?;
+    let events: Vec<Event> = array("events")
+        .iter()
+        .map(parse_event)
+        .collect::<Result<_>>()?;
+    let mut masters: Vec<Event> = array("masters")
+        .iter()
+        .map(parse_event)
+        .collect::<Result<_>>()?;
+
+    // EventKit leaves the occurrences deleted from a recurring event out,
+    // so those its rule gives that aren't there are the exceptions:
+    let bound = |key: &str| {
+        output
+            .get(key)
+            .and_then(Value::as_str)
+            .and_then(|time| time.parse::<LocalTime>().ok())
+    };
+    if let (Some(from), Some(to)) = (bound("from"), bound("to")) {
+        for master in &mut masters {
+            let (Some(rule), Some(start)) = (&master.recurrence, &master.start) else {
+                continue;
+            };
+            let occurring: BTreeSet<&str> = events
+                .iter()
+                .filter(|event| event.id == master.id)
+                .filter_map(|event| event.occurrence.as_deref())
+                .collect();
+            let start = start.get(..19).unwrap_or(start);
+            master.exceptions = rule
+                .occurrences(start, &to)
+                .into_iter()
+                .filter(|time| {
+                    let time = match time.len() {
+                        10 => format!("{time}T00:00:00"),
+                        _ => time.clone(),
+                    };
+                    time.as_str() >= from.as_str()
+                })
+                .filter(|time| !occurring.contains(time.as_str()))
+                .collect();
+        }
+    }
+    Ok(Library {
+        calendars,
+        events,
+        masters,
+    })
+}
+
+fn parse_calendar(record: &Value) -> Result<Calendar> {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).map(String::from);
+    let Some(id) = text("id") else {
+        return Err(Error::Parse {
+            context: "reading calendar id",
+            message: "missing id field".to_string(),
+        });
+    };
+    Ok(Calendar {
+        id,
+        name: text("name").unwrap_or_default(),
+        color: text("color"),
+        account: text("account").unwrap_or_default(),
+        kind: match record.get("type").and_then(Value::as_u64) {
+            Some(1) => CalendarType::CalDav,
+            Some(2) => CalendarType::Exchange,
+            Some(3) => CalendarType::Subscription,
+            Some(4) => CalendarType::Birthday,
+            _ => CalendarType::Local,
+        },
+        read_only: record.get("readOnly").and_then(Value::as_bool) == Some(true),
+    })
+}
+
+fn parse_event(record: &Value) -> Result<Event> {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).map(String::from);
+    let Some(id) = text("id") else {
+        return Err(Error::Parse {
+            context: "reading event id",
+            message: "missing id field".to_string(),
+        });
+    };
+    Ok(Event {
+        id,
+        occurrence: text("occurrence"),
+        title: text("title").unwrap_or_default(),
+        notes: text("notes").filter(|notes| !notes.trim().is_empty()),
+        url: text("url"),
+        location: text("location").filter(|location| !location.trim().is_empty()),
+        place: record
+            .get("place")
+            .filter(|place| place.is_object())
+            .map(|place| {
+                let number = |key: &str| place.get(key).and_then(Value::as_f64);
+                Place {
+                    name: place.get("name").and_then(Value::as_str).map(String::from),
+                    latitude: number("latitude"),
+                    longitude: number("longitude"),
+                    radius: number("radius"),
+                }
+            }),
+        all_day: record.get("allDay").and_then(Value::as_bool) == Some(true),
+        start: text("start"),
+        end: text("end"),
+        time_zone: text("timeZone"),
+        // EKEventStatusTentative and EKEventStatusCanceled:
+        status: match record.get("status").and_then(Value::as_u64) {
+            Some(2) => Status::Tentative,
+            Some(3) => Status::Cancelled,
+            _ => Status::Confirmed,
+        },
+        free: record.get("free").and_then(Value::as_bool) == Some(true),
+        organizer: record
+            .get("organizer")
+            .filter(|organizer| organizer.is_object())
+            .map(parse_person),
+        attendees: record
+            .get("attendees")
+            .and_then(Value::as_array)
+            .map(|attendees| attendees.iter().map(parse_person).collect())
+            .unwrap_or_default(),
+        alarms: record
+            .get("alarms")
+            .and_then(Value::as_array)
+            .map(|alarms| alarms.iter().map(parse_alarm).collect())
+            .unwrap_or_default(),
+        calendar: text("calendar").unwrap_or_default(),
+        account: text("account").unwrap_or_default(),
+        created: text("created"),
+        modified: text("modified"),
+        recurrence: record
+            .get("recurrence")
+            .and_then(recurrence::Rule::from_json),
+        detached: record.get("detached").and_then(Value::as_bool) == Some(true),
+        ..Event::default()
+    })
+}
+
+fn parse_person(record: &Value) -> Person {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).map(String::from);
+    let number = |key: &str| {
+        record
+            .get(key)
+            .and_then(Value::as_u64)
+            .map_or(0, |n| n as u8)
+    };
+    Person {
+        name: text("name"),
+        email: text("email"),
+        status: number("status"),
+        role: number("role"),
+    }
+}
+
+fn parse_alarm(record: &Value) -> Alarm {
+    Alarm {
+        // EventKit gives whole seconds, though as a double:
+        offset: record
+            .get("offset")
+            .and_then(Value::as_f64)
+            .map(|offset| offset as i64),
+        time: record.get("time").and_then(Value::as_str).map(String::from),
+        kind: match record.get("type").and_then(Value::as_u64) {
+            Some(1) => AlarmType::Audio,
+            Some(2) => AlarmType::Procedure,
+            Some(3) => AlarmType::Email,
+            _ => AlarmType::Display,
+        },
+    }
+}
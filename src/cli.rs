@@ -0,0 +1,299 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Command-line machinery shared by every program in this module, whether
+//! invoked as a standalone binary or as a subcommand of `asimov-apple`.
+
+use crate::{
+    Error, Result,
+    signal::{self, Completion, EXIT_INTERRUPTED},
+};
+use asimov_module::SysexitsError::{self, *};
+use clientele::StandardOptions;
+use serde_json::{Map, Value, json};
+use std::{eprintln, format, print, println, process, string::ToString, sync::OnceLock};
+
+pub mod agent;
+pub mod calendar;
+pub mod contacts;
+pub mod daemon;
+pub mod digest;
+pub mod emitter;
+pub mod export;
+pub mod http;
+pub mod import;
+pub mod log;
+pub mod mail;
+pub mod mcp;
+pub mod messages;
+pub mod music;
+pub mod notes;
+pub mod photos;
+pub mod reminders;
+pub mod safari;
+pub mod schema;
+pub mod search;
+pub mod sink;
+pub mod stats;
+pub mod throttle;
+
+static ERROR_FORMAT: OnceLock<ErrorFormat> = OnceLock::new();
+
+/// Options every program takes, on top of the standard ones.
+#[derive(Clone, Debug, clap::Args)]
+pub struct CommonOptions {
+    #[clap(flatten)]
+    pub log: log::LogOptions,
+
+    #[clap(flatten)]
+    pub throttle: throttle::ThrottleOptions,
+
+    /// How to report a failed run on stderr: `text`, or `json` for orchestrators
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_enum,
+        default_value = "text",
+        global = true
+    )]
+    pub error_format: ErrorFormat,
+}
+
+/// How errors are reported on stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ErrorFormat {
+    /// An `Error: …` message
+    #[default]
+    Text,
+    /// A single JSON object
+    Json,
+}
+
+/// Handles the `--version` and `--license` flags, returning the exit code
+/// to terminate with if either was given.
+pub fn handle_standard_flags(flags: &StandardOptions) -> Option<SysexitsError> {
+    // Handle the `--version` flag:
+    if flags.version {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        return Some(EX_OK);
+    }
+
+    // Handle the `--license` flag:
+    if flags.license {
+        print!("{}", include_str!("../UNLICENSE"));
+        return Some(EX_OK);
+    }
+
+    None
+}
+
+/// Configures error reporting, logging & tracing, the rate limit, and the
+/// Ctrl-C handler.
+pub fn init(_flags: &StandardOptions, options: &CommonOptions) -> Result<()> {
+    // Report errors as requested, including any from here on:
+    ERROR_FORMAT.set(options.error_format).ok();
+
+    // Open the log file, if any:
+    log::install(&options.log)?;
+
+    // Configure logging & tracing:
+    #[cfg(feature = "tracing")]
+    init_tracing(_flags);
+
+    // Pace the Apple Events sent to target apps:
+    options.throttle.install();
+
+    // Handle Ctrl-C by flushing whatever was already emitted:
+    signal::install();
+    Ok(())
+}
+
+/// Sends tracing output to stderr, as every ASIMOV module does, and also
+/// to the log file if there is one.
+#[cfg(feature = "tracing")]
+fn init_tracing(flags: &StandardOptions) {
+    use asimov_module::tracing_subscriber::{self, filter::LevelFilter, fmt, prelude::*};
+
+    if !log::enabled() {
+        asimov_module::init_tracing_subscriber(flags).expect("failed to initialize logging");
+        return;
+    }
+
+    let level: LevelFilter = flags.into();
+    tracing_subscriber::registry()
+        .with(level)
+        .with(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_level(flags.debug || flags.verbose > 0)
+                .with_target(flags.debug)
+                .without_time(),
+        )
+        .with(
+            fmt::layer()
+                .with_writer(|| log::LogWriter)
+                .with_ansi(false)
+                .with_target(true),
+        )
+        .try_init()
+        .expect("failed to initialize logging");
+}
+
+/// Turns the outcome of a run into the process exit code, exiting
+/// immediately with [`EXIT_INTERRUPTED`] if the run was cut short.
+pub fn finish(result: Result<Completion>) -> SysexitsError {
+    match result {
+        Ok(Completion::Finished) => EX_OK,
+        Ok(Completion::Interrupted) => process::exit(EXIT_INTERRUPTED),
+        Err(err) => handle_error(&err),
+    }
+}
+
+/// Reports `err` on stderr and returns the matching exit code.
+pub fn handle_error(err: &Error) -> SysexitsError {
+    match ERROR_FORMAT.get().copied().unwrap_or_default() {
+        ErrorFormat::Text => eprintln!("Error: {err}"),
+        ErrorFormat::Json => eprintln!("{}", error_json(err)),
+    }
+    log::line(&format!("Error: {err}"));
+
+    #[cfg(feature = "tracing")]
+    match err {
+        Error::Usage { .. } => {}
+        Error::Io { context, source } => {
+            asimov_module::tracing::debug!(
+                target: "asimov_apple_module",
+                %context,
+                error = %source,
+                "I/O error details"
+            );
+        }
+        Error::PermissionDenied { stderr, .. }
+        | Error::Unavailable { stderr, .. }
+        | Error::Timeout { stderr, .. } => {
+            asimov_module::tracing::debug!(
+                target: "asimov_apple_module",
+                stderr = %stderr,
+                "osascript failure details"
+            );
+        }
+        Error::OsaScriptFailed { status, stderr, .. } => {
+            asimov_module::tracing::debug!(
+                target: "asimov_apple_module",
+                ?status,
+                stderr = %stderr,
+                "osascript failure details"
+            );
+        }
+        Error::CommandFailed {
+            program, status, ..
+        } => {
+            asimov_module::tracing::debug!(
+                target: "asimov_apple_module",
+                %program,
+                ?status,
+                "external program failure details"
+            );
+        }
+        Error::Delivery { url, message } => {
+            asimov_module::tracing::debug!(
+                target: "asimov_apple_module",
+                %url,
+                %message,
+                "delivery failure details"
+            );
+        }
+        Error::Sandbox { .. } => {}
+        Error::NoFullDiskAccess { path } => {
+            asimov_module::tracing::debug!(
+                target: "asimov_apple_module",
+                %path,
+                "Full Disk Access failure details"
+            );
+        }
+        Error::NotFound { what } => {
+            asimov_module::tracing::debug!(
+                target: "asimov_apple_module",
+                %what,
+                "lookup failure details"
+            );
+        }
+        Error::Parse { context, message } => {
+            asimov_module::tracing::debug!(
+                target: "asimov_apple_module",
+                %context,
+                %message,
+                "parse failure details"
+            );
+        }
+        Error::Mismatch { what, problems } => {
+            asimov_module::tracing::debug!(
+                target: "asimov_apple_module",
+                %what,
+                problems,
+                "check failure details"
+            );
+        }
+        Error::Json { context, source } => {
+            asimov_module::tracing::debug!(
+                target: "asimov_apple_module",
+                %context,
+                error = %source,
+                "JSON serialization failure details"
+            );
+        }
+    }
+
+    err.exit_code()
+}
+
+/// Returns the structured form of `err` for `--error-format json`.
+pub fn error_json(err: &Error) -> Value {
+    let mut details = Map::new();
+    let mut detail = |key: &str, value: &str| {
+        details.insert(key.to_string(), json!(value.trim()));
+    };
+    match err {
+        Error::Usage { .. } | Error::Sandbox { .. } => {}
+        Error::Io { context, source } => {
+            detail("context", context);
+            detail("detail", &source.to_string());
+        }
+        Error::PermissionDenied { app, stderr }
+        | Error::Unavailable { app, stderr }
+        | Error::Timeout { app, stderr }
+        | Error::OsaScriptFailed { app, stderr, .. } => {
+            detail("app", app);
+            detail("detail", stderr);
+        }
+        Error::CommandFailed {
+            program, stderr, ..
+        } => {
+            detail("program", program);
+            detail("detail", stderr);
+        }
+        Error::Delivery { url, .. } => detail("url", url),
+        Error::NoFullDiskAccess { path } => detail("path", path),
+        Error::NotFound { what } => detail("what", what),
+        Error::Mismatch { what, problems } => {
+            detail("what", what);
+            detail("problems", &problems.to_string());
+        }
+        Error::Parse { context, message } => {
+            detail("context", context);
+            detail("detail", message);
+        }
+        Error::Json { context, source } => {
+            detail("context", context);
+            detail("detail", &source.to_string());
+        }
+    }
+
+    let mut object = Map::new();
+    object.insert("kind".into(), json!(err.kind()));
+    object.insert("message".into(), json!(err.to_string()));
+    object.extend(details);
+    object.insert("remediation".into(), json!(err.remediation()));
+    object.insert("retryable".into(), json!(err.is_retryable()));
+    object.insert("exit_code".into(), json!(err.exit_code().code()));
+    json!({ "error": object })
+}
@@ -0,0 +1,232 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Installs `asimov-apple` as a per-user launchd agent, so that it runs on
+//! a schedule without anyone having to keep a terminal open.
+
+use crate::{Error, Result, exec, signal::Completion};
+use std::{
+    eprintln, format, fs,
+    path::{Path, PathBuf},
+    print,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The label agents are installed under unless `--label` says otherwise.
+pub const DEFAULT_LABEL: &str = "so.asimov.apple.notes-emitter";
+
+/// launchd starts agents with only the system directories on `PATH`, which
+/// would hide Homebrew-installed tools such as `oxigraph`.
+const AGENT_PATH: &str = "/opt/homebrew/bin:/usr/local/bin:/usr/bin:/bin:/usr/sbin:/sbin";
+
+/// Options for installing a launchd agent.
+#[derive(Clone, Debug, clap::Args)]
+pub struct InstallAgentOptions {
+    /// The launchd label, which also names the plist file
+    #[arg(long, value_name = "LABEL", default_value = DEFAULT_LABEL)]
+    pub label: String,
+
+    /// Run every this many seconds
+    #[arg(long, value_name = "SECONDS", conflicts_with = "at")]
+    pub interval: Option<u64>,
+
+    /// Run daily at this local time
+    #[arg(long, value_name = "HH:MM", value_parser = parse_time)]
+    pub at: Option<(u8, u8)>,
+
+    /// Also run whenever the agent is loaded, e.g. at login
+    #[arg(long)]
+    pub run_at_load: bool,
+
+    /// Where to append the agent's standard output [default: ~/Library/Logs/asimov-apple/LABEL.log]
+    #[arg(long, value_name = "PATH")]
+    pub stdout_path: Option<PathBuf>,
+
+    /// Where to append the agent's standard error [default: ~/Library/Logs/asimov-apple/LABEL.err.log]
+    #[arg(long, value_name = "PATH")]
+    pub stderr_path: Option<PathBuf>,
+
+    /// Print the plist instead of installing it
+    #[arg(long)]
+    pub print: bool,
+
+    /// The `asimov-apple` arguments the agent runs with [default: notes emit]
+    #[arg(last = true, value_name = "ARGS")]
+    pub args: Vec<String>,
+}
+
+/// Options for uninstalling a launchd agent.
+#[derive(Clone, Debug, clap::Args)]
+pub struct UninstallAgentOptions {
+    /// The launchd label the agent was installed under
+    #[arg(long, value_name = "LABEL", default_value = DEFAULT_LABEL)]
+    pub label: String,
+}
+
+/// Writes the agent's plist to `~/Library/LaunchAgents` and loads it,
+/// replacing any agent previously installed under the same label.
+pub fn install(opts: &InstallAgentOptions) -> Result<Completion> {
+    let home = home_dir()?;
+    let logs = home.join("Library/Logs/asimov-apple");
+    let stdout_path = opts
+        .stdout_path
+        .clone()
+        .unwrap_or_else(|| logs.join(format!("{}.log", opts.label)));
+    let stderr_path = opts
+        .stderr_path
+        .clone()
+        .unwrap_or_else(|| logs.join(format!("{}.err.log", opts.label)));
+
+    let program = std::env::current_exe().map_err(|e| Error::Io {
+        context: "locating the asimov-apple executable",
+        source: e,
+    })?;
+    let mut arguments = std::vec![program.to_string_lossy().into_owned()];
+    if opts.args.is_empty() {
+        arguments.extend(["notes".to_string(), "emit".to_string()]);
+    } else {
+        arguments.extend(opts.args.iter().cloned());
+    }
+
+    let plist = plist(opts, &arguments, &stdout_path, &stderr_path);
+    if opts.print {
+        print!("{plist}");
+        return Ok(Completion::Finished);
+    }
+
+    for log in [&stdout_path, &stderr_path] {
+        if let Some(dir) = log.parent() {
+            fs::create_dir_all(dir).map_err(|e| Error::Io {
+                context: "creating the agent log directory",
+                source: e,
+            })?;
+        }
+    }
+
+    let path = plist_path(&home, &opts.label);
+    if path.exists() {
+        // Unloading fails harmlessly if the agent isn't currently loaded:
+        exec::run("launchctl", [Path::new("unload"), &path], b"").ok();
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| Error::Io {
+            context: "creating ~/Library/LaunchAgents",
+            source: e,
+        })?;
+    }
+    fs::write(&path, plist).map_err(|e| Error::Io {
+        context: "writing the agent plist",
+        source: e,
+    })?;
+    exec::run(
+        "launchctl",
+        [Path::new("load"), Path::new("-w"), &path],
+        b"",
+    )?;
+
+    eprintln!("Installed {} at {}", opts.label, path.display());
+    Ok(Completion::Finished)
+}
+
+/// Unloads the agent and deletes its plist.
+pub fn uninstall(opts: &UninstallAgentOptions) -> Result<Completion> {
+    let path = plist_path(&home_dir()?, &opts.label);
+    if !path.exists() {
+        return Err(Error::NotFound {
+            what: format!("agent {}", opts.label),
+        });
+    }
+
+    exec::run(
+        "launchctl",
+        [Path::new("unload"), Path::new("-w"), &path],
+        b"",
+    )
+    .ok();
+    fs::remove_file(&path).map_err(|e| Error::Io {
+        context: "removing the agent plist",
+        source: e,
+    })?;
+
+    eprintln!("Uninstalled {}", opts.label);
+    Ok(Completion::Finished)
+}
+
+fn home_dir() -> Result<PathBuf> {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| Error::Usage {
+            message: "HOME is not set".to_string(),
+        })
+}
+
+fn plist_path(home: &Path, label: &str) -> PathBuf {
+    home.join("Library/LaunchAgents")
+        .join(format!("{label}.plist"))
+}
+
+fn plist(
+    opts: &InstallAgentOptions,
+    arguments: &[String],
+    stdout_path: &Path,
+    stderr_path: &Path,
+) -> String {
+    let mut plist = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+        "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n<dict>\n",
+    ));
+    plist += &format!(
+        "  <key>Label</key>\n  <string>{}</string>\n",
+        escape(&opts.label)
+    );
+    plist += "  <key>ProgramArguments</key>\n  <array>\n";
+    for argument in arguments {
+        plist += &format!("    <string>{}</string>\n", escape(argument));
+    }
+    plist += "  </array>\n";
+    plist += &format!(
+        "  <key>EnvironmentVariables</key>\n  <dict>\n    <key>PATH</key>\n    <string>{AGENT_PATH}</string>\n  </dict>\n"
+    );
+    // launchd runs a calendar job missed during sleep as soon as the Mac
+    // wakes, and coalesces missed intervals into a single run on wake:
+    if let Some(interval) = opts.interval {
+        plist += &format!("  <key>StartInterval</key>\n  <integer>{interval}</integer>\n");
+    }
+    if let Some((hour, minute)) = opts.at {
+        plist += &format!(
+            "  <key>StartCalendarInterval</key>\n  <dict>\n    <key>Hour</key>\n    <integer>{hour}</integer>\n    <key>Minute</key>\n    <integer>{minute}</integer>\n  </dict>\n"
+        );
+    }
+    if opts.run_at_load {
+        plist += "  <key>RunAtLoad</key>\n  <true/>\n";
+    }
+    plist += &format!(
+        "  <key>StandardOutPath</key>\n  <string>{}</string>\n",
+        escape(&stdout_path.to_string_lossy())
+    );
+    plist += &format!(
+        "  <key>StandardErrorPath</key>\n  <string>{}</string>\n",
+        escape(&stderr_path.to_string_lossy())
+    );
+    plist += "  <key>ProcessType</key>\n  <string>Background</string>\n";
+    plist += "</dict>\n</plist>\n";
+    plist
+}
+
+fn parse_time(input: &str) -> core::result::Result<(u8, u8), String> {
+    input
+        .split_once(':')
+        .and_then(|(hour, minute)| Some((hour.parse().ok()?, minute.parse().ok()?)))
+        .filter(|&(hour, minute)| hour < 24 && minute < 60)
+        .ok_or_else(|| format!("not a time of day (HH:MM): {input}"))
+}
+
+/// Escapes `value` for use as XML character data.
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
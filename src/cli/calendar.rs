@@ -0,0 +1,476 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `calendar` programs.
+
+use super::emitter::EmitterOptions;
+use crate::{
+    Error, Result, calendar,
+    eventkit::{self, Backend},
+    osascript, signal,
+    signal::Completion,
+    stats::Stats,
+    timezone::{LocalTime, TimeZone},
+};
+use std::{
+    eprintln, format,
+    io::{self, IsTerminal, Write},
+    string::String,
+    time::Instant,
+    vec::Vec,
+};
+
+/// Options for the Apple Calendar emitter.
+#[derive(Clone, Debug, clap::Args)]
+pub struct EmitOptions {
+    /// Only emit events ending after this date, or date and time [default: 90 days ago]
+    #[arg(long, value_name = "DATE")]
+    pub from: Option<LocalTime>,
+
+    /// Only emit events starting before this date, or date and time [default: in 90 days]
+    #[arg(long, value_name = "DATE")]
+    pub to: Option<LocalTime>,
+
+    /// Emit each occurrence of a recurring event in the window, rather than the event with its rule
+    #[arg(long)]
+    pub expand_recurring: bool,
+
+    #[clap(flatten)]
+    pub calendars: CalendarOptions,
+
+    /// Look up the coordinates of locations given only as text, with CoreLocation
+    #[arg(long)]
+    pub geocode: bool,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for the Apple Calendar free/busy emitter.
+#[derive(Clone, Debug, clap::Args)]
+pub struct FreeBusyOptions {
+    /// Emit the busy times from this date, or date and time
+    #[arg(long, value_name = "DATE")]
+    pub from: LocalTime,
+
+    /// Emit the busy times up to this date, or date and time
+    #[arg(long, value_name = "DATE")]
+    pub to: LocalTime,
+
+    #[clap(flatten)]
+    pub calendars: CalendarOptions,
+
+    /// Emit the busy times of each calendar on their own, rather than merged across calendars
+    #[arg(long)]
+    pub per_calendar: bool,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for creating an event.
+#[derive(Clone, Debug, clap::Args)]
+pub struct CreateOptions {
+    /// The calendar to add the event to [default: the default calendar for new events]
+    #[arg(long, value_name = "NAME")]
+    pub calendar: Option<String>,
+
+    /// When the event starts: a date and time, or a date for an all-day event
+    #[arg(long, value_name = "DATE")]
+    pub start: LocalTime,
+
+    /// When the event ends; for an all-day event, the last date [default: an hour, or the day, after the start]
+    #[arg(long, value_name = "DATE")]
+    pub end: Option<LocalTime>,
+
+    /// Where the event takes place
+    #[arg(long, value_name = "PLACE")]
+    pub location: Option<String>,
+
+    /// Add this person as an attendee, by email address (repeatable)
+    #[arg(long = "attendee", value_name = "EMAIL")]
+    pub attendees: Vec<String>,
+
+    /// The event's title; its notes are read from stdin, unless that is a terminal
+    #[arg(value_name = "TITLE")]
+    pub title: String,
+}
+
+/// Options for changing an event.
+#[derive(Clone, Debug, clap::Args)]
+pub struct UpdateOptions {
+    /// A new title
+    #[arg(long, value_name = "TITLE")]
+    pub title: Option<String>,
+
+    /// A new start: a date and time, or a date for an all-day event; the event keeps its length
+    #[arg(long, value_name = "DATE")]
+    pub start: Option<LocalTime>,
+
+    /// A new end; for an all-day event, the last date
+    #[arg(long, value_name = "DATE")]
+    pub end: Option<LocalTime>,
+
+    /// Move the event to this calendar
+    #[arg(long, value_name = "NAME")]
+    pub calendar: Option<String>,
+
+    /// A new location
+    #[arg(long, value_name = "PLACE")]
+    pub location: Option<String>,
+
+    /// New notes
+    #[arg(long, value_name = "TEXT")]
+    pub notes: Option<String>,
+
+    /// Add this person as an attendee, by email address (repeatable)
+    #[arg(long = "attendee", value_name = "EMAIL")]
+    pub attendees: Vec<String>,
+
+    /// The event to change, as a `urn:apple:calendar:event:` URN or EventKit id; an occurrence's changes only it
+    #[arg(value_name = "URN")]
+    pub urn: String,
+}
+
+/// Options selecting the calendars whose events are read.
+#[derive(Clone, Debug, clap::Args)]
+pub struct CalendarOptions {
+    /// Only read this calendar (repeatable)
+    #[arg(long = "calendar", value_name = "NAME")]
+    pub calendars: Vec<String>,
+
+    /// Don't read this calendar (repeatable)
+    #[arg(long = "exclude-calendar", value_name = "NAME")]
+    pub excluded_calendars: Vec<String>,
+
+    /// Don't read the Birthdays calendar, made up from Contacts
+    #[arg(long)]
+    pub exclude_birthdays: bool,
+
+    /// Don't read subscribed calendars, such as holiday calendars
+    #[arg(long)]
+    pub exclude_subscribed: bool,
+
+    /// Read EventKit through JavaScript for Automation, or in process,
+    /// which is much faster but takes a build with the eventkit feature
+    #[arg(long, value_name = "BACKEND", default_value = eventkit::DEFAULT_BACKEND)]
+    pub backend: Backend,
+}
+
+impl CalendarOptions {
+    /// Returns the filter that reads these calendars' events in the
+    /// window from `from` to `to`.
+    pub fn filter(&self, from: Option<LocalTime>, to: Option<LocalTime>) -> calendar::Filter {
+        calendar::Filter {
+            from,
+            to,
+            calendars: self.calendars.clone(),
+            excluded_calendars: self.excluded_calendars.clone(),
+            birthdays: !self.exclude_birthdays,
+            subscribed: !self.exclude_subscribed,
+            geocode: false,
+        }
+    }
+
+    /// Returns how the backend these options select is recorded in
+    /// provenance.
+    fn backend_name(&self) -> &'static str {
+        match self.backend {
+            Backend::Jxa => calendar::BACKEND,
+            Backend::Native => eventkit::NATIVE_BACKEND,
+        }
+    }
+}
+
+impl EmitOptions {
+    /// Returns the filter these options select events by.
+    pub fn filter(&self) -> calendar::Filter {
+        calendar::Filter {
+            geocode: self.geocode,
+            ..self.calendars.filter(self.from.clone(), self.to.clone())
+        }
+    }
+}
+
+/// Emits every calendar, then every event (that passes the filters), as one
+/// JSON record per line.
+pub fn emit(opts: &EmitOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-calendar-emitter", |stats| {
+        emit_events(opts, stats)
+    })
+}
+
+fn emit_events(opts: &EmitOptions, stats: &mut Stats) -> Result<Completion> {
+    let Some(library) = extract(&opts.filter(), opts.calendars.backend, stats)? else {
+        return Ok(Completion::Interrupted);
+    };
+
+    let mut output = opts.output.open(opts.calendars.backend_name())?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    for calendar in &library.calendars {
+        output.emit(
+            stats,
+            &calendar.to_json(),
+            &calendar.account,
+            &calendar.name,
+        )?;
+    }
+    // Every occurrence, or else the events that don't recur, then each
+    // recurring one followed by the occurrences changed on their own.
+    // Occurrences stand on their own, too, if their recurring event can't
+    // be looked up, as in some read-only calendars:
+    let mut records = Vec::new();
+    for event in &library.events {
+        let orphan = || !library.masters.iter().any(|master| master.id == event.id);
+        if opts.expand_recurring || event.occurrence.is_none() || orphan() {
+            records.push(event);
+        }
+    }
+    if !opts.expand_recurring {
+        for master in &library.masters {
+            records.push(master);
+            records.extend(library.detached(master));
+        }
+    }
+    let mut found = records.into_iter();
+    for event in found.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::calendar_emitter",
+            event_id = %event.id,
+            calendar = %event.calendar,
+            "emitting event"
+        );
+
+        output.emit(stats, &event.to_json(), &event.account, &event.calendar)?;
+    }
+    stats.skipped(found.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count() - library.calendars.len();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} events");
+        stats.warn(format!("interrupted after emitting {count} events"));
+    }
+    Ok(completion)
+}
+
+/// Emits the times the events (that pass the filters) take up, merged, as
+/// one JSON record per interval.
+pub fn freebusy(opts: &FreeBusyOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-calendar-freebusy", |stats| {
+        emit_busy(opts, stats)
+    })
+}
+
+fn emit_busy(opts: &FreeBusyOptions, stats: &mut Stats) -> Result<Completion> {
+    let filter = opts
+        .calendars
+        .filter(Some(opts.from.clone()), Some(opts.to.clone()));
+    let Some(library) = extract(&filter, opts.calendars.backend, stats)? else {
+        return Ok(Completion::Interrupted);
+    };
+    let local = TimeZone::local();
+    let window = match (
+        local.instant(opts.from.as_str()),
+        local.instant(opts.to.as_str()),
+    ) {
+        (Some(from), Some(to)) => (from, to),
+        _ => {
+            return Err(Error::Usage {
+                message: format!("--from {} or --to {} is out of range", opts.from, opts.to),
+            });
+        }
+    };
+
+    let started = Instant::now();
+    let mut intervals = Vec::new();
+    match opts.per_calendar {
+        true => {
+            for calendar in &library.calendars {
+                let events = library.events.iter().filter(|event| {
+                    event.calendar == calendar.name && event.account == calendar.account
+                });
+                intervals.extend(calendar::busy(events, window, &local).into_iter().map(
+                    |(start, end)| calendar::Busy {
+                        calendar: Some(calendar),
+                        start,
+                        end,
+                    },
+                ));
+            }
+        }
+        false => {
+            intervals.extend(
+                calendar::busy(&library.events, window, &local)
+                    .into_iter()
+                    .map(|(start, end)| calendar::Busy {
+                        calendar: None,
+                        start,
+                        end,
+                    }),
+            );
+        }
+    }
+
+    let mut output = opts.output.open(opts.calendars.backend_name())?;
+    let mut completion = Completion::Finished;
+    let mut found = intervals.into_iter();
+    for busy in found.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+        let (account, name) = busy
+            .calendar
+            .map_or(("", ""), |calendar| (&calendar.account, &calendar.name));
+        output.emit(stats, &busy.to_json(), account, name)?;
+    }
+    stats.skipped(found.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} busy intervals");
+        stats.warn(format!("interrupted after emitting {count} busy intervals"));
+    }
+    Ok(completion)
+}
+
+/// Extracts the calendars and events `filter` selects with `backend`, or
+/// `None` if interrupted first.
+fn extract(
+    filter: &calendar::Filter,
+    backend: Backend,
+    stats: &mut Stats,
+) -> Result<Option<calendar::Library>> {
+    if let (Some(from), Some(to)) = (&filter.from, &filter.to)
+        && from >= to
+    {
+        return Err(Error::Usage {
+            message: format!("--from {from} is not before --to {to}"),
+        });
+    }
+    let started = Instant::now();
+    let output = match backend {
+        Backend::Jxa => osascript::run_javascript(
+            calendar::APP,
+            &calendar::script(filter),
+            signal::interrupted,
+        )
+        .and_then(|stdout| stdout.map(|stdout| calendar::parse(&stdout)).transpose()),
+        Backend::Native => eventkit::read_events(filter, signal::interrupted)
+            .and_then(|found| found.map(|found| calendar::from_json(&found)).transpose()),
+    };
+    stats.finish_phase("extract", started);
+    let Some(library) = output? else {
+        eprintln!("Interrupted before any events were emitted");
+        stats.warn("interrupted before any events were emitted");
+        return Ok(None);
+    };
+    if let Some(name) = filter
+        .calendars
+        .iter()
+        .find(|name| !library.calendars.iter().any(|c| c.name == **name))
+    {
+        return Err(Error::NotFound {
+            what: format!("calendar {name}"),
+        });
+    }
+    Ok(Some(library))
+}
+
+/// Creates an event and prints its record.
+pub fn create(opts: &CreateOptions) -> Result<Completion> {
+    let stdin = io::stdin();
+    let notes = match stdin.is_terminal() {
+        true => String::new(),
+        false => io::read_to_string(stdin).map_err(|e| Error::Io {
+            context: "reading the event's notes from stdin",
+            source: e,
+        })?,
+    };
+    let changes = calendar::Changes {
+        title: Some(opts.title.clone()),
+        start: Some(opts.start.clone()),
+        end: opts.end.clone(),
+        location: opts.location.clone(),
+        notes: Some(notes.trim_end())
+            .filter(|notes| !notes.is_empty())
+            .map(String::from),
+        attendees: opts.attendees.clone(),
+    };
+    check_times(&changes)?;
+    let script = calendar::create_script(opts.calendar.as_deref(), &changes);
+    write_back(&script, || Error::NotFound {
+        what: match &opts.calendar {
+            Some(name) => format!("calendar {name}"),
+            None => "default calendar".into(),
+        },
+    })
+}
+
+/// Changes an event and prints its record.
+pub fn update(opts: &UpdateOptions) -> Result<Completion> {
+    let (id, occurrence) = calendar::id_from_urn(&opts.urn).ok_or_else(|| Error::Usage {
+        message: format!("not an Apple Calendar URN: {}", opts.urn),
+    })?;
+    let changes = calendar::Changes {
+        title: opts.title.clone(),
+        start: opts.start.clone(),
+        end: opts.end.clone(),
+        location: opts.location.clone(),
+        notes: opts.notes.clone(),
+        attendees: opts.attendees.clone(),
+    };
+    check_times(&changes)?;
+    let script = calendar::update_script(id, occurrence, opts.calendar.as_deref(), &changes);
+    write_back(&script, || Error::NotFound {
+        what: match &opts.calendar {
+            Some(name) => format!("event {} or calendar {name}", opts.urn),
+            None => format!("event {}", opts.urn),
+        },
+    })
+}
+
+/// Refuses an end before the start, when both are given.
+fn check_times(changes: &calendar::Changes) -> Result<()> {
+    match (&changes.start, &changes.end) {
+        (Some(start), Some(end)) if end < start => Err(Error::Usage {
+            message: format!("--end {end} is before --start {start}"),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Runs a script that changes one event, printing the record of the event
+/// as changed, or failing with `missing()` if there was none.
+fn write_back(script: &str, missing: impl FnOnce() -> Error) -> Result<Completion> {
+    let Some(stdout) = osascript::run_javascript(calendar::APP, script, signal::interrupted)?
+    else {
+        return Ok(Completion::Interrupted);
+    };
+    let library = calendar::parse(&stdout)?;
+    let Some(event) = library.events.first() else {
+        return Err(missing());
+    };
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &event.to_json())?;
+    stdout.write_all(b"\n").map_err(|e| Error::Io {
+        context: "writing newline to stdout",
+        source: e,
+    })?;
+    Ok(Completion::Finished)
+}
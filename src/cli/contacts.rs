@@ -0,0 +1,368 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `contacts` programs.
+
+use super::emitter::{EmitterOptions, Output};
+use crate::{
+    Error, Result,
+    contacts::{self, Contact, Field, Photo},
+    osascript, signal,
+    signal::Completion,
+    sink::OutputFormat,
+    stats::Stats,
+};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    eprintln, format, fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    time::Instant,
+    vec::Vec,
+};
+
+/// Options for the Apple Contacts emitter.
+#[derive(Clone, Debug, clap::Args)]
+pub struct EmitOptions {
+    /// Only emit this group and the contacts in it (repeatable)
+    #[arg(long = "group", value_name = "NAME")]
+    pub groups: Vec<String>,
+
+    /// Only emit contacts whose name, organization, email address, or
+    /// phone number contains this text
+    #[arg(long, value_name = "TEXT")]
+    pub search: Option<String>,
+
+    /// Only emit contacts that have at least one email, phone, or address
+    /// (repeatable: all must match)
+    #[arg(long = "has", value_name = "FIELD")]
+    pub has: Vec<Field>,
+
+    /// Write each contact's photo and thumbnail to this directory, and
+    /// link them from its record
+    #[arg(long, value_name = "PATH")]
+    pub photos_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for the duplicate contacts report.
+#[derive(Clone, Debug, clap::Args)]
+pub struct DedupeReportOptions {
+    /// Only report contacts this likely, from 0 to 1, to be duplicates
+    #[arg(long, value_name = "SCORE", default_value_t = 0.5)]
+    pub min_confidence: f64,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for creating contacts.
+#[derive(Clone, Debug, clap::Args)]
+pub struct CreateOptions {
+    /// The account to add the contacts to [default: the default account]
+    #[arg(long, value_name = "NAME")]
+    pub account: Option<String>,
+}
+
+/// Options for changing contacts.
+#[derive(Clone, Debug, clap::Args)]
+pub struct UpdateOptions {}
+
+/// Emits every group, then every contact (in the groups selected), as one
+/// JSON record per line.
+pub fn emit(opts: &EmitOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-contacts-emitter", |stats| {
+        emit_contacts(opts, stats)
+    })
+}
+
+fn emit_contacts(opts: &EmitOptions, stats: &mut Stats) -> Result<Completion> {
+    let started = Instant::now();
+    // Photos are read only where they go, which is slow with many:
+    let photos = opts.output.sink.attachments
+        || opts.photos_dir.is_some()
+        || matches!(opts.output.sink.output_format, OutputFormat::Vcard(_));
+    let output = osascript::run_javascript(
+        contacts::APP,
+        &contacts::script(photos),
+        signal::interrupted,
+    );
+    stats.finish_phase("extract", started);
+    let Some(stdout) = output? else {
+        eprintln!("Interrupted before any contacts were emitted");
+        stats.warn("interrupted before any contacts were emitted");
+        return Ok(Completion::Interrupted);
+    };
+    let mut library = contacts::parse(&stdout)?;
+    if !opts.groups.is_empty() {
+        if let Some(name) = opts
+            .groups
+            .iter()
+            .find(|name| !library.groups.iter().any(|group| group.name == **name))
+        {
+            return Err(Error::NotFound {
+                what: format!("contact group {name}"),
+            });
+        }
+        library.retain_groups(&opts.groups);
+    }
+    library.contacts.retain(|contact| {
+        opts.has.iter().all(|field| contact.has(*field))
+            && opts
+                .search
+                .as_ref()
+                .is_none_or(|query| contact.matches(query))
+    });
+
+    // Written before the sink is opened, which may confine what is written:
+    let mut files = BTreeMap::new();
+    if let Some(dir) = &opts.photos_dir {
+        let started = Instant::now();
+        for contact in &library.contacts {
+            files.insert(contact.id.as_str(), write_photos(dir, contact)?);
+        }
+        stats.finish_phase("photos", started);
+    }
+
+    let mut output = opts.output.open(contacts::BACKEND)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    for group in &library.groups {
+        output.emit(stats, &group.to_json(), &group.account, &group.name)?;
+    }
+    let mut found = library.contacts.iter();
+    for contact in found.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::contacts_emitter",
+            contact_id = %contact.id,
+            account = %contact.account,
+            "emitting contact"
+        );
+
+        let mut record = contact.to_json();
+        let (image, thumbnail) = match files.remove(contact.id.as_str()) {
+            Some(urls) => urls,
+            None => (
+                attach(&mut output, contact.photo.as_ref())?,
+                attach(&mut output, contact.thumbnail.as_ref())?,
+            ),
+        };
+        if let Some(image) = image {
+            record["image"] = image;
+        }
+        if let Some(thumbnail) = thumbnail {
+            record["thumbnail"] = thumbnail;
+        }
+        output.emit(stats, &record, &contact.account, "")?;
+    }
+    stats.skipped(found.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count() - library.groups.len();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} contacts");
+        stats.warn(format!("interrupted after emitting {count} contacts"));
+    }
+    Ok(completion)
+}
+
+type Images = (Option<Value>, Option<Value>);
+
+/// Uploads `photo` with the sink, if it takes attachments, returning it
+/// as an `ImageObject` at the URL it was given.
+fn attach(output: &mut Output, photo: Option<&Photo>) -> Result<Option<Value>> {
+    let Some(photo) = photo else {
+        return Ok(None);
+    };
+    let url = output.attach(&photo.data, photo.mime, photo.extension())?;
+    Ok(url.map(|url| photo.to_json(&url)))
+}
+
+/// Writes a contact's photo and thumbnail to `dir`, named for the contact,
+/// returning them as `ImageObject`s at their `file:` URLs.
+fn write_photos(dir: &Path, contact: &Contact) -> Result<Images> {
+    let io = |e| Error::Io {
+        context: "writing contact photos",
+        source: e,
+    };
+    let name: String = contact
+        .id
+        .chars()
+        .map(|c| match c {
+            '/' | ':' | '\\' => '_',
+            c => c,
+        })
+        .collect();
+    let write = |photo: Option<&Photo>, suffix: &str| -> Result<Option<Value>> {
+        let Some(photo) = photo else {
+            return Ok(None);
+        };
+        fs::create_dir_all(dir).map_err(io)?;
+        let path = dir.join(format!("{name}{suffix}.{}", photo.extension()));
+        fs::write(&path, &photo.data).map_err(io)?;
+        let path = fs::canonicalize(&path).map_err(io)?;
+        let url = url::Url::from_file_path(&path)
+            .map(String::from)
+            .unwrap_or_else(|()| path.display().to_string());
+        Ok(Some(photo.to_json(&url)))
+    };
+    Ok((
+        write(contact.photo.as_ref(), "")?,
+        write(contact.thumbnail.as_ref(), ".thumbnail")?,
+    ))
+}
+
+/// Emits each set of contacts that are likely duplicates, for merging by
+/// hand, as one JSON record per set. Nothing is changed.
+pub fn dedupe_report(opts: &DedupeReportOptions) -> Result<Completion> {
+    opts.output
+        .run("asimov-apple-contacts-dedupe-report", |stats| {
+            emit_duplicates(opts, stats)
+        })
+}
+
+fn emit_duplicates(opts: &DedupeReportOptions, stats: &mut Stats) -> Result<Completion> {
+    if !(0.0..=1.0).contains(&opts.min_confidence) {
+        return Err(Error::Usage {
+            message: format!(
+                "--min-confidence {} is not between 0 and 1",
+                opts.min_confidence
+            ),
+        });
+    }
+    let started = Instant::now();
+    let output =
+        osascript::run_javascript(contacts::APP, &contacts::script(false), signal::interrupted);
+    stats.finish_phase("extract", started);
+    let Some(stdout) = output? else {
+        eprintln!("Interrupted before any duplicates were emitted");
+        stats.warn("interrupted before any duplicates were emitted");
+        return Ok(Completion::Interrupted);
+    };
+    let library = contacts::parse(&stdout)?;
+
+    let started = Instant::now();
+    let sets = contacts::duplicates(&library.contacts, opts.min_confidence);
+    stats.finish_phase("match", started);
+
+    let mut output = opts.output.open(contacts::BACKEND)?;
+    let mut completion = Completion::Finished;
+    let started = Instant::now();
+    let mut found = sets.iter();
+    for set in found.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+        let account = set.contacts.first().map_or("", |c| c.account.as_str());
+        output.emit(stats, &set.to_json(), account, "")?;
+    }
+    stats.skipped(found.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} sets of duplicates");
+        stats.warn(format!(
+            "interrupted after emitting {count} sets of duplicates"
+        ));
+    }
+    Ok(completion)
+}
+
+/// Creates a contact from each record on stdin, in the format the
+/// emitter writes, and prints its record.
+pub fn create(opts: &CreateOptions) -> Result<Completion> {
+    write_back(|_, changes| Ok(contacts::create_script(opts.account.as_deref(), changes)))
+}
+
+/// Changes the contact each record on stdin has the `@id` of, as the
+/// record has it, and prints its record.
+pub fn update(_opts: &UpdateOptions) -> Result<Completion> {
+    write_back(|record, changes| {
+        let urn = record
+            .get("@id")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let id = contacts::id_from_urn(urn).ok_or_else(|| Error::Usage {
+            message: match urn {
+                "" => "a contact record has no @id".into(),
+                urn => format!("not an Apple Contacts URN: {urn}"),
+            },
+        })?;
+        Ok(contacts::update_script(id, changes))
+    })
+}
+
+/// Runs the script `script` returns for each record on stdin, one after
+/// another, printing the record of each contact as written.
+fn write_back(script: impl Fn(&Value, &contacts::Changes) -> Result<String>) -> Result<Completion> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return Err(Error::Usage {
+            message: "expected contact records on stdin".into(),
+        });
+    }
+    let input = io::read_to_string(stdin).map_err(|e| Error::Io {
+        context: "reading contact records from stdin",
+        source: e,
+    })?;
+    let mut records = serde_json::Deserializer::from_str(&input)
+        .into_iter::<Value>()
+        .collect::<core::result::Result<Vec<_>, _>>()
+        .map_err(|e| Error::Parse {
+            context: "reading contact records from stdin",
+            message: e.to_string(),
+        })?;
+    // The emitter's groups, and any other records, pass by:
+    records.retain(|record| {
+        matches!(
+            record.get("@type").and_then(Value::as_str),
+            None | Some("Person" | "Organization")
+        )
+    });
+    if records.is_empty() {
+        return Err(Error::Usage {
+            message: "no contact records on stdin".into(),
+        });
+    }
+    // Every record is checked before any contact is written:
+    let scripts = records
+        .iter()
+        .map(|record| script(record, &contacts::Changes::from_json(record)?))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut stdout = io::stdout().lock();
+    for script in scripts {
+        if signal::interrupted() {
+            return Ok(Completion::Interrupted);
+        }
+        let Some(output) = osascript::run_javascript(contacts::APP, &script, signal::interrupted)?
+        else {
+            return Ok(Completion::Interrupted);
+        };
+        let contact = contacts::parse_written(&output)?;
+        serde_json::to_writer(&mut stdout, &contact.to_json())?;
+        stdout.write_all(b"\n").map_err(|e| Error::Io {
+            context: "writing newline to stdout",
+            source: e,
+        })?;
+    }
+    Ok(Completion::Finished)
+}
@@ -0,0 +1,388 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Runs the watchers for several sources from one long-lived process,
+//! multiplexing their records into one shared sink or per-source sinks.
+
+use super::{messages::stamp, sink::SinkOptions};
+use crate::{
+    Result, messages, notes, osascript, safari, signal,
+    signal::Completion,
+    sink::{Sink, SinkSpec, tombstone, tombstone_id},
+};
+use core::{fmt, str::FromStr};
+use serde_json::Value;
+use std::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    eprintln, format,
+    path::PathBuf,
+    string::String,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+    vec::Vec,
+};
+
+/// How often the watchers check for Ctrl-C while waiting for the next poll.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A source the daemon can watch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Notes,
+    Messages,
+    SafariHistory,
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        match input {
+            "notes" => Ok(Source::Notes),
+            "messages" => Ok(Source::Messages),
+            "safari-history" => Ok(Source::SafariHistory),
+            _ => Err(format!("unsupported source: {input}")),
+        }
+    }
+}
+
+impl Source {
+    /// The backend records from this source are extracted through.
+    pub fn backend(self) -> &'static str {
+        match self {
+            Source::Notes => notes::BACKEND,
+            Source::Messages => messages::BACKEND,
+            Source::SafariHistory => safari::HISTORY_BACKEND,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Notes => write!(f, "notes"),
+            Source::Messages => write!(f, "messages"),
+            Source::SafariHistory => write!(f, "safari-history"),
+        }
+    }
+}
+
+/// A parsed `--source` argument: a source, optionally with its own sink.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceSpec {
+    pub source: Source,
+    pub sink: Option<SinkSpec>,
+}
+
+impl FromStr for SourceSpec {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        let (source, sink) = match input.split_once('=') {
+            Some((source, sink)) => (source, Some(sink.parse()?)),
+            None => (input, None),
+        };
+        Ok(SourceSpec {
+            source: source.parse()?,
+            sink,
+        })
+    }
+}
+
+/// Options for the multi-source daemon.
+#[derive(Clone, Debug, clap::Args)]
+pub struct DaemonOptions {
+    /// A source to watch (`notes`, `messages`, or `safari-history`), optionally with its own sink (e.g. `notes=sqlite:notes.db`)
+    #[arg(long = "source", value_name = "SOURCE[=SINK]", required = true)]
+    pub sources: Vec<SourceSpec>,
+
+    /// How many seconds to wait between polls of each source
+    #[arg(long, value_name = "SECONDS", default_value = "300")]
+    pub interval: u64,
+
+    /// Wrap width for plain-text conversion from HTML
+    #[arg(
+        short = 'w',
+        long = "wrap-width",
+        value_name = "WIDTH",
+        default_value = "80"
+    )]
+    pub wrap_width: usize,
+
+    #[clap(flatten)]
+    pub sink: SinkOptions,
+}
+
+/// What the watcher threads report back to the thread owning the sinks.
+enum Event {
+    /// A new or changed record from the watcher at the given index, or a
+    /// tombstone for one deleted.
+    Record(usize, Value),
+    /// The watcher at the given index finished a poll.
+    Polled(usize),
+}
+
+/// Polls every source until Ctrl-C is pressed. The first poll of a source
+/// emits all of its records; later polls only those that changed, and
+/// delete those that are gone.
+pub fn run(opts: &DaemonOptions) -> Result<Completion> {
+    // Sinks stay on this thread, so that they needn't be `Send`. Sources
+    // without a sink of their own share the default one:
+    let mut shared = opts.sink.open(notes::BACKEND)?;
+    let mut own: Vec<Option<Box<dyn Sink>>> = opts
+        .sources
+        .iter()
+        .map(|spec| {
+            spec.sink
+                .as_ref()
+                .map(|s| opts.sink.open_spec(s, spec.source.backend()))
+                .transpose()
+        })
+        .collect::<Result<_>>()?;
+
+    let (events, received) = mpsc::channel();
+    let interval = Duration::from_secs(opts.interval);
+    let watchers: Vec<_> = opts
+        .sources
+        .iter()
+        .enumerate()
+        .map(|(index, spec)| {
+            let events = events.clone();
+            let mut watcher = Watcher::new(spec.source, opts.wrap_width)?;
+            Ok(thread::spawn(move || watcher.run(index, interval, events)))
+        })
+        .collect::<Result<_>>()?;
+    drop(events);
+
+    eprintln!(
+        "Watching {} every {}s",
+        opts.sources
+            .iter()
+            .map(|spec| format!("{}", spec.source))
+            .collect::<Vec<_>>()
+            .join(", "),
+        opts.interval
+    );
+
+    // The channel disconnects once every watcher has exited on Ctrl-C:
+    let mut result = Ok(());
+    for event in received {
+        let step = match event {
+            Event::Record(index, record) => {
+                let sink = own[index].as_mut().unwrap_or(&mut shared);
+                match tombstone_id(&record) {
+                    Some(id) => sink.delete(id),
+                    None => sink.write(&record),
+                }
+            }
+            Event::Polled(index) => own[index].as_mut().unwrap_or(&mut shared).flush(),
+        };
+        if let Err(err) = step {
+            result = Err(err);
+            break;
+        }
+    }
+
+    for sink in own.iter_mut().flatten() {
+        sink.finish()?;
+    }
+    shared.finish()?;
+    result?;
+
+    for watcher in watchers {
+        watcher.join().ok();
+    }
+    Ok(Completion::Interrupted)
+}
+
+/// Polls one source, remembering what it already emitted.
+struct Watcher {
+    source: Source,
+    wrap_width: usize,
+    /// The database read, for the sources read from one.
+    database: Option<PathBuf>,
+    /// When the database was last modified, and its size, as last polled.
+    stamp: [Option<(SystemTime, u64)>; 2],
+    /// What changes when a record does (as its modification date), for
+    /// every record emitted so far, by `@id`.
+    seen: BTreeMap<String, String>,
+    /// The `ROWID` of the last message read, for Messages, which only
+    /// ever get added.
+    last: Option<i64>,
+}
+
+impl Watcher {
+    fn new(source: Source, wrap_width: usize) -> Result<Self> {
+        let database = match source {
+            Source::Notes => None,
+            Source::Messages => Some(messages::default_path()?),
+            Source::SafariHistory => Some(safari::default_dir()?.join("History.db")),
+        };
+        Ok(Watcher {
+            source,
+            wrap_width,
+            database,
+            stamp: [None, None],
+            seen: BTreeMap::new(),
+            last: None,
+        })
+    }
+
+    fn run(&mut self, index: usize, interval: Duration, events: mpsc::Sender<Event>) {
+        while !signal::interrupted() {
+            let started = Instant::now();
+            let mut send = |record| events.send(Event::Record(index, record)).is_ok();
+            match self.poll(&mut send) {
+                Ok(false) => return,
+                Ok(true) => {
+                    if events.send(Event::Polled(index)).is_err() {
+                        return;
+                    }
+                }
+                // A source failing (say, its app not running) shouldn't take
+                // the other sources down, so report it and try again later:
+                Err(err) => eprintln!("Warning: failed to poll {}: {err}", self.source),
+            }
+            while started.elapsed() < interval && !signal::interrupted() {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    /// Sends on the records that are new or changed since the last poll,
+    /// returning whether they all could be.
+    fn poll(&mut self, send: &mut dyn FnMut(Value) -> bool) -> Result<bool> {
+        let records = match self.source {
+            Source::Notes => self.poll_notes()?,
+            Source::Messages => return self.poll_messages(send),
+            Source::SafariHistory => self.poll_safari_history()?,
+        };
+        Ok(records.into_iter().all(send))
+    }
+
+    /// Returns whether the database changed since the last poll, as
+    /// `--watch` on the Messages emitter tells.
+    fn database_changed(&mut self) -> bool {
+        let Some(path) = &self.database else {
+            return true;
+        };
+        let stamp = stamp(path);
+        let changed = stamp != self.stamp || stamp == [None, None];
+        self.stamp = stamp;
+        changed
+    }
+
+    /// Forgets the records emitted before that are no longer in `present`,
+    /// returning tombstones for them.
+    fn forget_missing(&mut self, present: &BTreeSet<String>) -> Vec<Value> {
+        let gone: Vec<String> = self
+            .seen
+            .keys()
+            .filter(|id| !present.contains(*id))
+            .cloned()
+            .collect();
+        gone.iter()
+            .map(|id| {
+                self.seen.remove(id);
+                tombstone(id)
+            })
+            .collect()
+    }
+
+    /// Lists the notes without their bodies first, fetching the full notes
+    /// only if any of them changed.
+    fn poll_notes(&mut self) -> Result<Vec<Value>> {
+        let Some(catalog) = osascript::run(notes::APP, &notes::script(false), signal::interrupted)?
+        else {
+            return Ok(Vec::new());
+        };
+        let catalog = notes::parse(&catalog).collect::<Result<Vec<_>>>()?;
+        // Notes deleted since the last poll are missing from the catalog:
+        let present = catalog.iter().map(notes::Note::urn).collect();
+        let mut records = self.forget_missing(&present);
+        let changed = catalog
+            .iter()
+            .any(|note| self.seen.get(&note.urn()) != Some(&note.modified));
+        if !changed {
+            return Ok(records);
+        }
+
+        let Some(output) = osascript::run(notes::APP, &notes::script(true), signal::interrupted)?
+        else {
+            return Ok(records);
+        };
+        for note in notes::parse(&output) {
+            let note = note?;
+            let id = note.urn();
+            if self.seen.get(&id) == Some(&note.modified) {
+                continue;
+            }
+            records.push(note.to_json(self.wrap_width)?);
+            self.seen.insert(id, note.modified);
+        }
+        Ok(records)
+    }
+
+    /// Sends on the messages added since the last poll, with their chats
+    /// and the files attached to them, a page at a time, without looking
+    /// up their handles in Contacts.
+    fn poll_messages(&mut self, send: &mut dyn FnMut(Value) -> bool) -> Result<bool> {
+        let Some(path) = self.database.clone().filter(|_| self.database_changed()) else {
+            return Ok(true);
+        };
+        let filter = messages::Filter {
+            after: self.last,
+            ..Default::default()
+        };
+        let mut pages = messages::read(&path, &filter)?;
+        for archive in pages.by_ref() {
+            // Read again from the same message at the next poll:
+            if signal::interrupted() {
+                return Ok(true);
+            }
+            let archive = archive?;
+            let mut records = archive
+                .chats
+                .iter()
+                .map(|chat| chat.to_json())
+                .chain(
+                    archive
+                        .attachments
+                        .iter()
+                        .map(|attachment| attachment.to_json(None, None)),
+                )
+                .chain(archive.messages.iter().map(|message| message.to_json()));
+            if !records.all(&mut *send) {
+                return Ok(false);
+            }
+        }
+        self.last = Some(pages.last_row());
+        Ok(true)
+    }
+
+    /// Reads the pages in the history, emitting those visited since the
+    /// last poll, and deleting those cleared from it.
+    fn poll_safari_history(&mut self) -> Result<Vec<Value>> {
+        let Some(path) = self.database.clone().filter(|_| self.database_changed()) else {
+            return Ok(Vec::new());
+        };
+        let pages = safari::history::read(&path, &Default::default())?;
+        let present = pages.iter().map(|page| page.urn()).collect();
+        let mut records = self.forget_missing(&present);
+        for page in pages {
+            let id = page.urn();
+            let visited = format!(
+                "{} {}",
+                page.visit_count,
+                page.visits.last().map_or("", String::as_str)
+            );
+            if self.seen.get(&id) == Some(&visited) {
+                continue;
+            }
+            records.push(page.to_json());
+            self.seen.insert(id, visited);
+        }
+        Ok(records)
+    }
+}
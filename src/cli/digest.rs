@@ -0,0 +1,140 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `digest` program: a periodic email of the notes changed since the
+//! last one.
+
+use super::export::fetch_notes;
+use crate::{
+    Error, Result,
+    export::digest::{self, Digest},
+    osascript, signal,
+    signal::Completion,
+    timezone::TimeZone,
+};
+use std::{
+    eprintln, format, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Options for the `digest` program.
+#[derive(Clone, Debug, clap::Args)]
+pub struct DigestOptions {
+    /// Send the digest through Mail.app to this address
+    #[arg(long, value_name = "ADDRESS", required_unless_present = "mbox")]
+    pub to: Option<String>,
+
+    /// Append the digest to this mbox file
+    #[arg(long, value_name = "FILE")]
+    pub mbox: Option<PathBuf>,
+
+    /// The sender of messages appended to the mbox file
+    #[arg(long, value_name = "ADDRESS", default_value = "asimov-apple@localhost")]
+    pub from: String,
+
+    /// Where to keep the time digests have covered notes up to [default: ~/Library/Application Support/asimov-apple/digest-since]
+    #[arg(long, value_name = "PATH")]
+    pub state: Option<PathBuf>,
+
+    /// Before the first digest, cover the notes changed in this many days
+    #[arg(long, value_name = "DAYS", default_value = "7")]
+    pub days: u32,
+}
+
+/// Sends a digest of the notes changed since the last one, if any, and
+/// then records how far it got.
+pub fn run(opts: &DigestOptions) -> Result<Completion> {
+    let state = match &opts.state {
+        Some(path) => path.clone(),
+        None => digest::default_state_path()?,
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default();
+    let since = match fs::read_to_string(&state) {
+        Ok(since) => since.trim().to_string(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            TimeZone::local().to_local(now - i64::from(opts.days) * 86400)
+        }
+        Err(e) => {
+            return Err(Error::Io {
+                context: "reading the digest state",
+                source: e,
+            });
+        }
+    };
+
+    let Some(notes) = fetch_notes()? else {
+        return Ok(Completion::Interrupted);
+    };
+    let changed = digest::changed_since(&notes, &since);
+    if changed.is_empty() {
+        eprintln!("No notes changed since {}", since.replacen('T', " ", 1));
+        return Ok(Completion::Finished);
+    }
+    let digest = digest::render(&changed, &since);
+
+    if let Some(path) = &opts.mbox {
+        let to = opts.to.as_deref().unwrap_or(&opts.from);
+        append(path, &digest::mbox_message(&digest, &opts.from, to, now))?;
+        eprintln!(
+            "Appended a digest of {} to {}",
+            count(changed.len()),
+            path.display()
+        );
+    }
+    if let Some(to) = &opts.to {
+        if !send(&digest, to)? {
+            return Ok(Completion::Interrupted);
+        }
+        eprintln!("Sent a digest of {} to {to}", count(changed.len()));
+    }
+
+    // Only once delivered, so that a failed run is retried in full:
+    write_state(&state, &digest.until)?;
+    Ok(Completion::Finished)
+}
+
+fn count(notes: usize) -> String {
+    if notes == 1 {
+        "1 note".to_string()
+    } else {
+        format!("{notes} notes")
+    }
+}
+
+/// Sends `digest` through Mail.app, returning false if interrupted.
+fn send(digest: &Digest, to: &str) -> Result<bool> {
+    let script = digest::mail_script(digest, to);
+    Ok(osascript::run(digest::MAIL_APP, &script, signal::interrupted)?.is_some())
+}
+
+fn append(path: &Path, message: &str) -> Result<()> {
+    let io = |e| Error::Io {
+        context: "appending to the mbox file",
+        source: e,
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(io)?;
+    }
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(message.as_bytes()))
+        .map_err(io)
+}
+
+fn write_state(path: &Path, until: &str) -> Result<()> {
+    let io = |e| Error::Io {
+        context: "writing the digest state",
+        source: e,
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(io)?;
+    }
+    fs::write(path, format!("{until}\n")).map_err(io)
+}
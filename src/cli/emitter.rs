@@ -0,0 +1,322 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The options and output machinery shared by every emitter in this module,
+//! so that each Apple source gets the same flags, sinks, output formats,
+//! redaction, state file, and run summary, and they behave identically
+//! everywhere.
+
+use super::{log, sink::SinkOptions, stats::StatsOptions};
+use crate::{
+    Error, Result, provenance,
+    sandbox::Policy,
+    signal::Completion,
+    sink::{OutputFormat, Sink, SinkSpec, oxigraph::Target},
+    stats::Stats,
+    timezone::{self, TimeZone},
+};
+use serde_json::{Value, json};
+use std::{
+    borrow::Cow,
+    boxed::Box,
+    format, fs, io,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    time::Instant,
+    vec::Vec,
+};
+
+/// Options every emitter takes, whatever its source.
+#[derive(Clone, Debug, clap::Args)]
+pub struct EmitterOptions {
+    #[clap(flatten)]
+    pub sink: SinkOptions,
+
+    #[clap(flatten)]
+    pub stats: StatsOptions,
+
+    /// Leave these top-level fields out of every record, as in `--redact text,email`
+    #[arg(long, value_name = "FIELD", value_delimiter = ',')]
+    pub redact: Vec<String>,
+
+    /// Keep this source's state between runs in FILE, one per source: when it last ran, how many records it emitted, and the latest `dateModified` emitted, for `--resume`
+    #[arg(long, value_name = "FILE")]
+    pub state_file: Option<PathBuf>,
+
+    /// Once everything is read, confine the process: no more Apple Events, no network, and no writes outside `--allow-dir`
+    #[arg(long)]
+    pub least_privilege: bool,
+
+    /// A directory that sinks may write to in least-privilege mode
+    #[arg(long, value_name = "DIR", requires = "least_privilege")]
+    pub allow_dir: Vec<PathBuf>,
+
+    /// Let sinks connect to the network in least-privilege mode
+    #[arg(long, requires = "least_privilege")]
+    pub allow_network: bool,
+}
+
+impl EmitterOptions {
+    /// Runs an emitter as `program`, reporting the run summary (if
+    /// requested) however it ends.
+    pub fn run(
+        &self,
+        program: &'static str,
+        emit: impl FnOnce(&mut Stats) -> Result<Completion>,
+    ) -> Result<Completion> {
+        let mut stats = Stats::new(program);
+        let result = emit(&mut stats);
+        self.stats.report(&stats, &result);
+        result
+    }
+
+    /// Opens the output for records extracted through `backend`, first
+    /// confining the process in least-privilege mode.
+    pub fn open(&self, backend: &'static str) -> Result<Output> {
+        self.open_writing(backend, None)
+    }
+
+    /// Opens the output like [`open`](Self::open), for an emitter that
+    /// goes on writing into a directory of its own, which in least-privilege
+    /// mode must be within an `--allow-dir`. It is named as `what` if not.
+    pub fn open_writing(
+        &self,
+        backend: &'static str,
+        dir: Option<(&'static str, &Path)>,
+    ) -> Result<Output> {
+        if self.least_privilege {
+            self.policy(dir)?.enter()?;
+        }
+        Ok(Output {
+            sink: self.sink.open(backend)?,
+            count: 0,
+            redact: self.redact.clone(),
+            state_file: self.state_file.clone(),
+            cursor: self.cursor()?,
+            zone: TimeZone::local(),
+        })
+    }
+
+    /// Returns the latest `dateModified` emitted by the runs that kept
+    /// their state in the state file, if any, in seconds since the epoch.
+    pub fn cursor(&self) -> Result<Option<i64>> {
+        let Some(path) = &self.state_file else {
+            return Ok(None);
+        };
+        let state = match fs::read_to_string(path) {
+            Ok(state) => state,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Error::Io {
+                    context: "reading the state file",
+                    source: e,
+                });
+            }
+        };
+        let state: Value = serde_json::from_str(&state).map_err(|e| Error::Parse {
+            context: "reading the state file",
+            message: e.to_string(),
+        })?;
+        match state["cursor"].as_str() {
+            Some(cursor) => {
+                TimeZone::local()
+                    .instant(cursor)
+                    .map(Some)
+                    .ok_or_else(|| Error::Parse {
+                        context: "reading the state file",
+                        message: format!("not an ISO 8601 time: {cursor}"),
+                    })
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the least-privilege policy for the selected sink, refusing
+    /// sinks that would write outside the allowed directories or need a
+    /// network they aren't allowed.
+    fn policy(&self, dir: Option<(&'static str, &Path)>) -> Result<Policy> {
+        let mut policy = Policy {
+            writable: self.allow_dir.clone(),
+            network: self.allow_network,
+        };
+
+        let mut files: Vec<(&str, &Path)> = dir.into_iter().collect();
+        match &self.sink.sink {
+            SinkSpec::Stdout => {
+                if let OutputFormat::EmlDir(dir) = &self.sink.output_format {
+                    files.push(("eml directory", dir));
+                }
+            }
+            SinkSpec::Sqlite(path) | SinkSpec::Oxigraph(Target::Store(path)) => {
+                files.push(("sink", path));
+            }
+            spec @ (SinkSpec::Oxigraph(Target::Endpoint(_))
+            | SinkSpec::Webhook(_)
+            | SinkSpec::S3(_)) => {
+                if !self.allow_network {
+                    return Err(Error::Usage {
+                        message: format!("least-privilege mode: sink {spec} needs --allow-network"),
+                    });
+                }
+            }
+        }
+        if self.sink.embed_url.is_some() && !self.allow_network {
+            return Err(Error::Usage {
+                message: "least-privilege mode: --embed-url needs --allow-network".into(),
+            });
+        }
+        files.extend(
+            self.sink
+                .dead_letter
+                .iter()
+                .map(|path| ("dead-letter file", path.as_path())),
+        );
+        files.extend(
+            self.stats
+                .stats_file
+                .iter()
+                .map(|path| ("stats file", path.as_path())),
+        );
+        files.extend(
+            self.state_file
+                .iter()
+                .map(|path| ("state file", path.as_path())),
+        );
+        if let Some(path) = files.iter().find(|(_, path)| !policy.allows(path)) {
+            return Err(Error::Usage {
+                message: format!(
+                    "least-privilege mode: {} {} is outside every --allow-dir",
+                    path.0,
+                    path.1.display()
+                ),
+            });
+        }
+
+        // Webhook request bodies and uploads are staged in temporary files,
+        // and the log file (already open) is rotated by renaming it:
+        if let SinkSpec::Webhook(_) | SinkSpec::S3(_) = self.sink.sink {
+            policy.writable.push(std::env::temp_dir());
+        }
+        if let Some(path) = log::path() {
+            let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+            policy
+                .writable
+                .push(dir.unwrap_or(Path::new(".")).to_path_buf());
+        }
+        Ok(policy)
+    }
+}
+
+/// The selected sink, counting what goes into it.
+pub struct Output {
+    sink: Box<dyn Sink>,
+    count: usize,
+    /// The fields left out of every record.
+    redact: Vec<String>,
+    /// Where the source's state is kept, if anywhere.
+    state_file: Option<PathBuf>,
+    /// The latest `dateModified` emitted, in this run or the last, in
+    /// seconds since the epoch.
+    cursor: Option<i64>,
+    /// The zone the `dateModified` of records without an offset are in.
+    zone: TimeZone,
+}
+
+impl Output {
+    /// Writes a record that came from the given account and container
+    /// (a folder, list, calendar, and so on).
+    pub fn emit(
+        &mut self,
+        stats: &mut Stats,
+        record: &Value,
+        account: &str,
+        container: &str,
+    ) -> Result<()> {
+        if let Some(modified) = record
+            .get("dateModified")
+            .and_then(Value::as_str)
+            .and_then(|modified| self.zone.instant(modified))
+            && self.cursor.is_none_or(|cursor| cursor < modified)
+        {
+            self.cursor = Some(modified);
+        }
+        let mut record = Cow::Borrowed(record);
+        if !self.redact.is_empty()
+            && let Some(fields) = record.to_mut().as_object_mut()
+        {
+            for field in &self.redact {
+                fields.remove(field);
+            }
+        }
+        self.sink.write(&record)?;
+        stats.emitted(account, container);
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Stores an attachment of the records with the sink, returning the
+    /// URL it is kept at, if the sink keeps attachments.
+    pub fn attach(&mut self, data: &[u8], mime: &str, extension: &str) -> Result<Option<String>> {
+        self.sink.attach(data, mime, extension)
+    }
+
+    /// Makes everything emitted so far visible downstream, for emitters
+    /// that keep running.
+    pub fn flush(&mut self) -> Result<()> {
+        self.sink.flush()
+    }
+
+    /// Deletes the record with the given `@id` downstream.
+    pub fn delete(&mut self, stats: &mut Stats, id: &str) -> Result<()> {
+        self.sink.delete(id)?;
+        stats.deleted();
+        Ok(())
+    }
+
+    /// Returns how many records were written so far.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Finishes the sink, timing it as the `finish` phase, and then
+    /// records the source's state.
+    pub fn finish(mut self, stats: &mut Stats) -> Result<()> {
+        let started = Instant::now();
+        let result = self.sink.finish();
+        stats.finish_phase("finish", started);
+        result?;
+        match &self.state_file {
+            Some(path) => write_state(path, &self.state(stats)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the source's state as of now.
+    fn state(&self, stats: &Stats) -> Value {
+        let mut state = json!({
+            "program": stats.program(),
+            "lastRun": provenance::now_iso8601(),
+            "emitted": self.count,
+            "cursor": self.cursor.map(timezone::iso8601),
+        });
+        if let Some(state) = state.as_object_mut() {
+            state.retain(|_, value| !value.is_null());
+        }
+        state
+    }
+}
+
+/// Writes the state of a source to `path`, replacing it only once
+/// complete.
+fn write_state(path: &Path, state: &Value) -> Result<()> {
+    let io = |e| Error::Io {
+        context: "writing the state file",
+        source: e,
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(io)?;
+    }
+    let mut partial = path.as_os_str().to_os_string();
+    partial.push(".part");
+    fs::write(&partial, format!("{state:#}\n")).map_err(io)?;
+    fs::rename(&partial, path).map_err(io)
+}
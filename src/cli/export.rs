@@ -0,0 +1,256 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `export` subcommands: notes into other apps' formats.
+
+use crate::{
+    Error, Result,
+    export::{self, ExportSummary, archive, feed},
+    notes, osascript, signal,
+    signal::Completion,
+};
+use std::{
+    eprintln, format,
+    path::{Path, PathBuf},
+    vec::Vec,
+};
+
+/// The formats notes can be exported to.
+#[derive(Clone, Debug, clap::Subcommand)]
+pub enum ExportCommand {
+    /// Export notes into an Obsidian vault
+    Obsidian(ObsidianOptions),
+
+    /// Export notes into a Logseq graph
+    Logseq(LogseqOptions),
+
+    /// Export notes as Org mode files
+    Org(OrgOptions),
+
+    /// Export notes as an Evernote ENEX archive
+    Enex(EnexOptions),
+
+    /// Export the most recently modified notes as an Atom feed
+    Feed(FeedOptions),
+
+    /// Export notes in the Markdown and CSV layout Notion imports
+    Notion(NotionOptions),
+
+    /// Export notes as a Joplin JEX archive
+    Jex(JexOptions),
+
+    /// Export notes as a static website with an index and search
+    Html(HtmlOptions),
+
+    /// Export notes into a zip archive with a manifest, or verify one
+    Archive(ArchiveOptions),
+
+    /// Export notes as PDF, a file per note or one with a table of contents
+    Pdf(PdfOptions),
+
+    /// Export notes into a Git repository, committing each run
+    Git(GitOptions),
+}
+
+/// Options for exporting into an Obsidian vault.
+#[derive(Clone, Debug, clap::Args)]
+pub struct ObsidianOptions {
+    /// The vault to export into (created if missing)
+    #[arg(value_name = "VAULT_DIR")]
+    pub vault: PathBuf,
+}
+
+/// Options for exporting into a Logseq graph.
+#[derive(Clone, Debug, clap::Args)]
+pub struct LogseqOptions {
+    /// The graph to export into (created if missing)
+    #[arg(value_name = "GRAPH_DIR")]
+    pub graph: PathBuf,
+}
+
+/// Options for exporting as Org mode files.
+#[derive(Clone, Debug, clap::Args)]
+pub struct OrgOptions {
+    /// The directory to export into (created if missing)
+    #[arg(value_name = "DIR")]
+    pub dir: PathBuf,
+}
+
+/// Options for exporting as an Evernote ENEX archive.
+#[derive(Clone, Debug, clap::Args)]
+pub struct EnexOptions {
+    /// The `.enex` file to write (replaced if it exists)
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+}
+
+/// Options for exporting an Atom feed.
+#[derive(Clone, Debug, clap::Args)]
+pub struct FeedOptions {
+    /// The number of notes to include
+    #[arg(
+        short = 'n',
+        long = "limit",
+        value_name = "COUNT",
+        default_value = "20"
+    )]
+    pub limit: usize,
+
+    /// The file to write the feed to [default: stdout]
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+/// Options for exporting for Notion.
+#[derive(Clone, Debug, clap::Args)]
+pub struct NotionOptions {
+    /// The directory to export into (created if missing)
+    #[arg(value_name = "DIR")]
+    pub dir: PathBuf,
+}
+
+/// Options for exporting as a Joplin JEX archive.
+#[derive(Clone, Debug, clap::Args)]
+pub struct JexOptions {
+    /// The `.jex` file to write (replaced if it exists)
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+}
+
+/// Options for exporting as a static website.
+#[derive(Clone, Debug, clap::Args)]
+pub struct HtmlOptions {
+    /// The directory to export into (created if missing)
+    #[arg(value_name = "DIR")]
+    pub dir: PathBuf,
+}
+
+/// Options for exporting into a zip archive.
+#[derive(Clone, Debug, clap::Args)]
+pub struct ArchiveOptions {
+    /// The `.zip` file to write (replaced if it exists), or to verify
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// What to write each note as: `markdown` or `html`
+    #[arg(long, value_name = "FORMAT", default_value = "markdown")]
+    pub format: archive::Format,
+
+    /// Check an existing archive against its manifest and the library,
+    /// instead of writing one
+    #[arg(long, conflicts_with = "format")]
+    pub verify: bool,
+}
+
+/// Options for exporting as PDF.
+#[derive(Clone, Debug, clap::Args)]
+pub struct PdfOptions {
+    /// The directory to export into (created if missing), or with
+    /// `--combined`, the `.pdf` file to write (replaced if it exists)
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+
+    /// Write every note into one PDF, after a table of contents
+    #[arg(long)]
+    pub combined: bool,
+}
+
+/// Options for exporting into a Git repository.
+#[derive(Clone, Debug, clap::Args)]
+pub struct GitOptions {
+    /// The repository to export into (created if missing)
+    #[arg(value_name = "REPO_DIR")]
+    pub repo: PathBuf,
+}
+
+/// Runs an export.
+pub fn run(command: &ExportCommand) -> Result<Completion> {
+    let Some(notes) = fetch_notes()? else {
+        return Ok(Completion::Interrupted);
+    };
+    if let ExportCommand::Archive(opts) = command
+        && opts.verify
+    {
+        return verify_archive(&notes, &opts.file);
+    }
+    let summary = match command {
+        ExportCommand::Obsidian(opts) => export::obsidian::export(&notes, &opts.vault)?,
+        ExportCommand::Logseq(opts) => export::logseq::export(&notes, &opts.graph)?,
+        ExportCommand::Org(opts) => export::org::export(&notes, &opts.dir)?,
+        ExportCommand::Enex(opts) => export::enex::export(&notes, &opts.file)?,
+        ExportCommand::Notion(opts) => export::notion::export(&notes, &opts.dir)?,
+        ExportCommand::Jex(opts) => export::jex::export(&notes, &opts.file)?,
+        ExportCommand::Archive(opts) => archive::export(&notes, &opts.file, opts.format)?,
+        ExportCommand::Pdf(opts) if opts.combined => {
+            export::pdf::export_combined(&notes, &opts.path)?
+        }
+        ExportCommand::Pdf(opts) => export::pdf::export(&notes, &opts.path)?,
+        ExportCommand::Git(opts) => {
+            let export = export::git::export(&notes, &opts.repo)?;
+            match &export.commit {
+                Some(commit) => eprintln!(
+                    "Committed {commit}: {} added, {} changed, {} deleted",
+                    export.added, export.changed, export.deleted
+                ),
+                None => eprintln!("Nothing changed since the last export"),
+            }
+            export.summary
+        }
+        ExportCommand::Html(opts) => export::html::export(&notes, &opts.dir)?,
+        ExportCommand::Feed(opts) => {
+            let atom = feed::atom(&notes, opts.limit, None)?;
+            match &opts.output {
+                Some(path) => export::write(path, atom)?,
+                None => std::print!("{atom}"),
+            }
+            ExportSummary {
+                notes: notes.len().min(opts.limit),
+                attachments: 0,
+            }
+        }
+    };
+    report(&summary);
+    Ok(Completion::Finished)
+}
+
+/// Checks an archive against the library, listing the problems found.
+fn verify_archive(notes: &[notes::Note], file: &Path) -> Result<Completion> {
+    let verification = archive::verify(notes, file)?;
+    for problem in &verification.problems {
+        eprintln!("{problem}");
+    }
+    if !verification.problems.is_empty() {
+        return Err(Error::Mismatch {
+            what: format!("archive {}", file.display()),
+            problems: verification.problems.len(),
+        });
+    }
+    eprintln!(
+        "Verified {} notes in {}",
+        verification.notes,
+        file.display()
+    );
+    Ok(Completion::Finished)
+}
+
+/// Reads every note, with its body, or returns `None` if interrupted.
+pub(super) fn fetch_notes() -> Result<Option<Vec<notes::Note>>> {
+    let Some(stdout) = osascript::run(notes::APP, &notes::script(true), signal::interrupted)?
+    else {
+        return Ok(None);
+    };
+    notes::parse(&stdout).collect::<Result<_>>().map(Some)
+}
+
+fn report(summary: &ExportSummary) {
+    #[cfg(feature = "tracing")]
+    asimov_module::tracing::info!(
+        target: "asimov_apple_module::export",
+        notes = summary.notes,
+        attachments = summary.attachments,
+        "finished export"
+    );
+    eprintln!(
+        "Exported {} notes and {} attachments",
+        summary.notes, summary.attachments
+    );
+}
@@ -0,0 +1,432 @@
+// This is free and unencumbered software released into the public domain.
+
+//! A minimal local HTTP/1.1 API serving the same JSON records as the
+//! emitters, for tools that would rather not spawn a process per query.
+
+use crate::{
+    Error, Result,
+    export::feed,
+    notes, osascript, signal,
+    signal::Completion,
+    timezone::{LocalTime, TimeZone},
+};
+use serde_json::{Value, json};
+use std::{
+    borrow::ToOwned,
+    eprintln, format,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    string::{String, ToString},
+    thread,
+    time::{Duration, Instant},
+    vec::Vec,
+};
+
+/// How often to check for Ctrl-C while waiting for the next connection.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a client has to send its whole request, and to take the
+/// response, so that one that stalls can't hold up the server for long.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The longest request line or header line read, so that a client can't
+/// make the server buffer without end.
+const MAX_LINE: u64 = 8192;
+
+/// The most header lines read before giving up on a request.
+const MAX_HEADERS: usize = 100;
+
+/// The environment variable holding the bearer token clients must present.
+pub const TOKEN_VAR: &str = "ASIMOV_APPLE_HTTP_TOKEN";
+
+/// Options for the HTTP server.
+#[derive(Clone, Debug, clap::Args)]
+pub struct ServeHttpOptions {
+    /// The address to listen on
+    #[arg(
+        short = 'l',
+        long = "listen",
+        value_name = "ADDR",
+        default_value = "127.0.0.1:8080"
+    )]
+    pub listen: SocketAddr,
+
+    /// Read the bearer token clients must present from FILE [default: $ASIMOV_APPLE_HTTP_TOKEN, or random]
+    #[arg(long, value_name = "FILE")]
+    pub token_file: Option<PathBuf>,
+
+    /// Wrap width for plain-text conversion from HTML
+    #[arg(
+        short = 'w',
+        long = "wrap-width",
+        value_name = "WIDTH",
+        default_value = "80"
+    )]
+    pub wrap_width: usize,
+}
+
+/// A response ready to be written back to the client.
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self::json(200, &body)
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, &json!({ "error": message }))
+    }
+
+    fn json(status: u16, body: &Value) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    fn atom(feed: String) -> Self {
+        Response {
+            status: 200,
+            content_type: "application/atom+xml; charset=utf-8",
+            body: feed.into_bytes(),
+        }
+    }
+}
+
+/// Serves HTTP requests until Ctrl-C is pressed.
+pub fn serve(opts: &ServeHttpOptions) -> Result<Completion> {
+    // Not given on the command line, where other users could read it in
+    // the process list:
+    let token = match &opts.token_file {
+        Some(path) => Some(
+            fs::read_to_string(path)
+                .map_err(|e| Error::Io {
+                    context: "reading the token file",
+                    source: e,
+                })?
+                .trim()
+                .to_string(),
+        ),
+        None => std::env::var(TOKEN_VAR).ok(),
+    };
+    let token = match token.filter(|token| !token.is_empty()) {
+        Some(token) => token,
+        None => {
+            let token = random_token()?;
+            eprintln!("Generated access token: {token}");
+            token
+        }
+    };
+
+    let listener = TcpListener::bind(opts.listen).map_err(|e| Error::Io {
+        context: "binding the HTTP listener",
+        source: e,
+    })?;
+    listener.set_nonblocking(true)?;
+    eprintln!("Listening on http://{}", opts.listen);
+
+    loop {
+        if signal::interrupted() {
+            return Ok(Completion::Interrupted);
+        }
+        match listener.accept() {
+            Ok((stream, _peer)) => {
+                if let Err(_err) = handle_connection(opts, &token, stream) {
+                    #[cfg(feature = "tracing")]
+                    asimov_module::tracing::warn!(
+                        target: "asimov_apple_module::http",
+                        peer = %_peer,
+                        error = %_err,
+                        "failed to handle HTTP connection"
+                    );
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                return Err(Error::Io {
+                    context: "accepting an HTTP connection",
+                    source: e,
+                });
+            }
+        }
+    }
+}
+
+fn handle_connection(opts: &ServeHttpOptions, token: &str, stream: TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(Deadline {
+        stream: stream.try_clone()?,
+        until: Instant::now() + REQUEST_TIMEOUT,
+    });
+
+    let mut request_line = String::new();
+    match read_line(&mut reader, &mut request_line) {
+        Ok(true) => {}
+        Ok(false) => {
+            return write_response(stream, &Response::error(431, "request line too long"));
+        }
+        Err(e) if is_timeout(&e) => {
+            return write_response(stream, &Response::error(408, "request timed out"));
+        }
+        Err(e) => return Err(e),
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_owned();
+    let target = parts.next().unwrap_or("").to_owned();
+
+    let mut authorization = None;
+    for count in 0.. {
+        let mut header = String::new();
+        match read_line(&mut reader, &mut header) {
+            Ok(true) if count < MAX_HEADERS => {}
+            Ok(_) => {
+                return write_response(stream, &Response::error(431, "request headers too large"));
+            }
+            Err(e) if is_timeout(&e) => {
+                return write_response(stream, &Response::error(408, "request timed out"));
+            }
+            Err(e) => return Err(e),
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("authorization")
+        {
+            authorization = Some(value.trim().to_owned());
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    asimov_module::tracing::debug!(
+        target: "asimov_apple_module::http",
+        %method,
+        %target,
+        "handling HTTP request"
+    );
+
+    let expected = format!("Bearer {token}");
+    let authorized =
+        authorization.is_some_and(|value| constant_time_eq(value.as_bytes(), expected.as_bytes()));
+    let response = if !authorized {
+        Response::error(401, "missing or invalid bearer token")
+    } else if method != "GET" {
+        Response::error(405, "only GET is supported")
+    } else {
+        route(opts, &target)
+    };
+
+    write_response(stream, &response)
+}
+
+/// A connection that can be read from until a deadline, however slowly
+/// the client sends.
+struct Deadline {
+    stream: TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+/// Whether reading failed because the client took too long.
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    )
+}
+
+/// Reads a line of at most [`MAX_LINE`] bytes, returning whether it fit
+/// (an empty line at the end of the stream does).
+fn read_line(reader: &mut BufReader<Deadline>, line: &mut String) -> io::Result<bool> {
+    let read = reader.by_ref().take(MAX_LINE).read_line(line)?;
+    Ok(read == 0 || line.ends_with('\n') || (read as u64) < MAX_LINE)
+}
+
+/// Compares two byte strings in time that depends only on their lengths,
+/// so that how long a token takes to be rejected tells nothing of how
+/// much of it was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let diff = a
+        .iter()
+        .zip(b)
+        .fold(a.len() ^ b.len(), |diff, (x, y)| diff | usize::from(x ^ y));
+    core::hint::black_box(diff) == 0
+}
+
+fn route(opts: &ServeHttpOptions, target: &str) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query: Vec<(String, String)> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let param = |key: &str| {
+        query
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    };
+
+    if path == "/feed" {
+        return feed(opts, param("limit"));
+    }
+
+    let result = match path {
+        "/notes" => match param("since").map(str::parse::<LocalTime>).transpose() {
+            Ok(since) => list_notes(opts, since.as_ref()),
+            Err(message) => return Response::error(400, &message),
+        },
+        "/folders" => list_folders(),
+        _ => match path.strip_prefix("/notes/") {
+            Some(id) => {
+                let id = percent_encoding::percent_decode_str(id).decode_utf8_lossy();
+                get_note(opts, &id)
+            }
+            None => return Response::error(404, "no such endpoint"),
+        },
+    };
+
+    match result {
+        Ok(Some(body)) => Response::ok(body),
+        Ok(None) => Response::error(503, "server is shutting down"),
+        Err(err) => Response::error(status_for(&err), &err.to_string()),
+    }
+}
+
+/// Maps an error to the HTTP status code best describing it.
+fn status_for(err: &Error) -> u16 {
+    match err {
+        Error::Usage { .. } => 400,
+        Error::PermissionDenied { .. } | Error::NoFullDiskAccess { .. } => 403,
+        Error::NotFound { .. } => 404,
+        Error::Unavailable { .. } => 503,
+        Error::Timeout { .. } => 504,
+        _ => 500,
+    }
+}
+
+fn list_notes(opts: &ServeHttpOptions, since: Option<&LocalTime>) -> Result<Option<Value>> {
+    let Some(output) = osascript::run(notes::APP, &notes::script(true), signal::interrupted)?
+    else {
+        return Ok(None);
+    };
+    let zone = TimeZone::local();
+    let since = since.and_then(|since| zone.to_timestamp(since.as_str()));
+    let mut records = Vec::new();
+    for note in notes::parse(&output) {
+        let note = note?;
+        if let Some(since) = since
+            && zone
+                .to_timestamp(&note.modified)
+                .is_none_or(|modified| modified < since)
+        {
+            continue;
+        }
+        records.push(note.to_json(opts.wrap_width)?);
+    }
+    Ok(Some(Value::Array(records)))
+}
+
+/// Serves the Atom feed of recently modified notes.
+fn feed(opts: &ServeHttpOptions, limit: Option<&str>) -> Response {
+    let limit = match limit.map(str::parse::<usize>) {
+        None => feed::DEFAULT_LIMIT,
+        Some(Ok(limit)) => limit,
+        Some(Err(_)) => return Response::error(400, "limit must be a whole number"),
+    };
+    let result =
+        osascript::run(notes::APP, &notes::script(true), signal::interrupted).and_then(|output| {
+            let Some(output) = output else {
+                return Ok(None);
+            };
+            let notes = notes::parse(&output).collect::<Result<Vec<_>>>()?;
+            let base = format!("http://{}", opts.listen);
+            feed::atom(&notes, limit, Some(&base)).map(Some)
+        });
+    match result {
+        Ok(Some(atom)) => Response::atom(atom),
+        Ok(None) => Response::error(503, "server is shutting down"),
+        Err(err) => Response::error(status_for(&err), &err.to_string()),
+    }
+}
+
+fn get_note(opts: &ServeHttpOptions, urn: &str) -> Result<Option<Value>> {
+    let id = notes::id_from_urn(urn).ok_or_else(|| Error::Usage {
+        message: format!("not an Apple Notes URN: {urn}"),
+    })?;
+    let Some(output) = osascript::run(notes::APP, &notes::note_script(id), signal::interrupted)?
+    else {
+        return Ok(None);
+    };
+    let note = notes::parse(&output)
+        .next()
+        .transpose()?
+        .ok_or_else(|| Error::NotFound {
+            what: format!("note {urn}"),
+        })?;
+    Ok(Some(note.to_json(opts.wrap_width)?))
+}
+
+fn list_folders() -> Result<Option<Value>> {
+    let Some(output) = osascript::run(notes::APP, notes::folders_script(), signal::interrupted)?
+    else {
+        return Ok(None);
+    };
+    let folders = notes::parse_folders(&output)
+        .map(|folder| folder.map(|folder| folder.to_json()))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Some(Value::Array(folders)))
+}
+
+fn write_response(mut stream: TcpStream, response: &Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+/// Returns 128 bits from the system's CSPRNG as a hex string.
+fn random_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(&mut bytes))
+        .map_err(|e| Error::Io {
+            context: "generating an access token",
+            source: e,
+        })?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
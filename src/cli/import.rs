@@ -0,0 +1,120 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The importer: loads the JSONL that the emitters write into a database
+//! or graph store, completing the extract→load pipeline.
+
+use super::emitter::EmitterOptions;
+use crate::{
+    Error, Result, signal,
+    signal::Completion,
+    sink::{SinkSpec, str_field, tombstone_id},
+    stats::Stats,
+};
+use serde_json::Value;
+use std::{
+    boxed::Box,
+    format,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    time::Instant,
+};
+
+/// The backend recorded in the provenance of imported records.
+const BACKEND: &str = "jsonl";
+
+/// Options for the importer.
+#[derive(Clone, Debug, clap::Args)]
+pub struct ImportOptions {
+    /// Read records from this file instead of stdin
+    #[arg(value_name = "FILE", default_value = "-")]
+    pub input: PathBuf,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Upserts every record read from the input into the selected sink by
+/// its `@id`, and deletes those that tombstones mark as deleted.
+pub fn import(opts: &ImportOptions) -> Result<Completion> {
+    opts.output
+        .run("asimov-apple-importer", |stats| import_records(opts, stats))
+}
+
+fn import_records(opts: &ImportOptions, stats: &mut Stats) -> Result<Completion> {
+    if opts.output.sink.sink == SinkSpec::Stdout {
+        return Err(Error::Usage {
+            message: "the importer needs a --sink to import into".to_string(),
+        });
+    }
+
+    let input = open_input(&opts.input)?;
+    let mut output = opts.output.open(BACKEND)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    for (index, line) in input.lines().enumerate() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            break;
+        }
+
+        let Some(record) = parse_record(index, line)? else {
+            continue;
+        };
+        match tombstone_id(&record) {
+            Some(id) => output.delete(stats, id)?,
+            None => {
+                let account = str_field(&record, "account").unwrap_or_default();
+                let container = str_field(&record, "isPartOf").unwrap_or_default();
+                output.emit(stats, &record, account, container)?;
+            }
+        }
+    }
+    stats.finish_phase("import", started);
+
+    output.finish(stats)?;
+
+    #[cfg(feature = "tracing")]
+    asimov_module::tracing::info!(
+        target: "asimov_apple_module::importer",
+        records = stats.total(),
+        "finished importing records"
+    );
+
+    Ok(completion)
+}
+
+/// Opens `path` for reading records, or stdin for `-`.
+pub(super) fn open_input(path: &Path) -> Result<Box<dyn BufRead>> {
+    if path.as_os_str() == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    let file = File::open(path).map_err(|e| Error::Io {
+        context: "opening the input file",
+        source: e,
+    })?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+/// Parses the record on the 0-based line `index` of the input, skipping
+/// blank lines. Every record must have an `@id`.
+pub(super) fn parse_record(index: usize, line: io::Result<String>) -> Result<Option<Value>> {
+    let line = line.map_err(|e| Error::Io {
+        context: "reading input records",
+        source: e,
+    })?;
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    let invalid = |message: &str| Error::Parse {
+        context: "reading input records",
+        message: format!("line {}: {message}", index + 1),
+    };
+    let record: Value = serde_json::from_str(&line).map_err(|e| invalid(&e.to_string()))?;
+    if str_field(&record, "@id").is_none() {
+        return Err(invalid("record has no @id"));
+    }
+    Ok(Some(record))
+}
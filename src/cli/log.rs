@@ -0,0 +1,115 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `--log-file` option: a rotated log of every run, on top of the
+//! tracing output on stderr.
+
+use crate::{
+    Error, Result,
+    log::{RotatingFile, Rotation},
+    provenance::now_iso8601,
+};
+use std::{
+    format,
+    io::{self, Write},
+    path::PathBuf,
+    process,
+    string::String,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+    vec::Vec,
+};
+
+static LOG: OnceLock<Mutex<RotatingFile>> = OnceLock::new();
+
+/// Options for logging to a file.
+#[derive(Clone, Debug, clap::Args)]
+pub struct LogOptions {
+    /// Also append log messages, errors, and the run summary to this file
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Rotate the log file before it grows beyond this many MiB
+    #[arg(long, value_name = "MIB", default_value = "10", global = true)]
+    pub log_max_size: u64,
+
+    /// Rotate the log file when a run starts with it older than this many days
+    #[arg(long, value_name = "DAYS", default_value = "7", global = true)]
+    pub log_max_age: u64,
+
+    /// How many rotated log files to keep
+    #[arg(long, value_name = "COUNT", default_value = "5", global = true)]
+    pub log_keep: usize,
+}
+
+/// Opens the log file, if one was requested, and notes the start of the run.
+pub fn install(opts: &LogOptions) -> Result<()> {
+    let Some(path) = &opts.log_file else {
+        return Ok(());
+    };
+    let rotation = Rotation {
+        max_size: opts.log_max_size.saturating_mul(1024 * 1024),
+        max_age: (opts.log_max_age > 0).then(|| Duration::from_secs(opts.log_max_age * 86400)),
+        keep: opts.log_keep,
+    };
+    let file = RotatingFile::open(path, rotation).map_err(|e| Error::Io {
+        context: "opening the log file",
+        source: e,
+    })?;
+    LOG.set(Mutex::new(file)).ok();
+
+    let args: Vec<String> = std::env::args().collect();
+    line(&format!(
+        "started (pid {}): {}",
+        process::id(),
+        args.join(" ")
+    ));
+    Ok(())
+}
+
+/// Returns whether a log file is in use.
+pub fn enabled() -> bool {
+    LOG.get().is_some()
+}
+
+/// Returns the path of the log file, if one is in use.
+pub fn path() -> Option<PathBuf> {
+    let file = LOG.get()?.lock().ok()?;
+    Some(file.path().to_path_buf())
+}
+
+/// Appends a timestamped line to the log file, if any. Logging is best
+/// effort: a full disk shouldn't fail the run it's meant to record.
+pub fn line(message: &str) {
+    if let Some(log) = LOG.get()
+        && let Ok(mut file) = log.lock()
+    {
+        writeln!(file, "{} {message}", now_iso8601()).ok();
+        file.flush().ok();
+    }
+}
+
+/// Writes tracing output to the log file.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match LOG.get() {
+            Some(log) => log
+                .lock()
+                .map_err(|_| io::Error::other("log file lock poisoned"))?
+                .write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match LOG.get() {
+            Some(log) => log
+                .lock()
+                .map_err(|_| io::Error::other("log file lock poisoned"))?
+                .flush(),
+            None => Ok(()),
+        }
+    }
+}
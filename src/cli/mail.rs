@@ -0,0 +1,325 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `mail` programs.
+
+use super::emitter::EmitterOptions;
+use crate::{
+    Error, Result,
+    mail::{self, Backend, mime::Attachment},
+    osascript, signal,
+    signal::Completion,
+    sink::{OutputFormat, mbox},
+    stats::Stats,
+    timezone::LocalTime,
+};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    eprintln, format, fs,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    time::Instant,
+    vec::Vec,
+};
+
+/// Options for the Apple Mail emitter.
+#[derive(Clone, Debug, clap::Args)]
+pub struct EmitOptions {
+    /// Only read this account, by name or glob, as in `Work*`; `On My Mac` for local mailboxes (repeatable)
+    #[arg(long = "account", value_name = "NAME")]
+    pub accounts: Vec<String>,
+
+    /// Don't read this account, by name or glob (repeatable)
+    #[arg(long = "exclude-account", value_name = "NAME")]
+    pub excluded_accounts: Vec<String>,
+
+    /// Only read this mailbox, by name or glob, as in `Archive*` (repeatable)
+    #[arg(long = "mailbox", value_name = "NAME")]
+    pub mailboxes: Vec<String>,
+
+    /// Don't read this mailbox, by name or glob, as in `Junk` (repeatable)
+    #[arg(long = "exclude-mailbox", value_name = "NAME")]
+    pub excluded_mailboxes: Vec<String>,
+
+    /// Only emit messages received at or after this date, or date and time
+    #[arg(long, value_name = "DATE")]
+    pub since: Option<LocalTime>,
+
+    /// Only emit messages received before this date, or date and time
+    #[arg(long, value_name = "DATE")]
+    pub until: Option<LocalTime>,
+
+    /// Only emit unread messages
+    #[arg(long)]
+    pub unread: bool,
+
+    /// Only emit flagged messages
+    #[arg(long)]
+    pub flagged: bool,
+
+    /// Only emit messages from this address, or part of one, as in `@example.com` (repeatable)
+    #[arg(long = "from", value_name = "ADDRESS")]
+    pub from: Vec<String>,
+
+    /// Only emit messages to, or copied to, this address, or part of one (repeatable)
+    #[arg(long = "to", value_name = "ADDRESS")]
+    pub to: Vec<String>,
+
+    /// Read messages through Mail, or straight from its files, which is
+    /// much faster for large mailboxes but takes Full Disk Access
+    #[arg(long, value_name = "BACKEND", default_value = "mail")]
+    pub backend: Backend,
+
+    /// The Mail data to read with --backend emlx [default: ~/Library/Mail]
+    #[arg(long, value_name = "PATH")]
+    pub mail_dir: Option<PathBuf>,
+
+    /// Leave out message bodies and attachments, which take longest to read
+    #[arg(long)]
+    pub no_body: bool,
+
+    /// Write the attachments of each message emitted to this directory,
+    /// and link them from its record
+    #[arg(long, value_name = "PATH", conflicts_with = "no_body")]
+    pub attachments_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for emitting Mail's rules and smart mailboxes.
+#[derive(Clone, Debug, clap::Args)]
+pub struct RulesOptions {
+    /// The Mail data to read smart mailboxes from [default: ~/Library/Mail]
+    #[arg(long, value_name = "PATH")]
+    pub mail_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+impl EmitOptions {
+    /// Returns which messages the options select.
+    fn filter(&self) -> mail::Filter {
+        mail::Filter {
+            accounts: self.accounts.clone(),
+            excluded_accounts: self.excluded_accounts.clone(),
+            mailboxes: self.mailboxes.clone(),
+            excluded_mailboxes: self.excluded_mailboxes.clone(),
+            since: self.since.clone(),
+            until: self.until.clone(),
+            unread: self.unread,
+            flagged: self.flagged,
+            from: self.from.clone(),
+            to: self.to.clone(),
+        }
+    }
+}
+
+/// Emits every conversation, then every message, in the mailboxes selected
+/// as one JSON record per line.
+pub fn emit(opts: &EmitOptions) -> Result<Completion> {
+    opts.output
+        .run("asimov-apple-mail-emitter", |stats| emit_mail(opts, stats))
+}
+
+fn emit_mail(opts: &EmitOptions, stats: &mut Stats) -> Result<Completion> {
+    if opts.mail_dir.is_some() && opts.backend != Backend::Emlx {
+        return Err(Error::Usage {
+            message: "--mail-dir takes --backend emlx".to_string(),
+        });
+    }
+    let started = Instant::now();
+    // Messages as they came are read only for their attachments or where
+    // they go, which is slow with many:
+    let sources = !opts.no_body
+        || opts.output.sink.attachments
+        || matches!(
+            opts.output.sink.output_format,
+            OutputFormat::Mbox | OutputFormat::EmlDir(_)
+        );
+    let (store, backend) = match opts.backend {
+        Backend::Mail => {
+            let output = osascript::run_javascript(
+                mail::APP,
+                &mail::script(&opts.filter(), !opts.no_body, sources),
+                signal::interrupted,
+            );
+            stats.finish_phase("extract", started);
+            let Some(stdout) = output? else {
+                eprintln!("Interrupted before any messages were emitted");
+                stats.warn("interrupted before any messages were emitted");
+                return Ok(Completion::Interrupted);
+            };
+            (mail::parse(&stdout)?, mail::BACKEND)
+        }
+        Backend::Emlx => {
+            let path = match &opts.mail_dir {
+                Some(path) => path.clone(),
+                None => mail::store::default_path()?,
+            };
+            let store = mail::store::read(&path, &opts.filter(), !opts.no_body, sources)?;
+            stats.finish_phase("extract", started);
+            (store, mail::store::BACKEND)
+        }
+    };
+    let attachments: Vec<Vec<Attachment>> = store
+        .messages
+        .iter()
+        .map(|message| match &message.source {
+            Some(source) if !opts.no_body => mail::mime::attachments(source),
+            _ => Vec::new(),
+        })
+        .collect();
+
+    // Written before the sink is opened, which may confine what is written:
+    let mut files = BTreeMap::new();
+    if let Some(dir) = &opts.attachments_dir {
+        let started = Instant::now();
+        files = save_attachments(dir, &attachments)?;
+        stats.finish_phase("export", started);
+    }
+
+    let mut output = opts.output.open(backend)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    let threads = mail::thread::threads(&store.messages);
+    for thread in &threads {
+        output.emit(stats, &thread.to_json(), "", "")?;
+    }
+    let mut found = store.messages.iter().zip(&attachments);
+    for (message, attachments) in found.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::mail_emitter",
+            message_id = %message.id,
+            "emitting message"
+        );
+
+        let mut record = message.to_json();
+        if let Some(source) = &message.source
+            && let Some(url) = output.attach(source.as_bytes(), mbox::MIME, "eml")?
+        {
+            record["archivedAt"] = Value::String(url);
+        }
+        let mut media = Vec::new();
+        for attachment in attachments {
+            let mut url = files.get(&attachment.sha256()).cloned();
+            if url.is_none() && opts.output.sink.attachments {
+                url = output.attach(&attachment.data, &attachment.mime, attachment.extension())?;
+            }
+            media.push(attachment.to_json(url.as_deref()));
+        }
+        if !media.is_empty() {
+            record["messageAttachment"] = Value::Array(media);
+        }
+        output.emit(
+            stats,
+            &record,
+            message.account.as_deref().unwrap_or_default(),
+            &message.mailbox,
+        )?;
+    }
+    stats.skipped(found.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count() - threads.len();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} messages");
+        stats.warn(format!("interrupted after emitting {count} messages"));
+    }
+    Ok(completion)
+}
+
+/// Emits every rule, in the order Mail applies them, then every smart
+/// mailbox, as one JSON record per line.
+pub fn rules(opts: &RulesOptions) -> Result<Completion> {
+    opts.output
+        .run("asimov-apple-mail-rules", |stats| emit_rules(opts, stats))
+}
+
+fn emit_rules(opts: &RulesOptions, stats: &mut Stats) -> Result<Completion> {
+    let started = Instant::now();
+    let output = osascript::run_javascript(mail::APP, &mail::rules::script(), signal::interrupted);
+    let Some(stdout) = output? else {
+        eprintln!("Interrupted before any rules were emitted");
+        stats.warn("interrupted before any rules were emitted");
+        return Ok(Completion::Interrupted);
+    };
+    let rules = mail::rules::parse(&stdout)?;
+
+    // Without Full Disk Access, smart mailboxes can't be read, and rules
+    // come out alone rather than not at all:
+    let path = match &opts.mail_dir {
+        Some(path) => path.clone(),
+        None => mail::store::default_path()?,
+    };
+    let smart_mailboxes = match mail::rules::smart_mailboxes(&path) {
+        Ok(mailboxes) => mailboxes,
+        Err(err) => {
+            eprintln!(
+                "Warning: no smart mailboxes, as reading {} failed: {err}",
+                path.display()
+            );
+            stats.warn(format!("no smart mailboxes: {err}"));
+            Vec::new()
+        }
+    };
+    stats.finish_phase("extract", started);
+
+    let mut output = opts.output.open(mail::BACKEND)?;
+    let started = Instant::now();
+    for rule in &rules {
+        output.emit(stats, &rule.to_json(), "", "")?;
+    }
+    for mailbox in &smart_mailboxes {
+        output.emit(stats, &mailbox.to_json(), "", "")?;
+    }
+    stats.finish_phase("emit", started);
+    output.finish(stats)?;
+    Ok(Completion::Finished)
+}
+
+/// Writes each attachment into `dir`, named by its SHA-256, unless
+/// already there, returning the `file:` URLs of the files by SHA-256.
+fn save_attachments(
+    dir: &Path,
+    attachments: &[Vec<Attachment>],
+) -> Result<BTreeMap<String, String>> {
+    let io = |e| Error::Io {
+        context: "writing mail attachments",
+        source: e,
+    };
+    fs::create_dir_all(dir).map_err(io)?;
+    let dir = fs::canonicalize(dir).map_err(io)?;
+    let mut files = BTreeMap::new();
+    for attachment in attachments.iter().flatten() {
+        let sha256 = attachment.sha256();
+        if files.contains_key(&sha256) {
+            continue;
+        }
+        let name = format!("{sha256}.{}", attachment.extension());
+        let path = dir.join(&name);
+        if !path.exists() {
+            // Given its name only once complete, so that an interrupted
+            // run leaves no partial file behind:
+            let partial = dir.join(format!(".{name}.part"));
+            fs::write(&partial, &attachment.data).map_err(io)?;
+            fs::rename(&partial, &path).map_err(io)?;
+        }
+        let url = url::Url::from_file_path(&path)
+            .map(String::from)
+            .unwrap_or_else(|()| path.display().to_string());
+        files.insert(sha256, url);
+    }
+    Ok(files)
+}
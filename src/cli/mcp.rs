@@ -0,0 +1,347 @@
+// This is free and unencumbered software released into the public domain.
+
+//! A Model Context Protocol server exposing this module's extractors as
+//! tools, speaking newline-delimited JSON-RPC 2.0 over stdio.
+
+use crate::{
+    Error, Result, calendar, notes, osascript, reminders, signal, signal::Completion,
+    timezone::LocalTime,
+};
+use serde_json::{Value, json};
+use std::{
+    format,
+    io::{self, BufRead, Write},
+    string::{String, ToString},
+    sync::mpsc,
+    thread,
+    time::Duration,
+    vec::Vec,
+};
+
+/// The MCP revision this server implements.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// How often to check for Ctrl-C while waiting for the next request.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Options for the MCP server.
+#[derive(Clone, Debug, clap::Args)]
+pub struct ServeMcpOptions {
+    /// Wrap width for plain-text conversion from HTML
+    #[arg(
+        short = 'w',
+        long = "wrap-width",
+        value_name = "WIDTH",
+        default_value = "80"
+    )]
+    pub wrap_width: usize,
+}
+
+/// Serves MCP requests from stdin until it is closed or Ctrl-C is pressed.
+pub fn serve(opts: &ServeMcpOptions) -> Result<Completion> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut stdout = io::stdout().lock();
+    loop {
+        if signal::interrupted() {
+            return Ok(Completion::Interrupted);
+        }
+        let line = match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => line.map_err(|e| Error::Io {
+                context: "reading MCP request from stdin",
+                source: e,
+            })?,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(Completion::Finished),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => handle_request(opts, &request)?,
+            Err(e) => Some(error_response(Value::Null, -32700, &e.to_string())),
+        };
+
+        if let Some(response) = response {
+            serde_json::to_writer(&mut stdout, &response)?;
+            stdout.write_all(b"\n")?;
+            stdout.flush().map_err(|e| Error::Io {
+                context: "flushing stdout",
+                source: e,
+            })?;
+        }
+    }
+}
+
+/// Handles one JSON-RPC message, returning the response to send back, if
+/// any. Notifications get no response.
+fn handle_request(opts: &ServeMcpOptions, request: &Value) -> Result<Option<Value>> {
+    let Some(id) = request.get("id").cloned() else {
+        return Ok(None);
+    };
+    let method = request.get("method").and_then(Value::as_str).unwrap_or("");
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+    #[cfg(feature = "tracing")]
+    asimov_module::tracing::debug!(
+        target: "asimov_apple_module::mcp",
+        %method,
+        "handling MCP request"
+    );
+
+    let result = match method {
+        "initialize" => json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": { "tools": {} },
+            "serverInfo": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        }),
+        "ping" => json!({}),
+        "tools/list" => json!({ "tools": tools() }),
+        "tools/call" => {
+            let name = params.get("name").and_then(Value::as_str).unwrap_or("");
+            let args = params.get("arguments").cloned().unwrap_or(json!({}));
+            match call_tool(opts, name, &args) {
+                Ok(output) => tool_result(&output, false),
+                // A Ctrl-C during a tool call stops the server, too:
+                Err(ToolError::Interrupted) => return Ok(None),
+                Err(ToolError::Failed(message)) => tool_result(&Value::String(message), true),
+            }
+        }
+        _ => {
+            return Ok(Some(error_response(
+                id,
+                -32601,
+                &format!("method not found: {method}"),
+            )));
+        }
+    };
+
+    Ok(Some(
+        json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+    ))
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+fn tool_result(output: &Value, is_error: bool) -> Value {
+    let text = match output {
+        Value::String(text) => text.clone(),
+        output => output.to_string(),
+    };
+    json!({
+        "content": [{ "type": "text", "text": text }],
+        "isError": is_error,
+    })
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "search_notes",
+            "description": "Search Apple Notes for a case-insensitive substring of the title or text.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "Text to search for" },
+                    "limit": { "type": "integer", "description": "Maximum number of results", "default": 20 },
+                },
+                "required": ["query"],
+            },
+        },
+        {
+            "name": "get_note",
+            "description": "Get the full record of a single Apple Note by URN.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "urn": { "type": "string", "description": "The note's urn:apple:notes:note: URN" },
+                },
+                "required": ["urn"],
+            },
+        },
+        {
+            "name": "list_folders",
+            "description": "List every Apple Notes folder with the account it belongs to.",
+            "inputSchema": { "type": "object", "properties": {} },
+        },
+        {
+            "name": "create_note",
+            "description": "Create a new Apple Note and return its URN.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "title": { "type": "string" },
+                    "body": { "type": "string", "description": "Plain-text body" },
+                    "folder": { "type": "string" },
+                    "account": { "type": "string" },
+                },
+                "required": ["title"],
+            },
+        },
+        {
+            "name": "list_events",
+            "description": "List Apple Calendar events in a window, with each occurrence of a recurring event on its own.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "from": { "type": "string", "description": "Only events ending after this date, or date and time (default: 90 days ago)" },
+                    "to": { "type": "string", "description": "Only events starting before this date, or date and time (default: in 90 days)" },
+                    "calendar": { "type": "string", "description": "Only events in the calendar by this name" },
+                    "limit": { "type": "integer", "description": "Maximum number of results", "default": 100 },
+                },
+            },
+        },
+        {
+            "name": "list_reminders",
+            "description": "List Apple Reminders, optionally by list, due date, or completion.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "list": { "type": "string", "description": "Only reminders in the list by this name" },
+                    "due_after": { "type": "string", "description": "Only reminders due at or after this date, or date and time" },
+                    "due_before": { "type": "string", "description": "Only reminders due before this date, or date and time" },
+                    "completed": { "type": "boolean", "description": "Only completed reminders, or with false, incomplete ones" },
+                    "limit": { "type": "integer", "description": "Maximum number of results", "default": 100 },
+                },
+            },
+        },
+    ])
+}
+
+enum ToolError {
+    Interrupted,
+    Failed(String),
+}
+
+impl From<Error> for ToolError {
+    fn from(err: Error) -> Self {
+        ToolError::Failed(err.to_string())
+    }
+}
+
+fn call_tool(
+    opts: &ServeMcpOptions,
+    name: &str,
+    args: &Value,
+) -> core::result::Result<Value, ToolError> {
+    let arg = |key: &str| args.get(key).and_then(Value::as_str);
+    let run = |script: &str| {
+        osascript::run(notes::APP, script, signal::interrupted)?.ok_or(ToolError::Interrupted)
+    };
+    let run_javascript = |app: &'static str, script: &str| {
+        osascript::run_javascript(app, script, signal::interrupted)?.ok_or(ToolError::Interrupted)
+    };
+    let time = |key: &str| {
+        arg(key)
+            .map(|value| value.parse::<LocalTime>())
+            .transpose()
+            .map_err(|e| ToolError::Failed(format!("`{key}`: {e}")))
+    };
+    let limit =
+        |default: u64| args.get("limit").and_then(Value::as_u64).unwrap_or(default) as usize;
+
+    match name {
+        "search_notes" => {
+            let query = arg("query").ok_or_else(|| ToolError::Failed("missing `query`".into()))?;
+            let output = run(&notes::search_script(query, limit(20)))?;
+            let results = notes::parse(&output)
+                .map(|note| Ok(note?.to_json(opts.wrap_width)?))
+                .collect::<Result<Vec<_>, ToolError>>()?;
+            Ok(Value::Array(results))
+        }
+        "get_note" => {
+            let urn = arg("urn").ok_or_else(|| ToolError::Failed("missing `urn`".into()))?;
+            let id = notes::id_from_urn(urn)
+                .ok_or_else(|| ToolError::Failed(format!("not an Apple Notes URN: {urn}")))?;
+            let output = run(&notes::note_script(id))?;
+            match notes::parse(&output).next().transpose()? {
+                Some(note) => Ok(note.to_json(opts.wrap_width)?),
+                None => Err(ToolError::Failed(format!("note {urn} not found"))),
+            }
+        }
+        "list_folders" => {
+            let output = run(notes::folders_script())?;
+            let folders = notes::parse_folders(&output)
+                .map(|folder| folder.map(|folder| folder.to_json()))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Value::Array(folders))
+        }
+        "create_note" => {
+            let title = arg("title").ok_or_else(|| ToolError::Failed("missing `title`".into()))?;
+            let body = notes::text_to_html(title, arg("body").unwrap_or(""));
+            let output = run(&notes::create_script(
+                title,
+                &body,
+                arg("folder"),
+                arg("account"),
+            ))?;
+            let id = output.trim();
+            Ok(json!({ "@id": format!("{}{id}", notes::URN_PREFIX) }))
+        }
+        "list_events" => {
+            let filter = calendar::Filter {
+                from: time("from")?,
+                to: time("to")?,
+                calendars: arg("calendar").into_iter().map(String::from).collect(),
+                excluded_calendars: Vec::new(),
+                birthdays: true,
+                subscribed: true,
+                geocode: false,
+            };
+            if let (Some(from), Some(to)) = (&filter.from, &filter.to)
+                && from >= to
+            {
+                return Err(ToolError::Failed(format!(
+                    "`from` {from} is not before `to` {to}"
+                )));
+            }
+            let output = run_javascript(calendar::APP, &calendar::script(&filter))?;
+            let library = calendar::parse(&output)?;
+            let events = library
+                .events
+                .iter()
+                .take(limit(100))
+                .map(|event| event.to_json())
+                .collect();
+            Ok(Value::Array(events))
+        }
+        "list_reminders" => {
+            let filter = reminders::Filter {
+                due_after: time("due_after")?,
+                due_before: time("due_before")?,
+                completed: args.get("completed").and_then(Value::as_bool),
+                overdue: false,
+                lists: arg("list").into_iter().map(String::from).collect(),
+                keep_recurring: false,
+            };
+            let output = run_javascript(reminders::APP, &reminders::script(&filter))?;
+            let library = reminders::parse(&output)?;
+            let found = library
+                .reminders
+                .iter()
+                .filter(|reminder| filter.admits_due(reminder.due.as_deref()))
+                .take(limit(100))
+                .map(|reminder| reminder.to_json())
+                .collect();
+            Ok(Value::Array(found))
+        }
+        _ => Err(ToolError::Failed(format!("unknown tool: {name}"))),
+    }
+}
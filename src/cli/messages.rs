@@ -0,0 +1,351 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `messages` programs.
+
+use super::emitter::{EmitterOptions, Output};
+use crate::{
+    Error, Result, contacts,
+    export::sha256_hex,
+    messages::{self, Archive, Attachment},
+    osascript, signal,
+    signal::Completion,
+    stats::Stats,
+    timezone::LocalTime,
+};
+use std::{
+    collections::BTreeMap,
+    eprintln,
+    ffi::OsString,
+    format, fs, io,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    thread,
+    time::{Duration, Instant, SystemTime},
+    vec::Vec,
+};
+
+/// How often `--watch` checks whether the database changed.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Options for the Apple Messages emitter.
+#[derive(Clone, Debug, clap::Args)]
+pub struct EmitOptions {
+    /// The database to read [default: ~/Library/Messages/chat.db]
+    #[arg(long, value_name = "PATH")]
+    pub database: Option<PathBuf>,
+
+    /// Only emit messages sent at or after this date, or date and time
+    #[arg(long, value_name = "DATE")]
+    pub since: Option<LocalTime>,
+
+    /// Only emit messages sent before this date, or date and time
+    #[arg(long, value_name = "DATE")]
+    pub until: Option<LocalTime>,
+
+    /// Only emit the messages in this chat, by its identifier, as in
+    /// `chat123456789`, its GUID, or its name (repeatable)
+    #[arg(long = "chat", value_name = "ID|NAME")]
+    pub chats: Vec<String>,
+
+    /// Only emit the messages in chats with this phone number or email
+    /// address (repeatable)
+    #[arg(long = "with", value_name = "HANDLE")]
+    pub with: Vec<String>,
+
+    /// Copy the files attached to messages into this directory, named by
+    /// the SHA-256 of their content, and link them from their records
+    #[arg(long, value_name = "PATH")]
+    pub attachments_dir: Option<PathBuf>,
+
+    /// Don't look up the contacts that sent or received the messages, by
+    /// their handles, in Contacts
+    #[arg(long)]
+    pub no_contacts: bool,
+
+    /// Once everything is emitted, keep watching the database, emitting
+    /// new messages as they arrive, until Ctrl-C
+    #[arg(long, conflicts_with = "least_privilege")]
+    pub watch: bool,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+impl EmitOptions {
+    /// Returns which messages the options select.
+    fn filter(&self) -> messages::Filter {
+        messages::Filter {
+            since: self.since.clone(),
+            until: self.until.clone(),
+            chats: self.chats.clone(),
+            with: self.with.clone(),
+            after: None,
+        }
+    }
+}
+
+/// Emits every chat, then every message, oldest first, and the files
+/// attached to them, in the chats and times selected, as one JSON record
+/// per line.
+pub fn emit(opts: &EmitOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-messages-emitter", |stats| {
+        emit_messages(opts, stats)
+    })
+}
+
+fn emit_messages(opts: &EmitOptions, stats: &mut Stats) -> Result<Completion> {
+    let path = match &opts.database {
+        Some(path) => path.clone(),
+        None => messages::default_path()?,
+    };
+    let mut filter = opts.filter();
+    let started = Instant::now();
+    let mut pages = messages::read(&path, &filter)?;
+    stats.finish_phase("extract", started);
+
+    let mut contacts = Vec::new();
+    if !opts.no_contacts {
+        let started = Instant::now();
+        let output =
+            osascript::run_javascript(contacts::APP, &contacts::script(false), signal::interrupted);
+        stats.finish_phase("resolve", started);
+        // Messages are worth emitting even with only their handles:
+        match output.and_then(|stdout| stdout.as_deref().map(contacts::parse).transpose()) {
+            Ok(Some(library)) => contacts = library.contacts,
+            Ok(None) => {
+                eprintln!("Interrupted before any messages were emitted");
+                stats.warn("interrupted before any messages were emitted");
+                return Ok(Completion::Interrupted);
+            }
+            Err(err) => {
+                eprintln!("Warning: handles not resolved, as reading Contacts failed: {err}");
+                stats.warn(format!("handles not resolved: {err}"));
+            }
+        }
+    }
+
+    // Files are copied as their messages are read, after the sink is
+    // opened, which may confine what is written:
+    let attachments_dir = opts.attachments_dir.as_deref();
+    if let Some(dir) = attachments_dir {
+        fs::create_dir_all(dir).map_err(|e| Error::Io {
+            context: "copying message attachments",
+            source: e,
+        })?;
+    }
+    let mut output = opts.output.open_writing(
+        messages::BACKEND,
+        attachments_dir.map(|dir| ("attachments directory", dir)),
+    )?;
+    let mut count = 0;
+
+    let mut completion = emit_pages(opts, stats, &mut output, &mut pages, &contacts, &mut count)?;
+
+    if opts.watch && completion == Completion::Finished {
+        output.flush()?;
+        eprintln!("Watching {} for new messages", path.display());
+        // Messages only ever get added, so the last one read marks where
+        // the next read starts:
+        let mut stamp = stamp(&path);
+        while !signal::interrupted() {
+            thread::sleep(WATCH_INTERVAL);
+            let changed = self::stamp(&path);
+            if changed == stamp {
+                continue;
+            }
+            stamp = changed;
+
+            let started = Instant::now();
+            filter.after = Some(pages.last_row());
+            pages = match messages::read(&path, &filter) {
+                Ok(pages) => pages,
+                // As while Messages is writing to it, so try again later:
+                Err(err) => {
+                    eprintln!("Warning: failed to read {}: {err}", path.display());
+                    stamp = [None, None];
+                    continue;
+                }
+            };
+            stats.finish_phase("extract", started);
+            emit_pages(opts, stats, &mut output, &mut pages, &contacts, &mut count)?;
+            output.flush()?;
+        }
+        completion = Completion::Interrupted;
+    }
+
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} messages");
+        stats.warn(format!("interrupted after emitting {count} messages"));
+    }
+    Ok(completion)
+}
+
+/// Emits every page of `pages` as it is read, looking up its handles in
+/// `contacts` and copying its files first.
+fn emit_pages(
+    opts: &EmitOptions,
+    stats: &mut Stats,
+    output: &mut Output,
+    pages: &mut messages::Pages,
+    contacts: &[contacts::Contact],
+    count: &mut usize,
+) -> Result<Completion> {
+    loop {
+        let started = Instant::now();
+        let Some(archive) = pages.next() else {
+            return Ok(Completion::Finished);
+        };
+        let mut archive = archive?;
+        stats.finish_phase("extract", started);
+        archive.resolve(contacts);
+
+        let mut files = BTreeMap::new();
+        if let Some(dir) = &opts.attachments_dir {
+            let started = Instant::now();
+            files = save_attachments(dir, &archive.attachments)?;
+            stats.finish_phase("export", started);
+        }
+
+        let started = Instant::now();
+        let completion = emit_archive(opts, stats, output, &archive, &files, count)?;
+        stats.finish_phase("emit", started);
+        if completion == Completion::Interrupted {
+            return Ok(completion);
+        }
+    }
+}
+
+/// Emits the chats in `archive`, then its messages, each after the files
+/// attached to it, counting the messages emitted in `count`.
+fn emit_archive(
+    opts: &EmitOptions,
+    stats: &mut Stats,
+    output: &mut Output,
+    archive: &Archive,
+    files: &BTreeMap<String, (String, String)>,
+    count: &mut usize,
+) -> Result<Completion> {
+    let attachments: BTreeMap<&str, &Attachment> = archive
+        .attachments
+        .iter()
+        .map(|attachment| (attachment.guid.as_str(), attachment))
+        .collect();
+    for chat in &archive.chats {
+        output.emit(
+            stats,
+            &chat.to_json(),
+            chat.service.as_deref().unwrap_or_default(),
+            chat.identifier.as_deref().unwrap_or_default(),
+        )?;
+    }
+    let mut completion = Completion::Finished;
+    let mut found = archive.messages.iter();
+    for message in found.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::messages_emitter",
+            guid = %message.guid,
+            "emitting message"
+        );
+
+        for guid in &message.attachments {
+            let Some(attachment) = attachments.get(guid.as_str()) else {
+                continue;
+            };
+            let (mut sha256, mut url) = match files.get(guid) {
+                Some((sha256, url)) => (Some(sha256.clone()), Some(url.clone())),
+                None => (None, None),
+            };
+            if url.is_none()
+                && opts.output.sink.attachments
+                && let Some(data) = attachment
+                    .path
+                    .as_deref()
+                    .and_then(|path| fs::read(path).ok())
+            {
+                let mime = attachment
+                    .mime
+                    .as_deref()
+                    .unwrap_or("application/octet-stream");
+                sha256 = Some(sha256_hex(&data));
+                url = output.attach(&data, mime, attachment.extension())?;
+            }
+            output.emit(
+                stats,
+                &attachment.to_json(sha256.as_deref(), url.as_deref()),
+                message.service.as_deref().unwrap_or_default(),
+                message.chat.as_deref().unwrap_or_default(),
+            )?;
+        }
+        output.emit(
+            stats,
+            &message.to_json(),
+            message.service.as_deref().unwrap_or_default(),
+            message.chat.as_deref().unwrap_or_default(),
+        )?;
+        *count += 1;
+    }
+    stats.skipped(found.count() as u64);
+    Ok(completion)
+}
+
+/// Returns when the database, and its write-ahead log, where Messages
+/// writes new messages first, were last modified, and their sizes.
+pub(super) fn stamp(path: &Path) -> [Option<(SystemTime, u64)>; 2] {
+    let mut wal = OsString::from(path);
+    wal.push("-wal");
+    [path, Path::new(&wal)].map(|path| {
+        let metadata = fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    })
+}
+
+/// Copies the files of `attachments` that are there into `dir`, as
+/// `<sha256>.<extension>`, returning the SHA-256 and the `file:` URL of
+/// each copied, by attachment GUID.
+fn save_attachments(
+    dir: &Path,
+    attachments: &[Attachment],
+) -> Result<BTreeMap<String, (String, String)>> {
+    let io = |e| Error::Io {
+        context: "copying message attachments",
+        source: e,
+    };
+    fs::create_dir_all(dir).map_err(io)?;
+    let dir = fs::canonicalize(dir).map_err(io)?;
+    let mut files = BTreeMap::new();
+    for attachment in attachments {
+        let Some(path) = &attachment.path else {
+            continue;
+        };
+        // Files not downloaded, or offloaded to iCloud, aren't there:
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(io(e)),
+        };
+        let sha256 = sha256_hex(&data);
+        let name = format!("{sha256}.{}", attachment.extension());
+        let copy = dir.join(&name);
+        if !copy.exists() {
+            // Given its name only once complete, so that an interrupted
+            // run leaves no partial file behind:
+            let partial = dir.join(format!(".{name}.part"));
+            fs::write(&partial, &data).map_err(io)?;
+            fs::rename(&partial, &copy).map_err(io)?;
+        }
+        let url = url::Url::from_file_path(&copy)
+            .map(String::from)
+            .unwrap_or_else(|()| copy.display().to_string());
+        files.insert(attachment.guid.clone(), (sha256, url));
+    }
+    Ok(files)
+}
@@ -0,0 +1,68 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `music` programs.
+
+use super::emitter::EmitterOptions;
+use crate::{Result, music, osascript, signal, signal::Completion, stats::Stats};
+use std::{eprintln, format, time::Instant};
+
+/// Options for the Apple Music emitter.
+#[derive(Clone, Debug, clap::Args)]
+pub struct EmitOptions {
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Emits every track in the library, in Music's order, as one JSON record
+/// per line.
+pub fn emit(opts: &EmitOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-music-emitter", |stats| {
+        emit_tracks(opts, stats)
+    })
+}
+
+fn emit_tracks(opts: &EmitOptions, stats: &mut Stats) -> Result<Completion> {
+    let started = Instant::now();
+    let output = osascript::run_javascript(music::APP, music::script(), signal::interrupted);
+    stats.finish_phase("extract", started);
+    let Some(stdout) = output? else {
+        eprintln!("Interrupted before any tracks were emitted");
+        stats.warn("interrupted before any tracks were emitted");
+        return Ok(Completion::Interrupted);
+    };
+    let tracks = music::parse(&stdout)?;
+
+    let mut output = opts.output.open(music::BACKEND)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    let mut found = tracks.iter();
+    for track in found.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::music_emitter",
+            id = %track.id,
+            "emitting track"
+        );
+
+        let album = track.album.as_deref().unwrap_or_default();
+        output.emit(stats, &track.to_json(), "", album)?;
+    }
+    stats.skipped(found.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} tracks");
+        stats.warn(format!("interrupted after emitting {count} tracks"));
+    }
+    Ok(completion)
+}
@@ -0,0 +1,385 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `notes` programs: emitter, cataloger, and fetcher.
+
+use super::{emitter::EmitterOptions, stats::StatsOptions};
+use crate::{
+    Error, Result,
+    markup::Document,
+    notes, osascript, signal,
+    signal::Completion,
+    stats::Stats,
+    timezone::{LocalTime, TimeZone},
+};
+use serde_json::Value;
+use std::{
+    eprintln, format, fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    string::String,
+    time::Instant,
+    vec::Vec,
+};
+
+/// Options for the Apple Notes emitter.
+#[derive(Clone, Debug, clap::Args)]
+pub struct EmitOptions {
+    /// Wrap width for plain-text conversion from HTML
+    #[arg(
+        short = 'w',
+        long = "wrap-width",
+        value_name = "WIDTH",
+        default_value = "80"
+    )]
+    pub wrap_width: usize,
+
+    /// Only emit the notes modified at or after this date, or date and time
+    #[arg(long, value_name = "DATE", conflicts_with = "resume")]
+    pub since: Option<LocalTime>,
+
+    /// Pick up where the last run left off: only emit the notes modified at or after the latest `dateModified` in `--state-file`
+    #[arg(long, requires = "state_file")]
+    pub resume: bool,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for the Apple Notes cataloger.
+#[derive(Clone, Debug, clap::Args)]
+pub struct CatalogOptions {
+    /// Stop after emitting this many notes
+    #[arg(short = 'n', long = "limit", value_name = "COUNT")]
+    pub limit: Option<usize>,
+
+    #[clap(flatten)]
+    pub stats: StatsOptions,
+}
+
+/// Options for the Apple Notes fetcher.
+#[derive(Clone, Debug, clap::Args)]
+pub struct FetchOptions {
+    /// Output the note body as raw HTML instead of a JSON record
+    #[arg(long)]
+    pub raw: bool,
+
+    /// Wrap width for plain-text conversion from HTML
+    #[arg(
+        short = 'w',
+        long = "wrap-width",
+        value_name = "WIDTH",
+        default_value = "80"
+    )]
+    pub wrap_width: usize,
+
+    /// Fetch the notes whose URNs or ids are listed in this file, one per
+    /// line, or on stdin if `-`
+    #[arg(long, value_name = "PATH", conflicts_with = "urn")]
+    pub ids: Option<PathBuf>,
+
+    /// The note to fetch, as a `urn:apple:notes:note:` URN or Notes id, or an
+    /// `apple+notes://ACCOUNT[/FOLDER[/NOTE-ID]]` URL for one or more notes
+    #[arg(value_name = "URN|URL", required_unless_present = "ids")]
+    pub urn: Option<String>,
+}
+
+/// Emits every note as one full JSON record per line.
+pub fn emit(opts: &EmitOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-notes-emitter", |stats| {
+        emit_notes(opts, stats)
+    })
+}
+
+fn emit_notes(opts: &EmitOptions, stats: &mut Stats) -> Result<Completion> {
+    #[cfg(feature = "tracing")]
+    asimov_module::tracing::info!(
+        target: "asimov_apple_module::notes_emitter",
+        "starting apple notes emitter"
+    );
+
+    let started = Instant::now();
+    let output = osascript::run(notes::APP, &notes::script(true), signal::interrupted);
+    stats.finish_phase("extract", started);
+    let Some(stdout) = output? else {
+        eprintln!("Interrupted before any notes were emitted");
+        stats.warn("interrupted before any notes were emitted");
+        return Ok(Completion::Interrupted);
+    };
+
+    if stdout.trim().is_empty() {
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::info!(
+            target: "asimov_apple_module::notes_emitter",
+            "no notes returned from Apple Notes"
+        );
+        return Ok(Completion::Finished);
+    }
+
+    // Compared as instants, so that a change of offset, as at the end of
+    // daylight saving time, neither skips nor repeats notes:
+    let zone = TimeZone::local();
+    let since = match &opts.since {
+        Some(since) => zone.to_timestamp(since.as_str()),
+        None if opts.resume => opts.output.cursor()?,
+        None => None,
+    };
+    let mut notes = notes::parse(&stdout).collect::<Result<Vec<_>>>()?;
+    if let Some(since) = since {
+        notes.retain(|note| {
+            zone.to_timestamp(&note.modified)
+                .is_some_and(|modified| modified >= since)
+        });
+    }
+    // Oldest first with a state file, so that the latest `dateModified`
+    // it keeps is as far as an interrupted run got:
+    if opts.output.state_file.is_some() {
+        notes.sort_by_key(|note| zone.to_timestamp(&note.modified));
+    }
+
+    let mut output = opts.output.open(notes::BACKEND)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    let mut parsed = notes.into_iter();
+    for note in parsed.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::notes_emitter",
+            note_id = %note.id,
+            account = %note.account,
+            folder = %note.folder,
+            name = %note.name,
+            "emitting note"
+        );
+
+        let mut record = note.to_json(opts.wrap_width)?;
+        if opts.output.sink.attachments {
+            let mut media = Vec::new();
+            for image in Document::parse(&note.body_html).images {
+                if let Some(url) = output.attach(&image.data, &image.mime, image.extension())? {
+                    media.push(Value::String(url));
+                }
+            }
+            if !media.is_empty() {
+                record["associatedMedia"] = Value::Array(media);
+            }
+        }
+        output.emit(stats, &record, &note.account, &note.folder)?;
+    }
+    stats.skipped(parsed.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} notes");
+        stats.warn(format!("interrupted after emitting {count} notes"));
+        return Ok(completion);
+    }
+
+    #[cfg(feature = "tracing")]
+    asimov_module::tracing::info!(
+        target: "asimov_apple_module::notes_emitter",
+        notes = count,
+        "finished apple notes emitter"
+    );
+
+    Ok(completion)
+}
+
+/// Emits one lightweight catalog record per note.
+pub fn catalog(opts: &CatalogOptions) -> Result<Completion> {
+    let mut stats = Stats::new("asimov-apple-notes-cataloger");
+    let result = catalog_notes(opts, &mut stats);
+    opts.stats.report(&stats, &result);
+    result
+}
+
+fn catalog_notes(opts: &CatalogOptions, stats: &mut Stats) -> Result<Completion> {
+    let started = Instant::now();
+    let output = osascript::run(notes::APP, &notes::script(false), signal::interrupted);
+    stats.finish_phase("extract", started);
+    let Some(stdout) = output? else {
+        stats.warn("interrupted before any notes were emitted");
+        return Ok(Completion::Interrupted);
+    };
+
+    let mut writer = BufWriter::new(io::stdout().lock());
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    let mut parsed = notes::parse(&stdout);
+    for note in parsed.by_ref().take(opts.limit.unwrap_or(usize::MAX)) {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        let note = note?;
+        serde_json::to_writer(&mut writer, &note.to_catalog_json())?;
+        writer.write_all(b"\n").map_err(|e| Error::Io {
+            context: "writing newline to stdout",
+            source: e,
+        })?;
+        stats.emitted(&note.account, &note.folder);
+    }
+
+    writer.flush().map_err(|e| Error::Io {
+        context: "flushing stdout",
+        source: e,
+    })?;
+    stats.skipped(parsed.count() as u64);
+    stats.finish_phase("emit", started);
+
+    Ok(completion)
+}
+
+/// Emits the single note identified by the given URN, or every note an
+/// `apple+notes://` URL refers to.
+pub fn fetch(opts: &FetchOptions) -> Result<Completion> {
+    let urn = match (&opts.ids, &opts.urn) {
+        (Some(path), _) => return fetch_ids(opts, path),
+        (None, Some(urn)) => urn,
+        (None, None) => {
+            return Err(Error::Usage {
+                message: "no note given to fetch".into(),
+            });
+        }
+    };
+    let locator = notes::Locator::parse(urn);
+    let id = match &locator {
+        Some(locator) => locator.full_id(),
+        None => Some(notes::id_from_urn(urn).ok_or_else(|| Error::Usage {
+            message: format!("not an Apple Notes URN or URL: {urn}"),
+        })?),
+    };
+
+    // Without a full id to look up, list everything and filter:
+    let script = match id {
+        Some(id) => notes::note_script(id),
+        None => notes::script(true),
+    };
+    let Some(stdout) = osascript::run(notes::APP, &script, signal::interrupted)? else {
+        return Ok(Completion::Interrupted);
+    };
+
+    let found: Vec<notes::Note> = notes::parse(&stdout)
+        .filter(|note| match (note, &locator) {
+            (Ok(note), Some(locator)) => locator.matches(note),
+            _ => true,
+        })
+        .collect::<Result<_>>()?;
+    if found.is_empty() {
+        return Err(Error::NotFound {
+            what: match locator {
+                Some(notes::Locator::Note { .. }) | None => format!("note {urn}"),
+                Some(_) => format!("notes at {urn}"),
+            },
+        });
+    }
+
+    let mut stdout = io::stdout().lock();
+    for note in found {
+        if opts.raw {
+            stdout.write_all(note.body_html.as_bytes())?;
+        } else {
+            serde_json::to_writer(&mut stdout, &note.to_json(opts.wrap_width)?)?;
+        }
+        stdout.write_all(b"\n").map_err(|e| Error::Io {
+            context: "writing newline to stdout",
+            source: e,
+        })?;
+    }
+
+    Ok(Completion::Finished)
+}
+
+/// How many notes to look up per AppleScript run when fetching by id.
+const ID_BATCH_SIZE: usize = 200;
+
+/// Emits the notes listed in `path`, in order, with an error record in
+/// place of each one that doesn't exist (or isn't a note URN at all).
+fn fetch_ids(opts: &FetchOptions, path: &Path) -> Result<Completion> {
+    let input = if path == Path::new("-") {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    }
+    .map_err(|e| Error::Io {
+        context: "reading the list of note ids",
+        source: e,
+    })?;
+    let lines: Vec<&str> = input
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let mut writer = BufWriter::new(io::stdout().lock());
+    for batch in lines.chunks(ID_BATCH_SIZE) {
+        let ids: Vec<&str> = batch
+            .iter()
+            .filter_map(|line| notes::id_from_urn(line))
+            .collect();
+        let output = if ids.is_empty() {
+            String::new()
+        } else {
+            let script = notes::batch_script(&ids);
+            let Some(output) = osascript::run(notes::APP, &script, signal::interrupted)? else {
+                writer.flush()?;
+                return Ok(Completion::Interrupted);
+            };
+            output
+        };
+
+        let mut found = notes::parse_batch(&output);
+        for line in batch {
+            let record = match notes::id_from_urn(line) {
+                None => id_error(line, None, "not an Apple Notes URN"),
+                Some(_) => match found.next().transpose()? {
+                    Some(Ok(note)) if opts.raw => {
+                        writer.write_all(note.body_html.as_bytes())?;
+                        writer.write_all(b"\n")?;
+                        continue;
+                    }
+                    Some(Ok(note)) => note.to_json(opts.wrap_width)?,
+                    Some(Err(id)) => id_error(line, Some(&id), "note not found"),
+                    None => {
+                        return Err(Error::Parse {
+                            context: "reading notes by id",
+                            message: format!("no record returned for {line}"),
+                        });
+                    }
+                },
+            };
+            serde_json::to_writer(&mut writer, &record)?;
+            writer.write_all(b"\n").map_err(|e| Error::Io {
+                context: "writing newline to stdout",
+                source: e,
+            })?;
+        }
+    }
+
+    writer.flush().map_err(|e| Error::Io {
+        context: "flushing stdout",
+        source: e,
+    })?;
+    Ok(Completion::Finished)
+}
+
+/// Returns the record emitted in place of a note that couldn't be fetched.
+fn id_error(input: &str, id: Option<&str>, message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "@id": id.map(|id| format!("{}{id}", notes::URN_PREFIX)),
+        "input": input,
+        "error": message,
+    })
+}
@@ -0,0 +1,368 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `photos` programs.
+
+use super::emitter::EmitterOptions;
+use crate::{
+    Error, Result, osascript,
+    photos::{self, Asset, Backend, Filter, Media, Quality},
+    signal,
+    signal::Completion,
+    stats::Stats,
+};
+use std::{
+    collections::BTreeMap,
+    eprint, eprintln, format, fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    time::Instant,
+    vec::Vec,
+};
+
+/// Options for the Apple Photos emitter.
+#[derive(Clone, Debug, clap::Args)]
+pub struct EmitOptions {
+    /// Only emit this album and the photos in it (repeatable)
+    #[arg(long = "album", value_name = "NAME")]
+    pub albums: Vec<String>,
+
+    /// Only emit this person and the photos of them (repeatable)
+    #[arg(long = "person", value_name = "NAME")]
+    pub people: Vec<String>,
+
+    /// Only emit favorites
+    #[arg(long)]
+    pub favorites: bool,
+
+    /// Only emit hidden photos and videos, which are otherwise left out
+    #[arg(long)]
+    pub hidden: bool,
+
+    /// Only emit screenshots
+    #[arg(long)]
+    pub screenshots: bool,
+
+    /// Only emit photos taken in bursts
+    #[arg(long)]
+    pub bursts: bool,
+
+    /// Only emit photos, videos, or Live Photos
+    #[arg(long, value_name = "TYPE")]
+    pub media_type: Option<Media>,
+
+    /// Read the library through PhotoKit, or straight from its database,
+    /// which is much faster for large libraries but takes Full Disk Access
+    #[arg(long, value_name = "BACKEND", default_value = "photokit")]
+    pub backend: Backend,
+
+    /// The library to read with --backend sqlite [default: ~/Pictures/Photos Library.photoslibrary]
+    #[arg(long, value_name = "PATH")]
+    pub library: Option<PathBuf>,
+
+    /// Leave out where each photo and video was taken
+    #[arg(long)]
+    pub no_location: bool,
+
+    /// Write the file of each photo and video emitted to this directory,
+    /// and link it from its record
+    #[arg(long, value_name = "PATH")]
+    pub export_dir: Option<PathBuf>,
+
+    /// Which version to export: original, full (with edits), or thumbnail
+    #[arg(
+        long,
+        value_name = "QUALITY",
+        default_value = "original",
+        requires = "export_dir"
+    )]
+    pub quality: Quality,
+
+    /// Download the files kept only in iCloud to export them
+    #[arg(long, requires = "export_dir")]
+    pub download_missing: bool,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for tagging a photo.
+#[derive(Clone, Debug, clap::Args)]
+pub struct TagOptions {
+    /// The photo or video to tag, as a `urn:apple:photos:asset:` URN or PhotoKit id
+    #[arg(value_name = "URN")]
+    pub urn: String,
+
+    /// The keywords to add
+    #[arg(value_name = "KEYWORD")]
+    pub keywords: Vec<String>,
+
+    /// Replace the keywords it has, rather than adding to them
+    #[arg(long)]
+    pub replace: bool,
+}
+
+/// Options for captioning a photo.
+#[derive(Clone, Debug, clap::Args)]
+pub struct CaptionOptions {
+    /// The photo or video to caption, as a `urn:apple:photos:asset:` URN or PhotoKit id
+    #[arg(value_name = "URN")]
+    pub urn: String,
+
+    /// The caption, replacing the one it has; empty to clear it
+    #[arg(value_name = "TEXT")]
+    pub caption: String,
+}
+
+impl EmitOptions {
+    /// Returns which assets the options select.
+    fn filter(&self) -> Filter {
+        Filter {
+            favorites: self.favorites,
+            hidden: self.hidden,
+            screenshots: self.screenshots,
+            bursts: self.bursts,
+            media: self.media_type,
+        }
+    }
+}
+
+/// Emits every album, person, and memory, then the metadata of every photo and
+/// video (in the albums and of the people selected), as one JSON record
+/// per line.
+pub fn emit(opts: &EmitOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-photos-emitter", |stats| {
+        emit_photos(opts, stats)
+    })
+}
+
+fn emit_photos(opts: &EmitOptions, stats: &mut Stats) -> Result<Completion> {
+    if opts.library.is_some() && opts.backend != Backend::Sqlite {
+        return Err(Error::Usage {
+            message: "--library takes --backend sqlite".to_string(),
+        });
+    }
+    let started = Instant::now();
+    let (mut library, backend) = match opts.backend {
+        Backend::PhotoKit => {
+            let output = osascript::run_javascript(
+                photos::APP,
+                &photos::script(&opts.filter(), !opts.no_location),
+                signal::interrupted,
+            );
+            stats.finish_phase("extract", started);
+            let Some(stdout) = output? else {
+                eprintln!("Interrupted before any photos were emitted");
+                stats.warn("interrupted before any photos were emitted");
+                return Ok(Completion::Interrupted);
+            };
+            (photos::parse(&stdout)?, photos::BACKEND)
+        }
+        Backend::Sqlite => {
+            let path = match &opts.library {
+                Some(path) => path.clone(),
+                None => photos::store::default_path()?,
+            };
+            let library = photos::store::read(&path, &opts.filter(), !opts.no_location)?;
+            stats.finish_phase("extract", started);
+            (library, photos::store::BACKEND)
+        }
+    };
+    if !opts.albums.is_empty() {
+        if let Some(name) = opts
+            .albums
+            .iter()
+            .find(|name| !library.albums.iter().any(|album| album.name == **name))
+        {
+            return Err(Error::NotFound {
+                what: format!("photo album {name}"),
+            });
+        }
+        library.retain_albums(&opts.albums);
+    }
+    if !opts.people.is_empty() {
+        if let Some(name) = opts
+            .people
+            .iter()
+            .find(|name| !library.people.iter().any(|person| person.name == **name))
+        {
+            return Err(Error::NotFound {
+                what: format!("person {name} in Photos"),
+            });
+        }
+        library.retain_people(&opts.people);
+    }
+
+    // Written before the sink is opened, which may confine what is written:
+    let (mut files, mut paired_videos) = (BTreeMap::new(), BTreeMap::new());
+    if let Some(dir) = &opts.export_dir {
+        let started = Instant::now();
+        let exported = export(opts, dir, &library.assets)?;
+        stats.finish_phase("export", started);
+        let Some(exported) = exported else {
+            eprintln!("Interrupted while exporting photos; export again to resume");
+            stats.warn("interrupted while exporting photos");
+            return Ok(Completion::Interrupted);
+        };
+        if !exported.missing.is_empty() {
+            let count = exported.missing.len();
+            eprintln!(
+                "{count} photos are only in iCloud; export with --download-missing to include them"
+            );
+            stats.warn(format!("{count} photos are only in iCloud"));
+        }
+        for (id, message) in &exported.failed {
+            eprintln!("Failed to export photo {id}: {message}");
+            stats.warn(format!("failed to export photo {id}: {message}"));
+        }
+        files = exported.files;
+        paired_videos = exported.paired_videos;
+    }
+
+    let mut output = opts.output.open(backend)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    for album in &library.albums {
+        output.emit(stats, &album.to_json(), "", &album.name)?;
+    }
+    for person in &library.people {
+        output.emit(stats, &person.to_json(), "", "")?;
+    }
+    for memory in &library.memories {
+        output.emit(stats, &memory.to_json(), "", "")?;
+    }
+    let mut found = library.assets.iter();
+    for asset in found.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::photos_emitter",
+            asset_id = %asset.id,
+            "emitting photo"
+        );
+
+        let mut record = asset.to_json();
+        if let Some(path) = files.get(&asset.id) {
+            record["contentUrl"] = file_url(Path::new(path)).into();
+        }
+        if let Some(path) = paired_videos.get(&asset.id)
+            && let Some(video) = record.pointer_mut("/associatedMedia/0")
+        {
+            video["contentUrl"] = file_url(Path::new(path)).into();
+        }
+        output.emit(stats, &record, "", "")?;
+    }
+    stats.skipped(found.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count =
+        output.count() - library.albums.len() - library.people.len() - library.memories.len();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} photos");
+        stats.warn(format!("interrupted after emitting {count} photos"));
+    }
+    Ok(completion)
+}
+
+/// Adds keywords to a photo or video, or replaces its keywords, printing
+/// its record as changed.
+pub fn tag(opts: &TagOptions) -> Result<Completion> {
+    let id = asset_id(&opts.urn)?;
+    if opts.keywords.is_empty() && !opts.replace {
+        return Err(Error::Usage {
+            message: "no keywords to add".to_string(),
+        });
+    }
+    write_back(
+        &opts.urn,
+        &photos::tag_script(id, &opts.keywords, opts.replace),
+    )
+}
+
+/// Sets or clears the caption of a photo or video, printing its record as
+/// changed.
+pub fn caption(opts: &CaptionOptions) -> Result<Completion> {
+    let id = asset_id(&opts.urn)?;
+    let caption = Some(opts.caption.trim()).filter(|caption| !caption.is_empty());
+    write_back(&opts.urn, &photos::caption_script(id, caption))
+}
+
+fn asset_id(urn: &str) -> Result<&str> {
+    photos::id_from_urn(urn).ok_or_else(|| Error::Usage {
+        message: format!("not an Apple Photos URN: {urn}"),
+    })
+}
+
+/// Runs a script that changes one asset, printing the record of the asset
+/// as changed, or failing if there was none.
+fn write_back(urn: &str, script: &str) -> Result<Completion> {
+    let Some(stdout) = osascript::run_javascript(photos::APP, script, signal::interrupted)? else {
+        return Ok(Completion::Interrupted);
+    };
+    let library = photos::parse(&stdout)?;
+    let Some(asset) = library.assets.first() else {
+        return Err(Error::NotFound {
+            what: format!("photo {urn}"),
+        });
+    };
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &asset.to_json())?;
+    stdout.write_all(b"\n").map_err(|e| Error::Io {
+        context: "writing newline to stdout",
+        source: e,
+    })?;
+    Ok(Completion::Finished)
+}
+
+/// How many files each export script writes, between reports of
+/// progress.
+const EXPORT_BATCH: usize = 100;
+
+/// Writes the files of `assets` into `dir`, returning what was written, or
+/// `None` if interrupted.
+fn export(opts: &EmitOptions, dir: &Path, assets: &[Asset]) -> Result<Option<photos::Exported>> {
+    let io = |e| Error::Io {
+        context: "exporting photos",
+        source: e,
+    };
+    fs::create_dir_all(dir).map_err(io)?;
+    let dir = fs::canonicalize(dir).map_err(io)?.display().to_string();
+    let progress = io::stderr().is_terminal();
+    let mut exported = photos::Exported::default();
+    let assets: Vec<&Asset> = assets.iter().collect();
+    for (i, batch) in assets.chunks(EXPORT_BATCH).enumerate() {
+        let script = photos::export_script(&dir, opts.quality, opts.download_missing, batch);
+        let Some(stdout) = osascript::run_javascript(photos::APP, &script, signal::interrupted)?
+        else {
+            return Ok(None);
+        };
+        let batch = photos::parse_exported(&stdout)?;
+        exported.files.extend(batch.files);
+        exported.paired_videos.extend(batch.paired_videos);
+        exported.missing.extend(batch.missing);
+        exported.failed.extend(batch.failed);
+        if progress {
+            let done = (i * EXPORT_BATCH + EXPORT_BATCH).min(assets.len());
+            eprint!("\rExported {done} of {} photos", assets.len());
+        }
+    }
+    if progress && !assets.is_empty() {
+        eprintln!();
+    }
+    Ok(Some(exported))
+}
+
+/// Returns the `file:` URL of an exported file.
+fn file_url(path: &Path) -> String {
+    url::Url::from_file_path(path)
+        .map(String::from)
+        .unwrap_or_else(|()| path.display().to_string())
+}
@@ -0,0 +1,287 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `reminders` programs.
+
+use super::emitter::EmitterOptions;
+use crate::{
+    Error, Result,
+    eventkit::{self, Backend},
+    osascript, reminders, signal,
+    signal::Completion,
+    stats::Stats,
+    timezone::LocalTime,
+};
+use std::{
+    eprintln, format,
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+    string::String,
+    time::Instant,
+    vec::Vec,
+};
+
+/// Options for the Apple Reminders emitter.
+#[derive(Clone, Debug, clap::Args)]
+pub struct EmitOptions {
+    /// Only emit reminders due at or after this date, or date and time
+    #[arg(long, value_name = "DATE")]
+    pub due_after: Option<LocalTime>,
+
+    /// Only emit reminders due before this date, or date and time
+    #[arg(long, value_name = "DATE")]
+    pub due_before: Option<LocalTime>,
+
+    /// Only emit completed reminders
+    #[arg(long, conflicts_with_all = ["incomplete", "overdue"])]
+    pub completed: bool,
+
+    /// Only emit incomplete reminders
+    #[arg(long)]
+    pub incomplete: bool,
+
+    /// Only emit incomplete reminders that are past due
+    #[arg(long)]
+    pub overdue: bool,
+
+    /// Only emit this list and its reminders (repeatable)
+    #[arg(long = "list", value_name = "NAME")]
+    pub lists: Vec<String>,
+
+    /// Also emit the occurrences of recurring reminders, up to --until
+    #[arg(long, requires = "until")]
+    pub expand_recurring: bool,
+
+    /// Expand recurring reminders into their occurrences before this date, or date and time
+    #[arg(long, value_name = "DATE", requires = "expand_recurring")]
+    pub until: Option<LocalTime>,
+
+    /// Where Reminders keeps its databases, read for subtasks, flags, and tags [default: ~/Library/Group Containers/group.com.apple.reminders/Container_v1/Stores]
+    #[arg(long, value_name = "DIR")]
+    pub store: Option<PathBuf>,
+
+    /// Read EventKit through JavaScript for Automation, or in process,
+    /// which is much faster but takes a build with the eventkit feature
+    #[arg(long, value_name = "BACKEND", default_value = eventkit::DEFAULT_BACKEND)]
+    pub backend: Backend,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for creating a reminder.
+#[derive(Clone, Debug, clap::Args)]
+pub struct CreateOptions {
+    /// The list to add the reminder to [default: the default list for new reminders]
+    #[arg(long, value_name = "NAME")]
+    pub list: Option<String>,
+
+    /// When the reminder is due: a date, or a date and time
+    #[arg(long, value_name = "DATE")]
+    pub due: Option<LocalTime>,
+
+    /// The reminder's title; its notes are read from stdin, unless that is a terminal
+    #[arg(value_name = "TITLE")]
+    pub title: String,
+}
+
+/// Options for completing a reminder.
+#[derive(Clone, Debug, clap::Args)]
+pub struct CompleteOptions {
+    /// The reminder to mark completed, as a `urn:apple:reminders:reminder:` URN or EventKit id
+    #[arg(value_name = "URN")]
+    pub urn: String,
+}
+
+impl EmitOptions {
+    /// Returns the filter these options select reminders by.
+    pub fn filter(&self) -> reminders::Filter {
+        reminders::Filter {
+            due_after: self.due_after.clone(),
+            due_before: self.due_before.clone(),
+            completed: match (self.completed, self.incomplete) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            overdue: self.overdue,
+            lists: self.lists.clone(),
+            keep_recurring: self.expand_recurring,
+        }
+    }
+}
+
+/// Emits every list, then every reminder (that passes the filters), as one
+/// JSON record per line.
+pub fn emit(opts: &EmitOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-reminders-emitter", |stats| {
+        emit_reminders(opts, stats)
+    })
+}
+
+fn emit_reminders(opts: &EmitOptions, stats: &mut Stats) -> Result<Completion> {
+    let filter = opts.filter();
+    let started = Instant::now();
+    let (output, backend) = match opts.backend {
+        Backend::Jxa => (
+            osascript::run_javascript(
+                reminders::APP,
+                &reminders::script(&filter),
+                signal::interrupted,
+            )
+            .and_then(|stdout| stdout.map(|stdout| reminders::parse(&stdout)).transpose()),
+            reminders::BACKEND,
+        ),
+        Backend::Native => (
+            eventkit::read_reminders(&filter, signal::interrupted)
+                .and_then(|found| found.map(|found| reminders::from_json(&found)).transpose()),
+            eventkit::NATIVE_BACKEND,
+        ),
+    };
+    stats.finish_phase("extract", started);
+    let Some(mut library) = output? else {
+        eprintln!("Interrupted before any reminders were emitted");
+        stats.warn("interrupted before any reminders were emitted");
+        return Ok(Completion::Interrupted);
+    };
+    if let Some(name) = opts
+        .lists
+        .iter()
+        .find(|name| !library.lists.iter().any(|list| list.name == **name))
+    {
+        return Err(Error::NotFound {
+            what: format!("reminders list {name}"),
+        });
+    }
+
+    // Without Full Disk Access, the stores can't be read, and reminders
+    // come out without their subtasks, flags, and tags rather than not at
+    // all:
+    let store = match &opts.store {
+        Some(dir) => dir.clone(),
+        None => reminders::store::default_dir()?,
+    };
+    match reminders::store::read(&store) {
+        Ok(details) => library.add_details(&details),
+        Err(err) => {
+            eprintln!(
+                "Warning: no subtasks, flags, or tags, as reading {} failed: {err}",
+                store.display()
+            );
+            stats.warn(format!("no subtasks, flags, or tags: {err}"));
+        }
+    }
+
+    let mut output = opts.output.open(backend)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    for list in &library.lists {
+        output.emit(stats, &list.to_json(), &list.account, &list.name)?;
+    }
+    let mut found = library.reminders.into_iter();
+    for reminder in found.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::reminders_emitter",
+            reminder_id = %reminder.id,
+            list = %reminder.list,
+            "emitting reminder"
+        );
+
+        if filter.admits_due(reminder.due.as_deref()) {
+            output.emit(
+                stats,
+                &reminder.to_json(),
+                &reminder.account,
+                &reminder.list,
+            )?;
+        }
+        // Completed reminders no longer recur; their next occurrence is
+        // another reminder:
+        if let (Some(until), Some(rule), Some(due), false) = (
+            &opts.until,
+            &reminder.recurrence,
+            &reminder.due,
+            reminder.completed,
+        ) {
+            for due in rule.occurrences(due, until) {
+                if filter.admits_due(Some(&due)) {
+                    output.emit(
+                        stats,
+                        &reminder.occurrence_to_json(&due),
+                        &reminder.account,
+                        &reminder.list,
+                    )?;
+                }
+            }
+        }
+    }
+    stats.skipped(found.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count() - library.lists.len();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} reminders");
+        stats.warn(format!("interrupted after emitting {count} reminders"));
+    }
+    Ok(completion)
+}
+
+/// Creates a reminder and prints its record.
+pub fn create(opts: &CreateOptions) -> Result<Completion> {
+    let stdin = io::stdin();
+    let notes = match stdin.is_terminal() {
+        true => String::new(),
+        false => io::read_to_string(stdin).map_err(|e| Error::Io {
+            context: "reading the reminder's notes from stdin",
+            source: e,
+        })?,
+    };
+    let notes = Some(notes.trim_end()).filter(|notes| !notes.is_empty());
+    let script =
+        reminders::create_script(&opts.title, opts.list.as_deref(), opts.due.as_ref(), notes);
+    write_back(&script, || Error::NotFound {
+        what: match &opts.list {
+            Some(name) => format!("reminders list {name}"),
+            None => "default reminders list".into(),
+        },
+    })
+}
+
+/// Marks a reminder completed and prints its record.
+pub fn complete(opts: &CompleteOptions) -> Result<Completion> {
+    let id = reminders::id_from_urn(&opts.urn).ok_or_else(|| Error::Usage {
+        message: format!("not an Apple Reminders URN: {}", opts.urn),
+    })?;
+    write_back(&reminders::complete_script(id), || Error::NotFound {
+        what: format!("reminder {}", opts.urn),
+    })
+}
+
+/// Runs a script that changes one reminder, printing the record of the
+/// reminder as changed, or failing with `missing()` if there was none.
+fn write_back(script: &str, missing: impl FnOnce() -> Error) -> Result<Completion> {
+    let Some(stdout) = osascript::run_javascript(reminders::APP, script, signal::interrupted)?
+    else {
+        return Ok(Completion::Interrupted);
+    };
+    let library = reminders::parse(&stdout)?;
+    let Some(reminder) = library.reminders.first() else {
+        return Err(missing());
+    };
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &reminder.to_json())?;
+    stdout.write_all(b"\n").map_err(|e| Error::Io {
+        context: "writing newline to stdout",
+        source: e,
+    })?;
+    Ok(Completion::Finished)
+}
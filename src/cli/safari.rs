@@ -0,0 +1,522 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `safari` programs.
+
+use super::emitter::EmitterOptions;
+use crate::{
+    Result, osascript, safari, signal, signal::Completion, stats::Stats, timezone::LocalTime,
+};
+use std::{
+    eprintln, format,
+    path::{Path, PathBuf},
+    string::String,
+    time::Instant,
+    vec::Vec,
+};
+
+/// What Safari keeps that can be emitted.
+#[derive(Clone, Debug, clap::Subcommand)]
+pub enum SafariCommand {
+    /// Emit every bookmark, and the folders they are in, as JSONL
+    Bookmarks(BookmarksOptions),
+
+    /// Emit every page in the history, with when it was visited, as JSONL
+    History(HistoryOptions),
+
+    /// Emit every page in the Reading List as JSONL
+    ReadingList(ReadingListOptions),
+
+    /// Emit every tab open in Safari, window by window, as JSONL
+    OpenTabs(OpenTabsOptions),
+
+    /// Emit every download in the Downloads list as JSONL
+    Downloads(DownloadsOptions),
+
+    /// Emit every tab open on the user's other devices as JSONL
+    CloudTabs(CloudTabsOptions),
+
+    /// Emit the pages visited most, ranked, as JSONL
+    TopSites(TopSitesOptions),
+}
+
+/// Options for emitting Safari's bookmarks.
+#[derive(Clone, Debug, clap::Args)]
+pub struct BookmarksOptions {
+    /// The bookmarks to read [default: ~/Library/Safari/Bookmarks.plist]
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for emitting Safari's history.
+#[derive(Clone, Debug, clap::Args)]
+pub struct HistoryOptions {
+    /// The database to read [default: ~/Library/Safari/History.db]
+    #[arg(long, value_name = "PATH")]
+    pub database: Option<PathBuf>,
+
+    /// Only emit the pages visited at or after this date, or date and
+    /// time, with only those visits
+    #[arg(long, value_name = "DATE")]
+    pub since: Option<LocalTime>,
+
+    /// Only emit the pages on this domain, or on its subdomains
+    /// (repeatable)
+    #[arg(long = "domain", value_name = "DOMAIN")]
+    pub domains: Vec<String>,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for emitting Safari's Reading List.
+#[derive(Clone, Debug, clap::Args)]
+pub struct ReadingListOptions {
+    /// The bookmarks to read [default: ~/Library/Safari/Bookmarks.plist]
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+
+    /// Include the text of the pages Safari saved for reading offline
+    #[arg(long)]
+    pub offline_text: bool,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for emitting the tabs open in Safari.
+#[derive(Clone, Debug, clap::Args)]
+pub struct OpenTabsOptions {
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for emitting Safari's downloads.
+#[derive(Clone, Debug, clap::Args)]
+pub struct DownloadsOptions {
+    /// The downloads to read [default: ~/Library/Safari/Downloads.plist]
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for emitting the tabs open on the user's other devices.
+#[derive(Clone, Debug, clap::Args)]
+pub struct CloudTabsOptions {
+    /// The database to read [default: Safari's CloudTabs.db]
+    #[arg(long, value_name = "PATH")]
+    pub database: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Options for emitting the pages visited most in Safari.
+#[derive(Clone, Debug, clap::Args)]
+pub struct TopSitesOptions {
+    /// The database to read [default: ~/Library/Safari/History.db]
+    #[arg(long, value_name = "PATH")]
+    pub database: Option<PathBuf>,
+
+    /// How many pages to emit
+    #[arg(long, value_name = "COUNT", default_value_t = 24)]
+    pub limit: usize,
+
+    #[clap(flatten)]
+    pub output: EmitterOptions,
+}
+
+/// Runs the Safari emitter in the mode `command` selects.
+pub fn run(command: &SafariCommand) -> Result<Completion> {
+    match command {
+        SafariCommand::Bookmarks(opts) => bookmarks(opts),
+        SafariCommand::History(opts) => history(opts),
+        SafariCommand::ReadingList(opts) => reading_list(opts),
+        SafariCommand::OpenTabs(opts) => open_tabs(opts),
+        SafariCommand::Downloads(opts) => downloads(opts),
+        SafariCommand::CloudTabs(opts) => cloud_tabs(opts),
+        SafariCommand::TopSites(opts) => top_sites(opts),
+    }
+}
+
+/// Emits every bookmark folder, then every bookmark, as one JSON record
+/// per line, each in the order Safari shows them.
+pub fn bookmarks(opts: &BookmarksOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-safari-emitter", |stats| {
+        emit_bookmarks(opts, stats)
+    })
+}
+
+fn emit_bookmarks(opts: &BookmarksOptions, stats: &mut Stats) -> Result<Completion> {
+    let started = Instant::now();
+    let path = match &opts.file {
+        Some(path) => path.clone(),
+        None => safari::default_dir()?.join("Bookmarks.plist"),
+    };
+    let found = safari::bookmarks::read(&path)?;
+    stats.finish_phase("extract", started);
+
+    let mut output = opts.output.open(safari::BOOKMARKS_BACKEND)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    for folder in &found.folders {
+        output.emit(stats, &folder.to_json(), "", &folder.path)?;
+    }
+    let mut bookmarks = found.bookmarks.iter();
+    for bookmark in bookmarks.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::safari_emitter",
+            id = %bookmark.id,
+            "emitting bookmark"
+        );
+
+        output.emit(stats, &bookmark.to_json(), "", &bookmark.path)?;
+    }
+    stats.skipped(bookmarks.count() as u64);
+    stats.finish_phase("emit", started);
+
+    // Folders aren't bookmarks:
+    let count = output.count() - found.folders.len();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} bookmarks");
+        stats.warn(format!("interrupted after emitting {count} bookmarks"));
+    }
+    Ok(completion)
+}
+
+/// Emits every page in the history that was visited in the time and on the
+/// domains selected, by when it was first visited, oldest first, as one
+/// JSON record per line.
+pub fn history(opts: &HistoryOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-safari-emitter", |stats| {
+        emit_history(opts, stats)
+    })
+}
+
+fn emit_history(opts: &HistoryOptions, stats: &mut Stats) -> Result<Completion> {
+    let started = Instant::now();
+    let path = match &opts.database {
+        Some(path) => path.clone(),
+        None => safari::default_dir()?.join("History.db"),
+    };
+    let filter = safari::history::Filter {
+        since: opts.since.clone(),
+        domains: opts.domains.clone(),
+    };
+    let pages = safari::history::read(&path, &filter)?;
+    stats.finish_phase("extract", started);
+
+    let mut output = opts.output.open(safari::HISTORY_BACKEND)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    let mut pages = pages.iter();
+    for page in pages.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::safari_emitter",
+            url = %page.url,
+            "emitting page"
+        );
+
+        output.emit(stats, &page.to_json(), "", "")?;
+    }
+    stats.skipped(pages.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} pages");
+        stats.warn(format!("interrupted after emitting {count} pages"));
+    }
+    Ok(completion)
+}
+
+/// Emits every page in the Reading List, in the order Safari shows it, as
+/// one JSON record per line, with the text Safari saved of it if asked.
+pub fn reading_list(opts: &ReadingListOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-safari-emitter", |stats| {
+        emit_reading_list(opts, stats)
+    })
+}
+
+fn emit_reading_list(opts: &ReadingListOptions, stats: &mut Stats) -> Result<Completion> {
+    let started = Instant::now();
+    let path = match &opts.file {
+        Some(path) => path.clone(),
+        None => safari::default_dir()?.join("Bookmarks.plist"),
+    };
+    let entries = safari::reading_list::read(&path)?;
+    stats.finish_phase("extract", started);
+
+    let mut output = opts.output.open(safari::READING_LIST_BACKEND)?;
+    let mut completion = Completion::Finished;
+
+    // The web archives are next to the bookmarks:
+    let archives = path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("ReadingListArchives");
+    let started = Instant::now();
+    let mut entries = entries.into_iter();
+    for mut entry in entries.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+        if opts.offline_text {
+            entry.text = safari::reading_list::offline_text(&archives, &entry.id)?;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::safari_emitter",
+            id = %entry.id,
+            "emitting Reading List page"
+        );
+
+        output.emit(stats, &entry.to_json(), "", "")?;
+    }
+    stats.skipped(entries.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} pages");
+        stats.warn(format!("interrupted after emitting {count} pages"));
+    }
+    Ok(completion)
+}
+
+/// Emits every tab open in Safari, the frontmost window's first, as one
+/// JSON record per line.
+pub fn open_tabs(opts: &OpenTabsOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-safari-emitter", |stats| {
+        emit_open_tabs(opts, stats)
+    })
+}
+
+fn emit_open_tabs(opts: &OpenTabsOptions, stats: &mut Stats) -> Result<Completion> {
+    let started = Instant::now();
+    let output =
+        osascript::run_javascript(safari::APP, safari::tabs::script(), signal::interrupted);
+    let Some(stdout) = output? else {
+        eprintln!("Interrupted before any tabs were emitted");
+        stats.warn("interrupted before any tabs were emitted");
+        return Ok(Completion::Interrupted);
+    };
+    let tabs = safari::tabs::parse(&stdout)?;
+    stats.finish_phase("extract", started);
+
+    let mut output = opts.output.open(safari::TABS_BACKEND)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    let mut tabs = tabs.into_iter();
+    for tab in tabs.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::safari_emitter",
+            url = ?tab.url,
+            "emitting tab"
+        );
+
+        output.emit(stats, &tab.to_json(), "", "")?;
+    }
+    stats.skipped(tabs.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} tabs");
+        stats.warn(format!("interrupted after emitting {count} tabs"));
+    }
+    Ok(completion)
+}
+
+/// Emits every download in the Downloads list, newest first, as one JSON
+/// record per line.
+pub fn downloads(opts: &DownloadsOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-safari-emitter", |stats| {
+        emit_downloads(opts, stats)
+    })
+}
+
+fn emit_downloads(opts: &DownloadsOptions, stats: &mut Stats) -> Result<Completion> {
+    let started = Instant::now();
+    let path = match &opts.file {
+        Some(path) => path.clone(),
+        None => safari::default_dir()?.join("Downloads.plist"),
+    };
+    let downloads = safari::downloads::read(&path)?;
+    stats.finish_phase("extract", started);
+
+    let mut output = opts.output.open(safari::DOWNLOADS_BACKEND)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    let mut downloads = downloads.into_iter();
+    for download in downloads.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::safari_emitter",
+            id = %download.id,
+            "emitting download"
+        );
+
+        output.emit(stats, &download.to_json(), "", "")?;
+    }
+    stats.skipped(downloads.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} downloads");
+        stats.warn(format!("interrupted after emitting {count} downloads"));
+    }
+    Ok(completion)
+}
+
+/// Emits every tab open on the user's other devices, as iCloud Tabs syncs
+/// them, device by device, as one JSON record per line.
+pub fn cloud_tabs(opts: &CloudTabsOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-safari-emitter", |stats| {
+        emit_cloud_tabs(opts, stats)
+    })
+}
+
+fn emit_cloud_tabs(opts: &CloudTabsOptions, stats: &mut Stats) -> Result<Completion> {
+    let started = Instant::now();
+    let path = match &opts.database {
+        Some(path) => path.clone(),
+        None => safari::cloud_tabs_path()?,
+    };
+    let tabs = safari::cloud_tabs::read(&path)?;
+    stats.finish_phase("extract", started);
+
+    let mut output = opts.output.open(safari::CLOUD_TABS_BACKEND)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    let mut tabs = tabs.into_iter();
+    for tab in tabs.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::safari_emitter",
+            id = %tab.id,
+            "emitting iCloud tab"
+        );
+
+        let device = tab.device.as_deref().unwrap_or(&tab.device_id);
+        output.emit(stats, &tab.to_json(), "", device)?;
+    }
+    stats.skipped(tabs.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} tabs");
+        stats.warn(format!("interrupted after emitting {count} tabs"));
+    }
+    Ok(completion)
+}
+
+/// Emits the pages visited most, as Safari ranks them, the first first, as
+/// one JSON record per line.
+pub fn top_sites(opts: &TopSitesOptions) -> Result<Completion> {
+    opts.output.run("asimov-apple-safari-emitter", |stats| {
+        emit_top_sites(opts, stats)
+    })
+}
+
+fn emit_top_sites(opts: &TopSitesOptions, stats: &mut Stats) -> Result<Completion> {
+    let started = Instant::now();
+    let path = match &opts.database {
+        Some(path) => path.clone(),
+        None => safari::default_dir()?.join("History.db"),
+    };
+    let sites = safari::history::read_top_sites(&path, opts.limit)?;
+    stats.finish_phase("extract", started);
+
+    let mut output = opts.output.open(safari::TOP_SITES_BACKEND)?;
+    let mut completion = Completion::Finished;
+
+    let started = Instant::now();
+    let mut sites = sites.into_iter();
+    for site in sites.by_ref() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            stats.skipped(1);
+            break;
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::safari_emitter",
+            url = %site.url,
+            "emitting top site"
+        );
+
+        output.emit(stats, &site.to_json(), "", "")?;
+    }
+    stats.skipped(sites.count() as u64);
+    stats.finish_phase("emit", started);
+
+    let count = output.count();
+    output.finish(stats)?;
+
+    if completion == Completion::Interrupted {
+        eprintln!("Interrupted after emitting {count} sites");
+        stats.warn(format!("interrupted after emitting {count} sites"));
+    }
+    Ok(completion)
+}
@@ -0,0 +1,40 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `schema` program, describing every record type this module emits.
+
+use crate::{Error, Result, schema, signal::Completion};
+use std::io::{self, Write};
+
+/// The kinds of schema the `schema` program can print.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SchemaFormat {
+    /// JSON Schema for the JSON records
+    JsonSchema,
+    /// SHACL shapes, in Turtle, for the RDF output
+    Shacl,
+}
+
+/// Options for the `schema` program.
+#[derive(Clone, Debug, clap::Args)]
+pub struct SchemaOptions {
+    /// Which kind of schema to print
+    #[arg(long, value_name = "FORMAT", value_enum, default_value = "json-schema")]
+    pub format: SchemaFormat,
+}
+
+/// Prints the requested schema to stdout.
+pub fn print(opts: &SchemaOptions) -> Result<Completion> {
+    let mut stdout = io::stdout().lock();
+    match opts.format {
+        SchemaFormat::JsonSchema => {
+            serde_json::to_writer_pretty(&mut stdout, &schema::json_schema())?;
+            stdout.write_all(b"\n")
+        }
+        SchemaFormat::Shacl => stdout.write_all(schema::shacl().as_bytes()),
+    }
+    .map_err(|e| Error::Io {
+        context: "writing the schema to stdout",
+        source: e,
+    })?;
+    Ok(Completion::Finished)
+}
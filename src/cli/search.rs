@@ -0,0 +1,116 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `index` and `search` programs: offline full-text search across the
+//! records the emitters write.
+
+use super::import::{open_input, parse_record};
+use crate::{
+    Error, Result, search,
+    search::IndexSink,
+    signal,
+    signal::Completion,
+    sink::{Sink, tombstone_id},
+};
+use std::{
+    eprintln,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    string::String,
+    vec::Vec,
+};
+
+/// Options for the `index` program.
+#[derive(Clone, Debug, clap::Args)]
+pub struct IndexOptions {
+    /// Read records from this file instead of stdin
+    #[arg(value_name = "FILE", default_value = "-")]
+    pub input: PathBuf,
+
+    /// The index to add to [default: ~/Library/Application Support/asimov-apple/index]
+    #[arg(long, value_name = "PATH")]
+    pub index: Option<PathBuf>,
+}
+
+/// Options for the `search` program.
+#[derive(Clone, Debug, clap::Args)]
+pub struct SearchOptions {
+    /// The words to search for; end one with `*` to match words it begins
+    #[arg(value_name = "QUERY", required = true, num_args = 1..)]
+    pub query: Vec<String>,
+
+    /// The index to search [default: ~/Library/Application Support/asimov-apple/index]
+    #[arg(long, value_name = "PATH")]
+    pub index: Option<PathBuf>,
+
+    /// The most results to print
+    #[arg(
+        short = 'n',
+        long = "limit",
+        value_name = "COUNT",
+        default_value = "20"
+    )]
+    pub limit: usize,
+}
+
+/// Adds every record read from the input to the index, replacing those
+/// with the same `@id`, and removes those that tombstones mark as deleted.
+pub fn index(opts: &IndexOptions) -> Result<Completion> {
+    let path = match &opts.index {
+        Some(path) => path.clone(),
+        None => search::default_path()?,
+    };
+    let input = open_input(&opts.input)?;
+    let mut sink = IndexSink::open(&path)?;
+    let (mut indexed, mut removed) = (0, 0);
+    let mut completion = Completion::Finished;
+
+    for (index, line) in input.lines().enumerate() {
+        if signal::interrupted() {
+            completion = Completion::Interrupted;
+            break;
+        }
+        let Some(record) = parse_record(index, line)? else {
+            continue;
+        };
+        match tombstone_id(&record) {
+            Some(id) => {
+                sink.delete(id)?;
+                removed += 1;
+            }
+            None => {
+                sink.write(&record)?;
+                indexed += 1;
+            }
+        }
+    }
+    sink.finish()?;
+
+    #[cfg(feature = "tracing")]
+    asimov_module::tracing::info!(
+        target: "asimov_apple_module::search",
+        indexed,
+        removed,
+        index = %path.display(),
+        "finished indexing records"
+    );
+    eprintln!("Indexed {indexed} records and removed {removed}");
+    Ok(completion)
+}
+
+/// Prints the records best matching the query as JSONL, best first.
+pub fn search(opts: &SearchOptions) -> Result<Completion> {
+    let path = match &opts.index {
+        Some(path) => path.clone(),
+        None => search::default_path()?,
+    };
+    let results = search::search(&path, &opts.query.join(" "), opts.limit)?;
+    let mut stdout = io::stdout().lock();
+    for result in results {
+        serde_json::to_writer(&mut stdout, &result)?;
+        stdout.write_all(b"\n").map_err(|e| Error::Io {
+            context: "writing search results to stdout",
+            source: e,
+        })?;
+    }
+    Ok(Completion::Finished)
+}
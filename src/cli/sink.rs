@@ -0,0 +1,151 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `--sink` option and the settings that go with it.
+
+use crate::{
+    Error, Result,
+    embed::{self, Embedder},
+    provenance::{Provenance, ProvenanceMode},
+    sink::{
+        EmbeddingSink, JsonlSink, NquadsSink, OutputFormat, ProvenanceSink, Sink, SinkSpec,
+        eml::EmlDirSink,
+        ics::IcsSink,
+        mbox::MboxSink,
+        s3::{S3Config, S3Sink},
+        vcard::VcardSink,
+        webhook::{BatchFormat, WebhookConfig, WebhookSink},
+    },
+};
+use std::{boxed::Box, format, io, path::PathBuf, string::String};
+
+/// The environment variable holding the bearer token for webhook sinks.
+pub const TOKEN_VAR: &str = "ASIMOV_APPLE_SINK_TOKEN";
+
+/// The environment variable holding the bearer token for `--embed-url`.
+pub const EMBED_TOKEN_VAR: &str = "ASIMOV_APPLE_EMBED_TOKEN";
+
+/// Options selecting and configuring where emitted records go.
+#[derive(Clone, Debug, clap::Args)]
+pub struct SinkOptions {
+    /// Where to write records: `stdout` (JSONL), `sqlite:PATH`, `oxigraph:PATH|URL`, an `https://` webhook URL, or an `s3://BUCKET/PREFIX` object store
+    #[arg(long, value_name = "SINK", default_value = "stdout")]
+    pub sink: SinkSpec,
+
+    /// How to encode records on stdout: `jsonl`, `nquads` with a named graph per account, `ics` (calendar events only), `vcard` (contacts only; `vcard3` for vCard 3.0), or `mbox` or `eml-dir:DIR` (mail only, as the messages came)
+    #[arg(long, value_name = "FORMAT", default_value = "jsonl")]
+    pub output_format: OutputFormat,
+
+    /// Upload notes' attachments, contacts' photos, and mail messages as they came to the `s3://` sink too, or store them in the `sqlite:` one, named by their SHA-256, and link them from each record
+    #[arg(long)]
+    pub attachments: bool,
+
+    /// How many records to send per webhook request
+    #[arg(long, value_name = "COUNT", default_value = "100")]
+    pub batch_size: usize,
+
+    /// How to encode webhook request bodies: `ndjson` or `json` (an array)
+    #[arg(long, value_name = "FORMAT", default_value = "ndjson")]
+    pub batch_format: BatchFormat,
+
+    /// The bearer token for webhook requests [default: $ASIMOV_APPLE_SINK_TOKEN]
+    #[arg(long, value_name = "TOKEN")]
+    pub sink_token: Option<String>,
+
+    /// How often to retry a failed webhook request or upload, with exponential backoff
+    #[arg(long, value_name = "COUNT", default_value = "3")]
+    pub retries: u32,
+
+    /// Append batches that couldn't be delivered to this file instead of failing
+    #[arg(long, value_name = "PATH")]
+    pub dead_letter: Option<PathBuf>,
+
+    /// Attach PROV-O provenance to every record: `inline`, or `activity` to emit it once as its own record
+    #[arg(long, value_name = "MODE")]
+    pub provenance: Option<ProvenanceMode>,
+
+    /// Attach an `embedding` to every record, computed by this shell command from the record's text on stdin
+    #[arg(long, value_name = "CMD", conflicts_with = "embed_url")]
+    pub embed_cmd: Option<String>,
+
+    /// Attach an `embedding` to every record, computed by this OpenAI-compatible `/embeddings` endpoint
+    #[arg(long, value_name = "URL")]
+    pub embed_url: Option<String>,
+
+    /// The model to request from `--embed-url`
+    #[arg(long, value_name = "MODEL", default_value = embed::DEFAULT_MODEL, requires = "embed_url")]
+    pub embed_model: String,
+
+    /// The bearer token for `--embed-url` [default: $ASIMOV_APPLE_EMBED_TOKEN]
+    #[arg(long, value_name = "TOKEN", requires = "embed_url")]
+    pub embed_token: Option<String>,
+}
+
+impl SinkOptions {
+    /// Opens the selected sink for records extracted through `backend`.
+    pub fn open(&self, backend: &'static str) -> Result<Box<dyn Sink>> {
+        self.open_spec(&self.sink, backend)
+    }
+
+    /// Opens `spec` with the settings given by these options.
+    pub fn open_spec(&self, spec: &SinkSpec, backend: &'static str) -> Result<Box<dyn Sink>> {
+        let mut sink = self.open_plain(spec)?;
+        if let Some(embedder) = self.embedder() {
+            sink = Box::new(EmbeddingSink::new(sink, embedder));
+        }
+        Ok(match self.provenance {
+            Some(mode) => Box::new(ProvenanceSink::new(sink, Provenance::new(mode, backend))?),
+            None => sink,
+        })
+    }
+
+    /// Returns where embeddings come from, if records are to have them.
+    pub fn embedder(&self) -> Option<Embedder> {
+        if let Some(command) = &self.embed_cmd {
+            return Some(Embedder::Command(command.clone()));
+        }
+        self.embed_url.as_ref().map(|url| Embedder::Http {
+            url: url.clone(),
+            model: self.embed_model.clone(),
+            token: self
+                .embed_token
+                .clone()
+                .or_else(|| std::env::var(EMBED_TOKEN_VAR).ok()),
+        })
+    }
+
+    fn open_plain(&self, spec: &SinkSpec) -> Result<Box<dyn Sink>> {
+        if self.attachments && !matches!(spec, SinkSpec::S3(_) | SinkSpec::Sqlite(_)) {
+            return Err(Error::Usage {
+                message: format!("--attachments needs an s3:// or sqlite: sink, not {spec}"),
+            });
+        }
+        match spec {
+            SinkSpec::Stdout => Ok(match &self.output_format {
+                OutputFormat::Jsonl => Box::new(JsonlSink::new(io::stdout().lock())),
+                OutputFormat::Nquads => Box::new(NquadsSink::new(io::stdout().lock())),
+                OutputFormat::Ics => Box::new(IcsSink::new(io::stdout().lock())),
+                OutputFormat::Vcard(version) => {
+                    Box::new(VcardSink::new(io::stdout().lock(), *version))
+                }
+                OutputFormat::Mbox => Box::new(MboxSink::new(io::stdout().lock())),
+                OutputFormat::EmlDir(dir) => Box::new(EmlDirSink::open(dir)?),
+            }),
+            SinkSpec::Webhook(url) => Ok(Box::new(WebhookSink::new(WebhookConfig {
+                batch_size: self.batch_size,
+                format: self.batch_format,
+                token: self
+                    .sink_token
+                    .clone()
+                    .or_else(|| std::env::var(TOKEN_VAR).ok()),
+                retries: self.retries,
+                dead_letter: self.dead_letter.clone(),
+                ..WebhookConfig::new(url.as_str())
+            }))),
+            SinkSpec::S3(location) => Ok(Box::new(S3Sink::new(S3Config {
+                retries: self.retries,
+                ..S3Config::from_env(location.clone())?
+            }))),
+            spec => spec.open(),
+        }
+    }
+}
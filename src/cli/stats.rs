@@ -0,0 +1,72 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `--stats` option: a machine-readable summary at the end of a run.
+
+use super::log;
+use crate::{Error, Result, signal::Completion, stats::Stats};
+use std::{
+    eprintln, format,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+    string::ToString,
+};
+
+/// The formats the run summary can be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum StatsFormat {
+    /// A single JSON object
+    Json,
+}
+
+/// Options for the end-of-run summary.
+#[derive(Clone, Debug, clap::Args)]
+pub struct StatsOptions {
+    /// Write a summary of the run (record counts, warnings, durations) at the end
+    #[arg(long, value_name = "FORMAT", value_enum)]
+    pub stats: Option<StatsFormat>,
+
+    /// Append the summary to this file instead of writing it to stderr
+    #[arg(long, value_name = "PATH", requires = "stats")]
+    pub stats_file: Option<PathBuf>,
+}
+
+impl StatsOptions {
+    /// Writes the summary of a run that ended with `result`, if requested.
+    /// Failing to write it is only worth a warning, not failing the run.
+    /// The summary also goes into the log file, if there is one.
+    pub fn report(&self, stats: &Stats, result: &Result<Completion>) {
+        let summary = summary(stats, result).to_string();
+        log::line(&format!("stats: {summary}"));
+        let Some(StatsFormat::Json) = self.stats else {
+            return;
+        };
+        if let Err(err) = self.write(&summary) {
+            eprintln!("Warning: failed to write run statistics: {err}");
+        }
+    }
+
+    fn write(&self, line: &str) -> Result<()> {
+        let result = match &self.stats_file {
+            Some(path) => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{line}")),
+            None => writeln!(io::stderr(), "{line}"),
+        };
+        result.map_err(|e| Error::Io {
+            context: "writing run statistics",
+            source: e,
+        })
+    }
+}
+
+/// Returns the summary object for a run that ended with `result`.
+pub fn summary(stats: &Stats, result: &Result<Completion>) -> serde_json::Value {
+    match result {
+        Ok(Completion::Finished) => stats.to_json("finished", None),
+        Ok(Completion::Interrupted) => stats.to_json("interrupted", None),
+        Err(err) => stats.to_json("failed", Some(&err.to_string())),
+    }
+}
@@ -0,0 +1,23 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `--throttle` option: pacing the Apple Events sent to target apps.
+
+use crate::throttle::{self, Rate};
+
+/// Options for rate-limiting extraction.
+#[derive(Clone, Debug, clap::Args)]
+pub struct ThrottleOptions {
+    /// Send at most this many batches of Apple Events per second (one per
+    /// note read, and one per `osascript` run), e.g. `20/sec`
+    #[arg(long, value_name = "N/SEC", global = true)]
+    pub throttle: Option<Rate>,
+}
+
+impl ThrottleOptions {
+    /// Installs the rate limit, if one was requested.
+    pub fn install(&self) {
+        if let Some(rate) = self.throttle {
+            throttle::install(rate);
+        }
+    }
+}
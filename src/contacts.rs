@@ -0,0 +1,1491 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Apple Contacts, read through the Contacts framework from JavaScript for
+//! Automation, which, unlike the Contacts AppleScript dictionary, gives
+//! stable identifiers and works without Contacts running.
+
+use crate::{Error, Result, export::sha256_hex};
+use core::str::FromStr;
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The application name used in error messages.
+pub const APP: &str = "Contacts";
+
+/// How contacts are extracted, as recorded in their provenance.
+pub const BACKEND: &str = "contacts";
+
+/// The prefix of the URNs identifying individual contacts.
+pub const URN_PREFIX: &str = "urn:apple:contacts:contact:";
+
+/// The prefix of the URNs identifying contact groups.
+pub const GROUP_URN_PREFIX: &str = "urn:apple:contacts:group:";
+
+/// The prefix of the URNs identifying sets of likely duplicate contacts.
+pub const DUPLICATES_URN_PREFIX: &str = "urn:apple:contacts:duplicates:";
+
+/// The JavaScript that every script starts with: it asks for access to
+/// contacts, waiting for the answer, and defines `wait(done)`, `nil`, and
+/// `string`, as [`eventkit::prelude`](crate::eventkit::prelude) does for
+/// EventKit.
+const PRELUDE: &str = r#"
+ObjC.import('Contacts');
+var store = $.CNContactStore.alloc.init;
+function wait(done) {
+    while (!done()) {
+        $.NSRunLoop.currentRunLoop.runUntilDate($.NSDate.dateWithTimeIntervalSinceNow(0.05));
+    }
+}
+var granted = null;
+store.requestAccessForEntityTypeCompletionHandler($.CNEntityTypeContacts, function (ok, error) { granted = ok; });
+wait(function () { return granted !== null; });
+if (!granted) {
+    throw new Error('Not authorized to access contacts. (-1743)');
+}
+function nil(value) {
+    return value === undefined || value === null || value.isNil();
+}
+function string(value) {
+    var text = nil(value) ? null : ObjC.unwrap(value);
+    return text === '' ? null : text;
+}
+"#;
+
+/// The JavaScript that defines `record(c, account, photos)`, describing
+/// contact `c` (with its photo and thumbnail, if `photos`) in the format [`parse`]
+/// reads, `groupRecords(container)`, describing the container's groups
+/// likewise, and `KEYS` and `PHOTO_KEYS`, the keys `record` reads.
+const RECORD_SCRIPT: &str = r#"
+var KEYS = ['identifier', 'contactType', 'namePrefix', 'givenName', 'middleName', 'familyName',
+    'nameSuffix', 'nickname', 'organizationName', 'departmentName', 'jobTitle', 'phoneNumbers',
+    'emailAddresses', 'postalAddresses', 'birthday', 'urlAddresses', 'socialProfiles',
+    'instantMessageAddresses', 'contactRelations'];
+var PHOTO_KEYS = ['imageDataAvailable', 'imageData', 'thumbnailImageData'];
+// Labels Contacts knows come as `_$!<Mobile>!$_`, and ones people type in
+// as they are:
+function label(value) {
+    var text = string(value);
+    if (text === null) return null;
+    var known = /^_\$!<(.*)>!\$_$/.exec(text);
+    return known === null ? text : known[1].toLowerCase();
+}
+function labeled(list, value) {
+    var found = [];
+    for (var i = 0; !nil(list) && i < list.count; i++) {
+        var item = list.objectAtIndex(i);
+        found.push({ label: label(item.label), value: value(item.value) });
+    }
+    return found;
+}
+function address(a) {
+    return {
+        street: string(a.street),
+        city: string(a.city),
+        region: string(a.state),
+        postalCode: string(a.postalCode),
+        country: string(a.country),
+        countryCode: string(a.ISOCountryCode),
+    };
+}
+function birthday(components) {
+    if (nil(components)) return null;
+    var pad = function (n) { return ('0' + n).slice(-2); };
+    // NSDateComponentUndefined, for birthdays without a year:
+    var year = components.year > 0 && components.year < 10000 ? ('000' + components.year).slice(-4) : '-';
+    return year + '-' + pad(components.month) + '-' + pad(components.day);
+}
+function base64(data) {
+    return nil(data) ? null : data.base64EncodedStringWithOptions(0).js;
+}
+function record(c, account, photos) {
+    var note = null;
+    // Reading notes takes an entitlement that osascript may not have:
+    try { note = string(c.note); } catch (e) {}
+    return {
+        id: c.identifier.js,
+        organization: c.contactType === 1,
+        prefix: string(c.namePrefix),
+        given: string(c.givenName),
+        middle: string(c.middleName),
+        family: string(c.familyName),
+        suffix: string(c.nameSuffix),
+        nickname: string(c.nickname),
+        organizationName: string(c.organizationName),
+        department: string(c.departmentName),
+        jobTitle: string(c.jobTitle),
+        phones: labeled(c.phoneNumbers, function (v) { return v.stringValue.js; }),
+        emails: labeled(c.emailAddresses, function (v) { return v.js; }),
+        addresses: labeled(c.postalAddresses, address),
+        urls: labeled(c.urlAddresses, function (v) { return v.js; }),
+        socialProfiles: labeled(c.socialProfiles, function (v) {
+            return { service: string(v.service), username: string(v.username),
+                userIdentifier: string(v.userIdentifier), url: string(v.urlString) };
+        }),
+        instantMessages: labeled(c.instantMessageAddresses, function (v) {
+            return { service: string(v.service), username: string(v.username) };
+        }),
+        related: labeled(c.contactRelations, function (v) { return v.name.js; }),
+        birthday: birthday(c.birthday),
+        notes: note,
+        photo: photos && c.imageDataAvailable ? base64(nil(c.imageData) ? c.thumbnailImageData : c.imageData) : null,
+        thumbnail: photos && c.imageDataAvailable ? base64(c.thumbnailImageData) : null,
+        account: account,
+    };
+}
+function groupRecords(container) {
+    var found = [];
+    var groups = store.groupsMatchingPredicateError(
+        $.CNGroup.predicateForGroupsInContainerWithIdentifier(container.identifier), $());
+    for (var j = 0; !nil(groups) && j < groups.count; j++) {
+        var g = groups.objectAtIndex(j);
+        var members = store.unifiedContactsMatchingPredicateKeysToFetchError(
+            $.CNContact.predicateForContactsInGroupWithIdentifier(g.identifier), ['identifier'], $());
+        var ids = [];
+        for (var k = 0; !nil(members) && k < members.count; k++) ids.push(members.objectAtIndex(k).identifier.js);
+        found.push({ id: g.identifier.js, name: string(g.name) || '', account: string(container.name) || '', members: ids });
+    }
+    return found;
+}
+"#;
+
+/// Everything a script extracts: every group and every contact, in every
+/// account.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Library {
+    pub groups: Vec<Group>,
+    pub contacts: Vec<Contact>,
+}
+
+impl Library {
+    /// Keeps only the groups by these names, and the contacts in them.
+    pub fn retain_groups(&mut self, names: &[String]) {
+        self.groups.retain(|group| names.contains(&group.name));
+        let groups = &self.groups;
+        self.contacts.retain(|contact| {
+            groups
+                .iter()
+                .any(|group| contact.groups.contains(&group.id))
+        });
+    }
+}
+
+/// A kind of field a contact may have any of, as `--has` selects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Email,
+    Phone,
+    Address,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        match input {
+            "email" => Ok(Field::Email),
+            "phone" => Ok(Field::Phone),
+            "address" => Ok(Field::Address),
+            _ => Err(format!(
+                "unsupported field: {input} (expected email, phone, or address)"
+            )),
+        }
+    }
+}
+
+/// A group of contacts, as in `Family`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    pub account: String,
+    /// How many contacts are in the group.
+    pub size: usize,
+}
+
+impl Group {
+    /// Returns the stable URN identifying this group.
+    pub fn urn(&self) -> String {
+        format!("{GROUP_URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this group.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "@type": "ItemList",
+            "@id": self.urn(),
+            "name": self.name,
+            "numberOfItems": self.size,
+            "account": self.account,
+            "source": "apple-contacts",
+        })
+    }
+}
+
+/// A phone number or email address, with the label Contacts shows it
+/// under, as in `mobile` or `work`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Labeled {
+    pub label: Option<String>,
+    pub value: String,
+}
+
+/// A profile on a social network, as in Twitter or LinkedIn.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SocialProfile {
+    /// The network, as Contacts names it, as in `Twitter`.
+    pub service: Option<String>,
+    pub username: Option<String>,
+    /// The network's own id for the account.
+    pub user_id: Option<String>,
+    pub url: Option<String>,
+}
+
+impl SocialProfile {
+    /// Returns the profile as a schema.org `PropertyValue`: the network
+    /// and user name, with their id and URL.
+    pub fn to_json(&self) -> Value {
+        let mut profile = json!({
+            "@type": "PropertyValue",
+            "propertyID": self.service,
+            "value": self.username,
+            "identifier": self.user_id,
+            "url": self.url,
+        });
+        if let Some(profile) = profile.as_object_mut() {
+            profile.retain(|_, value| !value.is_null());
+        }
+        profile
+    }
+}
+
+/// An instant messaging handle, as in a Skype name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstantMessage {
+    pub label: Option<String>,
+    /// The service, as Contacts names it, as in `Skype` or `Jabber`.
+    pub service: Option<String>,
+    pub username: String,
+}
+
+/// A postal address, with the label Contacts shows it under.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Address {
+    pub label: Option<String>,
+    pub street: Option<String>,
+    pub city: Option<String>,
+    /// The state, province, or other region.
+    pub region: Option<String>,
+    pub postal_code: Option<String>,
+    pub country: Option<String>,
+    /// The ISO 3166-1 code of the country, as in `de`.
+    pub country_code: Option<String>,
+}
+
+impl Address {
+    /// Returns the address as a schema.org `PostalAddress`.
+    pub fn to_json(&self) -> Value {
+        let mut address = json!({
+            "@type": "PostalAddress",
+            "contactType": self.label,
+            "streetAddress": self.street,
+            "addressLocality": self.city,
+            "addressRegion": self.region,
+            "postalCode": self.postal_code,
+            "addressCountry": self.country.as_ref().or(self.country_code.as_ref()),
+        });
+        if let Some(address) = address.as_object_mut() {
+            address.retain(|_, value| !value.is_null());
+        }
+        address
+    }
+}
+
+/// A contact's photo.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Photo {
+    /// The photo's MIME type, as in `image/jpeg`.
+    pub mime: &'static str,
+    pub data: Vec<u8>,
+}
+
+impl Photo {
+    /// Reads a photo from Base64, telling its type from its first bytes.
+    fn from_base64(encoded: &str) -> Option<Self> {
+        let url = format!("data:application/octet-stream;base64,{encoded}");
+        let (data, _) = data_url::DataUrl::process(&url)
+            .ok()?
+            .decode_to_vec()
+            .ok()?;
+        let mime = match data.as_slice() {
+            [0xFF, 0xD8, ..] => "image/jpeg",
+            [0x89, b'P', b'N', b'G', ..] => "image/png",
+            [b'G', b'I', b'F', ..] => "image/gif",
+            data if data.get(4..12) == Some(b"ftypheic") => "image/heic",
+            [b'I', b'I', 42, 0, ..] | [b'M', b'M', 0, 42, ..] => "image/tiff",
+            _ => "application/octet-stream",
+        };
+        Some(Photo { mime, data })
+    }
+
+    /// Returns the photo as a schema.org `ImageObject` kept at `url`, with
+    /// its type and SHA-256 digest.
+    pub fn to_json(&self, url: &str) -> Value {
+        json!({
+            "@type": "ImageObject",
+            "contentUrl": url,
+            "encodingFormat": self.mime,
+            "sha256": sha256_hex(&self.data),
+        })
+    }
+
+    /// Returns the file name extension for the photo's type.
+    pub fn extension(&self) -> &'static str {
+        match self.mime {
+            "image/jpeg" => "jpg",
+            "image/png" => "png",
+            "image/gif" => "gif",
+            "image/heic" => "heic",
+            "image/tiff" => "tiff",
+            _ => "bin",
+        }
+    }
+}
+
+/// A single contact, a person or an organization, as the Contacts
+/// framework describes it, linked cards unified into one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Contact {
+    pub id: String,
+    /// Whether the card is a company's, rather than a person's.
+    pub organization: bool,
+    /// The honorific before the name, as in `Dr.`.
+    pub prefix: Option<String>,
+    pub given: Option<String>,
+    pub middle: Option<String>,
+    pub family: Option<String>,
+    /// The honorific after the name, as in `Jr.`.
+    pub suffix: Option<String>,
+    pub nickname: Option<String>,
+    /// The organization the person works for, or the organization's name.
+    pub organization_name: Option<String>,
+    pub department: Option<String>,
+    pub job_title: Option<String>,
+    pub phones: Vec<Labeled>,
+    pub emails: Vec<Labeled>,
+    pub addresses: Vec<Address>,
+    /// Web pages, as in a homepage.
+    pub urls: Vec<Labeled>,
+    pub social_profiles: Vec<SocialProfile>,
+    pub instant_messages: Vec<InstantMessage>,
+    /// The names of related people, labeled with how they are related,
+    /// as in `spouse` or `assistant`.
+    pub related: Vec<Labeled>,
+    /// The birthday, as in `1815-12-10`, or `--12-10` without a year.
+    pub birthday: Option<String>,
+    pub notes: Option<String>,
+    /// The contact's photo, if it has one and it was asked for.
+    pub photo: Option<Photo>,
+    /// The small, square version of the photo Contacts shows in lists.
+    pub thumbnail: Option<Photo>,
+    /// The ids of the groups the contact is in.
+    pub groups: Vec<String>,
+    /// The name of the account (container) the contact is in, as in
+    /// `iCloud`.
+    pub account: String,
+}
+
+impl Contact {
+    /// Returns the stable URN identifying this contact.
+    pub fn urn(&self) -> String {
+        format!("{URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the name Contacts shows the card under: the person's full
+    /// name, or the organization's, falling back to either, then to the
+    /// first email address.
+    pub fn name(&self) -> String {
+        let person = [
+            &self.prefix,
+            &self.given,
+            &self.middle,
+            &self.family,
+            &self.suffix,
+        ]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ");
+        let organization = self.organization_name.clone().unwrap_or_default();
+        let (first, second) = match self.organization {
+            true => (organization, person),
+            false => (person, organization),
+        };
+        [first, second]
+            .into_iter()
+            .find(|name| !name.is_empty())
+            .or_else(|| self.emails.first().map(|email| email.value.clone()))
+            .unwrap_or_default()
+    }
+
+    /// Returns whether the contact has at least one `field`.
+    pub fn has(&self, field: Field) -> bool {
+        match field {
+            Field::Email => !self.emails.is_empty(),
+            Field::Phone => !self.phones.is_empty(),
+            Field::Address => !self.addresses.is_empty(),
+        }
+    }
+
+    /// Returns whether `query` is in the contact's name, nickname,
+    /// organization, or an email address, ignoring case, or, if it has
+    /// no letters, in a phone number, ignoring all but digits.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let texts = [
+            Some(self.name()),
+            self.nickname.clone(),
+            self.organization_name.clone(),
+            self.department.clone(),
+        ];
+        if texts
+            .into_iter()
+            .flatten()
+            .chain(self.emails.iter().map(|email| email.value.clone()))
+            .any(|text| text.to_lowercase().contains(&query))
+        {
+            return true;
+        }
+        let digits = |text: &str| -> String { text.chars().filter(char::is_ascii_digit).collect() };
+        let number = digits(&query);
+        !number.is_empty()
+            && !query.chars().any(char::is_alphabetic)
+            && self
+                .phones
+                .iter()
+                .any(|phone| digits(&phone.value).contains(&number))
+    }
+
+    /// Returns the JSON-LD record for this contact: a schema.org `Person`,
+    /// or `Organization`. Unset fields are left out.
+    pub fn to_json(&self) -> Value {
+        let points: Vec<Value> = self
+            .phones
+            .iter()
+            .map(|phone| ("telephone", phone))
+            .chain(self.emails.iter().map(|email| ("email", email)))
+            .chain(self.urls.iter().map(|url| ("url", url)))
+            .map(|(kind, point)| {
+                let mut json = json!({
+                    "@type": "ContactPoint",
+                    "contactType": point.label,
+                });
+                if let Some(json) = json.as_object_mut() {
+                    json.insert(kind.into(), json!(point.value));
+                    json.retain(|_, value| !value.is_null());
+                }
+                json
+            })
+            .collect();
+        let list = |values: Vec<Value>| match values.is_empty() {
+            true => Value::Null,
+            false => Value::Array(values),
+        };
+        let department = self.department.as_ref().map(|name| {
+            json!({
+                "@type": "Organization",
+                "name": name,
+            })
+        });
+        let employer = self.organization_name.as_ref().map(|name| {
+            let mut employer = json!({
+                "@type": "Organization",
+                "name": name,
+                "department": department,
+            });
+            if let Some(employer) = employer.as_object_mut() {
+                employer.retain(|_, value| !value.is_null());
+            }
+            employer
+        });
+        let mut record = match self.organization {
+            true => json!({
+                "@type": "Organization",
+                "@id": self.urn(),
+                "name": self.name(),
+                "alternateName": self.nickname,
+                "department": department,
+            }),
+            false => json!({
+                "@type": "Person",
+                "@id": self.urn(),
+                "name": self.name(),
+                "honorificPrefix": self.prefix,
+                "givenName": self.given,
+                "additionalName": self.middle,
+                "familyName": self.family,
+                "honorificSuffix": self.suffix,
+                "alternateName": self.nickname,
+                "worksFor": employer,
+                "jobTitle": self.job_title,
+                "birthDate": self.birthday,
+            }),
+        };
+        if let Some(fields) = record.as_object_mut() {
+            fields.insert("contactPoint".into(), list(points));
+            fields.insert(
+                "address".into(),
+                list(self.addresses.iter().map(Address::to_json).collect()),
+            );
+            // Profile URLs, which other sources link to, for joining:
+            fields.insert(
+                "sameAs".into(),
+                list(
+                    self.social_profiles
+                        .iter()
+                        .filter_map(|profile| profile.url.as_ref().map(|url| json!(url)))
+                        .collect(),
+                ),
+            );
+            fields.insert(
+                "socialProfile".into(),
+                list(
+                    self.social_profiles
+                        .iter()
+                        .map(SocialProfile::to_json)
+                        .collect(),
+                ),
+            );
+            let handles = self.instant_messages.iter().map(|handle| {
+                let mut handle = json!({
+                    "@type": "PropertyValue",
+                    "propertyID": handle.service,
+                    "value": handle.username,
+                    "contactType": handle.label,
+                });
+                if let Some(handle) = handle.as_object_mut() {
+                    handle.retain(|_, value| !value.is_null());
+                }
+                handle
+            });
+            fields.insert("instantMessage".into(), list(handles.collect()));
+            let related = self.related.iter().map(|person| {
+                let mut person = json!({
+                    "@type": "Person",
+                    "name": person.value,
+                    "contactType": person.label,
+                });
+                if let Some(person) = person.as_object_mut() {
+                    person.retain(|_, value| !value.is_null());
+                }
+                person
+            });
+            fields.insert("relatedTo".into(), list(related.collect()));
+            fields.insert("description".into(), json!(self.notes));
+            fields.insert(
+                "memberOf".into(),
+                list(
+                    self.groups
+                        .iter()
+                        .map(|id| json!(format!("{GROUP_URN_PREFIX}{id}")))
+                        .collect(),
+                ),
+            );
+            fields.insert("account".into(), json!(self.account));
+            fields.insert("source".into(), json!("apple-contacts"));
+            fields.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Contacts that are likely the same person or organization, entered
+/// more than once.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Duplicates<'a> {
+    /// The contacts, in the order they came in.
+    pub contacts: Vec<&'a Contact>,
+    /// How likely, from 0 to 1, the contacts are one and the same: that
+    /// of the weakest match linking them.
+    pub confidence: f64,
+    /// What matched: `name`, `email`, or `phone`.
+    pub matched: Vec<&'static str>,
+}
+
+impl Duplicates<'_> {
+    /// Returns the stable URN identifying this set, from its contacts'
+    /// ids.
+    pub fn urn(&self) -> String {
+        let ids: Vec<&str> = self.contacts.iter().map(|c| c.id.as_str()).collect();
+        format!("{DUPLICATES_URN_PREFIX}{}", ids.join("+"))
+    }
+
+    /// Returns the JSON-LD record for this set: a schema.org `ItemList`
+    /// of the contacts' URNs, to merge by hand.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "@type": "ItemList",
+            "@id": self.urn(),
+            "name": self.contacts.first().map(|contact| contact.name()),
+            "itemListElement": self.contacts.iter().map(|c| c.urn()).collect::<Vec<_>>(),
+            "numberOfItems": self.contacts.len(),
+            "confidence": self.confidence,
+            "matchedOn": self.matched,
+            "source": "apple-contacts",
+        })
+    }
+}
+
+/// How much each kind of match says two contacts are the same, on its own.
+/// Several together say more: one minus the chance that each is a
+/// coincidence.
+const MATCH_WEIGHTS: [(&str, f64); 3] = [("name", 0.6), ("email", 0.8), ("phone", 0.6)];
+
+/// Returns the sets of `contacts` that are likely duplicates, with at
+/// least `min_confidence`, most likely first. Contacts match by name (its
+/// words in any order, ignoring case and punctuation), by an email address
+/// (ignoring case), or by a phone number (its last 10 digits, ignoring
+/// country codes and formatting); each set links its contacts by the most
+/// likely matches.
+pub fn duplicates(contacts: &[Contact], min_confidence: f64) -> Vec<Duplicates<'_>> {
+    // The contacts sharing each key, by the kind of match:
+    let mut shared: BTreeMap<(usize, String), Vec<usize>> = BTreeMap::new();
+    for (index, contact) in contacts.iter().enumerate() {
+        let mut keys: Vec<(usize, String)> = Vec::new();
+        keys.extend(name_key(contact).map(|key| (0, key)));
+        keys.extend(
+            contact
+                .emails
+                .iter()
+                .map(|email| (1, email.value.trim().to_lowercase())),
+        );
+        keys.extend(contact.phones.iter().filter_map(|phone| {
+            let digits: String = phone.value.chars().filter(char::is_ascii_digit).collect();
+            let start = digits.len().saturating_sub(10);
+            (digits.len() >= 7).then(|| (2, digits[start..].to_string()))
+        }));
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            shared.entry(key).or_default().push(index);
+        }
+    }
+
+    // What each pair of contacts shares:
+    let mut pairs: BTreeMap<(usize, usize), [bool; 3]> = BTreeMap::new();
+    for ((kind, _), indices) in &shared {
+        for (n, &a) in indices.iter().enumerate() {
+            for &b in &indices[n + 1..] {
+                pairs.entry((a, b)).or_default()[*kind] = true;
+            }
+        }
+    }
+    let mut links: Vec<(f64, usize, usize, [bool; 3])> = pairs
+        .into_iter()
+        .map(|((a, b), matched)| {
+            let coincidence: f64 = MATCH_WEIGHTS
+                .iter()
+                .zip(matched)
+                .filter(|(_, matched)| *matched)
+                .map(|((_, weight), _)| 1.0 - weight)
+                .product();
+            (1.0 - coincidence, a, b, matched)
+        })
+        .filter(|(confidence, ..)| *confidence >= min_confidence)
+        .collect();
+    links.sort_by(|x, y| y.0.total_cmp(&x.0).then((x.1, x.2).cmp(&(y.1, y.2))));
+
+    // Joined most likely first, so each set's weakest link is as strong
+    // as can be:
+    let mut set: Vec<usize> = (0..contacts.len()).collect();
+    let root = |set: &mut Vec<usize>, mut index: usize| {
+        while set[index] != index {
+            set[index] = set[set[index]];
+            index = set[index];
+        }
+        index
+    };
+    let mut found: BTreeMap<usize, (f64, [bool; 3])> = BTreeMap::new();
+    for (confidence, a, b, matched) in links {
+        let (a, b) = (root(&mut set, a), root(&mut set, b));
+        if a == b {
+            continue;
+        }
+        let (low, high) = (a.min(b), a.max(b));
+        set[high] = low;
+        let mut joined = (confidence, matched);
+        for (other, also) in [found.remove(&low), found.remove(&high)]
+            .into_iter()
+            .flatten()
+        {
+            joined.0 = joined.0.min(other);
+            joined.1 = core::array::from_fn(|kind| joined.1[kind] || also[kind]);
+        }
+        found.insert(low, joined);
+    }
+
+    let mut members: BTreeMap<usize, Vec<&Contact>> = BTreeMap::new();
+    for (index, contact) in contacts.iter().enumerate() {
+        let root = root(&mut set, index);
+        if found.contains_key(&root) {
+            members.entry(root).or_default().push(contact);
+        }
+    }
+    let mut sets: Vec<Duplicates> = found
+        .into_iter()
+        .map(|(root, (confidence, matched))| Duplicates {
+            contacts: members.remove(&root).unwrap_or_default(),
+            confidence: (confidence * 100.0).round() / 100.0,
+            matched: MATCH_WEIGHTS
+                .iter()
+                .zip(matched)
+                .filter(|(_, matched)| *matched)
+                .map(|((kind, _), _)| *kind)
+                .collect(),
+        })
+        .collect();
+    sets.sort_by(|x, y| y.confidence.total_cmp(&x.confidence));
+    sets
+}
+
+/// Returns the words of a contact's name, lowercased and sorted, without
+/// punctuation, honorifics, or middle names, as in `ada lovelace` for
+/// Ada Lovelace, even with given and family names swapped; `None` if it
+/// has no name.
+fn name_key(contact: &Contact) -> Option<String> {
+    let name = match contact.organization {
+        true => contact.organization_name.clone()?,
+        false => [&contact.given, &contact.family]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+    let mut words: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.sort();
+    let key = words.join(" ");
+    match (key.is_empty(), contact.organization) {
+        (true, _) => None,
+        (false, true) => Some(format!("organization:{key}")),
+        (false, false) => Some(key),
+    }
+}
+
+/// Returns the script that extracts every group and every contact, with
+/// their photos if `photos`, as JSON in the format [`parse`] reads.
+/// Contacts linked across accounts come up once, in the first account
+/// that has them.
+pub fn script(photos: bool) -> String {
+    format!(
+        r#"
+{PRELUDE}
+{RECORD_SCRIPT}
+var photos = {photos};
+if (photos) KEYS = KEYS.concat(PHOTO_KEYS);
+var output = {{ groups: [], contacts: [] }};
+var seen = {{}};
+var containers = store.containersMatchingPredicateError($(), $());
+function fetch(container, keys) {{
+    var predicate = $.CNContact.predicateForContactsInContainerWithIdentifier(container.identifier);
+    return store.unifiedContactsMatchingPredicateKeysToFetchError(predicate, keys, $());
+}}
+for (var i = 0; !nil(containers) && i < containers.count; i++) {{
+    var container = containers.objectAtIndex(i);
+    var contacts = null;
+    try {{ contacts = fetch(container, KEYS.concat(['note'])); }} catch (e) {{}}
+    if (nil(contacts)) contacts = fetch(container, KEYS);
+    for (var j = 0; !nil(contacts) && j < contacts.count; j++) {{
+        var c = contacts.objectAtIndex(j);
+        if (seen[c.identifier.js]) continue;
+        seen[c.identifier.js] = true;
+        output.contacts.push(record(c, string(container.name) || '', photos));
+    }}
+    output.groups = output.groups.concat(groupRecords(container));
+}}
+JSON.stringify(output);
+"#
+    )
+}
+
+/// The JavaScript that defines `apply(c, input)`, making the changes
+/// [`Changes`] describes to the mutable contact `c` and `groups` to the
+/// save request `request`, and `finish(c, container)`, saving and
+/// describing it as [`script`] does, or throwing what went wrong.
+const WRITE_SCRIPT: &str = r#"
+var TEXT_KEYS = ['namePrefix', 'givenName', 'middleName', 'familyName', 'nameSuffix', 'nickname',
+    'organizationName', 'departmentName', 'jobTitle', 'note'];
+var request = $.CNSaveRequest.alloc.init;
+function labeledValues(list, value) {
+    var values = $.NSMutableArray.array;
+    list.forEach(function (item) {
+        values.addObject($.CNLabeledValue.labeledValueWithLabelValue(item.label === null ? $() : item.label, value(item)));
+    });
+    return values;
+}
+function postal(a) {
+    var address = $.CNMutablePostalAddress.alloc.init;
+    address.street = a.street || '';
+    address.city = a.city || '';
+    address.state = a.region || '';
+    address.postalCode = a.postalCode || '';
+    address.country = a.country || '';
+    return address;
+}
+function apply(c, input) {
+    if (input.organization !== null) c.contactType = input.organization ? 1 : 0;
+    TEXT_KEYS.forEach(function (key) {
+        if (key in input.text) c.setValueForKey(input.text[key] === null ? '' : input.text[key], key);
+    });
+    if (input.phones !== null) c.phoneNumbers = labeledValues(input.phones, function (item) {
+        return $.CNPhoneNumber.phoneNumberWithStringValue(item.value);
+    });
+    if (input.emails !== null) c.emailAddresses = labeledValues(input.emails, function (item) { return $(item.value); });
+    if (input.addresses !== null) c.postalAddresses = labeledValues(input.addresses, postal);
+    if (input.urls !== null) c.urlAddresses = labeledValues(input.urls, function (item) { return $(item.value); });
+    if (input.socialProfiles !== null) c.socialProfiles = labeledValues(input.socialProfiles, function (item) {
+        return $.CNSocialProfile.alloc.initWithUrlStringUsernameUserIdentifierService(
+            item.url || $(), item.username || $(), item.userIdentifier || $(), item.service || $());
+    });
+    if (input.instantMessages !== null) c.instantMessageAddresses = labeledValues(input.instantMessages, function (item) {
+        return $.CNInstantMessageAddress.alloc.initWithUsernameService(item.username, item.service || $());
+    });
+    if (input.related !== null) c.contactRelations = labeledValues(input.related, function (item) {
+        return $.CNContactRelation.contactRelationWithName(item.value);
+    });
+    if (input.birthday !== undefined) {
+        if (input.birthday === null) {
+            c.birthday = $();
+        } else {
+            var components = $.NSDateComponents.alloc.init;
+            if (input.birthday.year !== null) components.year = input.birthday.year;
+            components.month = input.birthday.month;
+            components.day = input.birthday.day;
+            c.birthday = components;
+        }
+    }
+}
+// Group changes go in the same request, once the contact is in it:
+function applyGroups(c, input, current) {
+    if (input.groups === null) return null;
+    for (var i = 0; i < input.groups.length; i++) {
+        var found = store.groupsMatchingPredicateError($.CNGroup.predicateForGroupsWithIdentifiers([input.groups[i]]), $());
+        if (nil(found) || found.count === 0) return 'contact group ' + input.groups[i];
+        if (current.indexOf(input.groups[i]) < 0) request.addMemberToGroup(c, found.objectAtIndex(0));
+    }
+    current.filter(function (id) { return input.groups.indexOf(id) < 0; }).forEach(function (id) {
+        var found = store.groupsMatchingPredicateError($.CNGroup.predicateForGroupsWithIdentifiers([id]), $());
+        if (!nil(found) && found.count > 0) request.removeMemberFromGroup(c, found.objectAtIndex(0));
+    });
+    return null;
+}
+function fetchContact(id, note) {
+    var keys = note ? KEYS.concat(['note']) : KEYS;
+    var found = store.unifiedContactsMatchingPredicateKeysToFetchError(
+        $.CNContact.predicateForContactsWithIdentifiers([id]), keys, $());
+    return nil(found) || found.count === 0 ? null : found.objectAtIndex(0);
+}
+function finish(c) {
+    var error = Ref();
+    if (!store.executeSaveRequestError(request, error)) {
+        throw new Error('Could not save the contact: ' + error[0].localizedDescription.js);
+    }
+    var id = c.identifier.js;
+    var saved = null;
+    try { saved = fetchContact(id, true); } catch (e) {}
+    if (saved === null) saved = fetchContact(id, false);
+    var containers = store.containersMatchingPredicateError(
+        $.CNContainer.predicateForContainerOfContactWithIdentifier(id), $());
+    var container = nil(containers) || containers.count === 0 ? null : containers.objectAtIndex(0);
+    var output = { groups: [], contacts: [record(saved, container === null ? '' : string(container.name) || '', false)] };
+    if (container !== null) output.groups = groupRecords(container);
+    return output;
+}
+"#;
+
+/// What to set on a contact: each field given, `None` in `text` and
+/// `birthday` clearing it, and lists given replacing the contact's.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// Whether the card is to be a company's, rather than a person's.
+    pub organization: Option<bool>,
+    /// The text fields, by their Contacts framework keys, as in
+    /// `givenName` or `note`.
+    pub text: BTreeMap<&'static str, Option<String>>,
+    pub phones: Option<Vec<Labeled>>,
+    pub emails: Option<Vec<Labeled>>,
+    pub addresses: Option<Vec<Address>>,
+    pub urls: Option<Vec<Labeled>>,
+    pub social_profiles: Option<Vec<SocialProfile>>,
+    pub instant_messages: Option<Vec<InstantMessage>>,
+    pub related: Option<Vec<Labeled>>,
+    /// The birthday, as in `1815-12-10`, or `--12-10` without a year.
+    pub birthday: Option<Option<String>>,
+    /// The ids of the groups the contact is to be in, and no others.
+    pub groups: Option<Vec<String>>,
+}
+
+impl Changes {
+    /// Reads the changes from a record in the format the emitter writes,
+    /// the fields it has setting the contact's. Fields that are derived,
+    /// such as `name` and `sameAs`, or that can't be written, such as
+    /// `image`, are ignored.
+    pub fn from_json(record: &Value) -> Result<Self> {
+        let invalid = |message: String| Error::Usage {
+            message: format!("invalid contact record: {message}"),
+        };
+        let Some(record) = record.as_object() else {
+            return Err(invalid("not a JSON object".into()));
+        };
+        let text = |value: &Value, key: &str| -> Result<Option<String>> {
+            match value {
+                Value::Null => Ok(None),
+                Value::String(text) => Ok(Some(text.clone()).filter(|text| !text.is_empty())),
+                _ => Err(invalid(format!("{key} is not a string"))),
+            }
+        };
+        let name = |value: &Value, key: &str| -> Result<Option<String>> {
+            match value {
+                Value::Object(object) => text(object.get("name").unwrap_or(&Value::Null), key),
+                value => text(value, key),
+            }
+        };
+        let list = |key: &str| -> Result<Option<&[Value]>> {
+            match record.get(key) {
+                None => Ok(None),
+                Some(Value::Null) => Ok(Some(&[])),
+                Some(Value::Array(items)) => Ok(Some(items)),
+                Some(_) => Err(invalid(format!("{key} is not an array"))),
+            }
+        };
+
+        let organization = match record.get("@type").and_then(Value::as_str) {
+            Some("Organization") => true,
+            Some("Person") | None => false,
+            Some(other) => return Err(invalid(format!("@type {other} is not a contact's"))),
+        };
+        let mut changes = Changes {
+            organization: record.get("@type").map(|_| organization),
+            ..Changes::default()
+        };
+        let mut fields = Vec::from([
+            ("honorificPrefix", "namePrefix"),
+            ("givenName", "givenName"),
+            ("additionalName", "middleName"),
+            ("familyName", "familyName"),
+            ("honorificSuffix", "nameSuffix"),
+            ("alternateName", "nickname"),
+            ("jobTitle", "jobTitle"),
+            ("description", "note"),
+        ]);
+        if organization {
+            fields.push(("name", "organizationName"));
+        }
+        for (field, key) in fields {
+            if let Some(value) = record.get(field) {
+                changes.text.insert(key, text(value, field)?);
+            }
+        }
+        let (company, department) = match organization {
+            true => (None, record.get("department")),
+            false => match record.get("worksFor") {
+                Some(Value::Object(employer)) => (employer.get("name"), employer.get("department")),
+                Some(Value::Null) => (Some(&Value::Null), Some(&Value::Null)),
+                Some(_) => return Err(invalid("worksFor is not an object".into())),
+                None => (None, None),
+            },
+        };
+        if let Some(company) = company {
+            changes
+                .text
+                .insert("organizationName", text(company, "worksFor")?);
+        }
+        if let Some(department) = department {
+            changes
+                .text
+                .insert("departmentName", name(department, "department")?);
+        }
+
+        if let Some(points) = list("contactPoint")? {
+            let (mut phones, mut emails, mut urls) = (Vec::new(), Vec::new(), Vec::new());
+            for point in points {
+                let label = text(
+                    point.get("contactType").unwrap_or(&Value::Null),
+                    "contactType",
+                )?;
+                let field = |key| text(point.get(key).unwrap_or(&Value::Null), key);
+                match (field("telephone")?, field("email")?, field("url")?) {
+                    (Some(value), ..) => phones.push(Labeled { label, value }),
+                    (None, Some(value), _) => emails.push(Labeled { label, value }),
+                    (None, None, Some(value)) => urls.push(Labeled { label, value }),
+                    (None, None, None) => {
+                        return Err(invalid(
+                            "contactPoint without telephone, email, or url".into(),
+                        ));
+                    }
+                }
+            }
+            (changes.phones, changes.emails, changes.urls) =
+                (Some(phones), Some(emails), Some(urls));
+        }
+        if let Some(profiles) = list("socialProfile")? {
+            let mut found = Vec::new();
+            for profile in profiles {
+                let field = |key| text(profile.get(key).unwrap_or(&Value::Null), key);
+                found.push(SocialProfile {
+                    service: field("propertyID")?,
+                    username: field("value")?,
+                    user_id: field("identifier")?,
+                    url: field("url")?,
+                });
+            }
+            changes.social_profiles = Some(found);
+        }
+        if let Some(handles) = list("instantMessage")? {
+            let mut found = Vec::new();
+            for handle in handles {
+                let field = |key| text(handle.get(key).unwrap_or(&Value::Null), key);
+                let Some(username) = field("value")? else {
+                    return Err(invalid("instantMessage without value".into()));
+                };
+                found.push(InstantMessage {
+                    label: field("contactType")?,
+                    service: field("propertyID")?,
+                    username,
+                });
+            }
+            changes.instant_messages = Some(found);
+        }
+        if let Some(people) = list("relatedTo")? {
+            let mut found = Vec::new();
+            for person in people {
+                let field = |key| text(person.get(key).unwrap_or(&Value::Null), key);
+                let Some(value) = field("name")? else {
+                    return Err(invalid("relatedTo without name".into()));
+                };
+                found.push(Labeled {
+                    label: field("contactType")?,
+                    value,
+                });
+            }
+            changes.related = Some(found);
+        }
+        if let Some(addresses) = list("address")? {
+            let mut found = Vec::new();
+            for address in addresses {
+                let field = |key| text(address.get(key).unwrap_or(&Value::Null), key);
+                found.push(Address {
+                    label: field("contactType")?,
+                    street: field("streetAddress")?,
+                    city: field("addressLocality")?,
+                    region: field("addressRegion")?,
+                    postal_code: field("postalCode")?,
+                    country: field("addressCountry")?,
+                    country_code: None,
+                });
+            }
+            changes.addresses = Some(found);
+        }
+        if let Some(birthday) = record.get("birthDate") {
+            let birthday = text(birthday, "birthDate")?;
+            if let Some(birthday) = &birthday
+                && birthday_components(birthday).is_none()
+            {
+                return Err(invalid(format!(
+                    "birthDate {birthday} is not a date, as in 1815-12-10 or --12-10"
+                )));
+            }
+            changes.birthday = Some(birthday);
+        }
+        if let Some(groups) = list("memberOf")? {
+            let ids = groups.iter().map(|group| {
+                let urn = group.as_str().unwrap_or_default();
+                urn.strip_prefix(GROUP_URN_PREFIX)
+                    .filter(|id| !id.is_empty())
+                    .map(String::from)
+                    .ok_or_else(|| invalid(format!("memberOf {group} is not a contact group URN")))
+            });
+            changes.groups = Some(ids.collect::<Result<_>>()?);
+        }
+        Ok(changes)
+    }
+
+    fn to_json(&self) -> Value {
+        let labeled = |list: &Option<Vec<Labeled>>| {
+            list.as_ref().map(|list| {
+                list.iter()
+                    .map(|item| json!({ "label": framework_label(item.label.as_deref()), "value": item.value }))
+                    .collect::<Vec<_>>()
+            })
+        };
+        let mut input = json!({
+            "organization": self.organization,
+            "text": self.text,
+            "phones": labeled(&self.phones),
+            "emails": labeled(&self.emails),
+            "urls": labeled(&self.urls),
+            "related": labeled(&self.related),
+            "socialProfiles": self.social_profiles.as_ref().map(|profiles| {
+                profiles
+                    .iter()
+                    .map(|profile| {
+                        json!({
+                            "label": null,
+                            "service": profile.service,
+                            "username": profile.username,
+                            "userIdentifier": profile.user_id,
+                            "url": profile.url,
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            }),
+            "instantMessages": self.instant_messages.as_ref().map(|handles| {
+                handles
+                    .iter()
+                    .map(|handle| {
+                        json!({
+                            "label": framework_label(handle.label.as_deref()),
+                            "service": handle.service,
+                            "username": handle.username,
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            }),
+            "addresses": self.addresses.as_ref().map(|addresses| {
+                addresses
+                    .iter()
+                    .map(|address| {
+                        json!({
+                            "label": framework_label(address.label.as_deref()),
+                            "street": address.street,
+                            "city": address.city,
+                            "region": address.region,
+                            "postalCode": address.postal_code,
+                            "country": address.country,
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            }),
+            "groups": self.groups,
+        });
+        if let Some(birthday) = &self.birthday {
+            input["birthday"] = birthday.as_deref().and_then(birthday_components).map_or(
+                Value::Null,
+                |(year, month, day)| json!({ "year": year, "month": month, "day": day }),
+            );
+        }
+        input
+    }
+}
+
+/// Returns a birthday's year (if it has one), month, and day, as in
+/// `1815-12-10` or `--12-10`.
+fn birthday_components(birthday: &str) -> Option<(Option<u32>, u32, u32)> {
+    let (year, rest) = match birthday.strip_prefix("--") {
+        Some(rest) => (None, rest),
+        None => {
+            let (year, rest) = birthday.split_once('-')?;
+            (Some(year.parse().ok().filter(|_| year.len() == 4)?), rest)
+        }
+    };
+    let (month, day) = rest.split_once('-')?;
+    let (month, day): (u32, u32) = (month.parse().ok()?, day.parse().ok()?);
+    ((1..=12).contains(&month) && (1..=31).contains(&day)).then_some((year, month, day))
+}
+
+/// Returns the label Contacts keeps for one the emitter writes: its own,
+/// as in `_$!<Mobile>!$_` for `mobile`, or custom ones as they are.
+fn framework_label(label: Option<&str>) -> Option<String> {
+    const KNOWN: &[&str] = &[
+        "Home",
+        "Work",
+        "Other",
+        "Mobile",
+        "Main",
+        "HomeFAX",
+        "WorkFAX",
+        "OtherFAX",
+        "Pager",
+        "School",
+        "HomePage",
+        "Father",
+        "Mother",
+        "Parent",
+        "Brother",
+        "Sister",
+        "Child",
+        "Friend",
+        "Spouse",
+        "Partner",
+        "Assistant",
+        "Manager",
+    ];
+    let label = label?;
+    Some(
+        match KNOWN.iter().find(|known| known.to_lowercase() == label) {
+            Some(known) => format!("_$!<{known}>!$_"),
+            None => label.to_string(),
+        },
+    )
+}
+
+/// Returns the script that creates a contact, as `changes` has it, in the
+/// account named `account`, or else the default one, and describes it as
+/// [`script`] does, with `missing`, what wasn't found, if it failed for
+/// that.
+pub fn create_script(account: Option<&str>, changes: &Changes) -> String {
+    let input = changes.to_json();
+    let account = json!(account);
+    format!(
+        r#"
+{PRELUDE}
+{RECORD_SCRIPT}
+{WRITE_SCRIPT}
+var input = {input};
+var account = {account};
+var containerId = null;
+var missing = null;
+if (account === null) {{
+    containerId = store.defaultContainerIdentifier.js;
+}} else {{
+    var containers = store.containersMatchingPredicateError($(), $());
+    for (var i = 0; !nil(containers) && i < containers.count; i++) {{
+        if ((string(containers.objectAtIndex(i).name) || '') === account) containerId = containers.objectAtIndex(i).identifier.js;
+    }}
+    if (containerId === null) missing = 'contacts account ' + account;
+}}
+var c = $.CNMutableContact.alloc.init;
+if (missing === null) {{
+    apply(c, input);
+    request.addContactToContainerWithIdentifier(c, containerId);
+    missing = applyGroups(c, input, []);
+}}
+JSON.stringify(missing === null ? finish(c) : {{ missing: missing }});
+"#
+    )
+}
+
+/// Returns the script that changes the contact `id` as `changes` has it,
+/// and describes it as [`script`] does, with `missing`, what wasn't
+/// found, if it failed for that.
+pub fn update_script(id: &str, changes: &Changes) -> String {
+    let input = changes.to_json();
+    let id = json!(id);
+    format!(
+        r#"
+{PRELUDE}
+{RECORD_SCRIPT}
+{WRITE_SCRIPT}
+var input = {input};
+var id = {id};
+var missing = null;
+var found = fetchContact(id, false);
+var c = null;
+if (found === null) {{
+    missing = 'contact ' + id;
+}} else {{
+    c = found.mutableCopy;
+    apply(c, input);
+    request.updateContact(c);
+    var current = [];
+    var groups = store.groupsMatchingPredicateError($(), $());
+    for (var i = 0; input.groups !== null && !nil(groups) && i < groups.count; i++) {{
+        var g = groups.objectAtIndex(i);
+        var members = store.unifiedContactsMatchingPredicateKeysToFetchError(
+            $.CNContact.predicateForContactsInGroupWithIdentifier(g.identifier), ['identifier'], $());
+        for (var k = 0; !nil(members) && k < members.count; k++) {{
+            if (members.objectAtIndex(k).identifier.js === id) current.push(g.identifier.js);
+        }}
+    }}
+    missing = applyGroups(c, input, current);
+}}
+JSON.stringify(missing === null ? finish(c) : {{ missing: missing }});
+"#
+    )
+}
+
+/// Extracts the contact id from a contact URN, also accepting a bare
+/// Contacts id.
+pub fn id_from_urn(input: &str) -> Option<&str> {
+    let input = input.trim();
+    let id = input.strip_prefix(URN_PREFIX).unwrap_or(input);
+    (!id.is_empty() && !id.starts_with("urn:")).then_some(id)
+}
+
+/// Parses the output of [`create_script`] or [`update_script`] into the
+/// contact as written, failing with [`Error::NotFound`] for what wasn't
+/// found.
+pub fn parse_written(output: &str) -> Result<Contact> {
+    let missing = serde_json::from_str::<Value>(output.trim())
+        .ok()
+        .and_then(|output| output.get("missing")?.as_str().map(String::from));
+    if let Some(what) = missing {
+        return Err(Error::NotFound { what });
+    }
+    parse(output)?
+        .contacts
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Parse {
+            context: "reading the contact written",
+            message: "no contact in the output".to_string(),
+        })
+}
+
+/// Parses the output of [`script`].
+pub fn parse(output: &str) -> Result<Library> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Library::default()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading contacts",
+            message: e.to_string(),
+        })?,
+    };
+    let array = |key: &str| {
+        output
+            .get(key)
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+    };
+    let mut contacts: Vec<Contact> = array("contacts")
+        .iter()
+        .map(parse_contact)
+        .collect::<Result<_>>()?;
+    let mut groups = Vec::new();
+    for record in array("groups") {
+        let text = |key: &str| record.get(key).and_then(Value::as_str).map(String::from);
+        let Some(id) = text("id") else {
+            return Err(Error::Parse {
+                context: "reading contact group id",
+                message: "missing id field".to_string(),
+            });
+        };
+        let members: Vec<&str> = record
+            .get("members")
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(Value::as_str)
+            .collect();
+        for contact in &mut contacts {
+            if members.contains(&contact.id.as_str()) {
+                contact.groups.push(id.clone());
+            }
+        }
+        groups.push(Group {
+            id,
+            name: text("name").unwrap_or_default(),
+            account: text("account").unwrap_or_default(),
+            size: members.len(),
+        });
+    }
+    Ok(Library { groups, contacts })
+}
+
+fn parse_contact(record: &Value) -> Result<Contact> {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).map(String::from);
+    let Some(id) = text("id") else {
+        return Err(Error::Parse {
+            context: "reading contact id",
+            message: "missing id field".to_string(),
+        });
+    };
+    let array = |key: &str| {
+        record
+            .get(key)
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+    };
+    let labeled = |key: &str| {
+        array(key)
+            .iter()
+            .filter_map(|item| {
+                Some(Labeled {
+                    label: item.get("label").and_then(Value::as_str).map(String::from),
+                    value: item.get("value").and_then(Value::as_str)?.into(),
+                })
+            })
+            .collect()
+    };
+    Ok(Contact {
+        id,
+        organization: record.get("organization").and_then(Value::as_bool) == Some(true),
+        prefix: text("prefix"),
+        given: text("given"),
+        middle: text("middle"),
+        family: text("family"),
+        suffix: text("suffix"),
+        nickname: text("nickname"),
+        organization_name: text("organizationName"),
+        department: text("department"),
+        job_title: text("jobTitle"),
+        phones: labeled("phones"),
+        emails: labeled("emails"),
+        addresses: array("addresses")
+            .iter()
+            .map(|item| {
+                let value = item.get("value").unwrap_or(&Value::Null);
+                let text = |key: &str| value.get(key).and_then(Value::as_str).map(String::from);
+                Address {
+                    label: item.get("label").and_then(Value::as_str).map(String::from),
+                    street: text("street"),
+                    city: text("city"),
+                    region: text("region"),
+                    postal_code: text("postalCode"),
+                    country: text("country"),
+                    country_code: text("countryCode"),
+                }
+            })
+            .collect(),
+        urls: labeled("urls"),
+        social_profiles: array("socialProfiles")
+            .iter()
+            .map(|item| {
+                let value = item.get("value").unwrap_or(&Value::Null);
+                let text = |key: &str| value.get(key).and_then(Value::as_str).map(String::from);
+                SocialProfile {
+                    service: text("service"),
+                    username: text("username"),
+                    user_id: text("userIdentifier"),
+                    url: text("url"),
+                }
+            })
+            .collect(),
+        instant_messages: array("instantMessages")
+            .iter()
+            .filter_map(|item| {
+                let value = item.get("value").unwrap_or(&Value::Null);
+                Some(InstantMessage {
+                    label: item.get("label").and_then(Value::as_str).map(String::from),
+                    service: value
+                        .get("service")
+                        .and_then(Value::as_str)
+                        .map(String::from),
+                    username: value.get("username").and_then(Value::as_str)?.into(),
+                })
+            })
+            .collect(),
+        related: labeled("related"),
+        birthday: text("birthday"),
+        notes: text("notes").filter(|notes| !notes.trim().is_empty()),
+        photo: record
+            .get("photo")
+            .and_then(Value::as_str)
+            .and_then(Photo::from_base64),
+        thumbnail: record
+            .get("thumbnail")
+            .and_then(Value::as_str)
+            .and_then(Photo::from_base64),
+        account: text("account").unwrap_or_default(),
+        groups: Vec::new(),
+    })
+}
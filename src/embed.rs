@@ -0,0 +1,87 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Computes embedding vectors for records' text, through an external
+//! command or an OpenAI-compatible `/embeddings` endpoint, so that records
+//! reach vector databases ready to index in a single pass.
+
+use crate::{Error, Result, exec, sink::webhook::curl_quote};
+use serde_json::{Value, json};
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The model requested from embedding endpoints unless told otherwise.
+pub const DEFAULT_MODEL: &str = "text-embedding-3-small";
+
+/// Where embeddings come from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Embedder {
+    /// A shell command reading the text on stdin and printing the vector.
+    Command(String),
+    /// An OpenAI-compatible endpoint, such as
+    /// `https://api.openai.com/v1/embeddings`.
+    Http {
+        url: String,
+        model: String,
+        token: Option<String>,
+    },
+}
+
+impl Embedder {
+    /// Returns the embedding of `text`.
+    pub fn embed(&self, text: &str) -> Result<Vec<f64>> {
+        let output = match self {
+            Embedder::Command(command) => exec::run("sh", ["-c", command], text.as_bytes())?,
+            Embedder::Http { url, model, token } => {
+                // As for webhooks, everything goes to `curl` through a config
+                // file on stdin, keeping the token out of the process list:
+                let body = json!({ "model": model, "input": text }).to_string();
+                let mut config = format!(
+                    "url = {}\nrequest = \"POST\"\nheader = \"Content-Type: application/json\"\ndata-binary = {}\n",
+                    curl_quote(url),
+                    curl_quote(&body),
+                );
+                if let Some(token) = token {
+                    config += &format!(
+                        "header = {}\n",
+                        curl_quote(&format!("Authorization: Bearer {token}"))
+                    );
+                }
+                exec::run(
+                    "curl",
+                    ["--silent", "--show-error", "--fail", "--config", "-"],
+                    config.as_bytes(),
+                )?
+            }
+        };
+        parse(&output)
+    }
+}
+
+/// Reads a vector printed as a JSON array of numbers, as an object with an
+/// `embedding` array, or as an OpenAI-style `{"data": [{"embedding": …}]}`
+/// response.
+fn parse(output: &[u8]) -> Result<Vec<f64>> {
+    let invalid = |message: String| Error::Parse {
+        context: "reading an embedding",
+        message,
+    };
+    let value: Value = serde_json::from_slice(output).map_err(|e| invalid(e.to_string()))?;
+    let vector = value
+        .pointer("/data/0/embedding")
+        .or_else(|| value.get("embedding"))
+        .unwrap_or(&value);
+    let Some(numbers) = vector.as_array() else {
+        return Err(invalid("expected an array of numbers".to_string()));
+    };
+    numbers
+        .iter()
+        .map(|number| {
+            number
+                .as_f64()
+                .ok_or_else(|| invalid(format!("not a number: {number}")))
+        })
+        .collect()
+}
@@ -0,0 +1,46 @@
+// This is free and unencumbered software released into the public domain.
+
+#[cfg(not(feature = "std"))]
+compile_error!("asimov-apple-contacts-emitter requires the 'std' feature");
+
+use asimov_apple_module::cli::{self, contacts::EmitOptions};
+use asimov_module::SysexitsError;
+use clap::Parser;
+use clientele::StandardOptions;
+use std::error::Error as StdError;
+
+/// asimov-apple-contacts-emitter
+#[derive(Debug, Parser)]
+struct Options {
+    #[clap(flatten)]
+    flags: StandardOptions,
+
+    #[clap(flatten)]
+    common: cli::CommonOptions,
+
+    #[clap(flatten)]
+    command: EmitOptions,
+}
+
+pub fn main() -> Result<SysexitsError, Box<dyn StdError>> {
+    // Load environment variables from `.env`:
+    asimov_module::dotenv().ok();
+
+    // Expand wildcards and @argfiles:
+    let args = asimov_module::args_os()?;
+
+    // Parse command-line options:
+    let options = Options::parse_from(args);
+
+    // Handle the `--version` and `--license` flags:
+    if let Some(exit_code) = cli::handle_standard_flags(&options.flags) {
+        return Ok(exit_code);
+    }
+
+    // Configure logging & tracing, and handle Ctrl-C:
+    if let Err(err) = cli::init(&options.flags, &options.common) {
+        return Ok(cli::handle_error(&err));
+    }
+
+    Ok(cli::finish(cli::contacts::emit(&options.command)))
+}
@@ -0,0 +1,371 @@
+// This is free and unencumbered software released into the public domain.
+
+use std::{
+    error::Error as StdError,
+    fmt, format,
+    fs::File,
+    io,
+    path::Path,
+    process::ExitStatus,
+    string::{String, ToString},
+};
+
+pub type Result<T, E = Error> = core::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The command line was well-formed but its arguments didn't make sense.
+    Usage { message: String },
+    Io {
+        context: &'static str,
+        source: io::Error,
+    },
+    /// The user hasn't granted this process Automation access to the app.
+    PermissionDenied { app: &'static str, stderr: String },
+    /// The user hasn't granted this process Full Disk Access, which
+    /// reading an app's data straight from disk takes.
+    NoFullDiskAccess { path: String },
+    /// The app isn't installed, isn't running, or refused the connection.
+    Unavailable { app: &'static str, stderr: String },
+    /// The app didn't answer the Apple Event in time.
+    Timeout { app: &'static str, stderr: String },
+    OsaScriptFailed {
+        app: &'static str,
+        status: ExitStatus,
+        stderr: String,
+    },
+    /// An external program used for output exited unsuccessfully.
+    CommandFailed {
+        program: &'static str,
+        status: ExitStatus,
+        stderr: String,
+    },
+    /// A remote endpoint wouldn't accept the records sent to it.
+    Delivery { url: String, message: String },
+    /// The process couldn't confine itself in least-privilege mode.
+    Sandbox { message: String },
+    /// The requested resource doesn't exist (any longer).
+    NotFound { what: String },
+    Parse {
+        context: &'static str,
+        message: String,
+    },
+    /// A check, such as of a backup against the library, found problems.
+    Mismatch { what: String, problems: usize },
+    Json {
+        context: &'static str,
+        source: serde_json::Error,
+    },
+}
+
+impl Error {
+    /// Classifies a failed `osascript` run by the AppleScript error number
+    /// it printed to stderr.
+    pub fn from_osascript(app: &'static str, status: ExitStatus, stderr: String) -> Self {
+        match osascript_error_number(&stderr) {
+            // errAEEventNotPermitted, errAEPrivilegeError:
+            Some(-1743 | -10004) => Error::PermissionDenied { app, stderr },
+            // procNotFound, connectionInvalid, app launch failure:
+            Some(-600 | -609 | -10810) => Error::Unavailable { app, stderr },
+            // errAENoSuchObject:
+            Some(-1728) => Error::NotFound {
+                what: missing_object(&stderr).unwrap_or_else(|| format!("{app} object")),
+            },
+            // errAETimeout:
+            Some(-1712) => Error::Timeout { app, stderr },
+            _ => Error::OsaScriptFailed {
+                app,
+                status,
+                stderr,
+            },
+        }
+    }
+
+    /// Returns a stable, machine-readable name for this kind of error.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Usage { .. } => "usage",
+            Error::Io { .. } => "io",
+            Error::PermissionDenied { .. } => "permission_denied",
+            Error::NoFullDiskAccess { .. } => "no_full_disk_access",
+            Error::Unavailable { .. } => "unavailable",
+            Error::Timeout { .. } => "timeout",
+            Error::OsaScriptFailed { .. } => "osascript_failed",
+            Error::CommandFailed { .. } => "command_failed",
+            Error::Delivery { .. } => "delivery",
+            Error::Sandbox { .. } => "sandbox",
+            Error::NotFound { .. } => "not_found",
+            Error::Parse { .. } => "parse",
+            Error::Mismatch { .. } => "mismatch",
+            Error::Json { .. } => "json",
+        }
+    }
+
+    /// Returns what the user can do about this error, if anything.
+    pub fn remediation(&self) -> Option<&'static str> {
+        Some(match self {
+            Error::Usage { .. } => "check the command line; see --help",
+            Error::PermissionDenied { .. } => {
+                "grant Automation access in System Settings > Privacy & Security > Automation, then retry"
+            }
+            Error::NoFullDiskAccess { .. } => {
+                "grant Full Disk Access to the terminal or app running this in System Settings > Privacy & Security > Full Disk Access, then retry"
+            }
+            Error::Unavailable { .. } => {
+                "make sure the app is installed and can be launched, then retry"
+            }
+            Error::Timeout { .. } => "retry later, or pace extraction with --throttle",
+            Error::CommandFailed { .. } => {
+                "make sure the program is installed and its target is reachable"
+            }
+            Error::Delivery { .. } => {
+                "retry later, or pass --dead-letter to keep undelivered records"
+            }
+            Error::Sandbox { .. } => "run without --least-privilege, or on macOS",
+            Error::Parse { .. } => "retry; if it keeps failing, please report a bug",
+            Error::Mismatch { .. } => "export again to bring it up to date",
+            Error::Io { source, .. } if source.kind() == io::ErrorKind::NotFound => {
+                "make sure the required program (such as osascript) or file exists"
+            }
+            Error::Io { .. }
+            | Error::OsaScriptFailed { .. }
+            | Error::NotFound { .. }
+            | Error::Json { .. } => return None,
+        })
+    }
+
+    /// Returns whether simply retrying later may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Timeout { .. } | Error::Unavailable { .. } | Error::Delivery { .. } => true,
+            Error::Io { source, .. } => source.kind() == io::ErrorKind::TimedOut,
+            _ => false,
+        }
+    }
+
+    /// Returns the sysexits(3) code that best describes this error, so that
+    /// automation wrappers can tell "grant permission" apart from "retry later".
+    #[cfg(feature = "cli")]
+    pub fn exit_code(&self) -> asimov_module::SysexitsError {
+        use asimov_module::SysexitsError::*;
+        match self {
+            Error::Usage { .. } => EX_USAGE,
+            Error::Io { source, .. } => match source.kind() {
+                io::ErrorKind::PermissionDenied => EX_NOPERM,
+                io::ErrorKind::NotFound => EX_UNAVAILABLE,
+                io::ErrorKind::TimedOut => EX_TEMPFAIL,
+                _ => EX_IOERR,
+            },
+            Error::PermissionDenied { .. } | Error::NoFullDiskAccess { .. } => EX_NOPERM,
+            Error::Unavailable { .. } => EX_UNAVAILABLE,
+            Error::Timeout { .. } => EX_TEMPFAIL,
+            Error::OsaScriptFailed { .. } => EX_UNAVAILABLE,
+            Error::CommandFailed { .. } => EX_IOERR,
+            Error::Delivery { .. } => EX_UNAVAILABLE,
+            Error::Sandbox { .. } => EX_OSERR,
+            Error::NotFound { .. } => EX_NOINPUT,
+            Error::Parse { .. } => EX_DATAERR,
+            Error::Mismatch { .. } => EX_DATAERR,
+            Error::Json { source, .. } if source.is_io() => EX_IOERR,
+            Error::Json { .. } => EX_SOFTWARE,
+        }
+    }
+}
+
+/// Extracts the trailing `(-NNNN)` error number from an `osascript`
+/// message such as `execution error: Not authorized ... (-1743)`.
+fn osascript_error_number(stderr: &str) -> Option<i32> {
+    let line = stderr.lines().rev().find(|line| !line.trim().is_empty())?;
+    let inner = line.trim().strip_suffix(')')?;
+    let (_, number) = inner.rsplit_once('(')?;
+    number.parse().ok()
+}
+
+/// Extracts what an app couldn't find from an `osascript` message such
+/// as `execution error: Notes got an error: Can’t get note id "x". (-1728)`.
+fn missing_object(stderr: &str) -> Option<String> {
+    let line = stderr.lines().rev().find(|line| !line.trim().is_empty())?;
+    let (_, object) = line
+        .split_once("Can’t get ")
+        .or_else(|| line.split_once("Can't get "))?;
+    let (object, _) = object.rsplit_once(" (")?;
+    Some(object.trim_end_matches('.').to_string())
+}
+
+/// Opens a file of an app's data to check that it can be read, telling
+/// a missing file, as `what`, and missing Full Disk Access apart from
+/// other failures.
+pub fn check_readable(path: &Path, what: &str) -> Result<()> {
+    match File::open(path) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Error::NotFound {
+            what: format!("{what} {}", path.display()),
+        }),
+        // macOS refuses with EPERM rather than EACCES:
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Err(Error::NoFullDiskAccess {
+            path: path.display().to_string(),
+        }),
+        Err(e) => Err(Error::Io {
+            context: "opening the app's data",
+            source: e,
+        }),
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Usage { message } => {
+                write!(f, "{message}")
+            }
+            Error::Io { context, .. } => {
+                write!(f, "I/O error while {context}")
+            }
+            Error::PermissionDenied { app, .. } => {
+                write!(
+                    f,
+                    "not authorized to control {app}; grant access in System Settings > Privacy & Security > Automation"
+                )
+            }
+            Error::NoFullDiskAccess { path } => {
+                write!(
+                    f,
+                    "not authorized to read {path}; grant Full Disk Access in System Settings > Privacy & Security > Full Disk Access"
+                )
+            }
+            Error::Unavailable { app, .. } => {
+                write!(f, "{app} is not available")
+            }
+            Error::Timeout { app, .. } => {
+                write!(f, "timed out waiting for {app} to respond")
+            }
+            Error::OsaScriptFailed { app, .. } => {
+                write!(f, "failed to talk to {app} (osascript)")
+            }
+            Error::CommandFailed {
+                program, stderr, ..
+            } => {
+                write!(f, "{program} failed: {}", stderr.trim())
+            }
+            Error::Delivery { url, message } => {
+                write!(f, "failed to deliver records to {url}: {message}")
+            }
+            Error::Sandbox { message } => {
+                write!(f, "failed to enter least-privilege mode: {message}")
+            }
+            Error::NotFound { what } => {
+                write!(f, "{what} not found")
+            }
+            Error::Parse { context, .. } => {
+                write!(f, "failed to parse output while {context}")
+            }
+            Error::Mismatch { what, problems } => {
+                write!(f, "{what} doesn't match the library: {problems} problems")
+            }
+            Error::Json { context, .. } => {
+                write!(f, "failed to serialize JSON while {context}")
+            }
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Json { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io {
+            context: "performing I/O",
+            source,
+        }
+    }
+}
+
+impl From<html2text::Error> for Error {
+    fn from(e: html2text::Error) -> Self {
+        Error::Parse {
+            context: "converting note body from HTML to text",
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json {
+            context: "writing JSON to stdout",
+            source: e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    fn classify(stderr: &str) -> Error {
+        Error::from_osascript("Notes", ExitStatus::from_raw(256), stderr.to_string())
+    }
+
+    #[test]
+    fn osascript_errors_are_classified_by_number() {
+        for (stderr, kind) in [
+            (
+                "execution error: Not authorized to send Apple events to Notes. (-1743)",
+                "permission_denied",
+            ),
+            (
+                "execution error: A privilege violation occurred. (-10004)",
+                "permission_denied",
+            ),
+            (
+                "execution error: Notes got an error: Application isn’t running. (-600)",
+                "unavailable",
+            ),
+            ("execution error: Invalid connection. (-609)", "unavailable"),
+            (
+                "execution error: Notes got an error: Can’t get note id \"x\". (-1728)",
+                "not_found",
+            ),
+            (
+                "execution error: Notes got an error: AppleEvent timed out. (-1712)",
+                "timeout",
+            ),
+            (
+                "execution error: Notes got an error: Application can’t be launched. (-10810)",
+                "unavailable",
+            ),
+            (
+                "execution error: Notes got an error: Can’t make x into type text. (-1700)",
+                "osascript_failed",
+            ),
+            (
+                "syntax error: Expected end of line. (-2741)\n\n",
+                "osascript_failed",
+            ),
+            ("", "osascript_failed"),
+        ] {
+            assert_eq!(classify(stderr).kind(), kind, "{stderr}");
+        }
+    }
+
+    #[test]
+    fn missing_objects_are_named() {
+        let error = classify(
+            "0:42: execution error: Notes got an error: Can’t get note id \"x\". (-1728)\n",
+        );
+        assert_eq!(error.to_string(), "note id \"x\" not found");
+        let error = classify("execution error: Can't get item 3 of {}. (-1728)");
+        assert_eq!(error.to_string(), "item 3 of {} not found");
+        let error = classify("execution error: No such object. (-1728)");
+        assert_eq!(error.to_string(), "Notes object not found");
+    }
+}
@@ -0,0 +1,163 @@
+// This is free and unencumbered software released into the public domain.
+
+//! What the EventKit scripts of the Reminders and Calendar extractors
+//! share, run as JavaScript for Automation, and, in builds with the
+//! `eventkit` feature, the backend that reads EventKit in process instead.
+
+use crate::{Result, calendar, reminders};
+use core::str::FromStr;
+use serde_json::Value;
+use std::{format, string::String};
+
+#[cfg(all(feature = "eventkit", target_os = "macos"))]
+mod native;
+
+/// How the native backend is recorded in provenance; the JXA one is
+/// recorded as [`calendar::BACKEND`] and [`reminders::BACKEND`] say.
+pub const NATIVE_BACKEND: &str = "eventkit-native";
+
+/// The backend used unless told otherwise: the native one where it is
+/// built in.
+pub const DEFAULT_BACKEND: &str = if cfg!(all(feature = "eventkit", target_os = "macos")) {
+    "native"
+} else {
+    "jxa"
+};
+
+/// How EventKit is read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Through the Objective-C bridge of JavaScript for Automation, by a
+    /// script that `osascript` runs.
+    Jxa,
+    /// In process, through the objc2 bindings, which is much faster; only
+    /// in builds with the `eventkit` feature, on macOS.
+    Native,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        match input {
+            "jxa" => Ok(Backend::Jxa),
+            "native" => Ok(Backend::Native),
+            _ => Err(format!(
+                "unsupported backend: {input} (expected jxa or native)"
+            )),
+        }
+    }
+}
+
+/// Reads the calendars and events `filter` selects in process, as the
+/// JSON object [`calendar::script`] prints, or `None` if interrupted first.
+pub fn read_events(
+    filter: &calendar::Filter,
+    interrupted: impl Fn() -> bool,
+) -> Result<Option<Value>> {
+    #[cfg(all(feature = "eventkit", target_os = "macos"))]
+    return native::events(filter, interrupted);
+    #[cfg(not(all(feature = "eventkit", target_os = "macos")))]
+    return Err(without_native((filter, interrupted)));
+}
+
+/// Reads the lists and reminders `filter` selects in process, as the JSON
+/// object [`reminders::script`] prints, or `None` if interrupted first.
+pub fn read_reminders(
+    filter: &reminders::Filter,
+    interrupted: impl Fn() -> bool,
+) -> Result<Option<Value>> {
+    #[cfg(all(feature = "eventkit", target_os = "macos"))]
+    return native::reminders(filter, interrupted);
+    #[cfg(not(all(feature = "eventkit", target_os = "macos")))]
+    return Err(without_native((filter, interrupted)));
+}
+
+#[cfg(not(all(feature = "eventkit", target_os = "macos")))]
+fn without_native<T>(_: T) -> crate::Error {
+    crate::Error::Usage {
+        message: "this build has no native EventKit backend; use --backend jxa, or build on macOS with the eventkit feature".into(),
+    }
+}
+
+/// The kind of EventKit item a script reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Entity {
+    Event,
+    Reminder,
+}
+
+impl Entity {
+    /// Returns the `EKEntityType` constant for the kind.
+    fn constant(&self) -> &'static str {
+        match self {
+            Entity::Event => "EKEntityTypeEvent",
+            Entity::Reminder => "EKEntityTypeReminder",
+        }
+    }
+
+    /// Returns the name of the kind in EventKit's access requests, as in
+    /// `requestFullAccessToEventsWithCompletion`.
+    fn plural(&self) -> &'static str {
+        match self {
+            Entity::Event => "Events",
+            Entity::Reminder => "Reminders",
+        }
+    }
+}
+
+/// Returns the JavaScript that every script starts with: it asks for
+/// access to items of `entity`, waiting for the answer, and defines
+/// `wait(done)`, which runs the run loop until `done()` holds, for
+/// EventKit's other callbacks, `nil`, `string`, and `date`, and, for
+/// calendars (and reminder lists) `c`, `account(c)` and `color(c)`.
+pub fn prelude(entity: Entity) -> String {
+    let (constant, plural) = (entity.constant(), entity.plural());
+    let lower = plural.to_lowercase();
+    format!(
+        r#"
+ObjC.import('AppKit');
+ObjC.import('EventKit');
+var store = $.EKEventStore.alloc.init;
+function wait(done) {{
+    while (!done()) {{
+        $.NSRunLoop.currentRunLoop.runUntilDate($.NSDate.dateWithTimeIntervalSinceNow(0.05));
+    }}
+}}
+var granted = null;
+var answer = function (ok, error) {{ granted = ok; }};
+if (store.respondsToSelector('requestFullAccessTo{plural}WithCompletion:')) {{
+    store.requestFullAccessTo{plural}WithCompletion(answer);
+}} else {{
+    store.requestAccessToEntityTypeCompletion($.{constant}, answer);
+}}
+wait(function () {{ return granted !== null; }});
+if (!granted) {{
+    throw new Error('Not authorized to access {lower}. (-1743)');
+}}
+var formatter = $.NSDateFormatter.alloc.init;
+formatter.locale = $.NSLocale.localeWithLocaleIdentifier('en_US_POSIX');
+formatter.dateFormat = "yyyy-MM-dd'T'HH:mm:ss";
+function nil(value) {{
+    return value === undefined || value === null || value.isNil();
+}}
+function string(value) {{
+    return nil(value) ? null : ObjC.unwrap(value);
+}}
+function date(value) {{
+    return nil(value) ? null : formatter.stringFromDate(value).js;
+}}
+function account(c) {{
+    return nil(c.source) ? '' : string(c.source.title) || '';
+}}
+function color(c) {{
+    if (nil(c.color)) return null;
+    var rgb = c.color.colorUsingColorSpace($.NSColorSpace.sRGBColorSpace);
+    if (nil(rgb)) return null;
+    return '#' + [rgb.redComponent, rgb.greenComponent, rgb.blueComponent].map(function (x) {{
+        return ('0' + Math.round(x * 255).toString(16)).slice(-2);
+    }}).join('').toUpperCase();
+}}
+"#
+    )
+}
@@ -0,0 +1,576 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads EventKit in process, through the objc2 bindings, into the very
+//! JSON objects that the scripts of [`calendar`] and [`reminders`] print,
+//! so that the two backends share everything after extraction. Each
+//! function here does what the script function of the same name does.
+
+#![allow(unsafe_code)]
+
+use super::Entity;
+use crate::{
+    Error, Result, calendar, reminders,
+    timezone::{self, LocalTime, TimeZone},
+};
+use block2::RcBlock;
+use objc2::{
+    rc::Retained,
+    runtime::{Bool, NSObjectProtocol},
+    sel,
+};
+use objc2_app_kit::NSColorSpace;
+use objc2_event_kit::{
+    EKAlarmProximity, EKCalendar, EKCalendarItem, EKEntityType, EKEvent, EKEventStore,
+    EKParticipant, EKReminder, EKStructuredLocation,
+};
+use objc2_foundation::{
+    NSArray, NSCalendar, NSDate, NSDateComponents, NSError, NSNumber, NSRunLoop, NSString,
+    NSTimeZone,
+};
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeSet,
+    format,
+    string::{String, ToString},
+    sync::mpsc,
+    time::Duration,
+    vec::Vec,
+};
+
+/// How long to wait for an answer between turns of the run loop.
+const POLL: Duration = Duration::from_millis(50);
+
+pub fn events(filter: &calendar::Filter, interrupted: impl Fn() -> bool) -> Result<Option<Value>> {
+    if filter.geocode {
+        return Err(Error::Usage {
+            message: "--geocode takes --backend jxa".to_string(),
+        });
+    }
+    let Some(store) = open(Entity::Event, calendar::APP, &interrupted)? else {
+        return Ok(None);
+    };
+    let window = f64::from(calendar::WINDOW_DAYS) * 86400.0;
+    let span = f64::from(calendar::SPAN_DAYS) * 86400.0;
+    let from = bound(filter.from.as_ref(), -window)?;
+    let to = bound(filter.to.as_ref(), window)?;
+
+    let mut calendars = Vec::new();
+    for c in unsafe { store.calendarsForEntityType(EKEntityType::Event) }.to_vec() {
+        let name = unsafe { c.title() }.to_string();
+        if !filter.calendars.is_empty() && !filter.calendars.contains(&name) {
+            continue;
+        }
+        if filter.excluded_calendars.contains(&name) {
+            continue;
+        }
+        // EKCalendarTypeBirthday and EKCalendarTypeSubscription:
+        let kind = unsafe { c.r#type() }.0;
+        if !filter.birthdays && kind == 4 {
+            continue;
+        }
+        if !filter.subscribed && (kind == 3 || unsafe { c.isSubscribed() }) {
+            continue;
+        }
+        calendars.push(c);
+    }
+    let mut output = json!({
+        "from": date(&from),
+        "to": date(&to),
+        "calendars": calendars.iter().map(|c| calendar_record(c)).collect::<Vec<_>>(),
+    });
+    let (mut events, mut masters) = (Vec::new(), Vec::new());
+    // Events overlapping two spans come up in both:
+    let mut seen = BTreeSet::new();
+    let selected = NSArray::from_retained_slice(&calendars);
+    let mut start = from.timeIntervalSince1970();
+    // EventKit takes no calendars at all to mean every calendar:
+    while !calendars.is_empty() && start < to.timeIntervalSince1970() {
+        if interrupted() {
+            return Ok(None);
+        }
+        let end = (start + span).min(to.timeIntervalSince1970());
+        let predicate = unsafe {
+            store.predicateForEventsWithStartDate_endDate_calendars(
+                &NSDate::dateWithTimeIntervalSince1970(start),
+                &NSDate::dateWithTimeIntervalSince1970(end),
+                Some(&selected),
+            )
+        };
+        for event in unsafe { store.eventsMatchingPredicate(&predicate) }.to_vec() {
+            let e = record(&event);
+            let id = e["id"].as_str().unwrap_or_default().to_string();
+            if !seen.insert(format!("{id}/{}", e["occurrence"])) {
+                continue;
+            }
+            let recurs = !e["occurrence"].is_null();
+            events.push(e);
+            if recurs && seen.insert(id.clone()) {
+                let item = unsafe { store.calendarItemWithIdentifier(&NSString::from_str(&id)) };
+                let Some(Ok(m)) = item.map(|item| item.downcast::<EKEvent>()) else {
+                    continue;
+                };
+                let mut master = record(&m);
+                master["occurrence"] = Value::Null;
+                master["detached"] = false.into();
+                master["recurrence"] = recurrence(&m);
+                masters.push(master);
+            }
+        }
+        start = end;
+    }
+    output["events"] = events.into();
+    output["masters"] = masters.into();
+    Ok(Some(output))
+}
+
+pub fn reminders(
+    filter: &reminders::Filter,
+    interrupted: impl Fn() -> bool,
+) -> Result<Option<Value>> {
+    let Some(store) = open(Entity::Reminder, reminders::APP, &interrupted)? else {
+        return Ok(None);
+    };
+    let completed = match filter.overdue {
+        true => Some(false),
+        false => filter.completed,
+    };
+    let calendars: Vec<Retained<EKCalendar>> =
+        unsafe { store.calendarsForEntityType(EKEntityType::Reminder) }
+            .to_vec()
+            .into_iter()
+            .filter(|c| {
+                filter.lists.is_empty() || filter.lists.contains(&unsafe { c.title() }.to_string())
+            })
+            .collect();
+    let selected = NSArray::from_retained_slice(&calendars);
+    let optional = |time: Option<&LocalTime>| time.map(|time| bound(Some(time), 0.0)).transpose();
+    let predicate = unsafe {
+        match completed {
+            Some(true) => store
+                .predicateForCompletedRemindersWithCompletionDateStarting_ending_calendars(
+                    None,
+                    None,
+                    Some(&selected),
+                ),
+            Some(false) => {
+                let after = match filter.keep_recurring {
+                    true => None,
+                    false => optional(filter.due_after.as_ref())?,
+                };
+                let before = optional(filter.due_before.as_ref())?;
+                store.predicateForIncompleteRemindersWithDueDateStarting_ending_calendars(
+                    after.as_deref(),
+                    before.as_deref(),
+                    Some(&selected),
+                )
+            }
+            None => store.predicateForRemindersInCalendars(Some(&selected)),
+        }
+    };
+
+    // EventKit takes no lists at all to mean every list:
+    let mut fetched = Vec::new();
+    if !calendars.is_empty() {
+        let (sender, answers) = mpsc::channel();
+        let completion = RcBlock::new(move |reminders: *mut NSArray<EKReminder>| {
+            sender.send(unsafe { Retained::retain(reminders) }).ok();
+        });
+        unsafe { store.fetchRemindersMatchingPredicate_completion(&predicate, &completion) };
+        let Some(found) = wait(&answers, &interrupted) else {
+            return Ok(None);
+        };
+        fetched = found.map(|found| found.to_vec()).unwrap_or_default();
+    }
+
+    let now = date(&NSDate::now());
+    let wanted = |r: &Value| {
+        let due = r["due"].as_str();
+        let completed_now = r["completed"].as_bool() == Some(true);
+        if completed.is_some_and(|completed| completed != completed_now) {
+            return false;
+        }
+        if filter.overdue {
+            let Some(due) = due.filter(|_| !completed_now) else {
+                return false;
+            };
+            let overdue = match due.len() > 10 {
+                true => due < now.as_str(),
+                false => due < &now[..10],
+            };
+            if !overdue {
+                return false;
+            }
+        }
+        if filter.due_after.is_none() && filter.due_before.is_none() {
+            return true;
+        }
+        let Some(due) = due else {
+            return false;
+        };
+        let due = match due.len() > 10 {
+            true => due.to_string(),
+            false => format!("{due}T00:00:00"),
+        };
+        let recurs = filter.keep_recurring && !r["recurrence"].is_null();
+        filter
+            .due_after
+            .as_ref()
+            .is_none_or(|after| recurs || due.as_str() >= after.as_str())
+            && filter
+                .due_before
+                .as_ref()
+                .is_none_or(|before| due.as_str() < before.as_str())
+    };
+    let found: Vec<Value> = fetched
+        .iter()
+        .map(|r| reminder_record(r))
+        .filter(wanted)
+        .collect();
+    Ok(Some(json!({
+        "lists": calendars.iter().map(|c| list_record(c)).collect::<Vec<_>>(),
+        "reminders": found,
+    })))
+}
+
+/// Opens the event store once it has been granted full access to items
+/// of `entity`, asking for it as the scripts' prelude does, or `None` if
+/// interrupted first.
+fn open(
+    entity: Entity,
+    app: &'static str,
+    interrupted: &impl Fn() -> bool,
+) -> Result<Option<Retained<EKEventStore>>> {
+    let store = unsafe { EKEventStore::new() };
+    let (sender, answers) = mpsc::channel();
+    let answer = RcBlock::new(move |granted: Bool, _: *mut NSError| {
+        sender.send(granted.as_bool()).ok();
+    });
+    let answer = RcBlock::as_ptr(&answer);
+    unsafe {
+        match entity {
+            Entity::Event
+                if store.respondsToSelector(sel!(requestFullAccessToEventsWithCompletion:)) =>
+            {
+                store.requestFullAccessToEventsWithCompletion(answer);
+            }
+            Entity::Reminder
+                if store.respondsToSelector(sel!(requestFullAccessToRemindersWithCompletion:)) =>
+            {
+                store.requestFullAccessToRemindersWithCompletion(answer);
+            }
+            Entity::Event => {
+                #[allow(deprecated)]
+                store.requestAccessToEntityType_completion(EKEntityType::Event, answer);
+            }
+            Entity::Reminder => {
+                #[allow(deprecated)]
+                store.requestAccessToEntityType_completion(EKEntityType::Reminder, answer);
+            }
+        }
+    }
+    match wait(&answers, interrupted) {
+        None => Ok(None),
+        Some(true) => Ok(Some(store)),
+        Some(false) => Err(Error::PermissionDenied {
+            app,
+            stderr: format!(
+                "Not authorized to access {}. (-1743)",
+                entity.plural().to_lowercase()
+            ),
+        }),
+    }
+}
+
+/// Turns the run loop until an answer comes, for the EventKit callbacks
+/// that are sent to it, or returns `None` if interrupted first. A callback
+/// dropped unanswered answers with the default.
+fn wait<T: Default>(answers: &mpsc::Receiver<T>, interrupted: &impl Fn() -> bool) -> Option<T> {
+    loop {
+        NSRunLoop::currentRunLoop().runUntilDate(&NSDate::now());
+        match answers.recv_timeout(POLL) {
+            Ok(answer) => return Some(answer),
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Some(T::default()),
+            Err(mpsc::RecvTimeoutError::Timeout) if interrupted() => return None,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+        }
+    }
+}
+
+/// Returns the local time `time` as a date, or, without one, the date
+/// `offset` seconds from now.
+fn bound(time: Option<&LocalTime>, offset: f64) -> Result<Retained<NSDate>> {
+    let Some(time) = time else {
+        return Ok(NSDate::dateWithTimeIntervalSinceNow(offset));
+    };
+    let seconds = TimeZone::local()
+        .to_timestamp(time.as_str())
+        .ok_or_else(|| Error::Usage {
+            message: format!("{time} isn't a time that exists here"),
+        })?;
+    Ok(NSDate::dateWithTimeIntervalSince1970(seconds as f64))
+}
+
+/// Formats `value` as ISO 8601 time in `zone`, with its offset if `offset`.
+fn format_in(value: &NSDate, zone: &NSTimeZone, offset: bool) -> String {
+    let shift = zone.secondsFromGMTForDate(value) as i64;
+    let mut time = timezone::iso8601(value.timeIntervalSince1970().floor() as i64 + shift);
+    time.pop();
+    if offset {
+        let sign = if shift < 0 { '-' } else { '+' };
+        time += &format!(
+            "{sign}{:02}:{:02}",
+            shift.abs() / 3600,
+            shift.abs() / 60 % 60
+        );
+    }
+    time
+}
+
+fn date(value: &NSDate) -> String {
+    format_in(value, &NSTimeZone::defaultTimeZone(), false)
+}
+
+fn zoned(value: &NSDate, zone: Option<&NSTimeZone>) -> String {
+    match zone {
+        Some(zone) => format_in(value, zone, true),
+        None => date(value),
+    }
+}
+
+fn wall_clock(value: &NSDate, zone: Option<&NSTimeZone>, all_day: bool) -> String {
+    let mut time = zoned(value, zone);
+    time.truncate(if all_day { 10 } else { 19 });
+    time
+}
+
+fn account(c: &EKCalendar) -> String {
+    unsafe { c.source() }
+        .map(|source| unsafe { source.title() }.to_string())
+        .unwrap_or_default()
+}
+
+fn color(c: &EKCalendar) -> Option<String> {
+    let rgb = unsafe { c.color() }.colorUsingColorSpace(&NSColorSpace::sRGBColorSpace())?;
+    let channel = |x: f64| (x * 255.0).round().clamp(0.0, 255.0) as u8;
+    Some(format!(
+        "#{:02X}{:02X}{:02X}",
+        channel(rgb.redComponent()),
+        channel(rgb.greenComponent()),
+        channel(rgb.blueComponent())
+    ))
+}
+
+fn calendar_record(c: &EKCalendar) -> Value {
+    unsafe {
+        json!({
+            "id": c.calendarIdentifier().to_string(),
+            "name": c.title().to_string(),
+            "color": color(c),
+            "account": account(c),
+            "type": c.r#type().0,
+            "readOnly": !c.allowsContentModifications(),
+        })
+    }
+}
+
+fn list_record(c: &EKCalendar) -> Value {
+    unsafe {
+        json!({
+            "id": c.calendarIdentifier().to_string(),
+            "name": c.title().to_string(),
+            "color": color(c),
+            "account": account(c),
+        })
+    }
+}
+
+fn person(p: &EKParticipant) -> Value {
+    unsafe {
+        let url = p.URL().absoluteString().map(|url| url.to_string());
+        let email = url
+            .as_deref()
+            .and_then(|url| url.strip_prefix("mailto:"))
+            .map(|email| {
+                percent_encoding::percent_decode_str(email)
+                    .decode_utf8_lossy()
+                    .into_owned()
+            });
+        json!({
+            "name": p.name().map(|name| name.to_string()),
+            "email": email,
+            "status": p.participantStatus().0,
+            "role": p.participantRole().0,
+        })
+    }
+}
+
+fn alarms(e: &EKEvent, zone: Option<&NSTimeZone>) -> Vec<Value> {
+    unsafe { e.alarms() }
+        .map(|alarms| alarms.to_vec())
+        .unwrap_or_default()
+        .iter()
+        .map(|a| unsafe {
+            match a.absoluteDate() {
+                None => json!({ "offset": a.relativeOffset(), "type": a.r#type().0 }),
+                Some(time) => json!({ "time": zoned(&time, zone), "type": a.r#type().0 }),
+            }
+        })
+        .collect()
+}
+
+/// Returns where `p` is, with its coordinates, if it has any, and radius.
+fn place(p: &EKStructuredLocation) -> serde_json::Map<String, Value> {
+    unsafe {
+        let point = p.geoLocation().map(|location| location.coordinate());
+        let radius = p.radius();
+        let mut place = serde_json::Map::new();
+        place.insert(
+            "name".into(),
+            p.title().map(|title| title.to_string()).into(),
+        );
+        place.insert("latitude".into(), point.map(|point| point.latitude).into());
+        place.insert(
+            "longitude".into(),
+            point.map(|point| point.longitude).into(),
+        );
+        place.insert("radius".into(), (radius > 0.0).then_some(radius).into());
+        place
+    }
+}
+
+fn record(e: &EKEvent) -> Value {
+    unsafe {
+        let zone = e.timeZone();
+        let zone = zone.as_deref();
+        let all_day = e.isAllDay();
+        let day = |value: &NSDate| {
+            let mut time = date(value);
+            time.truncate(10);
+            time
+        };
+        let occurrence = match e.hasRecurrenceRules() {
+            true => e
+                .occurrenceDate()
+                .map(|time| wall_clock(&time, zone, all_day)),
+            false => None,
+        };
+        let (start, end) = (e.startDate(), e.endDate());
+        let calendar = e.calendar();
+        json!({
+            "id": e.calendarItemIdentifier().to_string(),
+            "occurrence": occurrence,
+            "detached": e.isDetached(),
+            "title": e.title().to_string(),
+            "notes": e.notes().map(|notes| notes.to_string()),
+            "url": e.URL().and_then(|url| url.absoluteString()).map(|url| url.to_string()),
+            "location": e.location().map(|location| location.to_string()),
+            "place": e.structuredLocation().map(|p| place(&p)),
+            "allDay": all_day,
+            "start": if all_day { day(&start) } else { zoned(&start, zone) },
+            "end": if all_day { day(&end) } else { zoned(&end, zone) },
+            "timeZone": zone.map(|zone| zone.name().to_string()),
+            "status": e.status().0,
+            "free": e.availability().0 == 1,
+            "organizer": e.organizer().map(|organizer| person(&organizer)),
+            "attendees": e
+                .attendees()
+                .map(|attendees| attendees.iter().map(|p| person(&p)).collect::<Vec<_>>())
+                .unwrap_or_default(),
+            "alarms": alarms(e, zone),
+            "calendar": calendar.as_ref().map(|c| c.title().to_string()).unwrap_or_default(),
+            "account": calendar.as_deref().map(account).unwrap_or_default(),
+            "created": e.creationDate().map(|time| date(&time)),
+            "modified": e.lastModifiedDate().map(|time| date(&time)),
+        })
+    }
+}
+
+/// Describes the first recurrence rule of `item` as
+/// [`recurrence::SCRIPT`](crate::recurrence::SCRIPT) does.
+fn recurrence(item: &EKCalendarItem) -> Value {
+    unsafe {
+        let Some(rule) = item
+            .recurrenceRules()
+            .filter(|_| item.hasRecurrenceRules())
+            .and_then(|rules| rules.firstObject())
+        else {
+            return Value::Null;
+        };
+        let numbers = |list: Option<Retained<NSArray<NSNumber>>>| -> Vec<isize> {
+            list.map(|list| list.iter().map(|n| n.integerValue()).collect())
+                .unwrap_or_default()
+        };
+        let weekdays: Vec<Value> = rule
+            .daysOfTheWeek()
+            .map(|days| days.to_vec())
+            .unwrap_or_default()
+            .iter()
+            .map(|day| json!({ "day": day.dayOfTheWeek().0, "week": day.weekNumber() }))
+            .collect();
+        let end = rule.recurrenceEnd();
+        json!({
+            "frequency": rule.frequency().0,
+            "interval": rule.interval(),
+            "firstDayOfTheWeek": rule.firstDayOfTheWeek(),
+            "daysOfTheWeek": weekdays,
+            "daysOfTheMonth": numbers(rule.daysOfTheMonth()),
+            "monthsOfTheYear": numbers(rule.monthsOfTheYear()),
+            "setPositions": numbers(rule.setPositions()),
+            "until": end.as_ref().and_then(|end| end.endDate()).map(|time| date(&time)),
+            "count": end.as_ref().map_or(0, |end| end.occurrenceCount()),
+        })
+    }
+}
+
+fn due(components: &NSDateComponents) -> Option<String> {
+    let day = NSCalendar::currentCalendar().dateFromComponents(components)?;
+    let mut time = date(&day);
+    if !(0..24).contains(&components.hour()) {
+        time.truncate(10);
+    }
+    Some(time)
+}
+
+fn geo_trigger(r: &EKReminder) -> Value {
+    unsafe {
+        for alarm in r.alarms().map(|alarms| alarms.to_vec()).unwrap_or_default() {
+            let proximity = alarm.proximity();
+            let Some(location) = alarm.structuredLocation() else {
+                continue;
+            };
+            if proximity == EKAlarmProximity::None {
+                continue;
+            }
+            let mut trigger = place(&location);
+            let proximity = match proximity {
+                EKAlarmProximity::Leave => "leave",
+                _ => "arrive",
+            };
+            trigger.insert("proximity".into(), proximity.into());
+            return trigger.into();
+        }
+        Value::Null
+    }
+}
+
+fn reminder_record(r: &EKReminder) -> Value {
+    unsafe {
+        let calendar = r.calendar();
+        json!({
+            "id": r.calendarItemIdentifier().to_string(),
+            "title": r.title().to_string(),
+            "notes": r.notes().map(|notes| notes.to_string()),
+            "url": r.URL().and_then(|url| url.absoluteString()).map(|url| url.to_string()),
+            "due": r.dueDateComponents().and_then(|components| due(&components)),
+            "completed": r.isCompleted(),
+            "completionDate": r.completionDate().map(|time| date(&time)),
+            "priority": r.priority(),
+            "list": calendar.as_ref().map(|c| c.title().to_string()).unwrap_or_default(),
+            "account": calendar.as_deref().map(account).unwrap_or_default(),
+            "created": r.creationDate().map(|time| date(&time)),
+            "modified": r.lastModifiedDate().map(|time| date(&time)),
+            "recurrence": recurrence(r),
+            "geoTrigger": geo_trigger(r),
+        })
+    }
+}
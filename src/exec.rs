@@ -0,0 +1,66 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Runs the external programs (`sqlite3`, `curl`, `oxigraph`, …) some
+//! sinks and exporters delegate to.
+
+use crate::{Error, Result};
+use std::{
+    ffi::OsStr,
+    io::Write,
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+    string::String,
+    thread,
+    vec::Vec,
+};
+
+/// Runs `program` with `args`, feeding it `input` on stdin, and returns
+/// its stdout. A non-zero exit status becomes [`Error::CommandFailed`].
+pub fn run<I, S>(program: &'static str, args: I, input: &[u8]) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    // In a process group of its own, so that Ctrl-C is ours to handle,
+    // rather than cutting it off midway:
+    let mut child = Command::new(program)
+        .args(args)
+        .process_group(0)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Io {
+            context: "invoking an external program",
+            source: e,
+        })?;
+
+    // Write on a separate thread so that a chatty child can't deadlock us
+    // by filling its stdout pipe while we're still writing its stdin:
+    let mut stdin = child.stdin.take();
+    let input = input.to_vec();
+    let writer = thread::spawn(move || match stdin.as_mut() {
+        Some(stdin) => stdin.write_all(&input),
+        None => Ok(()),
+    });
+
+    let output = child.wait_with_output().map_err(|e| Error::Io {
+        context: "waiting for an external program",
+        source: e,
+    })?;
+    let written = writer.join().unwrap_or(Ok(()));
+
+    if !output.status.success() {
+        return Err(Error::CommandFailed {
+            program,
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    written.map_err(|e| Error::Io {
+        context: "writing to an external program",
+        source: e,
+    })?;
+
+    Ok(output.stdout)
+}
@@ -0,0 +1,527 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Exports notes as a single zip archive, for backups and for handing off
+//! to other tools: a Markdown or HTML file per note, the images it embeds,
+//! and a `manifest.json` listing every note's URN, dates, and the SHA-256
+//! of each file, against which [`verify`] later checks the archive.
+
+use super::{ExportSummary, UniqueNames, file_name, html, markdown, sha256_hex, url_path, write};
+use crate::{Error, Result, markup::Document, notes::Note};
+use core::str::FromStr;
+use serde_json::{Value, json};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    format, fs, io,
+    path::Path,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The name of the manifest within the archive.
+pub const MANIFEST: &str = "manifest.json";
+
+/// What each note becomes within the archive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Markdown,
+    Html,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::Markdown => "markdown",
+            Format::Html => "html",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Html => "html",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        match input {
+            "markdown" | "md" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            _ => Err(format!("unsupported archive format: {input}")),
+        }
+    }
+}
+
+/// Writes every note into the zip file at `file`, replacing it if it
+/// exists.
+pub fn export(notes: &[Note], file: &Path, format: Format) -> Result<ExportSummary> {
+    let mut names = UniqueNames::default();
+    let stems: Vec<String> = notes
+        .iter()
+        .map(|note| names.claim(&file_name(&note.name)))
+        .collect();
+    let mut links: BTreeMap<&str, String> = BTreeMap::new();
+    for (note, stem) in notes.iter().zip(&stems) {
+        links
+            .entry(note.name.trim())
+            .or_insert_with(|| format!("{}.{}", url_path(stem), format.extension()));
+    }
+
+    let mut zip = ZipWriter::default();
+    let mut entries = Vec::new();
+    let mut summary = ExportSummary::default();
+
+    for (note, stem) in notes.iter().zip(&stems) {
+        let document = Document::parse(&note.body_html);
+        let mut images = Vec::new();
+        let mut attachments = Vec::new();
+        for (n, image) in document.images.iter().enumerate() {
+            let name = format!("{stem}/{}.{}", n + 1, image.extension());
+            let path = format!("notes/{name}");
+            zip.add(&path, &image.data, &note.modified);
+            attachments.push(json!({
+                "path": path,
+                "mimeType": image.mime,
+                "size": image.data.len(),
+                "sha256": sha256_hex(&image.data),
+            }));
+            images.push(url_path(&name));
+            summary.attachments += 1;
+        }
+
+        let contents = match format {
+            Format::Markdown => {
+                let style = MarkdownStyle {
+                    links: &links,
+                    images: &images,
+                };
+                format!(
+                    "# {}\n\n{}",
+                    note.name.trim(),
+                    markdown::render(&document, Some(&note.name), &style)
+                )
+            }
+            Format::Html => {
+                let renderer = html::Renderer {
+                    document: &document,
+                    links: &links,
+                    images: &images,
+                };
+                let title = super::escape_xml(note.name.trim());
+                format!(
+                    "{}<h1>{title}</h1>\n{}</body>\n</html>\n",
+                    html::page_head(&title, "../"),
+                    renderer.render(&note.name)
+                )
+            }
+        };
+        let path = format!("notes/{stem}.{}", format.extension());
+        zip.add(&path, contents.as_bytes(), &note.modified);
+        entries.push(json!({
+            "@id": note.urn(),
+            "name": note.name,
+            "dateCreated": note.created,
+            "dateModified": note.modified,
+            "isPartOf": note.folder,
+            "account": note.account,
+            "sourceSha256": sha256_hex(note.body_html.as_bytes()),
+            "path": path,
+            "size": contents.len(),
+            "sha256": sha256_hex(contents.as_bytes()),
+            "attachments": attachments,
+        }));
+        summary.notes += 1;
+    }
+
+    // The archive is dated by its newest note, so that exporting an
+    // unchanged library again yields the very same bytes:
+    let newest = notes
+        .iter()
+        .map(|note| note.modified.as_str())
+        .max()
+        .unwrap_or_default();
+    if format == Format::Html {
+        zip.add("style.css", html::STYLE.as_bytes(), newest);
+    }
+    let manifest = json!({
+        "generator": format!("asimov-apple {}", env!("CARGO_PKG_VERSION")),
+        "format": format.name(),
+        "notes": entries,
+    });
+    let manifest = serde_json::to_vec_pretty(&manifest).map_err(|e| Error::Json {
+        context: "writing an archive manifest",
+        source: e,
+    })?;
+    zip.add(MANIFEST, &manifest, newest);
+
+    write(file, zip.finish()?)?;
+    Ok(summary)
+}
+
+/// What checking an archive against the library found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Verification {
+    /// The number of notes the archive holds.
+    pub notes: usize,
+    /// A line per problem, such as a corrupt file or a note changed since.
+    pub problems: Vec<String>,
+}
+
+/// Checks the archive at `file` against the manifest it holds, and that
+/// against `notes`: that every file is intact, and that every note is in
+/// the archive, unchanged since.
+pub fn verify(notes: &[Note], file: &Path) -> Result<Verification> {
+    let data = fs::read(file).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => Error::NotFound {
+            what: format!("archive {}", file.display()),
+        },
+        _ => Error::Io {
+            context: "reading an export archive",
+            source: e,
+        },
+    })?;
+    let files = read_zip(&data)?;
+    let manifest: Value = files
+        .get(MANIFEST)
+        .ok_or_else(|| invalid(format!("no {MANIFEST} in the archive")))
+        .and_then(|manifest| {
+            serde_json::from_slice(manifest).map_err(|e| invalid(e.to_string()))
+        })?;
+    let entries = manifest
+        .get("notes")
+        .and_then(Value::as_array)
+        .ok_or_else(|| invalid(format!("{MANIFEST} lists no notes")))?;
+
+    let mut verification = Verification {
+        notes: entries.len(),
+        problems: Vec::new(),
+    };
+    let problems = &mut verification.problems;
+    let mut archived: BTreeMap<&str, &Value> = BTreeMap::new();
+    for entry in entries {
+        let field = |key: &str| entry.get(key).and_then(Value::as_str).unwrap_or_default();
+        archived.insert(field("@id"), entry);
+        let attachments = entry.get("attachments").and_then(Value::as_array);
+        for item in core::iter::once(entry).chain(attachments.into_iter().flatten()) {
+            let path = item.get("path").and_then(Value::as_str).unwrap_or_default();
+            let hash = item.get("sha256").and_then(Value::as_str);
+            match files.get(path) {
+                None => problems.push(format!("missing from the archive: {path}")),
+                Some(data) if hash != Some(sha256_hex(data).as_str()) => {
+                    problems.push(format!("corrupt: {path}"));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    let mut live = BTreeSet::new();
+    for note in notes {
+        let urn = note.urn();
+        match archived.get(urn.as_str()) {
+            None => problems.push(format!("not archived: {} ({urn})", note.name.trim())),
+            Some(entry) => {
+                let hash = entry.get("sourceSha256").and_then(Value::as_str);
+                if hash != Some(sha256_hex(note.body_html.as_bytes()).as_str()) {
+                    problems.push(format!("changed since: {} ({urn})", note.name.trim()));
+                }
+            }
+        }
+        live.insert(urn);
+    }
+    for (urn, entry) in &archived {
+        if !live.contains(*urn) {
+            let name = entry
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            problems.push(format!("deleted from the library: {} ({urn})", name.trim()));
+        }
+    }
+    Ok(verification)
+}
+
+fn invalid(message: String) -> Error {
+    Error::Parse {
+        context: "reading an export archive",
+        message,
+    }
+}
+
+struct MarkdownStyle<'a> {
+    links: &'a BTreeMap<&'a str, String>,
+    images: &'a [String],
+}
+
+impl markdown::Style for MarkdownStyle<'_> {
+    fn note_link(&self, title: &str) -> String {
+        let text = markdown::escape(title);
+        match self.links.get(title) {
+            Some(link) => format!("[{text}]({link})"),
+            None => text,
+        }
+    }
+
+    fn image(&self, index: usize) -> String {
+        match self.images.get(index) {
+            Some(url) => format!("![]({url})"),
+            None => String::new(),
+        }
+    }
+}
+
+/// A zip archive of uncompressed ("stored") files, built in memory. Notes
+/// compress well, but their images, the bulk of most archives, don't.
+#[derive(Default)]
+struct ZipWriter {
+    data: Vec<u8>,
+    directory: Vec<u8>,
+    entries: usize,
+}
+
+impl ZipWriter {
+    /// Appends a file, dated by a local `YYYY-MM-DDTHH:MM:SS` time.
+    fn add(&mut self, name: &str, contents: &[u8], modified: &str) {
+        let (time, date) = dos_time(modified);
+        let crc = crc32(contents);
+        let offset = self.data.len();
+        // Version 2.0, with bit 11 flagging UTF-8 names, and method 0:
+        let common = |output: &mut Vec<u8>| {
+            for value in [20u16, 0x0800, 0, time, date] {
+                output.extend_from_slice(&value.to_le_bytes());
+            }
+            for value in [crc, contents.len() as u32, contents.len() as u32] {
+                output.extend_from_slice(&value.to_le_bytes());
+            }
+            output.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            output.extend_from_slice(&0u16.to_le_bytes());
+        };
+
+        self.data.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        common(&mut self.data);
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(contents);
+
+        self.directory
+            .extend_from_slice(&0x02014b50u32.to_le_bytes());
+        self.directory.extend_from_slice(&20u16.to_le_bytes());
+        common(&mut self.directory);
+        // No comment, disk 0, no attributes, then the local header's offset:
+        for value in [0u16, 0, 0, 0, 0] {
+            self.directory.extend_from_slice(&value.to_le_bytes());
+        }
+        self.directory
+            .extend_from_slice(&(offset as u32).to_le_bytes());
+        self.directory.extend_from_slice(name.as_bytes());
+        self.entries += 1;
+    }
+
+    /// Ends the archive with its central directory.
+    fn finish(mut self) -> Result<Vec<u8>> {
+        // Without ZIP64 extensions, offsets, sizes, and counts must fit
+        // in 32 or 16 bits:
+        if self.data.len() + self.directory.len() > u32::MAX as usize
+            || self.entries > usize::from(u16::MAX)
+        {
+            return Err(Error::Usage {
+                message: "too much to archive in one zip file; export a smaller selection"
+                    .to_string(),
+            });
+        }
+        let offset = self.data.len() as u32;
+        let size = self.directory.len() as u32;
+        self.data.append(&mut self.directory);
+        self.data.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        for value in [0u16, 0, self.entries as u16, self.entries as u16] {
+            self.data.extend_from_slice(&value.to_le_bytes());
+        }
+        self.data.extend_from_slice(&size.to_le_bytes());
+        self.data.extend_from_slice(&offset.to_le_bytes());
+        self.data.extend_from_slice(&0u16.to_le_bytes());
+        Ok(self.data)
+    }
+}
+
+/// Reads the files of a zip archive of stored files, as [`ZipWriter`]
+/// writes, by its central directory. Their checksums are left to the
+/// manifest's hashes.
+fn read_zip(data: &[u8]) -> Result<BTreeMap<String, &[u8]>> {
+    let u16_at = |offset: usize| {
+        data.get(offset..offset + 2)
+            .map(|bytes| usize::from(u16::from_le_bytes([bytes[0], bytes[1]])))
+            .ok_or_else(|| invalid("truncated zip file".to_string()))
+    };
+    let u32_at = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+            .ok_or_else(|| invalid("truncated zip file".to_string()))
+    };
+
+    // The end of central directory record, 22 bytes before any comment:
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .take(u16::MAX as usize + 22)
+        .find(|&offset| data[offset..].starts_with(&0x06054b50u32.to_le_bytes()))
+        .ok_or_else(|| invalid("not a zip file".to_string()))?;
+    let count = u16_at(end + 10)?;
+    let mut offset = u32_at(end + 16)?;
+
+    let mut files = BTreeMap::new();
+    for _ in 0..count {
+        if u32_at(offset)? != 0x02014b50 {
+            return Err(invalid("corrupt zip central directory".to_string()));
+        }
+        let method = u16_at(offset + 10)?;
+        let size = u32_at(offset + 20)?;
+        let name_len = u16_at(offset + 28)?;
+        let skip = name_len + u16_at(offset + 30)? + u16_at(offset + 32)?;
+        let local = u32_at(offset + 42)?;
+        let name = data
+            .get(offset + 46..offset + 46 + name_len)
+            .ok_or_else(|| invalid("truncated zip file".to_string()))?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        offset += 46 + skip;
+
+        if method != 0 {
+            return Err(invalid(format!(
+                "{name} is compressed; only archives written by `export archive` can be verified"
+            )));
+        }
+        let start = local + 30 + u16_at(local + 26)? + u16_at(local + 28)?;
+        let contents = data
+            .get(start..start + size)
+            .ok_or_else(|| invalid("truncated zip file".to_string()))?;
+        files.insert(name, contents);
+    }
+    Ok(files)
+}
+
+/// Returns the CRC-32 (as in zip and gzip) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Converts a local `YYYY-MM-DDTHH:MM:SS` time into the MS-DOS time and
+/// date zip files record, which can't go before 1980.
+fn dos_time(local: &str) -> (u16, u16) {
+    let field = |range: core::ops::Range<usize>| {
+        local
+            .get(range)
+            .and_then(|field| field.parse::<u16>().ok())
+            .unwrap_or_default()
+    };
+    let year = field(0..4);
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let time = (field(11..13) << 11) | (field(14..16) << 5) | (field(17..19) / 2);
+    let date = ((year - 1980).min(127) << 9) | (field(5..7) << 5) | field(8..10);
+    (time, date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn read_zip_reads_what_zip_writer_writes() {
+        let mut zip = ZipWriter::default();
+        zip.add(
+            "notes/Groceries.md",
+            b"# Groceries\n\n- milk\n",
+            "2025-01-20T13:30:00",
+        );
+        zip.add(
+            "notes/Café/1.png",
+            &[0x89, b'P', b'N', b'G', 0, 0xff],
+            "1970-01-01T00:00:00",
+        );
+        zip.add("empty.txt", b"", "");
+        let data = zip.finish().unwrap();
+
+        let files = read_zip(&data).unwrap();
+        assert_eq!(
+            files.keys().map(String::as_str).collect::<Vec<_>>(),
+            ["empty.txt", "notes/Café/1.png", "notes/Groceries.md"]
+        );
+        assert_eq!(files["notes/Groceries.md"], b"# Groceries\n\n- milk\n");
+        assert_eq!(files["notes/Café/1.png"], [0x89, b'P', b'N', b'G', 0, 0xff]);
+        assert_eq!(files["empty.txt"], b"");
+    }
+
+    #[test]
+    fn manifest_hashes_match_the_archived_files() {
+        let note = |id: &str, name: &str, body: &str| Note {
+            id: format!("x-coredata://1/ICNote/{id}"),
+            name: name.to_string(),
+            body_html: body.to_string(),
+            created: "2025-01-20T13:30:00".to_string(),
+            modified: "2025-01-21T09:00:00".to_string(),
+            folder: "Notes".to_string(),
+            account: "iCloud".to_string(),
+        };
+        let mut notes = [
+            note("p1", "Groceries", "<div>milk</div>"),
+            note(
+                "p2",
+                "Photo",
+                "<div><img src=\"data:image/png;base64,iVBORw0KGgo=\"></div>",
+            ),
+        ];
+        let file = std::env::temp_dir().join(format!(
+            "asimov-apple-archive-test-{}.zip",
+            std::process::id()
+        ));
+        let summary = export(&notes, &file, Format::Markdown).unwrap();
+        let data = fs::read(&file).unwrap();
+        assert_eq!((summary.notes, summary.attachments), (2, 1));
+
+        let files = read_zip(&data).unwrap();
+        let manifest: Value = serde_json::from_slice(files[MANIFEST]).unwrap();
+        let entries = manifest["notes"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        let mut listed = 1;
+        for entry in entries {
+            let attachments = entry["attachments"].as_array().unwrap();
+            for item in core::iter::once(entry).chain(attachments) {
+                let path = item["path"].as_str().unwrap();
+                assert_eq!(item["sha256"], sha256_hex(files[path]), "{path}");
+                listed += 1;
+            }
+        }
+        assert_eq!(listed, files.len());
+        assert_eq!(
+            verify(&notes, &file).unwrap().problems,
+            Vec::<String>::new()
+        );
+
+        notes[0].body_html = "<div>oat milk</div>".to_string();
+        let problems = verify(&notes, &file).unwrap().problems;
+        fs::remove_file(&file).ok();
+        assert_eq!(
+            problems,
+            ["changed since: Groceries (urn:apple:notes:note:x-coredata://1/ICNote/p1)"]
+        );
+    }
+}
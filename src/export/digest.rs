@@ -0,0 +1,160 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Renders the notes changed since some time into a single HTML email, a
+//! periodic summary to send through Mail.app or append to an mbox file.
+
+use super::{base64, escape_xml as escape, html};
+use crate::{Error, Result, markup::Document, notes::Note, osascript, timezone};
+use std::{
+    collections::BTreeMap,
+    env, format,
+    path::PathBuf,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The app sending digests, as errors name it.
+pub const MAIL_APP: &str = "Mail";
+
+/// Returns where the time the last digest covered notes up to is kept
+/// unless told otherwise:
+/// `~/Library/Application Support/asimov-apple/digest-since`.
+pub fn default_state_path() -> Result<PathBuf> {
+    let home = env::var_os("HOME").ok_or_else(|| Error::Usage {
+        message: "HOME is not set".to_string(),
+    })?;
+    Ok(PathBuf::from(home).join("Library/Application Support/asimov-apple/digest-since"))
+}
+
+/// A digest, ready to send.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Digest {
+    pub subject: String,
+    pub html: String,
+    /// The latest modification time among the notes covered, from which
+    /// the next digest picks up.
+    pub until: String,
+}
+
+/// Returns the notes modified after `since`, a local ISO 8601 time, most
+/// recently modified first.
+pub fn changed_since<'a>(notes: &'a [Note], since: &str) -> Vec<&'a Note> {
+    let mut changed: Vec<&Note> = notes
+        .iter()
+        .filter(|note| note.modified.as_str() > since)
+        .collect();
+    changed.sort_by(|a, b| b.modified.cmp(&a.modified));
+    changed
+}
+
+/// Renders `notes`, changed since `since`, as a digest. Images are left
+/// out, keeping the email small; the digest says how many each note has.
+pub fn render(notes: &[&Note], since: &str) -> Digest {
+    let since = since.replacen('T', " ", 1);
+    let subject = match notes.len() {
+        1 => format!("1 note changed since {since}"),
+        n => format!("{n} notes changed since {since}"),
+    };
+
+    let mut body = format!("<h1>{}</h1>\n", escape(&subject));
+    for note in notes {
+        let document = Document::parse(&note.body_html);
+        let renderer = html::Renderer {
+            document: &document,
+            links: &BTreeMap::new(),
+            images: &[],
+        };
+        body += "<hr>\n<section>\n";
+        body += &format!("<h2>{}</h2>\n", escape(note.name.trim()));
+        body += &format!(
+            "<p class=\"meta\">{} &middot; Modified {}</p>\n",
+            escape(note.folder.trim()),
+            escape(&note.modified.replacen('T', " ", 1)),
+        );
+        body += &renderer.render(&note.name);
+        match document.images.len() {
+            0 => {}
+            1 => body += "<p class=\"meta\">1 image not shown</p>\n",
+            n => body += &format!("<p class=\"meta\">{n} images not shown</p>\n"),
+        }
+        body += "</section>\n";
+    }
+
+    // Mail clients ignore linked stylesheets, so the styles go inline:
+    let html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        escape(&subject),
+        html::STYLE,
+    );
+    let until = notes
+        .iter()
+        .map(|note| note.modified.as_str())
+        .max()
+        .unwrap_or_default()
+        .to_string();
+    Digest {
+        subject,
+        html,
+        until,
+    }
+}
+
+/// Returns the AppleScript sending `digest` to `to` from Mail.app's
+/// default account.
+pub fn mail_script(digest: &Digest, to: &str) -> String {
+    format!(
+        r#"
+        tell application "Mail"
+            set theMessage to make new outgoing message with properties {{subject:{}, visible:false}}
+            tell theMessage to make new to recipient at end of to recipients with properties {{address:{}}}
+            set html content of theMessage to {}
+            send theMessage
+        end tell
+        "#,
+        osascript::quote(&digest.subject),
+        osascript::quote(to),
+        osascript::quote(&digest.html),
+    )
+}
+
+/// Returns `digest` as a message to append to an mbox file, dated by
+/// `timestamp` (in seconds since the epoch). The body is Base64-encoded,
+/// so no line of it can be mistaken for the start of the next message.
+pub fn mbox_message(digest: &Digest, from: &str, to: &str, timestamp: i64) -> String {
+    let utc = timezone::iso8601(timestamp);
+    let field = |range: core::ops::Range<usize>| utc.get(range).unwrap_or_default();
+    let weekday = WEEKDAYS[(timestamp.div_euclid(86400) + 4).rem_euclid(7) as usize];
+    let month = MONTHS[field(5..7).parse::<usize>().unwrap_or(1).clamp(1, 12) - 1];
+    let (year, day, time) = (field(0..4), field(8..10), field(11..19));
+
+    let mut message = format!("From {from} {weekday} {month} {day} {time} {year}\n");
+    message += &format!("From: {from}\n");
+    message += &format!("To: {to}\n");
+    message += &format!("Subject: {}\n", encode_header(&digest.subject));
+    message += &format!("Date: {weekday}, {day} {month} {year} {time} +0000\n");
+    message += "MIME-Version: 1.0\n";
+    message += "Content-Type: text/html; charset=utf-8\n";
+    message += "Content-Transfer-Encoding: base64\n\n";
+    let encoded = base64(digest.html.as_bytes());
+    for line in encoded.as_bytes().chunks(76) {
+        message += &String::from_utf8_lossy(line);
+        message.push('\n');
+    }
+    message.push('\n');
+    message
+}
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Encodes a header value as RFC 2047 requires if it isn't plain ASCII.
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!("=?utf-8?B?{}?=", base64(value.as_bytes()))
+    }
+}
@@ -0,0 +1,111 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Renders the most recently modified notes as an Atom feed (RFC 4287),
+//! for following one's own note activity in a feed reader.
+
+use super::escape_xml as escape;
+use crate::{Result, markup::Document, notes::Note, timezone::TimeZone};
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The feed's stable identity.
+pub const FEED_ID: &str = "urn:asimov:apple:notes:feed";
+
+/// The number of entries in a feed unless asked otherwise.
+pub const DEFAULT_LIMIT: usize = 20;
+
+/// The longest entry summary, in characters.
+const MAX_SUMMARY: usize = 280;
+
+/// Renders the `limit` most recently modified of `notes` as an Atom feed.
+/// Entries link to each note's `apple+notes://` URL and, given the base
+/// URL of the HTTP API in `http_base`, to its record there too.
+pub fn atom(notes: &[Note], limit: usize, http_base: Option<&str>) -> Result<String> {
+    let zone = TimeZone::local();
+    let utc = |local: &str| zone.to_utc(local).unwrap_or_else(|| local.to_string());
+
+    // ISO 8601 timestamps compare correctly as strings:
+    let mut recent: Vec<&Note> = notes.iter().collect();
+    recent.sort_by(|a, b| b.modified.cmp(&a.modified));
+    recent.truncate(limit);
+
+    let updated = recent
+        .first()
+        .map(|note| utc(&note.modified))
+        .unwrap_or_else(crate::provenance::now_iso8601);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml += "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n";
+    xml += &format!("  <id>{FEED_ID}</id>\n");
+    xml += "  <title>Recently modified notes</title>\n";
+    xml += &format!("  <updated>{updated}</updated>\n");
+    xml += "  <author><name>Apple Notes</name></author>\n";
+    xml += &format!(
+        "  <generator uri=\"{}\" version=\"{}\">asimov-apple</generator>\n",
+        env!("CARGO_PKG_REPOSITORY"),
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some(base) = http_base {
+        xml += &format!(
+            "  <link rel=\"self\" type=\"application/atom+xml\" href=\"{}\"/>\n",
+            escape(&format!("{base}/feed"))
+        );
+    }
+
+    for note in recent {
+        xml += "  <entry>\n";
+        xml += &format!("    <id>{}</id>\n", escape(&note.urn()));
+        xml += &format!("    <title>{}</title>\n", escape(note.name.trim()));
+        xml += &format!("    <published>{}</published>\n", utc(&note.created));
+        xml += &format!("    <updated>{}</updated>\n", utc(&note.modified));
+        xml += &format!(
+            "    <link rel=\"alternate\" href=\"{}\"/>\n",
+            escape(&note.url())
+        );
+        if let Some(base) = http_base {
+            let urn = note.urn();
+            let urn =
+                percent_encoding::utf8_percent_encode(&urn, percent_encoding::NON_ALPHANUMERIC);
+            xml += &format!(
+                "    <link rel=\"related\" type=\"application/json\" href=\"{}\"/>\n",
+                escape(&format!("{base}/notes/{urn}"))
+            );
+        }
+        xml += &format!(
+            "    <category term=\"{}\" label=\"{}\"/>\n",
+            escape(&note.folder),
+            escape(&note.folder)
+        );
+        for tag in Document::parse(&note.body_html).tags() {
+            xml += &format!("    <category term=\"#{}\"/>\n", escape(&tag));
+        }
+        let summary = summary(note)?;
+        if !summary.is_empty() {
+            xml += &format!("    <summary>{}</summary>\n", escape(&summary));
+        }
+        xml += "  </entry>\n";
+    }
+
+    xml += "</feed>\n";
+    Ok(xml)
+}
+
+/// Returns the start of a note's text, on one line and without its title.
+fn summary(note: &Note) -> Result<String> {
+    let text = note.text(usize::MAX / 2)?;
+    let text = text.trim_start();
+    let text = text
+        .strip_prefix(note.name.trim())
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .unwrap_or(text);
+    let words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if words.chars().count() <= MAX_SUMMARY {
+        return Ok(words);
+    }
+    let mut summary: String = words.chars().take(MAX_SUMMARY - 1).collect();
+    summary.truncate(summary.trim_end().len());
+    summary.push('…');
+    Ok(summary)
+}
@@ -0,0 +1,205 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Exports notes into a Git repository as Markdown, committing each run, so
+//! that the repository's history is the notes' history. Files are named by
+//! note id rather than title, so renaming a note shows up as an edit of
+//! its file rather than as one file deleted and another added.
+
+use super::{ExportSummary, file_name, markdown, obsidian::front_matter, url_path, write};
+use crate::{Error, Result, exec, markup::Document, notes::Note, provenance};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsStr,
+    format, fs,
+    path::Path,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Who commits when the user hasn't configured Git with a name and email.
+const FALLBACK_IDENTITY: [&str; 4] = [
+    "-c",
+    "user.name=asimov-apple",
+    "-c",
+    "user.email=asimov-apple@localhost",
+];
+
+/// What a run exported and committed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GitExport {
+    pub summary: ExportSummary,
+    pub added: usize,
+    pub changed: usize,
+    pub deleted: usize,
+    /// The new commit, or `None` if nothing changed since the last run.
+    pub commit: Option<String>,
+}
+
+/// Writes every note into the repository at `dir`, creating it if need
+/// be, removes the files of notes since deleted, and commits the result.
+pub fn export(notes: &[Note], dir: &Path) -> Result<GitExport> {
+    // Both directories must exist for Git to take them as pathspecs:
+    for subdir in ["notes", "attachments"] {
+        fs::create_dir_all(dir.join(subdir)).map_err(|e| Error::Io {
+            context: "creating the export repository",
+            source: e,
+        })?;
+    }
+    if !dir.join(".git").exists() {
+        git(dir, &["init", "--quiet"])?;
+    }
+
+    let slugs: Vec<String> = notes.iter().map(slug).collect();
+    let mut links: BTreeMap<&str, String> = BTreeMap::new();
+    for (note, slug) in notes.iter().zip(&slugs) {
+        links
+            .entry(note.name.trim())
+            .or_insert_with(|| format!("{}.md", url_path(slug)));
+    }
+
+    let mut export = GitExport::default();
+    for (note, slug) in notes.iter().zip(&slugs) {
+        let document = Document::parse(&note.body_html);
+        // Images are rewritten whole, so that none linger once removed:
+        let attachment_dir = dir.join("attachments").join(slug);
+        remove(&attachment_dir)?;
+        let mut images = Vec::new();
+        for (n, image) in document.images.iter().enumerate() {
+            let name = format!("{}.{}", n + 1, image.extension());
+            write(&attachment_dir.join(&name), &image.data)?;
+            images.push(format!("../attachments/{}/{name}", url_path(slug)));
+            export.summary.attachments += 1;
+        }
+
+        let style = Style {
+            links: &links,
+            images: &images,
+        };
+        let mut contents = front_matter(note, &document);
+        contents += &format!("\n# {}\n\n", note.name.trim());
+        contents += &markdown::render(&document, Some(&note.name), &style);
+        write(&dir.join("notes").join(format!("{slug}.md")), contents)?;
+        export.summary.notes += 1;
+    }
+    prune(dir, &slugs.iter().map(String::as_str).collect())?;
+
+    git(dir, &["add", "--all", "--", "notes", "attachments"])?;
+    let staged = git(
+        dir,
+        &[
+            "diff",
+            "--cached",
+            "--name-status",
+            "--no-renames",
+            "--",
+            "notes",
+        ],
+    )?;
+    for line in String::from_utf8_lossy(&staged).lines() {
+        match line.split('\t').next() {
+            Some("A") => export.added += 1,
+            Some("D") => export.deleted += 1,
+            Some(_) => export.changed += 1,
+            None => {}
+        }
+    }
+    let unchanged = git(dir, &["diff", "--cached", "--quiet"]).is_ok();
+    if unchanged {
+        return Ok(export);
+    }
+
+    let message = format!(
+        "Export {} notes: {} added, {} changed, {} deleted\n\n\
+         Exported by asimov-apple {} at {}.\n",
+        export.summary.notes,
+        export.added,
+        export.changed,
+        export.deleted,
+        env!("CARGO_PKG_VERSION"),
+        provenance::now_iso8601(),
+    );
+    let mut args: Vec<&str> = Vec::new();
+    if git(dir, &["config", "user.email"]).is_err() {
+        args.extend(FALLBACK_IDENTITY);
+    }
+    args.extend(["commit", "--quiet", "--file", "-"]);
+    git_with_input(dir, &args, message.as_bytes())?;
+    let head = git(dir, &["rev-parse", "--short", "HEAD"])?;
+    export.commit = Some(String::from_utf8_lossy(&head).trim().to_string());
+    Ok(export)
+}
+
+/// Names a note's files by its id, e.g. `5F3C…-ICNote-p123` for
+/// `x-coredata://5F3C…/ICNote/p123`, which stays put across renames and
+/// moves between folders.
+fn slug(note: &Note) -> String {
+    file_name(note.id.trim_start_matches("x-coredata://"))
+}
+
+/// Removes the files of notes that are no longer in the library.
+fn prune(dir: &Path, slugs: &BTreeSet<&str>) -> Result<()> {
+    for subdir in ["notes", "attachments"] {
+        let Ok(entries) = fs::read_dir(dir.join(subdir)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let stem = match subdir {
+                "notes" => path.file_stem(),
+                _ => path.file_name(),
+            };
+            let stem = stem.and_then(OsStr::to_str).unwrap_or_default();
+            if !slugs.contains(stem) {
+                remove(&path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result<()> {
+    let result = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else if path.exists() {
+        fs::remove_file(path)
+    } else {
+        Ok(())
+    };
+    result.map_err(|e| Error::Io {
+        context: "removing a stale export file",
+        source: e,
+    })
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    git_with_input(dir, args, b"")
+}
+
+fn git_with_input(dir: &Path, args: &[&str], input: &[u8]) -> Result<Vec<u8>> {
+    let mut all = Vec::with_capacity(args.len() + 2);
+    all.extend([OsStr::new("-C"), dir.as_os_str()]);
+    all.extend(args.iter().map(OsStr::new));
+    exec::run("git", all, input)
+}
+
+struct Style<'a> {
+    links: &'a BTreeMap<&'a str, String>,
+    images: &'a [String],
+}
+
+impl markdown::Style for Style<'_> {
+    fn note_link(&self, title: &str) -> String {
+        let text = markdown::escape(title);
+        match self.links.get(title) {
+            Some(link) => format!("[{text}]({link})"),
+            None => text,
+        }
+    }
+
+    fn image(&self, index: usize) -> String {
+        match self.images.get(index) {
+            Some(url) => format!("![]({url})"),
+            None => String::new(),
+        }
+    }
+}
@@ -0,0 +1,409 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Exports notes as a static website to browse them with: an index of the
+//! notes by folder and by tag, a page per note keeping its formatting and
+//! images, and a search box that works offline, straight from the disk.
+
+use super::{
+    ExportSummary, UniqueNames, escape_xml as escape, file_name, note_link_title, url_path, write,
+};
+use crate::{
+    Result,
+    markup::{Block, Document, Inline, ListItem},
+    notes::Note,
+};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    path::Path,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The site's stylesheet.
+pub(super) const STYLE: &str = "\
+body { font: 16px/1.5 -apple-system, system-ui, sans-serif; max-width: 46em; margin: 2em auto; padding: 0 1em; color: #222; }
+a { color: #0a5fb4; }
+header p, .meta { color: #666; font-size: 0.9em; }
+img { max-width: 100%; }
+pre { background: #f4f4f4; padding: 0.75em; overflow-x: auto; }
+blockquote { border-left: 3px solid #ccc; margin-left: 0; padding-left: 1em; color: #555; }
+table { border-collapse: collapse; }
+td { border: 1px solid #ccc; padding: 0.25em 0.5em; vertical-align: top; }
+ul.checklist { list-style: none; padding-left: 0.5em; }
+input[type=search] { width: 100%; font-size: 1em; padding: 0.4em; box-sizing: border-box; }
+.tags a { margin-right: 0.5em; }
+@media (prefers-color-scheme: dark) {
+  body { background: #1e1e1e; color: #ddd; }
+  a { color: #6cb4ff; }
+  pre { background: #2a2a2a; }
+}
+";
+
+/// Searches the index that precedes it in `search.js`. The index is a
+/// script rather than a JSON file, as browsers won't fetch one from disk.
+const SEARCH: &str = "\
+(function () {
+  var input = document.getElementById('search');
+  var results = document.getElementById('results');
+  var sections = document.getElementById('browse');
+  input.addEventListener('input', function () {
+    var words = input.value.toLowerCase().split(/\\s+/).filter(Boolean);
+    results.innerHTML = '';
+    sections.hidden = words.length > 0;
+    if (!words.length) return;
+    var matches = SEARCH_INDEX.filter(function (note) {
+      var haystack = (note.title + '\\n' + note.tags.join(' ') + '\\n' + note.text).toLowerCase();
+      return words.every(function (word) { return haystack.indexOf(word) >= 0; });
+    });
+    matches.slice(0, 100).forEach(function (note) {
+      var item = document.createElement('li');
+      var link = document.createElement('a');
+      link.href = note.url;
+      link.textContent = note.title;
+      item.appendChild(link);
+      item.appendChild(document.createTextNode(' \\u2014 ' + note.folder));
+      results.appendChild(item);
+    });
+    if (!matches.length) results.innerHTML = '<li>No notes found</li>';
+  });
+})();
+";
+
+/// Writes the site into `dir`, which need not exist yet. Re-exporting
+/// overwrites the files of the previous export in place.
+pub fn export(notes: &[Note], dir: &Path) -> Result<ExportSummary> {
+    let accounts: BTreeSet<&str> = notes.iter().map(|note| note.account.as_str()).collect();
+    let mut names = UniqueNames::default();
+    let stems: Vec<String> = notes
+        .iter()
+        .map(|note| names.claim(&file_name(&note.name)))
+        .collect();
+    let mut links: BTreeMap<&str, String> = BTreeMap::new();
+    for (note, stem) in notes.iter().zip(&stems) {
+        links
+            .entry(note.name.trim())
+            .or_insert_with(|| format!("{}.html", url_path(stem)));
+    }
+
+    let mut folders: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut tags: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut index = Vec::new();
+    let mut summary = ExportSummary::default();
+
+    for (n, (note, stem)) in notes.iter().zip(&stems).enumerate() {
+        let document = Document::parse(&note.body_html);
+        let mut images = Vec::new();
+        for (i, image) in document.images.iter().enumerate() {
+            let name = format!("{stem}/{}.{}", i + 1, image.extension());
+            write(&dir.join("notes").join(&name), &image.data)?;
+            images.push(url_path(&name));
+            summary.attachments += 1;
+        }
+
+        let folder = if accounts.len() > 1 {
+            format!("{} / {}", note.account.trim(), note.folder.trim())
+        } else {
+            note.folder.trim().to_string()
+        };
+        let note_tags = document.tags();
+        let renderer = Renderer {
+            document: &document,
+            links: &links,
+            images: &images,
+        };
+        write(
+            &dir.join("notes").join(format!("{stem}.html")),
+            note_page(note, &folder, &note_tags, &renderer),
+        )?;
+        summary.notes += 1;
+
+        index.push(json!({
+            "title": note.name.trim(),
+            "url": format!("notes/{}.html", url_path(stem)),
+            "folder": folder,
+            "tags": note_tags,
+            "text": plain_text(&document.blocks),
+        }));
+        folders.entry(folder).or_default().push(n);
+        for tag in note_tags {
+            tags.entry(tag.to_lowercase()).or_default().push(n);
+        }
+    }
+
+    write(
+        &dir.join("index.html"),
+        index_page(notes, &stems, &folders, &tags),
+    )?;
+    write(&dir.join("style.css"), STYLE)?;
+    write(
+        &dir.join("search.js"),
+        format!(
+            "var SEARCH_INDEX = {};\n{SEARCH}",
+            serde_json::Value::from(index)
+        ),
+    )?;
+    Ok(summary)
+}
+
+/// Renders the page of one note, within `notes/`.
+fn note_page(note: &Note, folder: &str, tags: &[String], renderer: &Renderer) -> String {
+    let title = escape(note.name.trim());
+    let mut html = page_head(&title, "../");
+    html += "<header>\n<p><a href=\"../index.html\">All notes</a></p>\n";
+    html += &format!("<h1>{title}</h1>\n");
+    html += &format!(
+        "<p class=\"meta\">{} &middot; Created <time datetime=\"{}\">{}</time> &middot; \
+         Modified <time datetime=\"{}\">{}</time></p>\n",
+        escape(folder),
+        escape(&note.created),
+        escape(&display_time(&note.created)),
+        escape(&note.modified),
+        escape(&display_time(&note.modified)),
+    );
+    if !tags.is_empty() {
+        html += "<p class=\"tags\">";
+        for tag in tags {
+            html += &format!(
+                "<a href=\"../index.html#{}\">#{}</a>",
+                escape(&tag_anchor(tag)),
+                escape(tag)
+            );
+        }
+        html += "</p>\n";
+    }
+    html += "</header>\n<main>\n";
+    html += &renderer.render(&note.name);
+    html += "</main>\n</body>\n</html>\n";
+    html
+}
+
+/// Renders the index of every note, by folder and by tag, above the
+/// search box and its results.
+fn index_page(
+    notes: &[Note],
+    stems: &[String],
+    folders: &BTreeMap<String, Vec<usize>>,
+    tags: &BTreeMap<String, Vec<usize>>,
+) -> String {
+    let list = |html: &mut String, members: &[usize]| {
+        *html += "<ul>\n";
+        for &n in members {
+            *html += &format!(
+                "<li><a href=\"notes/{}.html\">{}</a></li>\n",
+                escape(&url_path(&stems[n])),
+                escape(notes[n].name.trim())
+            );
+        }
+        *html += "</ul>\n";
+    };
+
+    let mut html = page_head("Notes", "");
+    html += &format!("<header>\n<h1>Notes</h1>\n<p>{} notes</p>\n", notes.len());
+    html += "<input type=\"search\" id=\"search\" placeholder=\"Search notes\" autofocus>\n";
+    html += "</header>\n<main>\n<ul id=\"results\"></ul>\n<div id=\"browse\">\n";
+    html += "<h2>Folders</h2>\n";
+    for (folder, members) in folders {
+        html += &format!("<h3>{}</h3>\n", escape(folder));
+        list(&mut html, members);
+    }
+    if !tags.is_empty() {
+        html += "<h2>Tags</h2>\n";
+        for (tag, members) in tags {
+            html += &format!(
+                "<h3 id=\"{}\">#{}</h3>\n",
+                escape(&tag_anchor(tag)),
+                escape(tag)
+            );
+            list(&mut html, members);
+        }
+    }
+    html += "</div>\n</main>\n<script src=\"search.js\"></script>\n</body>\n</html>\n";
+    html
+}
+
+pub(super) fn page_head(title: &str, root: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <meta name=\"generator\" content=\"asimov-apple {}\">\n\
+         <title>{title}</title>\n<link rel=\"stylesheet\" href=\"{root}style.css\">\n\
+         </head>\n<body>\n",
+        env!("CARGO_PKG_VERSION"),
+    )
+}
+
+/// Returns the fragment identifying a tag on the index page.
+fn tag_anchor(tag: &str) -> String {
+    format!("tag-{}", url_path(&tag.to_lowercase()))
+}
+
+/// Shortens a note time, e.g. `2025-01-20T13:30:00`, to `2025-01-20 13:30`.
+fn display_time(time: &str) -> String {
+    let time = time.replacen('T', " ", 1);
+    match time.char_indices().nth(16) {
+        Some((end, _)) => time[..end].to_string(),
+        None => time,
+    }
+}
+
+/// Returns the words of some blocks, a line per paragraph, for searching.
+fn plain_text(blocks: &[Block]) -> String {
+    let mut lines = Vec::new();
+    for block in blocks {
+        match block {
+            Block::Heading { content, .. } | Block::Paragraph(content) => {
+                lines.push(Inline::plain_text(content));
+            }
+            Block::List { items, .. } => {
+                for item in items {
+                    lines.push(Inline::plain_text(&item.content));
+                    lines.push(plain_text(&item.children));
+                }
+            }
+            Block::Code(code) => lines.push(code.clone()),
+            Block::Quote(blocks) => lines.push(plain_text(blocks)),
+            Block::Table(rows) => {
+                for cell in rows.iter().flatten() {
+                    lines.push(Inline::plain_text(cell));
+                }
+            }
+            Block::Rule => {}
+        }
+    }
+    lines.retain(|line| !line.trim().is_empty());
+    lines.join("\n")
+}
+
+/// Renders note bodies as HTML, linking to other notes' pages and to
+/// wherever their images went.
+pub(super) struct Renderer<'a> {
+    pub(super) document: &'a Document,
+    /// The URL of the first note with each title.
+    pub(super) links: &'a BTreeMap<&'a str, String>,
+    /// The URL of each image in the document.
+    pub(super) images: &'a [String],
+}
+
+impl Renderer<'_> {
+    /// Renders a whole note body, leaving out its first line if that is
+    /// just the note's `title`, which the page shows as its heading.
+    pub(super) fn render(&self, title: &str) -> String {
+        let blocks = &self.document.blocks;
+        let skip = usize::from(self.document.starts_with_title(title)).min(blocks.len());
+        let mut output = String::new();
+        for block in &blocks[skip..] {
+            self.render_block(&mut output, block);
+            output.push('\n');
+        }
+        output
+    }
+
+    fn render_block(&self, output: &mut String, block: &Block) {
+        match block {
+            Block::Heading { level, content } => {
+                // The page title is the only `<h1>`:
+                let level = (*level + 1).clamp(2, 6);
+                *output += &format!("<h{level}>");
+                self.render_inlines(output, content);
+                *output += &format!("</h{level}>");
+            }
+            Block::Paragraph(content) => {
+                if content.is_empty() {
+                    return;
+                }
+                *output += "<p>";
+                self.render_inlines(output, content);
+                *output += "</p>";
+            }
+            Block::List { ordered, items } => self.render_list(output, *ordered, items),
+            Block::Code(code) => *output += &format!("<pre><code>{}</code></pre>", escape(code)),
+            Block::Quote(blocks) => {
+                *output += "<blockquote>";
+                for block in blocks {
+                    self.render_block(output, block);
+                }
+                *output += "</blockquote>";
+            }
+            Block::Table(rows) => {
+                *output += "<table>";
+                for row in rows {
+                    *output += "<tr>";
+                    for cell in row {
+                        *output += "<td>";
+                        self.render_inlines(output, cell);
+                        *output += "</td>";
+                    }
+                    *output += "</tr>";
+                }
+                *output += "</table>";
+            }
+            Block::Rule => *output += "<hr>",
+        }
+    }
+
+    fn render_list(&self, output: &mut String, ordered: bool, items: &[ListItem]) {
+        let checklist = items.iter().any(|item| item.checked.is_some());
+        let tag = if ordered { "ol" } else { "ul" };
+        if checklist {
+            *output += &format!("<{tag} class=\"checklist\">");
+        } else {
+            *output += &format!("<{tag}>");
+        }
+        for item in items {
+            *output += "<li>";
+            match item.checked {
+                Some(true) => *output += "<input type=\"checkbox\" checked disabled> ",
+                Some(false) => *output += "<input type=\"checkbox\" disabled> ",
+                None => {}
+            }
+            self.render_inlines(output, &item.content);
+            for child in &item.children {
+                self.render_block(output, child);
+            }
+            *output += "</li>";
+        }
+        *output += &format!("</{tag}>");
+    }
+
+    fn render_inlines(&self, output: &mut String, content: &[Inline]) {
+        let wrap = |output: &mut String, tag: &str, inner: &[Inline]| {
+            *output += &format!("<{tag}>");
+            self.render_inlines(output, inner);
+            *output += &format!("</{tag}>");
+        };
+        for inline in content {
+            match inline {
+                Inline::Text(text) => *output += &escape(text),
+                Inline::Bold(inner) => wrap(output, "strong", inner),
+                Inline::Italic(inner) => wrap(output, "em", inner),
+                Inline::Underline(inner) => wrap(output, "u", inner),
+                Inline::Strike(inner) => wrap(output, "s", inner),
+                Inline::Code(code) => *output += &format!("<code>{}</code>", escape(code)),
+                Inline::Link { href, content } => {
+                    let href = match note_link_title(href, content) {
+                        Some(title) => match self.links.get(title.as_str()) {
+                            Some(link) => link.clone(),
+                            None => {
+                                self.render_inlines(output, content);
+                                continue;
+                            }
+                        },
+                        None => href.clone(),
+                    };
+                    *output += &format!("<a href=\"{}\">", escape(&href));
+                    self.render_inlines(output, content);
+                    *output += "</a>";
+                }
+                Inline::Image(index) => {
+                    if let Some(url) = self.images.get(*index) {
+                        *output +=
+                            &format!("<img src=\"{}\" alt=\"\" loading=\"lazy\">", escape(url));
+                    }
+                }
+                Inline::Break => *output += "<br>",
+            }
+        }
+    }
+}
@@ -0,0 +1,322 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Exports notes as a Joplin JEX archive: a tar file of Joplin's raw items,
+//! with folders as notebooks (under a notebook per account, given several),
+//! `#tags` as tags, and embedded images as resources. Item ids derive from
+//! URNs, so re-exports keep them.
+
+use super::{ExportSummary, markdown, md5_hex, write};
+use crate::{Result, markup::Document, notes::Note, timezone::TimeZone};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    path::Path,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Joplin's item types.
+const NOTE: u8 = 1;
+const FOLDER: u8 = 2;
+const RESOURCE: u8 = 4;
+const TAG: u8 = 5;
+const NOTE_TAG: u8 = 6;
+
+/// Writes every note into the JEX file at `file`, replacing it if it
+/// exists.
+pub fn export(notes: &[Note], file: &Path) -> Result<ExportSummary> {
+    let zone = TimeZone::local();
+    let time = |local: &str| Time::new(&zone, local);
+    let accounts: BTreeSet<&str> = notes.iter().map(|note| note.account.as_str()).collect();
+    let mut archive = Tar::default();
+    let mut folders: BTreeMap<String, Time> = BTreeMap::new();
+    let mut tags: BTreeMap<String, Time> = BTreeMap::new();
+    let mut summary = ExportSummary::default();
+
+    // Links between notes go by id, to the first note with each title:
+    let mut ids: BTreeMap<&str, String> = BTreeMap::new();
+    for note in notes {
+        ids.entry(note.name.trim())
+            .or_insert_with(|| id(&note.urn()));
+    }
+
+    for note in notes {
+        let (created, updated) = (time(&note.created), time(&note.modified));
+        let note_id = id(&note.urn());
+        let document = Document::parse(&note.body_html);
+
+        // Notebooks are created as of their oldest note:
+        let mut parent_id = String::new();
+        if accounts.len() > 1 {
+            parent_id = folder(&mut folders, &note.account, "", &created);
+        }
+        parent_id = folder(&mut folders, &note.folder, &parent_id, &created);
+
+        let mut resources = Vec::new();
+        for (n, image) in document.images.iter().enumerate() {
+            let resource_id = id(&format!("{}#image-{}", note.urn(), n + 1));
+            let extension = image.extension();
+            let title = format!("{} {}.{extension}", note.name.trim(), n + 1);
+            archive.add(
+                &format!("resources/{resource_id}.{extension}"),
+                &image.data,
+                updated.epoch,
+            );
+            archive.add_item(
+                &resource_id,
+                &item(
+                    Some(&title),
+                    None,
+                    &[
+                        ("id", resource_id.clone()),
+                        ("mime", image.mime.clone()),
+                        ("filename", title.clone()),
+                        ("created_time", created.iso.clone()),
+                        ("updated_time", updated.iso.clone()),
+                        ("user_created_time", created.iso.clone()),
+                        ("user_updated_time", updated.iso.clone()),
+                        ("file_extension", extension.to_string()),
+                        ("encryption_applied", "0".into()),
+                        ("encryption_blob_encrypted", "0".into()),
+                        ("size", image.data.len().to_string()),
+                        ("is_shared", "0".into()),
+                        ("type_", RESOURCE.to_string()),
+                    ],
+                ),
+                updated.epoch,
+            );
+            resources.push(resource_id);
+            summary.attachments += 1;
+        }
+
+        let style = Style {
+            ids: &ids,
+            resources: &resources,
+        };
+        let body = markdown::render(&document, Some(&note.name), &style);
+        archive.add_item(
+            &note_id,
+            &item(
+                Some(note.name.trim()),
+                Some(body.trim_end()),
+                &[
+                    ("id", note_id.clone()),
+                    ("parent_id", parent_id),
+                    ("created_time", created.iso.clone()),
+                    ("updated_time", updated.iso.clone()),
+                    ("is_conflict", "0".into()),
+                    ("is_todo", "0".into()),
+                    ("source", "apple-notes".into()),
+                    ("source_application", "asimov-apple".into()),
+                    ("application_data", note.urn()),
+                    ("user_created_time", created.iso.clone()),
+                    ("user_updated_time", updated.iso.clone()),
+                    ("encryption_applied", "0".into()),
+                    ("markup_language", "1".into()),
+                    ("is_shared", "0".into()),
+                    ("type_", NOTE.to_string()),
+                ],
+            ),
+            updated.epoch,
+        );
+        summary.notes += 1;
+
+        for tag in document.tags() {
+            let tag_id = id(&format!("tag:{}", tag.to_lowercase()));
+            tags.entry(tag.to_lowercase())
+                .or_insert_with(|| created.clone());
+            let note_tag_id = id(&format!("{note_id}{tag_id}"));
+            archive.add_item(
+                &note_tag_id,
+                &item(
+                    None,
+                    None,
+                    &[
+                        ("id", note_tag_id.clone()),
+                        ("note_id", note_id.clone()),
+                        ("tag_id", tag_id),
+                        ("created_time", created.iso.clone()),
+                        ("updated_time", updated.iso.clone()),
+                        ("user_created_time", created.iso.clone()),
+                        ("user_updated_time", updated.iso.clone()),
+                        ("encryption_applied", "0".into()),
+                        ("is_shared", "0".into()),
+                        ("type_", NOTE_TAG.to_string()),
+                    ],
+                ),
+                updated.epoch,
+            );
+        }
+    }
+
+    for (key, created) in &folders {
+        let (parent_id, title) = key.split_once('\n').unwrap_or_default();
+        let folder_id = id(&format!("folder:{key}"));
+        archive.add_item(
+            &folder_id,
+            &item(
+                Some(title),
+                None,
+                &[
+                    ("id", folder_id.clone()),
+                    ("created_time", created.iso.clone()),
+                    ("updated_time", created.iso.clone()),
+                    ("user_created_time", created.iso.clone()),
+                    ("user_updated_time", created.iso.clone()),
+                    ("encryption_applied", "0".into()),
+                    ("parent_id", parent_id.to_string()),
+                    ("is_shared", "0".into()),
+                    ("type_", FOLDER.to_string()),
+                ],
+            ),
+            created.epoch,
+        );
+    }
+    for (title, created) in &tags {
+        let tag_id = id(&format!("tag:{title}"));
+        archive.add_item(
+            &tag_id,
+            &item(
+                Some(title),
+                None,
+                &[
+                    ("id", tag_id.clone()),
+                    ("created_time", created.iso.clone()),
+                    ("updated_time", created.iso.clone()),
+                    ("user_created_time", created.iso.clone()),
+                    ("user_updated_time", created.iso.clone()),
+                    ("encryption_applied", "0".into()),
+                    ("is_shared", "0".into()),
+                    ("parent_id", String::new()),
+                    ("type_", TAG.to_string()),
+                ],
+            ),
+            created.epoch,
+        );
+    }
+
+    write(file, archive.finish())?;
+    Ok(summary)
+}
+
+/// Returns the id of the folder called `title` within `parent_id`,
+/// keeping its oldest creation time.
+fn folder(
+    folders: &mut BTreeMap<String, Time>,
+    title: &str,
+    parent_id: &str,
+    created: &Time,
+) -> String {
+    let title = title.trim();
+    let key = format!("{parent_id}\n{title}");
+    let id = id(&format!("folder:{key}"));
+    let time = folders.entry(key).or_insert_with(|| created.clone());
+    if created.epoch < time.epoch {
+        *time = created.clone();
+    }
+    id
+}
+
+/// Derives a Joplin item id (32 hex digits) from a stable key.
+fn id(key: &str) -> String {
+    md5_hex(key.as_bytes())
+}
+
+/// Serializes an item the way Joplin does: the title and body, each
+/// followed by a blank line, then a `key: value` line per property.
+fn item(title: Option<&str>, body: Option<&str>, properties: &[(&str, String)]) -> String {
+    let mut output = String::new();
+    if let Some(title) = title {
+        output += &title.replace('\n', " ");
+        output += "\n\n";
+    }
+    if let Some(body) = body.filter(|body| !body.is_empty()) {
+        output += body;
+        output += "\n\n";
+    }
+    let properties: Vec<String> = properties
+        .iter()
+        .map(|(key, value)| format!("{key}: {}", value.replace('\n', " ")))
+        .collect();
+    output + &properties.join("\n")
+}
+
+/// A time as Joplin writes it, and as seconds since the epoch.
+#[derive(Clone, Debug)]
+struct Time {
+    iso: String,
+    epoch: i64,
+}
+
+impl Time {
+    fn new(zone: &TimeZone, local: &str) -> Self {
+        let epoch = zone.to_timestamp(local).unwrap_or_default();
+        let iso = crate::timezone::iso8601(epoch).replace('Z', ".000Z");
+        Time { iso, epoch }
+    }
+}
+
+struct Style<'a> {
+    ids: &'a BTreeMap<&'a str, String>,
+    resources: &'a [String],
+}
+
+impl markdown::Style for Style<'_> {
+    fn note_link(&self, title: &str) -> String {
+        let text = markdown::escape(title);
+        match self.ids.get(title) {
+            Some(id) => format!("[{text}](:/{id})"),
+            None => text,
+        }
+    }
+
+    fn image(&self, index: usize) -> String {
+        match self.resources.get(index) {
+            Some(id) => format!("![](:/{id})"),
+            None => String::new(),
+        }
+    }
+}
+
+/// A POSIX (ustar) tar archive, built in memory.
+#[derive(Default)]
+struct Tar {
+    data: Vec<u8>,
+}
+
+impl Tar {
+    fn add_item(&mut self, id: &str, item: &str, mtime: i64) {
+        self.add(&format!("{id}.md"), item.as_bytes(), mtime);
+    }
+
+    /// Appends a regular file. Names must fit in 100 bytes.
+    fn add(&mut self, name: &str, contents: &[u8], mtime: i64) {
+        let mut header = [0u8; 512];
+        let mut field = |offset: usize, value: &[u8]| {
+            header[offset..offset + value.len()].copy_from_slice(value);
+        };
+        field(0, &name.as_bytes()[..name.len().min(100)]);
+        field(100, b"0000644\0");
+        field(108, b"0000000\0");
+        field(116, b"0000000\0");
+        field(124, format!("{:011o}\0", contents.len()).as_bytes());
+        field(136, format!("{:011o}\0", mtime.max(0)).as_bytes());
+        field(148, b"        ");
+        field(156, b"0");
+        field(257, b"ustar\0");
+        field(263, b"00");
+        let checksum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+        header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+        self.data.extend_from_slice(&header);
+        self.data.extend_from_slice(contents);
+        let padding = (512 - contents.len() % 512) % 512;
+        self.data.resize(self.data.len() + padding, 0);
+    }
+
+    /// Ends the archive with the two empty blocks tar expects.
+    fn finish(mut self) -> Vec<u8> {
+        self.data.resize(self.data.len() + 1024, 0);
+        self.data
+    }
+}
@@ -0,0 +1,178 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Exports notes into a Logseq graph: an outline page per note under
+//! `pages/`, notes titled with a date as journal pages under `journals/`,
+//! and embedded images under `assets/`.
+
+use super::{ExportSummary, UniqueNames, file_name, markdown, write};
+use crate::{
+    Result,
+    markup::{Block, Document, ListItem},
+    notes::Note,
+};
+use std::{
+    format,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Writes every note into the graph at `graph`, which need not exist yet.
+pub fn export(notes: &[Note], graph: &Path) -> Result<ExportSummary> {
+    let mut pages = UniqueNames::default();
+    let mut journals = UniqueNames::default();
+    let mut assets = UniqueNames::default();
+    let mut summary = ExportSummary::default();
+
+    for note in notes {
+        let document = Document::parse(&note.body_html);
+        let path = match journal_date(&note.name) {
+            Some((year, month, day)) => {
+                let stem = journals.claim(&format!("{year:04}_{month:02}_{day:02}"));
+                PathBuf::from("journals").join(format!("{stem}.md"))
+            }
+            None => PathBuf::from("pages")
+                .join(format!("{}.md", pages.claim(&page_file_name(&note.name)))),
+        };
+
+        let images: Vec<String> = document
+            .images
+            .iter()
+            .enumerate()
+            .map(|(n, image)| {
+                let stem = format!("{}_{}", file_name(&note.name), n + 1).replace(' ', "_");
+                format!("{}.{}", assets.claim(&stem), image.extension())
+            })
+            .collect();
+        for (image, name) in document.images.iter().zip(&images) {
+            write(&graph.join("assets").join(name), &image.data)?;
+            summary.attachments += 1;
+        }
+
+        let style = Style { images: &images };
+        let mut contents = properties(note, &document, path.starts_with("journals"));
+        contents += &outline(&document, &note.name, &style);
+        write(&graph.join(path), contents)?;
+        summary.notes += 1;
+    }
+
+    Ok(summary)
+}
+
+/// Recognizes note titles that name a day, such as `2025-01-20`, as
+/// `(year, month, day)`.
+fn journal_date(title: &str) -> Option<(u32, u32, u32)> {
+    let title = title.trim();
+    let mut parts = title.split(['-', '/', '.', '_']);
+    let year: u32 = parts.next().filter(|y| y.len() == 4)?.parse().ok()?;
+    let month: u32 = parts.next().filter(|m| m.len() <= 2)?.parse().ok()?;
+    let day: u32 = parts.next().filter(|d| d.len() <= 2)?.parse().ok()?;
+    let valid = parts.next().is_none() && (1..=12).contains(&month) && (1..=31).contains(&day);
+    valid.then_some((year, month, day))
+}
+
+/// Names a page file the way Logseq does by default, with namespaces
+/// (`a/b`) written as `a___b`.
+fn page_file_name(title: &str) -> String {
+    let parts: Vec<String> = title.split('/').map(file_name).collect();
+    parts.join("___")
+}
+
+/// The page properties block: the original title, tags, and the note's
+/// identity, dates, and location.
+fn properties(note: &Note, document: &Document, journal: bool) -> String {
+    let mut block = String::new();
+    if !journal {
+        block += &format!("title:: {}\n", note.name.trim());
+    }
+    let tags = document.tags();
+    if !tags.is_empty() {
+        block += &format!("tags:: {}\n", tags.join(", "));
+    }
+    block += &format!("apple-notes-id:: {}\n", note.urn());
+    block += &format!("created:: {}\n", note.created);
+    block += &format!("modified:: {}\n", note.modified);
+    block += &format!("folder:: {}\n", note.folder);
+    block += &format!("account:: {}\n\n", note.account);
+    block
+}
+
+/// Renders the body as an outline, nesting each section under its heading.
+fn outline(document: &Document, title: &str, style: &Style) -> String {
+    let skip = usize::from(document.starts_with_title(title)).min(document.blocks.len());
+    let mut lines = Vec::new();
+    let mut headings: Vec<u8> = Vec::new();
+    for block in &document.blocks[skip..] {
+        match block {
+            Block::Heading { level, .. } => {
+                while headings.last().is_some_and(|open| open >= level) {
+                    headings.pop();
+                }
+                bullet(
+                    &mut lines,
+                    headings.len(),
+                    &markdown::render_block(block, style),
+                );
+                headings.push(*level);
+            }
+            Block::List { items, .. } => list(&mut lines, headings.len(), items, style),
+            block => bullet(
+                &mut lines,
+                headings.len(),
+                &markdown::render_block(block, style),
+            ),
+        }
+    }
+    let mut output = lines.join("\n");
+    if !output.is_empty() {
+        output.push('\n');
+    }
+    output
+}
+
+fn list(lines: &mut Vec<String>, depth: usize, items: &[ListItem], style: &Style) {
+    for item in items {
+        let content = markdown::render_inlines(&item.content, style);
+        let content = match item.checked {
+            Some(true) => format!("DONE {content}"),
+            Some(false) => format!("TODO {content}"),
+            None => content,
+        };
+        bullet(lines, depth, &content);
+        for child in &item.children {
+            match child {
+                Block::List { items, .. } => list(lines, depth + 1, items, style),
+                block => bullet(lines, depth + 1, &markdown::render_block(block, style)),
+            }
+        }
+    }
+}
+
+/// Adds a block at `depth`, continuing its extra lines underneath.
+fn bullet(lines: &mut Vec<String>, depth: usize, content: &str) {
+    let indent = "\t".repeat(depth);
+    let mut content_lines = content.lines();
+    lines.push(
+        format!("{indent}- {}", content_lines.next().unwrap_or_default())
+            .trim_end()
+            .to_string(),
+    );
+    lines.extend(content_lines.map(|line| format!("{indent}  {line}")));
+}
+
+struct Style<'a> {
+    images: &'a [String],
+}
+
+impl markdown::Style for Style<'_> {
+    fn note_link(&self, title: &str) -> String {
+        format!("[[{}]]", title.replace(['[', ']'], ""))
+    }
+
+    fn image(&self, index: usize) -> String {
+        match self.images.get(index) {
+            Some(name) => format!("![{name}](../assets/{name})"),
+            None => String::new(),
+        }
+    }
+}
@@ -0,0 +1,215 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Renders parsed note bodies as Markdown, for exports into Markdown-based
+//! apps. Each app links notes and embeds images its own way, so those are
+//! left to a [`Style`].
+
+use super::note_link_title;
+use crate::markup::{Block, Document, Inline, ListItem};
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// How an export renders what plain Markdown has no one way to express.
+pub trait Style {
+    /// Renders a link to the note titled `title`.
+    fn note_link(&self, title: &str) -> String;
+
+    /// Renders the image at `index` in the note's document.
+    fn image(&self, index: usize) -> String;
+}
+
+/// Renders a whole note body, leaving out its first line if that is just
+/// the note's `title` (which the export shows by other means).
+pub fn render(document: &Document, title: Option<&str>, style: &impl Style) -> String {
+    let skip = title.is_some_and(|title| document.starts_with_title(title));
+    let blocks = &document.blocks[usize::from(skip).min(document.blocks.len())..];
+    let mut output = render_blocks(blocks, style);
+    if !output.is_empty() {
+        output.push('\n');
+    }
+    output
+}
+
+/// Renders blocks separated by blank lines.
+pub fn render_blocks(blocks: &[Block], style: &impl Style) -> String {
+    blocks
+        .iter()
+        .map(|block| render_block(block, style))
+        .filter(|block| !block.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+pub fn render_block(block: &Block, style: &impl Style) -> String {
+    match block {
+        Block::Heading { level, content } => {
+            format!(
+                "{} {}",
+                "#".repeat(usize::from(*level)),
+                render_inlines(content, style)
+            )
+        }
+        Block::Paragraph(content) => protect_line_starts(&render_inlines(content, style)),
+        Block::List { ordered, items } => render_list(*ordered, items, style),
+        Block::Code(code) => {
+            let fence = if code.contains("```") { "~~~" } else { "```" };
+            format!("{fence}\n{}\n{fence}", code.trim_end_matches('\n'))
+        }
+        Block::Quote(blocks) => render_blocks(blocks, style)
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    ">".to_string()
+                } else {
+                    format!("> {line}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Block::Table(rows) => render_table(rows, style),
+        Block::Rule => "---".into(),
+    }
+}
+
+fn render_list(ordered: bool, items: &[ListItem], style: &impl Style) -> String {
+    let mut lines = Vec::new();
+    for (n, item) in items.iter().enumerate() {
+        let mut marker = if ordered {
+            format!("{}.", n + 1)
+        } else {
+            "-".into()
+        };
+        match item.checked {
+            Some(true) => marker += " [x]",
+            Some(false) => marker += " [ ]",
+            None => {}
+        }
+        let content = render_inlines(&item.content, style);
+        let mut content_lines = content.lines();
+        lines.push(
+            format!("{marker} {}", content_lines.next().unwrap_or_default())
+                .trim_end()
+                .to_string(),
+        );
+        lines.extend(content_lines.map(|line| format!("    {line}")));
+        for child in &item.children {
+            let child = render_block(child, style);
+            lines.extend(child.lines().map(|line| format!("    {line}")));
+        }
+    }
+    lines.join("\n")
+}
+
+fn render_table(rows: &[Vec<Vec<Inline>>], style: &impl Style) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return String::new();
+    }
+    let row = |cells: &[Vec<Inline>]| {
+        let mut cells: Vec<String> = cells
+            .iter()
+            .map(|cell| render_inlines(cell, style).replace('\n', "<br>"))
+            .collect();
+        cells.resize(columns, String::new());
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines = Vec::new();
+    lines.push(row(&rows[0]));
+    lines.push(format!("|{}", " --- |".repeat(columns)));
+    lines.extend(rows[1..].iter().map(|cells| row(cells)));
+    lines.join("\n")
+}
+
+/// Renders inline content, with line breaks as newlines.
+pub fn render_inlines(content: &[Inline], style: &impl Style) -> String {
+    let mut output = String::new();
+    for inline in content {
+        match inline {
+            Inline::Text(text) => output += &escape(text),
+            Inline::Bold(inner) => output += &wrap("**", &render_inlines(inner, style)),
+            Inline::Italic(inner) => output += &wrap("*", &render_inlines(inner, style)),
+            Inline::Strike(inner) => output += &wrap("~~", &render_inlines(inner, style)),
+            Inline::Underline(inner) => {
+                let inner = render_inlines(inner, style);
+                output += &if inner.trim().is_empty() {
+                    inner
+                } else {
+                    format!("<u>{inner}</u>")
+                };
+            }
+            Inline::Code(code) => {
+                let fence = if code.contains('`') { "``" } else { "`" };
+                output += &format!("{fence}{code}{fence}");
+            }
+            Inline::Link { href, content } => match note_link_title(href, content) {
+                Some(title) => output += &style.note_link(&title),
+                None => {
+                    let text = render_inlines(content, style);
+                    output += &if text.is_empty() || text == *href {
+                        format!("<{href}>")
+                    } else {
+                        format!("[{text}]({})", href.replace(' ', "%20").replace(')', "%29"))
+                    };
+                }
+            },
+            Inline::Image(index) => output += &style.image(*index),
+            Inline::Break => output.push('\n'),
+        }
+    }
+    output
+}
+
+/// Wraps `inner` in a delimiter pair, keeping surrounding whitespace
+/// outside so the delimiters still count.
+fn wrap(delimiter: &str, inner: &str) -> String {
+    let trimmed = inner.trim();
+    if trimmed.is_empty() {
+        return inner.to_string();
+    }
+    let start = &inner[..inner.len() - inner.trim_start().len()];
+    let end = &inner[inner.trim_end().len()..];
+    format!("{start}{delimiter}{trimmed}{delimiter}{end}")
+}
+
+/// Escapes the characters that would otherwise start Markdown formatting.
+/// `#` is left alone, so that `#tags` stay tags.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '~' | '|') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes whatever would make a paragraph line a heading, list item, or
+/// block quote.
+fn protect_line_starts(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let rest = &line[digits..];
+            let starts_block = (digits == 0
+                && (line.starts_with("- ")
+                    || line.starts_with("+ ")
+                    || line.starts_with('>')
+                    || line.starts_with("# ")
+                    || line == "-"
+                    || line.starts_with("---")))
+                || (digits > 0 && (rest.starts_with(". ") || rest.starts_with(") ")));
+            if !starts_block {
+                line.to_string()
+            } else if digits > 0 {
+                format!("{}\\{}", &line[..digits], rest)
+            } else {
+                format!("\\{line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
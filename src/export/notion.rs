@@ -0,0 +1,172 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Exports notes in the shape of a Notion export, which Notion's importer
+//! takes back in: a CSV database per folder listing its notes, a Markdown
+//! page per note in a directory of the same name, and a directory per
+//! note for the images it embeds.
+
+use super::{ExportSummary, UniqueNames, file_name, markdown, url_path, write};
+use crate::{Result, markup::Document, notes::Note};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    path::{Component, Path, PathBuf},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The columns of each folder's CSV database.
+const COLUMNS: [&str; 5] = ["Name", "Created", "Last edited", "Tags", "Apple Notes ID"];
+
+/// Writes every note into `dir`, which need not exist yet. Re-exporting
+/// overwrites the files of the previous export in place.
+pub fn export(notes: &[Note], dir: &Path) -> Result<ExportSummary> {
+    let layout = Layout::new(notes);
+    let mut databases: BTreeMap<PathBuf, String> = BTreeMap::new();
+    let mut summary = ExportSummary::default();
+
+    for (note, path) in notes.iter().zip(&layout.paths) {
+        let document = Document::parse(&note.body_html);
+        let note_dir = path.parent().unwrap_or(Path::new(""));
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+        let mut names = UniqueNames::default();
+        let images: Vec<String> = document
+            .images
+            .iter()
+            .enumerate()
+            .map(|(n, image)| {
+                let name = names.claim(&format!("{stem} {}", n + 1));
+                format!("{stem}/{name}.{}", image.extension())
+            })
+            .collect();
+        for (image, name) in document.images.iter().zip(&images) {
+            write(&dir.join(note_dir).join(name), &image.data)?;
+            summary.attachments += 1;
+        }
+
+        let tags = document.tags().join(", ");
+        let style = Style {
+            layout: &layout,
+            from: note_dir,
+            images: &images,
+        };
+        let mut page = format!("# {}\n\n", note.name.trim());
+        page += &format!("Created: {}\n", note.created);
+        page += &format!("Last edited: {}\n", note.modified);
+        if !tags.is_empty() {
+            page += &format!("Tags: {tags}\n");
+        }
+        page += &format!("Apple Notes ID: {}\n\n", note.urn());
+        page += &markdown::render(&document, Some(&note.name), &style);
+        write(&dir.join(path), page)?;
+        summary.notes += 1;
+
+        let database = databases
+            .entry(note_dir.with_extension("csv"))
+            .or_insert_with(|| csv_row(&COLUMNS));
+        *database += &csv_row(&[
+            note.name.trim(),
+            &note.created,
+            &note.modified,
+            &tags,
+            &note.urn(),
+        ]);
+    }
+
+    for (path, contents) in databases {
+        // A byte order mark makes spreadsheet apps read the CSV as UTF-8:
+        write(&dir.join(path), format!("\u{FEFF}{contents}"))?;
+    }
+    Ok(summary)
+}
+
+/// Where every note goes, and the first note with each title.
+struct Layout {
+    /// Each note's page within the export, in the order of the notes.
+    paths: Vec<PathBuf>,
+    targets: BTreeMap<String, PathBuf>,
+}
+
+impl Layout {
+    fn new(notes: &[Note]) -> Self {
+        let accounts: BTreeSet<&str> = notes.iter().map(|note| note.account.as_str()).collect();
+        let mut names: BTreeMap<PathBuf, UniqueNames> = BTreeMap::new();
+        let paths: Vec<PathBuf> = notes
+            .iter()
+            .map(|note| {
+                let mut dir = PathBuf::new();
+                if accounts.len() > 1 {
+                    dir.push(file_name(&note.account));
+                }
+                dir.push(file_name(&note.folder));
+                let stem = names
+                    .entry(dir.clone())
+                    .or_default()
+                    .claim(&file_name(&note.name));
+                dir.join(format!("{stem}.md"))
+            })
+            .collect();
+        let mut targets = BTreeMap::new();
+        for (note, path) in notes.iter().zip(&paths) {
+            targets
+                .entry(note.name.trim().to_string())
+                .or_insert_with(|| path.clone());
+        }
+        Layout { paths, targets }
+    }
+}
+
+struct Style<'a> {
+    layout: &'a Layout,
+    /// The directory of the page being rendered.
+    from: &'a Path,
+    images: &'a [String],
+}
+
+impl markdown::Style for Style<'_> {
+    fn note_link(&self, title: &str) -> String {
+        let text = markdown::escape(title);
+        match self.layout.targets.get(title) {
+            Some(target) => format!("[{text}]({})", url_path(&relative(self.from, target))),
+            None => text,
+        }
+    }
+
+    fn image(&self, index: usize) -> String {
+        match self.images.get(index) {
+            Some(name) => format!("![]({})", url_path(name)),
+            None => String::new(),
+        }
+    }
+}
+
+/// Returns the path of `target` relative to the directory `from`, both
+/// relative to the export's root.
+fn relative(from: &Path, target: &Path) -> String {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = target.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts: Vec<String> = (common..from.len()).map(|_| "..".to_string()).collect();
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|part| part.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+/// Renders one CSV record, quoting fields as RFC 4180 requires.
+fn csv_row(fields: &[&str]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    format!("{}\r\n", fields.join(","))
+}
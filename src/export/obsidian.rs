@@ -0,0 +1,196 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Exports notes into an Obsidian vault: one Markdown file per note, with
+//! YAML front matter, `[[wikilinks]]` between notes, and embedded images
+//! saved in the vault's attachment folder.
+
+use super::{ExportSummary, UniqueNames, file_name, markdown, write};
+use crate::{Result, markup::Document, notes::Note};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    format, fs,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Writes every note into the vault at `vault`, which need not exist yet.
+/// Re-exporting overwrites the files of the previous export in place.
+pub fn export(notes: &[Note], vault: &Path) -> Result<ExportSummary> {
+    let attachments = AttachmentFolder::of(vault);
+    let layout = Layout::new(notes);
+    let mut attachment_names: BTreeMap<PathBuf, UniqueNames> = BTreeMap::new();
+    let mut summary = ExportSummary::default();
+
+    for (note, path) in notes.iter().zip(&layout.paths) {
+        let document = Document::parse(&note.body_html);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let note_dir = path.parent().unwrap_or(Path::new(""));
+
+        let attachment_dir = attachments.dir(note_dir);
+        let names = attachment_names.entry(attachment_dir.clone()).or_default();
+        let images: Vec<String> = document
+            .images
+            .iter()
+            .enumerate()
+            .map(|(n, image)| {
+                let name = names.claim(&format!("{stem} {}", n + 1));
+                format!("{name}.{}", image.extension())
+            })
+            .collect();
+        for (image, name) in document.images.iter().zip(&images) {
+            write(&vault.join(&attachment_dir).join(name), &image.data)?;
+            summary.attachments += 1;
+        }
+
+        let style = Style {
+            layout: &layout,
+            images: &images,
+        };
+        let mut contents = front_matter(note, &document);
+        contents += &markdown::render(&document, Some(&note.name), &style);
+        write(&vault.join(path), contents)?;
+        summary.notes += 1;
+    }
+
+    Ok(summary)
+}
+
+/// Where every note goes, and how to link to it.
+struct Layout {
+    /// Each note's path within the vault, in the order of the notes.
+    paths: Vec<PathBuf>,
+    /// The link target for each note title (the first note, for repeats).
+    targets: BTreeMap<String, String>,
+}
+
+impl Layout {
+    fn new(notes: &[Note]) -> Self {
+        let accounts: BTreeSet<&str> = notes.iter().map(|note| note.account.as_str()).collect();
+        let mut names: BTreeMap<PathBuf, UniqueNames> = BTreeMap::new();
+        let paths: Vec<PathBuf> = notes
+            .iter()
+            .map(|note| {
+                let mut dir = PathBuf::new();
+                if accounts.len() > 1 {
+                    dir.push(file_name(&note.account));
+                }
+                dir.push(file_name(&note.folder));
+                let stem = names
+                    .entry(dir.clone())
+                    .or_default()
+                    .claim(&file_name(&note.name));
+                dir.join(format!("{stem}.md"))
+            })
+            .collect();
+
+        // Obsidian resolves a bare file name anywhere in the vault, so only
+        // names that occur in more than one folder need a path:
+        let mut stem_counts: BTreeMap<String, usize> = BTreeMap::new();
+        for path in &paths {
+            *stem_counts.entry(stem_of(path).to_lowercase()).or_default() += 1;
+        }
+        let mut targets = BTreeMap::new();
+        for (note, path) in notes.iter().zip(&paths) {
+            let stem = stem_of(path);
+            let target = match stem_counts.get(&stem.to_lowercase()) {
+                Some(1) => stem,
+                _ => path.with_extension("").to_string_lossy().replace('\\', "/"),
+            };
+            targets
+                .entry(note.name.trim().to_string())
+                .or_insert(target);
+        }
+
+        Layout { paths, targets }
+    }
+}
+
+fn stem_of(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+struct Style<'a> {
+    layout: &'a Layout,
+    images: &'a [String],
+}
+
+impl markdown::Style for Style<'_> {
+    fn note_link(&self, title: &str) -> String {
+        match self.layout.targets.get(title) {
+            Some(target) if target == title => format!("[[{target}]]"),
+            Some(target) => format!("[[{target}|{}]]", title.replace('|', "-")),
+            None => format!("[[{}]]", file_name(title)),
+        }
+    }
+
+    fn image(&self, index: usize) -> String {
+        match self.images.get(index) {
+            Some(name) => format!("![[{name}]]"),
+            None => String::new(),
+        }
+    }
+}
+
+/// The YAML front matter: the note's identity, dates, location, and tags.
+pub(super) fn front_matter(note: &Note, document: &Document) -> String {
+    let quote = |value: &str| serde_json::Value::from(value).to_string();
+    let mut yaml = String::from("---\n");
+    yaml += &format!("title: {}\n", quote(&note.name));
+    yaml += &format!("id: {}\n", quote(&note.urn()));
+    yaml += &format!("created: {}\n", note.created);
+    yaml += &format!("modified: {}\n", note.modified);
+    yaml += &format!("folder: {}\n", quote(&note.folder));
+    yaml += &format!("account: {}\n", quote(&note.account));
+    let tags = document.tags();
+    if !tags.is_empty() {
+        yaml += "tags:\n";
+        for tag in tags {
+            yaml += &format!("  - {}\n", quote(&tag));
+        }
+    }
+    yaml += "source: apple-notes\n---\n";
+    yaml
+}
+
+/// The vault's "Default location for new attachments" setting.
+enum AttachmentFolder {
+    /// A folder relative to the vault root (the root itself if empty).
+    Vault(PathBuf),
+    /// A folder relative to each note's folder (the note's folder if empty).
+    Note(PathBuf),
+}
+
+impl AttachmentFolder {
+    /// Reads the setting from `.obsidian/app.json`, defaulting to the
+    /// vault root as Obsidian does.
+    fn of(vault: &Path) -> Self {
+        let setting = fs::read_to_string(vault.join(".obsidian").join("app.json"))
+            .ok()
+            .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+            .and_then(|config| {
+                config
+                    .get("attachmentFolderPath")
+                    .and_then(serde_json::Value::as_str)
+                    .map(ToString::to_string)
+            })
+            .unwrap_or_default();
+        match setting.strip_prefix("./") {
+            Some(relative) => AttachmentFolder::Note(relative.into()),
+            None if setting == "." => AttachmentFolder::Note(PathBuf::new()),
+            None => AttachmentFolder::Vault(setting.trim_start_matches('/').into()),
+        }
+    }
+
+    /// Returns the attachment folder, relative to the vault root, for a
+    /// note in `note_dir`.
+    fn dir(&self, note_dir: &Path) -> PathBuf {
+        match self {
+            AttachmentFolder::Vault(dir) => dir.clone(),
+            AttachmentFolder::Note(dir) => note_dir.join(dir),
+        }
+    }
+}
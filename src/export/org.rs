@@ -0,0 +1,354 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Exports notes as Org mode files: one `.org` file per folder, with each
+//! note a top-level entry whose properties drawer holds its identity and
+//! dates. Embedded images are saved in an `attachments` folder alongside.
+
+use super::{ExportSummary, UniqueNames, file_name, note_link_title, write};
+use crate::{
+    Result,
+    markup::{Block, Document, Inline, ListItem},
+    notes::Note,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The folder, next to the `.org` files, that holds embedded images.
+const ATTACHMENTS: &str = "attachments";
+
+/// Writes every note into `dir`, which need not exist yet. Re-exporting
+/// overwrites the files of the previous export in place.
+pub fn export(notes: &[Note], dir: &Path) -> Result<ExportSummary> {
+    let accounts: BTreeSet<&str> = notes.iter().map(|note| note.account.as_str()).collect();
+    let mut file_names: BTreeMap<PathBuf, UniqueNames> = BTreeMap::new();
+    let mut files: BTreeMap<(&str, &str), PathBuf> = BTreeMap::new();
+    let mut contents: BTreeMap<PathBuf, String> = BTreeMap::new();
+    let mut attachment_names: BTreeMap<PathBuf, UniqueNames> = BTreeMap::new();
+    let mut summary = ExportSummary::default();
+
+    // Notes link to each other by title; Org links to an entry by its ID:
+    let mut ids: BTreeMap<String, String> = BTreeMap::new();
+    for note in notes {
+        ids.entry(note.name.trim().to_string())
+            .or_insert_with(|| note.urn());
+    }
+
+    for note in notes {
+        let path = files
+            .entry((note.account.as_str(), note.folder.as_str()))
+            .or_insert_with(|| {
+                let mut parent = PathBuf::new();
+                if accounts.len() > 1 {
+                    parent.push(file_name(&note.account));
+                }
+                let stem = file_names
+                    .entry(parent.clone())
+                    .or_default()
+                    .claim(&file_name(&note.folder));
+                parent.join(format!("{stem}.org"))
+            })
+            .clone();
+        let document = Document::parse(&note.body_html);
+
+        let parent = path.parent().map(PathBuf::from).unwrap_or_default();
+        let names = attachment_names.entry(parent.clone()).or_default();
+        let images: Vec<String> = document
+            .images
+            .iter()
+            .enumerate()
+            .map(|(n, image)| {
+                let name = names.claim(&format!("{} {}", file_name(&note.name), n + 1));
+                format!("{name}.{}", image.extension())
+            })
+            .collect();
+        for (image, name) in document.images.iter().zip(&images) {
+            write(&dir.join(&parent).join(ATTACHMENTS).join(name), &image.data)?;
+            summary.attachments += 1;
+        }
+
+        let file = contents
+            .entry(path)
+            .or_insert_with(|| format!("#+TITLE: {}\n", note.folder.trim()));
+        let context = Context {
+            ids: &ids,
+            images: &images,
+        };
+        file.push('\n');
+        *file += &entry(note, &document, &context);
+        summary.notes += 1;
+    }
+
+    for (path, contents) in contents {
+        write(&dir.join(path), contents)?;
+    }
+    Ok(summary)
+}
+
+struct Context<'a> {
+    ids: &'a BTreeMap<String, String>,
+    images: &'a [String],
+}
+
+/// Renders a note as a top-level entry with its tags and properties.
+fn entry(note: &Note, document: &Document, context: &Context) -> String {
+    let mut output = format!("* {}", one_line(&note.name));
+    let tags: Vec<String> = document.tags().iter().map(|tag| org_tag(tag)).collect();
+    if !tags.is_empty() {
+        output += &format!(" :{}:", tags.join(":"));
+    }
+    output += "\n:PROPERTIES:\n";
+    output += &format!(":ID:       {}\n", note.urn());
+    output += &format!(":CREATED:  {}\n", timestamp(&note.created));
+    output += &format!(":MODIFIED: {}\n", timestamp(&note.modified));
+    output += &format!(":FOLDER:   {}\n", one_line(&note.folder));
+    output += &format!(":ACCOUNT:  {}\n", one_line(&note.account));
+    output += ":END:\n";
+
+    let skip = usize::from(document.starts_with_title(&note.name)).min(document.blocks.len());
+    let mut headings: Vec<u8> = Vec::new();
+    let mut blocks = Vec::new();
+    for block in &document.blocks[skip..] {
+        if let Block::Heading { level, content } = block {
+            while headings.last().is_some_and(|open| open >= level) {
+                headings.pop();
+            }
+            headings.push(*level);
+            let stars = "*".repeat(headings.len() + 1);
+            blocks.push(format!("{stars} {}", one_line(&inlines(content, context))));
+        } else {
+            blocks.push(render_block(block, context));
+        }
+    }
+    let body = blocks
+        .into_iter()
+        .filter(|block| !block.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if !body.is_empty() {
+        output += &body;
+        output.push('\n');
+    }
+    output
+}
+
+fn render_block(block: &Block, context: &Context) -> String {
+    match block {
+        // Headings nested in quotes or lists can't be entries:
+        Block::Heading { content, .. } => format!("*{}*", inlines(content, context).trim()),
+        Block::Paragraph(content) => protect_line_starts(&inlines(content, context)),
+        Block::List { ordered, items } => render_list(*ordered, items, context),
+        Block::Code(code) => {
+            let code: Vec<String> = code
+                .trim_end_matches('\n')
+                .lines()
+                .map(|line| {
+                    if line.starts_with('*') || line.starts_with("#+") {
+                        format!(",{line}")
+                    } else {
+                        line.to_string()
+                    }
+                })
+                .collect();
+            format!("#+BEGIN_SRC\n{}\n#+END_SRC", code.join("\n"))
+        }
+        Block::Quote(blocks) => {
+            let inner: Vec<String> = blocks
+                .iter()
+                .map(|block| render_block(block, context))
+                .filter(|block| !block.is_empty())
+                .collect();
+            format!("#+BEGIN_QUOTE\n{}\n#+END_QUOTE", inner.join("\n\n"))
+        }
+        Block::Table(rows) => render_table(rows, context),
+        Block::Rule => "-----".into(),
+    }
+}
+
+fn render_list(ordered: bool, items: &[ListItem], context: &Context) -> String {
+    let mut lines = Vec::new();
+    for (n, item) in items.iter().enumerate() {
+        let mut marker = if ordered {
+            format!("{}.", n + 1)
+        } else {
+            "-".into()
+        };
+        match item.checked {
+            Some(true) => marker += " [X]",
+            Some(false) => marker += " [ ]",
+            None => {}
+        }
+        let indent = " ".repeat(marker.len() + 1);
+        let content = inlines(&item.content, context);
+        let mut content_lines = content.lines();
+        lines.push(
+            format!("{marker} {}", content_lines.next().unwrap_or_default())
+                .trim_end()
+                .to_string(),
+        );
+        lines.extend(content_lines.map(|line| format!("{indent}{line}")));
+        for child in &item.children {
+            let child = render_block(child, context);
+            lines.extend(child.lines().map(|line| format!("{indent}{line}")));
+        }
+    }
+    lines.join("\n")
+}
+
+fn render_table(rows: &[Vec<Vec<Inline>>], context: &Context) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return String::new();
+    }
+    let row = |cells: &[Vec<Inline>]| {
+        let mut cells: Vec<String> = cells
+            .iter()
+            .map(|cell| one_line(&inlines(cell, context)).replace('|', "\\vert{}"))
+            .collect();
+        cells.resize(columns, String::new());
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines = Vec::new();
+    lines.push(row(&rows[0]));
+    lines.push(format!(
+        "|{}|",
+        core::iter::repeat_n("---", columns)
+            .collect::<Vec<_>>()
+            .join("+")
+    ));
+    lines.extend(rows[1..].iter().map(|cells| row(cells)));
+    lines.join("\n")
+}
+
+/// Renders inline content with Org emphasis markers and links.
+fn inlines(content: &[Inline], context: &Context) -> String {
+    let mut output = String::new();
+    for inline in content {
+        match inline {
+            Inline::Text(text) => output += text,
+            Inline::Bold(inner) => output += &wrap("*", &inlines(inner, context)),
+            Inline::Italic(inner) => output += &wrap("/", &inlines(inner, context)),
+            Inline::Underline(inner) => output += &wrap("_", &inlines(inner, context)),
+            Inline::Strike(inner) => output += &wrap("+", &inlines(inner, context)),
+            Inline::Code(code) => output += &wrap("~", code),
+            Inline::Link { href, content } => match note_link_title(href, content) {
+                Some(title) => match context.ids.get(&title) {
+                    Some(id) => output += &format!("[[id:{id}][{}]]", link_text(&title)),
+                    None => output += &link_text(&title),
+                },
+                None => {
+                    let text = inlines(content, context);
+                    let target = href.replace('[', "%5B").replace(']', "%5D");
+                    output += &if text.is_empty() || text == *href {
+                        format!("[[{target}]]")
+                    } else {
+                        format!("[[{target}][{}]]", link_text(&text))
+                    };
+                }
+            },
+            Inline::Image(index) => {
+                if let Some(name) = context.images.get(*index) {
+                    output += &format!("[[file:{ATTACHMENTS}/{name}]]");
+                }
+            }
+            Inline::Break => output.push('\n'),
+        }
+    }
+    output
+}
+
+/// Wraps `inner` in an emphasis marker, keeping surrounding whitespace
+/// outside as Org requires.
+fn wrap(marker: &str, inner: &str) -> String {
+    let trimmed = inner.trim();
+    if trimmed.is_empty() {
+        return inner.to_string();
+    }
+    let start = &inner[..inner.len() - inner.trim_start().len()];
+    let end = &inner[inner.trim_end().len()..];
+    format!("{start}{marker}{trimmed}{marker}{end}")
+}
+
+fn link_text(text: &str) -> String {
+    one_line(text).replace('[', "{").replace(']', "}")
+}
+
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Turns a `#tag` into an Org tag, which allows only letters, digits,
+/// and `_@#%`.
+fn org_tag(tag: &str) -> String {
+    tag.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '_' | '@' | '#' | '%') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Breaks up whatever would make a paragraph line a heading, list item,
+/// or keyword, with the zero-width space Org suggests for this.
+fn protect_line_starts(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let rest = &line[digits..];
+            let starts_block = (digits == 0
+                && (line.starts_with('*')
+                    || line.starts_with("- ")
+                    || line.starts_with("+ ")
+                    || line.starts_with("# ")
+                    || line.starts_with("#+")
+                    || line.starts_with(':')
+                    || line.starts_with('|')
+                    || line.starts_with("-----")))
+                || (digits > 0 && (rest.starts_with(". ") || rest.starts_with(") ")));
+            if starts_block {
+                format!("\u{200B}{line}")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Formats an ISO 8601 local time, e.g. `2025-01-20T13:30:00`, as an
+/// inactive Org timestamp, e.g. `[2025-01-20 Mon 13:30]`.
+fn timestamp(iso: &str) -> String {
+    let parse = |range: core::ops::Range<usize>| iso.get(range).and_then(|s| s.parse::<u32>().ok());
+    let (Some(year), Some(month), Some(day)) = (parse(0..4), parse(5..7), parse(8..10)) else {
+        return iso.to_string();
+    };
+    let weekday = weekday(year, month, day);
+    match iso.get(11..16) {
+        Some(time) => format!("[{} {weekday} {time}]", &iso[..10]),
+        None => format!("[{} {weekday}]", &iso[..10]),
+    }
+}
+
+/// Returns the abbreviated day of the week of a Gregorian date.
+fn weekday(year: u32, month: u32, day: u32) -> &'static str {
+    const OFFSETS: [u32; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    const NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    let year = if month < 3 {
+        year.saturating_sub(1)
+    } else {
+        year
+    };
+    let index = (year + year / 4 - year / 100
+        + year / 400
+        + OFFSETS[(month.clamp(1, 12) - 1) as usize]
+        + day)
+        % 7;
+    NAMES[index as usize]
+}
@@ -0,0 +1,190 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Exports notes as PDF, for the fixed-layout copies records retention
+//! asks for: a PDF per note, or a single document with a table of
+//! contents. Pages are rendered from HTML by `cupsfilter`, which ships
+//! with macOS.
+
+use super::{ExportSummary, UniqueNames, base64, escape_xml as escape, file_name, html, write};
+use crate::{Error, Result, exec, markup::Document, notes::Note};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    format, fs,
+    path::{Path, PathBuf},
+    process,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Print adjustments to the site's stylesheet.
+const PRINT_STYLE: &str = "\
+@page { margin: 2cm; }
+body { max-width: none; margin: 0; padding: 0; }
+a { color: inherit; }
+section.note { page-break-before: always; }
+img, pre, table, blockquote { page-break-inside: avoid; }
+";
+
+/// Distinguishes the temporary HTML files of concurrent conversions.
+static CONVERSIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Writes a PDF per note into `dir`, which need not exist yet, in a
+/// directory per folder (under one per account, given several). Links
+/// between notes become plain text.
+pub fn export(notes: &[Note], dir: &Path) -> Result<ExportSummary> {
+    let accounts: BTreeSet<&str> = notes.iter().map(|note| note.account.as_str()).collect();
+    let mut names: BTreeMap<PathBuf, UniqueNames> = BTreeMap::new();
+    let mut summary = ExportSummary::default();
+
+    for note in notes {
+        let mut folder = PathBuf::new();
+        if accounts.len() > 1 {
+            folder.push(file_name(&note.account));
+        }
+        folder.push(file_name(&note.folder));
+        let stem = names
+            .entry(folder.clone())
+            .or_default()
+            .claim(&file_name(&note.name));
+
+        let document = Document::parse(&note.body_html);
+        let body = section(note, &document, &BTreeMap::new(), None);
+        let pdf = to_pdf(&page(note.name.trim(), &body))?;
+        write(&dir.join(folder).join(format!("{stem}.pdf")), pdf)?;
+        summary.notes += 1;
+        summary.attachments += document.images.len();
+    }
+    Ok(summary)
+}
+
+/// Writes every note into the single PDF at `file`, replacing it if it
+/// exists: a table of contents by folder, then a page per note. Links
+/// between notes go to their pages.
+pub fn export_combined(notes: &[Note], file: &Path) -> Result<ExportSummary> {
+    let accounts: BTreeSet<&str> = notes.iter().map(|note| note.account.as_str()).collect();
+    let mut links: BTreeMap<&str, String> = BTreeMap::new();
+    for (n, note) in notes.iter().enumerate() {
+        links
+            .entry(note.name.trim())
+            .or_insert_with(|| format!("#note-{}", n + 1));
+    }
+    let mut folders: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (n, note) in notes.iter().enumerate() {
+        let folder = if accounts.len() > 1 {
+            format!("{} / {}", note.account.trim(), note.folder.trim())
+        } else {
+            note.folder.trim().to_string()
+        };
+        folders.entry(folder).or_default().push(n);
+    }
+
+    let mut body = format!(
+        "<h1>Notes</h1>\n<p class=\"meta\">{} notes</p>\n",
+        notes.len()
+    );
+    body += "<h2>Contents</h2>\n";
+    for (folder, members) in &folders {
+        body += &format!("<h3>{}</h3>\n<ol>\n", escape(folder));
+        for &n in members {
+            body += &format!(
+                "<li><a href=\"#note-{}\">{}</a></li>\n",
+                n + 1,
+                escape(notes[n].name.trim())
+            );
+        }
+        body += "</ol>\n";
+    }
+
+    let mut summary = ExportSummary::default();
+    for members in folders.values() {
+        for &n in members {
+            let document = Document::parse(&notes[n].body_html);
+            body += &section(&notes[n], &document, &links, Some(n + 1));
+            summary.notes += 1;
+            summary.attachments += document.images.len();
+        }
+    }
+
+    write(file, to_pdf(&page("Notes", &body))?)?;
+    Ok(summary)
+}
+
+/// Renders one note: its title, where it's from, and its body, with its
+/// images inlined. Numbered sections start a page of their own.
+fn section(
+    note: &Note,
+    document: &Document,
+    links: &BTreeMap<&str, String>,
+    number: Option<usize>,
+) -> String {
+    let images: Vec<String> = document
+        .images
+        .iter()
+        .map(|image| format!("data:{};base64,{}", image.mime, base64(&image.data)))
+        .collect();
+    let renderer = html::Renderer {
+        document,
+        links,
+        images: &images,
+    };
+    let mut output = match number {
+        Some(number) => format!("<section class=\"note\" id=\"note-{number}\">\n"),
+        None => "<section>\n".to_string(),
+    };
+    output += &format!("<h1>{}</h1>\n", escape(note.name.trim()));
+    output += &format!(
+        "<p class=\"meta\">{} / {} &middot; Created {} &middot; Modified {}</p>\n",
+        escape(note.account.trim()),
+        escape(note.folder.trim()),
+        escape(&note.created.replacen('T', " ", 1)),
+        escape(&note.modified.replacen('T', " ", 1)),
+    );
+    output += &renderer.render(&note.name);
+    output += "</section>\n";
+    output
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{}</title>\n<style>\n{}{PRINT_STYLE}</style>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        escape(title),
+        html::STYLE,
+    )
+}
+
+/// Renders an HTML document as PDF with `cupsfilter`, which takes its
+/// input from a file.
+fn to_pdf(html: &str) -> Result<Vec<u8>> {
+    let path = std::env::temp_dir().join(format!(
+        "asimov-apple-pdf-{}-{}.html",
+        process::id(),
+        CONVERSIONS.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&path, html).map_err(|e| Error::Io {
+        context: "writing a note for conversion to PDF",
+        source: e,
+    })?;
+    let result = exec::run(
+        "cupsfilter",
+        [
+            "-i".as_ref(),
+            "text/html".as_ref(),
+            "-m".as_ref(),
+            "application/pdf".as_ref(),
+            path.as_os_str(),
+        ],
+        b"",
+    );
+    fs::remove_file(&path).ok();
+
+    let pdf = result?;
+    if !pdf.starts_with(b"%PDF") {
+        return Err(Error::Parse {
+            context: "converting a note to PDF",
+            message: "cupsfilter printed no PDF".to_string(),
+        });
+    }
+    Ok(pdf)
+}
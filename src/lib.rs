@@ -1,4 +1,100 @@
 // This is free and unencumbered software released into the public domain.
 
 #![no_std]
-#![forbid(unsafe_code)]
+#![deny(unsafe_code)]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "std")]
+pub mod bplist;
+
+#[cfg(feature = "std")]
+pub mod calendar;
+
+#[cfg(feature = "cli")]
+pub mod cli;
+
+#[cfg(feature = "std")]
+pub mod contacts;
+
+#[cfg(feature = "std")]
+pub mod embed;
+
+#[cfg(feature = "std")]
+pub mod error;
+
+#[cfg(feature = "std")]
+pub use error::{Error, Result};
+
+#[cfg(feature = "std")]
+pub mod eventkit;
+
+#[cfg(feature = "std")]
+pub mod exec;
+
+#[cfg(feature = "std")]
+pub mod export;
+
+#[cfg(feature = "std")]
+pub mod log;
+
+#[cfg(feature = "std")]
+pub mod mail;
+
+#[cfg(feature = "std")]
+pub mod markup;
+
+#[cfg(feature = "std")]
+pub mod messages;
+
+#[cfg(feature = "std")]
+pub mod music;
+
+#[cfg(feature = "std")]
+pub mod notes;
+
+#[cfg(feature = "std")]
+pub mod osascript;
+
+#[cfg(feature = "std")]
+pub mod photos;
+
+#[cfg(feature = "std")]
+pub mod provenance;
+
+#[cfg(feature = "std")]
+pub mod rdf;
+
+#[cfg(feature = "std")]
+pub mod recurrence;
+
+#[cfg(feature = "std")]
+pub mod reminders;
+
+#[cfg(feature = "std")]
+pub mod safari;
+
+#[cfg(feature = "std")]
+pub mod sandbox;
+
+#[cfg(feature = "std")]
+pub mod schema;
+
+#[cfg(feature = "cli")]
+pub mod search;
+
+#[cfg(feature = "cli")]
+pub mod signal;
+
+#[cfg(feature = "std")]
+pub mod sink;
+
+#[cfg(feature = "std")]
+pub mod stats;
+
+#[cfg(feature = "std")]
+pub mod throttle;
+
+#[cfg(feature = "std")]
+pub mod timezone;
@@ -0,0 +1,114 @@
+// This is free and unencumbered software released into the public domain.
+
+//! A log file that rotates itself by size and age, so that scheduled runs
+//! accumulate a history without filling the disk.
+
+use std::{
+    format,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// When to start a new log file, and how many old ones to keep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rotation {
+    /// Rotate before the file would grow beyond this many bytes.
+    pub max_size: u64,
+    /// Rotate when a run starts with the file older than this.
+    pub max_age: Option<Duration>,
+    /// How many rotated files (`PATH.1` being the newest) to keep.
+    pub keep: usize,
+}
+
+/// An append-only log file, renamed to `PATH.1` (and `PATH.1` to `PATH.2`,
+/// and so on) once it gets too big or too old.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Opens the log at `path`, first rotating it if it's too old or big.
+    pub fn open(path: &Path, rotation: Rotation) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        if let Ok(metadata) = fs::metadata(path) {
+            let born = metadata.created().or_else(|_| metadata.modified()).ok();
+            let age = born.and_then(|born| SystemTime::now().duration_since(born).ok());
+            let too_old = matches!((age, rotation.max_age), (Some(age), Some(max)) if age >= max);
+            if too_old || metadata.len() >= rotation.max_size {
+                shift(path, rotation.keep)?;
+            }
+        }
+        let file = append(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            rotation,
+            file,
+            size,
+        })
+    }
+
+    /// Returns the path of the current log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        shift(&self.path, self.rotation.keep)?;
+        self.file = append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.rotation.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Renames `PATH.N` to `PATH.N+1` for every kept file, dropping the
+/// oldest, and finally `PATH` to `PATH.1`.
+fn shift(path: &Path, keep: usize) -> io::Result<()> {
+    let numbered = |n: usize| {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    };
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+    match fs::remove_file(numbered(keep)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    for n in (1..keep).rev() {
+        match fs::rename(numbered(n), numbered(n + 1)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    fs::rename(path, numbered(1))
+}
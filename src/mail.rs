@@ -0,0 +1,544 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Apple Mail, read through Mail's scripting dictionary from JavaScript for
+//! Automation, which reads each property of every message in a mailbox at
+//! once rather than message by message.
+
+use crate::{Error, Result, timezone::LocalTime};
+use core::str::FromStr;
+use serde_json::{Value, json};
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+pub mod mime;
+pub mod rules;
+pub mod store;
+pub mod thread;
+
+/// The application name used in error messages.
+pub const APP: &str = "Mail";
+
+/// How messages are extracted, as recorded in their provenance.
+pub const BACKEND: &str = "mail";
+
+/// The prefix of the URNs identifying individual messages.
+pub const URN_PREFIX: &str = "urn:apple:mail:message:";
+
+/// The prefix of the URNs identifying conversations.
+pub const THREAD_URN_PREFIX: &str = "urn:apple:mail:thread:";
+
+/// The prefix of the URNs identifying rules.
+pub const RULE_URN_PREFIX: &str = "urn:apple:mail:rule:";
+
+/// The prefix of the URNs identifying smart mailboxes.
+pub const SMART_MAILBOX_URN_PREFIX: &str = "urn:apple:mail:smart-mailbox:";
+
+/// How many characters of a message's body its snippet keeps.
+const SNIPPET_LENGTH: usize = 200;
+
+/// The JavaScript that every script starts with: it defines `Mail`, and
+/// `date`, formatting a date as ISO 8601 local time.
+const PRELUDE: &str = r#"
+var Mail = Application('Mail');
+function pad(n) {
+    return ('0' + n).slice(-2);
+}
+function date(d) {
+    if (!d) return null;
+    return d.getFullYear() + '-' + pad(d.getMonth() + 1) + '-' + pad(d.getDate()) + 'T' +
+        pad(d.getHours()) + ':' + pad(d.getMinutes()) + ':' + pad(d.getSeconds());
+}
+"#;
+
+/// Where messages are read from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Mail, through its scripting dictionary.
+    #[default]
+    Mail,
+    /// Mail's files, through [`store`].
+    Emlx,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        match input {
+            "mail" => Ok(Backend::Mail),
+            "emlx" => Ok(Backend::Emlx),
+            _ => Err(format!(
+                "unsupported backend: {input} (expected mail or emlx)"
+            )),
+        }
+    }
+}
+
+/// The account that mailboxes "On My Mac" are in, as far as filters go.
+pub const ON_MY_MAC: &str = "On My Mac";
+
+/// Which messages to extract: those in the accounts and mailboxes
+/// selected, by name or by a glob (as in `Work*`), ignoring case, that
+/// match each of the other criteria given.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Only the messages received at or after this time.
+    pub since: Option<LocalTime>,
+    /// Only the messages received before this time.
+    pub until: Option<LocalTime>,
+    pub unread: bool,
+    pub flagged: bool,
+    /// Only the messages from senders whose address contains any of
+    /// these, ignoring case.
+    pub from: Vec<String>,
+    /// Only the messages to, copied to, or blind copied to recipients
+    /// whose address contains any of these, ignoring case.
+    pub to: Vec<String>,
+    /// Only the messages in accounts matching these, if any are given.
+    pub accounts: Vec<String>,
+    /// None of the messages in accounts matching these.
+    pub excluded_accounts: Vec<String>,
+    /// Only the messages in mailboxes matching these, if any are given.
+    pub mailboxes: Vec<String>,
+    /// None of the messages in mailboxes matching these.
+    pub excluded_mailboxes: Vec<String>,
+}
+
+impl Filter {
+    /// Returns whether the mailbox `name` in `account` (`On My Mac` for
+    /// mailboxes in none) is selected, as the script works it out.
+    pub fn selects(&self, account: &str, name: &str) -> bool {
+        let selected = |included: &[String], excluded: &[String], name: &str| {
+            let matches = |globs: &[String]| globs.iter().any(|glob| glob_matches(glob, name));
+            (included.is_empty() || matches(included)) && !matches(excluded)
+        };
+        selected(&self.accounts, &self.excluded_accounts, account)
+            && selected(&self.mailboxes, &self.excluded_mailboxes, name)
+    }
+}
+
+/// Returns whether a glob, in which `*` matches any run of characters and
+/// `?` any one, matches all of a name, ignoring case.
+fn glob_matches(glob: &str, name: &str) -> bool {
+    let glob: Vec<char> = glob.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    // Where the last `*` was, and where in the name it matched up to:
+    let (mut g, mut n, mut star) = (0, 0, None);
+    while n < name.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, n));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                g += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_g, star_n)) => {
+                    star = Some((star_g, star_n + 1));
+                    g = star_g + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+/// Returns a glob, in which `*` matches any run of characters and `?` any
+/// one, as the source of a JavaScript regular expression matching all of
+/// a name.
+fn glob_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '\\' | '^' | '$' | '.' | '|' | '+' | '(' | ')' | '[' | ']' | '{' | '}' => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Returns the value of the first header named `name` (ignoring case) of
+/// a message as it came, in RFC 5322 form, with folded lines joined.
+pub fn header(source: &str, name: &str) -> Option<String> {
+    let mut value: Option<String> = None;
+    for line in source.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some(value) = value.as_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        if value.is_some() {
+            break;
+        }
+        if let Some((field, rest)) = line.split_once(':')
+            && field.trim().eq_ignore_ascii_case(name)
+        {
+            value = Some(rest.trim().to_string());
+        }
+    }
+    value
+}
+
+/// Everything a script extracts: every message, oldest first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mailstore {
+    pub messages: Vec<Message>,
+}
+
+/// Someone a message is from or to.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Address {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+impl Address {
+    /// Parses an address as Mail gives senders, as in
+    /// `Ada Lovelace <ada@example.com>` or `ada@example.com`, returning
+    /// `None` if there is no address at all.
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let (name, email) = match (input.rfind('<'), input.ends_with('>')) {
+            (Some(start), true) => (&input[..start], &input[start + 1..input.len() - 1]),
+            _ => ("", input),
+        };
+        let name = name.trim().trim_matches('"').trim();
+        let email = email.trim();
+        (!email.is_empty()).then(|| Address {
+            name: (!name.is_empty()).then(|| name.to_string()),
+            email: email.to_string(),
+        })
+    }
+
+    /// Returns the JSON-LD record for this address: a schema.org `Person`.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "Person",
+            "name": self.name,
+            "email": self.email,
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// A single message, as Mail's scripting dictionary describes it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Message {
+    /// Mail's id for the message, as in `12345`, which is also the name
+    /// of the file Mail keeps it in.
+    pub id: String,
+    /// Its `Message-ID` header, without the angle brackets.
+    pub message_id: Option<String>,
+    /// The `Message-ID` of the message it replies to, from its
+    /// `In-Reply-To` header.
+    pub in_reply_to: Option<String>,
+    /// The `Message-ID`s of the messages before it in its conversation,
+    /// oldest first, from its `References` header.
+    pub references: Vec<String>,
+    pub subject: Option<String>,
+    pub sender: Option<Address>,
+    pub to: Vec<Address>,
+    pub cc: Vec<Address>,
+    pub bcc: Vec<Address>,
+    /// When it was sent and received, in local time.
+    pub sent: Option<String>,
+    pub received: Option<String>,
+    /// The name of the mailbox it is in, as in `INBOX`.
+    pub mailbox: String,
+    /// The name of the account the mailbox is in, or `None` for mailboxes
+    /// "On My Mac".
+    pub account: Option<String>,
+    /// Its body as plain text; `None` when extracted without bodies.
+    pub body: Option<String>,
+    /// The message as it came, in RFC 5322 form, with every header; `None`
+    /// when extracted without it.
+    pub source: Option<String>,
+}
+
+impl Message {
+    /// Returns the stable URN identifying this message.
+    pub fn urn(&self) -> String {
+        format!("{URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the first characters of the body, on one line.
+    pub fn snippet(&self) -> Option<String> {
+        let body = self.body.as_deref()?;
+        let words: Vec<&str> = body.split_whitespace().collect();
+        let text = words.join(" ");
+        if text.is_empty() {
+            return None;
+        }
+        Some(match text.char_indices().nth(SNIPPET_LENGTH) {
+            Some((end, _)) => format!("{}…", text[..end].trim_end()),
+            None => text,
+        })
+    }
+
+    /// Returns the JSON-LD record for this message: a schema.org
+    /// `EmailMessage`. Unset fields are left out.
+    pub fn to_json(&self) -> Value {
+        let addresses =
+            |list: &[Address]| -> Vec<Value> { list.iter().map(Address::to_json).collect() };
+        let mut record = json!({
+            "@type": "EmailMessage",
+            "@id": self.urn(),
+            "identifier": self.message_id,
+            "name": self.subject,
+            "sender": self.sender.as_ref().map(Address::to_json),
+            "toRecipient": (!self.to.is_empty()).then(|| addresses(&self.to)),
+            "ccRecipient": (!self.cc.is_empty()).then(|| addresses(&self.cc)),
+            "bccRecipient": (!self.bcc.is_empty()).then(|| addresses(&self.bcc)),
+            "dateSent": self.sent,
+            "dateReceived": self.received,
+            "isPartOf": self.mailbox,
+            "account": self.account,
+            "abstract": self.snippet(),
+            "text": self.body,
+            "source": "apple-mail",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Returns the script that extracts every message in the mailboxes
+/// `filter` selects, of every account and "On My Mac", as JSON in the
+/// format [`parse`] reads, with their bodies only if `body` is true, as
+/// reading them is what takes longest, and as they came only if `source`
+/// is true, and otherwise their headers. Mail itself selects the messages
+/// by all but their recipients, which Mail can't query.
+pub fn script(filter: &Filter, body: bool, source: bool) -> String {
+    let globs = |globs: &[String]| -> Vec<String> { globs.iter().map(|g| glob_regex(g)).collect() };
+    let filter = json!({
+        "accounts": globs(&filter.accounts),
+        "excludedAccounts": globs(&filter.excluded_accounts),
+        "mailboxes": globs(&filter.mailboxes),
+        "excludedMailboxes": globs(&filter.excluded_mailboxes),
+        "since": filter.since.as_ref().map(LocalTime::as_str),
+        "until": filter.until.as_ref().map(LocalTime::as_str),
+        "unread": filter.unread,
+        "flagged": filter.flagged,
+        "from": filter.from,
+        "to": filter.to.iter().map(|to| to.to_lowercase()).collect::<Vec<_>>(),
+    });
+    format!(
+        r#"
+{PRELUDE}
+var withBody = {body};
+var withSource = {source};
+var filter = {filter};
+function matches(globs, name) {{
+    return globs.some(function (glob) {{ return new RegExp(glob, 'i').test(name); }});
+}}
+function selected(included, excluded, name) {{
+    return (included.length === 0 || matches(included, name)) && !matches(excluded, name);
+}}
+function localDate(text) {{
+    var p = text.split(/[-T:]/).map(Number);
+    return new Date(p[0], p[1] - 1, p[2], p[3], p[4], p[5]);
+}}
+var conditions = [];
+if (filter.since !== null) conditions.push({{ dateReceived: {{ _greaterThanEquals: localDate(filter.since) }} }});
+if (filter.until !== null) conditions.push({{ dateReceived: {{ _lessThan: localDate(filter.until) }} }});
+if (filter.unread) conditions.push({{ readStatus: false }});
+if (filter.flagged) conditions.push({{ flaggedStatus: true }});
+// Mail compares text ignoring case:
+if (filter.from.length > 0) {{
+    var senders = filter.from.map(function (address) {{ return {{ sender: {{ _contains: address }} }}; }});
+    conditions.push(senders.length === 1 ? senders[0] : {{ _or: senders }});
+}}
+function query(messages) {{
+    if (conditions.length === 0) return messages;
+    return messages.whose(conditions.length === 1 ? conditions[0] : {{ _and: conditions }});
+}}
+function addressedTo(lists) {{
+    if (filter.to.length === 0) return true;
+    return lists.some(function (list) {{
+        return list.some(function (address) {{
+            var email = address.email.toLowerCase();
+            return filter.to.some(function (to) {{ return email.indexOf(to) >= 0; }});
+        }});
+    }});
+}}
+var output = {{ messages: [] }};
+function addresses(addresses, names) {{
+    var found = [];
+    for (var i = 0; i < addresses.length; i++) {{
+        found.push({{ name: names[i] || null, email: addresses[i] }});
+    }}
+    return found;
+}}
+// Each property of every message in the mailbox at once, as asking
+// message by message is much slower:
+function read(mailbox, account) {{
+    var name = mailbox.name();
+    if (!selected(filter.mailboxes, filter.excludedMailboxes, name)) return;
+    var messages = query(mailbox.messages);
+    var ids = messages.id();
+    if (ids.length === 0) return;
+    var messageIds = messages.messageId();
+    var subjects = messages.subject();
+    var senders = messages.sender();
+    var sent = messages.dateSent();
+    var received = messages.dateReceived();
+    var to = [messages.toRecipients.address(), messages.toRecipients.name()];
+    var cc = [messages.ccRecipients.address(), messages.ccRecipients.name()];
+    var bcc = [messages.bccRecipients.address(), messages.bccRecipients.name()];
+    var bodies = withBody ? messages.content() : [];
+    var sources = withSource ? messages.source() : [];
+    // Only the headers, for threading, when not the whole message:
+    var headers = withSource ? [] : messages.allHeaders();
+    for (var i = 0; i < ids.length; i++) {{
+        var recipients = [
+            addresses(to[0][i], to[1][i]),
+            addresses(cc[0][i], cc[1][i]),
+            addresses(bcc[0][i], bcc[1][i]),
+        ];
+        if (!addressedTo(recipients)) continue;
+        output.messages.push({{
+            id: ids[i],
+            messageId: messageIds[i] || null,
+            subject: subjects[i] || null,
+            sender: senders[i] || null,
+            to: recipients[0],
+            cc: recipients[1],
+            bcc: recipients[2],
+            sent: date(sent[i]),
+            received: date(received[i]),
+            mailbox: name,
+            account: account,
+            body: withBody ? bodies[i] || '' : null,
+            source: withSource ? sources[i] || null : null,
+            headers: withSource ? null : headers[i] || null,
+        }});
+    }}
+}}
+Mail.accounts().forEach(function (account) {{
+    var name = account.name();
+    if (!selected(filter.accounts, filter.excludedAccounts, name)) return;
+    account.mailboxes().forEach(function (mailbox) {{ read(mailbox, name); }});
+}});
+if (selected(filter.accounts, filter.excludedAccounts, '{ON_MY_MAC}')) {{
+    Mail.mailboxes().forEach(function (mailbox) {{ read(mailbox, null); }});
+}}
+JSON.stringify(output);
+"#
+    )
+}
+
+/// Parses the output of [`script`], sorting the messages oldest first.
+pub fn parse(output: &str) -> Result<Mailstore> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Mailstore::default()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading mail",
+            message: e.to_string(),
+        })?,
+    };
+    let mut messages = output
+        .get("messages")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .map(parse_message)
+        .collect::<Result<Vec<_>>>()?;
+    messages.sort_by(|a, b| a.received.cmp(&b.received));
+    Ok(Mailstore { messages })
+}
+
+fn parse_message(record: &Value) -> Result<Message> {
+    let text = |key: &str| {
+        record
+            .get(key)
+            .and_then(Value::as_str)
+            .filter(|text| !text.is_empty())
+            .map(String::from)
+    };
+    let id = match record.get("id") {
+        Some(Value::Number(id)) => id.to_string(),
+        Some(Value::String(id)) if !id.is_empty() => id.clone(),
+        _ => {
+            return Err(Error::Parse {
+                context: "reading mail message id",
+                message: "missing id field".to_string(),
+            });
+        }
+    };
+    let addresses = |key: &str| -> Vec<Address> {
+        record
+            .get(key)
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(|address| {
+                let email = address.get("email").and_then(Value::as_str)?.trim();
+                let name = address
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty() && *name != email);
+                (!email.is_empty()).then(|| Address {
+                    name: name.map(String::from),
+                    email: email.to_string(),
+                })
+            })
+            .collect()
+    };
+    let source = text("source");
+    let headers = text("headers")
+        .or_else(|| source.clone())
+        .unwrap_or_default();
+    let field = |name: &str| header(&headers, name).unwrap_or_default();
+    Ok(Message {
+        id,
+        message_id: text("messageId").map(|id| {
+            id.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        }),
+        in_reply_to: thread::message_ids(&field("In-Reply-To"))
+            .into_iter()
+            .next(),
+        references: thread::message_ids(&field("References")),
+        subject: text("subject"),
+        sender: text("sender").as_deref().and_then(Address::parse),
+        to: addresses("to"),
+        cc: addresses("cc"),
+        bcc: addresses("bcc"),
+        sent: text("sent"),
+        received: text("received"),
+        mailbox: text("mailbox").unwrap_or_default(),
+        account: text("account"),
+        body: record
+            .get("body")
+            .and_then(Value::as_str)
+            .map(|body| body.trim().to_string()),
+        source,
+    })
+}
@@ -0,0 +1,402 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads the parts of a message as it came (RFC 2045 and on), for its
+//! attachments, which Mail's scripting dictionary tells too little about.
+
+use super::header;
+use crate::export::sha256_hex;
+use serde_json::{Value, json};
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// How deep multiparts may nest before the rest is ignored.
+const MAX_DEPTH: usize = 16;
+
+/// A file attached to a message.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Attachment {
+    /// Its file name, as in `report.pdf`.
+    pub name: Option<String>,
+    /// Its MIME type, as in `application/pdf`.
+    pub mime: String,
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    /// Returns the SHA-256 of its content, in hex.
+    pub fn sha256(&self) -> String {
+        sha256_hex(&self.data)
+    }
+
+    /// Returns the file name extension for the attachment: that of its
+    /// name, if it has one, or else the usual one for its type.
+    pub fn extension(&self) -> &str {
+        let named = self
+            .name
+            .as_deref()
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, extension)| extension)
+            .filter(|extension| {
+                !extension.is_empty()
+                    && extension.len() <= 8
+                    && extension.chars().all(|c| c.is_ascii_alphanumeric())
+            });
+        if let Some(extension) = named {
+            return extension;
+        }
+        match self.mime.as_str() {
+            "image/jpeg" => "jpg",
+            "text/plain" => "txt",
+            "message/rfc822" => "eml",
+            mime => mime
+                .split_once('/')
+                .map(|(_, subtype)| subtype)
+                .filter(|ext| ext.chars().all(|c| c.is_ascii_alphanumeric()))
+                .unwrap_or("bin"),
+        }
+    }
+
+    /// Returns the JSON-LD record for this attachment: a schema.org
+    /// `MediaObject`, linking its file from `contentUrl` if it was
+    /// written somewhere.
+    pub fn to_json(&self, url: Option<&str>) -> Value {
+        let mut record = json!({
+            "@type": "MediaObject",
+            "name": self.name,
+            "encodingFormat": self.mime,
+            "contentSize": self.data.len(),
+            "sha256": self.sha256(),
+            "contentUrl": url,
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Returns the attachments of a message as it came, in order: every part
+/// that isn't text of the message itself, as in a file attached or an
+/// image shown inline.
+pub fn attachments(source: &str) -> Vec<Attachment> {
+    let mut found = Vec::new();
+    collect(source, 0, &mut found);
+    found
+}
+
+/// Returns the body of a message as it came, as plain text: its first
+/// text part that isn't attached, preferring plain text to HTML.
+pub fn text(source: &str) -> Option<String> {
+    let mut texts = Vec::new();
+    collect_text(source, 0, &mut texts);
+    if let Some((_, text)) = texts.iter().find(|(mime, _)| mime == "text/plain") {
+        return Some(text.trim().to_string());
+    }
+    let (_, html) = texts.iter().find(|(mime, _)| mime == "text/html")?;
+    html2text::from_read(html.as_bytes(), usize::MAX)
+        .ok()
+        .map(|text| text.trim().to_string())
+}
+
+fn collect_text(part: &str, depth: usize, found: &mut Vec<(String, String)>) {
+    let body = split(part).1;
+    let content_type = header(part, "Content-Type").unwrap_or_default();
+    let (mime, params) = parse_type(&content_type);
+    let mime = if mime.is_empty() {
+        "text/plain".to_string()
+    } else {
+        mime
+    };
+    if mime.starts_with("multipart/") {
+        if let Some(boundary) = param(&params, "boundary")
+            && depth < MAX_DEPTH
+        {
+            for part in parts(body, &boundary) {
+                collect_text(part, depth + 1, found);
+            }
+        }
+        return;
+    }
+    let disposition = header(part, "Content-Disposition").unwrap_or_default();
+    let (kind, disposition) = parse_type(&disposition);
+    let name = param(&disposition, "filename").or_else(|| param(&params, "name"));
+    if kind == "attachment" || name.is_some() || !mime.starts_with("text/") {
+        return;
+    }
+    let data = decode(part, body);
+    let charset = param(&params, "charset").unwrap_or_default();
+    found.push((mime, decode_charset(&data, &charset)));
+}
+
+fn collect(part: &str, depth: usize, found: &mut Vec<Attachment>) {
+    let body = split(part).1;
+    let content_type = header(part, "Content-Type").unwrap_or_default();
+    let (mime, params) = parse_type(&content_type);
+    let mime = if mime.is_empty() {
+        "text/plain".to_string()
+    } else {
+        mime
+    };
+    if mime.starts_with("multipart/") {
+        if let Some(boundary) = param(&params, "boundary")
+            && depth < MAX_DEPTH
+        {
+            for part in parts(body, &boundary) {
+                collect(part, depth + 1, found);
+            }
+        }
+        return;
+    }
+    let disposition = header(part, "Content-Disposition").unwrap_or_default();
+    let (kind, disposition) = parse_type(&disposition);
+    let name = param(&disposition, "filename").or_else(|| param(&params, "name"));
+    // Text without a name is the message's own:
+    if kind != "attachment" && name.is_none() && mime.starts_with("text/") {
+        return;
+    }
+    let data = decode(part, body);
+    found.push(Attachment { name, mime, data });
+}
+
+/// Decodes the body of a part by its `Content-Transfer-Encoding`.
+fn decode(part: &str, body: &str) -> Vec<u8> {
+    let encoding = header(part, "Content-Transfer-Encoding")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match encoding.trim() {
+        "base64" => decode_base64(body),
+        "quoted-printable" => decode_quoted_printable(body),
+        _ => body.as_bytes().to_vec(),
+    }
+}
+
+/// Decodes text in a charset, UTF-8 or Latin-1, taking any other for
+/// UTF-8.
+fn decode_charset(bytes: &[u8], charset: &str) -> String {
+    let latin1 = ["iso-8859-1", "latin1", "windows-1252", "us-ascii"]
+        .iter()
+        .any(|name| charset.eq_ignore_ascii_case(name));
+    if latin1 {
+        bytes.iter().map(|b| char::from(*b)).collect()
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+/// Returns the headers of a message as it came, without its body.
+pub fn headers(message: &str) -> &str {
+    split(message).0
+}
+
+/// Splits a part into its headers and its body.
+fn split(part: &str) -> (&str, &str) {
+    // A part may have no headers at all:
+    for separator in ["\r\n", "\n"] {
+        if let Some(body) = part.strip_prefix(separator) {
+            return ("", body);
+        }
+    }
+    for separator in ["\r\n\r\n", "\n\n"] {
+        if let Some(end) = part.find(separator) {
+            return (&part[..end], &part[end + separator.len()..]);
+        }
+    }
+    (part, "")
+}
+
+/// Returns the parts of a multipart body, between its boundaries.
+fn parts<'a>(body: &'a str, boundary: &str) -> Vec<&'a str> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut offset = 0;
+    for line in body.split_inclusive('\n') {
+        let text = line.trim_end();
+        if text == delimiter || text == format!("{delimiter}--") {
+            if let Some(start) = start {
+                // The line break before a boundary belongs to it:
+                let part = &body[start..offset];
+                let part = part.strip_suffix('\n').unwrap_or(part);
+                parts.push(part.strip_suffix('\r').unwrap_or(part));
+            }
+            if text != delimiter {
+                return parts;
+            }
+            start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    parts
+}
+
+/// Parses a header like `Content-Type` into its value, lowercased, and
+/// its parameters, with their names lowercased.
+fn parse_type(value: &str) -> (String, Vec<(String, String)>) {
+    let mut fields = split_params(value).into_iter();
+    let kind = fields
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let params = fields
+        .filter_map(|field| {
+            let (name, value) = field.split_once('=')?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .map_or_else(|| value.to_string(), |value| value.replace("\\\"", "\""));
+            Some((name.trim().to_ascii_lowercase(), value))
+        })
+        .collect();
+    (kind, params)
+}
+
+/// Splits a header value at the semicolons that aren't quoted.
+fn split_params(value: &str) -> Vec<String> {
+    let (mut fields, mut field, mut quoted, mut escaped) =
+        (Vec::new(), String::new(), false, false);
+    for c in value.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                fields.push(core::mem::take(&mut field));
+                continue;
+            }
+            _ => {}
+        }
+        field.push(c);
+    }
+    fields.push(field);
+    fields
+}
+
+/// Returns a parameter, decoded: as given, in RFC 2231 form (as in
+/// `filename*=utf-8''r%C3%A9sum%C3%A9.pdf`), maybe split in several, or
+/// as RFC 2047 encoded words, as many mail programs write them.
+fn param(params: &[(String, String)], name: &str) -> Option<String> {
+    if let Some((_, value)) = params.iter().find(|(key, _)| key == name) {
+        return Some(decode_words(value)).filter(|value| !value.is_empty());
+    }
+    let mut pieces: Vec<(usize, bool, &str)> = params
+        .iter()
+        .filter_map(|(key, value)| {
+            let rest = key.strip_prefix(name)?.strip_prefix('*')?;
+            let (rest, encoded) = match rest.strip_suffix('*') {
+                Some(rest) => (rest, true),
+                None => (rest, rest.is_empty()),
+            };
+            let index = if rest.is_empty() {
+                0
+            } else {
+                rest.parse().ok()?
+            };
+            Some((index, encoded, value.as_str()))
+        })
+        .collect();
+    pieces.sort_by_key(|(index, ..)| *index);
+    let mut bytes = Vec::new();
+    for (index, encoded, value) in pieces {
+        if !encoded {
+            bytes.extend_from_slice(value.as_bytes());
+            continue;
+        }
+        // The first piece starts with its charset and language:
+        let value = match value.splitn(3, '\'').collect::<Vec<_>>()[..] {
+            [_, _, value] if index == 0 => value,
+            _ => value,
+        };
+        bytes.extend(percent_encoding::percent_decode_str(value));
+    }
+    let value = String::from_utf8_lossy(&bytes).into_owned();
+    (!value.is_empty()).then_some(value)
+}
+
+/// Decodes the RFC 2047 encoded words in a header value, as in
+/// `=?utf-8?B?cmVwb3J0LnBkZg==?=`.
+pub fn decode_words(value: &str) -> String {
+    let mut text = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let word = &rest[start + 2..];
+        let decoded = word.split_once('?').and_then(|(charset, word)| {
+            let (encoding, word) = word.split_once('?')?;
+            let (encoded, _) = word.split_once("?=")?;
+            let bytes = match encoding {
+                "B" | "b" => decode_base64(encoded),
+                "Q" | "q" => decode_quoted_printable(&encoded.replace('_', " ")),
+                _ => return None,
+            };
+            let decoded = decode_charset(&bytes, charset.split('*').next().unwrap_or_default());
+            let length = charset.len() + encoding.len() + encoded.len() + 6;
+            Some((decoded, length))
+        });
+        match decoded {
+            Some((decoded, length)) => {
+                // White space between encoded words is left out:
+                let before = &rest[..start];
+                if !(after_word && before.trim().is_empty()) {
+                    text.push_str(before);
+                }
+                text.push_str(&decoded);
+                rest = &rest[start + length..];
+                after_word = true;
+            }
+            None => {
+                text.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+fn decode_base64(text: &str) -> Vec<u8> {
+    let encoded: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let url = format!("data:application/octet-stream;base64,{encoded}");
+    data_url::DataUrl::process(&url)
+        .ok()
+        .and_then(|url| url.decode_to_vec().ok())
+        .map(|(data, _)| data)
+        .unwrap_or_default()
+}
+
+fn decode_quoted_printable(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut data = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'=' if bytes[i + 1..].starts_with(b"\r\n") => i += 3,
+            b'=' if bytes[i + 1..].starts_with(b"\n") => i += 2,
+            b'=' if i + 2 < bytes.len() => {
+                let hex = core::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(byte) => {
+                        data.push(byte);
+                        i += 3;
+                    }
+                    None => {
+                        data.push(b'=');
+                        i += 1;
+                    }
+                }
+            }
+            byte => {
+                data.push(byte);
+                i += 1;
+            }
+        }
+    }
+    data
+}
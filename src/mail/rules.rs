@@ -0,0 +1,366 @@
+// This is free and unencumbered software released into the public domain.
+
+//! How mail is organized and filtered: Mail's rules, read through its
+//! scripting dictionary, and its smart mailboxes, which the dictionary
+//! leaves out, read from the property list Mail keeps them in.
+
+use super::{PRELUDE, RULE_URN_PREFIX, SMART_MAILBOX_URN_PREFIX};
+use crate::{Error, Result, exec};
+use serde_json::{Map, Value, json};
+use std::{
+    ffi::OsStr,
+    format,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The property lists Mail keeps smart mailboxes in, in its `MailData`
+/// directory: synced through iCloud, or not.
+const SMART_MAILBOX_FILES: [&str; 2] = ["SyncedSmartMailboxes.plist", "SmartMailboxes.plist"];
+
+/// What a rule or a smart mailbox looks for in a message.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Condition {
+    /// What it looks at, as Mail names it, as in `from` or `subject`.
+    pub field: String,
+    /// How it compares, as in `does contain value`.
+    pub qualifier: Option<String>,
+    /// What it compares with.
+    pub expression: Option<String>,
+    /// The header it looks at, if `field` is one.
+    pub header: Option<String>,
+}
+
+impl Condition {
+    fn to_json(&self) -> Value {
+        let mut record = json!({
+            "field": self.field,
+            "qualifier": self.qualifier,
+            "expression": self.expression,
+            "header": self.header,
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// What a rule does with the messages it matches.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Action {
+    /// What it does: `move`, `copy`, `delete`, `markRead`, `markFlagged`,
+    /// `forward`, `redirect`, `reply`, `playSound`, `runScript`, `color`,
+    /// or `stop` (evaluating rules).
+    pub kind: String,
+    /// What it does it with, as in the mailbox a message is moved to.
+    pub value: Option<String>,
+}
+
+/// A rule, applied to messages as they arrive.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rule {
+    pub name: String,
+    /// Where it comes in the order rules are applied in, from 1.
+    pub position: usize,
+    pub enabled: bool,
+    /// Whether a message must meet all its conditions, or any.
+    pub all: bool,
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+}
+
+impl Rule {
+    /// Returns the stable URN identifying this rule, by its name.
+    pub fn urn(&self) -> String {
+        format!("{RULE_URN_PREFIX}{}", encode(&self.name))
+    }
+
+    /// Returns the JSON-LD record for this rule.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "@type": "Thing",
+            "@id": self.urn(),
+            "additionalType": "rule",
+            "name": self.name,
+            "position": self.position,
+            "enabled": self.enabled,
+            "match": if self.all { "all" } else { "any" },
+            "conditions": self.conditions.iter().map(Condition::to_json).collect::<Vec<_>>(),
+            "actions": self
+                .actions
+                .iter()
+                .map(|action| {
+                    let mut record = json!({ "type": action.kind });
+                    if let Some(value) = &action.value {
+                        record["value"] = value.clone().into();
+                    }
+                    record
+                })
+                .collect::<Vec<_>>(),
+            "source": "apple-mail",
+        })
+    }
+}
+
+/// A smart mailbox: the messages, wherever they are, that meet its
+/// conditions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SmartMailbox {
+    /// Mail's id for it, or else its name.
+    pub id: String,
+    pub name: String,
+    /// Whether a message must meet all its conditions, or any.
+    pub all: bool,
+    pub conditions: Vec<Condition>,
+}
+
+impl SmartMailbox {
+    /// Returns the stable URN identifying this smart mailbox.
+    pub fn urn(&self) -> String {
+        format!("{SMART_MAILBOX_URN_PREFIX}{}", encode(&self.id))
+    }
+
+    /// Returns the JSON-LD record for this smart mailbox: a schema.org
+    /// `Collection`.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "@type": "Collection",
+            "@id": self.urn(),
+            "additionalType": "smartMailbox",
+            "name": self.name,
+            "match": if self.all { "all" } else { "any" },
+            "conditions": self.conditions.iter().map(Condition::to_json).collect::<Vec<_>>(),
+            "source": "apple-mail",
+        })
+    }
+}
+
+fn encode(text: &str) -> String {
+    percent_encoding::utf8_percent_encode(text, percent_encoding::NON_ALPHANUMERIC).to_string()
+}
+
+/// Returns the script that extracts every rule, in order, as JSON in the
+/// format [`parse`] reads.
+pub fn script() -> String {
+    format!(
+        r#"
+{PRELUDE}
+// Mailboxes a rule names may be gone:
+function mailbox(property) {{
+    try {{
+        var box = property();
+        return box ? box.name() : null;
+    }} catch (e) {{
+        return null;
+    }}
+}}
+var output = {{ rules: [] }};
+Mail.rules().forEach(function (rule) {{
+    var conditions = rule.ruleConditions().map(function (condition) {{
+        return {{
+            field: condition.ruleType(),
+            qualifier: condition.qualifier(),
+            expression: condition.expression() || null,
+            header: condition.header() || null,
+        }};
+    }});
+    var actions = [];
+    if (rule.shouldMoveMessage()) actions.push({{ type: 'move', value: mailbox(rule.moveMessage) }});
+    if (rule.shouldCopyMessage()) actions.push({{ type: 'copy', value: mailbox(rule.copyMessage) }});
+    if (rule.deleteMessage()) actions.push({{ type: 'delete' }});
+    if (rule.markRead()) actions.push({{ type: 'markRead' }});
+    if (rule.markFlagged()) actions.push({{ type: 'markFlagged', value: String(rule.markFlagIndex()) }});
+    if (rule.forwardMessage()) actions.push({{ type: 'forward', value: rule.forwardMessage() }});
+    if (rule.redirectMessage()) actions.push({{ type: 'redirect', value: rule.redirectMessage() }});
+    if (rule.replyText()) actions.push({{ type: 'reply', value: rule.replyText() }});
+    if (rule.playSound()) actions.push({{ type: 'playSound', value: rule.playSound() }});
+    if (rule.runScript()) actions.push({{ type: 'runScript', value: String(rule.runScript()) }});
+    if (rule.colorMessage() !== 'none') actions.push({{ type: 'color', value: rule.colorMessage() }});
+    if (rule.stopEvaluatingRules()) actions.push({{ type: 'stop' }});
+    output.rules.push({{
+        name: rule.name(),
+        enabled: rule.enabled(),
+        all: rule.allConditionsMustBeMet(),
+        conditions: conditions,
+        actions: actions,
+    }});
+}});
+JSON.stringify(output);
+"#
+    )
+}
+
+/// Parses the output of [`script`], numbering the rules in order.
+pub fn parse(output: &str) -> Result<Vec<Rule>> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Vec::new()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading mail rules",
+            message: e.to_string(),
+        })?,
+    };
+    let rules = output
+        .get("rules")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
+    Ok(rules
+        .iter()
+        .enumerate()
+        .map(|(index, rule)| Rule {
+            name: text(rule, "name").unwrap_or_default(),
+            position: index + 1,
+            enabled: rule.get("enabled").and_then(Value::as_bool).unwrap_or(true),
+            all: rule.get("all").and_then(Value::as_bool).unwrap_or(true),
+            conditions: list(rule, "conditions")
+                .iter()
+                .filter_map(|condition| {
+                    Some(Condition {
+                        field: text(condition, "field")?,
+                        qualifier: text(condition, "qualifier"),
+                        expression: text(condition, "expression"),
+                        header: text(condition, "header"),
+                    })
+                })
+                .collect(),
+            actions: list(rule, "actions")
+                .iter()
+                .filter_map(|action| {
+                    Some(Action {
+                        kind: text(action, "type")?,
+                        value: text(action, "value"),
+                    })
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+fn text(record: &Value, key: &str) -> Option<String> {
+    record
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|text| !text.is_empty())
+        .map(String::from)
+}
+
+fn list<'a>(record: &'a Value, key: &str) -> &'a [Value] {
+    record
+        .get(key)
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+}
+
+/// Reads the smart mailboxes in the Mail data at `path`, as in
+/// `~/Library/Mail`, from the property lists of its newest `V*`
+/// directory, through `plutil`. Reading them takes Full Disk Access,
+/// lacking which this fails.
+pub fn smart_mailboxes(path: &Path) -> Result<Vec<SmartMailbox>> {
+    let dir = super::store::version_dir(path)?.join("MailData");
+    let files: Vec<PathBuf> = SMART_MAILBOX_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .filter(|file| file.is_file())
+        .collect();
+    let mut mailboxes: Vec<SmartMailbox> = Vec::new();
+    for file in files {
+        let output = exec::run(
+            "plutil",
+            [
+                OsStr::new("-convert"),
+                OsStr::new("json"),
+                OsStr::new("-o"),
+                OsStr::new("-"),
+                file.as_os_str(),
+            ],
+            &[],
+        )?;
+        let plist: Value = serde_json::from_slice(&output).map_err(|e| Error::Parse {
+            context: "reading the smart mailboxes",
+            message: e.to_string(),
+        })?;
+        for mailbox in parse_smart_mailboxes(&plist) {
+            if !mailboxes.iter().any(|other| other.id == mailbox.id) {
+                mailboxes.push(mailbox);
+            }
+        }
+    }
+    Ok(mailboxes)
+}
+
+/// Parses a property list of smart mailboxes: an array of dictionaries,
+/// maybe nested in folders or under a key, each with its `MailboxName`
+/// and the `Criteria` it has messages meet.
+fn parse_smart_mailboxes(plist: &Value) -> Vec<SmartMailbox> {
+    let mut found = Vec::new();
+    collect_smart_mailboxes(plist, &mut found);
+    found
+}
+
+fn collect_smart_mailboxes(value: &Value, found: &mut Vec<SmartMailbox>) {
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                collect_smart_mailboxes(value, found);
+            }
+        }
+        Value::Object(dict) => {
+            let name = first(dict, &["MailboxName", "name"]);
+            let criteria = dict
+                .get("MailboxCriteria")
+                .or_else(|| dict.get("Criteria"))
+                .and_then(Value::as_array);
+            match (name, criteria) {
+                (Some(name), Some(criteria)) => found.push(SmartMailbox {
+                    id: first(dict, &["MailboxID", "IMAPMailboxName"])
+                        .unwrap_or_else(|| name.clone()),
+                    name,
+                    all: [
+                        "MailboxAllCriteriaMustBeSatisfied",
+                        "AllCriteriaMustBeSatisfied",
+                    ]
+                    .iter()
+                    .find_map(|key| boolean(dict.get(*key)?))
+                    .unwrap_or(true),
+                    conditions: criteria.iter().filter_map(criterion).collect(),
+                }),
+                // Folders of smart mailboxes hold them in turn:
+                _ => {
+                    for value in dict.values() {
+                        collect_smart_mailboxes(value, found);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn criterion(value: &Value) -> Option<Condition> {
+    let dict = value.as_object()?;
+    Some(Condition {
+        field: first(dict, &["Header", "CriterionIdentifier"])?,
+        qualifier: first(dict, &["Qualifier"]),
+        expression: first(dict, &["Expression"]),
+        header: None,
+    })
+}
+
+fn first(dict: &Map<String, Value>, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| match dict.get(*key)? {
+        Value::String(text) if !text.is_empty() => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    })
+}
+
+/// Reads a boolean as property lists have them, maybe as `YES` or `1`.
+fn boolean(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(value) => Some(*value),
+        Value::Number(number) => Some(number.as_i64()? != 0),
+        Value::String(text) => Some(matches!(text.as_str(), "YES" | "yes" | "true" | "1")),
+        _ => None,
+    }
+}
@@ -0,0 +1,379 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads Mail's data straight from disk: the messages' metadata from the
+//! `Envelope Index` database, through the `sqlite3` shell, and each
+//! message as it came from its `.emlx` file. Much faster than scripting
+//! Mail for a large store, and it works with Mail not running.
+//!
+//! Mail keeps everything in `~/Library/Mail/V<N>`, `N` going up with
+//! each version of macOS that changes the layout:
+//!
+//! - `MailData/Envelope Index`, a database of every message, with its
+//!   mailbox, subject, addresses, dates, and status
+//! - `<account>/<mailbox>.mbox/…/Messages/<id>.emlx`, each message as it
+//!   came, after a line with its length, followed by a property list;
+//!   `<id>.partial.emlx` if Mail keeps its attachments apart
+
+use super::{Filter, Mailstore, ON_MY_MAC, header, mime};
+use crate::{Error, Result, exec};
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsStr,
+    format, fs,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// How messages read from disk are extracted, as recorded in their
+/// provenance.
+pub const BACKEND: &str = "mail-emlx";
+
+/// Returns where Mail keeps its data: `~/Library/Mail`.
+pub fn default_path() -> Result<PathBuf> {
+    let home = env::var_os("HOME").ok_or_else(|| Error::Usage {
+        message: "HOME is not set".to_string(),
+    })?;
+    Ok(PathBuf::from(home).join("Library/Mail"))
+}
+
+/// Returns the directory of the newest layout in `path`, as in
+/// `~/Library/Mail/V10`, or `path` itself if it is one.
+pub(crate) fn version_dir(path: &Path) -> Result<PathBuf> {
+    if path.join("MailData").is_dir() {
+        return Ok(path.to_path_buf());
+    }
+    let entries = fs::read_dir(path).map_err(|e| Error::Io {
+        context: "listing the Mail directory",
+        source: e,
+    })?;
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let version: u32 = name.to_str()?.strip_prefix('V')?.parse().ok()?;
+            Some((version, entry.path()))
+        })
+        .filter(|(_, dir)| dir.join("MailData").is_dir())
+        .max()
+        .map(|(_, dir)| dir)
+        .ok_or_else(|| Error::NotFound {
+            what: format!("Mail data in {}", path.display()),
+        })
+}
+
+/// Reads every message `filter` selects from the Mail data at `path`, as
+/// [`super::script`] does through Mail, with their bodies only if `body`
+/// is true, and as they came only if `source` is true. Reading it takes
+/// Full Disk Access, lacking which this fails.
+pub fn read(path: &Path, filter: &Filter, body: bool, source: bool) -> Result<Mailstore> {
+    let dir = version_dir(path)?;
+    let database = dir.join("MailData/Envelope Index");
+    if !database.is_file() {
+        return Err(Error::NotFound {
+            what: format!("Mail database {}", database.display()),
+        });
+    }
+
+    let accounts = accounts(&dir);
+    let mut mailboxes = BTreeMap::new();
+    for row in rows(&database, MAILBOXES_QUERY)? {
+        let (Some(id), Some(url)) = (
+            row.get("id").and_then(Value::as_i64),
+            row.get("url").and_then(Value::as_str),
+        ) else {
+            continue;
+        };
+        let Some((account, name)) = mailbox(url, &accounts) else {
+            continue;
+        };
+        let account_name = account.as_deref().unwrap_or(ON_MY_MAC);
+        if filter.selects(account_name, &name) {
+            mailboxes.insert(id, (account, name));
+        }
+    }
+    if mailboxes.is_empty() {
+        return Ok(Mailstore::default());
+    }
+
+    let files = emlx_files(&dir);
+    let query = messages_query(&database, filter, mailboxes.keys())?;
+    let mut messages = Vec::new();
+    for mut row in rows(&database, &query)? {
+        let Some((account, name)) = row
+            .get("mailbox")
+            .and_then(Value::as_i64)
+            .and_then(|id| mailboxes.get(&id))
+        else {
+            continue;
+        };
+        let message = files
+            .get(&row.get("id").and_then(Value::as_i64).unwrap_or_default())
+            .and_then(|path| emlx(path));
+        let headers = message
+            .as_deref()
+            .map(|message| mime::headers(message).to_string());
+        if row.get("messageId").is_none_or(Value::is_null) {
+            row["messageId"] = headers
+                .as_deref()
+                .and_then(|headers| header(headers, "Message-ID"))
+                .into();
+        }
+        // `sqlite3 -json` gives JSON columns as text:
+        for key in ["to", "cc", "bcc"] {
+            if let Some(list) = row.get(key).and_then(Value::as_str) {
+                row[key] = serde_json::from_str(list).unwrap_or_default();
+            }
+        }
+        row["mailbox"] = name.clone().into();
+        row["account"] = account.clone().into();
+        row["body"] = message
+            .as_deref()
+            .filter(|_| body)
+            .and_then(mime::text)
+            .into();
+        row["source"] = message.filter(|_| source).into();
+        row["headers"] = headers.into();
+        messages.push(row);
+    }
+    super::parse(&json!({ "messages": messages }).to_string())
+}
+
+/// The query listing every mailbox, with its URL, as in
+/// `imap://<account>/INBOX`.
+const MAILBOXES_QUERY: &str = "SELECT ROWID AS id, url FROM mailboxes;\n";
+
+/// Returns the query reading every message `filter` selects in
+/// `mailboxes`, but for its account and mailbox, in the format
+/// [`super::parse`] reads, mailboxes by id.
+fn messages_query<'a>(
+    database: &Path,
+    filter: &Filter,
+    mailboxes: impl Iterator<Item = &'a i64>,
+) -> Result<String> {
+    // Message-IDs are kept apart since macOS 10.15, and subjects without
+    // their `Re:` since macOS 11:
+    let columns = |table: &str| -> Result<Vec<String>> {
+        Ok(rows(
+            database,
+            &format!("SELECT name FROM pragma_table_info('{table}');\n"),
+        )?
+        .iter()
+        .filter_map(|row| row.get("name")?.as_str().map(String::from))
+        .collect())
+    };
+    let message_columns = columns("messages")?;
+    let has = |column: &str| message_columns.iter().any(|name| name == column);
+    let (message_id, message_id_join) = if has("global_message_id")
+        && columns("message_global_data")?
+            .iter()
+            .any(|c| c == "message_id_header")
+    {
+        (
+            "g.message_id_header",
+            "LEFT JOIN message_global_data g ON g.ROWID = m.global_message_id",
+        )
+    } else {
+        ("NULL", "")
+    };
+    let subject = if has("subject_prefix") {
+        "coalesce(m.subject_prefix, '') || s.subject"
+    } else {
+        "s.subject"
+    };
+
+    let quote = |text: &str| format!("'{}'", text.to_lowercase().replace('\'', "''"));
+    let date =
+        |column: &str| format!("strftime('%Y-%m-%dT%H:%M:%S', {column}, 'unixepoch', 'localtime')");
+    let recipients = |kind: u8| {
+        format!(
+            "json((SELECT coalesce(json_group_array(json_object('name', a.comment, \
+             'email', a.address)), '[]') FROM (SELECT a.comment, a.address FROM recipients r \
+             JOIN addresses a ON a.ROWID = r.address WHERE r.message = m.ROWID \
+             AND r.type = {kind} ORDER BY r.position) a))"
+        )
+    };
+
+    let mailboxes: Vec<String> = mailboxes.map(i64::to_string).collect();
+    let mut clauses = Vec::from([
+        "coalesce(m.deleted, 0) = 0".to_string(),
+        format!("m.mailbox IN ({})", mailboxes.join(", ")),
+    ]);
+    // Local times, which `utc` turns into Unix time:
+    if let Some(since) = &filter.since {
+        clauses.push(format!(
+            "m.date_received >= CAST(strftime('%s', '{}', 'utc') AS INTEGER)",
+            since.as_str()
+        ));
+    }
+    if let Some(until) = &filter.until {
+        clauses.push(format!(
+            "m.date_received < CAST(strftime('%s', '{}', 'utc') AS INTEGER)",
+            until.as_str()
+        ));
+    }
+    if filter.unread {
+        clauses.push("m.read = 0".to_string());
+    }
+    if filter.flagged {
+        clauses.push("m.flagged = 1".to_string());
+    }
+    if !filter.from.is_empty() {
+        let senders: Vec<String> = filter
+            .from
+            .iter()
+            .map(|from| format!("instr(lower(sa.address), {}) > 0", quote(from)))
+            .collect();
+        clauses.push(format!("({})", senders.join(" OR ")));
+    }
+    if !filter.to.is_empty() {
+        let recipients: Vec<String> = filter
+            .to
+            .iter()
+            .map(|to| format!("instr(lower(a.address), {}) > 0", quote(to)))
+            .collect();
+        clauses.push(format!(
+            "EXISTS (SELECT 1 FROM recipients r JOIN addresses a ON a.ROWID = r.address \
+             WHERE r.message = m.ROWID AND ({}))",
+            recipients.join(" OR ")
+        ));
+    }
+
+    Ok(format!(
+        "SELECT m.ROWID AS id, {message_id} AS messageId, {subject} AS subject, \
+         CASE WHEN coalesce(sa.comment, '') = '' THEN sa.address \
+         ELSE sa.comment || ' <' || sa.address || '>' END AS sender, \
+         {to} AS \"to\", {cc} AS cc, {bcc} AS bcc, \
+         {sent} AS sent, {received} AS received, m.mailbox AS mailbox \
+         FROM messages m \
+         LEFT JOIN subjects s ON s.ROWID = m.subject \
+         LEFT JOIN addresses sa ON sa.ROWID = m.sender \
+         {message_id_join} \
+         WHERE {clauses} ORDER BY m.date_received;\n",
+        to = recipients(0),
+        cc = recipients(1),
+        bcc = recipients(2),
+        sent = date("m.date_sent"),
+        received = date("m.date_received"),
+        clauses = clauses.join(" AND "),
+    ))
+}
+
+/// Runs a query, returning its rows.
+fn rows(database: &Path, query: &str) -> Result<Vec<Value>> {
+    let args = [
+        OsStr::new("-readonly"),
+        OsStr::new("-bail"),
+        OsStr::new("-json"),
+        database.as_os_str(),
+    ];
+    let output = exec::run("sqlite3", args, query.as_bytes())?;
+    // `sqlite3 -json` prints nothing at all when there are no rows:
+    if output.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Vec::new());
+    }
+    serde_json::from_slice(&output).map_err(|e| Error::Parse {
+        context: "reading the Mail database",
+        message: e.to_string(),
+    })
+}
+
+/// Returns the names of the accounts, by the id Mail names their
+/// directories after, from the system's accounts database, best effort:
+/// an account not found there goes by its id.
+fn accounts(dir: &Path) -> BTreeMap<String, String> {
+    let Some(database) = dir
+        .parent()
+        .and_then(Path::parent)
+        .map(|library| library.join("Accounts/Accounts4.sqlite"))
+        .filter(|database| database.is_file())
+    else {
+        return BTreeMap::new();
+    };
+    let query = "SELECT ZIDENTIFIER AS id, ZACCOUNTDESCRIPTION AS name FROM ZACCOUNT \
+                 WHERE ZACCOUNTDESCRIPTION IS NOT NULL;\n";
+    rows(&database, query)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| {
+            let text = |key: &str| row.get(key).and_then(Value::as_str).map(String::from);
+            Some((text("id")?, text("name")?))
+        })
+        .collect()
+}
+
+/// Returns the account and the name of the mailbox at a URL, as in
+/// `imap://<account>/Archive/2024`, which is in the account and named
+/// `2024`; mailboxes "On My Mac" (`local:`) are in none.
+fn mailbox(url: &str, accounts: &BTreeMap<String, String>) -> Option<(Option<String>, String)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let (account, path) = rest.split_once('/')?;
+    let name = path.trim_end_matches('/').rsplit('/').next()?;
+    let name = percent_encoding::percent_decode_str(name).decode_utf8_lossy();
+    let name = name.trim_end_matches(".mbox").to_string();
+    if name.is_empty() {
+        return None;
+    }
+    let account = (scheme != "local").then(|| {
+        let account = percent_encoding::percent_decode_str(account).decode_utf8_lossy();
+        accounts
+            .get(account.as_ref())
+            .cloned()
+            .unwrap_or_else(|| account.into_owned())
+    });
+    Some((account, name))
+}
+
+/// Returns the `.emlx` file of every message under `dir`, by id, the
+/// whole message rather than the partial one where there are both.
+fn emlx_files(dir: &Path) -> BTreeMap<i64, PathBuf> {
+    let mut files = BTreeMap::new();
+    let mut dirs = Vec::from([dir.to_path_buf()]);
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_str().unwrap_or_default();
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                if name != "MailData" {
+                    dirs.push(path);
+                }
+                continue;
+            }
+            let (id, partial) = match name.strip_suffix(".partial.emlx") {
+                Some(id) => (id, true),
+                None => match name.strip_suffix(".emlx") {
+                    Some(id) => (id, false),
+                    None => continue,
+                },
+            };
+            let Ok(id) = id.parse() else {
+                continue;
+            };
+            if !partial || !files.contains_key(&id) {
+                files.insert(id, path);
+            }
+        }
+    }
+    files
+}
+
+/// Returns the message in an `.emlx` file, as it came: as many bytes as
+/// its first line says, after that line.
+fn emlx(path: &Path) -> Option<String> {
+    let data = fs::read(path).ok()?;
+    let start = data.iter().position(|&b| b == b'\n')?;
+    let length: usize = core::str::from_utf8(&data[..start])
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    let message = data.get(start + 1..)?;
+    let message = &message[..length.min(message.len())];
+    Some(String::from_utf8_lossy(message).into_owned())
+}
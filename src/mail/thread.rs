@@ -0,0 +1,161 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Groups messages into conversations by their `References` and
+//! `In-Reply-To` headers (RFC 5322), as mail programs thread them.
+
+use super::{Message, THREAD_URN_PREFIX, URN_PREFIX};
+use crate::export::sha256_hex;
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// A conversation: messages replying, directly or not, to one another.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Thread {
+    /// The `Message-ID` of the message the conversation started with, as
+    /// far as its messages tell, without the angle brackets.
+    pub root: String,
+    /// Its subject, as its first message gave it, without `Re:`.
+    pub subject: Option<String>,
+    /// The ids of its messages, oldest first.
+    pub messages: Vec<String>,
+    /// When its first and last messages were received, in local time.
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+impl Thread {
+    /// Returns the stable URN identifying this conversation, by the
+    /// `Message-ID` it started with.
+    pub fn urn(&self) -> String {
+        format!("{THREAD_URN_PREFIX}{}", sha256_hex(self.root.as_bytes()))
+    }
+
+    /// Returns the JSON-LD record for this conversation: a schema.org
+    /// `Conversation`, with its messages in order.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "Conversation",
+            "@id": self.urn(),
+            "identifier": self.root,
+            "name": self.subject,
+            "temporalCoverage": match (&self.start, &self.end) {
+                (Some(start), Some(end)) => Some(format!("{start}/{end}")),
+                _ => None,
+            },
+            "hasPart": self
+                .messages
+                .iter()
+                .map(|id| format!("{URN_PREFIX}{id}"))
+                .collect::<Vec<_>>(),
+            "numberOfItems": self.messages.len(),
+            "source": "apple-mail",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Returns the `Message-ID`s in a header like `References`, in order,
+/// without the angle brackets.
+pub fn message_ids(value: &str) -> Vec<String> {
+    value
+        .split('<')
+        .skip(1)
+        .filter_map(|id| id.split_once('>'))
+        .map(|(id, _)| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect()
+}
+
+/// Returns the conversations of more than one message that `messages`
+/// (oldest first) make up, in the order they started.
+pub fn threads(messages: &[Message]) -> Vec<Thread> {
+    // Every Message-ID a message names is a set here, joined with the
+    // others it names, so that messages naming one in common are in the
+    // same conversation, even if that one itself wasn't extracted:
+    let mut indices: BTreeMap<String, usize> = BTreeMap::new();
+    let mut parents: Vec<usize> = Vec::new();
+    let mut sets = Vec::with_capacity(messages.len());
+    for message in messages {
+        let own = message.message_id.clone().unwrap_or_else(|| message.urn());
+        let own = index(&mut indices, &mut parents, own);
+        for id in message.references.iter().chain(&message.in_reply_to) {
+            let other = index(&mut indices, &mut parents, id.clone());
+            union(&mut parents, own, other);
+        }
+        sets.push(own);
+    }
+
+    let mut threads: Vec<Thread> = Vec::new();
+    let mut by_set: BTreeMap<usize, usize> = BTreeMap::new();
+    for (message, own) in messages.iter().zip(sets) {
+        let set = find(&mut parents, own);
+        let thread = *by_set.entry(set).or_insert_with(|| {
+            threads.push(Thread {
+                // The oldest message names the start of the conversation
+                // first in its references, if it has any:
+                root: message
+                    .references
+                    .first()
+                    .or(message.in_reply_to.as_ref())
+                    .or(message.message_id.as_ref())
+                    .cloned()
+                    .unwrap_or_else(|| message.urn()),
+                subject: message.subject.as_deref().map(strip_reply),
+                start: message.received.clone(),
+                ..Thread::default()
+            });
+            threads.len() - 1
+        });
+        let thread = &mut threads[thread];
+        thread.messages.push(message.id.clone());
+        thread.end = message.received.clone();
+    }
+    threads.retain(|thread| thread.messages.len() > 1);
+    threads
+}
+
+fn index(indices: &mut BTreeMap<String, usize>, parents: &mut Vec<usize>, id: String) -> usize {
+    *indices.entry(id).or_insert_with(|| {
+        parents.push(parents.len());
+        parents.len() - 1
+    })
+}
+
+fn find(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parents, a), find(parents, b));
+    // The set first named is kept, so that the order is the messages':
+    parents[a.max(b)] = a.min(b);
+}
+
+/// Returns a subject without the `Re:`, `Fwd:`, and the like in front.
+fn strip_reply(subject: &str) -> String {
+    let mut subject = subject.trim();
+    while let Some((prefix, rest)) = subject.split_once(':') {
+        let prefix = prefix.trim().to_ascii_lowercase();
+        let prefix = prefix.trim_end_matches(|c: char| c.is_ascii_digit() || "[]()".contains(c));
+        if !matches!(
+            prefix,
+            "re" | "fw" | "fwd" | "aw" | "sv" | "vs" | "antw" | "wg"
+        ) {
+            break;
+        }
+        subject = rest.trim_start();
+    }
+    subject.to_string()
+}
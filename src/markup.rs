@@ -0,0 +1,698 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Parses the HTML note bodies Apple Notes returns into a small document
+//! model of blocks and inlines, which the exporters then render as
+//! Markdown, Org, and so on. Notes writes a narrow, regular subset of HTML
+//! (a `<div>` per line, headings, lists, tables, inline styles, links, and
+//! images inlined as `data:` URLs), so a forgiving parser suffices.
+
+use std::{
+    borrow::ToOwned,
+    boxed::Box,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+/// A parsed note body.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+    /// Images embedded in the body, referred to by [`Inline::Image`].
+    pub images: Vec<Image>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Block {
+    Heading { level: u8, content: Vec<Inline> },
+    Paragraph(Vec<Inline>),
+    List { ordered: bool, items: Vec<ListItem> },
+    Code(String),
+    Quote(Vec<Block>),
+    Table(Vec<Vec<Vec<Inline>>>),
+    Rule,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ListItem {
+    /// `Some` for checklist items, with whether the item is ticked off.
+    pub checked: Option<bool>,
+    pub content: Vec<Inline>,
+    /// Nested lists.
+    pub children: Vec<Block>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Inline {
+    Text(String),
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    Underline(Vec<Inline>),
+    Strike(Vec<Inline>),
+    Code(String),
+    Link {
+        href: String,
+        content: Vec<Inline>,
+    },
+    /// The index of an image in [`Document::images`].
+    Image(usize),
+    Break,
+}
+
+/// An image embedded in a note body.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    /// The MIME type, such as `image/png`.
+    pub mime: String,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Returns the usual file extension for this image's type.
+    pub fn extension(&self) -> &str {
+        match self.mime.as_str() {
+            "image/jpeg" => "jpg",
+            "image/svg+xml" => "svg",
+            "image/tiff" => "tiff",
+            mime => mime
+                .strip_prefix("image/")
+                .filter(|ext| ext.chars().all(|c| c.is_ascii_alphanumeric()))
+                .unwrap_or("bin"),
+        }
+    }
+}
+
+/// The URL scheme of links from one note to another.
+pub const NOTE_LINK_SCHEME: &str = "applenotes:";
+
+impl Inline {
+    /// Returns the plain text of `inlines`, without any formatting.
+    pub fn plain_text(inlines: &[Inline]) -> String {
+        let mut text = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(s) | Inline::Code(s) => text.push_str(s),
+                Inline::Bold(content)
+                | Inline::Italic(content)
+                | Inline::Underline(content)
+                | Inline::Strike(content)
+                | Inline::Link { content, .. } => text.push_str(&Inline::plain_text(content)),
+                Inline::Image(_) => {}
+                Inline::Break => text.push('\n'),
+            }
+        }
+        text
+    }
+}
+
+impl Document {
+    /// Parses a note body.
+    pub fn parse(html: &str) -> Document {
+        let root = dom(html);
+        let mut document = Document::default();
+        let mut paragraph = Vec::new();
+        document.blocks = blocks(&root.children, &mut document.images, &mut paragraph);
+        flush(&mut paragraph, &mut document.blocks);
+        document
+    }
+
+    /// Returns the `#tags` in the body, in order of first appearance.
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags = Vec::new();
+        self.visit_text(&mut |text| {
+            for tag in hashtags(text) {
+                if !tags.iter().any(|known: &String| known == tag) {
+                    tags.push(tag.to_string());
+                }
+            }
+        });
+        tags
+    }
+
+    /// Returns whether the first block is a heading or line reading `title`,
+    /// as Notes makes the first line of a note its title.
+    pub fn starts_with_title(&self, title: &str) -> bool {
+        match self.blocks.first() {
+            Some(Block::Heading { content, .. } | Block::Paragraph(content)) => {
+                Inline::plain_text(content).trim() == title.trim()
+            }
+            _ => false,
+        }
+    }
+
+    fn visit_text(&self, visit: &mut impl FnMut(&str)) {
+        fn inlines(content: &[Inline], visit: &mut impl FnMut(&str)) {
+            for inline in content {
+                match inline {
+                    Inline::Text(text) => visit(text),
+                    Inline::Bold(content)
+                    | Inline::Italic(content)
+                    | Inline::Underline(content)
+                    | Inline::Strike(content)
+                    | Inline::Link { content, .. } => inlines(content, visit),
+                    Inline::Code(_) | Inline::Image(_) | Inline::Break => {}
+                }
+            }
+        }
+        fn blocks(content: &[Block], visit: &mut impl FnMut(&str)) {
+            for block in content {
+                match block {
+                    Block::Heading { content, .. } | Block::Paragraph(content) => {
+                        inlines(content, visit)
+                    }
+                    Block::List { items, .. } => {
+                        for item in items {
+                            inlines(&item.content, visit);
+                            blocks(&item.children, visit);
+                        }
+                    }
+                    Block::Quote(content) => blocks(content, visit),
+                    Block::Table(rows) => {
+                        for cell in rows.iter().flatten() {
+                            inlines(cell, visit);
+                        }
+                    }
+                    Block::Code(_) | Block::Rule => {}
+                }
+            }
+        }
+        blocks(&self.blocks, visit)
+    }
+}
+
+/// Returns the `#tags` in `text`: a `#` at the start of a word, followed
+/// by letters, digits, `-`, `_`, or `/`, and not just digits.
+pub fn hashtags(text: &str) -> impl Iterator<Item = &str> {
+    let is_tag_char = |c: char| c.is_alphanumeric() || matches!(c, '-' | '_' | '/');
+    text.match_indices('#').filter_map(move |(start, _)| {
+        let before = text[..start].chars().next_back();
+        if before.is_some_and(|c| !c.is_whitespace() && c != '(') {
+            return None;
+        }
+        let rest = &text[start + 1..];
+        let end = rest.find(|c: char| !is_tag_char(c)).unwrap_or(rest.len());
+        let tag = rest[..end].trim_end_matches('/');
+        (!tag.is_empty() && !tag.chars().all(|c| c.is_ascii_digit())).then_some(tag)
+    })
+}
+
+// ---------------------------------------------------------------------------
+// From the DOM to blocks and inlines:
+
+fn flush(paragraph: &mut Vec<Inline>, blocks: &mut Vec<Block>) {
+    while paragraph.last() == Some(&Inline::Break) {
+        paragraph.pop();
+    }
+    let blank = paragraph.iter().all(|inline| match inline {
+        Inline::Text(text) => text.trim().is_empty(),
+        Inline::Break => true,
+        _ => false,
+    });
+    if !blank {
+        blocks.push(Block::Paragraph(trim_inlines(core::mem::take(paragraph))));
+    }
+    paragraph.clear();
+}
+
+/// Converts block-level content. Inline content between blocks collects
+/// in `paragraph` until the next block boundary.
+fn blocks(nodes: &[Node], images: &mut Vec<Image>, paragraph: &mut Vec<Inline>) -> Vec<Block> {
+    let mut out = Vec::new();
+    for node in nodes {
+        let Node::Element(element) = node else {
+            paragraph.extend(inlines(core::slice::from_ref(node), images));
+            continue;
+        };
+        match element.name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                flush(paragraph, &mut out);
+                let level = element.name.as_bytes()[1] - b'0';
+                let content = trim_inlines(inlines(&element.children, images));
+                if !content.is_empty() {
+                    out.push(Block::Heading { level, content });
+                }
+            }
+            "div" | "p" | "body" | "html" | "span" if is_block_container(element) => {
+                flush(paragraph, &mut out);
+                out.extend(blocks(&element.children, images, paragraph));
+                flush(paragraph, &mut out);
+            }
+            "div" | "p" => {
+                flush(paragraph, &mut out);
+                paragraph.extend(inlines(&element.children, images));
+                flush(paragraph, &mut out);
+            }
+            "ul" | "ol" => {
+                flush(paragraph, &mut out);
+                out.push(list(element, images));
+            }
+            "pre" => {
+                flush(paragraph, &mut out);
+                out.push(Block::Code(text_content(&element.children)));
+            }
+            "blockquote" => {
+                flush(paragraph, &mut out);
+                let mut inner = Vec::new();
+                let mut content = blocks(&element.children, images, &mut inner);
+                flush(&mut inner, &mut content);
+                out.push(Block::Quote(content));
+            }
+            "table" => {
+                flush(paragraph, &mut out);
+                out.push(table(element, images));
+            }
+            "hr" => {
+                flush(paragraph, &mut out);
+                out.push(Block::Rule);
+            }
+            "head" | "style" | "script" | "title" => {}
+            _ => paragraph.extend(inlines(core::slice::from_ref(node), images)),
+        }
+    }
+    out
+}
+
+/// Returns whether an element holds blocks rather than a single line.
+fn is_block_container(element: &Element) -> bool {
+    element.children.iter().any(|child| {
+        matches!(child, Node::Element(e) if matches!(
+            e.name.as_str(),
+            "div" | "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "ul" | "ol" | "pre"
+                | "blockquote" | "table" | "hr"
+        ))
+    })
+}
+
+fn list(element: &Element, images: &mut Vec<Image>) -> Block {
+    let ordered = element.name == "ol";
+    let checklist = element.has_class("checklist");
+    let mut items = Vec::new();
+    for child in &element.children {
+        let Node::Element(li) = child else { continue };
+        if li.name != "li" {
+            continue;
+        }
+        let mut item = ListItem::default();
+        let mut rest = Vec::new();
+        for node in &li.children {
+            match node {
+                Node::Element(e) if e.name == "ul" || e.name == "ol" => {
+                    item.children.push(list(e, images))
+                }
+                Node::Element(e) if e.name == "input" && e.attr("type") == Some("checkbox") => {
+                    item.checked = Some(e.attr("checked").is_some());
+                }
+                node => rest.push(node.clone()),
+            }
+        }
+        item.content = trim_inlines(inlines(&rest, images));
+        if item.checked.is_none() && (checklist || li.has_class("checked")) {
+            item.checked = Some(li.has_class("checked"));
+        }
+        items.push(item);
+    }
+    Block::List { ordered, items }
+}
+
+fn table(element: &Element, images: &mut Vec<Image>) -> Block {
+    let mut rows = Vec::new();
+    element.each("tr", &mut |tr| {
+        let cells = tr
+            .children
+            .iter()
+            .filter_map(|cell| match cell {
+                Node::Element(e) if e.name == "td" || e.name == "th" => {
+                    Some(trim_inlines(inlines(&e.children, images)))
+                }
+                _ => None,
+            })
+            .collect();
+        rows.push(cells);
+    });
+    Block::Table(rows)
+}
+
+fn inlines(nodes: &[Node], images: &mut Vec<Image>) -> Vec<Inline> {
+    let mut out = Vec::new();
+    for node in nodes {
+        let element = match node {
+            Node::Text(text) => {
+                push_text(&mut out, text);
+                continue;
+            }
+            Node::Element(element) => element,
+        };
+        let children = || element.children.as_slice();
+        match element.name.as_str() {
+            "b" | "strong" => out.push(Inline::Bold(inlines(children(), images))),
+            "i" | "em" => out.push(Inline::Italic(inlines(children(), images))),
+            "u" => out.push(Inline::Underline(inlines(children(), images))),
+            "s" | "strike" | "del" => out.push(Inline::Strike(inlines(children(), images))),
+            "tt" | "code" | "kbd" => out.push(Inline::Code(text_content(children()))),
+            "br" => out.push(Inline::Break),
+            "a" => {
+                let content = inlines(children(), images);
+                match element.attr("href") {
+                    Some(href) => out.push(Inline::Link {
+                        href: href.to_string(),
+                        content,
+                    }),
+                    None => out.extend(content),
+                }
+            }
+            "img" => {
+                if let Some(image) = element.attr("src").and_then(decode_data_url) {
+                    images.push(image);
+                    out.push(Inline::Image(images.len() - 1));
+                }
+            }
+            "span" if element.style_contains("font-weight: bold") => {
+                out.push(Inline::Bold(inlines(children(), images)))
+            }
+            "span" if element.style_contains("font-style: italic") => {
+                out.push(Inline::Italic(inlines(children(), images)))
+            }
+            "div" | "p" | "li" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if !out.is_empty() && out.last() != Some(&Inline::Break) {
+                    out.push(Inline::Break);
+                }
+                out.extend(inlines(children(), images));
+            }
+            "head" | "style" | "script" | "title" => {}
+            _ => out.extend(inlines(children(), images)),
+        }
+    }
+    out
+}
+
+fn push_text(out: &mut Vec<Inline>, text: &str) {
+    let text = collapse_whitespace(text);
+    if text.is_empty() {
+        return;
+    }
+    match out.last_mut() {
+        Some(Inline::Text(last)) => last.push_str(&text),
+        _ => out.push(Inline::Text(text)),
+    }
+}
+
+/// Collapses runs of whitespace (but not non-breaking spaces) into one
+/// space, as browsers do.
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            space = true;
+            continue;
+        }
+        if space {
+            out.push(' ');
+            space = false;
+        }
+        out.push(if c == '\u{a0}' { ' ' } else { c });
+    }
+    if space {
+        out.push(' ');
+    }
+    out
+}
+
+/// Trims whitespace at the start and end of a run of inlines.
+fn trim_inlines(mut inlines: Vec<Inline>) -> Vec<Inline> {
+    if let Some(Inline::Text(text)) = inlines.first_mut() {
+        *text = text.trim_start().to_string();
+    }
+    if let Some(Inline::Text(text)) = inlines.last_mut() {
+        *text = text.trim_end().to_string();
+    }
+    inlines.retain(|inline| !matches!(inline, Inline::Text(text) if text.is_empty()));
+    inlines
+}
+
+fn text_content(nodes: &[Node]) -> String {
+    let mut text = String::new();
+    for node in nodes {
+        match node {
+            Node::Text(s) => text.push_str(s),
+            Node::Element(e) if e.name == "br" => text.push('\n'),
+            Node::Element(e) => {
+                if matches!(e.name.as_str(), "div" | "p")
+                    && !text.is_empty()
+                    && !text.ends_with('\n')
+                {
+                    text.push('\n');
+                }
+                text.push_str(&text_content(&e.children));
+            }
+        }
+    }
+    text
+}
+
+fn decode_data_url(src: &str) -> Option<Image> {
+    let url = data_url::DataUrl::process(src).ok()?;
+    let mime = url.mime_type();
+    let mime = std::format!("{}/{}", mime.type_, mime.subtype);
+    let (data, _) = url.decode_to_vec().ok()?;
+    Some(Image { mime, data })
+}
+
+// ---------------------------------------------------------------------------
+// A forgiving HTML parser:
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    Element(Box<Element>),
+    Text(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn has_class(&self, class: &str) -> bool {
+        self.attr("class")
+            .is_some_and(|classes| classes.split_whitespace().any(|c| c == class))
+    }
+
+    fn style_contains(&self, declaration: &str) -> bool {
+        self.attr("style").is_some_and(|style| {
+            style
+                .replace(": ", ":")
+                .contains(&declaration.replace(": ", ":"))
+        })
+    }
+
+    /// Calls `f` on every descendant element named `name`, not looking
+    /// inside those.
+    fn each(&self, name: &str, f: &mut impl FnMut(&Element)) {
+        for child in &self.children {
+            if let Node::Element(e) = child {
+                if e.name == name {
+                    f(e);
+                } else {
+                    e.each(name, f);
+                }
+            }
+        }
+    }
+}
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Parses `html` into a tree under an unnamed root element.
+fn dom(html: &str) -> Element {
+    let mut stack = vec![Element::default()];
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(lt) = rest.find('<') else {
+            push_node(&mut stack, Node::Text(decode_entities(rest)));
+            break;
+        };
+        if lt > 0 {
+            push_node(&mut stack, Node::Text(decode_entities(&rest[..lt])));
+        }
+        rest = &rest[lt..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+        let Some(end) = tag_end(rest) else {
+            push_node(&mut stack, Node::Text(decode_entities(rest)));
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_ascii_lowercase();
+            if let Some(depth) = stack.iter().rposition(|e| e.name == name)
+                && depth > 0
+            {
+                while stack.len() > depth {
+                    pop(&mut stack);
+                }
+            }
+            continue;
+        }
+
+        let (element, self_closing) = parse_tag(tag);
+        if element.name.is_empty() {
+            continue;
+        }
+        // Raw text elements aren't parsed:
+        if matches!(element.name.as_str(), "style" | "script") {
+            let close = std::format!("</{}", element.name);
+            let end = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+            rest = rest[end..].find('>').map_or("", |e| &rest[end + e + 1..]);
+            continue;
+        }
+        // A new list item or row closes the previous one:
+        if matches!(element.name.as_str(), "li" | "tr" | "td" | "th" | "p")
+            && stack.last().is_some_and(|e| e.name == element.name)
+        {
+            pop(&mut stack);
+        }
+        if self_closing || VOID_ELEMENTS.contains(&element.name.as_str()) {
+            push_node(&mut stack, Node::Element(Box::new(element)));
+        } else {
+            stack.push(element);
+        }
+    }
+    while stack.len() > 1 {
+        pop(&mut stack);
+    }
+    stack.pop().unwrap_or_default()
+}
+
+fn push_node(stack: &mut [Element], node: Node) {
+    if let Some(parent) = stack.last_mut() {
+        parent.children.push(node);
+    }
+}
+
+fn pop(stack: &mut Vec<Element>) {
+    if let Some(element) = stack.pop() {
+        push_node(stack, Node::Element(Box::new(element)));
+    }
+}
+
+/// Finds the `>` ending the tag at the start of `input`, skipping quoted
+/// attribute values.
+fn tag_end(input: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in input.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_tag(tag: &str) -> (Element, bool) {
+    let self_closing = tag.ends_with('/');
+    let tag = tag.trim_end_matches('/');
+    let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+    let mut element = Element {
+        name: tag[..name_end].to_ascii_lowercase(),
+        ..Element::default()
+    };
+
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = rest[..key_end].to_ascii_lowercase();
+        rest = rest[key_end..].trim_start();
+        let value = if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (value, remainder) = match after.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let inner = &after[1..];
+                    let end = inner.find(q).unwrap_or(inner.len());
+                    (&inner[..end], inner.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            rest = remainder.trim_start();
+            decode_entities(value)
+        } else {
+            String::new()
+        };
+        if !key.is_empty() {
+            element.attrs.push((key, value));
+        }
+    }
+    (element, self_closing)
+}
+
+/// Decodes the character references in `text`.
+pub fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_owned();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => match entity.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16)
+                        .ok()
+                        .and_then(char::from_u32),
+                    Some(dec) => dec.parse().ok().and_then(char::from_u32),
+                    None => None,
+                },
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,921 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Apple Messages, read straight from its database, `chat.db`, through the
+//! `sqlite3` shell, as Messages has no scripting dictionary to speak of.
+//! Reading it takes Full Disk Access.
+
+use crate::{
+    Error, Result,
+    bplist::{self, Plist},
+    contacts::{self, Contact},
+    error::check_readable,
+    exec,
+    timezone::{LocalTime, TimeZone},
+};
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsStr,
+    format,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// How messages are extracted, as recorded in their provenance.
+pub const BACKEND: &str = "messages-sqlite";
+
+/// The prefix of the URNs identifying individual messages.
+pub const URN_PREFIX: &str = "urn:apple:messages:message:";
+
+/// The prefix of the URNs identifying chats, of two or of a group.
+pub const CHAT_URN_PREFIX: &str = "urn:apple:messages:chat:";
+
+/// The prefix of the URNs identifying the files attached to messages.
+pub const ATTACHMENT_URN_PREFIX: &str = "urn:apple:messages:attachment:";
+
+/// The `transfer_state` of an attachment whose file is all there.
+pub const TRANSFER_FINISHED: i64 = 5;
+
+/// The seconds from the Unix epoch to Apple's, 2001-01-01, which the
+/// database counts dates from.
+const APPLE_EPOCH: i64 = 978_307_200;
+
+/// How many messages are read from the database at a time, so that even
+/// a history of many gigabytes is emitted as it is read, in bounded
+/// memory.
+const PAGE_SIZE: usize = 1000;
+
+/// Returns where Messages keeps its database: `~/Library/Messages/chat.db`.
+pub fn default_path() -> Result<PathBuf> {
+    let home = env::var_os("HOME").ok_or_else(|| Error::Usage {
+        message: "HOME is not set".to_string(),
+    })?;
+    Ok(PathBuf::from(home).join("Library/Messages/chat.db"))
+}
+
+/// Which messages to read: all of them, unless told otherwise.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Only the messages sent at or after this time.
+    pub since: Option<LocalTime>,
+    /// Only the messages sent before this time.
+    pub until: Option<LocalTime>,
+    /// Only the messages in chats with any of these identifiers, GUIDs,
+    /// or names, ignoring case.
+    pub chats: Vec<String>,
+    /// Only the messages in chats with any of these handles, as
+    /// [`Archive::resolve`] matches them.
+    pub with: Vec<String>,
+    /// Only the messages after the one with this `ROWID`, and the chats
+    /// they are in, as in [`Pages::last_row`].
+    pub after: Option<i64>,
+}
+
+/// A page of what a read extracts: messages, oldest first, the files
+/// attached to them, and, in the first page, every chat.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Archive {
+    pub chats: Vec<Chat>,
+    pub messages: Vec<Message>,
+    pub attachments: Vec<Attachment>,
+}
+
+impl Archive {
+    /// Resolves the handles of the messages and the chats to the
+    /// `contacts` that have them, by email address, ignoring case, or by
+    /// phone number, its last 10 digits, ignoring country codes and
+    /// formatting.
+    pub fn resolve(&mut self, contacts: &[Contact]) {
+        let mut by_handle: BTreeMap<String, &Contact> = BTreeMap::new();
+        for contact in contacts {
+            for handle in contact.emails.iter().chain(&contact.phones) {
+                if let Some(key) = handle_key(&handle.value) {
+                    by_handle.entry(key).or_insert(contact);
+                }
+            }
+        }
+        let handles = self
+            .messages
+            .iter_mut()
+            .filter_map(|message| message.handle.as_mut())
+            .chain(
+                self.chats
+                    .iter_mut()
+                    .flat_map(|chat| &mut chat.participants),
+            );
+        for handle in handles {
+            let Some(contact) = handle_key(&handle.id).and_then(|key| by_handle.get(&key)) else {
+                continue;
+            };
+            handle.contact = Some(contact.id.clone());
+            handle.name = Some(contact.name()).filter(|name| !name.is_empty());
+        }
+    }
+}
+
+/// Returns the key a handle is looked up by: an email address in lower
+/// case, or the last 10 digits of a phone number.
+fn handle_key(handle: &str) -> Option<String> {
+    let handle = handle.trim();
+    if handle.contains('@') {
+        return Some(handle.to_lowercase());
+    }
+    let digits: String = handle.chars().filter(char::is_ascii_digit).collect();
+    let start = digits.len().saturating_sub(10);
+    (!digits.is_empty()).then(|| digits[start..].to_string())
+}
+
+/// A conversation, of two or of a group.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Chat {
+    /// Messages' GUID for the chat, as in `iMessage;+;chat123456789`.
+    pub guid: String,
+    /// Its identifier, as in `+15555550123`, or `chat123456789` for a
+    /// group.
+    pub identifier: Option<String>,
+    /// The name the group was given, if it was.
+    pub name: Option<String>,
+    /// How its messages are sent: `iMessage`, `SMS`, or `RCS`.
+    pub service: Option<String>,
+    /// Whether it is a group chat, rather than a chat of two.
+    pub group: bool,
+    /// Everyone in it but the user.
+    pub participants: Vec<Handle>,
+    /// How many messages are in it.
+    pub messages: u64,
+}
+
+impl Chat {
+    /// Returns the stable URN identifying this chat.
+    pub fn urn(&self) -> String {
+        format!("{CHAT_URN_PREFIX}{}", self.guid)
+    }
+
+    /// Returns the JSON-LD record for this chat: a schema.org
+    /// `Conversation`, with its participants.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "Conversation",
+            "@id": self.urn(),
+            "identifier": self.identifier,
+            "name": self.name,
+            "service": self.service,
+            "groupChat": self.group,
+            "participant": self.participants.iter().map(Handle::to_json).collect::<Vec<_>>(),
+            "numberOfItems": self.messages,
+            "source": "apple-messages",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Someone the user messages with, by their handle.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Handle {
+    /// The handle, a phone number or an email address.
+    pub id: String,
+    /// The id of the contact with that handle, and the name Contacts shows
+    /// it under, if resolved.
+    pub contact: Option<String>,
+    pub name: Option<String>,
+}
+
+impl Handle {
+    /// Returns the handle as a schema.org `Person`, with its `email` or
+    /// `telephone`, and its `name` and contact, as `sameAs`, if resolved.
+    pub fn to_json(&self) -> Value {
+        let key = if self.id.contains('@') {
+            "email"
+        } else {
+            "telephone"
+        };
+        let mut record = json!({
+            "@type": "Person",
+            "identifier": self.id,
+            key: self.id,
+            "name": self.name,
+            "sameAs": self.contact.as_ref().map(|id| format!("{}{id}", contacts::URN_PREFIX)),
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// A single message, sent or received.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Message {
+    /// Messages' GUID for the message, the same on every device.
+    pub guid: String,
+    pub text: Option<String>,
+    /// Whoever the message was sent by, or in a chat of two, to if sent
+    /// by the user.
+    pub handle: Option<Handle>,
+    /// The identifier of the chat it is in, as in `+15555550123` or
+    /// `chat123456789`.
+    pub chat: Option<String>,
+    /// The GUID of that chat.
+    pub chat_guid: Option<String>,
+    /// The GUID of the message it replies to inline, the first of its
+    /// thread.
+    pub reply_to: Option<String>,
+    /// How it was sent: `iMessage`, `SMS`, or `RCS`.
+    pub service: Option<String>,
+    pub from_me: bool,
+    pub read: bool,
+    /// When it was sent, and read, if it was, in local time.
+    pub sent: Option<String>,
+    pub read_at: Option<String>,
+    /// The GUIDs of the files attached to it, in order.
+    pub attachments: Vec<String>,
+    /// The tapback it is, if it is one, rather than a message of its own.
+    pub reaction: Option<Reaction>,
+    /// Its text as first sent and as edited since, oldest first, if it was
+    /// edited, and when it last was, in local time.
+    pub edits: Vec<Edit>,
+    pub edited: Option<String>,
+    /// Whether it was unsent, in part or in full.
+    pub unsent: bool,
+}
+
+/// A tapback: a reaction to a message, or the removal of one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Reaction {
+    /// What it is: `love`, `like`, `dislike`, `laugh`, `emphasize`,
+    /// `question`, `sticker`, or the emoji reacted with.
+    pub kind: String,
+    /// The GUID of the message reacted to.
+    pub target: String,
+    /// Whether it takes the reaction back.
+    pub removed: bool,
+}
+
+impl Reaction {
+    /// Returns the tapback a message is, given its `associated_message_*`
+    /// columns, or `None` if it isn't one.
+    pub fn new(kind: i64, target: &str, emoji: Option<&str>) -> Option<Self> {
+        let kind_name = match kind % 1000 {
+            0 => "love",
+            1 => "like",
+            2 => "dislike",
+            3 => "laugh",
+            4 => "emphasize",
+            5 => "question",
+            6 => emoji?,
+            7 => "sticker",
+            _ => return None,
+        };
+        // Targets name the part reacted to, as in `p:0/<GUID>`, or the
+        // whole balloon, as in `bp:<GUID>`:
+        let target = match target.split_once('/') {
+            Some((_, guid)) => guid,
+            None => target.strip_prefix("bp:").unwrap_or(target),
+        };
+        (matches!(kind / 1000, 2 | 3) && !target.is_empty()).then(|| Reaction {
+            kind: kind_name.to_string(),
+            target: target.to_string(),
+            removed: kind / 1000 == 3,
+        })
+    }
+}
+
+/// A version of a message's text, as sent or as edited.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Edit {
+    pub text: Option<String>,
+    /// When it was sent or edited, in local time.
+    pub date: Option<String>,
+}
+
+impl Message {
+    /// Returns the stable URN identifying this message.
+    pub fn urn(&self) -> String {
+        format!("{URN_PREFIX}{}", self.guid)
+    }
+
+    /// Returns the JSON-LD record for this message: a schema.org
+    /// `Message`. Unset fields are left out.
+    pub fn to_json(&self) -> Value {
+        let handle = self.handle.as_ref().map(Handle::to_json);
+        let mut record = json!({
+            "@type": "Message",
+            "@id": self.urn(),
+            "text": self.text,
+            "sender": if self.from_me { None } else { handle.clone() },
+            "recipient": if self.from_me { handle } else { None },
+            "fromMe": self.from_me,
+            "isPartOf": self.chat_guid.as_ref().map(|guid| format!("{CHAT_URN_PREFIX}{guid}")),
+            "parentItem": self.reply_to.as_ref().map(|guid| format!("{URN_PREFIX}{guid}")),
+            "service": self.service,
+            "dateSent": self.sent,
+            "dateRead": self.read_at,
+            "read": self.read,
+            "messageAttachment": (!self.attachments.is_empty()).then(|| {
+                self.attachments
+                    .iter()
+                    .map(|guid| format!("{ATTACHMENT_URN_PREFIX}{guid}"))
+                    .collect::<Vec<_>>()
+            }),
+            "reaction": self.reaction.as_ref().map(|reaction| &reaction.kind),
+            "reactionTo": self
+                .reaction
+                .as_ref()
+                .map(|reaction| format!("{URN_PREFIX}{}", reaction.target)),
+            "reactionRemoved": self
+                .reaction
+                .as_ref()
+                .and_then(|reaction| reaction.removed.then_some(true)),
+            "editHistory": (!self.edits.is_empty()).then(|| {
+                self.edits
+                    .iter()
+                    .map(|edit| json!({ "text": edit.text, "dateModified": edit.date }))
+                    .collect::<Vec<_>>()
+            }),
+            "dateModified": self.edited,
+            "unsent": self.unsent.then_some(true),
+            "source": "apple-messages",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// A file attached to a message, as in a photo or a voice message.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Attachment {
+    /// Messages' GUID for the attachment.
+    pub guid: String,
+    /// The GUID of the message it is attached to.
+    pub message: Option<String>,
+    /// Its file name as sent, as in `IMG_0001.HEIC`.
+    pub name: Option<String>,
+    /// Where its file is, under `~/Library/Messages/Attachments`, whether
+    /// or not it is there.
+    pub path: Option<PathBuf>,
+    /// Its MIME type, as in `image/heic`.
+    pub mime: Option<String>,
+    pub size: Option<u64>,
+    /// How far its transfer got, in Messages' own terms: it is
+    /// [`TRANSFER_FINISHED`] once the file is all there.
+    pub transfer_state: Option<i64>,
+}
+
+impl Attachment {
+    /// Returns the stable URN identifying this attachment.
+    pub fn urn(&self) -> String {
+        format!("{ATTACHMENT_URN_PREFIX}{}", self.guid)
+    }
+
+    /// Returns the file name extension for the attachment, that of its
+    /// name or its file, or `bin` if neither has a sensible one.
+    pub fn extension(&self) -> &str {
+        let path = self.path.as_deref().and_then(Path::to_str);
+        self.name
+            .as_deref()
+            .into_iter()
+            .chain(path)
+            .filter_map(|name| name.rsplit_once('.'))
+            .map(|(_, extension)| extension)
+            .find(|extension| {
+                !extension.is_empty()
+                    && extension.len() <= 8
+                    && extension.chars().all(|c| c.is_ascii_alphanumeric())
+            })
+            .unwrap_or("bin")
+    }
+
+    /// Returns the JSON-LD record for this attachment: a schema.org
+    /// `MediaObject`, part of its message, linking a copy of its file
+    /// from `contentUrl` if one was written somewhere.
+    pub fn to_json(&self, sha256: Option<&str>, url: Option<&str>) -> Value {
+        let mut record = json!({
+            "@type": "MediaObject",
+            "@id": self.urn(),
+            "name": self.name,
+            "encodingFormat": self.mime,
+            "contentSize": self.size,
+            "path": self.path.as_deref().map(Path::display).map(|path| path.to_string()),
+            "transferState": self.transfer_state,
+            "isPartOf": self.message.as_ref().map(|guid| format!("{URN_PREFIX}{guid}")),
+            "sha256": sha256,
+            "contentUrl": url,
+            "source": "apple-messages",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Reads the messages `filter` selects in the database at `path`, and the
+/// chats they are in, a page at a time. Reading it takes Full Disk
+/// Access, lacking which this fails, saying so.
+pub fn read(path: &Path, filter: &Filter) -> Result<Pages> {
+    check_readable(path, "Messages database")?;
+    let mut pages = Pages {
+        path: path.to_path_buf(),
+        columns: Vec::new(),
+        filter: filter.clone(),
+        chats: Vec::new(),
+        last: 0,
+        position: None,
+        done: false,
+        zone: TimeZone::local(),
+    };
+    // Columns come and go with macOS versions, and those missing are read
+    // as null:
+    let columns = pages.sqlite("SELECT name FROM pragma_table_info('message');\n")?;
+    pages.columns = String::from_utf8_lossy(&columns)
+        .lines()
+        .map(String::from)
+        .collect();
+
+    let (chats, _) = clauses(filter, &seconds("m.date"));
+    let output = pages.sqlite(&format!(
+        "SELECT json_object('chats', json((SELECT coalesce(json_group_array(json(r)), '[]') \
+         FROM (SELECT {CHAT} AS r FROM chat c WHERE {chats} ORDER BY c.ROWID))), \
+         'last', (SELECT coalesce(max(ROWID), 0) FROM message));\n"
+    ))?;
+    let output: Value = serde_json::from_slice(&output).map_err(|e| Error::Parse {
+        context: "reading chats",
+        message: e.to_string(),
+    })?;
+    pages.chats = output
+        .get("chats")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .filter_map(parse_chat)
+        .collect();
+    pages.last = output
+        .get("last")
+        .and_then(Value::as_i64)
+        .unwrap_or_default();
+    Ok(pages)
+}
+
+/// The messages [`read`] selects, oldest first, in pages of at most
+/// [`PAGE_SIZE`], each with the files attached to them. The first page
+/// also has the chats.
+pub struct Pages {
+    path: PathBuf,
+    /// The columns of the `message` table.
+    columns: Vec<String>,
+    filter: Filter,
+    /// The chats, until the first page takes them.
+    chats: Vec<Chat>,
+    /// The `ROWID` of the last message in the database when reading began.
+    last: i64,
+    /// The date, in seconds since 2001, and the `ROWID` of the last
+    /// message read, which the next page starts after.
+    position: Option<(i64, i64)>,
+    done: bool,
+    /// For the dates of edits, which only the summary has.
+    zone: TimeZone,
+}
+
+impl Pages {
+    /// Returns the `ROWID` of the last message in the database, selected
+    /// or not, when reading began. Messages added since are left for the
+    /// next read, with this as [`Filter::after`].
+    pub fn last_row(&self) -> i64 {
+        self.last
+    }
+
+    fn sqlite(&self, query: &str) -> Result<Vec<u8>> {
+        exec::run(
+            "sqlite3",
+            [
+                OsStr::new("-readonly"),
+                OsStr::new("-bail"),
+                self.path.as_os_str(),
+            ],
+            query.as_bytes(),
+        )
+    }
+
+    /// Reads the next page, with one JSON object per message, and then
+    /// the files attached to them.
+    fn read_page(&mut self) -> Result<Option<Archive>> {
+        let columns: Vec<&str> = self.columns.iter().map(String::as_str).collect();
+        let output = self.sqlite(&query(&columns, &self.filter, self.last, self.position))?;
+        let mut messages = Vec::new();
+        let mut rows = Vec::new();
+        for line in output
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+        {
+            let record: Value = serde_json::from_slice(line).map_err(|e| Error::Parse {
+                context: "reading messages",
+                message: e.to_string(),
+            })?;
+            let row = record
+                .get("row")
+                .and_then(Value::as_i64)
+                .unwrap_or_default();
+            let date = record
+                .get("date")
+                .and_then(Value::as_i64)
+                .unwrap_or_default();
+            messages.push(parse_message(&record, &self.zone)?);
+            rows.push(row);
+            self.position = Some((date, row));
+        }
+        self.done = messages.len() < PAGE_SIZE;
+
+        let chats = core::mem::take(&mut self.chats);
+        if messages.is_empty() && chats.is_empty() {
+            return Ok(None);
+        }
+        let mut attachments = Vec::new();
+        if !rows.is_empty() {
+            let rows: Vec<String> = rows.iter().map(ToString::to_string).collect();
+            let output = self.sqlite(&format!(
+                "SELECT {ATTACHMENT} FROM attachment a WHERE a.ROWID IN \
+                 (SELECT j.attachment_id FROM message_attachment_join j \
+                 WHERE j.message_id IN ({})) ORDER BY a.ROWID;\n",
+                rows.join(", ")
+            ))?;
+            for line in output
+                .split(|&b| b == b'\n')
+                .filter(|line| !line.is_empty())
+            {
+                let record: Value = serde_json::from_slice(line).map_err(|e| Error::Parse {
+                    context: "reading message attachments",
+                    message: e.to_string(),
+                })?;
+                if let Some(attachment) = parse_attachment(&record) {
+                    attachments.push(self.locate(attachment));
+                }
+            }
+        }
+        Ok(Some(Archive {
+            chats,
+            messages,
+            attachments,
+        }))
+    }
+
+    /// Makes the path of an attachment's file absolute: they begin with a
+    /// `~`, for the home folder the database is in.
+    fn locate(&self, mut attachment: Attachment) -> Attachment {
+        let messages = self.path.parent().unwrap_or(Path::new("."));
+        if let Some(file) = attachment.path.take() {
+            attachment.path = Some(match file.strip_prefix("~/Library/Messages") {
+                Ok(rest) => messages.join(rest),
+                Err(_) => match (file.strip_prefix("~"), env::var_os("HOME")) {
+                    (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+                    _ => file,
+                },
+            });
+        }
+        attachment
+    }
+}
+
+impl Iterator for Pages {
+    type Item = Result<Archive>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let page = self.read_page();
+        if page.is_err() {
+            self.done = true;
+        }
+        page.transpose()
+    }
+}
+
+/// The JSON object for a chat, as `c`, in the format [`parse_chat`] reads.
+const CHAT: &str = "json_object('guid', c.guid, 'identifier', c.chat_identifier, \
+     'name', c.display_name, 'service', c.service_name, 'style', c.style, \
+     'handles', json((SELECT json_group_array(h.id) FROM chat_handle_join j \
+     JOIN handle h ON h.ROWID = j.handle_id WHERE j.chat_id = c.ROWID)), \
+     'messages', (SELECT count(*) FROM chat_message_join j WHERE j.chat_id = c.ROWID))";
+
+/// The JSON object for an attachment, as `a`, in the format
+/// [`parse_attachment`] reads.
+const ATTACHMENT: &str = "json_object('guid', a.guid, \
+     'message', (SELECT m.guid FROM message_attachment_join j \
+     JOIN message m ON m.ROWID = j.message_id WHERE j.attachment_id = a.ROWID LIMIT 1), \
+     'name', a.transfer_name, 'path', a.filename, 'mime', a.mime_type, \
+     'size', a.total_bytes, 'transferState', a.transfer_state)";
+
+/// Returns a date column in seconds since 2001: dates are in nanoseconds
+/// since macOS 10.13, and in seconds before.
+fn seconds(column: &str) -> String {
+    format!("(CASE WHEN {column} > 1000000000000 THEN {column} / 1000000000 ELSE {column} END)")
+}
+
+/// Returns the query that reads the page of messages after `position`, up
+/// to the one with `ROWID` `last`, one JSON object per line, in the
+/// format [`parse_message`] reads, given the columns of the `message`
+/// table. Each also has its `row` and `date`, for the next page to start
+/// after. The filter is applied by the database, which keeps to its
+/// indices.
+fn query(columns: &[&str], filter: &Filter, last: i64, position: Option<(i64, i64)>) -> String {
+    let column = |name: &str| match columns.contains(&name) {
+        true => format!("m.{name}"),
+        false => "NULL".to_string(),
+    };
+    let date = |column: &str| {
+        format!(
+            "CASE WHEN {column} > 0 THEN strftime('%Y-%m-%dT%H:%M:%S', \
+             {} + {APPLE_EPOCH}, 'unixepoch', 'localtime') END",
+            seconds(column)
+        )
+    };
+    let key = seconds("m.date");
+    // Since macOS 13, the text of many messages is only in their
+    // `attributedBody`, an archived `NSAttributedString`:
+    let record = format!(
+        "json_object('row', m.ROWID, 'date', {key}, \
+         'guid', m.guid, 'text', m.text, \
+         'body', CASE WHEN m.text IS NULL THEN hex(m.attributedBody) END, \
+         'handle', h.id, 'chat', c.chat_identifier, 'chatGuid', c.guid, \
+         'replyTo', {reply_to}, \
+         'reactionType', {reaction_type}, 'reactionTo', {reaction_to}, \
+         'reactionEmoji', {reaction_emoji}, 'edited', {edited}, \
+         'summary', hex({summary}), \
+         'service', m.service, 'fromMe', m.is_from_me, 'read', m.is_read, \
+         'sent', {sent}, 'readAt', {read}, \
+         'attachments', json((SELECT json_group_array(a.guid) FROM message_attachment_join j \
+         JOIN attachment a ON a.ROWID = j.attachment_id WHERE j.message_id = m.ROWID)))",
+        sent = date("m.date"),
+        read = date("m.date_read"),
+        reply_to = column("thread_originator_guid"),
+        reaction_type = column("associated_message_type"),
+        reaction_to = column("associated_message_guid"),
+        reaction_emoji = column("associated_message_emoji"),
+        edited = date(&column("date_edited")),
+        summary = column("message_summary_info"),
+    );
+    let (_, mut messages) = clauses(filter, &key);
+    messages += &format!(" AND m.ROWID <= {last}");
+    if let Some((date, row)) = position {
+        messages += &format!(" AND ({key}, m.ROWID) > ({date}, {row})");
+    }
+    format!(
+        "SELECT {record} FROM message m \
+         LEFT JOIN handle h ON h.ROWID = m.handle_id \
+         LEFT JOIN chat c ON c.ROWID = (SELECT j.chat_id FROM chat_message_join j \
+         WHERE j.message_id = m.ROWID LIMIT 1) \
+         WHERE {messages} ORDER BY {key}, m.ROWID LIMIT {PAGE_SIZE};\n"
+    )
+}
+
+/// Returns the conditions on chats, as `c`, and on messages, as `m`, that
+/// `filter` makes, given the date of `m` in seconds since 2001.
+fn clauses(filter: &Filter, date: &str) -> (String, String) {
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    let mut chats = Vec::new();
+    if !filter.chats.is_empty() {
+        let names: Vec<String> = filter.chats.iter().map(|name| quote(name)).collect();
+        let names = names.join(", ");
+        chats.push(format!(
+            "(c.chat_identifier IN ({names}) OR c.guid IN ({names}) \
+             OR lower(c.display_name) IN ({}))",
+            names.to_lowercase()
+        ));
+    }
+    if !filter.with.is_empty() {
+        // Phone numbers are kept as in `+15555550123`, so matching their
+        // end matches them however given:
+        let handles: Vec<String> = filter
+            .with
+            .iter()
+            .filter_map(|handle| handle_key(handle))
+            .map(|key| match key.contains('@') {
+                true => format!("lower(wh.id) = {}", quote(&key)),
+                false => format!("substr(wh.id, -{}) = {}", key.len(), quote(&key)),
+            })
+            .collect();
+        chats.push(format!(
+            "EXISTS (SELECT 1 FROM chat_handle_join ch JOIN handle wh ON wh.ROWID = ch.handle_id \
+             WHERE ch.chat_id = c.ROWID AND ({}))",
+            match handles.is_empty() {
+                true => "0".to_string(),
+                false => handles.join(" OR "),
+            }
+        ));
+    }
+
+    let mut messages = Vec::new();
+    if let Some(after) = filter.after {
+        chats.push(format!(
+            "EXISTS (SELECT 1 FROM chat_message_join j \
+             WHERE j.chat_id = c.ROWID AND j.message_id > {after})"
+        ));
+        messages.push(format!("m.ROWID > {after}"));
+    }
+    // Local times, which `utc` turns into Unix time:
+    if let Some(since) = &filter.since {
+        messages.push(format!(
+            "{date} >= CAST(strftime('%s', '{}', 'utc') AS INTEGER) - {APPLE_EPOCH}",
+            since.as_str()
+        ));
+    }
+    if let Some(until) = &filter.until {
+        messages.push(format!(
+            "{date} < CAST(strftime('%s', '{}', 'utc') AS INTEGER) - {APPLE_EPOCH}",
+            until.as_str()
+        ));
+    }
+    if !chats.is_empty() {
+        messages.push(format!(
+            "m.ROWID IN (SELECT j.message_id FROM chat_message_join j \
+             JOIN chat c ON c.ROWID = j.chat_id WHERE {})",
+            chats.join(" AND ")
+        ));
+    }
+    let all = |clauses: Vec<String>| match clauses.is_empty() {
+        true => "1".to_string(),
+        false => clauses.join(" AND "),
+    };
+    (all(chats), all(messages))
+}
+
+fn parse_message(record: &Value, zone: &TimeZone) -> Result<Message> {
+    let text = |key: &str| {
+        record
+            .get(key)
+            .and_then(Value::as_str)
+            .filter(|text| !text.is_empty())
+            .map(String::from)
+    };
+    let flag = |key: &str| record.get(key).and_then(Value::as_i64).unwrap_or_default() != 0;
+    let Some(guid) = text("guid") else {
+        return Err(Error::Parse {
+            context: "reading message guid",
+            message: "missing guid field".to_string(),
+        });
+    };
+    // Each attachment stands in the text as an object replacement
+    // character, left out here:
+    let body = text("text")
+        .or_else(|| text("body").and_then(|body| attributed_text(&from_hex(&body)?)))
+        .map(|body| body.replace('\u{FFFC}', "").trim().to_string())
+        .filter(|body| !body.is_empty());
+    let reaction = record
+        .get("reactionType")
+        .and_then(Value::as_i64)
+        .zip(text("reactionTo"))
+        .and_then(|(kind, target)| Reaction::new(kind, &target, text("reactionEmoji").as_deref()));
+    let summary = text("summary")
+        .and_then(|summary| bplist::parse(&from_hex(&summary)?))
+        .unwrap_or(Plist::Dict(Vec::new()));
+    Ok(Message {
+        guid,
+        text: body,
+        handle: text("handle").map(|id| Handle {
+            id,
+            ..Handle::default()
+        }),
+        chat: text("chat"),
+        chat_guid: text("chatGuid"),
+        reply_to: text("replyTo"),
+        service: text("service"),
+        from_me: flag("fromMe"),
+        read: flag("read"),
+        sent: text("sent"),
+        read_at: text("readAt"),
+        attachments: record
+            .get("attachments")
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect(),
+        reaction,
+        edits: edits(&summary, zone),
+        edited: text("edited"),
+        // The parts unsent, by their indices:
+        unsent: summary
+            .get("rp")
+            .and_then(Plist::as_array)
+            .is_some_and(|parts| !parts.is_empty()),
+    })
+}
+
+/// Returns the history of a message's text in its `message_summary_info`,
+/// each version of every part edited, oldest first.
+fn edits(summary: &Plist, zone: &TimeZone) -> Vec<Edit> {
+    let mut edits: Vec<(f64, Edit)> = summary
+        .get("ec")
+        .and_then(Plist::as_dict)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|(_, versions)| versions.as_array())
+        .flatten()
+        .map(|version| {
+            let date = version.get("d").and_then(Plist::as_f64).unwrap_or_default();
+            let text = version
+                .get("t")
+                .and_then(Plist::as_data)
+                .and_then(attributed_text);
+            let edit = Edit {
+                text,
+                date: (date > 0.0).then(|| zone.to_local(date as i64 + APPLE_EPOCH)),
+            };
+            (date, edit)
+        })
+        .collect();
+    edits.sort_by(|a, b| a.0.total_cmp(&b.0));
+    edits.into_iter().map(|(_, edit)| edit).collect()
+}
+
+fn parse_chat(record: &Value) -> Option<Chat> {
+    let text = |key: &str| {
+        record
+            .get(key)
+            .and_then(Value::as_str)
+            .filter(|text| !text.is_empty())
+            .map(String::from)
+    };
+    Some(Chat {
+        guid: text("guid")?,
+        identifier: text("identifier"),
+        name: text("name"),
+        service: text("service"),
+        // Messages' style for a group chat, as opposed to 45 for a chat of
+        // two:
+        group: record.get("style").and_then(Value::as_i64) == Some(43),
+        participants: record
+            .get("handles")
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|id| Handle {
+                id: id.to_string(),
+                ..Handle::default()
+            })
+            .collect(),
+        messages: record
+            .get("messages")
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+    })
+}
+
+fn parse_attachment(record: &Value) -> Option<Attachment> {
+    let text = |key: &str| {
+        record
+            .get(key)
+            .and_then(Value::as_str)
+            .filter(|text| !text.is_empty())
+            .map(String::from)
+    };
+    Some(Attachment {
+        guid: text("guid")?,
+        message: text("message"),
+        name: text("name"),
+        path: text("path").map(PathBuf::from),
+        mime: text("mime"),
+        size: record.get("size").and_then(Value::as_u64),
+        transfer_state: record.get("transferState").and_then(Value::as_i64),
+    })
+}
+
+/// Returns the bytes a column read with `hex()` holds.
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len() / 2)
+        .map(|i| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok())
+        .collect()
+}
+
+/// Returns the text of an `NSAttributedString` archived in the typedstream
+/// format: the bytes of the first `NSString` in it, after a `+` and their
+/// length.
+fn attributed_text(bytes: &[u8]) -> Option<String> {
+    let start = bytes.windows(8).position(|window| window == b"NSString")?;
+    let rest = &bytes[start + 8..];
+    let rest = &rest[rest.iter().position(|&b| b == b'+')? + 1..];
+    // Lengths under 128 take a byte; longer ones follow a marker, in
+    // little-endian order:
+    let (length, rest) = match *rest.first()? {
+        0x81 => (
+            usize::from(u16::from_le_bytes([*rest.get(1)?, *rest.get(2)?])),
+            &rest[3..],
+        ),
+        0x82 => (
+            u32::from_le_bytes(rest.get(1..5)?.try_into().ok()?) as usize,
+            &rest[5..],
+        ),
+        length => (usize::from(length), &rest[1..]),
+    };
+    let text = String::from_utf8_lossy(rest.get(..length)?).into_owned();
+    (!text.is_empty()).then_some(text)
+}
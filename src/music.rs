@@ -0,0 +1,214 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Apple Music, read through its scripting dictionary from JavaScript for
+//! Automation, a property of every track at a time, which keeps a large
+//! library from taking an Apple event per track.
+
+use crate::{Error, Result, photos::iso8601_duration};
+use serde_json::{Value, json};
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The application name used in error messages.
+pub const APP: &str = "Music";
+
+/// How tracks are extracted, as recorded in their provenance.
+pub const BACKEND: &str = "music";
+
+/// The prefix of the URNs identifying individual tracks.
+pub const URN_PREFIX: &str = "urn:apple:music:track:";
+
+/// A track in the library.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Track {
+    /// Music's persistent ID for the track, in hex.
+    pub id: String,
+    pub name: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    /// Its length in seconds.
+    pub duration: Option<f64>,
+    pub year: Option<i64>,
+    pub track_number: Option<i64>,
+    pub disc_number: Option<i64>,
+    /// When it was added to the library, in local time.
+    pub added: Option<String>,
+    /// Where its file is, if it has one on this Mac.
+    pub location: Option<String>,
+    /// How it is in iCloud, as Music says: `matched`, `purchased`,
+    /// `uploaded`, `subscription`, and so on.
+    pub cloud_status: Option<String>,
+}
+
+impl Track {
+    /// Returns the stable URN identifying this track.
+    pub fn urn(&self) -> String {
+        format!("{URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this track: a schema.org
+    /// `MusicRecording`, by its artist, in its album.
+    pub fn to_json(&self) -> Value {
+        let artist = |name: &Option<String>| {
+            name.as_ref()
+                .map(|name| json!({ "@type": "MusicGroup", "name": name }))
+        };
+        let album = self.album.as_ref().map(|name| {
+            let mut album = json!({
+                "@type": "MusicAlbum",
+                "name": name,
+                "byArtist": artist(&self.album_artist),
+            });
+            if let Some(album) = album.as_object_mut() {
+                album.retain(|_, value| !value.is_null());
+            }
+            album
+        });
+        let mut record = json!({
+            "@type": "MusicRecording",
+            "@id": self.urn(),
+            "name": self.name,
+            "byArtist": artist(&self.artist),
+            "inAlbum": album,
+            "genre": self.genre,
+            "duration": self.duration.map(iso8601_duration),
+            "datePublished": self.year.map(|year| year.to_string()),
+            "trackNumber": self.track_number,
+            "discNumber": self.disc_number,
+            "dateCreated": self.added,
+            "path": self.location,
+            "cloudStatus": self.cloud_status,
+            "source": "apple-music",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Returns the script that extracts every track in the library, in
+/// Music's order, as JSON in the format [`parse`] reads.
+pub fn script() -> &'static str {
+    r#"
+var Music = Application('Music');
+function pad(n) {
+    return ('0' + n).slice(-2);
+}
+function date(d) {
+    if (!d) return null;
+    return d.getFullYear() + '-' + pad(d.getMonth() + 1) + '-' + pad(d.getDate()) + 'T' +
+        pad(d.getHours()) + ':' + pad(d.getMinutes()) + ':' + pad(d.getSeconds());
+}
+var library = Music.libraryPlaylists[0];
+var tracks = library.tracks;
+// A property of every track at once, or none, where Music has none to
+// give, as cloud status without iCloud Music Library:
+function all(property) {
+    try {
+        return tracks[property]();
+    } catch (e) {
+        return [];
+    }
+}
+var ids = all('persistentID');
+var names = all('name');
+var artists = all('artist');
+var albums = all('album');
+var albumArtists = all('albumArtist');
+var genres = all('genre');
+var durations = all('duration');
+var years = all('year');
+var trackNumbers = all('trackNumber');
+var discNumbers = all('discNumber');
+var added = all('dateAdded');
+var cloudStatuses = all('cloudStatus');
+// Only file tracks have a location, which is missing if the file is:
+var locations = {};
+try {
+    var files = library.fileTracks;
+    var fileIds = files.persistentID();
+    var paths = files.location();
+    fileIds.forEach(function (id, i) {
+        if (paths[i]) locations[id] = paths[i].toString();
+    });
+} catch (e) {}
+var output = { tracks: ids.map(function (id, i) {
+    return {
+        id: id,
+        name: names[i],
+        artist: artists[i],
+        album: albums[i],
+        albumArtist: albumArtists[i],
+        genre: genres[i],
+        duration: durations[i],
+        year: years[i],
+        trackNumber: trackNumbers[i],
+        discNumber: discNumbers[i],
+        added: date(added[i]),
+        location: locations[id] || null,
+        cloudStatus: cloudStatuses[i],
+    };
+}) };
+JSON.stringify(output);
+"#
+}
+
+/// Parses the output of [`script`], keeping the tracks in order.
+pub fn parse(output: &str) -> Result<Vec<Track>> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Vec::new()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading Music tracks",
+            message: e.to_string(),
+        })?,
+    };
+    let tracks = output
+        .get("tracks")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
+    Ok(tracks
+        .iter()
+        .filter_map(|track| {
+            // Music leaves numbers it doesn't know at 0:
+            let number = |key: &str| {
+                track
+                    .get(key)
+                    .and_then(Value::as_i64)
+                    .filter(|&number| number > 0)
+            };
+            Some(Track {
+                id: text(track, "id")?,
+                name: text(track, "name").unwrap_or_default(),
+                artist: text(track, "artist"),
+                album: text(track, "album"),
+                album_artist: text(track, "albumArtist"),
+                genre: text(track, "genre"),
+                duration: track
+                    .get("duration")
+                    .and_then(Value::as_f64)
+                    .filter(|&duration| duration > 0.0),
+                year: number("year"),
+                track_number: number("trackNumber"),
+                disc_number: number("discNumber"),
+                added: text(track, "added"),
+                location: text(track, "location"),
+                // Music says `unknown` for tracks iCloud knows nothing of:
+                cloud_status: text(track, "cloudStatus").filter(|status| status != "unknown"),
+            })
+        })
+        .collect())
+}
+
+fn text(record: &Value, key: &str) -> Option<String> {
+    record
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|text| !text.is_empty())
+        .map(String::from)
+}
@@ -0,0 +1,499 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::{Error, Result, osascript, throttle, timezone::TimeZone};
+use serde_json::{Value, json};
+use std::{
+    format,
+    string::{String, ToString},
+    sync::OnceLock,
+    vec::Vec,
+};
+
+/// The application name used in error messages.
+pub const APP: &str = "Apple Notes";
+
+/// How notes are extracted, as recorded in their provenance.
+pub const BACKEND: &str = "applescript";
+
+/// The prefix of the URNs identifying individual notes.
+pub const URN_PREFIX: &str = "urn:apple:notes:note:";
+
+/// The URL scheme registered for Apple Notes in the module manifest, as in
+/// `apple+notes://ACCOUNT/FOLDER/NOTE-ID`.
+pub const URL_SCHEME: &str = "apple+notes";
+
+const FIELD_SEPARATOR: &str = "|||";
+const RECORD_SEPARATOR: &str = "~~~";
+
+/// A single note as returned by the Notes AppleScript dictionary.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Note {
+    pub id: String,
+    pub name: String,
+    /// The note body as HTML; empty when extracted without bodies.
+    pub body_html: String,
+    /// The creation date in ISO 8601 local time, e.g. `2025-01-20T13:30:00`.
+    pub created: String,
+    /// The modification date in ISO 8601 local time.
+    pub modified: String,
+    pub folder: String,
+    pub account: String,
+}
+
+impl Note {
+    /// Returns the stable URN identifying this note.
+    pub fn urn(&self) -> String {
+        format!("{URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the `apple+notes://ACCOUNT/FOLDER/NOTE-ID` URL locating
+    /// this note, with the id shortened to its trailing `pNNN`.
+    pub fn url(&self) -> String {
+        let encode = |part: &str| {
+            percent_encoding::utf8_percent_encode(part, percent_encoding::NON_ALPHANUMERIC)
+                .to_string()
+        };
+        format!(
+            "{URL_SCHEME}://{}/{}/{}",
+            encode(&self.account),
+            encode(&self.folder),
+            encode(self.id.rsplit('/').next().unwrap_or(&self.id))
+        )
+    }
+
+    /// Converts the HTML body into plain text wrapped at `wrap_width`.
+    pub fn text(&self, wrap_width: usize) -> Result<String> {
+        Ok(html2text::from_read(self.body_html.as_bytes(), wrap_width)?
+            .trim()
+            .to_string())
+    }
+
+    /// Returns the full JSON-LD record for this note.
+    pub fn to_json(&self, wrap_width: usize) -> Result<Value> {
+        Ok(json!({
+            "@type": "CreativeWork",
+            "@id": self.urn(),
+            "name": self.name,
+            "text": self.text(wrap_width)?,
+            "dateCreated": with_offset(&self.created),
+            "dateModified": with_offset(&self.modified),
+            "isPartOf": self.folder,
+            "account": self.account,
+            "source": "apple-notes",
+        }))
+    }
+
+    /// Returns the lightweight catalog record for this note, which omits
+    /// the body so that enumerating a large library stays cheap.
+    pub fn to_catalog_json(&self) -> Value {
+        json!({
+            "@type": "CreativeWork",
+            "@id": self.urn(),
+            "name": self.name,
+            "dateCreated": with_offset(&self.created),
+            "dateModified": with_offset(&self.modified),
+            "source": "apple-notes",
+        })
+    }
+}
+
+/// Returns a local time of a note with the UTC offset then in effect, so
+/// that records tell the same instant wherever they are read. Anything
+/// that isn't an ISO 8601 local time is kept as it is.
+fn with_offset(local: &str) -> String {
+    static ZONE: OnceLock<TimeZone> = OnceLock::new();
+    ZONE.get_or_init(TimeZone::local)
+        .with_offset(local)
+        .unwrap_or_else(|| local.to_string())
+}
+
+/// A folder as returned by the Notes AppleScript dictionary.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Folder {
+    pub id: String,
+    pub name: String,
+    pub account: String,
+}
+
+impl Folder {
+    /// Returns the stable URN identifying this folder.
+    pub fn urn(&self) -> String {
+        format!("urn:apple:notes:folder:{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this folder.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "@type": "Collection",
+            "@id": self.urn(),
+            "name": self.name,
+            "account": self.account,
+            "source": "apple-notes",
+        })
+    }
+}
+
+/// Returns the AppleScript that lists every note in every folder of every
+/// account. Fetching bodies is by far the slowest part, so catalogers
+/// leave it out.
+pub fn script(include_body: bool) -> String {
+    let body = if include_body {
+        "the body of n"
+    } else {
+        "\"\""
+    };
+    let pace = throttle::applescript_delay();
+    format!(
+        r#"
+        set output to ""
+        tell application "Notes"
+            set theAccounts to every account
+            repeat with acc in theAccounts
+                set accName to the name of acc
+                set foldersList to every folder of acc
+                repeat with f in foldersList
+                    set folderName to the name of f
+                    set notesList to every note of f
+                    repeat with n in notesList
+                        set noteId to the id of n
+                        set noteName to the name of n
+                        set noteBody to {body}
+                        set noteCreated to the creation date of n as «class isot» as string
+                        set noteModified to the modification date of n as «class isot» as string
+                        set output to output & noteId & "|||"
+                        set output to output & noteName & "|||"
+                        set output to output & noteBody & "|||"
+                        set output to output & noteCreated & "|||"
+                        set output to output & noteModified & "|||"
+                        set output to output & folderName & "|||"
+                        set output to output & accName & "~~~"
+                        {pace}
+                    end repeat
+                end repeat
+            end repeat
+        end tell
+        return output
+    "#
+    )
+}
+
+/// The AppleScript that appends the record of note `n` to `output`, in
+/// the format [`parse`] reads.
+const NOTE_RECORD_SCRIPT: &str = r#"
+            set f to the container of n
+            set acc to f
+            repeat until class of acc is account
+                set acc to the container of acc
+            end repeat
+            set output to output & the id of n & "|||"
+            set output to output & the name of n & "|||"
+            set output to output & the body of n & "|||"
+            set output to output & (the creation date of n as «class isot» as string) & "|||"
+            set output to output & (the modification date of n as «class isot» as string) & "|||"
+            set output to output & the name of f & "|||"
+            set output to output & the name of acc & "~~~"
+"#;
+
+/// Returns the AppleScript that looks up the single note with the given
+/// id, producing the same record format as [`script`], or an empty string
+/// if no such note exists.
+pub fn note_script(id: &str) -> String {
+    let id = osascript::quote(id);
+    format!(
+        r#"
+        set output to ""
+        tell application "Notes"
+            if not (exists note id {id}) then return ""
+            set n to note id {id}
+            {NOTE_RECORD_SCRIPT}
+        end tell
+        return output
+    "#
+    )
+}
+
+/// Returns the AppleScript that finds the notes whose title or text
+/// contains `query`, ignoring case, producing the records of the first
+/// `limit` of them as [`script`] does. Notes does the searching, so notes
+/// that don't match are never sent over.
+pub fn search_script(query: &str, limit: usize) -> String {
+    let query = osascript::quote(query);
+    let pace = throttle::applescript_delay();
+    format!(
+        r#"
+        set output to ""
+        set found to 0
+        tell application "Notes"
+            repeat with n in (every note whose name contains {query} or plaintext contains {query})
+                if found ≥ {limit} then exit repeat
+                {NOTE_RECORD_SCRIPT}
+                set found to found + 1
+                {pace}
+            end repeat
+        end tell
+        return output
+    "#
+    )
+}
+
+/// Returns the AppleScript that looks up every note in `ids` in one go,
+/// producing one record per id in order: a note as in [`script`], or just
+/// the id for one that doesn't exist. See [`parse_batch`].
+pub fn batch_script(ids: &[&str]) -> String {
+    let ids: Vec<String> = ids.iter().map(|id| osascript::quote(id)).collect();
+    let pace = throttle::applescript_delay();
+    format!(
+        r#"
+        set output to ""
+        tell application "Notes"
+            repeat with noteId in {{{}}}
+                set noteId to contents of noteId
+                if exists note id noteId then
+                    set n to note id noteId
+                    {NOTE_RECORD_SCRIPT}
+                else
+                    set output to output & noteId & "~~~"
+                end if
+                {pace}
+            end repeat
+        end tell
+        return output
+    "#,
+        ids.join(", ")
+    )
+}
+
+/// Returns the AppleScript that lists every folder of every account.
+pub fn folders_script() -> &'static str {
+    r#"
+        set output to ""
+        tell application "Notes"
+            repeat with acc in every account
+                set accName to the name of acc
+                repeat with f in every folder of acc
+                    set output to output & the id of f & "|||"
+                    set output to output & the name of f & "|||"
+                    set output to output & accName & "~~~"
+                end repeat
+            end repeat
+        end tell
+        return output
+    "#
+}
+
+/// Returns the AppleScript that creates a note and prints its id. The note
+/// lands in `folder` (of `account`, if given), in the default folder of
+/// `account`, or in the default folder of the default account.
+pub fn create_script(
+    title: &str,
+    body_html: &str,
+    folder: Option<&str>,
+    account: Option<&str>,
+) -> String {
+    let location = match (folder, account) {
+        (Some(folder), Some(account)) => format!(
+            "at folder {} of account {} ",
+            osascript::quote(folder),
+            osascript::quote(account)
+        ),
+        (Some(folder), None) => format!("at folder {} ", osascript::quote(folder)),
+        (None, Some(account)) => {
+            format!(
+                "at default folder of account {} ",
+                osascript::quote(account)
+            )
+        }
+        (None, None) => String::new(),
+    };
+    format!(
+        r#"
+        tell application "Notes"
+            set n to make new note {location}with properties {{name:{title}, body:{body}}}
+            return the id of n
+        end tell
+    "#,
+        title = osascript::quote(title),
+        body = osascript::quote(body_html),
+    )
+}
+
+/// Converts a plain-text title and body into the HTML structure Notes
+/// itself produces: a heading followed by one `<div>` per line.
+pub fn text_to_html(title: &str, text: &str) -> String {
+    let mut html = format!("<div><h1>{}</h1></div>", escape_html(title));
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            html.push_str("<div><br></div>");
+        } else {
+            html.push_str(&format!("<div>{}</div>", escape_html(line)));
+        }
+    }
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Extracts the note id from a note URN, also accepting a bare Notes id
+/// such as `x-coredata://…/ICNote/p123`.
+pub fn id_from_urn(input: &str) -> Option<&str> {
+    let input = input.trim();
+    let id = input.strip_prefix(URN_PREFIX).unwrap_or(input);
+    id.starts_with("x-coredata://").then_some(id)
+}
+
+/// What an `apple+notes://ACCOUNT[/FOLDER[/NOTE-ID]]` URL refers to. Each
+/// part is percent-encoded; the note id is either a full Notes id or just
+/// its trailing `pNNN`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Locator {
+    Account {
+        account: String,
+    },
+    Folder {
+        account: String,
+        folder: String,
+    },
+    Note {
+        account: String,
+        folder: String,
+        id: String,
+    },
+}
+
+impl Locator {
+    /// Parses an `apple+notes://` URL, returning `None` for anything else.
+    pub fn parse(input: &str) -> Option<Self> {
+        let url = url::Url::parse(input.trim()).ok()?;
+        if url.scheme() != URL_SCHEME {
+            return None;
+        }
+        let decode = |part: &str| {
+            percent_encoding::percent_decode_str(part)
+                .decode_utf8()
+                .ok()
+                .map(|part| part.into_owned())
+        };
+        let account = decode(url.host_str().filter(|host| !host.is_empty())?)?;
+        let mut segments = url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty());
+        let folder = match segments.next() {
+            Some(folder) => decode(folder)?,
+            None => return Some(Locator::Account { account }),
+        };
+        let id = match segments.next() {
+            Some(id) => decode(id)?,
+            None => return Some(Locator::Folder { account, folder }),
+        };
+        segments.next().is_none().then_some(Locator::Note {
+            account,
+            folder,
+            id,
+        })
+    }
+
+    /// Returns the full Notes id, if this locates a note by one.
+    pub fn full_id(&self) -> Option<&str> {
+        match self {
+            Locator::Note { id, .. } => id_from_urn(id),
+            _ => None,
+        }
+    }
+
+    /// Returns whether `note` is, or is contained in, what this locates.
+    pub fn matches(&self, note: &Note) -> bool {
+        match self {
+            Locator::Account { account } => note.account == *account,
+            Locator::Folder { account, folder } => {
+                note.account == *account && note.folder == *folder
+            }
+            Locator::Note {
+                account,
+                folder,
+                id,
+            } => {
+                note.account == *account
+                    && note.folder == *folder
+                    && (note.id == *id || note.id.rsplit('/').next() == Some(id.as_str()))
+            }
+        }
+    }
+}
+
+/// Parses the output of [`script`] into notes, one per record.
+pub fn parse(output: &str) -> impl Iterator<Item = Result<Note>> + '_ {
+    output
+        .split(RECORD_SEPARATOR)
+        .filter(|chunk| !chunk.trim().is_empty())
+        .map(parse_record)
+}
+
+/// Parses the output of [`batch_script`]: for each id in order, either
+/// the note, or `Err` with the id of a note that doesn't exist.
+pub fn parse_batch(
+    output: &str,
+) -> impl Iterator<Item = Result<core::result::Result<Note, String>>> + '_ {
+    output
+        .split(RECORD_SEPARATOR)
+        .filter(|chunk| !chunk.trim().is_empty())
+        .map(|chunk| {
+            if chunk.contains(FIELD_SEPARATOR) {
+                parse_record(chunk).map(Ok)
+            } else {
+                Ok(Err(chunk.trim().to_string()))
+            }
+        })
+}
+
+/// Parses the output of [`folders_script`] into folders, one per record.
+pub fn parse_folders(output: &str) -> impl Iterator<Item = Result<Folder>> + '_ {
+    output
+        .split(RECORD_SEPARATOR)
+        .filter(|chunk| !chunk.trim().is_empty())
+        .map(|chunk| {
+            let mut parts = chunk.split(FIELD_SEPARATOR).map(str::trim);
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(id), Some(name), Some(account)) => Ok(Folder {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    account: account.to_string(),
+                }),
+                _ => Err(Error::Parse {
+                    context: "reading folder record",
+                    message: "missing folder fields".to_string(),
+                }),
+            }
+        })
+}
+
+fn parse_record(chunk: &str) -> Result<Note> {
+    let mut parts = chunk.split(FIELD_SEPARATOR);
+    let mut field = |context: &'static str, message: &'static str| {
+        parts
+            .next()
+            .map(|part| part.trim().to_string())
+            .ok_or_else(|| Error::Parse {
+                context,
+                message: message.to_string(),
+            })
+    };
+
+    Ok(Note {
+        id: field("reading note id", "missing id field")?,
+        name: field("reading note name", "missing name field")?,
+        body_html: field("reading note body", "missing body field")?,
+        created: field("reading creation date", "missing creation date field")?,
+        modified: field(
+            "reading modification date",
+            "missing modification date field",
+        )?,
+        folder: field("reading folder name", "missing folder field")?,
+        account: field("reading account name", "missing account field")?,
+    })
+}
@@ -0,0 +1,138 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::{Error, Result, throttle};
+use std::{
+    io::{self, Read},
+    process::{Command, Stdio},
+    string::String,
+    thread,
+    time::Duration,
+    vec::Vec,
+};
+
+/// How often to check on a running `osascript` child.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs `script` through `osascript` on behalf of `app` and returns its
+/// stdout, or `None` if `interrupted` reported true before the script
+/// finished (in which case the child has already been killed). Waits for
+/// its turn first if a [`throttle`] rate limit is installed.
+pub fn run(
+    app: &'static str,
+    script: &str,
+    interrupted: impl Fn() -> bool,
+) -> Result<Option<String>> {
+    spawn(app, &["-e", script], interrupted)
+}
+
+/// Runs `script` as JavaScript for Automation, as [`run`] runs AppleScript.
+/// Scripts reach frameworks such as EventKit through its Objective-C
+/// bridge, for what an app's AppleScript dictionary leaves out.
+pub fn run_javascript(
+    app: &'static str,
+    script: &str,
+    interrupted: impl Fn() -> bool,
+) -> Result<Option<String>> {
+    spawn(app, &["-l", "JavaScript", "-e", script], interrupted)
+}
+
+fn spawn(
+    app: &'static str,
+    args: &[&str],
+    interrupted: impl Fn() -> bool,
+) -> Result<Option<String>> {
+    if !throttle::wait(&interrupted) {
+        return Ok(None);
+    }
+
+    let mut child = Command::new("osascript")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Io {
+            context: "invoking osascript",
+            source: e,
+        })?;
+
+    let stdout_reader = spawn_reader(child.stdout.take());
+    let stderr_reader = spawn_reader(child.stderr.take());
+
+    let status = loop {
+        if interrupted() {
+            child.kill().ok();
+            child.wait().ok();
+            break None;
+        }
+        match child.try_wait().map_err(|e| Error::Io {
+            context: "waiting for osascript",
+            source: e,
+        })? {
+            Some(status) => break Some(status),
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    };
+
+    let stdout = join_reader(stdout_reader, "reading osascript output")?;
+    let stderr = join_reader(stderr_reader, "reading osascript errors")?;
+
+    // The child shares our process group, so Ctrl-C may have killed
+    // it before we noticed the interrupt ourselves:
+    let Some(status) = status.filter(|_| !interrupted()) else {
+        return Ok(None);
+    };
+
+    #[cfg(feature = "tracing")]
+    asimov_module::tracing::debug!(
+        target: "asimov_apple_module::osascript",
+        app,
+        ?status,
+        stdout_len = stdout.len(),
+        stderr_len = stderr.len(),
+        "osascript completed"
+    );
+
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr).into_owned();
+        return Err(Error::from_osascript(app, status, stderr));
+    }
+
+    Ok(Some(String::from_utf8_lossy(&stdout).into_owned()))
+}
+
+/// Quotes `value` as an AppleScript string literal.
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn spawn_reader<R: Read + Send + 'static>(
+    pipe: Option<R>,
+) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buf)?;
+        }
+        Ok(buf)
+    })
+}
+
+fn join_reader(
+    handle: thread::JoinHandle<io::Result<Vec<u8>>>,
+    context: &'static str,
+) -> Result<Vec<u8>> {
+    handle
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("reader thread panicked")))
+        .map_err(|source| Error::Io { context, source })
+}
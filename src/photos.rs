@@ -0,0 +1,1316 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Apple Photos, read through PhotoKit from JavaScript for Automation,
+//! which gives every asset's metadata without downloading it from iCloud.
+//! Only the camera's EXIF data needs each photo's file, and is read only
+//! for the photos kept on this Mac.
+
+use crate::{Error, Result};
+use core::str::FromStr;
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+pub mod store;
+
+/// The application name used in error messages.
+pub const APP: &str = "Photos";
+
+/// How photos are extracted, as recorded in their provenance.
+pub const BACKEND: &str = "photokit";
+
+/// The prefix of the URNs identifying individual assets.
+pub const URN_PREFIX: &str = "urn:apple:photos:asset:";
+
+/// The prefix of the URNs identifying albums.
+pub const ALBUM_URN_PREFIX: &str = "urn:apple:photos:album:";
+
+/// The prefix of the URNs identifying the people Photos recognizes.
+pub const PERSON_URN_PREFIX: &str = "urn:apple:photos:person:";
+
+/// The prefix of the URNs identifying the memories Photos makes.
+pub const MEMORY_URN_PREFIX: &str = "urn:apple:photos:memory:";
+
+/// The JavaScript that every script starts with: it asks for access to
+/// the library, waiting for the answer, and defines `wait(done)`, `nil`,
+/// `string`, and `date`, as [`eventkit::prelude`](crate::eventkit::prelude)
+/// does for EventKit.
+const PRELUDE: &str = r#"
+ObjC.import('Photos');
+function wait(done) {
+    while (!done()) {
+        $.NSRunLoop.currentRunLoop.runUntilDate($.NSDate.dateWithTimeIntervalSinceNow(0.05));
+    }
+}
+var status = null;
+// PHAccessLevelReadWrite; PHAuthorizationStatusAuthorized or Limited:
+$.PHPhotoLibrary.requestAuthorizationForAccessLevelHandler(2, function (s) { status = s; });
+wait(function () { return status !== null; });
+if (status !== 3 && status !== 4) {
+    throw new Error('Not authorized to access photos. (-1743)');
+}
+var formatter = $.NSDateFormatter.alloc.init;
+formatter.locale = $.NSLocale.localeWithLocaleIdentifier('en_US_POSIX');
+formatter.dateFormat = "yyyy-MM-dd'T'HH:mm:ss";
+function nil(value) {
+    return value === undefined || value === null || value.isNil();
+}
+function string(value) {
+    var text = nil(value) ? null : ObjC.unwrap(value);
+    return text === '' ? null : text;
+}
+function date(value) {
+    return nil(value) ? null : formatter.stringFromDate(value).js;
+}
+"#;
+
+/// The JavaScript that defines `record(a, albums, people)`, describing
+/// asset `a`, in the albums `albums` and showing the people `people`
+/// (their ids), in the format [`parse`] reads, with its location unless
+/// `withLocation` is false.
+const RECORD_SCRIPT: &str = r#"
+ObjC.import('CoreImage');
+var images = $.PHImageManager.defaultManager;
+var imageOptions = $.PHImageRequestOptions.alloc.init;
+imageOptions.synchronous = true;
+// Only photos kept on this Mac, as the others would be downloaded:
+imageOptions.networkAccessAllowed = false;
+// The camera's EXIF data, read from the header of the photo's file:
+function camera(a) {
+    if (a.mediaType !== 1) return null;
+    var data = null;
+    images.requestImageDataAndOrientationForAssetOptionsResultHandler(a, imageOptions, function (d) { data = d; });
+    var image = nil(data) ? null : $.CIImage.imageWithData(data);
+    if (image === null || nil(image)) return null;
+    var properties = image.properties;
+    function dictionary(key) {
+        var value = properties.objectForKey(key);
+        return nil(value) ? {} : ObjC.deepUnwrap(value) || {};
+    }
+    var tiff = dictionary('{TIFF}'), exif = dictionary('{Exif}'), aux = dictionary('{ExifAux}');
+    var iso = exif.ISOSpeedRatings;
+    return {
+        make: tiff.Make || null,
+        model: tiff.Model || null,
+        lens: exif.LensModel || aux.LensModel || null,
+        exposureTime: exif.ExposureTime || null,
+        fNumber: exif.FNumber || null,
+        iso: Array.isArray(iso) ? iso[0] : iso || null,
+        focalLength: exif.FocalLength || null,
+    };
+}
+function place(a) {
+    var location = withLocation ? a.location : $();
+    if (nil(location)) return null;
+    return {
+        latitude: location.coordinate.latitude,
+        longitude: location.coordinate.longitude,
+        altitude: location.verticalAccuracy < 0 ? null : location.altitude,
+    };
+}
+function filename(a) {
+    var resources = $.PHAssetResource.assetResourcesForAsset(a);
+    return nil(resources) || resources.count === 0 ? null : string(resources.objectAtIndex(0).originalFilename);
+}
+// The video of a Live Photo (PHAssetMediaSubtypePhotoLive), from its
+// PHAssetResourceTypePairedVideo resource:
+function pairedVideo(a) {
+    if ((a.mediaSubtypes & 8) === 0) return null;
+    var resources = $.PHAssetResource.assetResourcesForAsset(a);
+    for (var i = 0; !nil(resources) && i < resources.count; i++) {
+        var r = resources.objectAtIndex(i);
+        if (r.type === 9) return string(r.originalFilename) || '';
+    }
+    return '';
+}
+// The other photos of the burst that `a` stands for, which PhotoKit
+// leaves out of fetches:
+var burstOptions = $.PHFetchOptions.alloc.init;
+burstOptions.includeAllBurstAssets = true;
+function burst(a) {
+    if (!a.representsBurst || nil(a.burstIdentifier)) return [];
+    var frames = $.PHAsset.fetchAssetsWithBurstIdentifierOptions(a.burstIdentifier, burstOptions);
+    var found = [];
+    for (var i = 0; !nil(frames) && i < frames.count; i++) {
+        var f = frames.objectAtIndex(i);
+        if (f.localIdentifier.js === a.localIdentifier.js) continue;
+        found.push({ id: f.localIdentifier.js, filename: filename(f), created: date(f.creationDate) });
+    }
+    return found;
+}
+function record(a, albums, people) {
+    return {
+        id: a.localIdentifier.js,
+        filename: filename(a),
+        mediaType: a.mediaType,
+        created: date(a.creationDate),
+        modified: date(a.modificationDate),
+        width: a.pixelWidth,
+        height: a.pixelHeight,
+        duration: a.duration,
+        albums: albums || [],
+        people: people || [],
+        camera: camera(a),
+        location: place(a),
+        pairedVideo: pairedVideo(a),
+        burst: burst(a),
+    };
+}
+"#;
+
+/// Everything a script extracts: every album, every named person, every
+/// memory, then every asset in the library, oldest first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Library {
+    pub albums: Vec<Album>,
+    pub people: Vec<Person>,
+    pub memories: Vec<Memory>,
+    pub assets: Vec<Asset>,
+}
+
+impl Library {
+    /// Keeps only the albums with the given names, and the assets in
+    /// them.
+    pub fn retain_albums(&mut self, names: &[String]) {
+        self.albums.retain(|album| names.contains(&album.name));
+        let albums = &self.albums;
+        self.assets
+            .retain(|asset| albums.iter().any(|album| asset.albums.contains(&album.id)));
+    }
+
+    /// Keeps only the people with the given names, and the assets showing
+    /// any of them.
+    pub fn retain_people(&mut self, names: &[String]) {
+        self.people.retain(|person| names.contains(&person.name));
+        let people = &self.people;
+        self.assets.retain(|asset| {
+            people
+                .iter()
+                .any(|person| asset.people.contains(&person.id))
+        });
+    }
+}
+
+/// Someone Photos recognizes the face of, and has been given a name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Person {
+    pub id: String,
+    pub name: String,
+}
+
+impl Person {
+    /// Returns the stable URN identifying this person.
+    pub fn urn(&self) -> String {
+        format!("{PERSON_URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this person.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "@type": "Person",
+            "@id": self.urn(),
+            "name": self.name,
+            "source": "apple-photos",
+        })
+    }
+}
+
+/// An album, or a smart album, which Photos fills with the assets that
+/// match its criteria.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Album {
+    pub id: String,
+    pub name: String,
+    pub smart: bool,
+    /// What a smart album Photos provides shows, as in `favorites`. The
+    /// rules of smart albums made in Photos can't be read through
+    /// PhotoKit.
+    pub criteria: Option<&'static str>,
+    /// How many assets are in the album.
+    pub size: usize,
+}
+
+impl Album {
+    /// Returns the stable URN identifying this album.
+    pub fn urn(&self) -> String {
+        format!("{ALBUM_URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this album.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "Collection",
+            "@id": self.urn(),
+            "name": self.name,
+            "additionalType": if self.smart { "smartAlbum" } else { "album" },
+            "criteria": self.criteria,
+            "numberOfItems": self.size,
+            "source": "apple-photos",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Returns what the smart album with PhotoKit's `PHAssetCollectionSubtype`
+/// shows, or `None` for those made in Photos.
+fn smart_album_criteria(subtype: i64) -> Option<&'static str> {
+    Some(match subtype {
+        201 => "panoramas",
+        202 => "videos",
+        203 => "favorites",
+        204 => "time-lapses",
+        205 => "hidden",
+        206 => "recently added",
+        207 => "bursts",
+        208 => "slo-mo videos",
+        210 => "selfies",
+        211 => "screenshots",
+        212 => "portraits",
+        213 => "live photos",
+        214 => "animated",
+        215 => "long exposures",
+        216 => "unable to upload",
+        217 => "raw",
+        218 => "cinematic videos",
+        219 => "spatial media",
+        _ => return None,
+    })
+}
+
+/// A memory, one of the collections Photos makes of the photos and videos
+/// of an event, a trip, a person, and so on.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Memory {
+    pub id: String,
+    /// Its title, as in `Summer in London`.
+    pub name: Option<String>,
+    /// When its first and last photos were taken, in local time.
+    pub start: Option<String>,
+    pub end: Option<String>,
+    /// The ids of its assets.
+    pub assets: Vec<String>,
+    /// The ids of the assets Photos shows it with.
+    pub key_assets: Vec<String>,
+}
+
+impl Memory {
+    /// Returns the stable URN identifying this memory.
+    pub fn urn(&self) -> String {
+        format!("{MEMORY_URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this memory.
+    pub fn to_json(&self) -> Value {
+        let urns = |ids: &[String]| -> Vec<String> {
+            ids.iter().map(|id| format!("{URN_PREFIX}{id}")).collect()
+        };
+        let mut record = json!({
+            "@type": "Collection",
+            "@id": self.urn(),
+            "name": self.name,
+            "additionalType": "memory",
+            "temporalCoverage": match (&self.start, &self.end) {
+                (Some(start), Some(end)) => Some(format!("{start}/{end}")),
+                _ => None,
+            },
+            "image": (!self.key_assets.is_empty()).then(|| urns(&self.key_assets)),
+            "hasPart": urns(&self.assets),
+            "numberOfItems": self.assets.len(),
+            "source": "apple-photos",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Which assets a script extracts; each filter set must match.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Only favorites.
+    pub favorites: bool,
+    /// Only hidden assets, which are otherwise left out.
+    pub hidden: bool,
+    pub screenshots: bool,
+    /// Only photos taken in bursts.
+    pub bursts: bool,
+    pub media: Option<Media>,
+}
+
+impl Filter {
+    /// Returns the `NSPredicate` format that PhotoKit selects the assets
+    /// with when fetching them, or `None` for every asset.
+    fn predicate(&self) -> Option<String> {
+        let mut clauses = Vec::new();
+        if self.favorites {
+            clauses.push("favorite == YES");
+        }
+        if self.hidden {
+            clauses.push("hidden == YES");
+        }
+        // PHAssetMediaSubtypePhotoScreenshot:
+        if self.screenshots {
+            clauses.push("(mediaSubtypes & 4) != 0");
+        }
+        if self.bursts {
+            clauses.push("burstIdentifier != nil");
+        }
+        // PHAssetMediaTypeImage and Video, and PHAssetMediaSubtypePhotoLive:
+        match self.media {
+            Some(Media::Photo) => clauses.push("mediaType == 1"),
+            Some(Media::Video) => clauses.push("mediaType == 2"),
+            Some(Media::Live) => clauses.push("(mediaSubtypes & 8) != 0"),
+            None => {}
+        }
+        (!clauses.is_empty()).then(|| clauses.join(" AND "))
+    }
+}
+
+/// Where the emitter reads the library from, as `--backend` selects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// PhotoKit, asset by asset.
+    #[default]
+    PhotoKit,
+    /// The library's database, through [`store`].
+    Sqlite,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        match input {
+            "photokit" => Ok(Backend::PhotoKit),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err(format!(
+                "unsupported backend: {input} (expected photokit or sqlite)"
+            )),
+        }
+    }
+}
+
+/// A kind of asset, as `--media-type` selects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Media {
+    Photo,
+    Video,
+    /// Live Photos, with the video taken along with them.
+    Live,
+}
+
+impl FromStr for Media {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        match input {
+            "photo" => Ok(Media::Photo),
+            "video" => Ok(Media::Video),
+            "live" => Ok(Media::Live),
+            _ => Err(format!(
+                "unsupported media type: {input} (expected photo, video, or live)"
+            )),
+        }
+    }
+}
+
+/// What an asset is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MediaType {
+    #[default]
+    Unknown,
+    Image,
+    Video,
+    Audio,
+}
+
+impl MediaType {
+    /// Returns the media type for PhotoKit's `PHAssetMediaType`.
+    fn from_photokit(value: i64) -> Self {
+        match value {
+            1 => MediaType::Image,
+            2 => MediaType::Video,
+            3 => MediaType::Audio,
+            _ => MediaType::Unknown,
+        }
+    }
+
+    /// Returns the name of the media type, as in `image`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MediaType::Unknown => "unknown",
+            MediaType::Image => "image",
+            MediaType::Video => "video",
+            MediaType::Audio => "audio",
+        }
+    }
+
+    /// Returns the schema.org type of assets of the media type, as in
+    /// `ImageObject`.
+    pub fn class(self) -> &'static str {
+        match self {
+            MediaType::Unknown => "MediaObject",
+            MediaType::Image => "ImageObject",
+            MediaType::Video => "VideoObject",
+            MediaType::Audio => "AudioObject",
+        }
+    }
+}
+
+/// A single photo or video in the library, as PhotoKit describes it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Asset {
+    /// The asset's local identifier, as in
+    /// `9F983DBA-EC35-42B8-8773-B597CF782EDD/L0/001`.
+    pub id: String,
+    /// The name of the file it was imported from, as in `IMG_0001.HEIC`.
+    pub filename: Option<String>,
+    pub media_type: MediaType,
+    /// When it was taken, in local time.
+    pub created: Option<String>,
+    /// When it was last changed, in local time.
+    pub modified: Option<String>,
+    /// Its size, in pixels.
+    pub width: u64,
+    pub height: u64,
+    /// How long a video lasts, in seconds.
+    pub duration: Option<f64>,
+    /// The ids of the albums it is in.
+    pub albums: Vec<String>,
+    /// The ids of the named people it shows.
+    pub people: Vec<String>,
+    /// What took a photo, from its EXIF data, for photos kept on this
+    /// Mac.
+    pub camera: Option<Camera>,
+    /// Where it was taken.
+    pub location: Option<Location>,
+    /// For a Live Photo, the name of its video's file, if known.
+    pub paired_video: Option<Option<String>>,
+    /// For the photo a burst is shown as, the burst's other photos.
+    pub burst: Vec<BurstFrame>,
+    /// Its keywords, which PhotoKit can't read, so only as written by
+    /// [`tag_script`] or [`caption_script`].
+    pub keywords: Vec<String>,
+    /// Its caption, likewise.
+    pub caption: Option<String>,
+}
+
+/// One of the photos of a burst, other than the one Photos shows it as.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BurstFrame {
+    pub id: String,
+    pub filename: Option<String>,
+    pub created: Option<String>,
+}
+
+impl BurstFrame {
+    /// Returns the JSON-LD node for this photo, for `hasPart`.
+    pub fn to_json(&self) -> Value {
+        let mut node = json!({
+            "@type": "ImageObject",
+            "@id": format!("{URN_PREFIX}{}", self.id),
+            "name": self.filename,
+            "dateCreated": self.created,
+        });
+        if let Some(node) = node.as_object_mut() {
+            node.retain(|_, value| !value.is_null());
+        }
+        node
+    }
+}
+
+/// The camera, lens, and exposure a photo was taken with.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Camera {
+    /// The camera's maker, as in `Apple`.
+    pub make: Option<String>,
+    /// The camera's model, as in `iPhone 15 Pro`.
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    /// The exposure time, in seconds.
+    pub exposure_time: Option<f64>,
+    /// The aperture, as in 1.8 for f/1.8.
+    pub f_number: Option<f64>,
+    pub iso: Option<u64>,
+    /// The focal length, in millimeters.
+    pub focal_length: Option<f64>,
+}
+
+impl Camera {
+    /// Returns the camera data as schema.org `PropertyValue`s, for
+    /// `exifData`.
+    pub fn to_json(&self) -> Vec<Value> {
+        let exposure_time = self.exposure_time.map(|seconds| match seconds {
+            seconds if seconds > 0.0 && seconds < 1.0 => {
+                format!("1/{}", (1.0 / seconds).round())
+            }
+            seconds => format!("{seconds}"),
+        });
+        [
+            ("make", self.make.clone().map(Value::from), None),
+            ("model", self.model.clone().map(Value::from), None),
+            ("lensModel", self.lens.clone().map(Value::from), None),
+            ("exposureTime", exposure_time.map(Value::from), Some("s")),
+            ("fNumber", self.f_number.map(Value::from), None),
+            ("isoSpeed", self.iso.map(Value::from), None),
+            (
+                "focalLength",
+                self.focal_length.map(Value::from),
+                Some("mm"),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(id, value, unit)| {
+            let mut property = json!({
+                "@type": "PropertyValue",
+                "propertyID": id,
+                "value": value?,
+                "unitText": unit,
+            });
+            if let Some(property) = property.as_object_mut() {
+                property.retain(|_, value| !value.is_null());
+            }
+            Some(property)
+        })
+        .collect()
+    }
+}
+
+/// Where an asset was taken, from its GPS data.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// The altitude, in meters above sea level, when known.
+    pub altitude: Option<f64>,
+}
+
+impl Location {
+    /// Returns the location as a schema.org `GeoCoordinates`.
+    pub fn to_json(&self) -> Value {
+        let mut geo = json!({
+            "@type": "GeoCoordinates",
+            "latitude": self.latitude,
+            "longitude": self.longitude,
+            "elevation": self.altitude,
+        });
+        if let Some(geo) = geo.as_object_mut() {
+            geo.retain(|_, value| !value.is_null());
+        }
+        geo
+    }
+}
+
+impl Asset {
+    /// Returns the stable URN identifying this asset.
+    pub fn urn(&self) -> String {
+        format!("{URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this asset: a schema.org
+    /// `ImageObject`, `VideoObject`, or `AudioObject`, with its metadata
+    /// only. Unset fields are left out.
+    pub fn to_json(&self) -> Value {
+        let albums: Vec<String> = self
+            .albums
+            .iter()
+            .map(|id| format!("{ALBUM_URN_PREFIX}{id}"))
+            .collect();
+        let people: Vec<String> = self
+            .people
+            .iter()
+            .map(|id| format!("{PERSON_URN_PREFIX}{id}"))
+            .collect();
+        let mut record = json!({
+            "@type": self.media_type.class(),
+            "@id": self.urn(),
+            "name": self.filename,
+            "additionalType": self.media_type.as_str(),
+            "dateCreated": self.created,
+            "dateModified": self.modified,
+            "width": self.width,
+            "height": self.height,
+            "duration": self.duration.map(iso8601_duration),
+            "isPartOf": (!albums.is_empty()).then_some(albums),
+            "about": (!people.is_empty()).then_some(people),
+            "exifData": self
+                .camera
+                .as_ref()
+                .map(Camera::to_json)
+                .filter(|exif| !exif.is_empty()),
+            "geo": self.location.as_ref().map(Location::to_json),
+            "associatedMedia": self.paired_video.as_ref().map(|filename| {
+                let mut video = json!({
+                    "@type": "VideoObject",
+                    "name": filename,
+                    "additionalType": "pairedVideo",
+                });
+                if let Some(video) = video.as_object_mut() {
+                    video.retain(|_, value| !value.is_null());
+                }
+                Vec::from([video])
+            }),
+            "keywords": (!self.keywords.is_empty()).then_some(&self.keywords),
+            "caption": self.caption,
+            "hasPart": (!self.burst.is_empty())
+                .then(|| self.burst.iter().map(BurstFrame::to_json).collect::<Vec<_>>()),
+            "source": "apple-photos",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Returns a length of time in seconds as an ISO 8601 duration, as in
+/// `PT1M5.5S`.
+pub(crate) fn iso8601_duration(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    let (minutes, millis) = (millis / 60_000, millis % 60_000);
+    let (hours, minutes) = (minutes / 60, minutes % 60);
+    let mut text = String::from("PT");
+    if hours > 0 {
+        text += &format!("{hours}H");
+    }
+    if minutes > 0 {
+        text += &format!("{minutes}M");
+    }
+    if millis > 0 || text.len() == 2 {
+        let seconds = format!("{}.{:03}", millis / 1000, millis % 1000);
+        text += seconds.trim_end_matches('0').trim_end_matches('.');
+        text += "S";
+    }
+    text
+}
+
+/// Returns the script that extracts every album and smart album, every
+/// named person, every memory, and every asset `filter` selects, oldest first, with the albums it is in
+/// and the people it shows, as JSON in the format
+/// [`parse`] reads. Hidden assets are left out, as Photos does, and so
+/// are empty smart albums and the "Recents" one with every asset in it.
+/// Locations are read only if `location` is true.
+pub fn script(filter: &Filter, location: bool) -> String {
+    let predicate = Value::from(filter.predicate());
+    let hidden = filter.hidden;
+    format!(
+        r#"
+{PRELUDE}
+var withLocation = {location};
+{RECORD_SCRIPT}
+var output = {{ albums: [], people: [], memories: [], assets: [] }};
+function ids(assets) {{
+    var found = [];
+    for (var i = 0; !nil(assets) && i < assets.count; i++) found.push(assets.objectAtIndex(i).localIdentifier.js);
+    return found;
+}}
+// Which albums each asset is in, read album by album, as asking asset by
+// asset is much slower:
+var albums = {{}};
+// PHAssetCollectionTypeAlbum, then PHAssetCollectionTypeSmartAlbum:
+[1, 2].forEach(function (type) {{
+    var collections = $.PHAssetCollection.fetchAssetCollectionsWithTypeSubtypeOptions(type, 2147483647, $());
+    for (var i = 0; !nil(collections) && i < collections.count; i++) {{
+        var album = collections.objectAtIndex(i);
+        var members = $.PHAsset.fetchAssetsInAssetCollectionOptions(album, $());
+        var size = nil(members) ? 0 : members.count;
+        // PHAssetCollectionSubtypeSmartAlbumUserLibrary:
+        if (type === 2 && (size === 0 || album.assetCollectionSubtype === 209)) continue;
+        output.albums.push({{
+            id: album.localIdentifier.js,
+            name: string(album.localizedTitle),
+            smart: type === 2,
+            subtype: album.assetCollectionSubtype,
+            size: size,
+        }});
+        for (var j = 0; j < size; j++) {{
+            var id = members.objectAtIndex(j).localIdentifier.js;
+            (albums[id] = albums[id] || []).push(album.localIdentifier.js);
+        }}
+    }}
+}});
+// Who each asset shows, read person by person. People aren't part of
+// PhotoKit's public API, so on some versions of macOS there are none:
+var people = {{}};
+try {{
+    var persons = $.PHPerson.fetchPersonsWithOptions($());
+    for (var i = 0; !nil(persons) && i < persons.count; i++) {{
+        var person = persons.objectAtIndex(i);
+        var name = string(person.name);
+        if (name === null) continue;
+        output.people.push({{ id: person.localIdentifier.js, name: name }});
+        var members = $.PHAsset.fetchAssetsForPersonOptions(person, $());
+        for (var j = 0; !nil(members) && j < members.count; j++) {{
+            var id = members.objectAtIndex(j).localIdentifier.js;
+            (people[id] = people[id] || []).push(person.localIdentifier.js);
+        }}
+    }}
+}} catch (e) {{
+    output.people = [];
+    people = {{}};
+}}
+// Memories are a collection type, 4, that isn't part of PhotoKit's public
+// API either:
+try {{
+    var memories = $.PHAssetCollection.fetchAssetCollectionsWithTypeSubtypeOptions(4, 2147483647, $());
+    for (var i = 0; !nil(memories) && i < memories.count; i++) {{
+        var memory = memories.objectAtIndex(i);
+        output.memories.push({{
+            id: memory.localIdentifier.js,
+            name: string(memory.localizedTitle),
+            start: date(memory.startDate),
+            end: date(memory.endDate),
+            assets: ids($.PHAsset.fetchAssetsInAssetCollectionOptions(memory, $())),
+            keyAssets: ids($.PHAsset.fetchKeyAssetsInAssetCollectionOptions(memory, $())),
+        }});
+    }}
+}} catch (e) {{
+    output.memories = [];
+}}
+var options = $.PHFetchOptions.alloc.init;
+var predicate = {predicate};
+if (predicate !== null) options.predicate = $.NSPredicate.predicateWithFormat(predicate);
+options.includeHiddenAssets = {hidden};
+options.sortDescriptors = [$.NSSortDescriptor.sortDescriptorWithKeyAscending('creationDate', true)];
+var assets = $.PHAsset.fetchAssetsWithOptions(options);
+for (var i = 0; !nil(assets) && i < assets.count; i++) {{
+    var a = assets.objectAtIndex(i);
+    output.assets.push(record(a, albums[a.localIdentifier.js], people[a.localIdentifier.js]));
+}}
+JSON.stringify(output);
+"#
+    )
+}
+
+/// Parses the output of [`script`].
+pub fn parse(output: &str) -> Result<Library> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Library::default()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading photos",
+            message: e.to_string(),
+        })?,
+    };
+    let albums = output
+        .get("albums")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .map(parse_album)
+        .collect::<Result<_>>()?;
+    let people = output
+        .get("people")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .map(parse_person)
+        .collect::<Result<_>>()?;
+    let memories = output
+        .get("memories")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .map(parse_memory)
+        .collect::<Result<_>>()?;
+    let assets = output
+        .get("assets")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .map(parse_asset)
+        .collect::<Result<_>>()?;
+    Ok(Library {
+        albums,
+        people,
+        memories,
+        assets,
+    })
+}
+
+fn parse_memory(record: &Value) -> Result<Memory> {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).map(String::from);
+    let Some(id) = text("id") else {
+        return Err(Error::Parse {
+            context: "reading photo memory id",
+            message: "missing id field".to_string(),
+        });
+    };
+    let ids = |key: &str| -> Vec<String> {
+        record
+            .get(key)
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect()
+    };
+    Ok(Memory {
+        id,
+        name: text("name"),
+        start: text("start"),
+        end: text("end"),
+        assets: ids("assets"),
+        key_assets: ids("keyAssets"),
+    })
+}
+
+fn parse_person(record: &Value) -> Result<Person> {
+    let text = |key: &str| record.get(key).and_then(Value::as_str);
+    let (Some(id), Some(name)) = (text("id"), text("name")) else {
+        return Err(Error::Parse {
+            context: "reading photo person",
+            message: "missing id or name field".to_string(),
+        });
+    };
+    Ok(Person {
+        id: id.to_string(),
+        name: name.to_string(),
+    })
+}
+
+fn parse_album(record: &Value) -> Result<Album> {
+    let Some(id) = record.get("id").and_then(Value::as_str) else {
+        return Err(Error::Parse {
+            context: "reading photo album id",
+            message: "missing id field".to_string(),
+        });
+    };
+    let smart = record.get("smart").and_then(Value::as_bool) == Some(true);
+    Ok(Album {
+        id: id.to_string(),
+        name: record
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        smart,
+        criteria: record
+            .get("subtype")
+            .and_then(Value::as_i64)
+            .filter(|_| smart)
+            .and_then(smart_album_criteria),
+        size: record
+            .get("size")
+            .and_then(Value::as_u64)
+            .unwrap_or_default() as usize,
+    })
+}
+
+fn parse_asset(record: &Value) -> Result<Asset> {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).map(String::from);
+    let Some(id) = text("id") else {
+        return Err(Error::Parse {
+            context: "reading photo id",
+            message: "missing id field".to_string(),
+        });
+    };
+    let media_type = MediaType::from_photokit(
+        record
+            .get("mediaType")
+            .and_then(Value::as_i64)
+            .unwrap_or_default(),
+    );
+    let number = |key: &str| record.get(key).and_then(Value::as_u64).unwrap_or_default();
+    Ok(Asset {
+        id,
+        filename: text("filename"),
+        media_type,
+        created: text("created"),
+        modified: text("modified"),
+        width: number("width"),
+        height: number("height"),
+        // Photos have a duration of 0:
+        duration: record
+            .get("duration")
+            .and_then(Value::as_f64)
+            .filter(|duration| *duration > 0.0),
+        albums: record
+            .get("albums")
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect(),
+        people: record
+            .get("people")
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect(),
+        keywords: record
+            .get("keywords")
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect(),
+        caption: text("caption"),
+        camera: record
+            .get("camera")
+            .filter(|camera| camera.is_object())
+            .map(parse_camera),
+        location: record.get("location").and_then(parse_location),
+        paired_video: record
+            .get("pairedVideo")
+            .and_then(Value::as_str)
+            .map(|filename| (!filename.is_empty()).then(|| filename.to_string())),
+        burst: record
+            .get("burst")
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(|frame| {
+                let text = |key: &str| frame.get(key).and_then(Value::as_str).map(String::from);
+                Some(BurstFrame {
+                    id: text("id")?,
+                    filename: text("filename"),
+                    created: text("created"),
+                })
+            })
+            .collect(),
+    })
+}
+
+fn parse_camera(record: &Value) -> Camera {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).map(String::from);
+    let number = |key: &str| record.get(key).and_then(Value::as_f64);
+    Camera {
+        make: text("make").map(|make| make.trim().to_string()),
+        model: text("model").map(|model| model.trim().to_string()),
+        lens: text("lens"),
+        exposure_time: number("exposureTime"),
+        f_number: number("fNumber"),
+        iso: record.get("iso").and_then(Value::as_u64),
+        focal_length: number("focalLength"),
+    }
+}
+
+fn parse_location(record: &Value) -> Option<Location> {
+    Some(Location {
+        latitude: record.get("latitude")?.as_f64()?,
+        longitude: record.get("longitude")?.as_f64()?,
+        altitude: record.get("altitude").and_then(Value::as_f64),
+    })
+}
+
+/// The JavaScript that defines `write(input)`, which changes the keywords
+/// or caption of the asset `input.id` through Photos, as PhotoKit can't,
+/// and describes it as [`script`] does, with them; the asset not found
+/// leaving `assets` empty. `input.keywords` are added to the asset's, or
+/// with `input.replace` replace them, and `input.caption` replaces its
+/// caption, when not `undefined`.
+const WRITE_SCRIPT: &str = r#"
+var withLocation = true;
+function write(input) {
+    var output = { albums: [], people: [], memories: [], assets: [] };
+    var options = $.PHFetchOptions.alloc.init;
+    options.includeHiddenAssets = true;
+    var fetched = $.PHAsset.fetchAssetsWithLocalIdentifiersOptions([input.id], options);
+    if (nil(fetched) || fetched.count === 0) return output;
+    var item = null;
+    try {
+        item = Application('Photos').mediaItems.byId(input.id);
+        item.id();
+    } catch (e) {
+        return output;
+    }
+    if (input.keywords !== undefined) {
+        var keywords = input.replace ? [] : item.keywords() || [];
+        input.keywords.forEach(function (k) {
+            if (keywords.indexOf(k) < 0) keywords.push(k);
+        });
+        item.keywords = keywords;
+    }
+    if (input.caption !== undefined) item.description = input.caption === null ? '' : input.caption;
+    var a = fetched.objectAtIndex(0);
+    var albums = [];
+    // PHAssetCollectionTypeAlbum, then PHAssetCollectionTypeSmartAlbum, but
+    // not PHAssetCollectionSubtypeSmartAlbumUserLibrary:
+    [1, 2].forEach(function (type) {
+        var collections = $.PHAssetCollection.fetchAssetCollectionsContainingAssetWithTypeOptions(a, type, $());
+        for (var i = 0; !nil(collections) && i < collections.count; i++) {
+            var album = collections.objectAtIndex(i);
+            if (type === 2 && album.assetCollectionSubtype === 209) continue;
+            albums.push(album.localIdentifier.js);
+        }
+    });
+    var people = [];
+    try {
+        var persons = $.PHPerson.fetchPersonsInAssetOptions(a, $());
+        for (var i = 0; !nil(persons) && i < persons.count; i++) {
+            var person = persons.objectAtIndex(i);
+            if (string(person.name) !== null) people.push(person.localIdentifier.js);
+        }
+    } catch (e) {
+        people = [];
+    }
+    var r = record(a, albums, people);
+    r.keywords = item.keywords() || [];
+    r.caption = item.description() || null;
+    output.assets.push(r);
+    return output;
+}
+"#;
+
+/// Returns the script that adds `keywords` to the asset `id`'s, or with
+/// `replace` replaces them, describing it as [`script`] does, the asset
+/// not found leaving `assets` empty.
+pub fn tag_script(id: &str, keywords: &[String], replace: bool) -> String {
+    write_script(json!({ "id": id, "keywords": keywords, "replace": replace }))
+}
+
+/// Returns the script that sets the caption of the asset `id`, or with
+/// `None` clears it, describing it as [`script`] does, the asset not found
+/// leaving `assets` empty.
+pub fn caption_script(id: &str, caption: Option<&str>) -> String {
+    write_script(json!({ "id": id, "caption": caption }))
+}
+
+fn write_script(input: Value) -> String {
+    format!(
+        r#"
+{PRELUDE}
+{RECORD_SCRIPT}
+{WRITE_SCRIPT}
+JSON.stringify(write({input}));
+"#
+    )
+}
+
+/// Extracts the asset id from an asset URN, also accepting a bare PhotoKit
+/// local identifier.
+pub fn id_from_urn(input: &str) -> Option<&str> {
+    let input = input.trim();
+    let id = input.strip_prefix(URN_PREFIX).unwrap_or(input);
+    (!id.is_empty() && !id.starts_with("urn:")).then_some(id)
+}
+
+/// Which version of a photo or video [`export_script`] writes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Quality {
+    /// The file as it was imported, before any edits.
+    #[default]
+    Original,
+    /// The full-size file as Photos shows it, with any edits.
+    Full,
+    /// A small JPEG preview, for photos and videos alike.
+    Thumbnail,
+}
+
+impl FromStr for Quality {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        match input {
+            "original" => Ok(Quality::Original),
+            "full" => Ok(Quality::Full),
+            "thumbnail" => Ok(Quality::Thumbnail),
+            _ => Err(format!(
+                "unsupported quality: {input} (expected original, full, or thumbnail)"
+            )),
+        }
+    }
+}
+
+/// What [`export_script`] wrote.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Exported {
+    /// The path of each asset's file, by the asset's id, whether written
+    /// now or by an earlier export.
+    pub files: BTreeMap<String, String>,
+    /// The path of each Live Photo's video, by the asset's id.
+    pub paired_videos: BTreeMap<String, String>,
+    /// The ids of the assets kept only in iCloud, which weren't
+    /// downloaded.
+    pub missing: Vec<String>,
+    /// The ids of the assets that couldn't be written, with why.
+    pub failed: Vec<(String, String)>,
+}
+
+/// Returns the name, without extension, that [`export_script`] gives the
+/// file of the asset with the given id.
+pub fn file_stem(id: &str) -> String {
+    id.chars()
+        .map(|c| match c {
+            '/' | ':' | '\\' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Returns the script that writes the files of the given assets, at the
+/// given quality, with the videos of Live Photos but for thumbnails, into
+/// the existing directory `dir`, as JSON in the format
+/// [`parse_exported`] reads. Files already there are kept, so an export
+/// that was interrupted picks up where it stopped; each file is written
+/// beside its final name first, and moved there once complete. Files kept
+/// only in iCloud are downloaded only if `download` is true.
+pub fn export_script(dir: &str, quality: Quality, download: bool, assets: &[&Asset]) -> String {
+    let names: serde_json::Map<String, Value> = assets
+        .iter()
+        .map(|asset| (asset.id.clone(), Value::from(file_stem(&asset.id))))
+        .collect();
+    let input = json!({
+        "dir": dir,
+        "quality": match quality {
+            Quality::Original => "original",
+            Quality::Full => "full",
+            Quality::Thumbnail => "thumbnail",
+        },
+        "download": download,
+        "names": names,
+    });
+    format!(
+        r#"
+{PRELUDE}
+ObjC.import('AppKit');
+var input = {input};
+var files = $.NSFileManager.defaultManager;
+var output = {{ files: {{}}, pairedVideos: {{}}, missing: [], failed: [] }};
+var resourceOptions = $.PHAssetResourceRequestOptions.alloc.init;
+resourceOptions.networkAccessAllowed = input.download;
+var imageOptions = $.PHImageRequestOptions.alloc.init;
+imageOptions.synchronous = true;
+imageOptions.networkAccessAllowed = input.download;
+// PHImageRequestOptionsDeliveryModeHighQualityFormat:
+imageOptions.deliveryMode = 1;
+// The resource the quality asks for: PHAssetResourceTypeFullSizePhoto or
+// FullSizeVideo for edited assets, else the Photo, Video, or Audio one;
+// or for a Live Photo's video, FullSizePairedVideo, else PairedVideo:
+function resource(a, paired) {{
+    var resources = $.PHAssetResource.assetResourcesForAsset(a);
+    var original = null, full = null;
+    for (var i = 0; !nil(resources) && i < resources.count; i++) {{
+        var r = resources.objectAtIndex(i);
+        if (original === null && (paired ? r.type === 9 : r.type >= 1 && r.type <= 3)) original = r;
+        if (full === null && (paired ? r.type === 10 : r.type === 5 || r.type === 6)) full = r;
+    }}
+    return input.quality === 'full' && full !== null ? full : original;
+}}
+// Writes resource `r` to `base` and its extension, returning the path, or
+// null, with what went wrong in the output:
+function write(id, r, base) {{
+    var filename = string(r.originalFilename) || '';
+    var dot = filename.lastIndexOf('.');
+    var extension = dot < 0 ? '' : filename.slice(dot).toLowerCase();
+    var path = base + (input.quality === 'full' ? '.full' : '') + extension;
+    if (files.fileExistsAtPath(path)) return path;
+    var part = path + '.part';
+    files.removeItemAtPathError(part, $());
+    var done = false, error = null;
+    $.PHAssetResourceManager.defaultManager.writeDataForAssetResourceToFileOptionsCompletionHandler(
+        r, $.NSURL.fileURLWithPath(part), resourceOptions, function (e) {{ error = e; done = true; }});
+    wait(function () {{ return done; }});
+    if (!nil(error)) {{
+        files.removeItemAtPathError(part, $());
+        // PHPhotosErrorNetworkAccessRequired:
+        if (error.code === 3164) output.missing.push(id);
+        else output.failed.push([id, error.localizedDescription.js]);
+        return null;
+    }}
+    files.moveItemAtPathToPathError(part, path, $());
+    return path;
+}}
+function thumbnail(a, path) {{
+    var image = null;
+    images.requestImageForAssetTargetSizeContentModeOptionsResultHandler(
+        a, $.NSMakeSize(512, 512), 0, imageOptions, function (i) {{ image = i; }});
+    if (image === null || nil(image)) return false;
+    var bitmap = $.NSBitmapImageRep.imageRepWithData(image.TIFFRepresentation);
+    // NSBitmapImageFileTypeJPEG:
+    var data = bitmap.representationUsingTypeProperties(3, $());
+    return data.writeToFileAtomically(path, true);
+}}
+var images = $.PHImageManager.defaultManager;
+var ids = Object.keys(input.names);
+var options = $.PHFetchOptions.alloc.init;
+options.includeHiddenAssets = true;
+var assets = $.PHAsset.fetchAssetsWithLocalIdentifiersOptions(ids, options);
+for (var i = 0; !nil(assets) && i < assets.count; i++) {{
+    var a = assets.objectAtIndex(i);
+    var id = a.localIdentifier.js;
+    var base = input.dir + '/' + input.names[id];
+    if (input.quality === 'thumbnail') {{
+        var path = base + '.thumbnail.jpg';
+        if (files.fileExistsAtPath(path) || thumbnail(a, path)) output.files[id] = path;
+        else output.missing.push(id);
+        continue;
+    }}
+    var r = resource(a, false);
+    if (r === null) {{
+        output.failed.push([id, 'no file']);
+        continue;
+    }}
+    var path = write(id, r, base);
+    if (path === null) continue;
+    output.files[id] = path;
+    var video = resource(a, true);
+    var videoPath = video === null ? null : write(id, video, base);
+    if (videoPath !== null) output.pairedVideos[id] = videoPath;
+}}
+JSON.stringify(output);
+"#
+    )
+}
+
+/// Parses the output of [`export_script`].
+pub fn parse_exported(output: &str) -> Result<Exported> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Exported::default()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading exported photos",
+            message: e.to_string(),
+        })?,
+    };
+    let paths = |key: &str| {
+        output
+            .get(key)
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .filter_map(|(id, path)| Some((id.clone(), path.as_str()?.to_string())))
+            .collect()
+    };
+    let files = paths("files");
+    let paired_videos = paths("pairedVideos");
+    let mut missing: Vec<String> = output
+        .get("missing")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .filter_map(Value::as_str)
+        .map(String::from)
+        .collect();
+    // A Live Photo's photo and video are both missing:
+    missing.dedup();
+    let failed = output
+        .get("failed")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .filter_map(|failure| {
+            let id = failure.get(0)?.as_str()?;
+            let message = failure.get(1)?.as_str().unwrap_or_default();
+            Some((id.to_string(), message.to_string()))
+        })
+        .collect();
+    Ok(Exported {
+        files,
+        paired_videos,
+        missing,
+        failed,
+    })
+}
@@ -0,0 +1,365 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads a Photos library straight from its Core Data database,
+//! `Photos.sqlite`, through the `sqlite3` shell: much faster than
+//! enumerating a large library through PhotoKit, if without the smart
+//! albums and memories PhotoKit works out.
+//!
+//! The layout of the database changes from one version of macOS to the
+//! next, so everything that depends on it lives in [`Layout`], which is
+//! worked out from the tables and columns the database has.
+
+use super::{Filter, Library, Media};
+use crate::{Error, Result, exec};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    ffi::OsStr,
+    format,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// How photos read from the database are extracted, as recorded in their
+/// provenance.
+pub const BACKEND: &str = "photos-sqlite";
+
+/// What PhotoKit appends to the UUIDs the database keys assets, albums,
+/// and people by to make their local identifiers.
+const ASSET_SUFFIX: &str = "/L0/001";
+const ALBUM_SUFFIX: &str = "/L0/040";
+const PERSON_SUFFIX: &str = "/L0/050";
+
+/// The seconds from the Unix epoch to the Core Data one, 2001-01-01,
+/// which the database counts dates from.
+const CORE_DATA_EPOCH: i64 = 978_307_200;
+
+/// The query listing every table of the database, with its columns.
+const TABLES_QUERY: &str = "\
+SELECT m.name AS \"table\", p.name AS \"column\" \
+FROM sqlite_master m JOIN pragma_table_info(m.name) p WHERE m.type = 'table';
+";
+
+/// Returns where Photos keeps the system library:
+/// `~/Pictures/Photos Library.photoslibrary`.
+pub fn default_path() -> Result<PathBuf> {
+    let home = env::var_os("HOME").ok_or_else(|| Error::Usage {
+        message: "HOME is not set".to_string(),
+    })?;
+    Ok(PathBuf::from(home).join("Pictures/Photos Library.photoslibrary"))
+}
+
+/// Returns the database of the library at `path`, which is either a
+/// `.photoslibrary` package or the database itself.
+pub fn database(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.join("database/Photos.sqlite")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Reads every album, every named person, and every asset `filter`
+/// selects from the library at `path`, as [`super::script`] does through
+/// PhotoKit, locations only if `location` is true. Reading it takes Full
+/// Disk Access, lacking which this fails.
+pub fn read(path: &Path, filter: &Filter, location: bool) -> Result<Library> {
+    let database = database(path);
+    if !database.is_file() {
+        return Err(Error::NotFound {
+            what: format!("Photos library database {}", database.display()),
+        });
+    }
+    let layout = Layout::detect(&tables(&database)?)?;
+    let output = run(&database, &[], &layout.query(filter, location))?;
+    super::parse(&String::from_utf8_lossy(&output))
+}
+
+/// Returns the columns of every table of `database`, by table.
+fn tables(database: &Path) -> Result<BTreeMap<String, BTreeSet<String>>> {
+    let output = run(database, &["-json"], TABLES_QUERY)?;
+    // `sqlite3 -json` prints nothing at all when there are no rows:
+    if output.iter().all(u8::is_ascii_whitespace) {
+        return Ok(BTreeMap::new());
+    }
+    let rows: Vec<Value> = serde_json::from_slice(&output).map_err(|e| Error::Parse {
+        context: "reading the Photos library database",
+        message: e.to_string(),
+    })?;
+    let mut tables: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for row in rows {
+        let text = |key: &str| row.get(key).and_then(Value::as_str).map(String::from);
+        if let (Some(table), Some(column)) = (text("table"), text("column")) {
+            tables.entry(table).or_default().insert(column);
+        }
+    }
+    Ok(tables)
+}
+
+fn run(database: &Path, flags: &[&str], query: &str) -> Result<Vec<u8>> {
+    let mut args = Vec::from([OsStr::new("-readonly"), OsStr::new("-bail")]);
+    args.extend(flags.iter().map(OsStr::new));
+    args.push(database.as_os_str());
+    exec::run("sqlite3", args, query.as_bytes())
+}
+
+/// Where a version of the database keeps what the emitter reads. Core
+/// Data numbers the entities of a model, and names the tables joining
+/// them after those numbers, as in `Z_28ASSETS`, so these are found by
+/// their columns rather than by name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Layout {
+    /// The table of assets: `ZASSET`, or `ZGENERICASSET` before macOS 11.
+    assets: String,
+    /// Whether assets have a `ZVISIBILITYSTATE`, which is 0 for all but
+    /// the photos of a burst the burst isn't shown as.
+    visibility: bool,
+    /// The table of each asset's camera data, since macOS 11.
+    exif: bool,
+    /// The table of captions.
+    captions: bool,
+    /// Which albums each asset is in.
+    album_assets: Option<Join>,
+    /// The columns of `ZDETECTEDFACE` naming a face's asset and person:
+    /// `ZASSETFORFACE` and `ZPERSONFORFACE` since macOS 14, `ZASSET` and
+    /// `ZPERSON` before.
+    faces: Option<(String, String)>,
+    /// Which keywords each asset's attributes have.
+    keywords: Option<Join>,
+}
+
+/// A table joining two entities, with its column for each.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Join {
+    table: String,
+    left: String,
+    right: String,
+}
+
+impl Layout {
+    /// Works out the layout of a database from the columns of each of its
+    /// tables, failing for a database it doesn't know.
+    fn detect(tables: &BTreeMap<String, BTreeSet<String>>) -> Result<Self> {
+        let has = |table: &str, column: &str| {
+            tables
+                .get(table)
+                .is_some_and(|columns| columns.contains(column))
+        };
+        let Some(assets) = ["ZASSET", "ZGENERICASSET"]
+            .into_iter()
+            .find(|table| has(table, "ZUUID") && has(table, "ZDATECREATED"))
+        else {
+            return Err(Error::Parse {
+                context: "reading the Photos library database",
+                message: "unsupported database layout: no table of assets".to_string(),
+            });
+        };
+        if !has("ZADDITIONALASSETATTRIBUTES", "ZORIGINALFILENAME") {
+            return Err(Error::Parse {
+                context: "reading the Photos library database",
+                message: "unsupported database layout: no asset attributes".to_string(),
+            });
+        }
+        let column = |table: &str, candidates: [&str; 2]| {
+            candidates
+                .into_iter()
+                .find(|column| has(table, column))
+                .map(String::from)
+        };
+        let faces = match (
+            column("ZDETECTEDFACE", ["ZASSETFORFACE", "ZASSET"]),
+            column("ZDETECTEDFACE", ["ZPERSONFORFACE", "ZPERSON"]),
+        ) {
+            (Some(asset), Some(person)) if has("ZPERSON", "ZPERSONUUID") => Some((asset, person)),
+            _ => None,
+        };
+        Ok(Layout {
+            assets: assets.to_string(),
+            visibility: has(assets, "ZVISIBILITYSTATE"),
+            exif: has("ZEXTENDEDATTRIBUTES", "ZASSET"),
+            captions: has("ZASSETDESCRIPTION", "ZASSETATTRIBUTES"),
+            album_assets: Join::find(tables, "ALBUMS", "ASSETS")
+                .filter(|_| has("ZGENERICALBUM", "ZUUID")),
+            faces,
+            keywords: Join::find(tables, "ASSETATTRIBUTES", "KEYWORDS")
+                .filter(|_| has("ZKEYWORD", "ZTITLE")),
+        })
+    }
+
+    /// Returns the query that reads the library as one JSON document, in
+    /// the format [`super::parse`] reads.
+    fn query(&self, filter: &Filter, location: bool) -> String {
+        let assets = &self.assets;
+        let date = |column: &str| {
+            format!(
+                "strftime('%Y-%m-%dT%H:%M:%S', {column} + {CORE_DATA_EPOCH}, 'unixepoch', 'localtime')"
+            )
+        };
+
+        // User albums (kind 2) and shared ones (1505), for which folders,
+        // projects, and import sessions don't count:
+        let album_kinds = "g.ZKIND IN (2, 1505) AND coalesce(g.ZTRASHEDSTATE, 0) = 0";
+        let (albums, asset_albums) = match &self.album_assets {
+            Some(Join { table, left, right }) => (
+                format!(
+                    "SELECT json_group_array(json_object('id', g.ZUUID || '{ALBUM_SUFFIX}', \
+                     'name', g.ZTITLE, 'smart', json('false'), \
+                     'size', (SELECT count(*) FROM {table} j WHERE j.{left} = g.Z_PK))) \
+                     FROM (SELECT * FROM ZGENERICALBUM g WHERE {album_kinds} ORDER BY g.ZTITLE) g"
+                ),
+                format!(
+                    "(SELECT json_group_array(g.ZUUID || '{ALBUM_SUFFIX}') FROM {table} j \
+                     JOIN ZGENERICALBUM g ON g.Z_PK = j.{left} \
+                     WHERE j.{right} = a.Z_PK AND {album_kinds})"
+                ),
+            ),
+            None => ("SELECT '[]'".to_string(), "'[]'".to_string()),
+        };
+
+        let named = "coalesce(nullif(p.ZFULLNAME, ''), nullif(p.ZDISPLAYNAME, ''))";
+        let (people, asset_people) = match &self.faces {
+            Some((asset, person)) => (
+                format!(
+                    "SELECT json_group_array(json_object('id', p.ZPERSONUUID || '{PERSON_SUFFIX}', \
+                     'name', {named})) FROM ZPERSON p WHERE {named} IS NOT NULL"
+                ),
+                format!(
+                    "(SELECT json_group_array(DISTINCT p.ZPERSONUUID || '{PERSON_SUFFIX}') \
+                     FROM ZDETECTEDFACE f JOIN ZPERSON p ON p.Z_PK = f.{person} \
+                     WHERE f.{asset} = a.Z_PK AND {named} IS NOT NULL)"
+                ),
+            ),
+            None => ("SELECT '[]'".to_string(), "'[]'".to_string()),
+        };
+
+        let keywords = match &self.keywords {
+            Some(Join { table, left, right }) => format!(
+                "(SELECT json_group_array(k.ZTITLE) FROM {table} j \
+                 JOIN ZKEYWORD k ON k.Z_PK = j.{right} WHERE j.{left} = attr.Z_PK)"
+            ),
+            None => "'[]'".to_string(),
+        };
+        let (caption, caption_join) = if self.captions {
+            (
+                "nullif(d.ZLONGDESCRIPTION, '')",
+                "LEFT JOIN ZASSETDESCRIPTION d ON d.ZASSETATTRIBUTES = attr.Z_PK",
+            )
+        } else {
+            ("NULL", "")
+        };
+        // Photos only (ZKIND 0):
+        let (camera, camera_join) = if self.exif {
+            (
+                "CASE WHEN a.ZKIND = 0 THEN json_object('make', x.ZCAMERAMAKE, \
+                 'model', x.ZCAMERAMODEL, 'lens', x.ZLENSMODEL, \
+                 'exposureTime', x.ZSHUTTERSPEED, 'fNumber', x.ZAPERTURE, \
+                 'iso', x.ZISO, 'focalLength', x.ZFOCALLENGTH) END",
+                "LEFT JOIN ZEXTENDEDATTRIBUTES x ON x.ZASSET = a.Z_PK",
+            )
+        } else {
+            ("NULL", "")
+        };
+        // Assets without a location have -180 for both coordinates:
+        let place = if location {
+            "CASE WHEN a.ZLATITUDE IS NOT NULL AND a.ZLATITUDE != -180 \
+             AND a.ZLONGITUDE != -180 THEN json_object('latitude', a.ZLATITUDE, \
+             'longitude', a.ZLONGITUDE) END"
+        } else {
+            "NULL"
+        };
+        // The other photos of a burst (its avalanche, to Core Data):
+        let burst = format!(
+            "CASE WHEN a.ZAVALANCHEUUID IS NULL THEN '[]' ELSE \
+             (SELECT json_group_array(json_object('id', b.ZUUID || '{ASSET_SUFFIX}', \
+             'filename', battr.ZORIGINALFILENAME, 'created', {})) FROM {assets} b \
+             LEFT JOIN ZADDITIONALASSETATTRIBUTES battr ON battr.ZASSET = b.Z_PK \
+             WHERE b.ZAVALANCHEUUID = a.ZAVALANCHEUUID AND b.Z_PK != a.Z_PK \
+             AND b.ZTRASHEDSTATE = 0) END",
+            date("b.ZDATECREATED"),
+        );
+
+        let mut clauses = Vec::from(["a.ZTRASHEDSTATE = 0".to_string()]);
+        clauses.push(format!("a.ZHIDDEN = {}", u8::from(filter.hidden)));
+        if self.visibility {
+            clauses.push("a.ZVISIBILITYSTATE = 0".to_string());
+        }
+        if filter.favorites {
+            clauses.push("a.ZFAVORITE = 1".to_string());
+        }
+        // ZKINDSUBTYPE is 10 for screenshots, and 2 for Live Photos:
+        if filter.screenshots {
+            clauses.push("a.ZKINDSUBTYPE = 10".to_string());
+        }
+        if filter.bursts {
+            clauses.push("a.ZAVALANCHEUUID IS NOT NULL".to_string());
+        }
+        match filter.media {
+            Some(Media::Photo) => clauses.push("a.ZKIND = 0".to_string()),
+            Some(Media::Video) => clauses.push("a.ZKIND = 1".to_string()),
+            Some(Media::Live) => clauses.push("a.ZKINDSUBTYPE = 2".to_string()),
+            None => {}
+        }
+        let clauses = clauses.join(" AND ");
+
+        // The asset's media type as PhotoKit's PHAssetMediaType:
+        let record = format!(
+            "json_object('id', a.ZUUID || '{ASSET_SUFFIX}', \
+             'filename', attr.ZORIGINALFILENAME, \
+             'mediaType', CASE a.ZKIND WHEN 0 THEN 1 WHEN 1 THEN 2 ELSE 0 END, \
+             'created', {created}, 'modified', {modified}, \
+             'width', a.ZWIDTH, 'height', a.ZHEIGHT, 'duration', a.ZDURATION, \
+             'albums', json({asset_albums}), 'people', json({asset_people}), \
+             'keywords', json({keywords}), 'caption', {caption}, \
+             'camera', json({camera}), 'location', json({place}), \
+             'pairedVideo', CASE WHEN a.ZKINDSUBTYPE = 2 THEN '' END, \
+             'burst', json({burst}))",
+            created = date("a.ZDATECREATED"),
+            modified = date("a.ZMODIFICATIONDATE"),
+        );
+        format!(
+            "SELECT json_object(\
+             'albums', json(({albums})), \
+             'people', json(({people})), \
+             'memories', json('[]'), \
+             'assets', json((SELECT coalesce(json_group_array(json(r)), '[]') FROM \
+             (SELECT {record} AS r FROM {assets} a \
+             LEFT JOIN ZADDITIONALASSETATTRIBUTES attr ON attr.ZASSET = a.Z_PK \
+             {caption_join} {camera_join} \
+             WHERE {clauses} ORDER BY a.ZDATECREATED))));\n"
+        )
+    }
+}
+
+impl Join {
+    /// Finds the table joining two entities, with a column named after
+    /// each, as in `Z_28ALBUMS` and `Z_3ASSETS`, for `left` of `ALBUMS`
+    /// and `right` of `ASSETS`.
+    fn find(tables: &BTreeMap<String, BTreeSet<String>>, left: &str, right: &str) -> Option<Self> {
+        let numbered = |column: &str, entity: &str| {
+            column
+                .strip_prefix("Z_")
+                .and_then(|column| column.strip_suffix(entity))
+                .is_some_and(|number| {
+                    !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit())
+                })
+        };
+        tables.iter().find_map(|(table, columns)| {
+            if !table.starts_with("Z_")
+                || !table
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+            {
+                return None;
+            }
+            let left = columns.iter().find(|column| numbered(column, left))?;
+            let right = columns.iter().find(|column| numbered(column, right))?;
+            Some(Join {
+                table: table.clone(),
+                left: left.clone(),
+                right: right.clone(),
+            })
+        })
+    }
+}
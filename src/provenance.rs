@@ -0,0 +1,135 @@
+// This is free and unencumbered software released into the public domain.
+
+//! PROV-O provenance for emitted records: when, where, by which version
+//! of this module, and through which backend each record was extracted.
+
+use crate::{exec, timezone};
+use core::str::FromStr;
+use serde_json::{Value, json};
+use std::{
+    format, process,
+    string::{String, ToString},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The prefix of the URNs identifying individual extraction runs.
+pub const RUN_URN_PREFIX: &str = "urn:asimov:apple:run:";
+
+/// The `@type` of the activity node of a run.
+pub const ACTIVITY_TYPE: &str = "prov:Activity";
+
+/// How provenance is attached to records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProvenanceMode {
+    /// Every record embeds the full activity that generated it.
+    Inline,
+    /// The activity is emitted once, as a record of its own, and every
+    /// record merely refers to it.
+    Activity,
+}
+
+impl FromStr for ProvenanceMode {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        match input {
+            "inline" => Ok(ProvenanceMode::Inline),
+            "activity" => Ok(ProvenanceMode::Activity),
+            _ => Err(format!("unsupported provenance mode: {input}")),
+        }
+    }
+}
+
+/// The extraction activity of one run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Provenance {
+    pub mode: ProvenanceMode,
+    pub run_id: String,
+    pub started: String,
+    pub device: String,
+    pub backend: &'static str,
+}
+
+impl Provenance {
+    /// Describes a run starting now on this device, extracting through
+    /// `backend` (e.g. `applescript`).
+    pub fn new(mode: ProvenanceMode, backend: &'static str) -> Self {
+        let started = now_iso8601();
+        Provenance {
+            mode,
+            run_id: format!(
+                "{RUN_URN_PREFIX}{}-{}",
+                started.replace(':', ""),
+                process::id()
+            ),
+            started,
+            device: device_id(),
+            backend,
+        }
+    }
+
+    /// Returns the `prov:Activity` node for this run.
+    pub fn activity(&self) -> Value {
+        json!({
+            "@type": ACTIVITY_TYPE,
+            "@id": self.run_id,
+            "prov:startedAtTime": self.started,
+            "prov:atLocation": {
+                "@id": format!(
+                    "urn:apple:device:{}",
+                    percent_encoding::utf8_percent_encode(
+                        &self.device,
+                        percent_encoding::NON_ALPHANUMERIC
+                    )
+                ),
+            },
+            "prov:wasAssociatedWith": {
+                "@type": "prov:SoftwareAgent",
+                "name": env!("CARGO_PKG_NAME"),
+                "softwareVersion": env!("CARGO_PKG_VERSION"),
+            },
+            "prov:used": {
+                "@id": format!("urn:asimov:apple:backend:{}", self.backend),
+                "name": self.backend,
+            },
+        })
+    }
+
+    /// Adds `prov:wasGeneratedBy` to `record`, which must be an object.
+    pub fn attach(&self, record: &mut Value) {
+        let activity = match self.mode {
+            ProvenanceMode::Inline => self.activity(),
+            ProvenanceMode::Activity => json!({ "@id": self.run_id }),
+        };
+        if let Some(record) = record.as_object_mut() {
+            record.insert("prov:wasGeneratedBy".to_string(), activity);
+        }
+    }
+}
+
+/// Returns the current UTC time in ISO 8601, e.g. `2025-01-20T13:30:00Z`.
+pub fn now_iso8601() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    timezone::iso8601(seconds as i64)
+}
+
+/// Returns the Mac's hardware UUID, or failing that its host name.
+fn device_id() -> String {
+    let uuid = exec::run("ioreg", ["-rd1", "-c", "IOPlatformExpertDevice"], b"")
+        .ok()
+        .and_then(|output| {
+            let output = String::from_utf8_lossy(&output).into_owned();
+            let line = output.lines().find(|l| l.contains("IOPlatformUUID"))?;
+            let (_, value) = line.split_once('=')?;
+            Some(value.trim().trim_matches('"').to_string())
+        });
+    uuid.or_else(|| {
+        exec::run("hostname", [""; 0], b"")
+            .ok()
+            .map(|output| String::from_utf8_lossy(&output).trim().to_string())
+    })
+    .filter(|id| !id.is_empty())
+    .unwrap_or_else(|| "unknown".to_string())
+}
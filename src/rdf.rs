@@ -0,0 +1,221 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Maps the JSON records this module emits onto RDF, the way a JSON-LD
+//! processor would with `{"@vocab": "http://schema.org/"}` as context,
+//! plus the `prov:` prefix for provenance.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use serde_json::Value;
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+pub const SCHEMA: &str = "http://schema.org/";
+pub const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+pub const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+pub const PROV: &str = "http://www.w3.org/ns/prov#";
+
+/// Blank node labels are scoped to a whole N-Quads document, so they must
+/// stay unique across records.
+static BLANK_NODES: AtomicUsize = AtomicUsize::new(0);
+
+/// An RDF term.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Term {
+    Iri(String),
+    BlankNode(String),
+    Literal {
+        value: String,
+        datatype: Option<String>,
+    },
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Iri(iri) => write!(f, "<{}>", escape_iri(iri)),
+            Term::BlankNode(id) => write!(f, "_:{id}"),
+            Term::Literal { value, datatype } => {
+                write!(f, "\"{}\"", escape_literal(value))?;
+                match datatype {
+                    Some(datatype) => write!(f, "^^<{datatype}>"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// A triple, optionally in a named graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quad {
+    pub subject: Term,
+    pub predicate: String,
+    pub object: Term,
+    pub graph: Option<String>,
+}
+
+impl Quad {
+    /// Formats the quad as an N-Triples line, ignoring its graph.
+    pub fn to_ntriple(&self) -> String {
+        format!("{} <{}> {} .", self.subject, self.predicate, self.object)
+    }
+}
+
+/// Formats the quad as an N-Quads line (without the trailing newline).
+impl fmt::Display for Quad {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} <{}> {}", self.subject, self.predicate, self.object)?;
+        if let Some(graph) = &self.graph {
+            write!(f, " <{}>", escape_iri(graph))?;
+        }
+        write!(f, " .")
+    }
+}
+
+/// Returns the named graph holding everything from the record's account,
+/// if it has one: `urn:apple:<app>:account:<name>`, by the app it came from,
+/// as its `source` says, so that accounts of the same name in different
+/// apps, such as `iCloud`, are kept apart.
+pub fn account_graph(record: &Value) -> Option<String> {
+    let account = record.get("account").and_then(Value::as_str)?;
+    let account =
+        percent_encoding::utf8_percent_encode(account, percent_encoding::NON_ALPHANUMERIC);
+    Some(format!(
+        "urn:apple:{}:account:{account}",
+        source_app(record)?
+    ))
+}
+
+/// Returns the app a record came from, as in `notes` for `apple-notes`,
+/// or `None` if its `source` names no app, as for provenance activities.
+pub fn source_app(record: &Value) -> Option<&str> {
+    record
+        .get("source")
+        .and_then(Value::as_str)
+        .and_then(|source| source.strip_prefix("apple-"))
+        .filter(|app| !app.is_empty() && app.bytes().all(|b| b.is_ascii_lowercase()))
+}
+
+/// Converts a record into quads in `graph`. Nested objects become linked
+/// resources (or blank nodes, lacking an `@id`), and arrays become
+/// repeated properties.
+pub fn to_quads(record: &Value, graph: Option<&str>) -> Vec<Quad> {
+    let mut quads = Vec::new();
+    node_quads(record, graph, &mut quads);
+    quads
+}
+
+fn node_quads(node: &Value, graph: Option<&str>, quads: &mut Vec<Quad>) -> Term {
+    let subject = match node.get("@id").and_then(Value::as_str) {
+        Some(id) => Term::Iri(id.to_string()),
+        None => Term::BlankNode(format!("b{}", BLANK_NODES.fetch_add(1, Ordering::Relaxed))),
+    };
+    let Some(object) = node.as_object() else {
+        return subject;
+    };
+
+    for (key, value) in object {
+        let predicate = match key.as_str() {
+            "@id" | "@context" => continue,
+            "@type" => RDF_TYPE.to_string(),
+            key => expand(key),
+        };
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => std::vec![value],
+        };
+        for value in values {
+            let object = match (key.as_str(), value) {
+                (_, Value::Null) => continue,
+                ("@type", Value::String(name)) => Term::Iri(expand(name)),
+                (_, Value::Object(_)) => node_quads(value, graph, quads),
+                (_, value) => literal(value),
+            };
+            quads.push(Quad {
+                subject: subject.clone(),
+                predicate: predicate.clone(),
+                object,
+                graph: graph.map(ToString::to_string),
+            });
+        }
+    }
+    subject
+}
+
+/// Expands a property or type name into an IRI.
+fn expand(name: &str) -> String {
+    match name.strip_prefix("prov:") {
+        Some(name) => format!("{PROV}{name}"),
+        None => format!("{SCHEMA}{name}"),
+    }
+}
+
+fn literal(value: &Value) -> Term {
+    let (value, datatype) = match value {
+        Value::Bool(b) => (b.to_string(), Some("boolean")),
+        Value::Number(n) if n.is_i64() || n.is_u64() => (n.to_string(), Some("integer")),
+        Value::Number(n) => (n.to_string(), Some("double")),
+        Value::String(s) => (s.clone(), date_datatype(s)),
+        value => (value.to_string(), None),
+    };
+    Term::Literal {
+        value,
+        datatype: datatype.map(|name| format!("{XSD}{name}")),
+    }
+}
+
+/// Recognizes ISO 8601 dates and date-times by shape.
+fn date_datatype(value: &str) -> Option<&'static str> {
+    let bytes = value.as_bytes();
+    let digits = |range: core::ops::Range<usize>| {
+        bytes
+            .get(range)
+            .is_some_and(|b| b.iter().all(u8::is_ascii_digit))
+    };
+    let is_date = digits(0..4)
+        && bytes.get(4) == Some(&b'-')
+        && digits(5..7)
+        && bytes.get(7) == Some(&b'-')
+        && digits(8..10);
+    match (is_date, bytes.len(), bytes.get(10)) {
+        (true, 10, _) => Some("date"),
+        (true, len, Some(b'T')) if len >= 19 && digits(11..13) && digits(14..16) => {
+            Some("dateTime")
+        }
+        _ => None,
+    }
+}
+
+fn escape_literal(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_iri(iri: &str) -> String {
+    let mut escaped = String::with_capacity(iri.len());
+    for c in iri.chars() {
+        match c {
+            '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\' | ' ' => {
+                escaped.push_str(&format!("%{:02X}", c as u32))
+            }
+            c if c.is_control() => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
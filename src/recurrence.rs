@@ -0,0 +1,439 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Recurrence rules, as EventKit reports them for recurring items, written
+//! out in the iCalendar `RRULE` form and expanded into occurrences.
+
+use crate::timezone::{LocalTime, civil_from_days, days_from_civil};
+use core::fmt;
+use serde_json::Value;
+use std::{format, string::String, vec, vec::Vec};
+
+/// The JavaScript that defines `recurrence(item)`, describing the first
+/// recurrence rule of EventKit item `item` in the format
+/// [`Rule::from_json`] reads, or giving `null` if it doesn't recur. It
+/// uses `nil` and `date` from the script it goes into.
+pub const SCRIPT: &str = r#"
+function recurrence(item) {
+    if (!item.hasRecurrenceRules) return null;
+    var rule = item.recurrenceRules.objectAtIndex(0);
+    var weekdays = [];
+    if (!nil(rule.daysOfTheWeek)) {
+        for (var i = 0; i < rule.daysOfTheWeek.count; i++) {
+            var day = rule.daysOfTheWeek.objectAtIndex(i);
+            weekdays.push({ day: day.dayOfTheWeek, week: day.weekNumber });
+        }
+    }
+    var end = rule.recurrenceEnd;
+    return {
+        frequency: rule.frequency,
+        interval: rule.interval,
+        firstDayOfTheWeek: rule.firstDayOfTheWeek,
+        daysOfTheWeek: weekdays,
+        daysOfTheMonth: ObjC.deepUnwrap(rule.daysOfTheMonth) || [],
+        monthsOfTheYear: ObjC.deepUnwrap(rule.monthsOfTheYear) || [],
+        setPositions: ObjC.deepUnwrap(rule.setPositions) || [],
+        until: nil(end) ? null : date(end.endDate),
+        count: nil(end) ? 0 : end.occurrenceCount,
+    };
+}
+"#;
+
+/// The most periods [`Rule::occurrences`] looks through, should a rule
+/// never match (as for the 30th of February).
+const MAX_PERIODS: i64 = 100_000;
+
+const WEEKDAYS: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Frequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        }
+    }
+}
+
+/// A day of the week a rule recurs on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weekday {
+    /// From 0 (Monday) to 6 (Sunday).
+    pub day: u8,
+    /// Which such day of the month or year: 1 for the first, -1 for the
+    /// last, and so on, or 0 for every one.
+    pub week: i8,
+}
+
+/// A recurrence rule. The `BY…` parts narrow down the days of each
+/// period, as in iCalendar.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub frequency: Frequency,
+    /// Every how many periods it recurs.
+    pub interval: u32,
+    /// From 0 (Monday) to 6 (Sunday).
+    pub week_start: u8,
+    pub weekdays: Vec<Weekday>,
+    /// Days of the month, negative ones counting from its end.
+    pub month_days: Vec<i8>,
+    /// Months, from 1 to 12.
+    pub months: Vec<u8>,
+    /// Which of each period's days to take, negative ones counting from
+    /// its end.
+    pub set_positions: Vec<i32>,
+    /// The last time it may recur at, in ISO 8601 local time.
+    pub until: Option<String>,
+    /// How many times it recurs in all, or 0 for no limit.
+    pub count: u32,
+}
+
+impl Rule {
+    /// Reads the rule that [`SCRIPT`] describes. EventKit counts days of
+    /// the week from 1 for Sunday.
+    pub fn from_json(value: &Value) -> Option<Rule> {
+        value.as_object()?;
+        let weekday = |day: i64| ((day + 5).rem_euclid(7)) as u8;
+        let numbers = |key: &str| -> Vec<i64> {
+            value
+                .get(key)
+                .and_then(Value::as_array)
+                .map(|values| values.iter().filter_map(Value::as_i64).collect())
+                .unwrap_or_default()
+        };
+        let number = |key: &str| value.get(key).and_then(Value::as_i64).unwrap_or(0);
+        Some(Rule {
+            frequency: match number("frequency") {
+                0 => Frequency::Daily,
+                1 => Frequency::Weekly,
+                2 => Frequency::Monthly,
+                3 => Frequency::Yearly,
+                _ => return None,
+            },
+            interval: number("interval").max(1) as u32,
+            week_start: match number("firstDayOfTheWeek") {
+                day @ 1..=7 => weekday(day),
+                _ => 0,
+            },
+            weekdays: value
+                .get("daysOfTheWeek")
+                .and_then(Value::as_array)
+                .map(|days| {
+                    days.iter()
+                        .map(|day| Weekday {
+                            day: weekday(day.get("day").and_then(Value::as_i64).unwrap_or(2)),
+                            week: day.get("week").and_then(Value::as_i64).unwrap_or(0) as i8,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            month_days: numbers("daysOfTheMonth")
+                .into_iter()
+                .map(|day| day as i8)
+                .collect(),
+            months: numbers("monthsOfTheYear")
+                .into_iter()
+                .map(|month| month as u8)
+                .collect(),
+            set_positions: numbers("setPositions")
+                .into_iter()
+                .map(|pos| pos as i32)
+                .collect(),
+            until: value.get("until").and_then(Value::as_str).map(String::from),
+            count: number("count").max(0) as u32,
+        })
+    }
+
+    /// Returns the times of the occurrences after the first one, at
+    /// `start` (a date, or a date and time, as reminders are due), and
+    /// before `before`. Each keeps the time of day of the first.
+    pub fn occurrences(&self, start: &str, before: &LocalTime) -> Vec<String> {
+        let mut found = Vec::new();
+        let Some(first) = day_number(start) else {
+            return found;
+        };
+        let time = start.get(10..).unwrap_or_default();
+        let last = day_number(before.as_str()).unwrap_or(first);
+        let until = self.until.as_deref().and_then(day_number);
+        let (year, month, _) = civil_from_days(first);
+        let mut seen = 1;
+        for period in 0..MAX_PERIODS {
+            let step = period * i64::from(self.interval);
+            let days = match self.frequency {
+                Frequency::Daily => vec![first + step],
+                Frequency::Weekly => {
+                    let week = first - (weekday_of(first) + 7 - i64::from(self.week_start)) % 7;
+                    (0..7).map(|n| week + 7 * step + n).collect()
+                }
+                Frequency::Monthly => {
+                    let months = year * 12 + month - 1 + step;
+                    month_range(months.div_euclid(12), months.rem_euclid(12) + 1).collect()
+                }
+                Frequency::Yearly => {
+                    let year = year + step;
+                    (days_from_civil(year, 1, 1)..days_from_civil(year + 1, 1, 1)).collect()
+                }
+            };
+            let Some(&period_start) = days.first() else {
+                break;
+            };
+            if period_start > last || until.is_some_and(|until| period_start > until) {
+                break;
+            }
+            let mut days: Vec<i64> = days
+                .into_iter()
+                .filter(|&candidate| self.matches(candidate, first))
+                .collect();
+            if !self.set_positions.is_empty() {
+                let all = days;
+                days = self
+                    .set_positions
+                    .iter()
+                    .filter_map(|&pos| match pos {
+                        1.. => all.get(pos as usize - 1),
+                        _ => all
+                            .len()
+                            .checked_sub(pos.unsigned_abs() as usize)
+                            .and_then(|n| all.get(n)),
+                    })
+                    .copied()
+                    .collect();
+                days.sort();
+                days.dedup();
+            }
+            for candidate in days.into_iter().filter(|&candidate| candidate > first) {
+                if self.count > 0 && seen >= self.count {
+                    return found;
+                }
+                if until.is_some_and(|until| candidate > until) {
+                    return found;
+                }
+                let (y, m, d) = civil_from_days(candidate);
+                let occurrence = format!("{y:04}-{m:02}-{d:02}{time}");
+                let compared = match time {
+                    "" => format!("{occurrence}T00:00:00"),
+                    _ => occurrence.clone(),
+                };
+                if compared.as_str() >= before.as_str() {
+                    return found;
+                }
+                seen += 1;
+                found.push(occurrence);
+            }
+        }
+        found
+    }
+
+    /// Whether `candidate`, a day in a period of the rule, is one it
+    /// recurs on, given the day of its first occurrence.
+    fn matches(&self, candidate: i64, first: i64) -> bool {
+        let (year, month, day) = civil_from_days(candidate);
+        let (_, first_month, first_day) = civil_from_days(first);
+        let months_given = !self.months.is_empty();
+        if months_given && !self.months.contains(&(month as u8)) {
+            return false;
+        }
+        let month_start = days_from_civil(year, month, 1);
+        let month_length = month_range(year, month).count() as i64;
+        if !self.month_days.is_empty()
+            && !self
+                .month_days
+                .iter()
+                .any(|&n| i64::from(n) == day || i64::from(n) == day - month_length - 1)
+        {
+            return false;
+        }
+        if !self.weekdays.is_empty() {
+            // The nth weekday counts within the month, or (yearly without
+            // months given) within the year:
+            let (range_start, range_end) = match (self.frequency, months_given) {
+                (Frequency::Yearly, false) => (
+                    days_from_civil(year, 1, 1),
+                    days_from_civil(year + 1, 1, 1) - 1,
+                ),
+                _ => (month_start, month_start + month_length - 1),
+            };
+            let from_start = (candidate - range_start) / 7 + 1;
+            let from_end = -((range_end - candidate) / 7 + 1);
+            let weekday = weekday_of(candidate) as u8;
+            return self.weekdays.iter().any(|w| {
+                w.day == weekday
+                    && (w.week == 0
+                        || self.frequency == Frequency::Daily
+                        || self.frequency == Frequency::Weekly
+                        || i64::from(w.week) == from_start
+                        || i64::from(w.week) == from_end)
+            });
+        }
+        if !self.month_days.is_empty() {
+            return true;
+        }
+        match self.frequency {
+            Frequency::Daily => true,
+            Frequency::Weekly => weekday_of(candidate) == weekday_of(first),
+            Frequency::Monthly => day == first_day,
+            Frequency::Yearly => day == first_day && (months_given || month == first_month),
+        }
+    }
+}
+
+/// Writes the rule in the iCalendar `RRULE` form, as in
+/// `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE;UNTIL=20250401T000000`.
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |values: Vec<String>| values.join(",");
+        write!(f, "FREQ={}", self.frequency.as_str())?;
+        if self.interval > 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.weekdays.is_empty() {
+            let days = self.weekdays.iter().map(|w| {
+                let name = WEEKDAYS[usize::from(w.day % 7)];
+                match w.week {
+                    0 => String::from(name),
+                    week => format!("{week}{name}"),
+                }
+            });
+            write!(f, ";BYDAY={}", list(days.collect()))?;
+        }
+        if !self.month_days.is_empty() {
+            let days = self.month_days.iter().map(|day| format!("{day}"));
+            write!(f, ";BYMONTHDAY={}", list(days.collect()))?;
+        }
+        if !self.months.is_empty() {
+            let months = self.months.iter().map(|month| format!("{month}"));
+            write!(f, ";BYMONTH={}", list(months.collect()))?;
+        }
+        if !self.set_positions.is_empty() {
+            let positions = self.set_positions.iter().map(|pos| format!("{pos}"));
+            write!(f, ";BYSETPOS={}", list(positions.collect()))?;
+        }
+        if self.week_start != 0 {
+            write!(f, ";WKST={}", WEEKDAYS[usize::from(self.week_start % 7)])?;
+        }
+        if let Some(until) = &self.until {
+            let until: String = until.chars().filter(|c| !matches!(c, '-' | ':')).collect();
+            write!(f, ";UNTIL={until}")?;
+        }
+        if self.count > 0 {
+            write!(f, ";COUNT={}", self.count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the days since the epoch of the date `time` starts with.
+fn day_number(time: &str) -> Option<i64> {
+    let number = |range: core::ops::Range<usize>| time.get(range)?.parse::<i64>().ok();
+    Some(days_from_civil(
+        number(0..4)?,
+        number(5..7)?,
+        number(8..10)?,
+    ))
+}
+
+/// Returns the day of the week of a day since the epoch, from 0 (Monday)
+/// to 6 (Sunday).
+fn weekday_of(day: i64) -> i64 {
+    (day + 3).rem_euclid(7)
+}
+
+/// Returns the days since the epoch of every day in a month.
+fn month_range(year: i64, month: i64) -> core::ops::Range<i64> {
+    let (next_year, next_month) = match month {
+        12 => (year + 1, 1),
+        _ => (year, month + 1),
+    };
+    days_from_civil(year, month, 1)..days_from_civil(next_year, next_month, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    fn rule(frequency: Frequency) -> Rule {
+        Rule {
+            frequency,
+            interval: 1,
+            week_start: 0,
+            weekdays: Vec::new(),
+            month_days: Vec::new(),
+            months: Vec::new(),
+            set_positions: Vec::new(),
+            until: None,
+            count: 0,
+        }
+    }
+
+    fn before(time: &str) -> LocalTime {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn count_includes_the_first_occurrence() {
+        let rule = Rule {
+            count: 3,
+            ..rule(Frequency::Daily)
+        };
+        assert_eq!(rule.to_string(), "FREQ=DAILY;COUNT=3");
+        assert_eq!(
+            rule.occurrences("2025-01-01T09:00:00", &before("2026-01-01")),
+            ["2025-01-02T09:00:00", "2025-01-03T09:00:00"]
+        );
+    }
+
+    #[test]
+    fn until_includes_its_own_day() {
+        let rule = Rule {
+            until: Some("2025-01-15T09:00:00".into()),
+            ..rule(Frequency::Weekly)
+        };
+        assert_eq!(rule.to_string(), "FREQ=WEEKLY;UNTIL=20250115T090000");
+        assert_eq!(
+            rule.occurrences("2025-01-01T09:00:00", &before("2026-01-01")),
+            ["2025-01-08T09:00:00", "2025-01-15T09:00:00"]
+        );
+    }
+
+    #[test]
+    fn byday_with_an_ordinal_counts_within_the_month() {
+        // The second Tuesday, and the last Friday:
+        let rule = Rule {
+            weekdays: vec![Weekday { day: 1, week: 2 }, Weekday { day: 4, week: -1 }],
+            ..rule(Frequency::Monthly)
+        };
+        assert_eq!(rule.to_string(), "FREQ=MONTHLY;BYDAY=2TU,-1FR");
+        assert_eq!(
+            rule.occurrences("2025-01-14", &before("2025-04-01")),
+            [
+                "2025-01-31",
+                "2025-02-11",
+                "2025-02-28",
+                "2025-03-11",
+                "2025-03-28"
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_on_the_31st_skips_shorter_months() {
+        assert_eq!(
+            rule(Frequency::Monthly).occurrences("2025-01-31T18:30:00", &before("2025-09-01")),
+            [
+                "2025-03-31T18:30:00",
+                "2025-05-31T18:30:00",
+                "2025-07-31T18:30:00",
+                "2025-08-31T18:30:00",
+            ]
+        );
+    }
+}
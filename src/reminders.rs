@@ -0,0 +1,612 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Apple Reminders, read through EventKit from JavaScript for Automation:
+//! the Reminders AppleScript dictionary leaves out URLs, among others.
+
+use crate::{
+    Error, Result,
+    eventkit::{self, Entity},
+    recurrence::{self, SCRIPT as RECURRENCE_SCRIPT},
+    timezone::LocalTime,
+};
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+pub mod store;
+
+/// The application name used in error messages.
+pub const APP: &str = "Reminders";
+
+/// How reminders are extracted, as recorded in their provenance.
+pub const BACKEND: &str = "eventkit";
+
+/// The prefix of the URNs identifying individual reminders.
+pub const URN_PREFIX: &str = "urn:apple:reminders:reminder:";
+
+/// The prefix of the URNs identifying reminder lists.
+pub const LIST_URN_PREFIX: &str = "urn:apple:reminders:list:";
+
+/// The JavaScript that defines `record(r)` and `listRecord(c)`,
+/// describing reminder `r` and list `c` in the format [`parse`] reads.
+/// It goes after [`recurrence::SCRIPT`].
+const RECORD_SCRIPT: &str = r#"
+function listRecord(c) {
+    return {
+        id: c.calendarIdentifier.js,
+        name: string(c.title) || '',
+        color: color(c),
+        account: account(c),
+    };
+}
+function due(components) {
+    if (nil(components)) return null;
+    var day = $.NSCalendar.currentCalendar.dateFromComponents(components);
+    var timed = components.hour >= 0 && components.hour < 24;
+    return timed ? date(day) : date(day).slice(0, 10);
+}
+function geoTrigger(r) {
+    var alarms = r.alarms;
+    if (nil(alarms)) return null;
+    for (var i = 0; i < alarms.count; i++) {
+        var alarm = alarms.objectAtIndex(i);
+        var place = alarm.structuredLocation;
+        if (nil(place) || alarm.proximity === $.EKAlarmProximityNone) continue;
+        var point = nil(place.geoLocation) ? null : place.geoLocation.coordinate;
+        return {
+            name: string(place.title),
+            latitude: point === null ? null : point.latitude,
+            longitude: point === null ? null : point.longitude,
+            radius: place.radius > 0 ? place.radius : null,
+            proximity: alarm.proximity === $.EKAlarmProximityLeave ? 'leave' : 'arrive',
+        };
+    }
+    return null;
+}
+function record(r) {
+    return {
+        id: r.calendarItemIdentifier.js,
+        title: string(r.title) || '',
+        notes: string(r.notes),
+        url: nil(r.URL) ? null : r.URL.absoluteString.js,
+        due: due(r.dueDateComponents),
+        completed: r.completed,
+        completionDate: date(r.completionDate),
+        priority: r.priority,
+        list: string(r.calendar.title) || '',
+        account: account(r.calendar),
+        created: date(r.creationDate),
+        modified: date(r.lastModifiedDate),
+        recurrence: recurrence(r),
+        geoTrigger: geoTrigger(r),
+    };
+}
+"#;
+
+/// Which reminders to extract. EventKit narrows them down by completion
+/// and (for incomplete reminders) due date itself, and the script does
+/// the rest before any reach this module.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Only reminders due at or after this time.
+    pub due_after: Option<LocalTime>,
+    /// Only reminders due before this time.
+    pub due_before: Option<LocalTime>,
+    /// Only completed reminders, or with `Some(false)`, incomplete ones.
+    pub completed: Option<bool>,
+    /// Only incomplete reminders due before now (or, due on a day without
+    /// a time, before today).
+    pub overdue: bool,
+    /// Only the reminders in lists by these names, if any are given.
+    pub lists: Vec<String>,
+    /// Also recurring reminders due before `due_after`, as occurrences of
+    /// them may fall after it. [`Filter::admits_due`] tells which to emit.
+    pub keep_recurring: bool,
+}
+
+impl Filter {
+    /// Whether a reminder (or occurrence) due at `due`, a date or a date
+    /// and time, falls within `due_after` and `due_before`.
+    pub fn admits_due(&self, due: Option<&str>) -> bool {
+        if self.due_after.is_none() && self.due_before.is_none() {
+            return true;
+        }
+        let Some(due) = due else {
+            return false;
+        };
+        let due = match due.len() {
+            10 => format!("{due}T00:00:00"),
+            _ => due.into(),
+        };
+        self.due_after
+            .as_ref()
+            .is_none_or(|after| due.as_str() >= after.as_str())
+            && self
+                .due_before
+                .as_ref()
+                .is_none_or(|before| due.as_str() < before.as_str())
+    }
+}
+
+/// Everything a script extracts: the lists, and the reminders in them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Library {
+    pub lists: Vec<List>,
+    pub reminders: Vec<Reminder>,
+}
+
+impl Library {
+    /// Adds what the stores tell to the reminders: their flags and tags,
+    /// and which are subtasks of which, putting each parent's subtasks in
+    /// the order Reminders shows them. Either may be a reminder the
+    /// filters left out.
+    pub fn add_details(&mut self, details: &BTreeMap<String, store::Details>) {
+        let mut children: BTreeMap<&str, Vec<(i64, &str)>> = BTreeMap::new();
+        for (id, detail) in details {
+            if let Some(parent) = &detail.parent {
+                children
+                    .entry(parent)
+                    .or_default()
+                    .push((detail.position, id));
+            }
+        }
+        for reminder in &mut self.reminders {
+            let detail = details.get(&reminder.id);
+            reminder.parent = detail.and_then(|detail| detail.parent.clone());
+            reminder.flagged = detail.map(|detail| detail.flagged);
+            reminder.tags = detail.map(|detail| detail.tags.clone()).unwrap_or_default();
+            let mut subtasks = children.remove(reminder.id.as_str()).unwrap_or_default();
+            subtasks.sort();
+            reminder.subtasks = subtasks.into_iter().map(|(_, id)| id.into()).collect();
+        }
+    }
+}
+
+/// A list of reminders.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct List {
+    pub id: String,
+    pub name: String,
+    /// The list's color in Reminders, as in `#FF9500`.
+    pub color: Option<String>,
+    pub account: String,
+}
+
+impl List {
+    /// Returns the stable URN identifying this list.
+    pub fn urn(&self) -> String {
+        format!("{LIST_URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this list.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "ItemList",
+            "@id": self.urn(),
+            "name": self.name,
+            "color": self.color,
+            "account": self.account,
+            "source": "apple-reminders",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Where a location-based reminder goes off.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeoTrigger {
+    /// The place's name, as in `Home`.
+    pub name: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// How near the place counts, in meters.
+    pub radius: Option<f64>,
+    /// Whether it goes off on leaving the place, rather than arriving.
+    pub leaving: bool,
+}
+
+impl GeoTrigger {
+    /// Returns the trigger as a schema.org `Place`, with `proximity`
+    /// `arrive` or `leave`.
+    pub fn to_json(&self) -> Value {
+        let mut place = json!({
+            "@type": "Place",
+            "name": self.name,
+            "latitude": self.latitude,
+            "longitude": self.longitude,
+            "radius": self.radius,
+            "proximity": match self.leaving {
+                true => "leave",
+                false => "arrive",
+            },
+        });
+        if let Some(place) = place.as_object_mut() {
+            place.retain(|_, value| !value.is_null());
+        }
+        place
+    }
+}
+
+/// A single reminder as EventKit describes it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reminder {
+    pub id: String,
+    pub title: String,
+    pub notes: Option<String>,
+    pub url: Option<String>,
+    /// When the reminder is due, in ISO 8601 local time: a date such as
+    /// `2025-01-20`, or a date and time such as `2025-01-20T13:30:00`.
+    pub due: Option<String>,
+    pub completed: bool,
+    pub completion_date: Option<String>,
+    /// From 1 (highest) to 9 (lowest), or 0 for none.
+    pub priority: u8,
+    /// Whether the reminder is flagged, or `None` if the stores weren't
+    /// read.
+    pub flagged: Option<bool>,
+    /// The reminder's tags, without the leading `#`.
+    pub tags: Vec<String>,
+    pub list: String,
+    pub account: String,
+    pub created: Option<String>,
+    pub modified: Option<String>,
+    /// How the reminder recurs, if it does.
+    pub recurrence: Option<recurrence::Rule>,
+    /// Where the reminder goes off, if it is location-based.
+    pub geo_trigger: Option<GeoTrigger>,
+    /// The id of the reminder this one is a subtask of.
+    pub parent: Option<String>,
+    /// The ids of this reminder's subtasks, in order.
+    pub subtasks: Vec<String>,
+}
+
+impl Reminder {
+    /// Returns the stable URN identifying this reminder.
+    pub fn urn(&self) -> String {
+        format!("{URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns `high`, `medium`, or `low`, as Reminders shows the priority,
+    /// or `None` if it has none.
+    pub fn priority_label(&self) -> Option<&'static str> {
+        match self.priority {
+            1..=4 => Some("high"),
+            5 => Some("medium"),
+            6..=9 => Some("low"),
+            _ => None,
+        }
+    }
+
+    /// Returns the JSON-LD record for this reminder. Unset fields are left
+    /// out.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "Action",
+            "@id": self.urn(),
+            "name": self.title,
+            "description": self.notes,
+            "scheduledTime": self.due,
+            "priority": self.priority_label(),
+            "flagged": self.flagged,
+            "keywords": match self.tags.is_empty() {
+                true => Value::Null,
+                false => json!(self.tags),
+            },
+            "url": self.url,
+            "actionStatus": match self.completed {
+                true => "CompletedActionStatus",
+                false => "PotentialActionStatus",
+            },
+            "endTime": self.completion_date,
+            "isPartOf": self.list,
+            "account": self.account,
+            "dateCreated": self.created,
+            "dateModified": self.modified,
+            "recurrenceRule": self.recurrence.as_ref().map(ToString::to_string),
+            "geoTrigger": self.geo_trigger.as_ref().map(GeoTrigger::to_json),
+            "parentId": self.parent.as_ref().map(|id| format!("{URN_PREFIX}{id}")),
+            "subtasks": match self.subtasks.is_empty() {
+                true => Value::Null,
+                false => self.subtasks.iter().map(|id| format!("{URN_PREFIX}{id}")).collect(),
+            },
+            "source": "apple-reminders",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+
+    /// Returns the JSON-LD record for the occurrence of this recurring
+    /// reminder due at `due`: the reminder's record, incomplete, with
+    /// `occurrenceOf` its URN, and without its rule or subtasks.
+    pub fn occurrence_to_json(&self, due: &str) -> Value {
+        let mut record = self.to_json();
+        if let Some(record) = record.as_object_mut() {
+            for key in ["recurrenceRule", "subtasks", "endTime"] {
+                record.remove(key);
+            }
+            record.insert("@id".into(), json!(format!("{}/{due}", self.urn())));
+            record.insert("scheduledTime".into(), json!(due));
+            record.insert("actionStatus".into(), json!("PotentialActionStatus"));
+            record.insert("occurrenceOf".into(), json!(self.urn()));
+        }
+        record
+    }
+}
+
+/// Returns the script that describes the lists `filter` selects (every
+/// list, unless it names some) and the reminders in them that pass it, as
+/// a JSON object with `lists` and `reminders` arrays.
+pub fn script(filter: &Filter) -> String {
+    let completed = match filter.overdue {
+        true => Some(false),
+        false => filter.completed,
+    };
+    let filter = json!({
+        "dueAfter": filter.due_after.as_ref().map(LocalTime::as_str),
+        "dueBefore": filter.due_before.as_ref().map(LocalTime::as_str),
+        "completed": completed,
+        "overdue": filter.overdue,
+        "lists": filter.lists,
+        "keepRecurring": filter.keep_recurring,
+    });
+    let prelude = eventkit::prelude(Entity::Reminder);
+    format!(
+        r#"
+{prelude}
+{RECURRENCE_SCRIPT}
+{RECORD_SCRIPT}
+var filter = {filter};
+function bound(time) {{
+    return time === null ? $() : formatter.dateFromString(time);
+}}
+var calendars = [];
+var all = store.calendarsForEntityType($.EKEntityTypeReminder);
+for (var i = 0; i < all.count; i++) {{
+    var c = all.objectAtIndex(i);
+    if (filter.lists.length === 0 || filter.lists.indexOf(string(c.title)) >= 0) calendars.push(c);
+}}
+var predicate;
+if (filter.completed === true) {{
+    predicate = store.predicateForCompletedRemindersWithCompletionDateStartingEndingCalendars(
+        $(), $(), calendars);
+}} else if (filter.completed === false) {{
+    predicate = store.predicateForIncompleteRemindersWithDueDateStartingEndingCalendars(
+        bound(filter.keepRecurring ? null : filter.dueAfter), bound(filter.dueBefore), calendars);
+}} else {{
+    predicate = store.predicateForRemindersInCalendars(calendars);
+}}
+function wanted(r) {{
+    var due = r.due === null || r.due.length > 10 ? r.due : r.due + 'T00:00:00';
+    if (filter.completed !== null && r.completed !== filter.completed) return false;
+    if (filter.overdue) {{
+        var now = date($.NSDate.date);
+        if (r.completed || r.due === null) return false;
+        if (r.due.length > 10 ? r.due >= now : r.due >= now.slice(0, 10)) return false;
+    }}
+    if (filter.dueAfter === null && filter.dueBefore === null) return true;
+    var recurs = filter.keepRecurring && r.recurrence !== null;
+    return due !== null
+        && (filter.dueAfter === null || recurs || due >= filter.dueAfter)
+        && (filter.dueBefore === null || due < filter.dueBefore);
+}}
+// EventKit takes no lists at all to mean every list:
+var fetched = calendars.length === 0 ? $.NSArray.array : null;
+if (fetched === null) {{
+    store.fetchRemindersMatchingPredicateCompletion(predicate, function (reminders) {{
+        fetched = reminders;
+    }});
+}}
+wait(function () {{ return fetched !== null; }});
+var output = {{ lists: calendars.map(listRecord), reminders: [] }};
+for (var i = 0; i < fetched.count; i++) {{
+    var r = record(fetched.objectAtIndex(i));
+    if (wanted(r)) output.reminders.push(r);
+}}
+JSON.stringify(output);
+"#
+    )
+}
+
+/// The JavaScript that defines `save(r)`, which saves reminder `r` or
+/// throws.
+const SAVE_SCRIPT: &str = r#"
+function save(r) {
+    var error = Ref();
+    if (!store.saveReminderCommitError(r, true, error)) {
+        throw new Error('Could not save the reminder: ' + error[0].localizedDescription.js);
+    }
+}
+"#;
+
+/// Returns the script that creates a reminder in the list named `list`, or
+/// else in the default list for new reminders, and describes it as
+/// [`script`] does, the list not found leaving `reminders` empty. A `due`
+/// given as a date makes it due that day, without a time.
+pub fn create_script(
+    title: &str,
+    list: Option<&str>,
+    due: Option<&LocalTime>,
+    notes: Option<&str>,
+) -> String {
+    let input = json!({
+        "title": title,
+        "list": list,
+        "due": due.map(LocalTime::as_str),
+        "timed": due.is_some_and(LocalTime::has_time),
+        "notes": notes,
+    });
+    let prelude = eventkit::prelude(Entity::Reminder);
+    format!(
+        r#"
+{prelude}
+{RECURRENCE_SCRIPT}
+{RECORD_SCRIPT}
+{SAVE_SCRIPT}
+var input = {input};
+var calendar = input.list === null ? store.defaultCalendarForNewReminders : null;
+var all = store.calendarsForEntityType($.EKEntityTypeReminder);
+for (var i = 0; input.list !== null && i < all.count; i++) {{
+    var c = all.objectAtIndex(i);
+    if (string(c.title) === input.list) {{
+        calendar = c;
+        break;
+    }}
+}}
+var output = {{ lists: [], reminders: [] }};
+if (!nil(calendar)) {{
+    var r = $.EKReminder.reminderWithEventStore(store);
+    r.title = input.title;
+    r.calendar = calendar;
+    if (input.notes !== null) r.notes = input.notes;
+    if (input.due !== null) {{
+        var units = $.NSCalendarUnitYear | $.NSCalendarUnitMonth | $.NSCalendarUnitDay;
+        if (input.timed) units |= $.NSCalendarUnitHour | $.NSCalendarUnitMinute | $.NSCalendarUnitSecond;
+        r.dueDateComponents = $.NSCalendar.currentCalendar.componentsFromDate(
+            units, formatter.dateFromString(input.due));
+    }}
+    save(r);
+    output.lists.push(listRecord(calendar));
+    output.reminders.push(record(r));
+}}
+JSON.stringify(output);
+"#
+    )
+}
+
+/// Returns the script that marks the reminder `id` completed and describes
+/// it as [`script`] does, the reminder not found leaving `reminders` empty.
+pub fn complete_script(id: &str) -> String {
+    let id = json!(id);
+    let prelude = eventkit::prelude(Entity::Reminder);
+    format!(
+        r#"
+{prelude}
+{RECURRENCE_SCRIPT}
+{RECORD_SCRIPT}
+{SAVE_SCRIPT}
+var r = store.calendarItemWithIdentifier({id});
+var output = {{ lists: [], reminders: [] }};
+if (!nil(r)) {{
+    r.completed = true;
+    save(r);
+    output.lists.push(listRecord(r.calendar));
+    output.reminders.push(record(r));
+}}
+JSON.stringify(output);
+"#
+    )
+}
+
+/// Extracts the reminder id from a reminder URN, also accepting a bare
+/// EventKit id. The URN of an occurrence (see
+/// [`Reminder::occurrence_to_json`]) is not one of a reminder.
+pub fn id_from_urn(input: &str) -> Option<&str> {
+    let input = input.trim();
+    let id = input.strip_prefix(URN_PREFIX).unwrap_or(input);
+    (!id.is_empty() && !id.contains(['/', ':'])).then_some(id)
+}
+
+/// Parses the output of [`script`].
+pub fn parse(output: &str) -> Result<Library> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Library::default()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading reminders",
+            message: e.to_string(),
+        })?,
+    };
+    from_json(&output)
+}
+
+/// Reads the lists and reminders of a JSON object as [`script`] prints,
+/// and as [`eventkit::read_reminders`] returns.
+pub fn from_json(output: &Value) -> Result<Library> {
+    let array = |key: &str| {
+        output
+            .get(key)
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+    };
+    Ok(Library {
+        lists: array("lists")
+            .iter()
+            .map(parse_list)
+            .collect::<Result<_>>()?,
+        reminders: array("reminders")
+            .iter()
+            .map(parse_record)
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn parse_list(record: &Value) -> Result<List> {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).map(String::from);
+    let Some(id) = text("id") else {
+        return Err(Error::Parse {
+            context: "reading reminder list id",
+            message: "missing id field".to_string(),
+        });
+    };
+    Ok(List {
+        id,
+        name: text("name").unwrap_or_default(),
+        color: text("color"),
+        account: text("account").unwrap_or_default(),
+    })
+}
+
+fn parse_record(record: &Value) -> Result<Reminder> {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).map(String::from);
+    let Some(id) = text("id") else {
+        return Err(Error::Parse {
+            context: "reading reminder id",
+            message: "missing id field".to_string(),
+        });
+    };
+    Ok(Reminder {
+        id,
+        title: text("title").unwrap_or_default(),
+        notes: text("notes").filter(|notes| !notes.trim().is_empty()),
+        url: text("url"),
+        due: text("due"),
+        completed: record.get("completed").and_then(Value::as_bool) == Some(true),
+        completion_date: text("completionDate"),
+        priority: record
+            .get("priority")
+            .and_then(Value::as_u64)
+            .map_or(0, |p| p.min(9) as u8),
+        list: text("list").unwrap_or_default(),
+        account: text("account").unwrap_or_default(),
+        created: text("created"),
+        modified: text("modified"),
+        recurrence: record
+            .get("recurrence")
+            .and_then(recurrence::Rule::from_json),
+        geo_trigger: record
+            .get("geoTrigger")
+            .filter(|trigger| trigger.is_object())
+            .map(|trigger| {
+                let number = |key: &str| trigger.get(key).and_then(Value::as_f64);
+                GeoTrigger {
+                    name: trigger
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .map(String::from),
+                    latitude: number("latitude"),
+                    longitude: number("longitude"),
+                    radius: number("radius"),
+                    leaving: trigger.get("proximity").and_then(Value::as_str) == Some("leave"),
+                }
+            }),
+        ..Reminder::default()
+    })
+}
@@ -0,0 +1,113 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads what EventKit doesn't tell about reminders, such as which are
+//! subtasks of which, flags, and tags, from the Core Data stores Reminders keeps them in,
+//! one SQLite database per account, through the `sqlite3` shell.
+
+use crate::{Error, Result, exec};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// What the stores tell about one reminder.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Details {
+    /// The id of the reminder this one is a subtask of.
+    pub parent: Option<String>,
+    /// Where the reminder sorts among its siblings.
+    pub position: i64,
+    pub flagged: bool,
+    /// The reminder's tags, without the leading `#`, sorted.
+    pub tags: Vec<String>,
+}
+
+/// The query, keyed by the id EventKit knows each reminder by. Tags are
+/// hashtag objects pointing at their reminder, and come out as a JSON
+/// array in a string.
+const QUERY: &str = "\
+SELECT r.ZDACALENDARITEMUNIQUEIDENTIFIER AS id, \
+p.ZDACALENDARITEMUNIQUEIDENTIFIER AS parent, \
+coalesce(r.ZICSDISPLAYORDER, 0) AS position, \
+coalesce(r.ZFLAGGED, 0) AS flagged, \
+(SELECT json_group_array(t.ZNAME1) FROM (SELECT o.ZNAME1 FROM ZREMCDOBJECT o \
+WHERE o.ZREMINDER3 = r.Z_PK AND o.ZNAME1 IS NOT NULL \
+AND o.Z_ENT = (SELECT Z_ENT FROM Z_PRIMARYKEY WHERE Z_NAME = 'REMCDHashtag') \
+ORDER BY o.ZNAME1) t) AS tags \
+FROM ZREMCDREMINDER r LEFT JOIN ZREMCDREMINDER p ON p.Z_PK = r.ZPARENTREMINDER \
+WHERE r.ZDACALENDARITEMUNIQUEIDENTIFIER IS NOT NULL AND coalesce(r.ZMARKEDFORDELETION, 0) = 0;
+";
+
+/// Returns where Reminders keeps its stores:
+/// `~/Library/Group Containers/group.com.apple.reminders/Container_v1/Stores`.
+pub fn default_dir() -> Result<PathBuf> {
+    let home = env::var_os("HOME").ok_or_else(|| Error::Usage {
+        message: "HOME is not set".to_string(),
+    })?;
+    Ok(PathBuf::from(home)
+        .join("Library/Group Containers/group.com.apple.reminders/Container_v1/Stores"))
+}
+
+/// Reads the details of every reminder in the stores in `dir`. Reading
+/// it takes Full Disk Access, lacking which this fails.
+pub fn read(dir: &Path) -> Result<BTreeMap<String, Details>> {
+    let entries = fs::read_dir(dir).map_err(|e| Error::Io {
+        context: "listing the Reminders stores",
+        source: e,
+    })?;
+    let mut stores: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+            name.starts_with("Data-") && name.ends_with(".sqlite")
+        })
+        .collect();
+    stores.sort();
+
+    let mut details = BTreeMap::new();
+    for store in stores {
+        let output = exec::run(
+            "sqlite3",
+            [
+                OsStr::new("-readonly"),
+                OsStr::new("-bail"),
+                OsStr::new("-json"),
+                store.as_os_str(),
+            ],
+            QUERY.as_bytes(),
+        )?;
+        // `sqlite3 -json` prints nothing at all when there are no rows:
+        if output.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let rows: Vec<Value> = serde_json::from_slice(&output).map_err(|e| Error::Parse {
+            context: "reading the Reminders stores",
+            message: e.to_string(),
+        })?;
+        for row in rows {
+            let text = |key: &str| row.get(key).and_then(Value::as_str).map(String::from);
+            let Some(id) = text("id") else {
+                continue;
+            };
+            details.insert(
+                id,
+                Details {
+                    parent: text("parent"),
+                    position: row.get("position").and_then(Value::as_i64).unwrap_or(0),
+                    flagged: row.get("flagged").and_then(Value::as_i64).unwrap_or(0) != 0,
+                    tags: text("tags")
+                        .and_then(|tags| serde_json::from_str(&tags).ok())
+                        .unwrap_or_default(),
+                },
+            );
+        }
+    }
+    Ok(details)
+}
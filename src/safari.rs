@@ -0,0 +1,139 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Safari, read straight from the files it keeps in `~/Library/Safari`, as
+//! its scripting dictionary only knows about open windows, which are read
+//! through it. Reading the files takes Full Disk Access.
+
+use crate::{
+    Error, Result,
+    bplist::{self, Plist},
+    error::check_readable,
+    timezone::TimeZone,
+};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+};
+
+pub mod bookmarks;
+pub mod cloud_tabs;
+pub mod downloads;
+pub mod history;
+pub mod reading_list;
+pub mod tabs;
+
+/// The name of the application, as scripted.
+pub const APP: &str = "Safari";
+
+/// How bookmarks are extracted, as recorded in their provenance.
+pub const BOOKMARKS_BACKEND: &str = "safari-bookmarks";
+
+/// How iCloud Tabs are extracted, as recorded in their provenance.
+pub const CLOUD_TABS_BACKEND: &str = "safari-cloud-tabs";
+
+/// How downloads are extracted, as recorded in their provenance.
+pub const DOWNLOADS_BACKEND: &str = "safari-downloads";
+
+/// How the pages visited most are extracted, as recorded in their
+/// provenance.
+pub const TOP_SITES_BACKEND: &str = "safari-top-sites";
+
+/// How history is extracted, as recorded in its provenance.
+pub const HISTORY_BACKEND: &str = "safari-history";
+
+/// The prefix of the URNs identifying bookmarks.
+pub const BOOKMARK_URN_PREFIX: &str = "urn:apple:safari:bookmark:";
+
+/// The prefix of the URNs identifying the tabs open on other devices.
+pub const CLOUD_TAB_URN_PREFIX: &str = "urn:apple:safari:cloud-tab:";
+
+/// The prefix of the URNs identifying downloads.
+pub const DOWNLOAD_URN_PREFIX: &str = "urn:apple:safari:download:";
+
+/// The prefix of the URNs identifying bookmark folders.
+pub const FOLDER_URN_PREFIX: &str = "urn:apple:safari:folder:";
+
+/// How the Reading List is extracted, as recorded in its provenance.
+pub const READING_LIST_BACKEND: &str = "safari-reading-list";
+
+/// The prefix of the URNs identifying the pages in the Reading List.
+pub const READING_LIST_URN_PREFIX: &str = "urn:apple:safari:reading-list:";
+
+/// How open tabs are extracted, as recorded in their provenance.
+pub const TABS_BACKEND: &str = "safari-applescript";
+
+/// The prefix of the URNs identifying open tabs, by window and place.
+pub const TAB_URN_PREFIX: &str = "urn:apple:safari:tab:";
+
+/// The prefix of the URNs identifying the pages visited most.
+pub const TOP_SITE_URN_PREFIX: &str = "urn:apple:safari:top-site:";
+
+/// The prefix of the URNs identifying the pages in the history.
+pub const HISTORY_URN_PREFIX: &str = "urn:apple:safari:history:";
+
+/// The title of the folder Safari keeps the Reading List in, with the
+/// bookmarks.
+const READING_LIST: &str = "com.apple.ReadingList";
+
+/// The seconds from the Unix epoch to Apple's, 2001-01-01, which Safari
+/// counts dates from.
+const APPLE_EPOCH: i64 = 978_307_200;
+
+/// Returns where Safari keeps its files: `~/Library/Safari`.
+pub fn default_dir() -> Result<PathBuf> {
+    let home = env::var_os("HOME").ok_or_else(|| Error::Usage {
+        message: "HOME is not set".to_string(),
+    })?;
+    Ok(PathBuf::from(home).join("Library/Safari"))
+}
+
+/// Returns where Safari keeps the tabs open on the user's other devices:
+/// `CloudTabs.db`, in its container since macOS 10.15, or in
+/// `~/Library/Safari` before.
+pub fn cloud_tabs_path() -> Result<PathBuf> {
+    let dir = default_dir()?;
+    let container = dir
+        .parent()
+        .unwrap_or(&dir)
+        .join("Containers/com.apple.Safari/Data/Library/Safari/CloudTabs.db");
+    Ok(match container.exists() {
+        true => container,
+        false => dir.join("CloudTabs.db"),
+    })
+}
+
+/// Formats seconds since 2001-01-01 as a local time, as in
+/// `2025-01-20T13:30:00`.
+fn local_time(zone: &TimeZone, seconds: f64) -> String {
+    zone.to_local(seconds as i64 + APPLE_EPOCH)
+}
+
+/// Reads the binary property list at `path`, as `what`, which takes Full
+/// Disk Access under `~/Library/Safari`.
+fn read_plist(path: &Path, what: &'static str) -> Result<Plist> {
+    check_readable(path, what)?;
+    let data = fs::read(path).map_err(|e| Error::Io {
+        context: "reading a Safari property list",
+        source: e,
+    })?;
+    bplist::parse(&data).ok_or_else(|| Error::Parse {
+        context: "reading a Safari property list",
+        message: "not a binary property list".to_string(),
+    })
+}
+
+/// Returns what is in a bookmark folder.
+fn children(item: &Plist) -> &[Plist] {
+    item.get("Children")
+        .and_then(Plist::as_array)
+        .unwrap_or_default()
+}
+
+/// Returns the string under `key` in a dictionary.
+fn text<'a>(item: &'a Plist, key: &str) -> Option<&'a str> {
+    match item.get(key)? {
+        Plist::String(text) => Some(text),
+        _ => None,
+    }
+}
@@ -0,0 +1,180 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads Safari's bookmarks, and the folders they are in, from
+//! `Bookmarks.plist`, a binary property list.
+
+use super::{
+    BOOKMARK_URN_PREFIX, FOLDER_URN_PREFIX, READING_LIST, children, local_time, read_plist, text,
+};
+use crate::{Result, bplist::Plist, export::sha256_hex, timezone::TimeZone};
+use serde_json::{Value, json};
+use std::{
+    format,
+    path::Path,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Everything `Bookmarks.plist` holds: every folder and every bookmark,
+/// in the order Safari shows them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bookmarks {
+    pub folders: Vec<Folder>,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+/// A bookmark folder, as in `Favorites`, or one in it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Folder {
+    /// Safari's UUID for the folder.
+    pub id: String,
+    pub name: String,
+    /// The names of the folders it is in, and its own, as in
+    /// `Favorites/News`.
+    pub path: String,
+    /// The id of the folder it is in, if any.
+    pub parent: Option<String>,
+}
+
+impl Folder {
+    /// Returns the stable URN identifying this folder.
+    pub fn urn(&self) -> String {
+        format!("{FOLDER_URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this folder: a schema.org
+    /// `Collection`, linked to the folder it is in.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "Collection",
+            "@id": self.urn(),
+            "additionalType": "bookmarkFolder",
+            "name": self.name,
+            "folder": self.path,
+            "isPartOf": self.parent.as_ref().map(|id| format!("{FOLDER_URN_PREFIX}{id}")),
+            "source": "apple-safari",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// A single bookmark.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bookmark {
+    /// Safari's UUID for the bookmark.
+    pub id: String,
+    pub title: Option<String>,
+    pub url: String,
+    /// The id of the folder it is in, and the path of that folder.
+    pub folder: Option<String>,
+    pub path: String,
+    /// When it was added, in local time, if Safari kept that.
+    pub added: Option<String>,
+}
+
+impl Bookmark {
+    /// Returns the stable URN identifying this bookmark.
+    pub fn urn(&self) -> String {
+        format!("{BOOKMARK_URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this bookmark: a schema.org
+    /// `WebPage`, linked to the folder it is in.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "WebPage",
+            "@id": self.urn(),
+            "additionalType": "bookmark",
+            "name": self.title,
+            "url": self.url,
+            "folder": self.path,
+            "isPartOf": self.folder.as_ref().map(|id| format!("{FOLDER_URN_PREFIX}{id}")),
+            "dateCreated": self.added,
+            "source": "apple-safari",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Reads the bookmarks in the `Bookmarks.plist` at `path`, leaving out the
+/// Reading List. Reading it takes Full Disk Access, lacking which this
+/// fails, saying so.
+pub fn read(path: &Path) -> Result<Bookmarks> {
+    let plist = read_plist(path, "Safari bookmarks")?;
+    Ok(parse(&plist, &TimeZone::local()))
+}
+
+/// Returns the bookmarks and folders in the property list, folders before
+/// what is in them.
+pub fn parse(plist: &Plist, zone: &TimeZone) -> Bookmarks {
+    let mut bookmarks = Bookmarks::default();
+    for child in children(plist) {
+        if text(child, "Title") == Some(READING_LIST) {
+            continue;
+        }
+        walk(child, None, "", zone, &mut bookmarks);
+    }
+    bookmarks
+}
+
+fn walk(item: &Plist, parent: Option<&Folder>, path: &str, zone: &TimeZone, found: &mut Bookmarks) {
+    // Safari gives everything a UUID; anything without one is told apart
+    // by where it is and where it links to:
+    let id = |key: &str| match text(item, "WebBookmarkUUID") {
+        Some(id) => id.to_string(),
+        None => sha256_hex(format!("{path}\n{key}").as_bytes()),
+    };
+    match text(item, "WebBookmarkType") {
+        Some("WebBookmarkTypeList") => {
+            // The folders at the top are named as Safari shows them:
+            let name = match (parent, text(item, "Title").unwrap_or_default()) {
+                (None, "BookmarksBar") => "Favorites",
+                (None, "BookmarksMenu") => "Bookmarks Menu",
+                (_, name) => name,
+            };
+            let folder = Folder {
+                id: id(name),
+                name: name.to_string(),
+                path: match path {
+                    "" => name.to_string(),
+                    path => format!("{path}/{name}"),
+                },
+                parent: parent.map(|folder| folder.id.clone()),
+            };
+            found.folders.push(folder.clone());
+            for child in children(item) {
+                walk(child, Some(&folder), &folder.path, zone, found);
+            }
+        }
+        Some("WebBookmarkTypeLeaf") => {
+            let Some(url) = text(item, "URLString") else {
+                return;
+            };
+            let title = item
+                .get("URIDictionary")
+                .and_then(|uri| text(uri, "title"))
+                .or_else(|| text(item, "Title"));
+            let added = item
+                .get("DateAdded")
+                .or_else(|| item.get("ReadingList")?.get("DateAdded"))
+                .and_then(Plist::as_f64)
+                .map(|date| local_time(zone, date));
+            found.bookmarks.push(Bookmark {
+                id: id(url),
+                title: title.filter(|title| !title.is_empty()).map(String::from),
+                url: url.to_string(),
+                folder: parent.map(|folder| folder.id.clone()),
+                path: path.to_string(),
+                added,
+            });
+        }
+        // Proxies, as for History, aren't bookmarks:
+        _ => {}
+    }
+}
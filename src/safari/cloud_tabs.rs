@@ -0,0 +1,119 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads the tabs open on the user's other devices, as iCloud Tabs syncs
+//! them, from Safari's `CloudTabs.db`, through the `sqlite3` shell.
+
+use super::{APPLE_EPOCH, CLOUD_TAB_URN_PREFIX};
+use crate::{Error, Result, error::check_readable, exec};
+use serde_json::{Value, json};
+use std::{
+    ffi::OsStr,
+    format,
+    path::Path,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// A tab open on another device.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CloudTab {
+    /// Safari's UUID for the tab.
+    pub id: String,
+    pub title: Option<String>,
+    pub url: String,
+    /// The name of the device it is open on, as in `iPhone`.
+    pub device: Option<String>,
+    /// iCloud's UUID for the device.
+    pub device_id: String,
+    /// When the device last synced its tabs, in local time.
+    pub synced: Option<String>,
+}
+
+impl CloudTab {
+    /// Returns the stable URN identifying this tab.
+    pub fn urn(&self) -> String {
+        format!("{CLOUD_TAB_URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this tab: a schema.org `WebPage`.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "WebPage",
+            "@id": self.urn(),
+            "additionalType": "cloudTab",
+            "name": self.title,
+            "url": self.url,
+            "device": self.device,
+            "deviceId": self.device_id,
+            "dateModified": self.synced,
+            "source": "apple-safari",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Reads the tabs in the database at `path`, device by device. Reading it
+/// takes Full Disk Access, lacking which this fails, saying so.
+pub fn read(path: &Path) -> Result<Vec<CloudTab>> {
+    check_readable(path, "Safari iCloud Tabs")?;
+    let output = exec::run(
+        "sqlite3",
+        [
+            OsStr::new("-readonly"),
+            OsStr::new("-bail"),
+            path.as_os_str(),
+        ],
+        query().as_bytes(),
+    )?;
+    parse(&String::from_utf8_lossy(&output))
+}
+
+/// Returns the query that reads the tabs as one JSON array, in the format
+/// [`parse`] reads.
+fn query() -> String {
+    format!(
+        "SELECT coalesce(json_group_array(json(r)), '[]') FROM \
+     (SELECT json_object('id', t.tab_uuid, 'title', t.title, 'url', t.url, \
+     'deviceId', t.device_uuid, 'device', d.device_name, \
+     'synced', CASE WHEN d.last_modified > 0 THEN strftime('%Y-%m-%dT%H:%M:%S', \
+     d.last_modified + {APPLE_EPOCH}, 'unixepoch', 'localtime') END) AS r \
+     FROM cloud_tabs t LEFT JOIN cloud_tab_devices d ON d.device_uuid = t.device_uuid \
+     ORDER BY d.device_name, t.device_uuid, t.rowid);\n"
+    )
+}
+
+/// Parses the output of the query, keeping the tabs in order.
+pub fn parse(output: &str) -> Result<Vec<CloudTab>> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Vec::new()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading Safari iCloud Tabs",
+            message: e.to_string(),
+        })?,
+    };
+    Ok(output
+        .as_array()
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .filter_map(|record| {
+            let text = |key: &str| {
+                record
+                    .get(key)
+                    .and_then(Value::as_str)
+                    .filter(|text| !text.is_empty())
+                    .map(String::from)
+            };
+            Some(CloudTab {
+                id: text("id")?,
+                title: text("title"),
+                url: text("url")?,
+                device: text("device"),
+                device_id: text("deviceId")?,
+                synced: text("synced"),
+            })
+        })
+        .collect())
+}
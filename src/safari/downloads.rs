@@ -0,0 +1,123 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads Safari's downloads from `Downloads.plist`, a binary property list
+//! of every download the Downloads list shows: where it came from, and
+//! where it went.
+
+use super::{DOWNLOAD_URN_PREFIX, local_time, read_plist, text};
+use crate::{Result, bplist::Plist, timezone::TimeZone};
+use serde_json::{Value, json};
+use std::{
+    env, format,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// A download, finished or not.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Download {
+    /// Safari's UUID for the download.
+    pub id: String,
+    /// Where it was downloaded from.
+    pub url: String,
+    /// Where it was downloaded to, as in `~/Downloads/file.zip`, expanded.
+    pub path: Option<PathBuf>,
+    /// Its size in bytes, if the server said.
+    pub size: Option<u64>,
+    /// How many of its bytes were downloaded.
+    pub downloaded: Option<u64>,
+    /// When it was started, and when it finished, in local time.
+    pub started: Option<String>,
+    pub finished: Option<String>,
+}
+
+impl Download {
+    /// Returns the stable URN identifying this download.
+    pub fn urn(&self) -> String {
+        format!("{DOWNLOAD_URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns whether all of it was downloaded.
+    pub fn is_finished(&self) -> bool {
+        self.finished.is_some() || (self.size.is_some() && self.downloaded >= self.size)
+    }
+
+    /// Returns the JSON-LD record for this download: a schema.org
+    /// `MediaObject`, the file downloaded.
+    pub fn to_json(&self) -> Value {
+        let name = self
+            .path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy());
+        let mut record = json!({
+            "@type": "MediaObject",
+            "@id": self.urn(),
+            "additionalType": "download",
+            "name": name,
+            "url": self.url,
+            "path": self.path.as_ref().map(|path| path.display().to_string()),
+            "contentSize": self.size,
+            "bytesDownloaded": self.downloaded,
+            "dateCreated": self.started,
+            "dateFinished": self.finished,
+            "finished": self.is_finished(),
+            "source": "apple-safari",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Reads the downloads in the `Downloads.plist` at `path`, in the order
+/// Safari lists them, newest first. Reading it takes Full Disk Access,
+/// lacking which this fails, saying so.
+pub fn read(path: &Path) -> Result<Vec<Download>> {
+    let plist = read_plist(path, "Safari downloads")?;
+    Ok(parse(&plist, &TimeZone::local()))
+}
+
+/// Returns the downloads in the property list.
+pub fn parse(plist: &Plist, zone: &TimeZone) -> Vec<Download> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    plist
+        .get("DownloadHistory")
+        .and_then(Plist::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|entry| {
+            let date = |key: &str| {
+                entry
+                    .get(key)
+                    .and_then(Plist::as_f64)
+                    .map(|date| local_time(zone, date))
+            };
+            let bytes = |key: &str| {
+                entry
+                    .get(key)
+                    .and_then(Plist::as_i64)
+                    .and_then(|bytes| u64::try_from(bytes).ok())
+            };
+            // Older versions of Safari keep paths with a `~`:
+            let path = text(entry, "DownloadEntryPath")
+                .filter(|path| !path.is_empty())
+                .map(|path| match (path.strip_prefix("~/"), &home) {
+                    (Some(rest), Some(home)) => home.join(rest),
+                    _ => PathBuf::from(path),
+                });
+            Some(Download {
+                id: text(entry, "DownloadEntryIdentifier")?.to_string(),
+                url: text(entry, "DownloadEntryURL")?.to_string(),
+                path,
+                // Unknown sizes are kept as -1:
+                size: bytes("DownloadEntryProgressTotalToLoad").filter(|&size| size > 0),
+                downloaded: bytes("DownloadEntryProgressBytesSoFar"),
+                started: date("DownloadEntryDateAddedKey"),
+                finished: date("DownloadEntryDateFinishedKey"),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,295 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads Safari's history from its database, `History.db`, through the
+//! `sqlite3` shell: every page visited, and when, and the pages visited
+//! most, as Safari ranks them.
+
+use super::{APPLE_EPOCH, HISTORY_URN_PREFIX, TOP_SITE_URN_PREFIX};
+use crate::{Error, Result, error::check_readable, exec, export::sha256_hex, timezone::LocalTime};
+use serde_json::{Value, json};
+use std::{
+    ffi::OsStr,
+    format,
+    path::Path,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Which pages to read: all of them, unless told otherwise.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Only the pages visited at or after this time, with only those
+    /// visits.
+    pub since: Option<LocalTime>,
+    /// Only the pages on any of these domains, or on their subdomains,
+    /// ignoring case.
+    pub domains: Vec<String>,
+}
+
+impl Filter {
+    /// Returns whether the page at `url` is on any of the domains, if
+    /// any are given.
+    pub fn matches(&self, url: &str) -> bool {
+        if self.domains.is_empty() {
+            return true;
+        }
+        let host = host(url).to_lowercase();
+        self.domains.iter().any(|domain| {
+            let domain = domain.trim_start_matches('.').to_lowercase();
+            host == domain
+                || host
+                    .strip_suffix(&domain)
+                    .is_some_and(|rest| rest.ends_with('.'))
+        })
+    }
+}
+
+/// A page in the history: a URL, and the times it was visited.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Page {
+    pub url: String,
+    /// The title it had when last visited, if it had one.
+    pub title: Option<String>,
+    /// How many times Safari counts it visited, ever.
+    pub visit_count: u64,
+    /// When it was visited, in local time, oldest first, of the visits
+    /// Safari still keeps and the filter selects.
+    pub visits: Vec<String>,
+}
+
+impl Page {
+    /// Returns the stable URN identifying this page, by the SHA-256 of
+    /// its URL, which is the same wherever it is visited.
+    pub fn urn(&self) -> String {
+        format!("{HISTORY_URN_PREFIX}{}", sha256_hex(self.url.as_bytes()))
+    }
+
+    /// Returns the JSON-LD record for this page: a schema.org `WebPage`,
+    /// with the times it was visited.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "WebPage",
+            "@id": self.urn(),
+            "additionalType": "historyItem",
+            "name": self.title,
+            "url": self.url,
+            "visitCount": self.visit_count,
+            "visits": self.visits,
+            "source": "apple-safari",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// One of the pages visited most, with its place in the ranking.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TopSite {
+    /// Its place, 1 for the page visited most.
+    pub position: usize,
+    pub url: String,
+    /// The title it had when last visited, if it had one.
+    pub title: Option<String>,
+    /// How many times Safari counts it visited, ever.
+    pub visit_count: u64,
+    /// When it was last visited, in local time.
+    pub last_visited: Option<String>,
+}
+
+impl TopSite {
+    /// Returns the stable URN identifying this page among the top sites,
+    /// by the SHA-256 of its URL.
+    pub fn urn(&self) -> String {
+        format!("{TOP_SITE_URN_PREFIX}{}", sha256_hex(self.url.as_bytes()))
+    }
+
+    /// Returns the JSON-LD record for this page: a schema.org `WebPage`,
+    /// with its place in the ranking.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "WebPage",
+            "@id": self.urn(),
+            "additionalType": "topSite",
+            "position": self.position,
+            "name": self.title,
+            "url": self.url,
+            "visitCount": self.visit_count,
+            "lastVisited": self.last_visited,
+            "source": "apple-safari",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Reads the pages `filter` selects in the database at `path`, by when
+/// they were first visited, oldest first. Reading it takes Full Disk
+/// Access, lacking which this fails, saying so.
+pub fn read(path: &Path, filter: &Filter) -> Result<Vec<Page>> {
+    let output = sqlite(path, &query(filter))?;
+    let pages = parse(&output)?;
+    Ok(pages
+        .into_iter()
+        .filter(|page| filter.matches(&page.url))
+        .collect())
+}
+
+/// Reads the `limit` pages visited most in the database at `path`, as
+/// Safari ranks them for its Frequently Visited sites, by how often and
+/// how lately they were visited, the first first.
+pub fn read_top_sites(path: &Path, limit: usize) -> Result<Vec<TopSite>> {
+    // Safari scores its pages since macOS 10.10, and they are ranked by
+    // their visits alone before:
+    let columns = sqlite(
+        path,
+        "SELECT name FROM pragma_table_info('history_items');\n",
+    )?;
+    let score = match columns.lines().any(|column| column == "visit_count_score") {
+        true => "i.visit_count_score DESC, ",
+        false => "",
+    };
+    let output = sqlite(
+        path,
+        &format!(
+            "SELECT coalesce(json_group_array(json(r)), '[]') FROM \
+             (SELECT json_object('url', i.url, 'visitCount', i.visit_count, \
+             'title', (SELECT v.title FROM history_visits v \
+             WHERE v.history_item = i.id AND v.title != '' ORDER BY v.visit_time DESC LIMIT 1), \
+             'lastVisited', (SELECT strftime('%Y-%m-%dT%H:%M:%S', max(v.visit_time) + {APPLE_EPOCH}, \
+             'unixepoch', 'localtime') FROM history_visits v WHERE v.history_item = i.id)) AS r \
+             FROM history_items i ORDER BY {score}i.visit_count DESC, i.id LIMIT {limit});\n"
+        ),
+    )?;
+    parse_top_sites(&output)
+}
+
+/// Runs `query` on the database at `path`, returning its output.
+fn sqlite(path: &Path, query: &str) -> Result<String> {
+    check_readable(path, "Safari history")?;
+    let output = exec::run(
+        "sqlite3",
+        [
+            OsStr::new("-readonly"),
+            OsStr::new("-bail"),
+            path.as_os_str(),
+        ],
+        query.as_bytes(),
+    )?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Returns the query that reads the pages as one JSON array, in the format
+/// [`parse`] reads. The times are applied by the database, and the domains
+/// by [`Filter::matches`].
+fn query(filter: &Filter) -> String {
+    // Local times, which `utc` turns into Unix time:
+    let visits = match &filter.since {
+        Some(since) => format!(
+            "v.visit_time >= CAST(strftime('%s', '{}', 'utc') AS INTEGER) - {APPLE_EPOCH}",
+            since.as_str()
+        ),
+        None => "1".to_string(),
+    };
+    let record = format!(
+        "json_object('url', i.url, 'visitCount', i.visit_count, \
+         'title', (SELECT v.title FROM history_visits v \
+         WHERE v.history_item = i.id AND v.title != '' ORDER BY v.visit_time DESC LIMIT 1), \
+         'visits', json((SELECT json_group_array(t) FROM \
+         (SELECT strftime('%Y-%m-%dT%H:%M:%S', v.visit_time + {APPLE_EPOCH}, 'unixepoch', \
+         'localtime') AS t FROM history_visits v \
+         WHERE v.history_item = i.id AND {visits} ORDER BY v.visit_time))))"
+    );
+    format!(
+        "SELECT coalesce(json_group_array(json(r)), '[]') FROM (SELECT {record} AS r, \
+         (SELECT min(v.visit_time) FROM history_visits v \
+         WHERE v.history_item = i.id AND {visits}) AS first \
+         FROM history_items i WHERE first IS NOT NULL ORDER BY first, i.id);\n"
+    )
+}
+
+/// Parses the output of the query, keeping the pages in order.
+pub fn parse(output: &str) -> Result<Vec<Page>> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Vec::new()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading Safari history",
+            message: e.to_string(),
+        })?,
+    };
+    Ok(output
+        .as_array()
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .filter_map(parse_page)
+        .collect())
+}
+
+fn parse_page(record: &Value) -> Option<Page> {
+    Some(Page {
+        url: text(record, "url")?,
+        title: text(record, "title"),
+        visit_count: record
+            .get("visitCount")
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+        visits: record
+            .get("visits")
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect(),
+    })
+}
+
+/// Parses the output of the query [`read_top_sites`] runs, numbering the
+/// pages in order.
+pub fn parse_top_sites(output: &str) -> Result<Vec<TopSite>> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Vec::new()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading Safari top sites",
+            message: e.to_string(),
+        })?,
+    };
+    let pages = output.as_array().map_or(&[][..], Vec::as_slice);
+    Ok(pages
+        .iter()
+        .filter_map(|record| {
+            let page = parse_page(record)?;
+            Some((page, text(record, "lastVisited")))
+        })
+        .enumerate()
+        .map(|(index, (page, last_visited))| TopSite {
+            position: index + 1,
+            url: page.url,
+            title: page.title,
+            visit_count: page.visit_count,
+            last_visited,
+        })
+        .collect())
+}
+
+/// Returns the host of `url`, without any port or user, as in
+/// `news.ycombinator.com`.
+fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    authority.split(':').next().unwrap_or_default()
+}
+
+fn text(record: &Value, key: &str) -> Option<String> {
+    record
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|text| !text.is_empty())
+        .map(String::from)
+}
@@ -0,0 +1,135 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads Safari's Reading List, which `Bookmarks.plist` keeps in a folder
+//! of its own, and the text of the pages Safari saved for reading offline,
+//! each in a web archive under `ReadingListArchives`.
+
+use super::{READING_LIST, READING_LIST_URN_PREFIX, children, local_time, read_plist, text};
+use crate::{Error, Result, bplist, bplist::Plist, timezone::TimeZone};
+use serde_json::{Value, json};
+use std::{
+    format, fs, io,
+    path::Path,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// A page in the Reading List.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// Safari's UUID for the page, which its web archive is named by.
+    pub id: String,
+    pub title: Option<String>,
+    pub url: String,
+    /// When it was added, in local time.
+    pub added: Option<String>,
+    /// When it was last read, in local time, unless it is unread.
+    pub viewed: Option<String>,
+    /// The first lines of the page, as Safari shows them in the list.
+    pub preview: Option<String>,
+    /// The text of the page as saved for reading offline, once
+    /// [`offline_text`] reads it.
+    pub text: Option<String>,
+}
+
+impl Entry {
+    /// Returns the stable URN identifying this page.
+    pub fn urn(&self) -> String {
+        format!("{READING_LIST_URN_PREFIX}{}", self.id)
+    }
+
+    /// Returns the JSON-LD record for this page: a schema.org `WebPage`.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "WebPage",
+            "@id": self.urn(),
+            "additionalType": "readingListItem",
+            "name": self.title,
+            "url": self.url,
+            "dateCreated": self.added,
+            "dateRead": self.viewed,
+            "unread": self.viewed.is_none(),
+            "description": self.preview,
+            "text": self.text,
+            "source": "apple-safari",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Reads the Reading List in the `Bookmarks.plist` at `path`, in the order
+/// Safari shows it. Reading it takes Full Disk Access, lacking which this
+/// fails, saying so.
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    let plist = read_plist(path, "Safari bookmarks")?;
+    Ok(parse(&plist, &TimeZone::local()))
+}
+
+/// Returns the pages in the Reading List in the property list.
+pub fn parse(plist: &Plist, zone: &TimeZone) -> Vec<Entry> {
+    let Some(folder) = children(plist)
+        .iter()
+        .find(|child| text(child, "Title") == Some(READING_LIST))
+    else {
+        return Vec::new();
+    };
+    children(folder)
+        .iter()
+        .filter_map(|item| {
+            let url = text(item, "URLString")?;
+            let list = item.get("ReadingList");
+            let date = |key: &str| {
+                list.and_then(|list| list.get(key))
+                    .and_then(Plist::as_f64)
+                    .map(|date| local_time(zone, date))
+            };
+            let title = item
+                .get("URIDictionary")
+                .and_then(|uri| text(uri, "title"))
+                .or_else(|| text(item, "Title"));
+            Some(Entry {
+                id: text(item, "WebBookmarkUUID")?.to_string(),
+                title: title.filter(|title| !title.is_empty()).map(String::from),
+                url: url.to_string(),
+                added: date("DateAdded"),
+                viewed: date("DateLastViewed"),
+                preview: list
+                    .and_then(|list| text(list, "PreviewText"))
+                    .map(str::trim)
+                    .filter(|preview| !preview.is_empty())
+                    .map(String::from),
+                text: None,
+            })
+        })
+        .collect()
+}
+
+/// Returns the text of the page with the given UUID as Safari saved it for
+/// reading offline, in `dir`, its `ReadingListArchives`, if it did.
+pub fn offline_text(dir: &Path, id: &str) -> Result<Option<String>> {
+    let path = dir.join(id).join("Page.webarchive");
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(Error::Io {
+                context: "reading a Reading List web archive",
+                source: e,
+            });
+        }
+    };
+    // A web archive is a property list of the page and what it loaded:
+    let Some(html) = bplist::parse(&data).and_then(|archive| {
+        let page = archive.get("WebMainResource")?;
+        Some(page.get("WebResourceData")?.as_data()?.to_vec())
+    }) else {
+        return Ok(None);
+    };
+    Ok(html2text::from_read(html.as_slice(), usize::MAX)
+        .ok()
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty()))
+}
@@ -0,0 +1,137 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Reads the tabs open in Safari, window by window, through its scripting
+//! dictionary, which is all it knows about.
+
+use super::TAB_URN_PREFIX;
+use crate::{Error, Result};
+use serde_json::{Value, json};
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// A tab open in a Safari window.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tab {
+    /// Safari's id for the window, which lasts as long as the window.
+    pub window_id: i64,
+    /// The window's place, 1 for the frontmost.
+    pub window: usize,
+    /// The tab's place in the window, 1 for the leftmost.
+    pub index: usize,
+    pub title: Option<String>,
+    /// What it shows, unless it is blank, as a new tab is.
+    pub url: Option<String>,
+    /// Whether it is the one its window shows.
+    pub current: bool,
+}
+
+impl Tab {
+    /// Returns the URN identifying this tab while its window is open: tabs
+    /// have no id of their own.
+    pub fn urn(&self) -> String {
+        format!("{TAB_URN_PREFIX}{}:{}", self.window_id, self.index)
+    }
+
+    /// Returns the JSON-LD record for this tab: a schema.org `WebPage`.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "@type": "WebPage",
+            "@id": self.urn(),
+            "additionalType": "openTab",
+            "name": self.title,
+            "url": self.url,
+            "windowIndex": self.window,
+            "tabIndex": self.index,
+            "currentTab": self.current,
+            "source": "apple-safari",
+        });
+        if let Some(record) = record.as_object_mut() {
+            record.retain(|_, value| !value.is_null());
+        }
+        record
+    }
+}
+
+/// Returns the script that lists every tab in every window, frontmost
+/// first, as JSON in the format [`parse`] reads. Safari isn't launched if
+/// it isn't running, as then no tabs are open.
+pub fn script() -> &'static str {
+    r#"
+var Safari = Application('Safari');
+var output = { windows: [] };
+if (Safari.running()) {
+    Safari.windows().forEach(function (window, index) {
+        // Windows such as Settings have no tabs:
+        var tabs, current = null;
+        try {
+            tabs = window.tabs();
+        } catch (e) {
+            return;
+        }
+        try {
+            current = window.currentTab().index();
+        } catch (e) {}
+        output.windows.push({
+            id: window.id(),
+            index: index + 1,
+            current: current,
+            tabs: tabs.map(function (tab) {
+                return { index: tab.index(), title: tab.name(), url: tab.url() };
+            }),
+        });
+    });
+}
+JSON.stringify(output);
+"#
+}
+
+/// Parses the output of [`script`], keeping the windows and tabs in order.
+pub fn parse(output: &str) -> Result<Vec<Tab>> {
+    let output: Value = match output.trim() {
+        "" => return Ok(Vec::new()),
+        output => serde_json::from_str(output).map_err(|e| Error::Parse {
+            context: "reading Safari tabs",
+            message: e.to_string(),
+        })?,
+    };
+    let mut tabs = Vec::new();
+    for window in list(&output, "windows") {
+        let number = |record: &Value, key: &str| record.get(key).and_then(Value::as_i64);
+        let Some(window_id) = number(window, "id") else {
+            continue;
+        };
+        let current = number(window, "current");
+        for tab in list(window, "tabs") {
+            let Some(index) = number(tab, "index") else {
+                continue;
+            };
+            tabs.push(Tab {
+                window_id,
+                window: number(window, "index").unwrap_or_default() as usize,
+                index: index as usize,
+                title: text(tab, "title"),
+                url: text(tab, "url"),
+                current: current == Some(index),
+            });
+        }
+    }
+    Ok(tabs)
+}
+
+fn text(record: &Value, key: &str) -> Option<String> {
+    record
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|text| !text.is_empty())
+        .map(String::from)
+}
+
+fn list<'a>(record: &'a Value, key: &str) -> &'a [Value] {
+    record
+        .get(key)
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+}
@@ -0,0 +1,131 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Least-privilege execution: once an emitter has read everything it
+//! needs from the target app, it can confine itself (and the programs its
+//! sink runs) with the macOS sandbox, so that the records it holds can
+//! only be written to stdout, to allow-listed directories, and (only if
+//! permitted) to the network. No further Apple Events can be sent either.
+
+use crate::Result;
+use std::{
+    format,
+    path::{Path, PathBuf},
+    string::String,
+    vec::Vec,
+};
+
+/// What a confined process may still do.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Policy {
+    /// Directories that may be written to, besides stdout and stderr.
+    pub writable: Vec<PathBuf>,
+    /// Whether network connections may be made.
+    pub network: bool,
+}
+
+impl Policy {
+    /// Returns whether writing to `path` is within one of the writable
+    /// directories. The file need not exist yet, but its directory must.
+    pub fn allows(&self, path: &Path) -> bool {
+        let Some(path) = canonicalize_new(path) else {
+            return false;
+        };
+        self.writable
+            .iter()
+            .filter_map(|dir| dir.canonicalize().ok())
+            .any(|dir| path.starts_with(dir))
+    }
+
+    /// Returns the Seatbelt profile enforcing this policy.
+    pub fn profile(&self) -> String {
+        let mut profile = String::from(
+            "(version 1)\n\
+             (allow default)\n\
+             (deny appleevent-send)\n\
+             (deny file-write*)\n\
+             (allow file-write*\n  \
+             (literal \"/dev/null\") (literal \"/dev/stdout\") (literal \"/dev/stderr\")\n  \
+             (literal \"/dev/tty\") (literal \"/dev/dtracehelper\")",
+        );
+        for dir in self
+            .writable
+            .iter()
+            .filter_map(|dir| dir.canonicalize().ok())
+        {
+            profile += &format!("\n  (subpath {})", quote(&dir.to_string_lossy()));
+        }
+        profile += ")\n";
+        if !self.network {
+            profile += "(deny network*)\n";
+        }
+        profile
+    }
+
+    /// Confines the rest of this process, and any programs it starts, to
+    /// this policy. There is no way back.
+    pub fn enter(&self) -> Result<()> {
+        #[cfg(target_os = "macos")]
+        return macos::apply(&self.profile());
+
+        #[cfg(not(target_os = "macos"))]
+        Err(crate::Error::Sandbox {
+            message: "least-privilege mode is only available on macOS".into(),
+        })
+    }
+}
+
+/// Canonicalizes a path that may not exist yet through its directory.
+fn canonicalize_new(path: &Path) -> Option<PathBuf> {
+    if let Ok(path) = path.canonicalize() {
+        return Some(path);
+    }
+    let name = path.file_name()?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Some(dir.canonicalize().ok()?.join(name))
+}
+
+/// Quotes `value` as an SBPL string literal.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(target_os = "macos")]
+#[allow(unsafe_code)]
+mod macos {
+    use crate::{Error, Result};
+    use core::ffi::{CStr, c_char, c_int};
+    use std::{ffi::CString, string::ToString};
+
+    unsafe extern "C" {
+        fn sandbox_init(profile: *const c_char, flags: u64, errorbuf: *mut *mut c_char) -> c_int;
+        fn sandbox_free_error(errorbuf: *mut c_char);
+    }
+
+    /// Applies a Seatbelt profile given as SBPL source.
+    pub fn apply(profile: &str) -> Result<()> {
+        let profile = CString::new(profile).map_err(|e| Error::Sandbox {
+            message: e.to_string(),
+        })?;
+        let mut error = core::ptr::null_mut();
+        // SAFETY: `profile` is a valid C string; without `SANDBOX_NAMED`
+        // it is taken as SBPL source, and any error message is freed below.
+        let status = unsafe { sandbox_init(profile.as_ptr(), 0, &mut error) };
+        if status == 0 {
+            return Ok(());
+        }
+        let message = if error.is_null() {
+            "sandbox_init failed".to_string()
+        } else {
+            // SAFETY: a non-null `error` is a C string owned by libsandbox.
+            let message = unsafe { CStr::from_ptr(error) }
+                .to_string_lossy()
+                .into_owned();
+            unsafe { sandbox_free_error(error) };
+            message
+        };
+        Err(Error::Sandbox { message })
+    }
+}
@@ -0,0 +1,2188 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Machine-readable descriptions of every record type this module emits,
+//! as JSON Schema for the JSON output and as SHACL shapes for RDF output.
+
+use crate::{
+    calendar, contacts, mail, messages, music, notes, photos,
+    provenance::{self, RUN_URN_PREFIX},
+    rdf::{PROV, SCHEMA, XSD},
+    reminders, safari,
+};
+use serde_json::{Map, Value, json};
+use std::{format, string::String, vec::Vec};
+
+/// The `$id` of the JSON Schema document.
+pub const SCHEMA_ID: &str = "https://asimov.so/schemas/apple-module.json";
+
+/// The namespace of the SHACL shapes.
+pub const SHAPES: &str = "https://asimov.so/shapes/apple#";
+
+/// What values a property takes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// A string.
+    String,
+    /// An array of strings.
+    Strings,
+    /// `true` or `false`.
+    Boolean,
+    /// A whole number.
+    Integer,
+    /// Any number.
+    Number,
+    /// An array of numbers.
+    Numbers,
+    /// A nested object, as described for the property.
+    Object,
+    /// An array of nested objects, as for [`Kind::Object`].
+    Objects,
+    /// An ISO 8601 date and time in local time, e.g. `2025-01-20T13:30:00`.
+    DateTime,
+    /// An ISO 8601 date, e.g. `2025-01-20`, or a date and time as above.
+    Date,
+    /// A URN starting with the given prefix.
+    Urn(&'static str),
+    /// The URN of another record, starting with the given prefix.
+    Reference(&'static str),
+    /// An array of URNs of other records, as for [`Kind::Reference`].
+    References(&'static str),
+    /// Always the given string.
+    Constant(&'static str),
+    /// The PROV-O activity of the run that emitted the record: inline, or
+    /// as `{ "@id": … }` referring to one emitted as a record of its own.
+    Activity,
+}
+
+/// A property of a record type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Property {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+/// A type of record emitted by some program of this module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordType {
+    /// The name of the JSON Schema definition and the SHACL shape.
+    pub name: &'static str,
+    /// The schema.org type in `@type`, or with `prov:`, the PROV-O one.
+    pub class: &'static str,
+    /// Whether every resource of `class` must conform to this shape, as
+    /// opposed to only those a consumer chooses to validate.
+    pub targets_class: bool,
+    pub description: &'static str,
+    pub properties: &'static [Property],
+}
+
+const fn property(
+    name: &'static str,
+    kind: Kind,
+    required: bool,
+    description: &'static str,
+) -> Property {
+    Property {
+        name,
+        kind,
+        required,
+        description,
+    }
+}
+
+/// The class of the activity records `--provenance activity` emits.
+const ACTIVITY_CLASS: &str = provenance::ACTIVITY_TYPE;
+
+/// The properties every record type but the activity may have, as the
+/// sink options add them.
+const COMMON_PROPERTIES: &[Property] = &[
+    property(
+        "prov:wasGeneratedBy",
+        Kind::Activity,
+        false,
+        "With --provenance, the run that emitted the record: the activity inline, or with --provenance activity, its @id",
+    ),
+    property(
+        "embedding",
+        Kind::Numbers,
+        false,
+        "With --embed-cmd or --embed-url, the vector embedding of the record's text",
+    ),
+];
+
+/// Every record type, in the order they are documented.
+pub const RECORD_TYPES: &[RecordType] = &[
+    RecordType {
+        name: "Note",
+        class: "CreativeWork",
+        targets_class: true,
+        description: "A note, as emitted by the notes emitter and fetcher",
+        properties: &[
+            property("@id", Kind::Urn(notes::URN_PREFIX), true, "The note's URN"),
+            property("name", Kind::String, true, "The note's title"),
+            property("text", Kind::String, true, "The note's body as plain text"),
+            property(
+                "dateCreated",
+                Kind::DateTime,
+                true,
+                "When the note was created",
+            ),
+            property(
+                "dateModified",
+                Kind::DateTime,
+                true,
+                "When the note was last modified",
+            ),
+            property(
+                "isPartOf",
+                Kind::String,
+                true,
+                "The name of the note's folder",
+            ),
+            property(
+                "account",
+                Kind::String,
+                true,
+                "The name of the note's account",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-notes"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "NoteCatalogEntry",
+        class: "CreativeWork",
+        targets_class: false,
+        description: "A note without its body, as emitted by the notes cataloger",
+        properties: &[
+            property("@id", Kind::Urn(notes::URN_PREFIX), true, "The note's URN"),
+            property("name", Kind::String, true, "The note's title"),
+            property(
+                "dateCreated",
+                Kind::DateTime,
+                true,
+                "When the note was created",
+            ),
+            property(
+                "dateModified",
+                Kind::DateTime,
+                true,
+                "When the note was last modified",
+            ),
+        ],
+    },
+    RecordType {
+        name: "Folder",
+        class: "Collection",
+        targets_class: true,
+        description: "A Notes folder",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn("urn:apple:notes:folder:"),
+                true,
+                "The folder's URN",
+            ),
+            property("name", Kind::String, true, "The folder's name"),
+            property(
+                "account",
+                Kind::String,
+                true,
+                "The name of the folder's account",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-notes"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "Reminder",
+        class: "Action",
+        targets_class: true,
+        description: "A reminder, as emitted by the reminders emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(reminders::URN_PREFIX),
+                true,
+                "The reminder's URN",
+            ),
+            property("name", Kind::String, true, "The reminder's title"),
+            property("description", Kind::String, false, "The reminder's notes"),
+            property(
+                "scheduledTime",
+                Kind::Date,
+                false,
+                "When the reminder is due: a date, or a date and time",
+            ),
+            property(
+                "priority",
+                Kind::String,
+                false,
+                "The reminder's priority: high, medium, or low",
+            ),
+            property(
+                "flagged",
+                Kind::Boolean,
+                false,
+                "Whether the reminder is flagged",
+            ),
+            property(
+                "keywords",
+                Kind::Strings,
+                false,
+                "The reminder's tags, without the leading #",
+            ),
+            property(
+                "url",
+                Kind::String,
+                false,
+                "The URL attached to the reminder",
+            ),
+            property(
+                "actionStatus",
+                Kind::String,
+                true,
+                "CompletedActionStatus or PotentialActionStatus",
+            ),
+            property(
+                "endTime",
+                Kind::DateTime,
+                false,
+                "When the reminder was completed",
+            ),
+            property(
+                "isPartOf",
+                Kind::String,
+                true,
+                "The name of the reminder's list",
+            ),
+            property(
+                "account",
+                Kind::String,
+                true,
+                "The name of the list's account",
+            ),
+            property(
+                "dateCreated",
+                Kind::DateTime,
+                false,
+                "When the reminder was created",
+            ),
+            property(
+                "dateModified",
+                Kind::DateTime,
+                false,
+                "When the reminder was last modified",
+            ),
+            property(
+                "geoTrigger",
+                Kind::Object,
+                false,
+                "Where a location-based reminder goes off: a Place with name, latitude, longitude, radius (in meters), and proximity (arrive or leave)",
+            ),
+            property(
+                "recurrenceRule",
+                Kind::String,
+                false,
+                "How the reminder recurs, as an iCalendar RRULE",
+            ),
+            property(
+                "occurrenceOf",
+                Kind::Reference(reminders::URN_PREFIX),
+                false,
+                "On an expanded occurrence, the URN of the recurring reminder",
+            ),
+            property(
+                "parentId",
+                Kind::Reference(reminders::URN_PREFIX),
+                false,
+                "The URN of the reminder this one is a subtask of",
+            ),
+            property(
+                "subtasks",
+                Kind::References(reminders::URN_PREFIX),
+                false,
+                "The URNs of the reminder's subtasks, in order",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-reminders"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "ReminderList",
+        class: "ItemList",
+        targets_class: true,
+        description: "A Reminders list",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(reminders::LIST_URN_PREFIX),
+                true,
+                "The list's URN",
+            ),
+            property("name", Kind::String, true, "The list's name"),
+            property(
+                "color",
+                Kind::String,
+                false,
+                "The list's color, as in #FF9500",
+            ),
+            property(
+                "account",
+                Kind::String,
+                true,
+                "The name of the list's account",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-reminders"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "Event",
+        class: "Event",
+        targets_class: true,
+        description: "A calendar event, or an occurrence of a recurring one, as emitted by the calendar emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(calendar::URN_PREFIX),
+                true,
+                "The event's URN; an occurrence's ends in /<ORIGINAL START>",
+            ),
+            property("name", Kind::String, true, "The event's title"),
+            property("description", Kind::String, false, "The event's notes"),
+            property(
+                "startDate",
+                Kind::Date,
+                false,
+                "When the event starts, with the offset of its time zone, or the date an all-day event starts on",
+            ),
+            property(
+                "endDate",
+                Kind::Date,
+                false,
+                "When the event ends, with the offset of its time zone, or the last date an all-day event spans",
+            ),
+            property(
+                "timeZone",
+                Kind::String,
+                false,
+                "The IANA name of the event's time zone, absent if it floats",
+            ),
+            property("allDay", Kind::Boolean, false, "true for all-day events"),
+            property(
+                "location",
+                Kind::Object,
+                false,
+                "Where the event takes place: a Place with name, and latitude, longitude, and radius (in meters) when known",
+            ),
+            property("url", Kind::String, false, "The URL attached to the event"),
+            property(
+                "eventStatus",
+                Kind::String,
+                true,
+                "EventScheduled or EventCancelled",
+            ),
+            property(
+                "tentative",
+                Kind::Boolean,
+                false,
+                "true for events not yet confirmed",
+            ),
+            property(
+                "organizer",
+                Kind::Object,
+                false,
+                "Who organizes the event: a Person with name, email, participationStatus, and role",
+            ),
+            property(
+                "attendee",
+                Kind::Objects,
+                false,
+                "Who is invited: Persons with name, email, participationStatus, and role",
+            ),
+            property(
+                "alarm",
+                Kind::Objects,
+                false,
+                "When the event notifies: objects with an offset (an ISO 8601 duration) or time, and alarmType",
+            ),
+            property(
+                "recurrenceRule",
+                Kind::String,
+                false,
+                "How a recurring event recurs, as an iCalendar RRULE",
+            ),
+            property(
+                "exceptionDates",
+                Kind::Strings,
+                false,
+                "When in the window a recurring event was to occur, but doesn't",
+            ),
+            property(
+                "recurrenceOf",
+                Kind::Reference(calendar::URN_PREFIX),
+                false,
+                "On an occurrence, the URN of the recurring event",
+            ),
+            property(
+                "isPartOf",
+                Kind::String,
+                true,
+                "The name of the event's calendar",
+            ),
+            property(
+                "account",
+                Kind::String,
+                true,
+                "The name of the calendar's account",
+            ),
+            property(
+                "dateCreated",
+                Kind::DateTime,
+                false,
+                "When the event was created",
+            ),
+            property(
+                "dateModified",
+                Kind::DateTime,
+                false,
+                "When the event was last modified",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-calendar"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "Calendar",
+        class: "DataFeed",
+        targets_class: true,
+        description: "A calendar, as emitted by the calendar emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(calendar::CALENDAR_URN_PREFIX),
+                true,
+                "The calendar's URN",
+            ),
+            property("name", Kind::String, true, "The calendar's name"),
+            property(
+                "color",
+                Kind::String,
+                false,
+                "The calendar's color, as in #1BADF8",
+            ),
+            property(
+                "account",
+                Kind::String,
+                true,
+                "The name of the calendar's account",
+            ),
+            property(
+                "calendarType",
+                Kind::String,
+                true,
+                "local, caldav, exchange, subscription, or birthday",
+            ),
+            property(
+                "readOnly",
+                Kind::Boolean,
+                false,
+                "true for calendars whose events can't be changed",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-calendar"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "BusyInterval",
+        class: "Schedule",
+        targets_class: true,
+        description: "A stretch of time taken up by events, as emitted by calendar freebusy",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(calendar::BUSY_URN_PREFIX),
+                true,
+                "The interval's URN",
+            ),
+            property(
+                "startDate",
+                Kind::DateTime,
+                true,
+                "When the interval starts, in UTC",
+            ),
+            property(
+                "endDate",
+                Kind::DateTime,
+                true,
+                "When the interval ends, in UTC",
+            ),
+            property(
+                "isPartOf",
+                Kind::String,
+                false,
+                "With --per-calendar, the name of the calendar",
+            ),
+            property(
+                "account",
+                Kind::String,
+                false,
+                "With --per-calendar, the name of the calendar's account",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-calendar"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "Contact",
+        class: "Person",
+        targets_class: true,
+        description: "A person's contact card, as emitted by the contacts emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(contacts::URN_PREFIX),
+                true,
+                "The contact's URN",
+            ),
+            property(
+                "name",
+                Kind::String,
+                true,
+                "The full name, or else the organization or first email address",
+            ),
+            property(
+                "honorificPrefix",
+                Kind::String,
+                false,
+                "The title before the name, as in Dr.",
+            ),
+            property("givenName", Kind::String, false, "The first name"),
+            property("additionalName", Kind::String, false, "The middle name"),
+            property("familyName", Kind::String, false, "The last name"),
+            property(
+                "honorificSuffix",
+                Kind::String,
+                false,
+                "The suffix after the name, as in Jr.",
+            ),
+            property("alternateName", Kind::String, false, "The nickname"),
+            property(
+                "worksFor",
+                Kind::Object,
+                false,
+                "The company: an Organization with name, and department",
+            ),
+            property("jobTitle", Kind::String, false, "The job title"),
+            property(
+                "birthDate",
+                Kind::String,
+                false,
+                "The birthday, as in 1815-12-10, or --12-10 without a year",
+            ),
+            property(
+                "contactPoint",
+                Kind::Objects,
+                false,
+                "The phone numbers, email addresses, and web pages: ContactPoints with telephone, email, or url, and contactType, the label (as in mobile or work)",
+            ),
+            property(
+                "address",
+                Kind::Objects,
+                false,
+                "The postal addresses: PostalAddresses with streetAddress, addressLocality, addressRegion, postalCode, addressCountry, and contactType, the label",
+            ),
+            property(
+                "sameAs",
+                Kind::Strings,
+                false,
+                "The URLs of the contact's social profiles",
+            ),
+            property(
+                "socialProfile",
+                Kind::Objects,
+                false,
+                "The social profiles: PropertyValues with propertyID, the network (as in Twitter), value, the user name, identifier, the network's id for the account, and url",
+            ),
+            property(
+                "instantMessage",
+                Kind::Objects,
+                false,
+                "The instant messaging handles: PropertyValues with propertyID, the service (as in Skype), value, the handle, and contactType, the label",
+            ),
+            property(
+                "relatedTo",
+                Kind::Objects,
+                false,
+                "The related names: Persons with name and contactType, how they are related (as in spouse or assistant)",
+            ),
+            property("description", Kind::String, false, "The contact's note"),
+            property(
+                "image",
+                Kind::Object,
+                false,
+                "The contact's photo, with --photos-dir, --attachments, or --output-format vcard: an ImageObject with contentUrl, encodingFormat, and sha256",
+            ),
+            property(
+                "thumbnail",
+                Kind::Object,
+                false,
+                "The photo's small, square version, as for image",
+            ),
+            property(
+                "memberOf",
+                Kind::References(contacts::GROUP_URN_PREFIX),
+                false,
+                "The URNs of the groups the contact is in",
+            ),
+            property(
+                "account",
+                Kind::String,
+                true,
+                "The name of the contact's account",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-contacts"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "OrganizationContact",
+        class: "Organization",
+        targets_class: true,
+        description: "A company's contact card, as emitted by the contacts emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(contacts::URN_PREFIX),
+                true,
+                "The contact's URN",
+            ),
+            property("name", Kind::String, true, "The company's name"),
+            property("alternateName", Kind::String, false, "The nickname"),
+            property(
+                "department",
+                Kind::Object,
+                false,
+                "The department: an Organization with a name",
+            ),
+            property(
+                "contactPoint",
+                Kind::Objects,
+                false,
+                "The phone numbers, email addresses, and web pages: ContactPoints with telephone, email, or url, and contactType, the label (as in mobile or work)",
+            ),
+            property(
+                "address",
+                Kind::Objects,
+                false,
+                "The postal addresses: PostalAddresses with streetAddress, addressLocality, addressRegion, postalCode, addressCountry, and contactType, the label",
+            ),
+            property(
+                "sameAs",
+                Kind::Strings,
+                false,
+                "The URLs of the contact's social profiles",
+            ),
+            property(
+                "socialProfile",
+                Kind::Objects,
+                false,
+                "The social profiles: PropertyValues with propertyID, the network (as in Twitter), value, the user name, identifier, the network's id for the account, and url",
+            ),
+            property(
+                "instantMessage",
+                Kind::Objects,
+                false,
+                "The instant messaging handles: PropertyValues with propertyID, the service (as in Skype), value, the handle, and contactType, the label",
+            ),
+            property(
+                "relatedTo",
+                Kind::Objects,
+                false,
+                "The related names: Persons with name and contactType, how they are related (as in spouse or assistant)",
+            ),
+            property("description", Kind::String, false, "The contact's note"),
+            property(
+                "image",
+                Kind::Object,
+                false,
+                "The contact's photo, with --photos-dir, --attachments, or --output-format vcard: an ImageObject with contentUrl, encodingFormat, and sha256",
+            ),
+            property(
+                "thumbnail",
+                Kind::Object,
+                false,
+                "The photo's small, square version, as for image",
+            ),
+            property(
+                "memberOf",
+                Kind::References(contacts::GROUP_URN_PREFIX),
+                false,
+                "The URNs of the groups the contact is in",
+            ),
+            property(
+                "account",
+                Kind::String,
+                true,
+                "The name of the contact's account",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-contacts"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "ContactGroup",
+        class: "ItemList",
+        targets_class: false,
+        description: "A group of contacts, as emitted by the contacts emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(contacts::GROUP_URN_PREFIX),
+                true,
+                "The group's URN",
+            ),
+            property("name", Kind::String, true, "The group's name"),
+            property(
+                "numberOfItems",
+                Kind::Integer,
+                true,
+                "How many contacts are in the group",
+            ),
+            property(
+                "account",
+                Kind::String,
+                true,
+                "The name of the group's account",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-contacts"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "DuplicateContacts",
+        class: "ItemList",
+        targets_class: false,
+        description: "Contacts that are likely duplicates, as emitted by contacts dedupe-report",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(contacts::DUPLICATES_URN_PREFIX),
+                true,
+                "The set's URN, from the contacts' ids",
+            ),
+            property("name", Kind::String, true, "The first contact's name"),
+            property(
+                "itemListElement",
+                Kind::References(contacts::URN_PREFIX),
+                true,
+                "The URNs of the contacts",
+            ),
+            property(
+                "numberOfItems",
+                Kind::Integer,
+                true,
+                "How many contacts are in the set",
+            ),
+            property(
+                "confidence",
+                Kind::Number,
+                true,
+                "How likely, from 0 to 1, the contacts are the same: that of the weakest match linking them",
+            ),
+            property(
+                "matchedOn",
+                Kind::Strings,
+                true,
+                "What matched: name, email, or phone",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-contacts"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "PhotoAlbum",
+        class: "Collection",
+        targets_class: false,
+        description: "A photo album or smart album, as emitted by the photos emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(photos::ALBUM_URN_PREFIX),
+                true,
+                "The album's URN",
+            ),
+            property("name", Kind::String, true, "The album's name"),
+            property("additionalType", Kind::String, true, "album, or smartAlbum"),
+            property(
+                "criteria",
+                Kind::String,
+                false,
+                "What a smart album Photos provides shows, as in favorites",
+            ),
+            property(
+                "numberOfItems",
+                Kind::Integer,
+                true,
+                "How many photos and videos are in the album",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-photos"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "PhotoMemory",
+        class: "Collection",
+        targets_class: false,
+        description: "A memory Photos made, as emitted by the photos emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(photos::MEMORY_URN_PREFIX),
+                true,
+                "The memory's URN",
+            ),
+            property("name", Kind::String, false, "The memory's title"),
+            property(
+                "additionalType",
+                Kind::Constant("memory"),
+                true,
+                "What kind of collection it is",
+            ),
+            property(
+                "temporalCoverage",
+                Kind::String,
+                false,
+                "When its first and last photos were taken, as in 2024-06-01T10:00:00/2024-06-03T18:30:00",
+            ),
+            property(
+                "image",
+                Kind::References(photos::URN_PREFIX),
+                false,
+                "The URNs of the photos Photos shows it with",
+            ),
+            property(
+                "hasPart",
+                Kind::References(photos::URN_PREFIX),
+                true,
+                "The URNs of its photos and videos",
+            ),
+            property(
+                "numberOfItems",
+                Kind::Integer,
+                true,
+                "How many photos and videos are in it",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-photos"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "PhotoPerson",
+        class: "Person",
+        targets_class: false,
+        description: "A person Photos recognizes, as emitted by the photos emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(photos::PERSON_URN_PREFIX),
+                true,
+                "The person's URN",
+            ),
+            property("name", Kind::String, true, "The name given to them"),
+            property(
+                "source",
+                Kind::Constant("apple-photos"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "Photo",
+        class: "ImageObject",
+        targets_class: false,
+        description: "A photo, as emitted by the photos emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(photos::URN_PREFIX),
+                true,
+                "The asset's URN",
+            ),
+            property(
+                "name",
+                Kind::String,
+                false,
+                "The name of the file it was imported from",
+            ),
+            property(
+                "additionalType",
+                Kind::String,
+                true,
+                "The media type: image, video, or audio",
+            ),
+            property("dateCreated", Kind::DateTime, false, "When it was taken"),
+            property(
+                "dateModified",
+                Kind::DateTime,
+                false,
+                "When it was last changed",
+            ),
+            property("width", Kind::Integer, true, "Its width, in pixels"),
+            property("height", Kind::Integer, true, "Its height, in pixels"),
+            property(
+                "isPartOf",
+                Kind::References(photos::ALBUM_URN_PREFIX),
+                false,
+                "The URNs of the albums it is in",
+            ),
+            property(
+                "about",
+                Kind::References(photos::PERSON_URN_PREFIX),
+                false,
+                "The URNs of the named people it shows",
+            ),
+            property(
+                "contentUrl",
+                Kind::String,
+                false,
+                "The file: URL of its exported file, with --export-dir",
+            ),
+            property(
+                "keywords",
+                Kind::Strings,
+                false,
+                "Its keywords, as printed by photos tag and photos caption",
+            ),
+            property(
+                "caption",
+                Kind::String,
+                false,
+                "Its caption, as printed by photos tag and photos caption",
+            ),
+            property(
+                "exifData",
+                Kind::Objects,
+                false,
+                "The camera's make, model, lensModel, exposureTime, fNumber, isoSpeed, and focalLength, each a PropertyValue",
+            ),
+            property(
+                "geo",
+                Kind::Object,
+                false,
+                "Where it was taken: a GeoCoordinates with latitude, longitude, and elevation",
+            ),
+            property(
+                "associatedMedia",
+                Kind::Objects,
+                false,
+                "For a Live Photo, its video: a VideoObject with name, additionalType pairedVideo, and with --export-dir, contentUrl",
+            ),
+            property(
+                "hasPart",
+                Kind::Objects,
+                false,
+                "For the photo a burst is shown as, the burst's other photos: each an ImageObject with @id, name, and dateCreated",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-photos"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "Video",
+        class: "VideoObject",
+        targets_class: true,
+        description: "A video, as emitted by the photos emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(photos::URN_PREFIX),
+                true,
+                "The asset's URN",
+            ),
+            property(
+                "name",
+                Kind::String,
+                false,
+                "The name of the file it was imported from",
+            ),
+            property(
+                "additionalType",
+                Kind::String,
+                true,
+                "The media type: image, video, or audio",
+            ),
+            property("dateCreated", Kind::DateTime, false, "When it was taken"),
+            property(
+                "dateModified",
+                Kind::DateTime,
+                false,
+                "When it was last changed",
+            ),
+            property("width", Kind::Integer, true, "Its width, in pixels"),
+            property("height", Kind::Integer, true, "Its height, in pixels"),
+            property(
+                "duration",
+                Kind::String,
+                true,
+                "How long the video lasts, as an ISO 8601 duration",
+            ),
+            property(
+                "isPartOf",
+                Kind::References(photos::ALBUM_URN_PREFIX),
+                false,
+                "The URNs of the albums it is in",
+            ),
+            property(
+                "about",
+                Kind::References(photos::PERSON_URN_PREFIX),
+                false,
+                "The URNs of the named people it shows",
+            ),
+            property(
+                "contentUrl",
+                Kind::String,
+                false,
+                "The file: URL of its exported file, with --export-dir",
+            ),
+            property(
+                "keywords",
+                Kind::Strings,
+                false,
+                "Its keywords, as printed by photos tag and photos caption",
+            ),
+            property(
+                "caption",
+                Kind::String,
+                false,
+                "Its caption, as printed by photos tag and photos caption",
+            ),
+            property(
+                "geo",
+                Kind::Object,
+                false,
+                "Where it was taken: a GeoCoordinates with latitude, longitude, and elevation",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-photos"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "MailRule",
+        class: "Thing",
+        targets_class: false,
+        description: "A rule Mail applies to messages as they arrive, as emitted by mail rules",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(mail::RULE_URN_PREFIX),
+                true,
+                "The rule's URN, by its name",
+            ),
+            property(
+                "additionalType",
+                Kind::Constant("rule"),
+                true,
+                "What kind of thing it is",
+            ),
+            property("name", Kind::String, true, "The rule's name"),
+            property(
+                "position",
+                Kind::Integer,
+                true,
+                "Where it comes in the order rules are applied in, from 1",
+            ),
+            property("enabled", Kind::Boolean, true, "Whether it is applied"),
+            property(
+                "match",
+                Kind::String,
+                true,
+                "all, if a message must meet every condition, or any",
+            ),
+            property(
+                "conditions",
+                Kind::Objects,
+                true,
+                "What it looks for: objects with field, qualifier, expression, and header",
+            ),
+            property(
+                "actions",
+                Kind::Objects,
+                true,
+                "What it does: objects with type, as in move or markRead, and value",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-mail"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "MailSmartMailbox",
+        class: "Collection",
+        targets_class: false,
+        description: "A smart mailbox, as emitted by mail rules",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(mail::SMART_MAILBOX_URN_PREFIX),
+                true,
+                "The smart mailbox's URN",
+            ),
+            property(
+                "additionalType",
+                Kind::Constant("smartMailbox"),
+                true,
+                "What kind of collection it is",
+            ),
+            property("name", Kind::String, true, "The smart mailbox's name"),
+            property(
+                "match",
+                Kind::String,
+                true,
+                "all, if a message must meet every condition, or any",
+            ),
+            property(
+                "conditions",
+                Kind::Objects,
+                true,
+                "What its messages meet: objects with field, qualifier, and expression",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-mail"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "EmailThread",
+        class: "Conversation",
+        targets_class: true,
+        description: "A conversation, as emitted by the mail emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(mail::THREAD_URN_PREFIX),
+                true,
+                "The conversation's URN, by the Message-ID it started with",
+            ),
+            property(
+                "identifier",
+                Kind::String,
+                true,
+                "The Message-ID it started with, without the angle brackets",
+            ),
+            property(
+                "name",
+                Kind::String,
+                false,
+                "The subject of its first message, without Re: and the like",
+            ),
+            property(
+                "temporalCoverage",
+                Kind::String,
+                false,
+                "When its first and last messages were received, as in 2025-02-13T10:00:00/2025-02-14T09:30:00",
+            ),
+            property(
+                "hasPart",
+                Kind::References(mail::URN_PREFIX),
+                true,
+                "The URNs of its messages, oldest first",
+            ),
+            property(
+                "numberOfItems",
+                Kind::Integer,
+                true,
+                "How many messages are in it",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-mail"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "EmailMessage",
+        class: "EmailMessage",
+        targets_class: true,
+        description: "A message, as emitted by the mail emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(mail::URN_PREFIX),
+                true,
+                "The message's URN",
+            ),
+            property(
+                "identifier",
+                Kind::String,
+                false,
+                "Its Message-ID header, without the angle brackets",
+            ),
+            property("name", Kind::String, false, "The message's subject"),
+            property(
+                "sender",
+                Kind::Object,
+                false,
+                "Who sent it: a Person with name and email",
+            ),
+            property(
+                "toRecipient",
+                Kind::Objects,
+                false,
+                "Who it was sent to: Persons with name and email",
+            ),
+            property(
+                "ccRecipient",
+                Kind::Objects,
+                false,
+                "Who it was copied to, likewise",
+            ),
+            property(
+                "bccRecipient",
+                Kind::Objects,
+                false,
+                "Who it was blind copied to, likewise",
+            ),
+            property("dateSent", Kind::DateTime, false, "When it was sent"),
+            property(
+                "dateReceived",
+                Kind::DateTime,
+                false,
+                "When it was received",
+            ),
+            property(
+                "isPartOf",
+                Kind::String,
+                true,
+                "The name of the message's mailbox",
+            ),
+            property(
+                "account",
+                Kind::String,
+                false,
+                "The name of the mailbox's account; none for mailboxes On My Mac",
+            ),
+            property(
+                "abstract",
+                Kind::String,
+                false,
+                "The start of its body, on one line",
+            ),
+            property("text", Kind::String, false, "Its body as plain text"),
+            property(
+                "messageAttachment",
+                Kind::Objects,
+                false,
+                "Its attachments: MediaObjects with name, encodingFormat, contentSize, sha256, and contentUrl",
+            ),
+            property(
+                "archivedAt",
+                Kind::String,
+                false,
+                "Where the message as it came was written, with --output-format eml-dir or --attachments",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-mail"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "TextChat",
+        class: "Conversation",
+        targets_class: false,
+        description: "A chat, of two or of a group, as emitted by the messages emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(messages::CHAT_URN_PREFIX),
+                true,
+                "The chat's URN, by its GUID",
+            ),
+            property(
+                "identifier",
+                Kind::String,
+                false,
+                "Its identifier, a handle, or as in chat123456789 for a group",
+            ),
+            property("name", Kind::String, false, "The name the group was given"),
+            property(
+                "service",
+                Kind::String,
+                false,
+                "How its messages are sent: iMessage, SMS, or RCS",
+            ),
+            property(
+                "groupChat",
+                Kind::Boolean,
+                true,
+                "Whether it is a group chat, rather than a chat of two",
+            ),
+            property(
+                "participant",
+                Kind::Objects,
+                true,
+                "Everyone in it but the user, as Persons, like a message's sender",
+            ),
+            property(
+                "numberOfItems",
+                Kind::Integer,
+                true,
+                "How many messages are in it",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-messages"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "TextMessage",
+        class: "Message",
+        targets_class: true,
+        description: "A message, as emitted by the messages emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(messages::URN_PREFIX),
+                true,
+                "The message's URN, by its GUID",
+            ),
+            property("text", Kind::String, false, "What it says"),
+            property(
+                "sender",
+                Kind::Object,
+                false,
+                "Who sent it, if not the user: a Person with identifier, the handle, telephone or email, and name and sameAs, the contact's URN, if resolved",
+            ),
+            property(
+                "recipient",
+                Kind::Object,
+                false,
+                "Who the user sent it to, in a chat of two, likewise",
+            ),
+            property("fromMe", Kind::Boolean, true, "Whether the user sent it"),
+            property(
+                "isPartOf",
+                Kind::Reference(messages::CHAT_URN_PREFIX),
+                false,
+                "The chat it is in",
+            ),
+            property(
+                "parentItem",
+                Kind::Reference(messages::URN_PREFIX),
+                false,
+                "The message it replies to inline, the first of its thread",
+            ),
+            property(
+                "service",
+                Kind::String,
+                false,
+                "How it was sent: iMessage, SMS, or RCS",
+            ),
+            property("dateSent", Kind::DateTime, false, "When it was sent"),
+            property("dateRead", Kind::DateTime, false, "When it was read"),
+            property("read", Kind::Boolean, true, "Whether it was read"),
+            property(
+                "messageAttachment",
+                Kind::References(messages::ATTACHMENT_URN_PREFIX),
+                false,
+                "The files attached to it, in order",
+            ),
+            property(
+                "reaction",
+                Kind::String,
+                false,
+                "The tapback it is: love, like, dislike, laugh, emphasize, question, sticker, or an emoji",
+            ),
+            property(
+                "reactionTo",
+                Kind::Reference(messages::URN_PREFIX),
+                false,
+                "The message the tapback reacts to",
+            ),
+            property(
+                "reactionRemoved",
+                Kind::Boolean,
+                false,
+                "Whether the tapback takes the reaction back",
+            ),
+            property(
+                "editHistory",
+                Kind::Objects,
+                false,
+                "Its text as sent and as edited since, oldest first: objects with text and dateModified",
+            ),
+            property(
+                "dateModified",
+                Kind::DateTime,
+                false,
+                "When it was last edited or unsent",
+            ),
+            property(
+                "unsent",
+                Kind::Boolean,
+                false,
+                "Whether it was unsent, in part or in full",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-messages"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "TextMessageAttachment",
+        class: "MediaObject",
+        targets_class: true,
+        description: "A file attached to a message, as emitted by the messages emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(messages::ATTACHMENT_URN_PREFIX),
+                true,
+                "The attachment's URN, by its GUID",
+            ),
+            property("name", Kind::String, false, "Its file name as sent"),
+            property("encodingFormat", Kind::String, false, "Its MIME type"),
+            property("contentSize", Kind::Integer, false, "Its size in bytes"),
+            property(
+                "path",
+                Kind::String,
+                false,
+                "Where its file is, under ~/Library/Messages/Attachments",
+            ),
+            property(
+                "transferState",
+                Kind::Integer,
+                false,
+                "How far its transfer got, in Messages' terms: 5 once the file is all there",
+            ),
+            property(
+                "isPartOf",
+                Kind::Reference(messages::URN_PREFIX),
+                false,
+                "The message it is attached to",
+            ),
+            property(
+                "sha256",
+                Kind::String,
+                false,
+                "The SHA-256 of its file, if copied",
+            ),
+            property(
+                "contentUrl",
+                Kind::String,
+                false,
+                "Where its file was copied to",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-messages"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "MusicTrack",
+        class: "MusicRecording",
+        targets_class: true,
+        description: "A track in the Music library, as emitted by the music emitter",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(music::URN_PREFIX),
+                true,
+                "The track's URN, by Music's persistent ID for it",
+            ),
+            property("name", Kind::String, true, "The track's title"),
+            property(
+                "byArtist",
+                Kind::Object,
+                false,
+                "Its artist, a MusicGroup with a name",
+            ),
+            property(
+                "inAlbum",
+                Kind::Object,
+                false,
+                "Its album, a MusicAlbum with a name, and byArtist, the album's artist",
+            ),
+            property("genre", Kind::String, false, "Its genre"),
+            property(
+                "duration",
+                Kind::String,
+                false,
+                "How long it lasts, as an ISO 8601 duration",
+            ),
+            property("datePublished", Kind::String, false, "Its year"),
+            property("trackNumber", Kind::Integer, false, "Its place on its disc"),
+            property("discNumber", Kind::Integer, false, "Its disc in its album"),
+            property(
+                "dateCreated",
+                Kind::DateTime,
+                false,
+                "When it was added to the library",
+            ),
+            property(
+                "path",
+                Kind::String,
+                false,
+                "Where its file is, if it has one on this Mac",
+            ),
+            property(
+                "cloudStatus",
+                Kind::String,
+                false,
+                "How it is in iCloud, as Music says: matched, purchased, uploaded, subscription, and so on",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-music"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "SafariBookmark",
+        class: "WebPage",
+        targets_class: true,
+        description: "A Safari bookmark, as emitted by the safari emitter's bookmarks mode",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(safari::BOOKMARK_URN_PREFIX),
+                true,
+                "The bookmark's URN, by Safari's UUID for it",
+            ),
+            property(
+                "additionalType",
+                Kind::Constant("bookmark"),
+                true,
+                "What kind of page it is",
+            ),
+            property("name", Kind::String, false, "The bookmark's title"),
+            property("url", Kind::String, true, "The address it links to"),
+            property(
+                "folder",
+                Kind::String,
+                true,
+                "The path of the folder it is in, as in Favorites/News",
+            ),
+            property(
+                "isPartOf",
+                Kind::Reference(safari::FOLDER_URN_PREFIX),
+                false,
+                "The folder it is in",
+            ),
+            property("dateCreated", Kind::DateTime, false, "When it was added"),
+            property(
+                "source",
+                Kind::Constant("apple-safari"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "SafariHistoryItem",
+        class: "WebPage",
+        targets_class: false,
+        description: "A page in Safari's history, as emitted by the safari emitter's history mode",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(safari::HISTORY_URN_PREFIX),
+                true,
+                "The page's URN, by the SHA-256 of its URL",
+            ),
+            property(
+                "additionalType",
+                Kind::Constant("historyItem"),
+                true,
+                "What kind of page it is",
+            ),
+            property("name", Kind::String, false, "Its title when last visited"),
+            property("url", Kind::String, true, "Its address"),
+            property(
+                "visitCount",
+                Kind::Integer,
+                true,
+                "How many times Safari counts it visited, ever",
+            ),
+            property(
+                "visits",
+                Kind::Strings,
+                true,
+                "When it was visited, in local time, oldest first, of the visits Safari keeps",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-safari"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "SafariReadingListItem",
+        class: "WebPage",
+        targets_class: false,
+        description: "A page in Safari's Reading List, as emitted by the safari emitter's reading-list mode",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(safari::READING_LIST_URN_PREFIX),
+                true,
+                "The page's URN, by Safari's UUID for it",
+            ),
+            property(
+                "additionalType",
+                Kind::Constant("readingListItem"),
+                true,
+                "What kind of page it is",
+            ),
+            property("name", Kind::String, false, "Its title"),
+            property("url", Kind::String, true, "Its address"),
+            property("dateCreated", Kind::DateTime, false, "When it was added"),
+            property("dateRead", Kind::DateTime, false, "When it was last read"),
+            property(
+                "unread",
+                Kind::Boolean,
+                true,
+                "Whether it is yet to be read",
+            ),
+            property(
+                "description",
+                Kind::String,
+                false,
+                "Its first lines, as the Reading List shows them",
+            ),
+            property(
+                "text",
+                Kind::String,
+                false,
+                "Its text as saved for reading offline, with --offline-text",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-safari"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "SafariOpenTab",
+        class: "WebPage",
+        targets_class: false,
+        description: "A tab open in Safari, as emitted by the safari emitter's open-tabs mode",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(safari::TAB_URN_PREFIX),
+                true,
+                "The tab's URN, by its window's id and its place, while the window is open",
+            ),
+            property(
+                "additionalType",
+                Kind::Constant("openTab"),
+                true,
+                "What kind of page it is",
+            ),
+            property("name", Kind::String, false, "Its title"),
+            property("url", Kind::String, false, "What it shows, unless blank"),
+            property(
+                "windowIndex",
+                Kind::Integer,
+                true,
+                "Its window's place, 1 for the frontmost",
+            ),
+            property(
+                "tabIndex",
+                Kind::Integer,
+                true,
+                "Its place in the window, 1 for the leftmost",
+            ),
+            property(
+                "currentTab",
+                Kind::Boolean,
+                true,
+                "Whether it is the one its window shows",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-safari"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "SafariDownload",
+        class: "MediaObject",
+        targets_class: false,
+        description: "A file downloaded in Safari, as emitted by the safari emitter's downloads mode",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(safari::DOWNLOAD_URN_PREFIX),
+                true,
+                "The download's URN, by Safari's UUID for it",
+            ),
+            property(
+                "additionalType",
+                Kind::Constant("download"),
+                true,
+                "What kind of file it is",
+            ),
+            property("name", Kind::String, false, "Its file name"),
+            property("url", Kind::String, true, "Where it was downloaded from"),
+            property("path", Kind::String, false, "Where it was downloaded to"),
+            property(
+                "contentSize",
+                Kind::Integer,
+                false,
+                "Its size in bytes, if the server said",
+            ),
+            property(
+                "bytesDownloaded",
+                Kind::Integer,
+                false,
+                "How many of its bytes were downloaded",
+            ),
+            property("dateCreated", Kind::DateTime, false, "When it was started"),
+            property("dateFinished", Kind::DateTime, false, "When it finished"),
+            property(
+                "finished",
+                Kind::Boolean,
+                true,
+                "Whether all of it was downloaded",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-safari"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "SafariCloudTab",
+        class: "WebPage",
+        targets_class: false,
+        description: "A tab open on another device, as emitted by the safari emitter's cloud-tabs mode",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(safari::CLOUD_TAB_URN_PREFIX),
+                true,
+                "The tab's URN, by Safari's UUID for it",
+            ),
+            property(
+                "additionalType",
+                Kind::Constant("cloudTab"),
+                true,
+                "What kind of page it is",
+            ),
+            property("name", Kind::String, false, "Its title"),
+            property("url", Kind::String, true, "What it shows"),
+            property(
+                "device",
+                Kind::String,
+                false,
+                "The name of the device it is open on",
+            ),
+            property(
+                "deviceId",
+                Kind::String,
+                true,
+                "iCloud's UUID for the device",
+            ),
+            property(
+                "dateModified",
+                Kind::DateTime,
+                false,
+                "When the device last synced its tabs",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-safari"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "SafariTopSite",
+        class: "WebPage",
+        targets_class: false,
+        description: "One of the pages visited most in Safari, as emitted by the safari emitter's top-sites mode",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(safari::TOP_SITE_URN_PREFIX),
+                true,
+                "The page's URN, by the SHA-256 of its URL",
+            ),
+            property(
+                "additionalType",
+                Kind::Constant("topSite"),
+                true,
+                "What kind of page it is",
+            ),
+            property(
+                "position",
+                Kind::Integer,
+                true,
+                "Its place in the ranking, 1 for the page visited most",
+            ),
+            property("name", Kind::String, false, "Its title when last visited"),
+            property("url", Kind::String, true, "Its address"),
+            property(
+                "visitCount",
+                Kind::Integer,
+                true,
+                "How many times Safari counts it visited, ever",
+            ),
+            property(
+                "lastVisited",
+                Kind::DateTime,
+                false,
+                "When it was last visited",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-safari"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "SafariBookmarkFolder",
+        class: "Collection",
+        targets_class: false,
+        description: "A Safari bookmark folder",
+        properties: &[
+            property(
+                "@id",
+                Kind::Urn(safari::FOLDER_URN_PREFIX),
+                true,
+                "The folder's URN",
+            ),
+            property(
+                "additionalType",
+                Kind::Constant("bookmarkFolder"),
+                true,
+                "What kind of collection it is",
+            ),
+            property("name", Kind::String, true, "The folder's name"),
+            property("folder", Kind::String, true, "Its path, its own name last"),
+            property(
+                "isPartOf",
+                Kind::Reference(safari::FOLDER_URN_PREFIX),
+                false,
+                "The folder it is in",
+            ),
+            property(
+                "source",
+                Kind::Constant("apple-safari"),
+                true,
+                "The app the record came from",
+            ),
+        ],
+    },
+    RecordType {
+        name: "Activity",
+        class: ACTIVITY_CLASS,
+        targets_class: true,
+        description: "A run that emitted records, as emitted once with --provenance activity",
+        properties: &[
+            property("@id", Kind::Urn(RUN_URN_PREFIX), true, "The run's URN"),
+            property(
+                "prov:startedAtTime",
+                Kind::DateTime,
+                true,
+                "When the run started, in UTC",
+            ),
+            property(
+                "prov:atLocation",
+                Kind::Object,
+                true,
+                "The Mac it ran on, by the URN of its hardware UUID",
+            ),
+            property(
+                "prov:wasAssociatedWith",
+                Kind::Object,
+                true,
+                "This module: a prov:SoftwareAgent with name and softwareVersion",
+            ),
+            property(
+                "prov:used",
+                Kind::Object,
+                true,
+                "The backend the records were extracted through, with its name",
+            ),
+        ],
+    },
+];
+
+impl RecordType {
+    /// Returns its properties, and those common to every record type.
+    pub fn all_properties(&self) -> impl Iterator<Item = &Property> {
+        let common = match self.class {
+            ACTIVITY_CLASS => &[],
+            _ => COMMON_PROPERTIES,
+        };
+        self.properties.iter().chain(common)
+    }
+}
+
+/// Returns the Turtle name of a class or property: its `prov:` name, or
+/// else its `schema:` one.
+fn term(name: &str) -> String {
+    match name.strip_prefix("prov:") {
+        Some(_) => name.into(),
+        None => format!("schema:{name}"),
+    }
+}
+
+/// The shape of local ISO 8601 date-times; JSON Schema's `date-time`
+/// format would also require a UTC offset.
+const DATE_TIME_PATTERN: &str = r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}";
+
+/// The shape of dates, optionally with a time.
+const DATE_PATTERN: &str = r"^\d{4}-\d{2}-\d{2}(T\d{2}:\d{2}:\d{2})?";
+
+/// Returns a JSON Schema (draft 2020-12) document that every emitted
+/// record validates against, with one definition per record type.
+pub fn json_schema() -> Value {
+    let mut defs = Map::new();
+    for record_type in RECORD_TYPES {
+        let mut properties = Map::new();
+        properties.insert("@type".into(), json!({ "const": record_type.class }));
+        for property in record_type.all_properties() {
+            let mut schema = match property.kind {
+                Kind::String => json!({ "type": "string" }),
+                Kind::Strings => json!({ "type": "array", "items": { "type": "string" } }),
+                Kind::Boolean => json!({ "type": "boolean" }),
+                Kind::Integer => json!({ "type": "integer" }),
+                Kind::Number => json!({ "type": "number" }),
+                Kind::Numbers => json!({ "type": "array", "items": { "type": "number" } }),
+                Kind::Object => json!({ "type": "object" }),
+                Kind::Objects => json!({ "type": "array", "items": { "type": "object" } }),
+                Kind::DateTime => json!({ "type": "string", "pattern": DATE_TIME_PATTERN }),
+                Kind::Date => json!({ "type": "string", "pattern": DATE_PATTERN }),
+                Kind::Urn(prefix) | Kind::Reference(prefix) => {
+                    json!({ "type": "string", "pattern": format!("^{prefix}") })
+                }
+                Kind::References(prefix) => json!({
+                    "type": "array",
+                    "items": { "type": "string", "pattern": format!("^{prefix}") },
+                }),
+                Kind::Constant(value) => json!({ "const": value }),
+                Kind::Activity => {
+                    let id = json!({ "type": "string", "pattern": format!("^{RUN_URN_PREFIX}") });
+                    json!({
+                        "type": "object",
+                        "anyOf": [
+                            {
+                                "properties": { "@id": id },
+                                "required": ["@id"],
+                                "additionalProperties": false,
+                            },
+                            {
+                                "properties": {
+                                    "@type": { "const": ACTIVITY_CLASS },
+                                    "@id": id,
+                                    "prov:startedAtTime": {
+                                        "type": "string",
+                                        "pattern": DATE_TIME_PATTERN,
+                                    },
+                                },
+                                "required": ["@type", "@id", "prov:startedAtTime"],
+                            },
+                        ],
+                    })
+                }
+            };
+            schema["description"] = property.description.into();
+            properties.insert(property.name.into(), schema);
+        }
+        let required: Vec<&str> = core::iter::once("@type")
+            .chain(
+                record_type
+                    .all_properties()
+                    .filter(|p| p.required)
+                    .map(|p| p.name),
+            )
+            .collect();
+        defs.insert(
+            record_type.name.into(),
+            json!({
+                "description": record_type.description,
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            }),
+        );
+    }
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": SCHEMA_ID,
+        "title": "ASIMOV Apple module records",
+        "anyOf": RECORD_TYPES
+            .iter()
+            .map(|t| json!({ "$ref": format!("#/$defs/{}", t.name) }))
+            .collect::<Vec<_>>(),
+        "$defs": defs,
+    })
+}
+
+/// Returns SHACL shapes, in Turtle, for the triples the RDF sinks and
+/// exporters produce from every record type.
+pub fn shacl() -> String {
+    let mut turtle = format!(
+        "@prefix sh: <http://www.w3.org/ns/shacl#> .\n\
+         @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .\n\
+         @prefix schema: <{SCHEMA}> .\n\
+         @prefix prov: <{PROV}> .\n\
+         @prefix xsd: <{XSD}> .\n\
+         @prefix : <{SHAPES}> .\n"
+    );
+    for record_type in RECORD_TYPES {
+        turtle += &format!(
+            "\n:{name}Shape a sh:NodeShape ;\n    rdfs:comment \"{description}\" ;\n",
+            name = record_type.name,
+            description = record_type.description,
+        );
+        if record_type.targets_class {
+            turtle += &format!("    sh:targetClass {} ;\n", term(record_type.class));
+        }
+        turtle += &format!("    sh:class {} ;\n", term(record_type.class));
+        let mut constraints = Vec::new();
+        for property in record_type.all_properties() {
+            let rule = match property.kind {
+                Kind::Urn(prefix) => {
+                    turtle +=
+                        &format!("    sh:nodeKind sh:IRI ;\n    sh:pattern \"^{prefix}\" ;\n");
+                    continue;
+                }
+                Kind::Reference(prefix) | Kind::References(prefix) => {
+                    format!("sh:datatype xsd:string ; sh:pattern \"^{prefix}\"")
+                }
+                Kind::String | Kind::Strings => "sh:datatype xsd:string".into(),
+                Kind::Boolean => "sh:datatype xsd:boolean".into(),
+                Kind::Integer => "sh:datatype xsd:integer".into(),
+                Kind::Number | Kind::Numbers => "sh:datatype xsd:double".into(),
+                Kind::Object | Kind::Objects => "sh:nodeKind sh:BlankNodeOrIRI".into(),
+                Kind::DateTime => "sh:datatype xsd:dateTime".into(),
+                Kind::Date => {
+                    "sh:or ( [ sh:datatype xsd:date ] [ sh:datatype xsd:dateTime ] )".into()
+                }
+                Kind::Constant(value) => format!("sh:hasValue \"{value}\""),
+                // Inline or not, the activity is the node of its `@id`:
+                Kind::Activity => format!("sh:nodeKind sh:IRI ; sh:pattern \"^{RUN_URN_PREFIX}\""),
+            };
+            let min_count = if property.required {
+                " ; sh:minCount 1"
+            } else {
+                ""
+            };
+            let max_count = match property.kind {
+                Kind::References(_) | Kind::Strings | Kind::Numbers | Kind::Objects => "",
+                _ => " ; sh:maxCount 1",
+            };
+            constraints.push(format!(
+                "[ sh:path {} ; {rule}{min_count}{max_count} ]",
+                term(property.name)
+            ));
+        }
+        turtle += &format!(
+            "    sh:property\n        {} .\n",
+            constraints.join(" ,\n        ")
+        );
+    }
+    turtle
+}
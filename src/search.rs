@@ -0,0 +1,395 @@
+// This is free and unencumbered software released into the public domain.
+
+//! A local full-text search index over emitted records, kept by
+//! [tantivy] in a directory of its own. Records of any type are indexed
+//! by their `name` and `text`, stemmed, ignoring case and diacritics.
+
+use crate::{
+    Error, Result,
+    sink::{Sink, str_field},
+};
+use serde_json::{Value, json};
+use std::{
+    boxed::Box,
+    env, format, io,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use tantivy::{
+    Index, IndexWriter, Score, TantivyDocument, TantivyError, Term,
+    collector::TopDocs,
+    query::{BooleanQuery, BoostQuery, Occur, Query, TermQuery},
+    schema::{Field, IndexRecordOption, STORED, STRING, Schema, TextFieldIndexing, TextOptions},
+    snippet::{Snippet, SnippetGenerator},
+    tokenizer::{
+        AsciiFoldingFilter, Language, LowerCaser, RemoveLongFilter, SimpleTokenizer, Stemmer,
+        TextAnalyzer,
+    },
+};
+
+/// The name the analyzer of `name` and `text` is registered under; it is
+/// not kept in the index, so it is registered again on every opening.
+const TOKENIZER: &str = "asimov";
+
+/// How much more a match in a record's name counts than one in its text.
+const NAME_WEIGHT: Score = 10.0;
+
+/// The most words a query word ending in `*` stands for.
+const MAX_EXPANSIONS: usize = 64;
+
+/// The memory the index writer may use before writing a segment out.
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// Returns where the index lives unless told otherwise:
+/// `~/Library/Application Support/asimov-apple/index`.
+pub fn default_path() -> Result<PathBuf> {
+    let home = env::var_os("HOME").ok_or_else(|| Error::Usage {
+        message: "HOME is not set".to_string(),
+    })?;
+    Ok(PathBuf::from(home).join("Library/Application Support/asimov-apple/index"))
+}
+
+/// The fields of the index. Full records are kept as JSON in `record` so
+/// that results can be turned back into them.
+struct Fields {
+    id: Field,
+    r#type: Field,
+    name: Field,
+    text: Field,
+    date_modified: Field,
+    record: Field,
+}
+
+impl Fields {
+    fn schema() -> Schema {
+        let text = TextOptions::default().set_stored().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(TOKENIZER)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+        let mut schema = Schema::builder();
+        schema.add_text_field("id", STRING | STORED);
+        schema.add_text_field("type", STORED);
+        schema.add_text_field("name", text.clone());
+        schema.add_text_field("text", text);
+        schema.add_text_field("date_modified", STORED);
+        schema.add_text_field("record", STORED);
+        schema.build()
+    }
+
+    fn of(index: &Index) -> Result<Self> {
+        let schema = index.schema();
+        let field = |name| {
+            schema
+                .get_field(name)
+                .map_err(index_error("reading the search index"))
+        };
+        Ok(Fields {
+            id: field("id")?,
+            r#type: field("type")?,
+            name: field("name")?,
+            text: field("text")?,
+            date_modified: field("date_modified")?,
+            record: field("record")?,
+        })
+    }
+}
+
+/// Returns the analyzer of `name` and `text`, which stems words unless
+/// `stem` is false, as for the beginnings of words that a query ends in
+/// `*`.
+fn analyzer(stem: bool) -> TextAnalyzer {
+    let builder = TextAnalyzer::builder(SimpleTokenizer::default())
+        .filter(RemoveLongFilter::limit(40))
+        .filter(LowerCaser)
+        .filter(AsciiFoldingFilter);
+    match stem {
+        true => builder.filter(Stemmer::new(Language::English)).build(),
+        false => builder.build(),
+    }
+}
+
+/// Opens the index at `path`, which must exist.
+fn open_index(path: &Path) -> Result<Index> {
+    let index = Index::open_in_dir(path).map_err(index_error("opening the search index"))?;
+    index.tokenizers().register(TOKENIZER, analyzer(true));
+    Ok(index)
+}
+
+fn index_error(context: &'static str) -> impl Fn(TantivyError) -> Error {
+    move |e| Error::Io {
+        context,
+        source: io::Error::other(e),
+    }
+}
+
+/// A sink adding records to the index, replacing those with the same `@id`.
+pub struct IndexSink {
+    writer: Option<IndexWriter>,
+    fields: Fields,
+}
+
+impl IndexSink {
+    /// Opens the index at `path`, creating it (and its directory) if needed.
+    pub fn open(path: &Path) -> Result<Self> {
+        std::fs::create_dir_all(path).map_err(|e| Error::Io {
+            context: "creating the search index directory",
+            source: e,
+        })?;
+        let index = match path.join("meta.json").exists() {
+            true => open_index(path)?,
+            false => {
+                Index::create_in_dir(path, Fields::schema())
+                    .map_err(index_error("creating the search index"))?;
+                open_index(path)?
+            }
+        };
+        let writer = index
+            .writer_with_num_threads(1, WRITER_MEMORY)
+            .map_err(index_error("opening the search index for writing"))?;
+        Ok(IndexSink {
+            fields: Fields::of(&index)?,
+            writer: Some(writer),
+        })
+    }
+
+    fn writer(&mut self) -> Result<&mut IndexWriter> {
+        self.writer.as_mut().ok_or_else(|| Error::Usage {
+            message: "the search index is closed".to_string(),
+        })
+    }
+}
+
+impl Sink for IndexSink {
+    fn write(&mut self, record: &Value) -> Result<()> {
+        let Some(id) = str_field(record, "@id") else {
+            return Err(Error::Parse {
+                context: "adding a record to the search index",
+                message: "record has no @id".to_string(),
+            });
+        };
+        let fields = &self.fields;
+        let mut document = TantivyDocument::default();
+        document.add_text(fields.id, id);
+        for (field, key) in [
+            (fields.r#type, "@type"),
+            (fields.name, "name"),
+            (fields.date_modified, "dateModified"),
+        ] {
+            if let Some(value) = str_field(record, key) {
+                document.add_text(field, value);
+            }
+        }
+        document.add_text(fields.text, text_of(record));
+        document.add_text(fields.record, record.to_string());
+
+        let id = Term::from_field_text(fields.id, id);
+        let writer = self.writer()?;
+        writer.delete_term(id);
+        writer
+            .add_document(document)
+            .map_err(index_error("adding a record to the search index"))?;
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &str) -> Result<()> {
+        let id = Term::from_field_text(self.fields.id, id);
+        self.writer()?.delete_term(id);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer()?
+            .commit()
+            .map_err(index_error("writing the search index"))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush()?;
+        if let Some(writer) = self.writer.take() {
+            writer
+                .wait_merging_threads()
+                .map_err(index_error("writing the search index"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the text to index a record by: its `text`, or else every other
+/// string it holds but its name and identity.
+fn text_of(record: &Value) -> String {
+    if let Some(text) = str_field(record, "text") {
+        return text.to_string();
+    }
+    let Some(fields) = record.as_object() else {
+        return String::new();
+    };
+    fields
+        .iter()
+        .filter(|(key, _)| !matches!(key.as_str(), "@id" | "@type" | "name"))
+        .filter_map(|(_, value)| value.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the records in the index at `path` best matching `query`, best
+/// first, as `{"@id", "@type", "name", "dateModified", "score", "snippet"}`
+/// objects. Every word of the query must match; a trailing `*` matches
+/// any word it begins.
+pub fn search(path: &Path, query: &str, limit: usize) -> Result<Vec<Value>> {
+    if !path.join("meta.json").exists() {
+        return Err(Error::NotFound {
+            what: format!("search index {}", path.display()),
+        });
+    }
+    let index = open_index(path)?;
+    let fields = Fields::of(&index)?;
+    let searcher = index
+        .reader()
+        .map_err(index_error("opening the search index"))?
+        .searcher();
+
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+    for word in query.split_whitespace() {
+        let (word, prefix) = match word.strip_suffix('*') {
+            Some(stem) if !stem.is_empty() => (stem, true),
+            _ => (word, false),
+        };
+        let mut analyzer = analyzer(!prefix);
+        let mut tokens = analyzer.token_stream(word);
+        while let Some(token) = tokens.next() {
+            let terms = |field| match prefix {
+                true => expand(&searcher, field, &token.text),
+                false => Ok(vec![Term::from_field_text(field, &token.text)]),
+            };
+            let mut alternatives: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+            for term in terms(fields.name)? {
+                let query = TermQuery::new(term, IndexRecordOption::WithFreqs);
+                alternatives.push((
+                    Occur::Should,
+                    Box::new(BoostQuery::new(Box::new(query), NAME_WEIGHT)),
+                ));
+            }
+            for term in terms(fields.text)? {
+                let query = TermQuery::new(term, IndexRecordOption::WithFreqsAndPositions);
+                alternatives.push((Occur::Should, Box::new(query)));
+            }
+            // A beginning that no word in the index has matches nothing:
+            if alternatives.is_empty() {
+                return Ok(Vec::new());
+            }
+            clauses.push((Occur::Must, Box::new(BooleanQuery::new(alternatives))));
+        }
+    }
+    if clauses.is_empty() {
+        return Err(Error::Usage {
+            message: "the search query is empty".to_string(),
+        });
+    }
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    let query = BooleanQuery::new(clauses);
+    let hits = searcher
+        .search(&query, &TopDocs::with_limit(limit).order_by_score())
+        .map_err(index_error("searching the search index"))?;
+    let mut snippets = SnippetGenerator::create(&searcher, &query, fields.text)
+        .map_err(index_error("searching the search index"))?;
+    snippets.set_max_num_chars(SNIPPET_CHARS);
+
+    use tantivy::schema::Value as _;
+    let mut results = Vec::with_capacity(hits.len());
+    for (score, address) in hits {
+        let document: TantivyDocument = searcher
+            .doc(address)
+            .map_err(index_error("reading the search index"))?;
+        let text = |field| document.get_first(field).and_then(|value| value.as_str());
+        let mut result = json!({
+            "@id": text(fields.id),
+            "@type": text(fields.r#type),
+            "name": text(fields.name),
+            "dateModified": text(fields.date_modified),
+            "score": score,
+        });
+        if let Some(body) = text(fields.text) {
+            let snippet = snippets.snippet(body);
+            if !snippet.highlighted().is_empty() {
+                result["snippet"] = mark(&snippet, body).into();
+            }
+        }
+        if let Some(fields) = result.as_object_mut() {
+            fields.retain(|_, value| !value.is_null());
+        }
+        results.push(result);
+    }
+    Ok(results)
+}
+
+/// The length a snippet is kept to, about 16 words.
+const SNIPPET_CHARS: usize = 100;
+
+/// Returns the words in the index's `field` that begin with `prefix`, at
+/// most [`MAX_EXPANSIONS`] of them.
+fn expand(searcher: &tantivy::Searcher, field: Field, prefix: &str) -> Result<Vec<Term>> {
+    let mut words = Vec::new();
+    for segment in searcher.segment_readers() {
+        let index = segment
+            .inverted_index(field)
+            .map_err(index_error("reading the search index"))?;
+        let mut stream = index
+            .terms()
+            .range()
+            .ge(prefix.as_bytes())
+            .into_stream()
+            .map_err(|e| Error::Io {
+                context: "reading the search index",
+                source: e,
+            })?;
+        while stream.advance() && words.len() < MAX_EXPANSIONS {
+            let Some(word) = core::str::from_utf8(stream.key())
+                .ok()
+                .filter(|word| word.starts_with(prefix))
+            else {
+                break;
+            };
+            if !words.iter().any(|known: &String| known == word) {
+                words.push(word.to_string());
+            }
+        }
+    }
+    Ok(words
+        .iter()
+        .map(|word| Term::from_field_text(field, word))
+        .collect())
+}
+
+/// Returns the snippet's fragment of `text` with the words that matched
+/// in brackets, and with an ellipsis where it leaves words out.
+fn mark(snippet: &Snippet, text: &str) -> String {
+    let fragment = snippet.fragment();
+    let (before, after) = match text.find(fragment) {
+        Some(start) => (&text[..start], &text[start + fragment.len()..]),
+        None => (text, text),
+    };
+    let mut marked = String::new();
+    if before.chars().any(char::is_alphanumeric) {
+        marked.push('…');
+    }
+    let mut end = 0;
+    for range in snippet.highlighted() {
+        marked.push_str(&fragment[end..range.start]);
+        marked.push('[');
+        marked.push_str(&fragment[range.clone()]);
+        marked.push(']');
+        end = range.end;
+    }
+    marked.push_str(&fragment[end..]);
+    if after.chars().any(char::is_alphanumeric) {
+        marked.push('…');
+    }
+    marked
+}
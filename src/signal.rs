@@ -0,0 +1,38 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Ctrl-C handling shared by the command-line programs.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigint(_signum: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Replaces the default SIGINT disposition with one that merely records
+/// the interrupt, so that a program can wind down on its own terms.
+#[allow(unsafe_code)]
+pub fn install() {
+    let handler = on_sigint as extern "C" fn(libc::c_int);
+    // SAFETY: the handler only performs an atomic store, which is
+    // async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+/// Returns whether Ctrl-C has been pressed since [`install`] was called.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Exit code used when a run is cut short by Ctrl-C (128 + SIGINT).
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// How a run of a command-line program ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion {
+    Finished,
+    Interrupted,
+}
@@ -0,0 +1,373 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Output sinks: where emitted records end up.
+
+use crate::{
+    Error, Result,
+    embed::Embedder,
+    provenance::{Provenance, ProvenanceMode},
+    rdf,
+};
+use core::{fmt, str::FromStr};
+use serde_json::Value;
+use std::{
+    boxed::Box,
+    format,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    string::{String, ToString},
+};
+
+pub mod eml;
+pub mod ics;
+pub mod mbox;
+pub mod oxigraph;
+pub mod s3;
+pub mod sqlite;
+pub mod vcard;
+pub mod webhook;
+
+/// A destination for emitted records.
+pub trait Sink {
+    /// Writes a single record.
+    fn write(&mut self, record: &Value) -> Result<()>;
+
+    /// Removes the record with the given `@id`. Sinks that can't delete
+    /// anything pass the tombstone on instead.
+    fn delete(&mut self, id: &str) -> Result<()> {
+        self.write(&tombstone(id))
+    }
+
+    /// Stores an attachment of the records being written, returning the
+    /// URL it is kept at. Sinks that keep only records store nothing and
+    /// return `None`.
+    fn attach(&mut self, data: &[u8], mime: &str, extension: &str) -> Result<Option<String>> {
+        let _ = (data, mime, extension);
+        Ok(None)
+    }
+
+    /// Makes everything written so far visible downstream, for long-running
+    /// programs that keep a sink open between batches of records.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Flushes everything written so far. Called exactly once, also when
+    /// a run is interrupted, so sinks must persist partial output here.
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// How records written to stdout are encoded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One JSON record per line.
+    #[default]
+    Jsonl,
+    /// N-Quads, with each account's records in its own named graph.
+    Nquads,
+    /// One iCalendar file of the calendar events, leaving out every other
+    /// record.
+    Ics,
+    /// The contacts as vCards of the given version, leaving out every
+    /// other record.
+    Vcard(vcard::Version),
+    /// The mail messages as they came, as one mbox file, leaving out
+    /// every record.
+    Mbox,
+    /// The mail messages as they came, each into its own `.eml` file in
+    /// the given directory rather than on stdout, leaving out every
+    /// record.
+    EmlDir(PathBuf),
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        match input {
+            "jsonl" | "ndjson" => Ok(OutputFormat::Jsonl),
+            "nquads" | "n-quads" => Ok(OutputFormat::Nquads),
+            "ics" | "ical" | "icalendar" => Ok(OutputFormat::Ics),
+            "vcard" | "vcf" | "vcard4" => Ok(OutputFormat::Vcard(vcard::Version::V4)),
+            "vcard3" => Ok(OutputFormat::Vcard(vcard::Version::V3)),
+            "mbox" => Ok(OutputFormat::Mbox),
+            "eml-dir" => Err("eml-dir needs a directory, as in eml-dir:./mail".into()),
+            _ => match input.split_once(':') {
+                Some(("eml-dir", path)) if !path.is_empty() => {
+                    Ok(OutputFormat::EmlDir(path.into()))
+                }
+                _ => Err(format!("unsupported output format: {input}")),
+            },
+        }
+    }
+}
+
+/// A parsed `--sink` argument.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SinkSpec {
+    /// JSONL on stdout.
+    #[default]
+    Stdout,
+    /// Upserts into an SQLite database at the given path.
+    Sqlite(PathBuf),
+    /// Replaces triples in an Oxigraph store or SPARQL Update endpoint.
+    Oxigraph(oxigraph::Target),
+    /// POSTs batches of records to an HTTP(S) URL.
+    Webhook(String),
+    /// Uploads batches of records as NDJSON objects to an S3-compatible
+    /// store.
+    S3(s3::Location),
+}
+
+impl SinkSpec {
+    /// Opens the sink this spec describes, with default settings.
+    pub fn open(&self) -> Result<Box<dyn Sink>> {
+        Ok(match self {
+            SinkSpec::Stdout => Box::new(JsonlSink::new(io::stdout().lock())),
+            SinkSpec::Sqlite(path) => Box::new(sqlite::SqliteSink::open(path)?),
+            SinkSpec::Oxigraph(target) => Box::new(oxigraph::OxigraphSink::new(target.clone())),
+            SinkSpec::Webhook(url) => {
+                Box::new(webhook::WebhookSink::new(webhook::WebhookConfig::new(url)))
+            }
+            SinkSpec::S3(location) => {
+                Box::new(s3::S3Sink::new(s3::S3Config::from_env(location.clone())?))
+            }
+        })
+    }
+}
+
+impl FromStr for SinkSpec {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        if input == "-" || input == "stdout" {
+            return Ok(SinkSpec::Stdout);
+        }
+        if input.starts_with("http://") || input.starts_with("https://") {
+            return Ok(SinkSpec::Webhook(input.into()));
+        }
+        if let Some(location) = input.strip_prefix("s3://") {
+            return s3::Location::parse(location)
+                .map(SinkSpec::S3)
+                .ok_or_else(|| format!("unsupported sink: {input}"));
+        }
+        match input.split_once(':') {
+            Some(("sqlite", path)) if !path.is_empty() => Ok(SinkSpec::Sqlite(path.into())),
+            Some(("oxigraph", url))
+                if url.starts_with("http://") || url.starts_with("https://") =>
+            {
+                Ok(SinkSpec::Oxigraph(oxigraph::Target::Endpoint(url.into())))
+            }
+            Some(("oxigraph", path)) if !path.is_empty() => {
+                Ok(SinkSpec::Oxigraph(oxigraph::Target::Store(path.into())))
+            }
+            _ => Err(format!("unsupported sink: {input}")),
+        }
+    }
+}
+
+impl fmt::Display for SinkSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkSpec::Stdout => write!(f, "stdout"),
+            SinkSpec::Sqlite(path) => write!(f, "sqlite:{}", path.display()),
+            SinkSpec::Oxigraph(oxigraph::Target::Store(path)) => {
+                write!(f, "oxigraph:{}", path.display())
+            }
+            SinkSpec::Oxigraph(oxigraph::Target::Endpoint(url)) => write!(f, "oxigraph:{url}"),
+            SinkSpec::Webhook(url) => write!(f, "{url}"),
+            SinkSpec::S3(location) => write!(f, "{location}"),
+        }
+    }
+}
+
+/// Writes one JSON record per line.
+pub struct JsonlSink<W: Write> {
+    writer: BufWriter<W>,
+}
+
+impl<W: Write> JsonlSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonlSink {
+            writer: BufWriter::new(writer),
+        }
+    }
+}
+
+impl<W: Write> Sink for JsonlSink<W> {
+    fn write(&mut self, record: &Value) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n").map_err(|e| Error::Io {
+            context: "writing newline to stdout",
+            source: e,
+        })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush().map_err(|e| Error::Io {
+            context: "flushing stdout",
+            source: e,
+        })
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Sink::flush(self)
+    }
+}
+
+/// Writes records as N-Quads, each in the named graph of the account it
+/// came from, in the app it came from (see [`rdf::account_graph`]), so
+/// that consumers can replace one account's graph wholesale on re-sync. Records without an account,
+/// such as provenance activities, go into the default graph.
+pub struct NquadsSink<W: Write> {
+    writer: BufWriter<W>,
+}
+
+impl<W: Write> NquadsSink<W> {
+    pub fn new(writer: W) -> Self {
+        NquadsSink {
+            writer: BufWriter::new(writer),
+        }
+    }
+}
+
+impl<W: Write> Sink for NquadsSink<W> {
+    fn write(&mut self, record: &Value) -> Result<()> {
+        let graph = rdf::account_graph(record);
+        for quad in rdf::to_quads(record, graph.as_deref()) {
+            writeln!(self.writer, "{quad}").map_err(|e| Error::Io {
+                context: "writing N-Quads to stdout",
+                source: e,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush().map_err(|e| Error::Io {
+            context: "flushing stdout",
+            source: e,
+        })
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Sink::flush(self)
+    }
+}
+
+/// Attaches provenance to every record before passing it on.
+pub struct ProvenanceSink {
+    inner: Box<dyn Sink>,
+    provenance: Provenance,
+}
+
+impl ProvenanceSink {
+    /// Wraps `inner`, first writing the activity node to it if records
+    /// are to refer to one.
+    pub fn new(mut inner: Box<dyn Sink>, provenance: Provenance) -> Result<Self> {
+        if provenance.mode == ProvenanceMode::Activity {
+            inner.write(&provenance.activity())?;
+        }
+        Ok(ProvenanceSink { inner, provenance })
+    }
+}
+
+impl Sink for ProvenanceSink {
+    fn write(&mut self, record: &Value) -> Result<()> {
+        let mut record = record.clone();
+        self.provenance.attach(&mut record);
+        self.inner.write(&record)
+    }
+
+    fn delete(&mut self, id: &str) -> Result<()> {
+        self.inner.delete(id)
+    }
+
+    fn attach(&mut self, data: &[u8], mime: &str, extension: &str) -> Result<Option<String>> {
+        self.inner.attach(data, mime, extension)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()
+    }
+}
+
+/// Attaches an `embedding` of each record's text before passing it on.
+pub struct EmbeddingSink {
+    inner: Box<dyn Sink>,
+    embedder: Embedder,
+}
+
+impl EmbeddingSink {
+    pub fn new(inner: Box<dyn Sink>, embedder: Embedder) -> Self {
+        EmbeddingSink { inner, embedder }
+    }
+}
+
+impl Sink for EmbeddingSink {
+    fn write(&mut self, record: &Value) -> Result<()> {
+        let text = record_text(record);
+        if text.trim().is_empty() {
+            return self.inner.write(record);
+        }
+        let mut record = record.clone();
+        let embedding = self.embedder.embed(&text)?;
+        if let Some(record) = record.as_object_mut() {
+            record.insert("embedding".into(), embedding.into());
+        }
+        self.inner.write(&record)
+    }
+
+    fn delete(&mut self, id: &str) -> Result<()> {
+        self.inner.delete(id)
+    }
+
+    fn attach(&mut self, data: &[u8], mime: &str, extension: &str) -> Result<Option<String>> {
+        self.inner.attach(data, mime, extension)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()
+    }
+}
+
+/// Returns the text a record is about: its name, then its `text` (which
+/// for notes usually starts with the name already).
+pub(crate) fn record_text(record: &Value) -> String {
+    let name = str_field(record, "name").unwrap_or_default().trim();
+    let text = str_field(record, "text").unwrap_or_default().trim();
+    if name.is_empty() || text.starts_with(name) {
+        text.to_string()
+    } else if text.is_empty() {
+        name.to_string()
+    } else {
+        format!("{name}\n\n{text}")
+    }
+}
+
+/// Returns the tombstone marking the record with the given `@id` as
+/// deleted at its source.
+pub fn tombstone(id: &str) -> Value {
+    serde_json::json!({ "@id": id, "deleted": true })
+}
+
+/// Returns the `@id` of `record` if it is a tombstone.
+pub fn tombstone_id(record: &Value) -> Option<&str> {
+    match record.get("deleted") {
+        Some(Value::Bool(true)) => str_field(record, "@id"),
+        _ => None,
+    }
+}
+
+/// Returns the string at `key` in `record`, if any.
+pub(crate) fn str_field<'a>(record: &'a Value, key: &str) -> Option<&'a str> {
+    record.get(key).and_then(Value::as_str)
+}
@@ -0,0 +1,75 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Writes mail messages as they came, each into its own `.eml` file in a
+//! directory, named by its SHA-256, so that a message in several
+//! mailboxes is written once, and writing again only adds what is new.
+//! Records themselves are left out: only the messages attached to them,
+//! as `message/rfc822`, are written.
+
+use super::{Sink, mbox::MIME};
+use crate::{Error, Result, export::sha256_hex};
+use std::{
+    format, fs,
+    path::{Path, PathBuf},
+    string::{String, ToString},
+};
+
+/// Writes each message as it is attached.
+pub struct EmlDirSink {
+    dir: PathBuf,
+}
+
+impl EmlDirSink {
+    /// Opens the directory at `dir`, creating it if need be.
+    pub fn open(dir: &Path) -> Result<Self> {
+        let io = |e| Error::Io {
+            context: "opening the eml directory",
+            source: e,
+        };
+        fs::create_dir_all(dir).map_err(io)?;
+        Ok(EmlDirSink {
+            dir: fs::canonicalize(dir).map_err(io)?,
+        })
+    }
+}
+
+impl Sink for EmlDirSink {
+    fn write(&mut self, _record: &serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    /// Deleted messages' files are kept, as others may be the same.
+    fn delete(&mut self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Writes a message, unless already written, returning the `file:`
+    /// URL of its file; anything else is left out.
+    fn attach(&mut self, data: &[u8], mime: &str, extension: &str) -> Result<Option<String>> {
+        if mime != MIME {
+            return Ok(None);
+        }
+        let io = |e| Error::Io {
+            context: "writing an eml file",
+            source: e,
+        };
+        let name = format!("{}.{extension}", sha256_hex(data));
+        let path = self.dir.join(&name);
+        if !path.exists() {
+            // Given its name only once complete, so that an interrupted
+            // run leaves no partial file behind:
+            let partial = self.dir.join(format!(".{name}.part"));
+            fs::write(&partial, data).map_err(io)?;
+            fs::rename(&partial, &path).map_err(io)?;
+        }
+        Ok(Some(
+            url::Url::from_file_path(&path)
+                .map(String::from)
+                .unwrap_or_else(|()| path.display().to_string()),
+        ))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
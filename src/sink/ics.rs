@@ -0,0 +1,685 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Writes calendar events as a single iCalendar object (RFC 5545), which
+//! any calendar software imports, with a `VTIMEZONE` for each time zone
+//! the events are in, and busy intervals as `VFREEBUSY`s. Other records
+//! are left out.
+
+use super::{Sink, str_field};
+use crate::{
+    Error, Result,
+    calendar::BUSY_URN_PREFIX,
+    provenance,
+    timezone::{self, TimeZone},
+};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    io::{BufWriter, Write},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The longest a content line may be, in octets, before it is folded.
+const LINE_LIMIT: usize = 75;
+
+/// Collects event records, writing them out as one `VCALENDAR` once
+/// finished, as the time zones have to be known before the events.
+pub struct IcsSink<W: Write> {
+    writer: BufWriter<W>,
+    events: Vec<Value>,
+    calendars: Vec<String>,
+    /// The busy intervals, by calendar (if counted on their own).
+    busy: BTreeMap<Option<String>, Vec<(String, String)>>,
+}
+
+impl<W: Write> IcsSink<W> {
+    pub fn new(writer: W) -> Self {
+        IcsSink {
+            writer: BufWriter::new(writer),
+            events: Vec::new(),
+            calendars: Vec::new(),
+            busy: BTreeMap::new(),
+        }
+    }
+}
+
+impl<W: Write> Sink for IcsSink<W> {
+    fn write(&mut self, record: &Value) -> Result<()> {
+        match str_field(record, "@type") {
+            Some("Event") => self.events.push(record.clone()),
+            Some("DataFeed") => {
+                if let Some(name) = str_field(record, "name") {
+                    self.calendars.push(name.into());
+                }
+            }
+            Some("Schedule") => {
+                if let (Some(start), Some(end)) =
+                    (str_field(record, "startDate"), str_field(record, "endDate"))
+                {
+                    let calendar = str_field(record, "isPartOf").map(String::from);
+                    let intervals = self.busy.entry(calendar).or_default();
+                    intervals.push((start.into(), end.into()));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Deleted events are simply not in the file.
+    fn delete(&mut self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let text = calendar(&self.events, &self.calendars, &self.busy);
+        self.writer
+            .write_all(text.as_bytes())
+            .and_then(|()| self.writer.flush())
+            .map_err(|e| Error::Io {
+                context: "writing iCalendar to stdout",
+                source: e,
+            })
+    }
+}
+
+/// Returns the `VCALENDAR` holding `events` and `busy` intervals, named
+/// after the calendar if they all come from one.
+fn calendar(
+    events: &[Value],
+    calendars: &[String],
+    busy: &BTreeMap<Option<String>, Vec<(String, String)>>,
+) -> String {
+    let local = TimeZone::local();
+    let stamp = basic(&provenance::now_iso8601());
+    // Occurrences go with their recurring event only if it is there too:
+    let masters: BTreeSet<&str> = events
+        .iter()
+        .filter(|event| event.get("recurrenceRule").is_some())
+        .filter_map(|event| str_field(event, "@id"))
+        .collect();
+
+    let mut zones = BTreeMap::new();
+    let mut body = Vec::new();
+    for event in events {
+        body.extend(vevent(event, &masters, &mut zones, &local, &stamp));
+    }
+
+    let mut lines = Vec::from([
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".into(),
+        format!(
+            "PRODID:-//asimov-apple//asimov-apple {}//EN",
+            env!("CARGO_PKG_VERSION")
+        ),
+        "CALSCALE:GREGORIAN".into(),
+    ]);
+    if let [name] = calendars {
+        lines.push(format!("X-WR-CALNAME:{}", escape(name)));
+    }
+    for (name, offset) in &zones {
+        lines.extend(vtimezone(name, offset));
+    }
+    lines.extend(body);
+    for (calendar, intervals) in busy {
+        lines.push("BEGIN:VFREEBUSY".into());
+        lines.push(match calendar {
+            Some(name) => format!("UID:{BUSY_URN_PREFIX}{stamp}/{}", escape(name)),
+            None => format!("UID:{BUSY_URN_PREFIX}{stamp}"),
+        });
+        lines.push(format!("DTSTAMP:{stamp}"));
+        if let Some(name) = calendar {
+            lines.push(format!("COMMENT:{}", escape(name)));
+        }
+        for (start, end) in intervals {
+            lines.push(format!(
+                "FREEBUSY;FBTYPE=BUSY:{}/{}",
+                basic(start),
+                basic(end)
+            ));
+        }
+        lines.push("END:VFREEBUSY".into());
+    }
+    lines.push("END:VCALENDAR".into());
+
+    let mut text = String::new();
+    for line in &lines {
+        fold(line, &mut text);
+    }
+    text
+}
+
+/// Returns the lines of the `VEVENT` for `event`, noting the time zones
+/// it is in, each with an offset it has, in `zones`.
+fn vevent(
+    event: &Value,
+    masters: &BTreeSet<&str>,
+    zones: &mut BTreeMap<String, String>,
+    local: &TimeZone,
+    stamp: &str,
+) -> Vec<String> {
+    let text = |key: &str| str_field(event, key);
+    let id = text("@id").unwrap_or_default();
+    let zone = text("timeZone");
+    let all_day = event.get("allDay").and_then(Value::as_bool) == Some(true);
+    if let (Some(zone), Some(start)) = (zone, text("startDate"))
+        && let Some(offset) = start.get(19..).filter(|offset| offset.len() == 6)
+    {
+        zones
+            .entry(zone.to_string())
+            .or_insert_with(|| offset.to_string());
+    }
+    // The wall-clock time (or date) `value` in the event's time zone:
+    let time = |name: &str, value: &str| match (value.len(), zone) {
+        (10, _) => format!("{name};VALUE=DATE:{}", basic(value)),
+        (_, Some(zone)) => format!("{name};TZID={}:{}", param(zone), basic(&value[..19])),
+        _ if value.ends_with('Z') => format!("{name}:{}", basic(value)),
+        _ => format!("{name}:{}", basic(value.get(..19).unwrap_or(value))),
+    };
+
+    let mut lines = Vec::from(["BEGIN:VEVENT".to_string()]);
+    let master = text("recurrenceOf").filter(|master| masters.contains(master));
+    match master.and_then(|master| Some((master, id.strip_prefix(master)?.strip_prefix('/')?))) {
+        Some((master, original)) => {
+            lines.push(format!("UID:{master}"));
+            lines.push(time("RECURRENCE-ID", original));
+        }
+        None => lines.push(format!("UID:{id}")),
+    }
+    lines.push(format!("DTSTAMP:{stamp}"));
+    if let Some(start) = text("startDate") {
+        lines.push(time("DTSTART", start));
+    }
+    // All-day events end the day after their last, in iCalendar:
+    match text("endDate") {
+        Some(end) if all_day => {
+            if let Some(next) = next_day(end) {
+                lines.push(format!("DTEND;VALUE=DATE:{}", basic(&next)));
+            }
+        }
+        Some(end) => lines.push(time("DTEND", end)),
+        None => {}
+    }
+    lines.push(format!(
+        "SUMMARY:{}",
+        escape(text("name").unwrap_or_default())
+    ));
+    if let Some(notes) = text("description") {
+        lines.push(format!("DESCRIPTION:{}", escape(notes)));
+    }
+    if let Some(location) = event
+        .get("location")
+        .and_then(|place| str_field(place, "name"))
+    {
+        lines.push(format!("LOCATION:{}", escape(location)));
+    }
+    if let Some(place) = event.get("location")
+        && let (Some(latitude), Some(longitude)) = (
+            place.get("latitude").and_then(Value::as_f64),
+            place.get("longitude").and_then(Value::as_f64),
+        )
+    {
+        lines.push(format!("GEO:{latitude};{longitude}"));
+    }
+    if let Some(url) = text("url") {
+        lines.push(format!("URL:{url}"));
+    }
+    lines.push(format!(
+        "STATUS:{}",
+        match (text("eventStatus"), event.get("tentative")) {
+            (Some("EventCancelled"), _) => "CANCELLED",
+            (_, Some(Value::Bool(true))) => "TENTATIVE",
+            _ => "CONFIRMED",
+        }
+    ));
+    if let Some(organizer) = event.get("organizer").and_then(participant) {
+        lines.push(format!("ORGANIZER{organizer}"));
+    }
+    for attendee in event
+        .get("attendee")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let Some(address) = participant(attendee) else {
+            continue;
+        };
+        let status = match str_field(attendee, "participationStatus") {
+            Some("pending") => Some("NEEDS-ACTION"),
+            Some("accepted") => Some("ACCEPTED"),
+            Some("declined") => Some("DECLINED"),
+            Some("tentative") => Some("TENTATIVE"),
+            Some("delegated") => Some("DELEGATED"),
+            Some("completed") => Some("COMPLETED"),
+            Some("in-process") => Some("IN-PROCESS"),
+            _ => None,
+        };
+        let role = match str_field(attendee, "role") {
+            Some("required") => Some("REQ-PARTICIPANT"),
+            Some("optional") => Some("OPT-PARTICIPANT"),
+            Some("chair") => Some("CHAIR"),
+            Some("non-participant") => Some("NON-PARTICIPANT"),
+            _ => None,
+        };
+        let mut line = String::from("ATTENDEE");
+        if let Some(role) = role {
+            line += &format!(";ROLE={role}");
+        }
+        if let Some(status) = status {
+            line += &format!(";PARTSTAT={status}");
+        }
+        lines.push(line + &address);
+    }
+    if let Some(rule) = text("recurrenceRule") {
+        lines.push(format!("RRULE:{}", rrule(rule, all_day, zone, local)));
+    }
+    for exception in event
+        .get("exceptionDates")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        lines.push(time("EXDATE", exception));
+    }
+    if let Some(created) = text("dateCreated").and_then(|time| local.to_utc(time)) {
+        lines.push(format!("CREATED:{}", basic(&created)));
+    }
+    if let Some(modified) = text("dateModified").and_then(|time| local.to_utc(time)) {
+        lines.push(format!("LAST-MODIFIED:{}", basic(&modified)));
+    }
+    for alarm in event
+        .get("alarm")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let trigger = match (str_field(alarm, "offset"), str_field(alarm, "time")) {
+            (Some(offset), _) => format!("TRIGGER:{offset}"),
+            (_, Some(time)) => match local.instant(time) {
+                Some(time) => format!(
+                    "TRIGGER;VALUE=DATE-TIME:{}",
+                    basic(&timezone::iso8601(time))
+                ),
+                None => continue,
+            },
+            _ => continue,
+        };
+        lines.push("BEGIN:VALARM".into());
+        // An EMAIL alarm would need the address to send to, which
+        // EventKit doesn't give, so it notifies on screen instead:
+        match str_field(alarm, "alarmType") {
+            Some("audio") => lines.push("ACTION:AUDIO".into()),
+            _ => {
+                lines.push("ACTION:DISPLAY".into());
+                lines.push(format!(
+                    "DESCRIPTION:{}",
+                    escape(text("name").unwrap_or_default())
+                ));
+            }
+        }
+        lines.push(trigger);
+        lines.push("END:VALARM".into());
+    }
+    lines.push("END:VEVENT".into());
+    lines
+}
+
+/// Returns the parameters and value of an `ORGANIZER` or `ATTENDEE`
+/// line for `person`, as in `;CN="Ada Lovelace":mailto:ada@example.com`,
+/// or `None` without an email address.
+fn participant(person: &Value) -> Option<String> {
+    let email = str_field(person, "email")?;
+    Some(match str_field(person, "name") {
+        Some(name) => format!(";CN={}:mailto:{email}", param(name)),
+        None => format!(":mailto:{email}"),
+    })
+}
+
+/// Returns `rule` with its `UNTIL` in the form iCalendar wants: a date
+/// for all-day events, UTC for events in a time zone. EventKit gives it
+/// in local time.
+fn rrule(rule: &str, all_day: bool, zone: Option<&str>, local: &TimeZone) -> String {
+    let parts = rule
+        .split(';')
+        .map(|part| match part.strip_prefix("UNTIL=") {
+            Some(until) if all_day => format!("UNTIL={}", until.get(..8).unwrap_or(until)),
+            Some(until) if zone.is_some() => {
+                let iso = extended(until);
+                match local.to_utc(&iso) {
+                    Some(utc) => format!("UNTIL={}", basic(&utc)),
+                    None => part.to_string(),
+                }
+            }
+            _ => part.to_string(),
+        });
+    parts.collect::<Vec<_>>().join(";")
+}
+
+/// Returns the `VTIMEZONE` for the zone called `name`, from the rule in
+/// the system's zone file, or failing that, one keeping `offset` (as in
+/// `+01:00`) throughout. Only the current rule is described, as for
+/// events in the years around now it is the one that applies.
+fn vtimezone(name: &str, offset: &str) -> Vec<String> {
+    let mut lines = Vec::from(["BEGIN:VTIMEZONE".to_string(), format!("TZID:{name}")]);
+    let rule = TimeZone::named(name).and_then(|zone| zone.rule().and_then(PosixRule::parse));
+    match rule {
+        Some(rule) => match &rule.daylight {
+            Some((daylight_name, daylight, start, end)) => {
+                lines.extend(observance(
+                    "DAYLIGHT",
+                    daylight_name,
+                    rule.standard,
+                    *daylight,
+                    Some(start),
+                ));
+                lines.extend(observance(
+                    "STANDARD",
+                    &rule.standard_name,
+                    *daylight,
+                    rule.standard,
+                    Some(end),
+                ));
+            }
+            None => lines.extend(observance(
+                "STANDARD",
+                &rule.standard_name,
+                rule.standard,
+                rule.standard,
+                None,
+            )),
+        },
+        None => {
+            let seconds = timezone::parse_offset(offset).unwrap_or(0);
+            lines.extend(observance("STANDARD", "", seconds, seconds, None));
+        }
+    }
+    lines.push("END:VTIMEZONE".into());
+    lines
+}
+
+/// Returns the lines of a `STANDARD` or `DAYLIGHT` observance, going from
+/// offset `from` to `to` (in seconds east of UTC) each year as `change`
+/// has it, or once and for all in 1970.
+fn observance(
+    kind: &str,
+    name: &str,
+    from: i64,
+    to: i64,
+    change: Option<&Transition>,
+) -> Vec<String> {
+    let mut lines = Vec::from([format!("BEGIN:{kind}")]);
+    match change {
+        Some(change) => {
+            lines.push(format!("DTSTART:{}", change.first()));
+            let week = match change.week {
+                5 => -1,
+                week => i64::from(week),
+            };
+            lines.push(format!(
+                "RRULE:FREQ=YEARLY;BYMONTH={};BYDAY={week}{}",
+                change.month,
+                ["SU", "MO", "TU", "WE", "TH", "FR", "SA"][usize::from(change.weekday)]
+            ));
+        }
+        None => lines.push("DTSTART:19700101T000000".into()),
+    }
+    lines.push(format!("TZOFFSETFROM:{}", utc_offset(from)));
+    lines.push(format!("TZOFFSETTO:{}", utc_offset(to)));
+    if !name.is_empty() {
+        lines.push(format!("TZNAME:{}", escape(name)));
+    }
+    lines.push(format!("END:{kind}"));
+    lines
+}
+
+/// A POSIX `TZ` rule, as in `CET-1CEST,M3.5.0,M10.5.0/3`, with offsets
+/// in seconds east of UTC.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PosixRule {
+    standard_name: String,
+    standard: i64,
+    /// The daylight saving time name and offset, and when it starts and
+    /// ends.
+    daylight: Option<(String, i64, Transition, Transition)>,
+}
+
+/// When a zone changes offset each year: at `time` (seconds after local
+/// midnight) on weekday `weekday` (0 for Sunday) of week `week` (1 to 4,
+/// or 5 for the last) of month `month`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Transition {
+    month: u8,
+    week: u8,
+    weekday: u8,
+    time: i64,
+}
+
+impl Transition {
+    /// Returns when it first happens in 1970, as a local iCalendar time.
+    fn first(&self) -> String {
+        let month = i64::from(self.month);
+        let first = timezone::days_from_civil(1970, month, 1);
+        let days = match month {
+            12 => timezone::days_from_civil(1971, 1, 1),
+            _ => timezone::days_from_civil(1970, month + 1, 1),
+        } - first;
+        // 1 January 1970 was a Thursday:
+        let weekday = |day: i64| (day + 4).rem_euclid(7);
+        let mut day = first + (i64::from(self.weekday) - weekday(first)).rem_euclid(7);
+        day += 7 * (i64::from(self.week) - 1);
+        while day >= first + days {
+            day -= 7;
+        }
+        let (year, month, date) = timezone::civil_from_days(day);
+        format!(
+            "{year:04}{month:02}{date:02}T{:02}{:02}{:02}",
+            self.time / 3600,
+            self.time / 60 % 60,
+            self.time % 60
+        )
+    }
+}
+
+impl PosixRule {
+    /// Parses `rule`, or returns `None` for forms iCalendar can't give as
+    /// a yearly weekday rule (day-of-year changes, and times outside the
+    /// day).
+    fn parse(rule: &str) -> Option<Self> {
+        let mut rest = rule;
+        let standard_name = posix_name(&mut rest)?;
+        let standard = -posix_time(&mut rest)?;
+        if rest.is_empty() {
+            return Some(PosixRule {
+                standard_name,
+                standard,
+                daylight: None,
+            });
+        }
+        let daylight_name = posix_name(&mut rest)?;
+        let daylight = match rest.starts_with(',') {
+            true => standard + 3600,
+            false => -posix_time(&mut rest)?,
+        };
+        let mut changes = rest.strip_prefix(',')?.split(',');
+        let (Some(start), Some(end), None) = (changes.next(), changes.next(), changes.next())
+        else {
+            return None;
+        };
+        Some(PosixRule {
+            standard_name,
+            standard,
+            daylight: Some((
+                daylight_name,
+                daylight,
+                posix_transition(start)?,
+                posix_transition(end)?,
+            )),
+        })
+    }
+}
+
+/// Takes a zone abbreviation, as in `CET` or `<-03>`, off the front of
+/// `rest`.
+fn posix_name(rest: &mut &str) -> Option<String> {
+    let (name, after) = match rest.strip_prefix('<') {
+        Some(quoted) => {
+            let (name, after) = quoted.split_once('>')?;
+            (name, after)
+        }
+        None => {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len());
+            rest.split_at(end)
+        }
+    };
+    if name.len() < 3 {
+        return None;
+    }
+    *rest = after;
+    Some(name.into())
+}
+
+/// Takes a time, as in `-1`, `5:30`, or `2:00:00`, off the front of
+/// `rest`, as seconds.
+fn posix_time(rest: &mut &str) -> Option<i64> {
+    let end = rest
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, ':' | '+' | '-')))
+        .unwrap_or(rest.len());
+    let (time, after) = rest.split_at(end);
+    let (sign, time) = match time.as_bytes().first() {
+        Some(b'-') => (-1, &time[1..]),
+        Some(b'+') => (1, &time[1..]),
+        _ => (1, time),
+    };
+    let mut seconds = 0;
+    for (n, part) in time.split(':').enumerate() {
+        if n > 2 || part.is_empty() {
+            return None;
+        }
+        seconds += part.parse::<i64>().ok()? * [3600, 60, 1][n];
+    }
+    *rest = after;
+    Some(sign * seconds)
+}
+
+/// Parses a change of offset, as in `M3.5.0` or `M10.5.0/3`.
+fn posix_transition(change: &str) -> Option<Transition> {
+    let (date, time) = match change.split_once('/') {
+        Some((date, mut time)) => (date, posix_time(&mut time)?),
+        None => (change, 7200),
+    };
+    if !(0..86400).contains(&time) {
+        return None;
+    }
+    let mut parts = date.strip_prefix('M')?.split('.');
+    let number = |part: Option<&str>, range: core::ops::RangeInclusive<u8>| {
+        part?.parse::<u8>().ok().filter(|n| range.contains(n))
+    };
+    let (Some(month), Some(week), Some(weekday), None) = (
+        number(parts.next(), 1..=12),
+        number(parts.next(), 1..=5),
+        number(parts.next(), 0..=6),
+        parts.next(),
+    ) else {
+        return None;
+    };
+    Some(Transition {
+        month,
+        week,
+        weekday,
+        time,
+    })
+}
+
+/// Formats seconds east of UTC as an iCalendar offset, as in `+0100`.
+fn utc_offset(seconds: i64) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    let mut offset = format!("{sign}{:02}{:02}", seconds / 3600, seconds / 60 % 60);
+    if seconds % 60 != 0 {
+        offset += &format!("{:02}", seconds % 60);
+    }
+    offset
+}
+
+/// Returns the date after `date`, both as in `2025-01-20`.
+fn next_day(date: &str) -> Option<String> {
+    let field = |range: core::ops::Range<usize>| date.get(range)?.parse::<i64>().ok();
+    let days = timezone::days_from_civil(field(0..4)?, field(5..7)?, field(8..10)?);
+    let (year, month, day) = timezone::civil_from_days(days + 1);
+    Some(format!("{year:04}-{month:02}-{day:02}"))
+}
+
+/// Returns an ISO 8601 date or time in iCalendar's basic form, as in
+/// `20250120T133000Z`.
+fn basic(time: &str) -> String {
+    time.chars().filter(|c| !matches!(c, '-' | ':')).collect()
+}
+
+/// Returns a basic iCalendar time, as in `20250120T133000`, in ISO 8601,
+/// as in `2025-01-20T13:30:00`.
+fn extended(time: &str) -> String {
+    let part = |range: core::ops::Range<usize>| time.get(range).unwrap_or("00");
+    format!(
+        "{}-{}-{}T{}:{}:{}",
+        time.get(0..4).unwrap_or("0000"),
+        part(4..6),
+        part(6..8),
+        part(9..11),
+        part(11..13),
+        part(13..15)
+    )
+}
+
+/// Escapes `text` as an iCalendar TEXT value.
+pub(super) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped += "\\\\",
+            ';' => escaped += "\\;",
+            ',' => escaped += "\\,",
+            '\n' => escaped += "\\n",
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Quotes `value` as a parameter value, dropping the double quotes it
+/// can't hold.
+pub(super) fn param(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .filter(|&c| c != '"' && !c.is_control())
+        .collect();
+    match value.contains([';', ':', ',']) || value.contains(' ') {
+        true => format!("\"{value}\""),
+        false => value,
+    }
+}
+
+/// Appends `line` to `text`, folded into lines of at most
+/// [`LINE_LIMIT`] octets, each ending in CRLF.
+pub(super) fn fold(line: &str, text: &mut String) {
+    let mut limit = LINE_LIMIT;
+    let mut rest = line;
+    while rest.len() > limit {
+        let mut end = limit;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.push_str(&rest[..end]);
+        text.push_str("\r\n ");
+        rest = &rest[end..];
+        // Continuation lines start with the space:
+        limit = LINE_LIMIT - 1;
+    }
+    text.push_str(rest);
+    text.push_str("\r\n");
+}
@@ -0,0 +1,125 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Writes mail messages as they came, one after another, as an mbox file
+//! (in the mboxrd variant) that Mail and most other mail software import.
+//! Records themselves are left out: only the messages attached to them,
+//! as `message/rfc822`, are written.
+
+use super::Sink;
+use crate::{
+    Error, Result,
+    mail::{self, Address},
+    timezone,
+};
+use std::{
+    format,
+    io::{BufWriter, Write},
+    string::String,
+    vec::Vec,
+};
+
+/// The MIME type of the messages written.
+pub const MIME: &str = "message/rfc822";
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Writes each message as it is attached.
+pub struct MboxSink<W: Write> {
+    writer: BufWriter<W>,
+}
+
+impl<W: Write> MboxSink<W> {
+    pub fn new(writer: W) -> Self {
+        MboxSink {
+            writer: BufWriter::new(writer),
+        }
+    }
+}
+
+impl<W: Write> Sink for MboxSink<W> {
+    fn write(&mut self, _record: &serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    /// Deleted messages are simply not in the file.
+    fn delete(&mut self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Appends a message, leaving anything else out.
+    fn attach(&mut self, data: &[u8], mime: &str, _extension: &str) -> Result<Option<String>> {
+        if mime != MIME {
+            return Ok(None);
+        }
+        let source = String::from_utf8_lossy(data);
+        let mut text = from_line(&source);
+        for line in source.split_inclusive('\n') {
+            // Lines that would read as the start of a message, quoted or
+            // not, are quoted once more:
+            if line.trim_start_matches('>').starts_with("From ") {
+                text.push('>');
+            }
+            text.push_str(line);
+        }
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push('\n');
+        self.writer
+            .write_all(text.as_bytes())
+            .map_err(|e| Error::Io {
+                context: "writing the mbox to stdout",
+                source: e,
+            })?;
+        Ok(None)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush().map_err(|e| Error::Io {
+            context: "writing the mbox to stdout",
+            source: e,
+        })
+    }
+}
+
+/// Returns the line a message starts with in the mbox, as in
+/// `From ada@example.com Thu Feb 13 10:00:00 2025`, naming the address
+/// it was sent from and when, by its headers.
+fn from_line(source: &str) -> String {
+    let sender = ["Return-Path", "From"]
+        .into_iter()
+        .filter_map(|name| mail::header(source, name))
+        .find_map(|value| Address::parse(&value))
+        .map_or_else(|| "MAILER-DAEMON".into(), |address| address.email);
+    let date = mail::header(source, "Date")
+        .and_then(|date| asctime(&date))
+        .unwrap_or_else(|| "Thu Jan  1 00:00:00 1970".into());
+    format!("From {sender} {date}\n")
+}
+
+/// Returns an RFC 5322 date, as in `Thu, 13 Feb 2025 10:00:00 +0100`, in
+/// the form of C's `asctime`, as in `Thu Feb 13 10:00:00 2025`, in the
+/// time zone it was given in.
+fn asctime(date: &str) -> Option<String> {
+    // The day of the week is optional:
+    let date = date.split_once(',').map_or(date, |(_, date)| date);
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    let [day, month, year, time, ..] = parts[..] else {
+        return None;
+    };
+    let day: i64 = day.parse().ok()?;
+    let month = MONTHS
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(month))?;
+    let year: i64 = year.parse().ok()?;
+    let time = match time.len() {
+        5 => format!("{time}:00"),
+        _ => time.into(),
+    };
+    let days = timezone::days_from_civil(year, month as i64 + 1, day);
+    let weekday = WEEKDAYS[(days + 4).rem_euclid(7) as usize];
+    Some(format!("{weekday} {} {day:2} {time} {year}", MONTHS[month]))
+}
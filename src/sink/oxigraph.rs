@@ -0,0 +1,161 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Writes records as triples into an RDF store, one named graph per
+//! account, via SPARQL Update: either into a local Oxigraph store through
+//! the `oxigraph` CLI, or to a remote SPARQL Update endpoint through `curl`.
+
+use super::{Sink, str_field};
+use crate::{Result, exec, rdf};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    format,
+    path::PathBuf,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// How many records to send per SPARQL Update request.
+const BATCH_SIZE: usize = 200;
+
+/// Where the triples go.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// A local Oxigraph store directory.
+    Store(PathBuf),
+    /// A SPARQL 1.1 Update endpoint URL.
+    Endpoint(String),
+}
+
+pub struct OxigraphSink {
+    target: Target,
+    /// Subjects and triples of the pending records, by named graph IRI.
+    pending: BTreeMap<Option<String>, (Vec<String>, Vec<String>)>,
+    /// Subjects to remove from every graph.
+    deleted: Vec<String>,
+    pending_records: usize,
+}
+
+impl OxigraphSink {
+    pub fn new(target: Target) -> Self {
+        OxigraphSink {
+            target,
+            pending: BTreeMap::new(),
+            deleted: Vec::new(),
+            pending_records: 0,
+        }
+    }
+
+    /// Replaces each pending subject's triples in its graph, so that
+    /// re-running an export doesn't accumulate stale values.
+    fn send(&mut self) -> Result<()> {
+        if self.pending_records == 0 {
+            return Ok(());
+        }
+
+        let mut update = String::new();
+        for subject in &self.deleted {
+            update += &format!("DELETE WHERE {{ {subject} ?p ?o . }};\n");
+            update += &format!("DELETE WHERE {{ GRAPH ?g {{ {subject} ?p ?o . }} }};\n");
+        }
+        let mut insert = String::new();
+        for (graph, (subjects, triples)) in &self.pending {
+            let (open, close) = match graph {
+                Some(graph) => (format!("GRAPH <{graph}> {{ "), " }"),
+                None => (String::new(), ""),
+            };
+            for subject in subjects {
+                update += &format!("DELETE WHERE {{ {open}{subject} ?p ?o .{close} }};\n");
+            }
+            insert += &format!("{open}\n{}\n{close}\n", triples.join("\n"));
+        }
+        if !insert.is_empty() {
+            update += &format!("INSERT DATA {{\n{insert}}};\n");
+        }
+
+        #[cfg(feature = "tracing")]
+        asimov_module::tracing::debug!(
+            target: "asimov_apple_module::sink::oxigraph",
+            records = self.pending_records,
+            bytes = update.len(),
+            "sending SPARQL update"
+        );
+
+        match &self.target {
+            Target::Store(path) => {
+                let location = path.to_string_lossy();
+                exec::run(
+                    "oxigraph",
+                    ["update", "--location", location.as_ref()],
+                    update.as_bytes(),
+                )?;
+            }
+            Target::Endpoint(url) => {
+                exec::run(
+                    "curl",
+                    [
+                        "--silent",
+                        "--show-error",
+                        "--fail-with-body",
+                        "--header",
+                        "Content-Type: application/sparql-update",
+                        "--data-binary",
+                        "@-",
+                        url.as_str(),
+                    ],
+                    update.as_bytes(),
+                )?;
+            }
+        }
+
+        self.pending.clear();
+        self.deleted.clear();
+        self.pending_records = 0;
+        Ok(())
+    }
+}
+
+impl Sink for OxigraphSink {
+    fn write(&mut self, record: &Value) -> Result<()> {
+        let graph = rdf::account_graph(record);
+        let quads = rdf::to_quads(record, None);
+        let (subjects, triples) = self.pending.entry(graph).or_default();
+        if let Some(id) = str_field(record, "@id") {
+            subjects.push(rdf::Term::Iri(id.to_string()).to_string());
+        }
+        triples.extend(quads.iter().map(rdf::Quad::to_ntriple));
+
+        self.pending_records += 1;
+        if self.pending_records >= BATCH_SIZE {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &str) -> Result<()> {
+        let subject = rdf::Term::Iri(id.to_string()).to_string();
+        // Deletions go first in an update, so one that follows an upsert
+        // of the same subject must wait for the next update:
+        if self
+            .pending
+            .values()
+            .any(|(subjects, _)| subjects.contains(&subject))
+        {
+            self.send()?;
+        }
+        self.deleted.push(subject);
+        self.pending_records += 1;
+        if self.pending_records >= BATCH_SIZE {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.send()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.send()
+    }
+}
@@ -0,0 +1,352 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Uploads records to an S3-compatible object store through `curl`, which
+//! signs the requests (AWS Signature Version 4). Each batch becomes an
+//! NDJSON object, or several once it grows past [`PART_RECORDS`] records
+//! or [`PART_BYTES`] bytes; attachments become objects named by their SHA-256, so
+//! that none is uploaded twice.
+
+use super::{Sink, webhook::curl_quote as quote};
+use crate::{
+    Error, Result, exec,
+    export::{sha256_hex, url_path},
+    provenance,
+};
+use core::{
+    fmt,
+    hash::BuildHasher,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use serde_json::Value;
+use std::{
+    collections::BTreeSet,
+    format,
+    fs::{self, File},
+    hash::RandomState,
+    io::{self, Write},
+    path::PathBuf,
+    process,
+    string::{String, ToString},
+    thread,
+    time::Duration,
+    vec::Vec,
+};
+
+/// The environment variable naming the endpoint of a store other than AWS.
+pub const ENDPOINT_VAR: &str = "AWS_ENDPOINT_URL";
+
+/// The environment variables holding the credentials.
+pub const ACCESS_KEY_VAR: &str = "AWS_ACCESS_KEY_ID";
+pub const SECRET_KEY_VAR: &str = "AWS_SECRET_ACCESS_KEY";
+pub const SESSION_TOKEN_VAR: &str = "AWS_SESSION_TOKEN";
+
+/// The region signed for unless `AWS_REGION` or `AWS_DEFAULT_REGION` says
+/// otherwise.
+pub const DEFAULT_REGION: &str = "us-east-1";
+
+/// The delay before the first retry; each further retry doubles it.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The number of records, and of bytes of them, that make an object of
+/// their own, uploaded without waiting for a flush.
+const PART_RECORDS: usize = 1000;
+const PART_BYTES: usize = 8 << 20;
+
+/// Distinguishes the temporary body files of concurrent uploads.
+static UPLOADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Where objects go: a bucket, and a prefix within it (without slashes
+/// at either end, and possibly empty).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub bucket: String,
+    pub prefix: String,
+}
+
+impl Location {
+    /// Parses the part of an `s3://BUCKET[/PREFIX]` URL after the scheme.
+    pub fn parse(input: &str) -> Option<Self> {
+        let (bucket, prefix) = input.split_once('/').unwrap_or((input, ""));
+        if bucket.is_empty() {
+            return None;
+        }
+        Some(Location {
+            bucket: bucket.into(),
+            prefix: prefix.trim_matches('/').into(),
+        })
+    }
+
+    /// Returns the key of the object `name` under the prefix.
+    pub fn key(&self, name: &str) -> String {
+        match self.prefix.as_str() {
+            "" => name.into(),
+            prefix => format!("{prefix}/{name}"),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.prefix.as_str() {
+            "" => write!(f, "s3://{}", self.bucket),
+            prefix => write!(f, "s3://{}/{prefix}", self.bucket),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct S3Config {
+    pub location: Location,
+    /// The base URL objects are addressed under, path-style.
+    pub endpoint: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub session_token: Option<String>,
+    pub retries: u32,
+}
+
+impl S3Config {
+    /// Takes the credentials, region, and endpoint from the environment,
+    /// as the AWS CLI does, and retries failed uploads three times.
+    pub fn from_env(location: Location) -> Result<Self> {
+        let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let (Some(access_key), Some(secret_key)) = (var(ACCESS_KEY_VAR), var(SECRET_KEY_VAR))
+        else {
+            return Err(Error::Usage {
+                message: format!(
+                    "the sink {location} needs {ACCESS_KEY_VAR} and {SECRET_KEY_VAR} to be set"
+                ),
+            });
+        };
+        let region = var("AWS_REGION")
+            .or_else(|| var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| DEFAULT_REGION.into());
+        let endpoint = var(ENDPOINT_VAR)
+            .unwrap_or_else(|| format!("https://s3.{region}.amazonaws.com"))
+            .trim_end_matches('/')
+            .to_string();
+        Ok(S3Config {
+            location,
+            endpoint,
+            region,
+            access_key,
+            secret_key,
+            session_token: var(SESSION_TOKEN_VAR),
+            retries: 3,
+        })
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.endpoint,
+            self.location.bucket,
+            url_path(key)
+        )
+    }
+}
+
+pub struct S3Sink {
+    config: S3Config,
+    pending: Vec<String>,
+    /// The size of the pending records, newlines included.
+    pending_bytes: usize,
+    /// The attachments known to be in the store already.
+    stored: BTreeSet<String>,
+}
+
+impl S3Sink {
+    pub fn new(config: S3Config) -> Self {
+        S3Sink {
+            config,
+            pending: Vec::new(),
+            pending_bytes: 0,
+            stored: BTreeSet::new(),
+        }
+    }
+
+    /// Uploads the pending records as one object, named by the time and
+    /// its content: `PREFIX/records/20250120T133000Z-1a2b3c4d5e6f.ndjson`.
+    fn send(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let body: String = self.pending.iter().map(|r| format!("{r}\n")).collect();
+        let time: String = provenance::now_iso8601()
+            .chars()
+            .filter(|c| !matches!(c, '-' | ':'))
+            .collect();
+        let hash = sha256_hex(body.as_bytes());
+        let key = self.config.location.key(&format!(
+            "records/{time}-{}.ndjson",
+            hash.get(..12).unwrap_or(&hash)
+        ));
+        self.put(&key, body.as_bytes(), "application/x-ndjson")?;
+        self.pending.clear();
+        self.pending_bytes = 0;
+        Ok(())
+    }
+
+    /// Uploads `body` as the object `key`, retrying with exponential
+    /// backoff.
+    fn put(&self, key: &str, body: &[u8], content_type: &str) -> Result<()> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        let failure = loop {
+            let reason = match self.request("PUT", key, Some((body, content_type))) {
+                Ok(200..=299) => return Ok(()),
+                Ok(status @ (0 | 408 | 429 | 500..=599)) => format!("HTTP {status}"),
+                Ok(status) => break format!("HTTP {status}"),
+                Err(Error::CommandFailed { stderr, .. }) => stderr.trim().to_string(),
+                Err(err) => return Err(err),
+            };
+            if attempt >= self.config.retries {
+                break reason;
+            }
+            #[cfg(feature = "tracing")]
+            asimov_module::tracing::warn!(
+                target: "asimov_apple_module::sink::s3",
+                attempt,
+                key,
+                reason = %reason,
+                "upload failed; retrying"
+            );
+            thread::sleep(backoff);
+            backoff *= 2;
+            attempt += 1;
+        };
+        Err(Error::Delivery {
+            url: format!("s3://{}/{key}", self.config.location.bucket),
+            message: failure,
+        })
+    }
+
+    /// Makes one signed request, returning the HTTP status. As for
+    /// webhooks, the credentials go to `curl` through a config file on
+    /// stdin, and the body through a temporary file.
+    fn request(&self, method: &str, key: &str, body: Option<(&[u8], &str)>) -> Result<u16> {
+        let mut config = format!(
+            "url = {}\naws-sigv4 = {}\nuser = {}\n",
+            quote(&self.config.url(key)),
+            quote(&format!("aws:amz:{}:s3", self.config.region)),
+            quote(&format!(
+                "{}:{}",
+                self.config.access_key, self.config.secret_key
+            )),
+        );
+        if let Some(token) = &self.config.session_token {
+            config += &format!(
+                "header = {}\n",
+                quote(&format!("x-amz-security-token: {token}"))
+            );
+        }
+        // `curl` waits for a body unless told the request is a HEAD one:
+        if method == "HEAD" {
+            config += "head\n";
+        } else {
+            config += &format!("request = \"{method}\"\n");
+        }
+
+        let body_path = match body {
+            Some((body, content_type)) => {
+                let path = write_temporary(body).map_err(|e| Error::Io {
+                    context: "writing an object to upload",
+                    source: e,
+                })?;
+                config += &format!(
+                    "upload-file = {}\nheader = {}\n",
+                    quote(&path.display().to_string()),
+                    quote(&format!("Content-Type: {content_type}")),
+                );
+                Some(path)
+            }
+            None => None,
+        };
+
+        let result = exec::run(
+            "curl",
+            [
+                "--silent",
+                "--show-error",
+                "--output",
+                "/dev/null",
+                "--write-out",
+                "%{http_code}",
+                "--config",
+                "-",
+            ],
+            config.as_bytes(),
+        );
+        if let Some(path) = &body_path {
+            fs::remove_file(path).ok();
+        }
+        Ok(String::from_utf8_lossy(&result?)
+            .trim()
+            .parse()
+            .unwrap_or(0))
+    }
+}
+
+/// Writes `body` to a new file in the temporary directory, readable by the
+/// user only. Its name is unpredictable, and a file already there by that
+/// name, or a link, is never written through.
+fn write_temporary(body: &[u8]) -> io::Result<PathBuf> {
+    loop {
+        let nonce = RandomState::new().hash_one(UPLOADS.fetch_add(1, Ordering::Relaxed));
+        let path =
+            std::env::temp_dir().join(format!("asimov-apple-s3-{}-{nonce:016x}", process::id()));
+        let mut options = File::options();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = match options.open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        if let Err(e) = file.write_all(body) {
+            fs::remove_file(&path).ok();
+            return Err(e);
+        }
+        return Ok(path);
+    }
+}
+
+impl Sink for S3Sink {
+    /// Queues a record, uploading the queue as an object of its own once it
+    /// holds [`PART_RECORDS`] records or [`PART_BYTES`] bytes.
+    fn write(&mut self, record: &Value) -> Result<()> {
+        let line = record.to_string();
+        self.pending_bytes += line.len() + 1;
+        self.pending.push(line);
+        if self.pending.len() >= PART_RECORDS || self.pending_bytes >= PART_BYTES {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    /// Uploads an attachment as `PREFIX/attachments/SHA256.EXT`, unless an
+    /// object by that name is there already.
+    fn attach(&mut self, data: &[u8], mime: &str, extension: &str) -> Result<Option<String>> {
+        let key = self
+            .config
+            .location
+            .key(&format!("attachments/{}.{extension}", sha256_hex(data)));
+        if !self.stored.contains(&key) {
+            if self.request("HEAD", &key, None)? != 200 {
+                self.put(&key, data, mime)?;
+            }
+            self.stored.insert(key.clone());
+        }
+        Ok(Some(format!("s3://{}/{key}", self.config.location.bucket)))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.send()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.send()
+    }
+}
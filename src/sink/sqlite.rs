@@ -0,0 +1,403 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Upserts records into an SQLite database by streaming SQL into the
+//! `sqlite3` shell that ships with macOS.
+
+use super::{Sink, str_field, tombstone_id};
+use crate::{Error, Result, export::sha256_hex, provenance, rdf};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    io::{BufWriter, Read, Write},
+    os::unix::process::CommandExt,
+    path::Path,
+    process::{Child, ChildStdin, Command, Stdio},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// How many records are written per transaction, so that what was written
+/// before a failure is kept.
+const BATCH_SIZE: usize = 1000;
+
+/// The schema, created on first use. Full records are kept as JSON in
+/// `record` so that nothing is lost to the column mapping.
+const SCHEMA: &str = r#"
+PRAGMA foreign_keys = ON;
+CREATE TABLE IF NOT EXISTS accounts (
+    name TEXT PRIMARY KEY
+);
+CREATE TABLE IF NOT EXISTS folders (
+    account TEXT NOT NULL REFERENCES accounts (name),
+    name TEXT NOT NULL,
+    PRIMARY KEY (account, name)
+);
+CREATE TABLE IF NOT EXISTS notes (
+    id TEXT PRIMARY KEY,
+    name TEXT,
+    text TEXT,
+    date_created TEXT,
+    date_modified TEXT,
+    account TEXT,
+    folder TEXT,
+    record TEXT NOT NULL,
+    FOREIGN KEY (account, folder) REFERENCES folders (account, name)
+);
+CREATE TABLE IF NOT EXISTS attachments (
+    record_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    content_type TEXT,
+    data BLOB,
+    PRIMARY KEY (record_id, url)
+);
+CREATE TABLE IF NOT EXISTS provenance (
+    id TEXT PRIMARY KEY,
+    started_at TEXT,
+    record TEXT NOT NULL
+);
+CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5 (
+    name, text, content = 'notes', content_rowid = 'rowid'
+);
+CREATE TRIGGER IF NOT EXISTS notes_ai AFTER INSERT ON notes BEGIN
+    INSERT INTO notes_fts (rowid, name, text) VALUES (new.rowid, new.name, new.text);
+END;
+CREATE TRIGGER IF NOT EXISTS notes_ad AFTER DELETE ON notes BEGIN
+    INSERT INTO notes_fts (notes_fts, rowid, name, text) VALUES ('delete', old.rowid, old.name, old.text);
+END;
+CREATE TRIGGER IF NOT EXISTS notes_au AFTER UPDATE ON notes BEGIN
+    INSERT INTO notes_fts (notes_fts, rowid, name, text) VALUES ('delete', old.rowid, old.name, old.text);
+    INSERT INTO notes_fts (rowid, name, text) VALUES (new.rowid, new.name, new.text);
+END;
+"#;
+
+/// The table of records from any app but Notes, one per app, named after
+/// it, as in `reminders`, created when its first record comes.
+const APP_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS {table} (
+    id TEXT PRIMARY KEY,
+    type TEXT,
+    name TEXT,
+    date_created TEXT,
+    date_modified TEXT,
+    account TEXT,
+    record TEXT NOT NULL
+);
+"#;
+
+pub struct SqliteSink {
+    shell: Shell,
+    /// The records written since the last commit.
+    pending: usize,
+    /// The tables of other apps' records created so far.
+    tables: BTreeSet<String>,
+    /// The attachments given since the last record, by URL, with their
+    /// content types; they belong to the record written next.
+    attached: BTreeMap<String, (String, Vec<u8>)>,
+}
+
+impl SqliteSink {
+    /// Opens the database at `path`, creating the schema if needed.
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(SqliteSink {
+            shell: Shell::open(path, SCHEMA)?,
+            pending: 0,
+            tables: BTreeSet::new(),
+            attached: BTreeMap::new(),
+        })
+    }
+
+    /// Creates the table of `app`'s records, unless already created.
+    fn create_table(&mut self, app: &str) -> Result<()> {
+        if self.tables.insert(app.to_string()) {
+            self.shell.execute(&APP_TABLE.replace("{table}", app))?;
+        }
+        Ok(())
+    }
+
+    /// Counts a record written, committing every [`BATCH_SIZE`].
+    fn written(&mut self) -> Result<()> {
+        self.pending += 1;
+        if self.pending >= BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the attachments of the record `id` with those given since
+    /// the last record and those in its `associatedMedia`, if any,
+    /// so that a run without attachments keeps those it stored before.
+    fn write_attachments(&mut self, id: &str, record: &Value) -> Result<()> {
+        let attached = core::mem::take(&mut self.attached);
+        let media = record
+            .get("associatedMedia")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .filter(|url| !attached.contains_key(*url))
+            .map(|url| (url.to_string(), None))
+            .collect::<Vec<_>>();
+        if attached.is_empty() && media.is_empty() {
+            return Ok(());
+        }
+        let id = quote(Some(id));
+        let mut sql = format!("DELETE FROM attachments WHERE record_id = {id};\n");
+        let rows = attached
+            .into_iter()
+            .map(|(url, attachment)| (url, Some(attachment)))
+            .chain(media);
+        for (url, attachment) in rows {
+            let (content_type, data) = match attachment {
+                Some((content_type, data)) => (quote(Some(&content_type)), blob(&data)),
+                None => ("NULL".to_string(), "NULL".to_string()),
+            };
+            sql += &format!(
+                "INSERT INTO attachments (record_id, url, content_type, data) \
+                 VALUES ({id}, {}, {content_type}, {data});\n",
+                quote(Some(&url)),
+            );
+        }
+        self.shell.execute(&sql)
+    }
+
+    /// Upserts the `prov:Activity` node of a run, which records refer to
+    /// by `prov:wasGeneratedBy`.
+    fn write_activity(&mut self, id: &str, record: &Value) -> Result<()> {
+        self.shell.execute(&format!(
+            "INSERT INTO provenance (id, started_at, record) VALUES ({}, {}, {}) \
+             ON CONFLICT (id) DO UPDATE SET started_at = excluded.started_at, \
+             record = excluded.record;\n",
+            quote(Some(id)),
+            quote(str_field(record, "prov:startedAtTime")),
+            quote(Some(&record.to_string())),
+        ))
+    }
+
+    /// Upserts a record from any app but Notes into that app's table,
+    /// keeping them out of the notes and their full-text index.
+    fn write_app_record(&mut self, app: &str, id: &str, record: &Value) -> Result<()> {
+        self.create_table(app)?;
+        let field = |key: &str| quote(str_field(record, key));
+        self.shell.execute(&format!(
+            "INSERT INTO {app} (id, type, name, date_created, date_modified, account, record) \
+             VALUES ({}, {}, {}, {}, {}, {}, {}) \
+             ON CONFLICT (id) DO UPDATE SET type = excluded.type, name = excluded.name, \
+             date_created = excluded.date_created, date_modified = excluded.date_modified, \
+             account = excluded.account, record = excluded.record;\n",
+            quote(Some(id)),
+            field("@type"),
+            field("name"),
+            field("dateCreated"),
+            field("dateModified"),
+            field("account"),
+            quote(Some(&record.to_string())),
+        ))
+    }
+}
+
+/// A `sqlite3` shell that SQL is streamed into, within a transaction.
+pub struct Shell {
+    child: Child,
+    stdin: Option<BufWriter<ChildStdin>>,
+}
+
+impl Shell {
+    /// Starts `sqlite3` on the database at `path`, running `schema` and
+    /// opening the transaction all later statements run in.
+    pub fn open(path: &Path, schema: &str) -> Result<Self> {
+        // In a process group of its own, so that Ctrl-C, which stops it
+        // reading, leaves it to commit what was written when we finish:
+        let mut child = Command::new("sqlite3")
+            .arg("-bail")
+            .arg(path)
+            .process_group(0)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::Io {
+                context: "invoking sqlite3",
+                source: e,
+            })?;
+        let stdin = child.stdin.take().map(BufWriter::new);
+        let mut shell = Shell { child, stdin };
+        shell.execute(schema)?;
+        shell.execute("BEGIN;\n")?;
+        Ok(shell)
+    }
+
+    pub fn execute(&mut self, sql: &str) -> Result<()> {
+        let result = match self.stdin.as_mut() {
+            Some(stdin) => stdin.write_all(sql.as_bytes()),
+            None => Ok(()),
+        };
+        // A write error usually means `sqlite3` bailed out; its stderr says why:
+        result.or_else(|e| {
+            self.wait()?;
+            Err(Error::Io {
+                context: "writing SQL to sqlite3",
+                source: e,
+            })
+        })
+    }
+
+    /// Commits what was written so far and opens a new transaction.
+    pub fn commit(&mut self) -> Result<()> {
+        self.execute("COMMIT;\nBEGIN;\n")?;
+        if let Some(stdin) = self.stdin.as_mut() {
+            stdin.flush().map_err(|e| Error::Io {
+                context: "writing SQL to sqlite3",
+                source: e,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Commits what was written and waits for `sqlite3` to exit.
+    pub fn close(&mut self) -> Result<()> {
+        self.execute("COMMIT;\n")?;
+        self.wait()
+    }
+
+    /// Closes the SQL stream and waits for `sqlite3` to exit.
+    fn wait(&mut self) -> Result<()> {
+        if let Some(mut stdin) = self.stdin.take() {
+            stdin.flush().ok();
+        }
+        let status = self.child.wait().map_err(|e| Error::Io {
+            context: "waiting for sqlite3",
+            source: e,
+        })?;
+        let mut stderr = String::new();
+        if let Some(mut pipe) = self.child.stderr.take() {
+            pipe.read_to_string(&mut stderr).ok();
+        }
+        if !status.success() {
+            return Err(Error::CommandFailed {
+                program: "sqlite3",
+                status,
+                stderr,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Sink for SqliteSink {
+    fn write(&mut self, record: &Value) -> Result<()> {
+        let Some(id) = str_field(record, "@id") else {
+            return Err(Error::Parse {
+                context: "writing a record to SQLite",
+                message: "record has no @id".to_string(),
+            });
+        };
+        if tombstone_id(record).is_some() {
+            return self.delete(id);
+        }
+        if str_field(record, "@type") == Some(provenance::ACTIVITY_TYPE) {
+            self.write_activity(id, record)?;
+            return self.written();
+        }
+        match rdf::source_app(record) {
+            Some("notes") => {}
+            Some(app) => {
+                let app = app.to_string();
+                self.write_app_record(&app, id, record)?;
+                self.write_attachments(id, record)?;
+                return self.written();
+            }
+            None => {
+                return Err(Error::Parse {
+                    context: "writing a record to SQLite",
+                    message: format!("record {id} has no apple-* source"),
+                });
+            }
+        }
+
+        let field = |key: &str| quote(str_field(record, key));
+        let account = field("account");
+        let folder = field("isPartOf");
+
+        let mut sql = String::new();
+        if account != "NULL" {
+            sql += &format!("INSERT OR IGNORE INTO accounts (name) VALUES ({account});\n");
+            if folder != "NULL" {
+                sql += &format!(
+                    "INSERT OR IGNORE INTO folders (account, name) VALUES ({account}, {folder});\n"
+                );
+            }
+        }
+        sql += &format!(
+            "INSERT INTO notes (id, name, text, date_created, date_modified, account, folder, record) \
+             VALUES ({}, {}, {}, {}, {}, {account}, {folder}, {}) \
+             ON CONFLICT (id) DO UPDATE SET name = excluded.name, text = excluded.text, \
+             date_created = excluded.date_created, date_modified = excluded.date_modified, \
+             account = excluded.account, folder = excluded.folder, record = excluded.record;\n",
+            quote(Some(id)),
+            field("name"),
+            field("text"),
+            field("dateCreated"),
+            field("dateModified"),
+            quote(Some(&record.to_string())),
+        );
+        self.shell.execute(&sql)?;
+        self.write_attachments(id, record)?;
+        self.written()
+    }
+
+    fn delete(&mut self, id: &str) -> Result<()> {
+        // The app is in the URN, as in `urn:apple:reminders:reminder:…`:
+        let app = id
+            .strip_prefix("urn:apple:")
+            .and_then(|rest| rest.split(':').next())
+            .filter(|app| !app.is_empty() && app.bytes().all(|b| b.is_ascii_lowercase()))
+            .filter(|app| *app != "notes");
+        let table = match app {
+            Some(app) => {
+                self.create_table(app)?;
+                app
+            }
+            None => "notes",
+        };
+        let id = quote(Some(id));
+        self.shell.execute(&format!(
+            "DELETE FROM attachments WHERE record_id = {id};\nDELETE FROM {table} WHERE id = {id};\n"
+        ))
+    }
+
+    fn attach(&mut self, data: &[u8], mime: &str, extension: &str) -> Result<Option<String>> {
+        let url = format!("attachments/{}.{extension}", sha256_hex(data));
+        self.attached
+            .insert(url.clone(), (mime.to_string(), data.to_vec()));
+        Ok(Some(url))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.pending = 0;
+        self.shell.commit()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.shell.close()
+    }
+}
+
+/// Writes `data` as an SQL blob literal.
+fn blob(data: &[u8]) -> String {
+    let mut literal = String::with_capacity(data.len() * 2 + 3);
+    literal += "X'";
+    for byte in data {
+        literal += &format!("{byte:02x}");
+    }
+    literal += "'";
+    literal
+}
+
+/// Quotes `value` as an SQL string literal, or `NULL`.
+pub fn quote(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("'{}'", value.replace('\'', "''")),
+        None => "NULL".to_string(),
+    }
+}
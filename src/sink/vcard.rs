@@ -0,0 +1,409 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Writes contacts as vCards, version 4.0 (RFC 6350) or 3.0 (RFC 2426),
+//! one after another, as an address book that Contacts and other address
+//! books import. Other records are left out.
+
+use super::{
+    Sink,
+    ics::{escape, fold, param},
+    str_field,
+};
+use crate::{Error, Result, contacts::GROUP_URN_PREFIX, export::base64};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    format,
+    io::{BufWriter, Write},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Which version of vCard to write.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Version {
+    /// vCard 3.0, which older address books read.
+    V3,
+    /// vCard 4.0.
+    #[default]
+    V4,
+}
+
+/// Writes each contact record as a `VCARD` as it comes, and once
+/// finished, a `VCARD` for each group, listing its members.
+pub struct VcardSink<W: Write> {
+    writer: BufWriter<W>,
+    version: Version,
+    /// The groups, by `@id`: their names (if written) and members' `@id`s.
+    groups: BTreeMap<String, (Option<String>, Vec<String>)>,
+}
+
+impl<W: Write> VcardSink<W> {
+    pub fn new(writer: W, version: Version) -> Self {
+        VcardSink {
+            writer: BufWriter::new(writer),
+            version,
+            groups: BTreeMap::new(),
+        }
+    }
+}
+
+impl<W: Write> Sink for VcardSink<W> {
+    fn write(&mut self, record: &Value) -> Result<()> {
+        let id = str_field(record, "@id").unwrap_or_default();
+        match str_field(record, "@type") {
+            Some("ItemList") if id.starts_with(GROUP_URN_PREFIX) => {
+                let name = str_field(record, "name").unwrap_or_default();
+                self.groups.entry(id.into()).or_default().0 = Some(name.into());
+                Ok(())
+            }
+            Some("Person" | "Organization") => {
+                for group in array(record, "memberOf").iter().filter_map(Value::as_str) {
+                    let members = &mut self.groups.entry(group.into()).or_default().1;
+                    members.push(id.into());
+                }
+                self.write_lines(vcard(record, self.version))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Deleted contacts are simply not in the file.
+    fn delete(&mut self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Keeps photos in the cards themselves, as `data:` URLs.
+    fn attach(&mut self, data: &[u8], mime: &str, _extension: &str) -> Result<Option<String>> {
+        Ok(Some(format!("data:{mime};base64,{}", base64(data))))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        // Groups only their members were written for are left out:
+        for (id, (name, members)) in core::mem::take(&mut self.groups) {
+            if let Some(name) = name {
+                self.write_lines(group(&id, &name, &members, self.version))?;
+            }
+        }
+        self.writer.flush().map_err(|e| Error::Io {
+            context: "writing vCards to stdout",
+            source: e,
+        })
+    }
+}
+
+impl<W: Write> VcardSink<W> {
+    fn write_lines(&mut self, lines: Vec<String>) -> Result<()> {
+        let mut text = String::new();
+        for line in lines {
+            fold(&line, &mut text);
+        }
+        self.writer
+            .write_all(text.as_bytes())
+            .map_err(|e| Error::Io {
+                context: "writing vCards to stdout",
+                source: e,
+            })
+    }
+}
+
+/// Returns the content lines of the `VCARD` for a group, as vCard 4.0
+/// has them, or as Contacts writes them in vCard 3.0.
+fn group(id: &str, name: &str, members: &[String], version: Version) -> Vec<String> {
+    let (kind, member) = match version {
+        Version::V3 => ("X-ADDRESSBOOKSERVER-KIND", "X-ADDRESSBOOKSERVER-MEMBER"),
+        Version::V4 => ("KIND", "MEMBER"),
+    };
+    let mut lines = Vec::from([
+        "BEGIN:VCARD".to_string(),
+        match version {
+            Version::V3 => "VERSION:3.0".into(),
+            Version::V4 => "VERSION:4.0".into(),
+        },
+        format!("UID:{id}"),
+        format!("{kind}:group"),
+        format!("FN:{}", escape(name)),
+    ]);
+    if version == Version::V3 {
+        lines.push(format!("N:{};;;;", escape(name)));
+    }
+    lines.extend(members.iter().map(|id| format!("{member}:{id}")));
+    lines.push("END:VCARD".into());
+    lines
+}
+
+/// Returns the content lines of the `VCARD` for a contact record.
+fn vcard(record: &Value, version: Version) -> Vec<String> {
+    let text = |key: &str| str_field(record, key);
+    let organization = str_field(record, "@type") == Some("Organization");
+    let mut lines = Vec::from([
+        "BEGIN:VCARD".to_string(),
+        match version {
+            Version::V3 => "VERSION:3.0".into(),
+            Version::V4 => "VERSION:4.0".into(),
+        },
+        format!(
+            "PRODID:-//asimov-apple//asimov-apple {}//EN",
+            env!("CARGO_PKG_VERSION")
+        ),
+    ]);
+    if let Some(id) = text("@id") {
+        lines.push(format!("UID:{id}"));
+    }
+    if organization {
+        lines.push(match version {
+            Version::V3 => "X-ABShowAs:COMPANY".into(),
+            Version::V4 => "KIND:org".into(),
+        });
+    }
+    lines.push(format!("FN:{}", escape(text("name").unwrap_or_default())));
+    // The structured name is required in vCard 3.0, if only empty:
+    let name = [
+        "familyName",
+        "givenName",
+        "additionalName",
+        "honorificPrefix",
+        "honorificSuffix",
+    ]
+    .map(|key| escape(text(key).unwrap_or_default()));
+    if version == Version::V3 || name.iter().any(|part| !part.is_empty()) {
+        lines.push(format!("N:{}", name.join(";")));
+    }
+    if let Some(nickname) = text("alternateName") {
+        lines.push(format!("NICKNAME:{}", escape(nickname)));
+    }
+    let (company, department) = match organization {
+        true => (Some(record), record.get("department")),
+        false => {
+            let employer = record.get("worksFor");
+            (
+                employer,
+                employer.and_then(|employer| employer.get("department")),
+            )
+        }
+    };
+    if let Some(company) = company.and_then(|company| str_field(company, "name")) {
+        let department = department.and_then(|department| str_field(department, "name"));
+        lines.push(match department {
+            Some(department) => format!("ORG:{};{}", escape(company), escape(department)),
+            None => format!("ORG:{}", escape(company)),
+        });
+    }
+    if let Some(title) = text("jobTitle") {
+        lines.push(format!("TITLE:{}", escape(title)));
+    }
+
+    // Labels vCard has no type for go along as Contacts writes them, on
+    // a grouped X-ABLabel:
+    let mut groups = 0;
+    let mut labeled = |property: String,
+                       label: Option<&str>,
+                       types: &[&str],
+                       lines: &mut Vec<String>| {
+        match label {
+            Some(label) if types.is_empty() => {
+                groups += 1;
+                lines.push(format!("item{groups}.{property}"));
+                lines.push(format!("item{groups}.X-ABLabel:{}", escape(label)));
+            }
+            _ => lines.push(property),
+        }
+    };
+    for point in array(record, "contactPoint") {
+        let label = str_field(point, "contactType");
+        if let Some(number) = str_field(point, "telephone") {
+            let types = phone_types(label);
+            let property = match version {
+                Version::V3 => format!("TEL{}:{}", type_param(&types), escape(number)),
+                Version::V4 => format!("TEL;VALUE=text{}:{}", type_param(&types), escape(number)),
+            };
+            labeled(property, label, &types, &mut lines);
+        }
+        if let Some(email) = str_field(point, "email") {
+            let known = label_types(label);
+            let mut types = known.clone();
+            if version == Version::V3 {
+                types.insert(0, "INTERNET");
+            }
+            let property = format!("EMAIL{}:{}", type_param(&types), escape(email));
+            labeled(property, label, &known, &mut lines);
+        }
+        if let Some(url) = str_field(point, "url") {
+            let types = label_types(label);
+            let property = format!("URL{}:{url}", type_param(&types));
+            labeled(property, label, &types, &mut lines);
+        }
+    }
+    for address in array(record, "address") {
+        let label = str_field(address, "contactType");
+        let types = label_types(label);
+        let parts = [
+            "",
+            "",
+            "streetAddress",
+            "addressLocality",
+            "addressRegion",
+            "postalCode",
+            "addressCountry",
+        ]
+        .map(|key| escape(str_field(address, key).unwrap_or_default()));
+        let property = format!("ADR{}:{}", type_param(&types), parts.join(";"));
+        labeled(property, label, &types, &mut lines);
+    }
+    // As Contacts writes them, in either version:
+    for profile in array(record, "socialProfile") {
+        let service = str_field(profile, "propertyID").unwrap_or("x-apple");
+        let mut property = format!("X-SOCIALPROFILE;TYPE={}", param(&service.to_lowercase()));
+        if let Some(username) = str_field(profile, "value") {
+            property += &format!(";X-USER={}", param(username));
+        }
+        let url = str_field(profile, "url").map_or_else(
+            || {
+                format!(
+                    "x-apple:{}",
+                    str_field(profile, "value").unwrap_or_default()
+                )
+            },
+            String::from,
+        );
+        lines.push(format!("{property}:{url}"));
+    }
+    for handle in array(record, "instantMessage") {
+        let Some(username) = str_field(handle, "value") else {
+            continue;
+        };
+        let label = str_field(handle, "contactType");
+        let types = label_types(label);
+        let service = str_field(handle, "propertyID");
+        let scheme = service.map_or("x-apple".into(), |service| {
+            service
+                .chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect::<String>()
+                .to_lowercase()
+        });
+        let property = format!(
+            "IMPP{}{}:{scheme}:{username}",
+            service.map_or(String::new(), |service| format!(
+                ";X-SERVICE-TYPE={}",
+                param(service)
+            )),
+            type_param(&types),
+        );
+        labeled(property, label, &types, &mut lines);
+    }
+    for person in array(record, "relatedTo") {
+        let Some(name) = str_field(person, "name") else {
+            continue;
+        };
+        let label = str_field(person, "contactType");
+        match version {
+            // Contacts has only its own, labeled property for these:
+            Version::V3 => labeled(
+                format!("X-ABRELATEDNAMES:{}", escape(name)),
+                label,
+                &[],
+                &mut lines,
+            ),
+            Version::V4 => {
+                let types = related_types(label);
+                let property = format!("RELATED{};VALUE=text:{}", type_param(&types), escape(name));
+                labeled(property, label, &types, &mut lines);
+            }
+        }
+    }
+    if let Some(birthday) = text("birthDate") {
+        lines.push(bday(birthday, version));
+    }
+    if let Some(url) = record
+        .get("image")
+        .and_then(|image| str_field(image, "contentUrl"))
+    {
+        lines.push(photo(url, version));
+    }
+    if let Some(note) = text("description") {
+        lines.push(format!("NOTE:{}", escape(note)));
+    }
+    lines.push("END:VCARD".into());
+    lines
+}
+
+/// Returns the array at `key` in `record`, or an empty one.
+fn array<'a>(record: &'a Value, key: &str) -> &'a [Value] {
+    record
+        .get(key)
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+}
+
+/// Returns the vCard types for a phone number's label, as in `cell` for
+/// `mobile`, empty for labels vCard has none for.
+fn phone_types(label: Option<&str>) -> Vec<&'static str> {
+    match label.map(str::to_lowercase).as_deref() {
+        Some("mobile" | "iphone") => Vec::from(["cell"]),
+        Some("homefax") => Vec::from(["home", "fax"]),
+        Some("workfax") => Vec::from(["work", "fax"]),
+        Some("otherfax") => Vec::from(["fax"]),
+        Some("pager") => Vec::from(["pager"]),
+        Some("main") => Vec::from(["voice"]),
+        _ => label_types(label),
+    }
+}
+
+/// Returns the vCard types for `home` and `work` labels.
+fn label_types(label: Option<&str>) -> Vec<&'static str> {
+    match label.map(str::to_lowercase).as_deref() {
+        Some("home") => Vec::from(["home"]),
+        Some("work") => Vec::from(["work"]),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the vCard 4.0 `RELATED` types for a related name's label, as
+/// in `spouse`, empty for labels vCard has none for, such as `assistant`
+/// or `mother`.
+fn related_types(label: Option<&str>) -> Vec<&'static str> {
+    match label.map(str::to_lowercase).as_deref() {
+        Some("spouse") => Vec::from(["spouse"]),
+        Some("parent") => Vec::from(["parent"]),
+        Some("child") => Vec::from(["child"]),
+        Some("friend") => Vec::from(["friend"]),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the `TYPE` parameter for `types`, or nothing if there are none.
+fn type_param(types: &[&str]) -> String {
+    match types.is_empty() {
+        true => String::new(),
+        false => format!(";TYPE={}", types.join(",")),
+    }
+}
+
+/// Returns the `BDAY` line for a birthday such as `1815-12-10`, or
+/// `--12-10` without a year, which vCard 3.0 can't tell, so it gets one
+/// Contacts knows to leave out.
+fn bday(birthday: &str, version: Version) -> String {
+    match (version, birthday.strip_prefix("--")) {
+        (Version::V3, Some(day)) => format!("BDAY;X-APPLE-OMIT-YEAR=1604:1604-{day}"),
+        (Version::V3, None) => format!("BDAY:{birthday}"),
+        (Version::V4, Some(day)) => format!("BDAY:--{}", day.replace('-', "")),
+        (Version::V4, None) => format!("BDAY:{}", birthday.replace('-', "")),
+    }
+}
+
+/// Returns the `PHOTO` line for a photo at `url`, embedding it if it is a
+/// `data:` URL.
+fn photo(url: &str, version: Version) -> String {
+    let embedded = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"));
+    match (version, embedded) {
+        (Version::V3, Some((mime, data))) => {
+            let kind = mime.strip_prefix("image/").unwrap_or(mime).to_uppercase();
+            format!("PHOTO;ENCODING=b;TYPE={kind}:{data}")
+        }
+        (Version::V3, None) => format!("PHOTO;VALUE=uri:{url}"),
+        (Version::V4, _) => format!("PHOTO:{url}"),
+    }
+}
@@ -0,0 +1,249 @@
+// This is free and unencumbered software released into the public domain.
+
+//! POSTs batches of records to an HTTP(S) endpoint through `curl`, with
+//! retries and a dead-letter file for batches that can't be delivered.
+
+use super::Sink;
+use crate::{Error, Result, exec};
+use core::{
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use serde_json::Value;
+use std::{
+    eprintln, format,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    process,
+    string::{String, ToString},
+    thread,
+    time::Duration,
+    vec::Vec,
+};
+
+/// The delay before the first retry; each further retry doubles it.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Distinguishes the temporary body files of concurrent batches.
+static BATCHES: AtomicUsize = AtomicUsize::new(0);
+
+/// How a batch is encoded in the request body.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BatchFormat {
+    /// One JSON record per line (`application/x-ndjson`).
+    #[default]
+    Ndjson,
+    /// A JSON array of records (`application/json`).
+    Json,
+}
+
+impl FromStr for BatchFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        match input {
+            "ndjson" | "jsonl" => Ok(BatchFormat::Ndjson),
+            "json" => Ok(BatchFormat::Json),
+            _ => Err(format!("unsupported batch format: {input}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub batch_size: usize,
+    pub format: BatchFormat,
+    pub token: Option<String>,
+    pub retries: u32,
+    pub dead_letter: Option<PathBuf>,
+}
+
+impl WebhookConfig {
+    /// The default batch size, format, and retry count, without a token.
+    pub fn new(url: impl Into<String>) -> Self {
+        WebhookConfig {
+            url: url.into(),
+            batch_size: 100,
+            format: BatchFormat::default(),
+            token: None,
+            retries: 3,
+            dead_letter: None,
+        }
+    }
+}
+
+pub struct WebhookSink {
+    config: WebhookConfig,
+    pending: Vec<String>,
+}
+
+impl WebhookSink {
+    pub fn new(config: WebhookConfig) -> Self {
+        WebhookSink {
+            config,
+            pending: Vec::new(),
+        }
+    }
+
+    fn body(&self) -> String {
+        match self.config.format {
+            BatchFormat::Ndjson => self.pending.iter().map(|r| format!("{r}\n")).collect(),
+            BatchFormat::Json => format!("[{}]", self.pending.join(",")),
+        }
+    }
+
+    fn send(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let body = self.body();
+
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        let failure = loop {
+            match self.post(&body) {
+                Ok(Delivery::Delivered) => {
+                    self.pending.clear();
+                    return Ok(());
+                }
+                Ok(Delivery::Rejected(status)) => break format!("HTTP {status}"),
+                Ok(Delivery::Retryable(reason))
+                | Err(Error::CommandFailed { stderr: reason, .. })
+                    if attempt < self.config.retries =>
+                {
+                    #[cfg(feature = "tracing")]
+                    asimov_module::tracing::warn!(
+                        target: "asimov_apple_module::sink::webhook",
+                        attempt,
+                        reason = %reason.trim(),
+                        "webhook delivery failed; retrying"
+                    );
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = reason;
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Ok(Delivery::Retryable(reason)) => break reason,
+                Err(Error::CommandFailed { stderr, .. }) => break stderr.trim().to_string(),
+                Err(err) => return Err(err),
+            }
+        };
+
+        let Some(dead_letter) = &self.config.dead_letter else {
+            return Err(Error::Delivery {
+                url: self.config.url.clone(),
+                message: failure,
+            });
+        };
+        eprintln!(
+            "Warning: failed to deliver {} records to {} ({failure}); appending them to {}",
+            self.pending.len(),
+            self.config.url,
+            dead_letter.display()
+        );
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dead_letter)
+            .and_then(|mut file| {
+                self.pending
+                    .iter()
+                    .try_for_each(|record| writeln!(file, "{record}"))
+            })
+            .map_err(|e| Error::Io {
+                context: "writing the dead-letter file",
+                source: e,
+            })?;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Makes one delivery attempt. The token and URL go to `curl` through a
+    /// config file on stdin and the body through a temporary file, keeping
+    /// the token out of the process list.
+    fn post(&self, body: &str) -> Result<Delivery> {
+        let body_path = std::env::temp_dir().join(format!(
+            "asimov-apple-webhook-{}-{}.json",
+            process::id(),
+            BATCHES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&body_path, body).map_err(|e| Error::Io {
+            context: "writing the webhook request body",
+            source: e,
+        })?;
+
+        let content_type = match self.config.format {
+            BatchFormat::Ndjson => "application/x-ndjson",
+            BatchFormat::Json => "application/json",
+        };
+        let mut config = format!(
+            "url = {}\nrequest = \"POST\"\nheader = \"Content-Type: {content_type}\"\ndata-binary = {}\n",
+            curl_quote(&self.config.url),
+            curl_quote(&format!("@{}", body_path.display())),
+        );
+        if let Some(token) = &self.config.token {
+            config += &format!(
+                "header = {}\n",
+                curl_quote(&format!("Authorization: Bearer {token}"))
+            );
+        }
+
+        let result = exec::run(
+            "curl",
+            [
+                "--silent",
+                "--show-error",
+                "--output",
+                "/dev/null",
+                "--write-out",
+                "%{http_code}",
+                "--config",
+                "-",
+            ],
+            config.as_bytes(),
+        );
+        fs::remove_file(&body_path).ok();
+
+        let status: u16 = String::from_utf8_lossy(&result?)
+            .trim()
+            .parse()
+            .unwrap_or(0);
+        Ok(match status {
+            200..=299 => Delivery::Delivered,
+            0 | 408 | 429 | 500..=599 => Delivery::Retryable(format!("HTTP {status}")),
+            status => Delivery::Rejected(status),
+        })
+    }
+}
+
+enum Delivery {
+    Delivered,
+    Retryable(String),
+    Rejected(u16),
+}
+
+impl Sink for WebhookSink {
+    fn write(&mut self, record: &Value) -> Result<()> {
+        self.pending.push(record.to_string());
+        if self.pending.len() >= self.config.batch_size.max(1) {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.send()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.send()
+    }
+}
+
+/// Quotes `value` for a curl config file.
+pub(crate) fn curl_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
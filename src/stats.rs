@@ -0,0 +1,123 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Run statistics, summarized at the end of a run for monitoring.
+
+use serde_json::{Map, Value, json};
+use std::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    time::{Duration, Instant},
+    vec::Vec,
+};
+
+/// What happened during one run of a program.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    program: &'static str,
+    started: Instant,
+    phases: Vec<(&'static str, Duration)>,
+    /// Records emitted, by account and then folder.
+    emitted: BTreeMap<String, BTreeMap<String, u64>>,
+    skipped: u64,
+    deleted: u64,
+    warnings: Vec<String>,
+}
+
+impl Stats {
+    pub fn new(program: &'static str) -> Self {
+        Stats {
+            program,
+            started: Instant::now(),
+            phases: Vec::new(),
+            emitted: BTreeMap::new(),
+            skipped: 0,
+            deleted: 0,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Returns the name of the program that ran.
+    pub fn program(&self) -> &'static str {
+        self.program
+    }
+
+    /// Records the time since `started` as spent in `phase`. Phases run
+    /// more than once accumulate.
+    pub fn finish_phase(&mut self, phase: &'static str, started: Instant) {
+        let elapsed = started.elapsed();
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => self.phases.push((phase, elapsed)),
+        }
+    }
+
+    /// Counts a record emitted from the given account and folder.
+    pub fn emitted(&mut self, account: &str, folder: &str) {
+        *self
+            .emitted
+            .entry(account.to_string())
+            .or_default()
+            .entry(folder.to_string())
+            .or_default() += 1;
+    }
+
+    /// Counts records that were found but not emitted.
+    pub fn skipped(&mut self, count: u64) {
+        self.skipped += count;
+    }
+
+    /// Counts a record deleted downstream because of a tombstone.
+    pub fn deleted(&mut self) {
+        self.deleted += 1;
+    }
+
+    pub fn warn(&mut self, message: impl Into<String>) {
+        self.warnings.push(message.into());
+    }
+
+    /// Returns the total number of records emitted so far.
+    pub fn total(&self) -> u64 {
+        self.emitted.values().flat_map(BTreeMap::values).sum()
+    }
+
+    /// Returns the summary as a JSON object. `status` is `finished`,
+    /// `interrupted`, or `failed`, and `error` says why a run failed.
+    pub fn to_json(&self, status: &str, error: Option<&str>) -> Value {
+        let accounts: Map<String, Value> = self
+            .emitted
+            .iter()
+            .map(|(account, folders)| {
+                let total: u64 = folders.values().sum();
+                (
+                    account.clone(),
+                    json!({ "records": total, "folders": folders }),
+                )
+            })
+            .collect();
+        let phases: Map<String, Value> = self
+            .phases
+            .iter()
+            .map(|(name, duration)| (name.to_string(), seconds(*duration)))
+            .collect();
+
+        json!({
+            "program": self.program,
+            "status": status,
+            "error": error,
+            "records": self.total(),
+            "skipped": self.skipped,
+            "deleted": self.deleted,
+            "accounts": accounts,
+            "warnings": self.warnings,
+            "durations": {
+                "total": seconds(self.started.elapsed()),
+                "phases": phases,
+            },
+        })
+    }
+}
+
+/// Formats a duration in seconds, to the millisecond.
+fn seconds(duration: Duration) -> Value {
+    json!((duration.as_millis() as f64) / 1000.0)
+}
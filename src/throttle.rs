@@ -0,0 +1,103 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Paces the Apple Events sent to target apps. Extracting everything as
+//! fast as possible can make Notes (or Mail) unresponsive for as long as
+//! a run takes, so a process-wide rate limit applies both to `osascript`
+//! runs and, within each script, to every item it reads.
+
+use core::{fmt, str::FromStr};
+use std::{
+    format,
+    string::String,
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often to check for an interrupt while waiting for the next slot.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static PACER: OnceLock<Mutex<Instant>> = OnceLock::new();
+static RATE: OnceLock<Rate> = OnceLock::new();
+
+/// A rate limit, in batches of Apple Events per second.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rate(f64);
+
+impl Rate {
+    pub fn per_second(&self) -> f64 {
+        self.0
+    }
+
+    /// Returns the time between two batches.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.0)
+    }
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    /// Parses `N`, `N/s`, or `N/sec`, where `N` may be fractional.
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        let number = input
+            .strip_suffix("/sec")
+            .or_else(|| input.strip_suffix("/s"))
+            .unwrap_or(input);
+        match number.trim().parse::<f64>() {
+            Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(Rate(rate)),
+            _ => Err(format!("invalid rate (expected N/sec): {input}")),
+        }
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/sec", self.0)
+    }
+}
+
+/// Sets the rate limit for the rest of the process. Only the first call
+/// has any effect.
+pub fn install(rate: Rate) {
+    RATE.set(rate).ok();
+    PACER.set(Mutex::new(Instant::now())).ok();
+}
+
+/// Returns the rate limit, if one was installed.
+pub fn rate() -> Option<Rate> {
+    RATE.get().copied()
+}
+
+/// Blocks until the next batch may be sent, returning `false` if
+/// `interrupted` reported true first.
+pub fn wait(interrupted: impl Fn() -> bool) -> bool {
+    let (Some(rate), Some(pacer)) = (RATE.get(), PACER.get()) else {
+        return true;
+    };
+    let slot = {
+        let mut next = pacer.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = (*next).max(Instant::now());
+        *next = slot + rate.interval();
+        slot
+    };
+    loop {
+        if interrupted() {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= slot {
+            return true;
+        }
+        thread::sleep((slot - now).min(POLL_INTERVAL));
+    }
+}
+
+/// Returns the AppleScript statement to run between two items a script
+/// reads, or an empty string without a rate limit.
+pub fn applescript_delay() -> String {
+    match rate() {
+        Some(rate) => format!("delay {:.3}", rate.interval().as_secs_f64()),
+        None => String::new(),
+    }
+}
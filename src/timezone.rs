@@ -0,0 +1,312 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Converts the local date-times the Apple apps report into UTC and back,
+//! using the system's time zone database, for formats that need UTC times.
+//! Also reads the local times given on the command line.
+
+use core::{fmt, str::FromStr};
+use std::{env, format, fs, string::String, vec::Vec};
+
+/// A local time given on the command line, as a date (`2025-01-20`, taken
+/// as its midnight) or a date and time (`2025-01-20T13:30`, with optional
+/// seconds, or with a space for the `T`). Kept in the ISO 8601 form the
+/// Apple apps report times in, so that it compares with them as a string.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LocalTime {
+    time: String,
+    /// Whether it was given with a time of day, rather than as a date.
+    timed: bool,
+}
+
+impl LocalTime {
+    /// Returns the time as `2025-01-20T13:30:00`.
+    pub fn as_str(&self) -> &str {
+        &self.time
+    }
+
+    /// Whether it was given with a time of day, rather than as a date.
+    pub fn has_time(&self) -> bool {
+        self.timed
+    }
+}
+
+impl FromStr for LocalTime {
+    type Err = String;
+
+    fn from_str(input: &str) -> core::result::Result<Self, Self::Err> {
+        let input = input.trim();
+        let invalid = || format!("not a date or date and time: {input}");
+        let (date, time) = input
+            .split_once(['T', ' '])
+            .map_or((input, None), |(date, time)| (date, Some(time)));
+        let number = |part: &str, digits: usize, max: u32| {
+            if part.len() != digits || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            part.parse::<u32>().ok().filter(|n| *n <= max)
+        };
+
+        let mut parts = date.split('-');
+        let (Some(year), Some(month), Some(day), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let (Some(year), Some(month), Some(day)) = (
+            number(year, 4, 9999),
+            number(month, 2, 12),
+            number(day, 2, 31),
+        ) else {
+            return Err(invalid());
+        };
+        if month == 0 || day == 0 {
+            return Err(invalid());
+        }
+
+        let (hour, minute, second) = match time {
+            None => (0, 0, 0),
+            Some(time) => {
+                let mut parts = time.split(':');
+                let hour = parts.next().and_then(|part| number(part, 2, 23));
+                let minute = parts.next().and_then(|part| number(part, 2, 59));
+                let second = match parts.next() {
+                    Some(part) => number(part, 2, 59),
+                    None => Some(0),
+                };
+                match (hour, minute, second, parts.next()) {
+                    (Some(hour), Some(minute), Some(second), None) => (hour, minute, second),
+                    _ => return Err(invalid()),
+                }
+            }
+        };
+        Ok(LocalTime {
+            time: format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}"),
+            timed: time.is_some(),
+        })
+    }
+}
+
+impl fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.time)
+    }
+}
+
+/// The local time zone, as its history of UTC offsets.
+#[derive(Clone, Debug, Default)]
+pub struct TimeZone {
+    /// When each offset took effect, in seconds since the epoch, sorted.
+    transitions: Vec<(i64, i64)>,
+    /// The offset in effect before the first transition, or throughout.
+    initial: i64,
+    /// The POSIX `TZ` rule for times after the last transition, as in
+    /// `CET-1CEST,M3.5.0,M10.5.0/3`, if the zone file has one.
+    rule: Option<String>,
+}
+
+impl TimeZone {
+    /// Loads the time zone named by `$TZ`, or else the system's. Without
+    /// a readable zone file, everything is taken to be UTC.
+    pub fn local() -> Self {
+        let path = match env::var("TZ") {
+            Ok(name) if !name.is_empty() => {
+                let name = name.trim_start_matches(':');
+                if name.starts_with('/') {
+                    name.into()
+                } else {
+                    format!("/usr/share/zoneinfo/{name}")
+                }
+            }
+            _ => "/etc/localtime".into(),
+        };
+        fs::read(path)
+            .ok()
+            .and_then(|data| Self::parse(&data))
+            .unwrap_or_default()
+    }
+
+    /// Loads the zone by its IANA name, as in `Europe/Berlin`, or `None`
+    /// if the system has no such zone.
+    pub fn named(name: &str) -> Option<Self> {
+        if name.is_empty() || name.split('/').any(|part| part.is_empty() || part == "..") {
+            return None;
+        }
+        fs::read(format!("/usr/share/zoneinfo/{name}"))
+            .ok()
+            .and_then(|data| Self::parse(&data))
+    }
+
+    /// Returns the POSIX `TZ` rule the zone follows from its last
+    /// transition on, as in `CET-1CEST,M3.5.0,M10.5.0/3`.
+    pub fn rule(&self) -> Option<&str> {
+        self.rule.as_deref()
+    }
+
+    /// Parses a TZif file, as described in RFC 8536.
+    fn parse(data: &[u8]) -> Option<Self> {
+        let header = |data: &[u8]| -> Option<[usize; 6]> {
+            if data.get(0..4)? != b"TZif" {
+                return None;
+            }
+            let mut counts = [0; 6];
+            for (n, count) in counts.iter_mut().enumerate() {
+                let bytes = data.get(20 + 4 * n..24 + 4 * n)?;
+                *count = u32::from_be_bytes(bytes.try_into().ok()?) as usize;
+            }
+            Some(counts)
+        };
+        let [isut, isstd, leap, time, types, chars] = header(data)?;
+        let v1_len = time * 5 + types * 6 + chars + leap * 8 + isstd + isut;
+
+        // Version 2 and later repeat the data with 64-bit times:
+        let (data, time_size) = if data[4] >= b'2' {
+            (data.get(44 + v1_len..)?, 8)
+        } else {
+            (data, 4)
+        };
+        let [isut, isstd, leap, time, types, chars] = header(data)?;
+        let data = data.get(44..)?;
+
+        // Version 2 and later end in the rule for later times, between
+        // newlines:
+        let rule = match time_size {
+            8 => data
+                .get(time * 9 + types * 6 + chars + leap * 12 + isstd + isut..)
+                .and_then(|footer| footer.strip_prefix(b"\n"))
+                .and_then(|footer| footer.split(|&b| b == b'\n').next())
+                .and_then(|rule| core::str::from_utf8(rule).ok())
+                .filter(|rule| !rule.is_empty())
+                .map(String::from),
+            _ => None,
+        };
+
+        let times = data.get(..time * time_size)?;
+        let indices = data.get(time * time_size..time * (time_size + 1))?;
+        let infos = data.get(time * (time_size + 1)..time * (time_size + 1) + types * 6)?;
+        let offset_of = |index: usize| -> Option<i64> {
+            let info = infos.get(index * 6..index * 6 + 4)?;
+            Some(i64::from(i32::from_be_bytes(info.try_into().ok()?)))
+        };
+
+        let mut transitions = Vec::with_capacity(time);
+        for (n, &index) in indices.iter().enumerate() {
+            let at = &times[n * time_size..(n + 1) * time_size];
+            let at = if time_size == 8 {
+                i64::from_be_bytes(at.try_into().ok()?)
+            } else {
+                i64::from(i32::from_be_bytes(at.try_into().ok()?))
+            };
+            transitions.push((at, offset_of(usize::from(index))?));
+        }
+        Some(TimeZone {
+            transitions,
+            initial: offset_of(0).unwrap_or_default(),
+            rule,
+        })
+    }
+
+    /// Returns the offset from UTC, in seconds, in effect at `utc`.
+    fn offset_at(&self, utc: i64) -> i64 {
+        match self.transitions.partition_point(|&(at, _)| at <= utc) {
+            0 => self.initial,
+            n => self.transitions[n - 1].1,
+        }
+    }
+
+    /// Converts an ISO 8601 local time, e.g. `2025-01-20T13:30:00`, into
+    /// seconds since the epoch.
+    pub fn to_timestamp(&self, local: &str) -> Option<i64> {
+        let field = |range: core::ops::Range<usize>| local.get(range)?.parse::<i64>().ok();
+        let days = days_from_civil(field(0..4)?, field(5..7)?, field(8..10)?);
+        let time = field(11..13)? * 3600 + field(14..16)? * 60 + field(17..19).unwrap_or(0);
+        let local = days * 86400 + time;
+        Some(local - self.offset_at(local - self.offset_at(local)))
+    }
+
+    /// Converts an ISO 8601 time into seconds since the epoch: one with an
+    /// offset (`2025-01-20T13:30:00+01:00`) or in UTC (`…Z`) as such, and
+    /// one without as a local time in this zone.
+    pub fn instant(&self, time: &str) -> Option<i64> {
+        let offset = match time.get(19..) {
+            Some("Z") => 0,
+            Some("") | None => return self.to_timestamp(time),
+            Some(offset) => parse_offset(offset)?,
+        };
+        Some(TimeZone::default().to_timestamp(&time[..19])? - offset)
+    }
+
+    /// Converts an ISO 8601 local time into UTC, e.g. `2025-01-20T12:30:00Z`
+    /// in Paris.
+    pub fn to_utc(&self, local: &str) -> Option<String> {
+        self.to_timestamp(local).map(iso8601)
+    }
+
+    /// Converts an ISO 8601 local time into one with the UTC offset then in
+    /// effect, e.g. `2025-01-20T13:30:00+01:00` in Paris.
+    pub fn with_offset(&self, local: &str) -> Option<String> {
+        let timestamp = self.to_timestamp(local)?;
+        let offset = self.offset_at(timestamp);
+        let sign = if offset < 0 { '-' } else { '+' };
+        Some(format!(
+            "{}{sign}{:02}:{:02}",
+            self.to_local(timestamp),
+            offset.abs() / 3600,
+            offset.abs() / 60 % 60
+        ))
+    }
+
+    /// Formats seconds since the epoch as an ISO 8601 local time, as the
+    /// Apple apps report them.
+    pub fn to_local(&self, timestamp: i64) -> String {
+        let mut local = iso8601(timestamp + self.offset_at(timestamp));
+        local.pop();
+        local
+    }
+}
+
+/// Parses an ISO 8601 offset, as in `+01:00`, as seconds east of UTC.
+pub fn parse_offset(offset: &str) -> Option<i64> {
+    let sign = match offset.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let hours = offset.get(1..3)?.parse::<i64>().ok()?;
+    let minutes = offset.get(4..6)?.parse::<i64>().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Formats seconds since the epoch in ISO 8601 UTC.
+pub fn iso8601(timestamp: i64) -> String {
+    let (days, time) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Howard Hinnant's `days_from_civil`: days since the epoch of a date.
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Howard Hinnant's `civil_from_days`: the date some days since the epoch.
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}